@@ -0,0 +1,123 @@
+// qtilerugo-msg: sends one command to the bridge, or with --renderer to the renderer,
+// and prints the reply of commands that have one.
+//
+//     qtilerugo-msg FocusLeft
+//     qtilerugo-msg '{"SwitchWorkspace": "2"}'
+//     qtilerugo-msg --dry-run '{"SwitchWorkspace": "2"}'
+//     qtilerugo-msg --renderer GetFrameStats
+//     qtilerugo-msg --renderer --socket /run/user/1000/renderer.sock Ping
+//
+// A command is its name, or the whole command as JSON. It is checked against the
+// protocol before it is sent, so a misspelled one fails here instead of in the log of
+// the process it was meant for. Replies starting with "error: " exit with failure.
+// With --dry-run the bridge answers with what a command would change instead of
+// carrying it out.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+use qtilerugo_ipc::renderer::{self, RendererCommand, Reply};
+use qtilerugo_ipc::wm::{self, Request};
+use qtilerugo_ipc::{encode, reply};
+
+const USAGE: &str = "usage: qtilerugo-msg [--renderer | --dry-run] [--socket <path>] <command>";
+
+fn main() -> ExitCode {
+    let mut renderer = false;
+    let mut dry_run = false;
+    let mut socket = None;
+    let mut command = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--renderer" => renderer = true,
+            "--dry-run" => dry_run = true,
+            "--socket" => match args.next() {
+                Some(path) => socket = Some(path),
+                None => return usage(),
+            },
+            _ if command.is_none() && !arg.starts_with("--") => command = Some(arg),
+            _ => return usage(),
+        }
+    }
+    let Some(command) = command.filter(|_| !(renderer && dry_run)) else {
+        return usage();
+    };
+    // A bare name is the command without arguments of that name.
+    let json = if command.trim_start().starts_with(['{', '"']) {
+        command
+    } else {
+        serde_json::Value::from(command).to_string()
+    };
+    // Left as it is when it isn't a command, for send to say so.
+    let json = match serde_json::from_str::<Request>(&json) {
+        Ok(request) if dry_run => encode(&Request { dry_run, ..request }),
+        _ => json,
+    };
+
+    let sent = if renderer {
+        send::<RendererCommand>(&json, socket.as_deref().unwrap_or(renderer::SOCKET), |c| {
+            c.reply()
+        })
+    } else {
+        send::<Request>(&json, socket.as_deref().unwrap_or(wm::SOCKET), |c| {
+            if c.replies() {
+                Reply::Line
+            } else {
+                Reply::None
+            }
+        })
+    };
+    match sent {
+        Ok(Some(line)) => match reply::parse(&line) {
+            Ok(answer) => {
+                println!("{}", answer);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("qtilerugo-msg: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Ok(None) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("qtilerugo-msg: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+/// Sends `json` as a `T` to `socket`, and reads the reply line if `reply` says there is
+/// one.
+fn send<T>(
+    json: &str,
+    socket: &str,
+    reply: impl Fn(&T) -> Reply,
+) -> Result<Option<String>, Box<dyn std::error::Error>>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let command: T = serde_json::from_str(json).map_err(|e| format!("not a command: {}", e))?;
+    let reply = reply(&command);
+    if reply == Reply::Fds {
+        return Err("the reply comes with file descriptors, which can't be printed".into());
+    }
+    let mut stream =
+        UnixStream::connect(socket).map_err(|e| format!("can't connect to {}: {}", socket, e))?;
+    stream.write_all(encode(&command).as_bytes())?;
+    if reply == Reply::None {
+        return Ok(None);
+    }
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err("the connection closed without a reply".into());
+    }
+    Ok(Some(line))
+}
@@ -0,0 +1,93 @@
+//! The protocol qtilerugo's processes speak: the commands the bridge takes on its
+//! socket, the commands the renderer takes on its own, the replies to them, and the
+//! shared-memory ring window updates come through. The bridge, the renderer, the
+//! compositor library and qtilerugo-msg all build and read their messages with these
+//! types, so a command one of them sends is one the other knows.
+//!
+//! On both sockets a message is one JSON value, like `"FocusLeft"` or
+//! `{"SwitchWorkspace":"2"}`, ended by a newline; [`encode`] writes them so, and a
+//! [`Decoder`] reads them back from a stream, also taking values sent back to back
+//! without one. Commands that are answered are answered with a line of their own: see
+//! [`reply`].
+//!
+//! ```
+//! use qtilerugo_ipc::wm::WmCommand;
+//! use qtilerugo_ipc::{encode, Decoder};
+//!
+//! let mut decoder = Decoder::default();
+//! decoder.push(encode(&WmCommand::FocusWindow(4194313)).as_bytes());
+//! let command: WmCommand = decoder.message().unwrap().unwrap();
+//! assert_eq!(command, WmCommand::FocusWindow(4194313));
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Commands the renderer takes, and the types they carry.
+pub mod renderer;
+/// Replies to the commands that are answered.
+pub mod reply;
+/// The shared-memory ring window updates go through, bridge to renderer.
+pub mod ring;
+/// Commands the bridge takes, and the types they carry.
+pub mod wm;
+
+/// `message` as it is sent: a line of JSON.
+pub fn encode<T: Serialize>(message: &T) -> String {
+    // Every message type serializes to JSON; maps all have string keys.
+    let mut line = serde_json::to_string(message).expect("messages serialize to JSON");
+    line.push('\n');
+    line
+}
+
+/// Splits what a stream receives into messages.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Received bytes that don't make a message yet.
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    /// Adds bytes read from the stream.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// The next whole message, if one came in; dropped, up to the end of its line,
+    /// when it isn't a `T`.
+    pub fn message<T: DeserializeOwned>(&mut self) -> Option<serde_json::Result<T>> {
+        let start = self
+            .buffer
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(self.buffer.len());
+        self.buffer.drain(..start);
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let mut values = serde_json::Deserializer::from_slice(&self.buffer).into_iter::<T>();
+        match values.next()? {
+            Ok(message) => {
+                let end = values.byte_offset();
+                self.buffer.drain(..end);
+                Some(Ok(message))
+            }
+            // Only part of it is here yet.
+            Err(e) if e.is_eof() => None,
+            Err(e) => {
+                let end = self
+                    .buffer
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(self.buffer.len(), |newline| newline + 1);
+                self.buffer.drain(..end);
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Bytes received past the last whole message.
+    pub fn pending(&self) -> &[u8] {
+        &self.buffer
+    }
+}
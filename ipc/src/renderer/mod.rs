@@ -0,0 +1,611 @@
+// Commands on the renderer's socket, and what they carry: the windows and outputs the
+// WM reports, what the bar shows, and what is captured. Settings commands carry tables
+// of renderer_config.toml as JSON, which the renderer checks as it reads the file.
+// Replies are described with each command; those that are JSON are typed here too.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where the renderer listens for commands unless its socket_path says otherwise.
+pub const SOCKET: &str = "/tmp/rust_qtile_helper.sock";
+
+/// A command on the renderer's socket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RendererCommand {
+    SpawnWindow,
+    SpawnStatusBar,
+    /// Replaces the window list with the WM's current state, bottom first.
+    UpdateWindows(Vec<WindowState>),
+    /// Replies with the shared-memory ring's memfd and eventfd (SCM_RIGHTS).
+    AttachShm,
+    /// Adds surface `id`, showing the contents of the window with that id, on top of
+    /// `layer`; later commands refer to it by `id`.
+    CreateSurface {
+        id: u32,
+        geometry: SurfaceGeometry,
+        #[serde(default)]
+        layer: Layer,
+    },
+    DestroySurface {
+        id: u32,
+    },
+    /// Moves and resizes a surface.
+    MoveSurface {
+        id: u32,
+        geometry: SurfaceGeometry,
+    },
+    SetOpacity {
+        id: u32,
+        opacity: f32,
+    },
+    /// Hides a surface, or shows it again, keeping its place in the stack.
+    SetVisible {
+        id: u32,
+        visible: bool,
+    },
+    /// Replaces the window animation settings with an [animations] table of
+    /// renderer_config.toml; omitted fields take their defaults.
+    SetAnimations(Value),
+    /// Replaces corner rounding and other per-window effect settings with an [effects]
+    /// table.
+    SetEffects(Value),
+    /// Chooses between tear-free output and lowest latency.
+    SetPresentMode(PresentMode),
+    /// Replaces the post-processing chain every output applies, first pass first, with
+    /// passes as post.passes has them.
+    SetPostProcessing(Vec<Value>),
+    /// Turns the post-processing chain on or off for the named output.
+    SetPostProcessingEnabled {
+        output: String,
+        enabled: bool,
+    },
+    /// Fades the named output, or every output, to a color temperature in kelvin; a
+    /// null temperature hands them back to the night light schedule.
+    SetColorTemp {
+        kelvin: Option<f32>,
+        #[serde(default)]
+        output: Option<String>,
+    },
+    /// Corrects the named output for the display's ICC profile at `path`; a null path
+    /// stops correcting it.
+    SetIccProfile {
+        output: String,
+        path: Option<PathBuf>,
+    },
+    /// Shows an image, a directory of images as a slideshow, or a video under the named
+    /// output, or under every output without its own; no path clears it.
+    SetWallpaper {
+        #[serde(default)]
+        output: Option<String>,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Reports that the named output, or every output, switched to workspace `index`,
+    /// before the windows change; the switch animates by the index moving up or down.
+    SetWorkspace {
+        #[serde(default)]
+        output: Option<String>,
+        index: i32,
+    },
+    /// Lists the WM's workspaces, in index order, with their windows, for the bar's
+    /// pager.
+    SetWorkspaces(Vec<WorkspaceInfo>),
+    /// What the WM knows of a window's client, for the bar's taskbar and effects rules.
+    SetWindowInfo(WindowInfo),
+    /// Where the volume or brightness is after the WM changed it, for the OSD and the
+    /// bar.
+    SetLevel(Level),
+    /// What the WM's MPRIS player plays, or null without one, for the bar.
+    SetMedia(Option<MediaInfo>),
+    /// Keeps a notification in the history the bar's calendar popup lists.
+    AddNotification(Notification),
+    /// Shrinks a window, or the focused one, into a corner above every other window.
+    PinWindow {
+        #[serde(default)]
+        window: Option<u32>,
+        #[serde(default)]
+        corner: Option<Corner>,
+    },
+    /// Puts a pinned window, or the focused one, back where the WM placed it.
+    UnpinWindow {
+        #[serde(default)]
+        window: Option<u32>,
+    },
+    /// Reports whether the client of a window answers the WM's pings; windows that
+    /// don't are drawn gray, offering to force their clients closed.
+    SetResponsive {
+        window: u32,
+        responsive: bool,
+    },
+    /// Has the WM kill the client of a window drawn as not responding.
+    ForceClose(u32),
+    /// Shows a loupe at the pointer until a click picks a color, and replies with it
+    /// as `#rrggbb r g b`.
+    PickColor,
+    /// Dims the outputs and draws a crosshair until dragging selects a region, or a
+    /// click a window, and replies with it as a JSON object of x, y, width, and height
+    /// in root window coordinates.
+    SelectRegion,
+    /// Magnifies the output under the pointer one step more, around the pointer.
+    ZoomIn,
+    /// Magnifies one step less.
+    ZoomOut,
+    /// Zooms all the way out, or back in to the last level.
+    ZoomToggle,
+    /// Reports the pointer position in root window coordinates, which zoom follows.
+    SetPointer {
+        x: i32,
+        y: i32,
+    },
+    /// Covers every output with the lock surface until the user's password is typed.
+    Lock,
+    /// Reports input the renderer doesn't see itself, restarting the idle timeouts.
+    ReportActivity,
+    /// Keeps outputs from dimming, locking, or powering down, and replies with an id
+    /// for `UninhibitIdle`; the connection closing releases it too.
+    InhibitIdle {
+        #[serde(default)]
+        reason: String,
+    },
+    UninhibitIdle(u32),
+    /// Shows a temporary overlay in the middle of every output, with an optional icon
+    /// and a gauge from 0 to 1 under the text, for `timeout` milliseconds.
+    ShowOsd {
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        timeout: Option<u64>,
+        #[serde(default)]
+        gauge: Option<f32>,
+    },
+    /// Outlines where a window will go, in root window coordinates, for `duration_ms`
+    /// milliseconds or the configured time; for preselections, for instance.
+    ShowInsertHint {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        duration_ms: Option<u64>,
+    },
+    /// Fills a tile, in root window coordinates, with bars around a window the WM keeps
+    /// centered in it at its own resolution, or stops with no tile.
+    SetLetterbox {
+        window: u32,
+        #[serde(default)]
+        tile: Option<[i32; 4]>,
+    },
+    /// Dims a window while a modal dialog of its own is open, or stops.
+    SetDimmed {
+        window: u32,
+        dimmed: bool,
+    },
+    /// Draws a shaped window as just the rectangles its X shape leaves of it, relative
+    /// to it, without border or shadow, or as all of it again without them.
+    SetWindowShape {
+        window: u32,
+        #[serde(default)]
+        rects: Option<Vec<[i32; 4]>>,
+    },
+    /// Records that the WM made a window click-through, so the compositor's own
+    /// pointer handling passes over it too, or that it takes input again.
+    SetClickThrough {
+        window: u32,
+        click_through: bool,
+    },
+    /// Switches the compositor-drawn cursor to another XCursor theme.
+    SetCursorTheme(String),
+    /// Sets the compositor-drawn cursor's size at scale 1, in pixels.
+    SetCursorSize(u32),
+    /// Shows the compositor-drawn cursor busy while the WM waits for an application it
+    /// launched, or not.
+    SetBusy(bool),
+    /// Switches to the named palette of the renderer's [theme], restyling the bar,
+    /// OSDs, and borders.
+    SetTheme(String),
+    /// Switches outputs that support it into HDR, or back to SDR.
+    SetHdr(bool),
+    /// Keeps to a power profile whatever the power supply, or follows it again with
+    /// none.
+    SetPowerProfile(Option<Profile>),
+    /// Turns the composition bypass for opaque fullscreen windows on or off.
+    SetDirectScanout(bool),
+    /// Allows or forbids variable refresh on the named output, or on every output.
+    SetVrr {
+        enabled: bool,
+        #[serde(default)]
+        output: Option<String>,
+    },
+    /// Renders one window per monitor, replacing the previously reported monitors.
+    SetOutputs(Vec<OutputInfo>),
+    /// Scales everything shown on the named output, e.g. by 1.5 or 2 on HiDPI monitors.
+    SetOutputScale {
+        output: String,
+        scale: f32,
+    },
+    /// Writes an image of an output, window, or region and replies with its path.
+    Screenshot(Screenshot),
+    /// Streams an output, window, or region to PipeWire and replies with the node id.
+    StartScreencast(Screencast),
+    /// Freezes an output for drawing on, then writes the annotated image and replies
+    /// with its path, or copies it and replies with `clipboard`.
+    Annotate(Annotate),
+    /// Ends the screencast with this node id.
+    StopScreencast(u32),
+    /// Replies with a picture of one window scaled down to fit `max_width` by
+    /// `max_height`: a JSON header line and a memfd holding the pixels.
+    GetPreview {
+        window: u32,
+        max_width: u32,
+        max_height: u32,
+    },
+    /// Reads renderer_config.toml again and applies it, replying ok; a file that doesn't
+    /// parse or validate is replied to with the problems, and changes nothing.
+    ReloadConfig,
+    /// Replies with the X ids of the output windows, as a JSON array, once the render
+    /// loop gets to it; for watchdogs.
+    Ping,
+    /// Replies with frame timing statistics of every output, as a JSON array.
+    GetFrameStats,
+    /// Shows or hides the frame statistics HUD.
+    SetHud(bool),
+    /// Replies with the key bindings in effect and the commands they send, as a JSON
+    /// array.
+    GetKeybinds,
+    // You can add more commands here.
+}
+
+/// How a command is answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    None,
+    /// With a line; see [`crate::reply`].
+    Line,
+    /// With file descriptors passed along with a byte or a line, then a line on error.
+    Fds,
+}
+
+impl RendererCommand {
+    pub fn reply(&self) -> Reply {
+        match self {
+            Self::AttachShm | Self::GetPreview { .. } => Reply::Fds,
+            Self::PickColor
+            | Self::SelectRegion
+            | Self::InhibitIdle { .. }
+            | Self::Screenshot(_)
+            | Self::StartScreencast(_)
+            | Self::Annotate(_)
+            | Self::ReloadConfig
+            | Self::Ping
+            | Self::GetFrameStats
+            | Self::GetKeybinds => Reply::Line,
+            _ => Reply::None,
+        }
+    }
+}
+
+/// Window state reported by the window manager.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub border_width: u32,
+    #[serde(default)]
+    pub focused: bool,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    #[serde(default)]
+    pub window_type: WindowType,
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// The client asked for the area behind it to be blurred.
+    #[serde(default)]
+    pub blur: bool,
+    #[serde(default)]
+    pub layer: Layer,
+    /// Hidden windows keep their place, but aren't drawn.
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+/// What a window is for, as far as effects care; the discriminants are what
+/// shared-memory records carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum WindowType {
+    #[default]
+    Normal = 0,
+    Dialog = 1,
+    /// Panels and bars.
+    Dock = 2,
+    /// Menus, tooltips, and other override-redirect windows.
+    Popup = 3,
+    Notification = 4,
+}
+
+impl WindowType {
+    /// Decodes the value carried in shared-memory records.
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => WindowType::Dialog,
+            2 => WindowType::Dock,
+            3 => WindowType::Popup,
+            4 => WindowType::Notification,
+            _ => WindowType::Normal,
+        }
+    }
+}
+
+/// Stacking layers inside an output, drawn bottom to top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Layer {
+    Background,
+    Bottom,
+    #[default]
+    Normal,
+    Top,
+    /// Pinned picture-in-picture windows, which only the compositor puts here.
+    #[serde(skip)]
+    Pip,
+    Overlay,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 6] = [
+        Layer::Background,
+        Layer::Bottom,
+        Layer::Normal,
+        Layer::Top,
+        Layer::Pip,
+        Layer::Overlay,
+    ];
+}
+
+/// Where a surface is, in root window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurfaceGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A monitor reported by the window manager, in root window coordinates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in millihertz; the display's own rate is used when omitted.
+    #[serde(default)]
+    pub refresh_mhz: Option<u32>,
+}
+
+/// How finished frames are handed to the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PresentMode {
+    /// Waits for vertical blank: tear-free, but a frame of latency.
+    #[default]
+    Fifo,
+    /// Tear-free, replacing queued frames with newer ones.
+    Mailbox,
+    /// Presents right away and may tear; lowest latency, for games.
+    Immediate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Profile {
+    Performance,
+    PowerSaver,
+}
+
+/// A workspace as the WM reports it; its place in the list is its index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceInfo {
+    pub name: String,
+    /// Its windows, bottom first.
+    #[serde(default)]
+    pub windows: Vec<u32>,
+}
+
+/// What the bridge reports of a window's client; what is left out stays as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowInfo {
+    pub window: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// WM_CLASS, for effects rules: the class, and the instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Whether it asks for attention, by its urgency hint or _NET_WM_STATE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urgent: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<IconData>,
+}
+
+/// A window icon as _NET_WM_ICON holds it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IconData {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels as ARGB words, not premultiplied, rows top first.
+    pub argb: Vec<u32>,
+}
+
+/// The controls the WM reports levels of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Control {
+    Volume,
+    Brightness,
+}
+
+/// Where the volume or the brightness is, as the WM reports it after changing it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub control: Control,
+    /// From 0 to 1, or above for volumes past 100%.
+    pub level: f32,
+    #[serde(default)]
+    pub muted: bool,
+}
+
+/// Whether a player plays, as MPRIS's PlaybackStatus says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Playback {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// What the WM reports of the MPRIS player it follows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MediaInfo {
+    /// The player's bus name, after org.mpris.MediaPlayer2.
+    pub player: String,
+    pub status: Playback,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub album: String,
+}
+
+/// A notification for the history, as the AddNotification command gives it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Notification {
+    /// The application that sent it.
+    #[serde(default)]
+    pub app: String,
+    pub summary: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// The corner of an output a pinned window sits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// What a `Screenshot` or `StartScreencast` command captures.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScreenshotTarget {
+    /// The composited frame of the output with this name, or of the first output.
+    Output(Option<String>),
+    /// The contents of one window, without decorations or effects.
+    Window(u32),
+    /// A rectangle in root window coordinates, cut from the output it starts on.
+    Region {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Default for ScreenshotTarget {
+    fn default() -> Self {
+        Self::Output(None)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Screenshot {
+    #[serde(default)]
+    pub target: ScreenshotTarget,
+    /// Where to write the image; a timestamped file in /tmp by default.
+    pub path: Option<PathBuf>,
+    /// Guessed from the path's extension when omitted, falling back to PNG.
+    pub format: Option<ImageFormat>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotate {
+    /// The output to freeze; the one under the pointer by default.
+    pub output: Option<String>,
+    /// Where to write the image when saved, as with `Screenshot`.
+    pub path: Option<PathBuf>,
+    pub format: Option<ImageFormat>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Screencast {
+    #[serde(default)]
+    pub target: ScreenshotTarget,
+    /// Frames per second at most; frames are only sent when the scene changed.
+    pub fps: Option<u32>,
+}
+
+/// Statistics of one output, as `GetFrameStats` replies with them in a JSON array;
+/// times are in milliseconds, averaged over the last frames.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputReport {
+    pub output: String,
+    pub frames: u64,
+    pub missed_vblanks: u64,
+    pub refresh_ms: f32,
+    /// Only known while the output draws continuously, e.g. during animations.
+    pub fps: Option<f32>,
+    pub frame_ms: Option<f32>,
+    pub cpu_ms: f32,
+    pub cpu_ms_max: f32,
+    /// Missing when the GPU can't write timestamps.
+    pub gpu_ms: Option<f32>,
+    pub gpu_ms_max: Option<f32>,
+    /// As of the output's last frame.
+    pub memory: MemoryUsage,
+}
+
+/// GPU memory of the renderer, which every output shares, in MiB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Window textures uploaded, including cached ones.
+    pub textures: usize,
+    pub texture_mib: f32,
+    /// Textures of windows no output shows, kept so they show again without an upload.
+    pub cached: usize,
+    pub cached_mib: f32,
+    /// Cached textures dropped because the cache or the device's budget was full.
+    pub evicted: u64,
+    /// Device-local memory the process uses and may use; missing without
+    /// VK_EXT_memory_budget.
+    pub device_mib: Option<f32>,
+    pub budget_mib: Option<f32>,
+}
@@ -0,0 +1,41 @@
+// Replies, one line each: "ok" for a command that only succeeded, whatever the command
+// answers with, like a path or a line of JSON, or a line starting with "error: " for
+// one that failed.
+
+use std::fmt;
+
+use serde::Serialize;
+
+const ERROR: &str = "error: ";
+
+/// The reply to a command that succeeded without anything else to say.
+pub fn ok() -> String {
+    "ok\n".to_string()
+}
+
+/// The reply `text` makes, like a path a screenshot was written to.
+pub fn text(text: impl fmt::Display) -> String {
+    format!("{}\n", text)
+}
+
+/// `value` as a reply: a line of JSON.
+pub fn json<T: Serialize>(value: &T) -> String {
+    match serde_json::to_string(value) {
+        Ok(json) => format!("{}\n", json),
+        Err(e) => error(e),
+    }
+}
+
+/// The reply to a command that failed.
+pub fn error(e: impl fmt::Display) -> String {
+    format!("{}{}\n", ERROR, e)
+}
+
+/// What a reply line says: what came back, or why the command failed.
+pub fn parse(line: &str) -> Result<&str, &str> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    match line.strip_prefix(ERROR) {
+        Some(e) => Err(e),
+        None => Ok(line),
+    }
+}
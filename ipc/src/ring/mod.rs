@@ -0,0 +1,99 @@
+// The layout of the shared-memory ring window updates go through: a memfd holding a
+// `Header` and as many records as it says, which the renderer creates and hands the
+// bridge with `AttachShm`, along with an eventfd the bridge rings after each batch.
+// Bump `VERSION` when the layout changes.
+
+use std::sync::atomic::AtomicU64;
+
+use crate::renderer::{Layer, WindowState, WindowType};
+
+pub const MAGIC: u32 = 0x5154_5253;
+pub const VERSION: u32 = 2;
+
+/// Creates or updates a window; `z` is its index in the stack, bottom first.
+pub const RECORD_UPSERT: u32 = 0;
+/// Removes `window`.
+pub const RECORD_REMOVE: u32 = 1;
+/// Removes every window; sent first by a producer that just attached.
+pub const RECORD_CLEAR: u32 = 2;
+
+pub const FLAG_FOCUSED: u32 = 1;
+pub const FLAG_FULLSCREEN: u32 = 2;
+pub const FLAG_BLUR: u32 = 4;
+
+/// The start of the ring; the records follow it.
+#[repr(C)]
+pub struct Header {
+    pub magic: u32,
+    pub version: u32,
+    /// How many records the ring holds.
+    pub capacity: u32,
+    pub reserved: u32,
+    /// Records published, which the producer counts up.
+    pub head: AtomicU64,
+    /// Records applied, which the consumer counts up.
+    pub tail: AtomicU64,
+}
+
+/// One window update, as laid out in shared memory.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub kind: u32,
+    pub window: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub border_width: u32,
+    pub z: i32,
+    pub opacity: f32,
+    pub flags: u32,
+    /// A `WindowType`, as its discriminant.
+    pub window_type: u32,
+}
+
+impl Record {
+    pub fn remove(window: u32) -> Self {
+        Self {
+            kind: RECORD_REMOVE,
+            window,
+            ..Self::clear()
+        }
+    }
+
+    pub fn clear() -> Self {
+        Self {
+            kind: RECORD_CLEAR,
+            window: 0,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            border_width: 0,
+            z: 0,
+            opacity: 1.0,
+            flags: 0,
+            window_type: WindowType::Normal as u32,
+        }
+    }
+
+    /// The window an upsert describes.
+    pub fn window_state(&self) -> WindowState {
+        WindowState {
+            id: self.window,
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            border_width: self.border_width,
+            focused: self.flags & FLAG_FOCUSED != 0,
+            opacity: self.opacity,
+            window_type: WindowType::from_u32(self.window_type),
+            fullscreen: self.flags & FLAG_FULLSCREEN != 0,
+            blur: self.flags & FLAG_BLUR != 0,
+            layer: Layer::Normal,
+            visible: true,
+        }
+    }
+}
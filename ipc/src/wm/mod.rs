@@ -0,0 +1,311 @@
+// Commands on the bridge's socket. Most run a command of wm_config.toml, or act on
+// the X server themselves; a few, the queries, are answered with a line of JSON, as
+// is any command sent as a dry run.
+
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// Where the bridge listens for commands.
+pub const SOCKET: &str = "/tmp/x11rb_wm.sock";
+
+/// A command on the bridge's socket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WmCommand {
+    FocusLeft,
+    FocusRight,
+    FocusDown,
+    FocusUp,
+    FocusNext,
+    ShuffleLeft,
+    ShuffleRight,
+    ShuffleDown,
+    ShuffleUp,
+    GrowLeft,
+    GrowRight,
+    GrowDown,
+    GrowUp,
+    Normalize,
+    ToggleSplit,
+    SpawnTerminal,
+    NextLayout,
+    KillWindow,
+    ToggleFullscreen,
+    ToggleFloating,
+    ReloadConfig,
+    Shutdown,
+    SpawnRofi,
+    /// Kills the client of a window, as the compositor asks for one not responding.
+    KillClient(u32),
+    /// Shows the named workspace, as a click on the renderer's pager asks.
+    SwitchWorkspace(String),
+    /// Moves a window to the named workspace, as dropping it on the pager asks.
+    MoveToWorkspace {
+        window: u32,
+        workspace: String,
+    },
+    /// Activates a window, as a click on the renderer's taskbar asks.
+    FocusWindow(u32),
+    /// Asks a window to close, as a middle click on the taskbar does.
+    CloseWindow(u32),
+    /// Runs a shell command, as bindings for qtile's lazy.spawn do.
+    Spawn(String),
+    /// Change the volume of the default sink, with an OSD showing where it is now.
+    VolumeUp,
+    VolumeDown,
+    VolumeMute,
+    /// Change the brightness of the backlight, with an OSD.
+    BrightnessUp,
+    BrightnessDown,
+    /// Have the followed MPRIS player play or pause, or skip a track either way.
+    MediaPlayPause,
+    MediaNext,
+    MediaPrevious,
+    /// Lists the windows, workspaces, or outputs matching every filter, like
+    /// ["--workspace", "focused", "--class", "firefox"]; the reply is a line of JSON.
+    Query {
+        domain: Domain,
+        #[serde(default)]
+        filters: Vec<String>,
+    },
+    /// Lists the clipboard history, most recent first; the reply is a line of JSON.
+    ListClipboard,
+    /// The bridge's metrics in Prometheus' text format; the reply is a JSON string.
+    GetMetrics,
+    /// Puts an entry of the clipboard history, by its index, back on the clipboard.
+    SelectClipboard(usize),
+    /// Keeps the focused window above others, or stops keeping it there.
+    ToggleAlwaysOnTop,
+    /// Puts the focused window in a layer: Below, Normal, Above, or Overlay.
+    SetLayer(Layer),
+    /// Forgets where windows of a class, and of a configured title pattern if given,
+    /// last floated.
+    ForgetFloating {
+        class: String,
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// With tags as the workspace model, shows the windows with any of the tags in the
+    /// mask, tag 1 being its lowest bit, like 5 for tags 1 and 3. SwitchWorkspace then
+    /// views the one tag its name numbers, and MoveToWorkspace gives a window just it.
+    ViewTag(u32),
+    /// Adds tag n, from 1, to the focused window, or takes it off unless it's the last.
+    ToggleWindowTag(u32),
+    /// Has the focused window fake fullscreen, staying in its tile when it asks for
+    /// fullscreen, or stop faking it, whatever fake_fullscreen.classes says.
+    ToggleFakeFullscreen,
+    /// Keeps the focused window centered in its tile at its own resolution, with
+    /// letterbox bars around it, or gives it the whole tile back, whatever
+    /// centered.classes says.
+    ToggleCentered,
+    /// Makes a window, the focused one unless given, click-through, so clicks reach
+    /// what is under it, or take input again.
+    SetClickThrough {
+        #[serde(default)]
+        window: Option<u32>,
+        click_through: bool,
+    },
+    /// Has a window, the focused one unless given, take input only in rectangles,
+    /// [x, y, width, height] inside it, or everywhere again without any.
+    SetInputRegion {
+        #[serde(default)]
+        window: Option<u32>,
+        #[serde(default)]
+        rects: Option<Vec<[i32; 4]>>,
+    },
+    /// Runs a command `count` times, like FocusNext three times or GrowRight five
+    /// steps, as the renderer's count prefix sends it.
+    Repeat {
+        count: u32,
+        command: Box<WmCommand>,
+    },
+    /// Presses key combinations, separated by spaces, like "Ctrl+l" or "Ctrl+x Ctrl+s",
+    /// through XTest. Like the other synthetic input, it is only taken from connections
+    /// to the command socket by the bridge's own user, with inject.enabled on.
+    SendKey(String),
+    /// Types text through XTest.
+    SendText(String),
+    /// Moves the pointer to a point on the root window and clicks a button there, the
+    /// left one unless given.
+    ClickAt {
+        x: i16,
+        y: i16,
+        #[serde(default)]
+        button: Option<u8>,
+    },
+    /// Logs everything that happens to a window, the focused one unless given, or stops
+    /// logging it: its X events and property changes, and what the bridge decides for
+    /// it, each a line of JSON with the time it happened.
+    TraceWindow {
+        #[serde(default)]
+        window: Option<u32>,
+        on: bool,
+    },
+    /// Reverts the last action that can be: a window moved to a workspace, a
+    /// workspace switched to, a toggle of floating, fullscreen, the split, faked
+    /// fullscreen, or centering, a shuffle, a layout change, a layer, or a window
+    /// closing while undo.close_grace_ms lets it.
+    Undo,
+    /// Lists the actions Undo can revert, the last first; the reply is a line of JSON.
+    GetUndoHistory,
+    /// Starts recording the commands carried out from now on as a macro of this name.
+    RecordMacro(String),
+    /// Keeps the macro being recorded, replacing one of the same name.
+    StopRecording,
+    /// Carries out the commands of a macro again, in order.
+    PlayMacro(String),
+    DeleteMacro(String),
+    /// Lists the macros kept and the one being recorded; the reply is a line of JSON.
+    ListMacros,
+    /// Launches the apps of a configured profile and arranges their windows on its
+    /// workspaces.
+    LaunchProfile(String),
+    /// Matches the words of a query fuzzily against the titles, classes, and instances
+    /// of the windows on every workspace; the reply is a line of JSON listing those that
+    /// match, best first.
+    SearchWindows(String),
+    /// Focuses a result of SearchWindows for the query, the best unless an index is
+    /// given, switching to its workspace.
+    FocusSearchResult {
+        query: String,
+        #[serde(default)]
+        index: usize,
+    },
+    /// Waits for a window to map with the WM_CLASS class, and a title matching the
+    /// pattern, of those given, for timeout milliseconds at most, 30000 without one;
+    /// the reply is a line of JSON with its id, like {"window": 12582914}.
+    WaitForWindow {
+        #[serde(default)]
+        class: Option<String>,
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+    /// Sets where new tiled windows go among those of their group, or leaves it to the
+    /// layout again without one.
+    SetInsertionPoint(Option<InsertionPoint>),
+}
+
+impl WmCommand {
+    /// Whether the bridge answers it, with a line of JSON.
+    pub fn replies(&self) -> bool {
+        matches!(
+            self,
+            Self::Query { .. }
+                | Self::ListClipboard
+                | Self::GetMetrics
+                | Self::GetUndoHistory
+                | Self::ListMacros
+                | Self::WaitForWindow { .. }
+                | Self::SearchWindows(_)
+        )
+    }
+}
+
+/// A command as the socket takes it: a WmCommand, which an object may also give
+/// "dry_run": true, like {"SwitchWorkspace": "2", "dry_run": true}, or
+/// {"FocusLeft": null, "dry_run": true} for a command without arguments. A dry run is
+/// answered with what the command would change, and not carried out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub command: WmCommand,
+    pub dry_run: bool,
+}
+
+impl Request {
+    /// Whether the bridge answers it, with a line of JSON.
+    pub fn replies(&self) -> bool {
+        self.dry_run || self.command.replies()
+    }
+}
+
+impl From<WmCommand> for Request {
+    fn from(command: WmCommand) -> Self {
+        Self {
+            command,
+            dry_run: false,
+        }
+    }
+}
+
+impl Serialize for Request {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !self.dry_run {
+            return self.command.serialize(serializer);
+        }
+        let mut object = match serde_json::to_value(&self.command).map_err(S::Error::custom)? {
+            Value::String(name) => serde_json::Map::from_iter([(name, Value::Null)]),
+            Value::Object(object) => object,
+            _ => return Err(S::Error::custom("commands are names or objects")),
+        };
+        object.insert("dry_run".to_string(), Value::Bool(true));
+        object.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Request {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        let dry_run = match value.as_object_mut().and_then(|o| o.remove("dry_run")) {
+            Some(Value::Bool(dry_run)) => dry_run,
+            Some(_) => return Err(D::Error::custom("dry_run is true or false")),
+            None => false,
+        };
+        let command = WmCommand::deserialize(value).map_err(D::Error::custom)?;
+        Ok(Self { command, dry_run })
+    }
+}
+
+/// What a query lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Domain {
+    Windows,
+    Workspaces,
+    Outputs,
+}
+
+/// Where a new tiled window goes in its group's layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InsertionPoint {
+    /// Right after the window focused before it.
+    AfterFocused,
+    /// After all the others.
+    End,
+    /// First, as the new master.
+    Master,
+    /// Right after the largest tile, taking half of it in layouts that split tiles.
+    SwallowLargest,
+}
+
+/// Where a window stacks, bottom first.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub enum Layer {
+    Below,
+    #[default]
+    Normal,
+    Above,
+    /// Over even the windows kept on top, for things like screen recorder controls.
+    Overlay,
+}
+
+impl Layer {
+    /// The name queries use.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Below => "below",
+            Self::Normal => "normal",
+            Self::Above => "above",
+            Self::Overlay => "overlay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Below, Self::Normal, Self::Above, Self::Overlay]
+            .into_iter()
+            .find(|layer| layer.name() == name)
+    }
+}
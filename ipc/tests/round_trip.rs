@@ -0,0 +1,390 @@
+// Every command goes through JSON and back as itself, and reads from what the other
+// side has always sent: the bridge and the renderer are often built at different
+// times, and scripts and qtile configs write these by hand.
+
+use std::path::PathBuf;
+
+use qtilerugo_ipc::renderer::{
+    Annotate, Control, Corner, IconData, ImageFormat, Layer, Level, MediaInfo, MemoryUsage,
+    Notification, OutputInfo, OutputReport, Playback, PresentMode, Profile, RendererCommand, Reply,
+    Screencast, Screenshot, ScreenshotTarget, SurfaceGeometry, WindowInfo, WindowState, WindowType,
+    WorkspaceInfo,
+};
+use qtilerugo_ipc::ring::{Record, FLAG_FOCUSED, RECORD_UPSERT};
+use qtilerugo_ipc::wm::{self, Domain, Request, WmCommand};
+use qtilerugo_ipc::{encode, reply, Decoder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(message: T) {
+    let line = encode(&message);
+    assert!(line.ends_with('\n'), "{:?}", line);
+    let back: T = serde_json::from_str(&line).unwrap();
+    assert_eq!(back, message, "through {}", line);
+}
+
+fn window() -> WindowState {
+    WindowState {
+        id: 4194313,
+        x: 10,
+        y: -20,
+        width: 640,
+        height: 480,
+        border_width: 2,
+        focused: true,
+        opacity: 0.5,
+        window_type: WindowType::Dialog,
+        fullscreen: false,
+        blur: true,
+        layer: Layer::Top,
+        visible: false,
+    }
+}
+
+#[test]
+fn wm_commands() {
+    for command in [
+        WmCommand::FocusLeft,
+        WmCommand::KillClient(7),
+        WmCommand::SwitchWorkspace("2".to_string()),
+        WmCommand::MoveToWorkspace {
+            window: 7,
+            workspace: "web".to_string(),
+        },
+        WmCommand::Spawn("alacritty -e htop".to_string()),
+        WmCommand::Query {
+            domain: Domain::Windows,
+            filters: vec!["--class".to_string(), "firefox".to_string()],
+        },
+        WmCommand::SelectClipboard(3),
+        WmCommand::SetLayer(wm::Layer::Overlay),
+        WmCommand::ForgetFloating {
+            class: "mpv".to_string(),
+            title: None,
+        },
+        WmCommand::SetInputRegion {
+            window: Some(9),
+            rects: Some(vec![[0, 0, 10, 10], [20, 0, 10, 10]]),
+        },
+        WmCommand::Repeat {
+            count: 3,
+            command: Box::new(WmCommand::GrowRight),
+        },
+        WmCommand::ClickAt {
+            x: -5,
+            y: 300,
+            button: Some(3),
+        },
+        WmCommand::Undo,
+        WmCommand::RecordMacro("two-columns".to_string()),
+        WmCommand::PlayMacro("two-columns".to_string()),
+        WmCommand::LaunchProfile("dev".to_string()),
+        WmCommand::SearchWindows("fire moz".to_string()),
+        WmCommand::FocusSearchResult {
+            query: "term".to_string(),
+            index: 2,
+        },
+        WmCommand::WaitForWindow {
+            class: Some("firefox".to_string()),
+            title: Some("*Mozilla Firefox".to_string()),
+            timeout: Some(5000),
+        },
+        WmCommand::SetInsertionPoint(Some(wm::InsertionPoint::SwallowLargest)),
+        WmCommand::SetInsertionPoint(None),
+    ] {
+        round_trip(command);
+    }
+}
+
+#[test]
+fn wm_commands_as_written() {
+    let parse = |json: &str| serde_json::from_str::<WmCommand>(json).unwrap();
+    assert_eq!(parse(r#""FocusLeft""#), WmCommand::FocusLeft);
+    assert_eq!(
+        parse(r#"{"Query": {"domain": "Workspaces"}}"#),
+        WmCommand::Query {
+            domain: Domain::Workspaces,
+            filters: Vec::new(),
+        }
+    );
+    assert_eq!(
+        parse(r#"{"Repeat": {"count": 2, "command": {"SwitchWorkspace": "3"}}}"#),
+        WmCommand::Repeat {
+            count: 2,
+            command: Box::new(WmCommand::SwitchWorkspace("3".to_string())),
+        }
+    );
+    assert_eq!(
+        parse(r#"{"SetClickThrough": {"click_through": true}}"#),
+        WmCommand::SetClickThrough {
+            window: None,
+            click_through: true,
+        }
+    );
+    assert_eq!(
+        parse(r#"{"TraceWindow": {"window": 4194313, "on": true}}"#),
+        WmCommand::TraceWindow {
+            window: Some(4194313),
+            on: true,
+        }
+    );
+    assert!(serde_json::from_str::<WmCommand>(r#""FocusSideways""#).is_err());
+    assert!(WmCommand::GetMetrics.replies());
+    assert!(WmCommand::GetUndoHistory.replies());
+    assert!(WmCommand::ListMacros.replies());
+    assert!(WmCommand::SearchWindows(String::new()).replies());
+    assert!(serde_json::from_str::<WmCommand>(r#"{"WaitForWindow": {"class": "mpv"}}"#)
+        .unwrap()
+        .replies());
+    assert!(!WmCommand::FocusNext.replies());
+}
+
+#[test]
+fn dry_runs() {
+    let parse = |json: &str| serde_json::from_str::<Request>(json).unwrap();
+    let switch = WmCommand::SwitchWorkspace("2".to_string());
+    assert_eq!(parse(r#""FocusLeft""#), Request::from(WmCommand::FocusLeft));
+    assert_eq!(
+        parse(r#"{"SwitchWorkspace": "2", "dry_run": true}"#),
+        Request {
+            command: switch.clone(),
+            dry_run: true,
+        }
+    );
+    assert_eq!(
+        parse(r#"{"FocusLeft": null, "dry_run": true}"#),
+        Request {
+            command: WmCommand::FocusLeft,
+            dry_run: true,
+        }
+    );
+    round_trip(Request::from(switch.clone()));
+    for command in [switch, WmCommand::FocusLeft] {
+        round_trip(Request {
+            command,
+            dry_run: true,
+        });
+    }
+    assert!(serde_json::from_str::<Request>(r#"{"FocusLeft": null, "dry_run": 1}"#).is_err());
+    assert!(!Request::from(WmCommand::FocusLeft).replies());
+    assert!(parse(r#"{"FocusLeft": null, "dry_run": true}"#).replies());
+}
+
+#[test]
+fn renderer_commands() {
+    for command in [
+        RendererCommand::UpdateWindows(vec![window()]),
+        RendererCommand::CreateSurface {
+            id: 1,
+            geometry: SurfaceGeometry {
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 50,
+            },
+            layer: Layer::Overlay,
+        },
+        RendererCommand::SetAnimations(json!({ "map": { "duration_ms": 250 } })),
+        RendererCommand::SetPostProcessing(vec![json!("Invert")]),
+        RendererCommand::SetPresentMode(PresentMode::Mailbox),
+        RendererCommand::SetColorTemp {
+            kelvin: Some(4500.0),
+            output: None,
+        },
+        RendererCommand::SetIccProfile {
+            output: "DP-1".to_string(),
+            path: Some(PathBuf::from("/usr/share/color/icc/dp1.icc")),
+        },
+        RendererCommand::SetWorkspaces(vec![WorkspaceInfo {
+            name: "1".to_string(),
+            windows: vec![1, 2],
+        }]),
+        RendererCommand::SetWindowInfo(WindowInfo {
+            window: 3,
+            title: Some("htop".to_string()),
+            class: Some("Alacritty".to_string()),
+            instance: None,
+            urgent: None,
+            icon: Some(IconData {
+                width: 1,
+                height: 1,
+                argb: vec![0xff00_ff00],
+            }),
+        }),
+        RendererCommand::SetLevel(Level {
+            control: Control::Volume,
+            level: 1.25,
+            muted: true,
+        }),
+        RendererCommand::SetMedia(Some(MediaInfo {
+            player: "spotify".to_string(),
+            status: Playback::Paused,
+            title: "Song".to_string(),
+            artist: String::new(),
+            album: String::new(),
+        })),
+        RendererCommand::SetMedia(None),
+        RendererCommand::AddNotification(Notification {
+            app: "mail".to_string(),
+            summary: "New mail".to_string(),
+            body: String::new(),
+        }),
+        RendererCommand::PinWindow {
+            window: None,
+            corner: Some(Corner::TopLeft),
+        },
+        RendererCommand::SetWindowShape {
+            window: 5,
+            rects: None,
+        },
+        RendererCommand::SetPowerProfile(Some(Profile::PowerSaver)),
+        RendererCommand::SetOutputs(vec![OutputInfo {
+            name: "HDMI-1".to_string(),
+            x: 2560,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            refresh_mhz: Some(60000),
+        }]),
+        RendererCommand::Screenshot(Screenshot {
+            target: ScreenshotTarget::Region {
+                x: 0,
+                y: 0,
+                width: 800,
+                height: 600,
+            },
+            path: Some(PathBuf::from("/tmp/shot.jpg")),
+            format: None,
+        }),
+        RendererCommand::StartScreencast(Screencast {
+            target: ScreenshotTarget::Window(9),
+            fps: Some(60),
+        }),
+        RendererCommand::Annotate(Annotate {
+            output: Some("DP-1".to_string()),
+            path: None,
+            format: Some(ImageFormat::Png),
+        }),
+        RendererCommand::SetTheme("default-dark".to_string()),
+        RendererCommand::GetFrameStats,
+    ] {
+        round_trip(command);
+    }
+}
+
+#[test]
+fn renderer_commands_as_written() {
+    let parse = |json: &str| serde_json::from_str::<RendererCommand>(json).unwrap();
+    let RendererCommand::UpdateWindows(windows) =
+        parse(r#"{"UpdateWindows":[{"id":1,"x":10,"y":10,"width":400,"height":300}]}"#)
+    else {
+        panic!("not UpdateWindows");
+    };
+    assert_eq!(windows[0].opacity, 1.0);
+    assert!(windows[0].visible);
+    assert_eq!(windows[0].layer, Layer::Normal);
+    assert_eq!(
+        parse(r#"{"Screenshot":{"target":{"Window":4194313}}}"#),
+        RendererCommand::Screenshot(Screenshot {
+            target: ScreenshotTarget::Window(4194313),
+            path: None,
+            format: None,
+        })
+    );
+    // Pinned windows' layer is the compositor's own.
+    assert!(serde_json::from_str::<Layer>(r#""Pip""#).is_err());
+    assert!(serde_json::from_str::<RendererCommand>(
+        r#"{"SetLevel":{"control":"Volume","level":0.5,"loud":true}}"#
+    )
+    .is_err());
+    assert_eq!(parse(r#""AttachShm""#).reply(), Reply::Fds);
+    assert_eq!(parse(r#""Ping""#).reply(), Reply::Line);
+    assert_eq!(parse(r#""SelectRegion""#).reply(), Reply::Line);
+    assert_eq!(parse(r#"{"SetBusy":true}"#).reply(), Reply::None);
+}
+
+#[test]
+fn frame_stats() {
+    round_trip(vec![OutputReport {
+        output: "DP-1".to_string(),
+        frames: 1200,
+        missed_vblanks: 3,
+        refresh_ms: 6.944,
+        fps: None,
+        frame_ms: Some(7.0),
+        cpu_ms: 0.8,
+        cpu_ms_max: 2.5,
+        gpu_ms: None,
+        gpu_ms_max: None,
+        memory: MemoryUsage {
+            textures: 12,
+            texture_mib: 96.5,
+            device_mib: Some(410.0),
+            ..MemoryUsage::default()
+        },
+    }]);
+}
+
+#[test]
+fn framing() {
+    let mut decoder = Decoder::default();
+    let mut stream = encode(&WmCommand::FocusLeft);
+    // Values back to back, as netcat users send them, and one cut in half.
+    stream.push_str(r#"{"KillClient":7}"Bogus""#);
+    stream.push('\n');
+    stream.push_str(r#"{"SwitchWorkspace":"#);
+    decoder.push(stream.as_bytes());
+    assert_eq!(
+        decoder.message::<WmCommand>().unwrap().unwrap(),
+        WmCommand::FocusLeft
+    );
+    assert_eq!(
+        decoder.message::<WmCommand>().unwrap().unwrap(),
+        WmCommand::KillClient(7)
+    );
+    assert!(decoder.message::<WmCommand>().unwrap().is_err());
+    assert!(decoder.message::<WmCommand>().is_none());
+    decoder.push(b" \"4\"}\n");
+    assert_eq!(
+        decoder.message::<WmCommand>().unwrap().unwrap(),
+        WmCommand::SwitchWorkspace("4".to_string())
+    );
+    assert!(decoder.message::<WmCommand>().is_none());
+    assert!(decoder.pending().is_empty());
+}
+
+#[test]
+fn replies() {
+    assert_eq!(reply::parse(&reply::ok()), Ok("ok"));
+    assert_eq!(
+        reply::parse(&reply::text("/tmp/shot.png")),
+        Ok("/tmp/shot.png")
+    );
+    assert_eq!(reply::parse(&reply::json(&[3u32, 4])), Ok("[3,4]"));
+    assert_eq!(reply::parse(&reply::error("cancelled")), Err("cancelled"));
+}
+
+#[test]
+fn ring_records() {
+    let record = Record {
+        kind: RECORD_UPSERT,
+        window: 12,
+        x: 1,
+        y: 2,
+        width: 3,
+        height: 4,
+        border_width: 1,
+        z: 0,
+        opacity: 1.0,
+        flags: FLAG_FOCUSED,
+        window_type: WindowType::Popup as u32,
+    };
+    let state = record.window_state();
+    assert!(state.focused && !state.fullscreen);
+    assert_eq!(state.window_type, WindowType::Popup);
+    assert_eq!(
+        WindowType::from_u32(WindowType::Notification as u32),
+        WindowType::Notification
+    );
+}
@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferContents;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+
+use super::renderer::FrameError;
+use super::shader::{ShaderFile, ShaderSources};
+use crate::scene::effects::BlurLevel;
+
+/// Push constants shared by both kawase shaders.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct KawasePush {
+    half_pixel: [f32; 2],
+    offset: f32,
+}
+
+/// Dual-kawase blur of a copy of the frame drawn so far.
+pub struct Blur {
+    render_pass: Arc<RenderPass>,
+    down: Arc<GraphicsPipeline>,
+    up: Arc<GraphicsPipeline>,
+    targets: Option<Targets>,
+    /// The extent and pass count targets last failed to allocate for, so they aren't
+    /// tried again every frame.
+    failed: Option<([u32; 2], u32)>,
+}
+
+/// Images for one output size and pass count.
+struct Targets {
+    extent: [u32; 2],
+    passes: u32,
+    /// Copy of the frame being blurred.
+    capture: Arc<Image>,
+    /// Level 0 is full size and holds the result; level `i` is 1/2^i of it.
+    levels: Vec<Arc<Framebuffer>>,
+    /// `down_sets[i]` samples the input of level `i + 1`.
+    down_sets: Vec<Arc<PersistentDescriptorSet>>,
+    /// `up_sets[i]` samples level `i + 1` while level `i` is drawn.
+    up_sets: Vec<Arc<PersistentDescriptorSet>>,
+    /// Samples the result through the quad pipeline.
+    result: Arc<PersistentDescriptorSet>,
+}
+
+impl Blur {
+    /// Fails only if the blur shaders in `sources` don't build.
+    pub fn new(device: Arc<Device>, format: Format, sources: &ShaderSources) -> Result<Self, String> {
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        let down = create_kawase_pipeline(
+            device.clone(),
+            render_pass.clone(),
+            sources,
+            ShaderFile::KawaseDownFrag,
+        )?;
+        let up = create_kawase_pipeline(device, render_pass.clone(), sources, ShaderFile::KawaseUpFrag)?;
+        Ok(Self {
+            render_pass,
+            down,
+            up,
+            targets: None,
+            failed: None,
+        })
+    }
+
+    /// Allocates the images blurring `source` at `level` takes, unless they're there;
+    /// returns whether they are. Without them, what's behind is drawn unblurred.
+    pub fn prepare(
+        &mut self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        quad_layout: &Arc<DescriptorSetLayout>,
+        source: &Image,
+        level: BlurLevel,
+    ) -> Result<bool, FrameError> {
+        let extent = [source.extent()[0], source.extent()[1]];
+        if matches!(&self.targets, Some(t) if t.extent == extent && t.passes == level.passes) {
+            return Ok(true);
+        }
+        if self.failed == Some((extent, level.passes)) {
+            return Ok(false);
+        }
+        self.targets = None;
+        match self.create_targets(
+            memory_allocator,
+            set_allocator,
+            sampler,
+            quad_layout,
+            source.format(),
+            extent,
+            level.passes,
+        ) {
+            Ok(targets) => {
+                self.targets = Some(targets);
+                self.failed = None;
+                Ok(true)
+            }
+            Err(FrameError::Lost) => Err(FrameError::Lost),
+            Err(e) => {
+                eprintln!(
+                    "Failed to allocate blur for {}x{}: {}",
+                    extent[0], extent[1], e
+                );
+                self.failed = Some((extent, level.passes));
+                Ok(false)
+            }
+        }
+    }
+
+    /// Records a blur of `source`, which `prepare` made room for, and returns a set
+    /// sampling the result, laid out like `quad_layout`'s textures.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        source: Arc<Image>,
+        level: BlurLevel,
+    ) -> Result<Arc<PersistentDescriptorSet>, FrameError> {
+        let Some(targets) = &self.targets else {
+            return Err(FrameError::Failed(
+                "blur was recorded before it was prepared".into(),
+            ));
+        };
+        builder.copy_image(CopyImageInfo::images(source, targets.capture.clone()))?;
+        for i in 0..level.passes as usize {
+            self.pass(builder, &self.down, &targets.levels[i + 1], &targets.down_sets[i], level.offset)?;
+        }
+        for i in (0..level.passes as usize).rev() {
+            self.pass(builder, &self.up, &targets.levels[i], &targets.up_sets[i], level.offset)?;
+        }
+        Ok(targets.result.clone())
+    }
+
+    fn pass(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<GraphicsPipeline>,
+        target: &Arc<Framebuffer>,
+        input: &Arc<PersistentDescriptorSet>,
+        offset: f32,
+    ) -> Result<(), FrameError> {
+        let [width, height] = target.extent();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(target.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [width as f32, height as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+            )?
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                input.clone(),
+            )?
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                KawasePush {
+                    half_pixel: [0.5 / width as f32, 0.5 / height as f32],
+                    offset,
+                },
+            )?
+            .draw(3, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_targets(
+        &self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        quad_layout: &Arc<DescriptorSetLayout>,
+        format: Format,
+        extent: [u32; 2],
+        passes: u32,
+    ) -> Result<Targets, FrameError> {
+        let image = |size: [u32; 2], usage| {
+            Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [size[0].max(1), size[1].max(1), 1],
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+        };
+        let set = |layout: &Arc<DescriptorSetLayout>, view: Arc<ImageView>| {
+            PersistentDescriptorSet::new(
+                set_allocator,
+                layout.clone(),
+                [
+                    WriteDescriptorSet::image_view(0, view),
+                    WriteDescriptorSet::sampler(1, sampler.clone()),
+                ],
+                [],
+            )
+        };
+
+        let capture = image(extent, ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED)?;
+        let views = (0..=passes)
+            .map(|i| {
+                let size = [extent[0] >> i, extent[1] >> i];
+                let usage = ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED;
+                Ok(ImageView::new_default(image(size, usage)?)?)
+            })
+            .collect::<Result<Vec<_>, FrameError>>()?;
+        let levels = views
+            .iter()
+            .map(|view| {
+                Framebuffer::new(
+                    self.render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![view.clone()],
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        let down_layout = &self.down.layout().set_layouts()[0];
+        let up_layout = &self.up.layout().set_layouts()[0];
+        let capture_view = ImageView::new_default(capture.clone())?;
+        let down_sets = (0..passes as usize)
+            .map(|i| match i {
+                0 => set(down_layout, capture_view.clone()),
+                i => set(down_layout, views[i].clone()),
+            })
+            .collect::<Result<_, _>>()?;
+        let up_sets = (0..passes as usize)
+            .map(|i| set(up_layout, views[i + 1].clone()))
+            .collect::<Result<_, _>>()?;
+        let result = set(quad_layout, views[0].clone())?;
+
+        Ok(Targets {
+            extent,
+            passes,
+            capture,
+            levels,
+            down_sets,
+            up_sets,
+            result,
+        })
+    }
+}
+
+/// Builds a fullscreen-triangle pipeline running one kawase pass.
+fn create_kawase_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    sources: &ShaderSources,
+    fragment: ShaderFile,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = sources.load(device.clone(), ShaderFile::FullscreenVert)?;
+    let fs = sources.load(device.clone(), fragment)?;
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,376 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferContents;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+    SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::{Format, NumericFormat};
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::swapchain::ColorSpace;
+
+use super::renderer::FrameError;
+use super::shader::{ShaderFile, ShaderSources};
+
+/// Push constants of `hdr.frag`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct HdrPush {
+    sdr_white: f32,
+    linear_input: u32,
+    transfer: u32,
+}
+
+/// How HDR swapchain values encode light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// HDR10: PQ over BT.2020 primaries.
+    Pq,
+    /// scRGB: linear BT.709, where 1.0 is 80 nits.
+    Linear,
+}
+
+/// A swapchain format and color space an output can show HDR in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HdrFormat {
+    pub format: Format,
+    pub color_space: ColorSpace,
+    pub transfer: Transfer,
+}
+
+/// Picks HDR10 if the surface offers it, then scRGB.
+pub fn choose_format(formats: &[(Format, ColorSpace)]) -> Option<HdrFormat> {
+    const PREFERRED: [HdrFormat; 3] = [
+        HdrFormat {
+            format: Format::A2B10G10R10_UNORM_PACK32,
+            color_space: ColorSpace::Hdr10St2084,
+            transfer: Transfer::Pq,
+        },
+        HdrFormat {
+            format: Format::A2R10G10B10_UNORM_PACK32,
+            color_space: ColorSpace::Hdr10St2084,
+            transfer: Transfer::Pq,
+        },
+        HdrFormat {
+            format: Format::R16G16B16A16_SFLOAT,
+            color_space: ColorSpace::ExtendedSrgbLinear,
+            transfer: Transfer::Linear,
+        },
+    ];
+    PREFERRED
+        .into_iter()
+        .find(|hdr| formats.contains(&(hdr.format, hdr.color_space)))
+}
+
+/// An output shown in HDR: frames are composited into `frame` as usual, then converted
+/// into the swapchain image.
+pub struct HdrTarget {
+    format: HdrFormat,
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    /// The composited frame, in the format of the quad render pass.
+    frame: Arc<Framebuffer>,
+    set: Arc<PersistentDescriptorSet>,
+    /// One per swapchain image.
+    outputs: Vec<Arc<Framebuffer>>,
+    linear_input: bool,
+}
+
+impl HdrTarget {
+    /// Sets up conversion into `images`, which have `format`; `sdr_pass` is the pass
+    /// frames are composited with. Fails if the frame image can't be allocated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: Arc<Device>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        sdr_pass: &Arc<RenderPass>,
+        sources: &ShaderSources,
+        format: HdrFormat,
+        images: &[Arc<Image>],
+    ) -> Result<Self, FrameError> {
+        let render_pass = create_render_pass(device.clone(), format.format)?;
+        let pipeline = create_hdr_pipeline(device, render_pass.clone(), sources)?;
+        let (frame, set, outputs) = create_framebuffers(
+            memory_allocator,
+            set_allocator,
+            sampler,
+            sdr_pass,
+            &render_pass,
+            &pipeline,
+            images,
+        )?;
+        Ok(Self {
+            format,
+            render_pass,
+            pipeline,
+            frame,
+            set,
+            outputs,
+            linear_input: sdr_pass.attachments()[0].format.numeric_format_color()
+                == Some(NumericFormat::SRGB),
+        })
+    }
+
+    /// Follows the swapchain to new `images` of the same format; on failure the old
+    /// images are kept, and have to be replaced before the next frame.
+    pub fn resize(
+        &mut self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        sdr_pass: &Arc<RenderPass>,
+        images: &[Arc<Image>],
+    ) -> Result<(), FrameError> {
+        (self.frame, self.set, self.outputs) = create_framebuffers(
+            memory_allocator,
+            set_allocator,
+            sampler,
+            sdr_pass,
+            &self.render_pass,
+            &self.pipeline,
+            images,
+        )?;
+        Ok(())
+    }
+
+    /// Switches to the conversion shader in `sources`, keeping the current one if it
+    /// doesn't build.
+    pub fn set_shaders(
+        &mut self,
+        device: Arc<Device>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        sources: &ShaderSources,
+    ) -> Result<(), String> {
+        let pipeline = create_hdr_pipeline(device, self.render_pass.clone(), sources)?;
+        let frame_view = self.frame.attachments()[0].clone();
+        self.set =
+            frame_set(set_allocator, &pipeline, frame_view, sampler).map_err(|e| e.to_string())?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    pub fn format(&self) -> HdrFormat {
+        self.format
+    }
+
+    /// Where frames are composited before conversion.
+    pub fn frame(&self) -> &Arc<Framebuffer> {
+        &self.frame
+    }
+
+    /// Records the conversion of the composited frame into swapchain image `image_index`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: u32,
+        sdr_white_nits: f32,
+    ) -> Result<(), FrameError> {
+        let target = &self.outputs[image_index as usize];
+        let [width, height] = target.extent();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(target.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [width as f32, height as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+            )?
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.set.clone(),
+            )?
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                HdrPush {
+                    sdr_white: sdr_white_nits,
+                    linear_input: self.linear_input as u32,
+                    transfer: match self.format.transfer {
+                        Transfer::Pq => 0,
+                        Transfer::Linear => 1,
+                    },
+                },
+            )?
+            .draw(3, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+}
+
+/// Checks that the conversion shader in `sources` builds, whether or not an output
+/// shows HDR right now.
+pub fn check_shaders(device: Arc<Device>, sources: &ShaderSources) -> Result<(), String> {
+    let render_pass = create_render_pass(device.clone(), Format::R16G16B16A16_SFLOAT)
+        .map_err(|e| e.to_string())?;
+    create_hdr_pipeline(device, render_pass, sources).map(|_| ())
+}
+
+fn create_render_pass(device: Arc<Device>, format: Format) -> Result<Arc<RenderPass>, FrameError> {
+    vulkano::single_pass_renderpass!(
+        device,
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: DontCare,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .map_err(FrameError::from)
+}
+
+/// The frame image's framebuffer and a set sampling it, and one framebuffer per
+/// swapchain image.
+type Framebuffers = (
+    Arc<Framebuffer>,
+    Arc<PersistentDescriptorSet>,
+    Vec<Arc<Framebuffer>>,
+);
+
+/// Creates the frame image the size of `images`, a set sampling it, and framebuffers
+/// drawing into each of `images`.
+#[allow(clippy::too_many_arguments)]
+fn create_framebuffers(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    set_allocator: &StandardDescriptorSetAllocator,
+    sampler: &Arc<Sampler>,
+    sdr_pass: &Arc<RenderPass>,
+    hdr_pass: &Arc<RenderPass>,
+    pipeline: &Arc<GraphicsPipeline>,
+    images: &[Arc<Image>],
+) -> Result<Framebuffers, FrameError> {
+    let frame = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: sdr_pass.attachments()[0].format,
+            extent: images[0].extent(),
+            // Blur copies out of the frame, like out of SDR swapchain images.
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+    let frame_view = ImageView::new_default(frame)?;
+    let set = frame_set(set_allocator, pipeline, frame_view.clone(), sampler)?;
+    let framebuffer = |render_pass: &Arc<RenderPass>, view: Arc<ImageView>| {
+        Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![view],
+                ..Default::default()
+            },
+        )
+    };
+    let outputs = images
+        .iter()
+        .map(|image| framebuffer(hdr_pass, ImageView::new_default(image.clone())?))
+        .collect::<Result<_, _>>()?;
+    Ok((framebuffer(sdr_pass, frame_view)?, set, outputs))
+}
+
+/// A set sampling the composited frame through `pipeline`.
+fn frame_set(
+    set_allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<GraphicsPipeline>,
+    frame_view: Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+) -> Result<Arc<PersistentDescriptorSet>, FrameError> {
+    PersistentDescriptorSet::new(
+        set_allocator,
+        pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::image_view(0, frame_view),
+            WriteDescriptorSet::sampler(1, sampler.clone()),
+        ],
+        [],
+    )
+    .map_err(FrameError::from)
+}
+
+/// Builds the fullscreen-triangle pipeline running `hdr.frag`.
+fn create_hdr_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    sources: &ShaderSources,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = sources.load(device.clone(), ShaderFile::FullscreenVert)?;
+    let fs = sources.load(device.clone(), ShaderFile::HdrFrag)?;
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,135 @@
+// GPU memory: how much of the device's local memory the process uses and may use, as
+// VK_EXT_memory_budget tells it, which vulkano doesn't wrap, so it is read through
+// vulkano's raw entry point, into structures laid out as the Vulkan headers have them.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::DeviceExtensions;
+use vulkano::memory::MemoryHeapFlags;
+use vulkano::{Version, VulkanObject};
+
+/// Device-local memory of the process, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    /// What the process has allocated.
+    pub usage: u64,
+    /// What it can allocate before the driver starts paging or failing, which changes
+    /// as other processes allocate.
+    pub budget: u64,
+}
+
+/// VK_MAX_MEMORY_TYPES and VK_MAX_MEMORY_HEAPS.
+const MAX_MEMORY_TYPES: usize = 32;
+const MAX_MEMORY_HEAPS: usize = 16;
+
+/// VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_PROPERTIES_2.
+const MEMORY_PROPERTIES_2: i32 = 1000059004;
+/// VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT.
+const MEMORY_BUDGET_PROPERTIES: i32 = 1000237000;
+
+/// VkMemoryType.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MemoryType {
+    property_flags: u32,
+    heap_index: u32,
+}
+
+/// VkMemoryHeap.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MemoryHeap {
+    size: u64,
+    flags: u32,
+}
+
+/// VkPhysicalDeviceMemoryProperties2, with the VkPhysicalDeviceMemoryProperties in it.
+#[repr(C)]
+struct MemoryProperties2 {
+    s_type: i32,
+    p_next: *mut c_void,
+    memory_type_count: u32,
+    memory_types: [MemoryType; MAX_MEMORY_TYPES],
+    memory_heap_count: u32,
+    memory_heaps: [MemoryHeap; MAX_MEMORY_HEAPS],
+}
+
+/// VkPhysicalDeviceMemoryBudgetPropertiesEXT.
+#[repr(C)]
+struct MemoryBudgetProperties {
+    s_type: i32,
+    p_next: *mut c_void,
+    heap_budget: [u64; MAX_MEMORY_HEAPS],
+    heap_usage: [u64; MAX_MEMORY_HEAPS],
+}
+
+/// The extension to enable on the device so its budget can be read, if it has it.
+pub fn budget_extension(physical: &PhysicalDevice) -> DeviceExtensions {
+    DeviceExtensions {
+        ext_memory_budget: physical.supported_extensions().ext_memory_budget,
+        ..DeviceExtensions::empty()
+    }
+}
+
+/// The budget of the device-local heaps of `physical`, or `None` if it has no
+/// VK_EXT_memory_budget.
+pub fn device_budget(physical: &PhysicalDevice) -> Option<Budget> {
+    if !physical.supported_extensions().ext_memory_budget {
+        return None;
+    }
+    let instance = physical.instance();
+    let fns = instance.fns();
+    let mut budget = MemoryBudgetProperties {
+        s_type: MEMORY_BUDGET_PROPERTIES,
+        p_next: ptr::null_mut(),
+        heap_budget: [0; MAX_MEMORY_HEAPS],
+        heap_usage: [0; MAX_MEMORY_HEAPS],
+    };
+    let mut properties = MemoryProperties2 {
+        s_type: MEMORY_PROPERTIES_2,
+        p_next: ptr::addr_of_mut!(budget).cast(),
+        memory_type_count: 0,
+        memory_types: [MemoryType {
+            property_flags: 0,
+            heap_index: 0,
+        }; MAX_MEMORY_TYPES],
+        memory_heap_count: 0,
+        memory_heaps: [MemoryHeap { size: 0, flags: 0 }; MAX_MEMORY_HEAPS],
+    };
+    let properties = ptr::addr_of_mut!(properties).cast();
+    // Safety: both structures are laid out as Vulkan has them and outlive the call,
+    // which fills them in and keeps neither.
+    unsafe {
+        if instance.api_version() >= Version::V1_1 {
+            (fns.v1_1.get_physical_device_memory_properties2)(physical.handle(), properties);
+        } else if instance
+            .enabled_extensions()
+            .khr_get_physical_device_properties2
+        {
+            (fns.khr_get_physical_device_properties2
+                .get_physical_device_memory_properties2_khr)(
+                physical.handle(), properties
+            );
+        } else {
+            return None;
+        }
+    }
+    let local = physical
+        .memory_properties()
+        .memory_heaps
+        .iter()
+        .enumerate()
+        .filter(|(_, heap)| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|(index, _)| index);
+    let mut total = Budget {
+        usage: 0,
+        budget: 0,
+    };
+    for index in local {
+        total.usage += budget.heap_usage[index];
+        total.budget += budget.heap_budget[index];
+    }
+    Some(total)
+}
@@ -0,0 +1,888 @@
+pub mod blur;
+pub mod hdr;
+pub mod memory;
+pub mod post;
+pub mod power;
+pub mod renderer;
+pub mod shader;
+pub mod text;
+pub mod timestamps;
+pub mod vrr;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use tokio::sync::oneshot;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{
+    ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget,
+};
+use winit::window::{Fullscreen, Window, WindowBuilder, WindowId};
+
+use crate::config::{GpuPreference, PresentMode};
+use crate::input;
+use crate::lock;
+use crate::scene::annotate::Annotated;
+use crate::scene::bar::BarButton;
+use crate::scene::picker::Sample;
+use crate::scene::{DamageWaker, NodeId, OutputInfo, SharedScene};
+use crate::screenshot::{Capture, ScreenshotTarget};
+use power::Power;
+use renderer::Renderer;
+use vrr::{OutputVrr, Vrr};
+
+/// Refresh rate assumed when neither the WM nor the display reports one.
+const DEFAULT_REFRESH_MHZ: u32 = 60_000;
+
+/// How often the X server is asked how long ago its last input was.
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+/// Typed by the Escape key, which puts the color picker and the calendar popup away.
+const ESCAPE: char = '\u{1b}';
+/// Pixels of touchpad scrolling that make a line.
+const SCROLL_LINE: f64 = 20.0;
+/// How long after a failed attempt to get past a lost device the next one is made,
+/// doubling with every failure up to `RECOVERY_MAX`.
+const RECOVERY_MIN: Duration = Duration::from_millis(250);
+const RECOVERY_MAX: Duration = Duration::from_secs(10);
+
+/// What other threads ask of the render loop.
+#[derive(Debug)]
+pub enum LoopRequest {
+    /// Opens a window that renders the scene as a new output.
+    SpawnWindow,
+    /// The WM reported a new monitor list with `SetOutputs`.
+    OutputsChanged,
+    /// Window contents changed, for outputs that draw as soon as they do.
+    Damage,
+    /// Asks for the X ids of the output windows, which the loop only sends while it
+    /// is still running.
+    Ping(oneshot::Sender<Vec<u32>>),
+}
+
+/// One window the scene is rendered into.
+struct OutputWindow {
+    /// The monitor it covers, or none for a window opened with `SpawnWindow`.
+    info: Option<OutputInfo>,
+    node: NodeId,
+    window: Arc<Window>,
+    interval: Duration,
+    /// When this output's frame clock next ticks.
+    next_frame: Instant,
+    /// When it last asked for a frame.
+    last_frame: Instant,
+    vrr: OutputVrr,
+    drawn_generation: Option<u64>,
+    /// Whether the X server's cursor is hidden over the window, for the scene's.
+    cursor_hidden: bool,
+}
+
+/// Output windows left without a swapchain since the device was lost, and when they
+/// are tried again.
+struct Recovery {
+    detached: Vec<WindowId>,
+    next: Instant,
+    delay: Duration,
+}
+
+/// The one event loop every output window lives in, sharing one renderer and so one
+/// Vulkan instance and device. winit wants it on the main thread; other threads reach
+/// it through `proxy`.
+pub struct RenderLoop {
+    event_loop: EventLoop<LoopRequest>,
+}
+
+impl RenderLoop {
+    pub fn new() -> Self {
+        Self {
+            event_loop: EventLoopBuilder::with_user_event().build(),
+        }
+    }
+
+    pub fn proxy(&self) -> EventLoopProxy<LoopRequest> {
+        self.event_loop.create_proxy()
+    }
+
+    /// Renders the scene into every window it's asked to open, following the monitors
+    /// the WM reports as they change, until the process exits.
+    pub fn run(self, scene: SharedScene) -> ! {
+        let mut renderer: Option<Renderer> = None;
+        let mut outputs: HashMap<WindowId, OutputWindow> = HashMap::new();
+        let mut recovery: Option<Recovery> = None;
+        let mut animating = false;
+        let mut locked = false;
+        let vrr = Vrr::connect()
+            .map_err(|e| eprintln!("Variable refresh is unavailable: {}", e))
+            .ok();
+        let proxy = self.event_loop.create_proxy();
+        let mut waking_on_damage = false;
+        let power = Power::connect()
+            .map_err(|e| eprintln!("Idle detection only counts reported input: {}", e))
+            .ok();
+        let mut next_idle_poll = Instant::now();
+        let mut x_idle = Duration::ZERO;
+        let mut displays_off = false;
+
+        self.event_loop.run(move |event, target, control_flow| match event {
+            Event::UserEvent(LoopRequest::SpawnWindow) => {
+                match spawn_window(&scene, target, &mut renderer) {
+                    Ok(output) => {
+                        println!("Created a new window with Vulkan support.");
+                        outputs.insert(output.window.id(), output);
+                    }
+                    Err(e) => eprintln!("Failed to create window: {}", e),
+                }
+            }
+            Event::UserEvent(LoopRequest::OutputsChanged) => {
+                reconcile_outputs(&scene, target, &mut renderer, &mut outputs);
+            }
+            Event::UserEvent(LoopRequest::Ping(reply)) => {
+                let windows = outputs.values().filter_map(|o| x11_window_id(&o.window));
+                let _ = reply.send(windows.collect());
+            }
+            // Monitor windows stay until the WM reports the monitor gone.
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::CloseRequested,
+            } if outputs.get(&window_id).is_some_and(|o| o.info.is_none()) => {
+                println!("Window closed.");
+                let output = outputs.remove(&window_id).unwrap();
+                close_output(&scene, &mut renderer, &output);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::Resized(size),
+            } => {
+                if let Some(output) = outputs.get(&window_id) {
+                    scene
+                        .lock()
+                        .unwrap()
+                        .resize_output(output.node, size.width, size.height);
+                    if let Some(renderer) = renderer.as_mut() {
+                        renderer.resize(output.node);
+                    }
+                }
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::CursorMoved { position, .. },
+            } => {
+                if let Some(output) = outputs.get(&window_id) {
+                    scene.lock().unwrap().set_output_pointer(
+                        output.node,
+                        position.x as f32,
+                        position.y as f32,
+                    );
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } => {
+                let mut locked = scene.lock().unwrap();
+                locked.report_activity(Instant::now());
+                if state == ElementState::Pressed && locked.picker_clicked() {
+                    return;
+                }
+                // A region selection takes the button, and an annotation draws with it.
+                let pressed = state == ElementState::Pressed;
+                if locked.selection_button(pressed) || locked.annotation_button(pressed) {
+                    return;
+                }
+                // The bar takes presses on it, and their releases wherever they are.
+                drop(locked);
+                if input::bar_button(&scene, BarButton::Left, pressed) {
+                    return;
+                }
+                let mut locked = scene.lock().unwrap();
+                let force_close = match state {
+                    ElementState::Pressed => locked.force_close_at_pointer(),
+                    ElementState::Released => None,
+                };
+                // Drags pinned windows, when the output windows get the pointer.
+                match (force_close, state) {
+                    (Some(window), _) => {
+                        drop(locked);
+                        if let Err(e) = input::force_close(&scene, window) {
+                            eprintln!("Not closing window {}: {}", window, e);
+                        }
+                    }
+                    (None, ElementState::Pressed) => {
+                        locked.pointer_pressed();
+                    }
+                    (None, ElementState::Released) => {
+                        locked.pointer_released();
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { state, button, .. },
+                ..
+            } => {
+                let pressed = state == ElementState::Pressed;
+                {
+                    let mut scene = scene.lock().unwrap();
+                    scene.report_activity(Instant::now());
+                    // Any other button puts the color picker or region selection away.
+                    if pressed && (scene.cancel_picking() || scene.cancel_selecting()) {
+                        return;
+                    }
+                }
+                let bar_button = match button {
+                    MouseButton::Middle => Some(BarButton::Middle),
+                    MouseButton::Right => Some(BarButton::Right),
+                    _ => None,
+                };
+                if let Some(bar_button) = bar_button {
+                    input::bar_button(&scene, bar_button, pressed);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { .. },
+                ..
+            } => scene.lock().unwrap().report_activity(Instant::now()),
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let mut scene = scene.lock().unwrap();
+                scene.report_activity(Instant::now());
+                // Scrolling down has negative deltas.
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => -y,
+                    MouseScrollDelta::PixelDelta(position) => (-position.y / SCROLL_LINE) as f32,
+                };
+                scene.calendar_scrolled(lines);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ReceivedCharacter(c),
+                ..
+            } => {
+                let cancelled = {
+                    let mut scene = scene.lock().unwrap();
+                    scene.annotation_typed(c)
+                        || (c == ESCAPE
+                            && (scene.cancel_picking()
+                                || scene.cancel_selecting()
+                                || scene.close_calendar()))
+                };
+                if !cancelled {
+                    lock::key_typed(&scene, c);
+                }
+            }
+            Event::MainEventsCleared => {
+                if let Some(lost) = renderer.take_if(|renderer| renderer.lost()) {
+                    eprintln!("Lost the Vulkan device; creating a new one.");
+                    lost.discard();
+                    recovery = Some(Recovery {
+                        detached: outputs.keys().copied().collect(),
+                        next: Instant::now(),
+                        delay: RECOVERY_MIN,
+                    });
+                }
+                let due = recovery.as_mut().filter(|retry| Instant::now() >= retry.next);
+                if let Some(retry) = due {
+                    recover_renderer(&scene, &mut renderer, &mut outputs, retry);
+                    if retry.detached.is_empty() {
+                        recovery = None;
+                    }
+                }
+                serve_screenshots(&scene, renderer.as_mut());
+                serve_previews(&scene, renderer.as_mut());
+                serve_picker(&scene, renderer.as_mut());
+                // Drawing on an annotation takes the keyboard.
+                if let Some(node) = serve_annotations(&scene, renderer.as_mut()) {
+                    if let Some(output) = outputs.values().find(|output| output.node == node) {
+                        output.window.focus_window();
+                    }
+                }
+                serve_screencasts(&scene, renderer.as_mut());
+                if renderer.as_mut().is_some_and(Renderer::reload_shaders) {
+                    for output in outputs.values_mut() {
+                        output.drawn_generation = None;
+                    }
+                }
+
+                // Each output redraws on its own clock, and only if there is something new.
+                let now = Instant::now();
+                // Input anywhere resets the X server's idle time, whoever it went to.
+                let mut input_at = None;
+                if let Some(power) = power.as_ref().filter(|_| now >= next_idle_poll) {
+                    next_idle_poll = now + IDLE_POLL;
+                    if let Some(idle) = power.idle_time() {
+                        if idle < x_idle {
+                            input_at = now.checked_sub(idle);
+                        }
+                        x_idle = idle;
+                    }
+                }
+                let (generation, now_locked, cursor_drawn, vrr_windows, now_off, max_fps) = {
+                    let mut scene = scene.lock().unwrap();
+                    scene.update_night_light(now);
+                    scene.update_wallpapers(now);
+                    scene.update_bar(now);
+                    scene.update_selection();
+                    if let Some(at) = input_at {
+                        scene.report_activity(at);
+                    }
+                    scene.update_idle(now);
+                    let vrr_windows: HashMap<NodeId, u32> = outputs
+                        .values()
+                        .filter_map(|output| Some((output.node, scene.vrr_window(output.node)?)))
+                        .collect();
+                    (
+                        scene.generation(),
+                        scene.locked(),
+                        scene.cursor_drawn(),
+                        vrr_windows,
+                        scene.displays_off(),
+                        scene.max_fps(),
+                    )
+                };
+                if now_off != displays_off {
+                    println!("Powering displays {}.", if now_off { "down" } else { "up" });
+                    if let Err(e) = power.as_ref().map_or(Ok(()), |p| p.set_displays_on(!now_off)) {
+                        eprintln!("Failed to switch display power: {}", e);
+                    }
+                    displays_off = now_off;
+                }
+                // The password is typed into an output window, so one of them needs the focus.
+                if now_locked && !locked {
+                    if let Some(output) = outputs.values().next() {
+                        output.window.focus_window();
+                    }
+                }
+                locked = now_locked;
+                for output in outputs.values_mut() {
+                    if output.cursor_hidden != cursor_drawn {
+                        output.window.set_cursor_visible(!cursor_drawn);
+                        output.cursor_hidden = cursor_drawn;
+                    }
+                    if let (Some(vrr), Some(id)) = (&vrr, x11_window_id(&output.window)) {
+                        let client = vrr_windows.get(&output.node).copied();
+                        if output.vrr.update(vrr, &output.window, id, client) {
+                            println!(
+                                "Variable refresh {} for output {}.",
+                                if output.vrr.active() { "on" } else { "off" },
+                                output.info.as_ref().map_or("window", |info| &info.name)
+                            );
+                        }
+                    }
+                    let redraw = animating || output.drawn_generation != Some(generation);
+                    // A frame cap, configured or the power saver profile's, draws less
+                    // often than the display refreshes.
+                    let interval = max_fps.map_or(output.interval, |fps| {
+                        output.interval.max(Duration::from_secs(1) / fps)
+                    });
+                    if output.vrr.active() || interval > output.interval {
+                        // Frames follow the window's damage as soon as it comes in, no
+                        // faster than the refresh rate or the cap, rather than waiting
+                        // for the next tick; with variable refresh the display follows.
+                        let earliest = output.last_frame + interval;
+                        if !redraw {
+                            output.next_frame = now + interval;
+                            continue;
+                        }
+                        if now < earliest {
+                            output.next_frame = earliest;
+                            continue;
+                        }
+                        output.next_frame = now + interval;
+                    } else {
+                        if now < output.next_frame {
+                            continue;
+                        }
+                        output.next_frame = (output.next_frame + interval).max(now);
+                        if !redraw {
+                            continue;
+                        }
+                    }
+                    output.last_frame = now;
+                    output.window.request_redraw();
+                }
+                let waking =
+                    max_fps.is_some() || outputs.values().any(|output| output.vrr.active());
+                if waking != waking_on_damage {
+                    let waker = waking.then(|| {
+                        let proxy = proxy.clone();
+                        DamageWaker(Box::new(move || {
+                            let _ = proxy.send_event(LoopRequest::Damage);
+                        }))
+                    });
+                    scene.lock().unwrap().set_damage_waker(waker);
+                    waking_on_damage = waking;
+                }
+                // Without windows there is nothing to draw until a request comes in.
+                let retry = recovery.as_ref().map(|retry| retry.next);
+                *control_flow = match outputs.values().map(|o| o.next_frame).chain(retry).min() {
+                    Some(next) => ControlFlow::WaitUntil(next),
+                    None => ControlFlow::Wait,
+                };
+            }
+            Event::RedrawRequested(window_id) => {
+                let (Some(output), Some(renderer)) =
+                    (outputs.get_mut(&window_id), renderer.as_mut())
+                else {
+                    return;
+                };
+                let now = Instant::now();
+                let continuous = animating;
+                let (generation, items, post, lut) = {
+                    let mut scene = scene.lock().unwrap();
+                    animating = scene.advance(now);
+                    renderer.set_present_mode(scene.present_mode());
+                    renderer.set_hdr(scene.hdr());
+                    renderer.set_memory_config(scene.memory_config());
+                    renderer.set_direct_scanout(scene.direct_scanout());
+                    if let Some(color) = scene.clear_color() {
+                        renderer.set_clear_color(color);
+                    }
+                    (
+                        scene.generation(),
+                        scene.draw_list(output.node),
+                        scene.post_passes(output.node),
+                        scene.color_lut(output.node),
+                    )
+                };
+                if let Some(timing) =
+                    renderer.draw_frame(output.node, &items, &post, lut.as_deref())
+                {
+                    scene.lock().unwrap().record_frame(
+                        output.node,
+                        now,
+                        timing,
+                        output.interval,
+                        continuous,
+                    );
+                }
+                output.drawn_generation = Some(generation);
+            }
+            _ => {}
+        })
+    }
+}
+
+impl Default for RenderLoop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Opens windows for newly reported monitors and closes those for monitors that went away.
+fn reconcile_outputs(
+    scene: &SharedScene,
+    target: &EventLoopWindowTarget<LoopRequest>,
+    renderer: &mut Option<Renderer>,
+    outputs: &mut HashMap<WindowId, OutputWindow>,
+) {
+    let monitors = scene.lock().unwrap().monitors().to_vec();
+    outputs.retain(|_, output| {
+        let Some(info) = &output.info else {
+            return true;
+        };
+        if monitors.contains(info) {
+            return true;
+        }
+        println!("Removing output {}.", info.name);
+        close_output(scene, renderer, output);
+        false
+    });
+
+    for info in monitors {
+        if outputs.values().any(|output| output.info.as_ref() == Some(&info)) {
+            continue;
+        }
+        match open_output(scene, target, renderer, &info) {
+            Ok(output) => {
+                println!(
+                    "Rendering output {} ({}x{} at {},{}).",
+                    info.name, info.width, info.height, info.x, info.y
+                );
+                outputs.insert(output.window.id(), output);
+            }
+            Err(e) => eprintln!("Failed to open output {}: {}", info.name, e),
+        }
+    }
+}
+
+/// Opens a window covering the monitor `info` describes.
+fn open_output(
+    scene: &SharedScene,
+    target: &EventLoopWindowTarget<LoopRequest>,
+    renderer: &mut Option<Renderer>,
+    info: &OutputInfo,
+) -> Result<OutputWindow, String> {
+    // Cover the monitor at the same position, or a window of the same size if winit
+    // doesn't see one there.
+    let monitor = target.available_monitors().find(|m| {
+        m.position() == PhysicalPosition::new(info.x, info.y)
+            && m.size() == PhysicalSize::new(info.width, info.height)
+    });
+    let builder = WindowBuilder::new()
+        .with_title(format!("qtilerugo output {}", info.name))
+        .with_decorations(false)
+        .with_position(PhysicalPosition::new(info.x, info.y))
+        .with_inner_size(PhysicalSize::new(info.width, info.height));
+    let builder = match &monitor {
+        Some(monitor) => builder.with_fullscreen(Some(Fullscreen::Borderless(Some(monitor.clone())))),
+        None => builder,
+    };
+    let window = Arc::new(builder.build(target).map_err(|e| e.to_string())?);
+
+    let node = add_output(scene, renderer, &info.name, &window)?;
+    scene
+        .lock()
+        .unwrap()
+        .set_output_position(node, info.x, info.y);
+    let refresh = info
+        .refresh_mhz
+        .or_else(|| monitor.and_then(|m| m.refresh_rate_millihertz()));
+    Ok(OutputWindow {
+        info: Some(info.clone()),
+        node,
+        window,
+        interval: frame_interval(refresh),
+        next_frame: Instant::now(),
+        last_frame: Instant::now(),
+        vrr: OutputVrr::default(),
+        drawn_generation: None,
+        cursor_hidden: false,
+    })
+}
+
+/// Opens a plain window that renders the scene as a new output.
+fn spawn_window(
+    scene: &SharedScene,
+    target: &EventLoopWindowTarget<LoopRequest>,
+    renderer: &mut Option<Renderer>,
+) -> Result<OutputWindow, String> {
+    let window = WindowBuilder::new()
+        .with_title("Rust-Created Window")
+        .build(target)
+        .map_err(|e| e.to_string())?;
+    let window = Arc::new(window);
+    let node = add_output(scene, renderer, "window", &window)?;
+    let refresh = window
+        .current_monitor()
+        .and_then(|m| m.refresh_rate_millihertz());
+    Ok(OutputWindow {
+        info: None,
+        node,
+        window,
+        interval: frame_interval(refresh),
+        next_frame: Instant::now(),
+        last_frame: Instant::now(),
+        vrr: OutputVrr::default(),
+        drawn_generation: None,
+        cursor_hidden: false,
+    })
+}
+
+/// Adds `window` to the scene as an output named `name` and gives it a swapchain,
+/// creating the renderer with the first one.
+fn add_output(
+    scene: &SharedScene,
+    renderer: &mut Option<Renderer>,
+    name: &str,
+    window: &Arc<Window>,
+) -> Result<NodeId, String> {
+    let (node, present_mode, gpu, shader_dir) = {
+        let mut scene = scene.lock().unwrap();
+        let size = window.inner_size();
+        let node = scene.add_output(name, size.width, size.height, x11_window_id(window));
+        let shader_dir = scene.shader_dir().map(Path::to_path_buf);
+        (node, scene.present_mode(), scene.gpu(), shader_dir)
+    };
+    if let Err(e) = attach(renderer, node, window, present_mode, gpu, shader_dir.as_deref()) {
+        scene.lock().unwrap().remove(node);
+        return Err(e);
+    }
+    Ok(node)
+}
+
+/// Gives `window` a swapchain showing `node`, creating the renderer with the first one.
+fn attach(
+    renderer: &mut Option<Renderer>,
+    node: NodeId,
+    window: &Arc<Window>,
+    present_mode: PresentMode,
+    gpu: GpuPreference,
+    shader_dir: Option<&Path>,
+) -> Result<(), String> {
+    match renderer {
+        Some(renderer) => renderer.add_output(node, window.clone()),
+        None => {
+            *renderer = Some(Renderer::new(window.clone(), node, present_mode, gpu, shader_dir)?);
+            Ok(())
+        }
+    }
+}
+
+/// Gives the windows `recovery` left detached a swapchain on a new device, creating
+/// the renderer with the first one. Everything the lost one drew is in the scene, so
+/// window contents are uploaded again as they're drawn. Windows that fail stay
+/// detached until the next attempt, which comes later after every failure.
+fn recover_renderer(
+    scene: &SharedScene,
+    renderer: &mut Option<Renderer>,
+    outputs: &mut HashMap<WindowId, OutputWindow>,
+    recovery: &mut Recovery,
+) {
+    let (present_mode, gpu, shader_dir) = {
+        let scene = scene.lock().unwrap();
+        let shader_dir = scene.shader_dir().map(Path::to_path_buf);
+        (scene.present_mode(), scene.gpu(), shader_dir)
+    };
+    // Windows closed meanwhile are forgotten.
+    recovery.detached.retain(|id| {
+        let Some(output) = outputs.get_mut(id) else {
+            return false;
+        };
+        output.drawn_generation = None;
+        let attached = attach(
+            renderer,
+            output.node,
+            &output.window,
+            present_mode,
+            gpu,
+            shader_dir.as_deref(),
+        );
+        match attached {
+            Ok(()) => false,
+            Err(e) => {
+                eprintln!("Failed to render into a window again: {}", e);
+                true
+            }
+        }
+    });
+    if !recovery.detached.is_empty() {
+        eprintln!("Trying again in {} ms.", recovery.delay.as_millis());
+        recovery.next = Instant::now() + recovery.delay;
+        recovery.delay = (recovery.delay * 2).min(RECOVERY_MAX);
+    }
+}
+
+/// Drops `output`'s swapchain and takes it out of the scene; its window closes when
+/// `output` is dropped.
+fn close_output(scene: &SharedScene, renderer: &mut Option<Renderer>, output: &OutputWindow) {
+    if let Some(renderer) = renderer.as_mut() {
+        renderer.remove_output(output.node);
+    }
+    scene.lock().unwrap().remove(output.node);
+}
+
+/// Answers queued screenshots, rendering output and region targets offscreen from
+/// the current scene.
+fn serve_screenshots(scene: &SharedScene, mut renderer: Option<&mut Renderer>) {
+    let requests = scene.lock().unwrap().take_screenshots();
+    for request in requests {
+        let result = capture_target(scene, renderer.as_deref_mut(), &request.target);
+        // The requester may have given up waiting.
+        let _ = request.reply.send(result);
+    }
+}
+
+/// Answers queued window previews, rendering each window offscreen on its own.
+fn serve_previews(scene: &SharedScene, mut renderer: Option<&mut Renderer>) {
+    let requests = scene.lock().unwrap().take_previews();
+    for request in requests {
+        let result = renderer
+            .as_deref_mut()
+            .ok_or_else(|| "no renderer is running yet".to_string())
+            .and_then(|renderer| {
+                let (items, extent) = scene
+                    .lock()
+                    .unwrap()
+                    .preview_list(request.window, request.max_size)?;
+                renderer.capture(&items, extent)
+            });
+        // The requester may have given up waiting.
+        let _ = request.reply.send(result);
+    }
+}
+
+/// Reads the pixels around the pointer for the color picker's loupe, when it needs
+/// them.
+fn serve_picker(scene: &SharedScene, renderer: Option<&mut Renderer>) {
+    let now = Instant::now();
+    let Some((output, pixel, pointer)) = scene.lock().unwrap().picker_target(now) else {
+        return;
+    };
+    let sample = capture_frame(scene, renderer, output)
+        .map(|capture| Sample::read(&capture, pixel, pointer, now));
+    scene.lock().unwrap().set_picker_sample(sample);
+}
+
+/// Freezes the outputs Annotate commands asked for, and renders and hands back the
+/// annotation finished; returns the output an annotation started on.
+fn serve_annotations(scene: &SharedScene, mut renderer: Option<&mut Renderer>) -> Option<NodeId> {
+    let mut started = None;
+    let requests = scene.lock().unwrap().take_annotation_requests();
+    for request in requests {
+        let output = scene
+            .lock()
+            .unwrap()
+            .annotation_output(request.output.as_deref());
+        let Some(output) = output else {
+            let _ = request.reply.send(Err(match request.output {
+                Some(name) => format!("no output {}", name),
+                None => "no outputs".into(),
+            }));
+            continue;
+        };
+        match capture_frame(scene, renderer.as_deref_mut(), output) {
+            Ok(frame) => {
+                let mut scene = scene.lock().unwrap();
+                scene.start_annotation(output, &frame, request.reply);
+                started = scene.annotating();
+            }
+            Err(e) => {
+                let _ = request.reply.send(Err(e));
+            }
+        }
+    }
+    let finished = scene.lock().unwrap().take_finished_annotation();
+    if let Some(rendering) = finished {
+        let result = renderer
+            .ok_or_else(|| "no renderer is running yet".to_string())
+            .and_then(|renderer| renderer.capture(&rendering.items, rendering.extent))
+            .map(|capture| Annotated {
+                capture,
+                finish: rendering.finish,
+            });
+        // The requester may have given up waiting.
+        let _ = rendering.reply.send(result);
+    }
+    started
+}
+
+/// The composited frame of `output`, without the cursor and the loupe.
+fn capture_frame(
+    scene: &SharedScene,
+    renderer: Option<&mut Renderer>,
+    output: NodeId,
+) -> Result<Capture, String> {
+    let renderer = renderer.ok_or("no renderer is running yet")?;
+    let (items, extent) = {
+        let scene = scene.lock().unwrap();
+        let (width, height) = scene.output_size(output).ok_or("the output went away")?;
+        (scene.frame_list(output), [width, height])
+    };
+    renderer.capture(&items, extent)
+}
+
+/// Sends a frame to every screencast that is due one; screencasts whose target
+/// went away end.
+fn serve_screencasts(scene: &SharedScene, mut renderer: Option<&mut Renderer>) {
+    let (due, locked) = {
+        let mut scene = scene.lock().unwrap();
+        let generation = scene.generation();
+        let due = scene.screencasts_mut().due(Instant::now(), generation);
+        (due, scene.locked())
+    };
+    for (id, target) in due {
+        // Window streams pause while locked; outputs and regions show the lock surface.
+        if locked && matches!(target, ScreenshotTarget::Window(_)) {
+            continue;
+        }
+        let capture = capture_target(scene, renderer.as_deref_mut(), &target);
+        let mut scene = scene.lock().unwrap();
+        match capture {
+            Ok(capture) => scene.screencasts_mut().send(id, capture),
+            Err(e) => {
+                println!("Screencast {} ended: {}", id, e);
+                scene.screencasts_mut().remove(id);
+            }
+        }
+    }
+}
+
+fn capture_target(
+    scene: &SharedScene,
+    renderer: Option<&mut Renderer>,
+    target: &ScreenshotTarget,
+) -> Result<Capture, String> {
+    match target {
+        ScreenshotTarget::Window(window) => {
+            let scene = scene.lock().unwrap();
+            if scene.locked() {
+                return Err("the screen is locked".into());
+            }
+            scene
+                .window_contents(*window)
+                .map(Capture::from_contents)
+                .ok_or_else(|| format!("window {} has no contents", window))
+        }
+        ScreenshotTarget::Output(name) => {
+            let output = scene.lock().unwrap().output_named(name.as_deref());
+            match output {
+                Some(output) => capture_output(scene, renderer, output),
+                None => Err(match name {
+                    Some(name) => format!("no output {}", name),
+                    None => "no outputs".into(),
+                }),
+            }
+        }
+        ScreenshotTarget::Region {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            let output = scene.lock().unwrap().output_at(*x, *y);
+            match output {
+                Some(output) => capture_output(scene, renderer, output).and_then(|capture| {
+                    let scene = scene.lock().unwrap();
+                    let (ox, oy, ..) = scene.output_rect(output).unwrap();
+                    // The capture is in output pixels.
+                    let scale = scene.output_scale(output);
+                    capture.crop(
+                        ((x - ox) as f32 * scale) as i32,
+                        ((y - oy) as f32 * scale) as i32,
+                        (*width as f32 * scale).round() as u32,
+                        (*height as f32 * scale).round() as u32,
+                    )
+                }),
+                None => Err(format!("no output shows {},{}", x, y)),
+            }
+        }
+    }
+}
+
+fn capture_output(
+    scene: &SharedScene,
+    renderer: Option<&mut Renderer>,
+    output: NodeId,
+) -> Result<Capture, String> {
+    let renderer = renderer.ok_or("no renderer is running yet")?;
+    let (items, extent) = {
+        let scene = scene.lock().unwrap();
+        let (width, height) = scene.output_size(output).ok_or("the output went away")?;
+        (scene.draw_list(output), [width, height])
+    };
+    renderer.capture(&items, extent)
+}
+
+/// Time between frames at `refresh_mhz`, or at 60 Hz if it is unknown.
+fn frame_interval(refresh_mhz: Option<u32>) -> Duration {
+    let refresh = refresh_mhz.filter(|r| *r > 0).unwrap_or(DEFAULT_REFRESH_MHZ);
+    Duration::from_secs_f64(1000.0 / refresh as f64)
+}
+
+/// Returns the X11 id of `window`, so the scene never composites its own output.
+fn x11_window_id(window: &Window) -> Option<u32> {
+    match window.raw_window_handle() {
+        RawWindowHandle::Xlib(handle) => Some(handle.window as u32),
+        RawWindowHandle::Xcb(handle) => Some(handle.window),
+        _ => None,
+    }
+}
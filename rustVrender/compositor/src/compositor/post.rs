@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyBufferToImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::{Format, NumericFormat};
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+
+use super::renderer::FrameError;
+use super::shader::{ShaderFile, ShaderSources};
+use crate::scene::post::{PostPass, MAX_PASSES};
+
+/// Push constants of `post.frag`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct PostPush {
+    stages: [[f32; 4]; MAX_PASSES],
+    count: u32,
+    linear_input: u32,
+    use_lut: u32,
+}
+
+/// The image an output's frame is composited into before its post-processing chain
+/// runs over it.
+pub struct PostFrame {
+    frame: Arc<Framebuffer>,
+    set: Arc<PersistentDescriptorSet>,
+    /// Bound in place of a color lookup table while the output has none.
+    no_lut: Option<Arc<PersistentDescriptorSet>>,
+    linear_input: bool,
+}
+
+impl PostFrame {
+    /// Allocates a frame of `extent` drawn with `render_pass` and sampled by `pipeline`;
+    /// `no_lut` is the lookup table bound while the output has none. Fails if the frame
+    /// can't be allocated.
+    pub fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        render_pass: &Arc<RenderPass>,
+        pipeline: &Arc<GraphicsPipeline>,
+        extent: [u32; 2],
+        no_lut: &Arc<ImageView>,
+    ) -> Result<Self, FrameError> {
+        let format = render_pass.attachments()[0].format;
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                // Blur copies out of the frame, like out of swapchain images.
+                usage: ImageUsage::COLOR_ATTACHMENT
+                    | ImageUsage::SAMPLED
+                    | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        let view = ImageView::new_default(image)?;
+        let set = PersistentDescriptorSet::new(
+            set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, view.clone()),
+                WriteDescriptorSet::sampler(1, sampler.clone()),
+            ],
+            [],
+        )?;
+        let frame = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![view],
+                ..Default::default()
+            },
+        )?;
+        Ok(Self {
+            frame,
+            set,
+            no_lut: lut_set(set_allocator, pipeline, no_lut.clone())?,
+            linear_input: format.numeric_format_color() == Some(NumericFormat::SRGB),
+        })
+    }
+
+    /// Where the output's frame is composited.
+    pub fn frame(&self) -> &Arc<Framebuffer> {
+        &self.frame
+    }
+
+    /// Records `passes` over the composited frame, then `lut`, if given, writing the
+    /// result to `target`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<GraphicsPipeline>,
+        target: &Arc<Framebuffer>,
+        passes: &[PostPass],
+        lut: Option<&Arc<PersistentDescriptorSet>>,
+    ) -> Result<(), FrameError> {
+        let mut stages = [[0.0; 4]; MAX_PASSES];
+        for (stage, pass) in stages.iter_mut().zip(passes) {
+            *stage = pass.stage();
+        }
+        let [width, height] = target.extent();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into())],
+                    ..RenderPassBeginInfo::framebuffer(target.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [width as f32, height as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+            )?
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                self.set.clone(),
+            )?;
+        // Shaders from the shader directory may predate lookup tables.
+        if let Some(lut) = lut.or(self.no_lut.as_ref()) {
+            if pipeline.layout().set_layouts().len() > 1 {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    1,
+                    lut.clone(),
+                )?;
+            }
+        }
+        builder
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                PostPush {
+                    stages,
+                    count: passes.len().min(MAX_PASSES) as u32,
+                    linear_input: self.linear_input as u32,
+                    use_lut: lut.is_some() as u32,
+                },
+            )?
+            .draw(3, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+}
+
+/// Uploads a color lookup table of `size` points per axis from RGBA8 `data`, red
+/// varying fastest.
+pub fn upload_lut(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    size: u32,
+    data: &[u8],
+) -> Result<Arc<ImageView>, String> {
+    let staging = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data.iter().copied(),
+    )
+    .map_err(|e| e.to_string())?;
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim3d,
+            format: Format::R8G8B8A8_UNORM,
+            extent: [size, size, size],
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+        .map_err(|e| e.to_string())?;
+    ImageView::new_default(image).map_err(|e| e.to_string())
+}
+
+/// The set binding lookup table `view` to `pipeline`, unless its shader samples none.
+pub fn lut_set(
+    allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<GraphicsPipeline>,
+    view: Arc<ImageView>,
+) -> Result<Option<Arc<PersistentDescriptorSet>>, FrameError> {
+    let Some(layout) = pipeline.layout().set_layouts().get(1) else {
+        return Ok(None);
+    };
+    let set = PersistentDescriptorSet::new(
+        allocator,
+        layout.clone(),
+        [WriteDescriptorSet::image_view(0, view)],
+        [],
+    )?;
+    Ok(Some(set))
+}
+
+/// Builds the fullscreen-triangle pipeline running `post.frag` in `render_pass`.
+pub fn create_post_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    sources: &ShaderSources,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = sources.load(device.clone(), ShaderFile::FullscreenVert)?;
+    let fs = sources.load(device.clone(), ShaderFile::PostFrag)?;
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .map_err(|e| e.to_string())
+}
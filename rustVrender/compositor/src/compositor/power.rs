@@ -0,0 +1,98 @@
+// Input idleness and display power on the X server: the screen saver extension tells
+// how long ago any device last gave input, and DPMS powers the displays down and up.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::dpms::{self, ConnectionExt as _, DPMSMode};
+use x11rb::protocol::screensaver::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// A connection to the X server the displays are on.
+pub struct Power {
+    conn: RustConnection,
+    root: u32,
+    screensaver: bool,
+    dpms: bool,
+    /// Whether DPMS was off until the displays were powered down, and goes off again.
+    enabled_dpms: Cell<bool>,
+}
+
+impl Power {
+    pub fn connect() -> Result<Self, String> {
+        let (conn, screen) = RustConnection::connect(None).map_err(|e| e.to_string())?;
+        let root = conn.setup().roots[screen].root;
+        let present = |name: &'static str| {
+            conn.extension_information(name)
+                .is_ok_and(|info| info.is_some())
+        };
+        let screensaver = present(screensaver::X11_EXTENSION_NAME);
+        let dpms = present(dpms::X11_EXTENSION_NAME)
+            && conn
+                .dpms_capable()
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some_and(|reply| reply.capable);
+        if !screensaver {
+            eprintln!("The X server has no screen saver extension; only reported input counts.");
+        }
+        if !dpms {
+            eprintln!("The X server can't power displays down; idle stops at locking.");
+        }
+        Ok(Self {
+            conn,
+            root,
+            screensaver,
+            dpms,
+            enabled_dpms: Cell::new(false),
+        })
+    }
+
+    /// How long ago the X server last saw input from any device.
+    pub fn idle_time(&self) -> Option<Duration> {
+        if !self.screensaver {
+            return None;
+        }
+        let reply = self
+            .conn
+            .screensaver_query_info(self.root)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(Duration::from_millis(reply.ms_since_user_input as u64))
+    }
+
+    /// Powers every display up or down.
+    pub fn set_displays_on(&self, on: bool) -> Result<(), String> {
+        if !self.dpms {
+            return Ok(());
+        }
+        if on {
+            self.conn
+                .dpms_force_level(DPMSMode::ON)
+                .map_err(|e| e.to_string())?;
+            // Left as it was, so the X server's own timeouts don't start applying.
+            if self.enabled_dpms.take() {
+                self.conn.dpms_disable().map_err(|e| e.to_string())?;
+            }
+        } else {
+            // Forcing a level needs DPMS on, which it often isn't.
+            let enabled = self
+                .conn
+                .dpms_info()
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?
+                .state;
+            if !enabled {
+                self.conn.dpms_enable().map_err(|e| e.to_string())?;
+                self.enabled_dpms.set(true);
+            }
+            self.conn
+                .dpms_force_level(DPMSMode::OFF)
+                .map_err(|e| e.to_string())?;
+        }
+        self.conn.flush().map_err(|e| e.to_string())
+    }
+}
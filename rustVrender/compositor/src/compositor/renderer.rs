@@ -0,0 +1,1964 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use winit::window::Window;
+
+use vulkano::buffer::{AllocateBufferError, Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, CommandBufferExecError, CommandBufferUsage,
+    CopyBufferToImageInfo, CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+    PrimaryCommandBufferAbstract, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::physical::PhysicalDeviceType;
+use vulkano::device::{
+    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AllocateImageError, Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{
+    VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate,
+    VertexInputState,
+};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::shader::EntryPoint;
+use vulkano::swapchain::{
+    acquire_next_image, ColorSpace, PresentMode as VkPresentMode, Surface, Swapchain,
+    SwapchainCreateInfo,
+    SwapchainPresentInfo,
+};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{Validated, ValidationError, VulkanError, VulkanLibrary};
+
+use super::blur::Blur;
+use super::hdr::{self, HdrFormat, HdrTarget};
+use super::memory::{self, Budget};
+use super::post::{create_post_pipeline, lut_set, upload_lut, PostFrame};
+use super::shader::{ShaderDir, ShaderFile, ShaderSources};
+use super::text::GlyphAtlas;
+use super::timestamps::Timestamps;
+use crate::config::{GpuPreference, HdrConfig, MemoryConfig, PresentMode};
+use crate::scene::color::ColorLut;
+use crate::scene::post::PostPass;
+use crate::scene::{DrawItem, DrawSource, NodeId, WindowContents, WHOLE_CONTENTS};
+use crate::screenshot::Capture;
+use crate::stats::{FrameTiming, MemoryUsage};
+
+/// Background drawn behind every output.
+pub const CLEAR_COLOR: [f32; 4] = [0.05, 0.05, 0.07, 1.0];
+/// What a headless renderer draws in; every implementation can render into it.
+const HEADLESS_FORMAT: Format = Format::B8G8R8A8_UNORM;
+/// How often the device's memory budget is read again.
+const BUDGET_POLL: Duration = Duration::from_secs(1);
+const MIB: u64 = 1 << 20;
+
+/// Per-instance data for the quad and text pipelines; must match `quad.vert` and
+/// `text.vert`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct QuadInstance {
+    rect: [f32; 4],
+    color: [f32; 4],
+    params: [f32; 4],
+    /// The part of a window's contents drawn; the text pipeline leaves it be.
+    part: [f32; 4],
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    viewport: [f32; 2],
+}
+
+/// Why a frame, or what it draws with, couldn't be recorded, allocated, or submitted.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The device was lost, and the renderer has to be replaced.
+    Lost,
+    Failed(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lost => f.write_str("the device was lost"),
+            Self::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<Validated<VulkanError>> for FrameError {
+    fn from(e: Validated<VulkanError>) -> Self {
+        match e {
+            Validated::Error(VulkanError::DeviceLost) => Self::Lost,
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+impl From<Validated<AllocateBufferError>> for FrameError {
+    fn from(e: Validated<AllocateBufferError>) -> Self {
+        match e {
+            Validated::Error(AllocateBufferError::CreateBuffer(VulkanError::DeviceLost)) => {
+                Self::Lost
+            }
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+impl From<Validated<AllocateImageError>> for FrameError {
+    fn from(e: Validated<AllocateImageError>) -> Self {
+        match e {
+            Validated::Error(AllocateImageError::CreateImage(VulkanError::DeviceLost)) => {
+                Self::Lost
+            }
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+impl From<String> for FrameError {
+    fn from(e: String) -> Self {
+        Self::Failed(e)
+    }
+}
+
+impl From<Box<ValidationError>> for FrameError {
+    fn from(e: Box<ValidationError>) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+impl From<CommandBufferExecError> for FrameError {
+    fn from(e: CommandBufferExecError) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+/// Draws the scene into one window surface per output, sharing a single device.
+pub struct Renderer {
+    instance: Arc<Instance>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    render_pass: Arc<RenderPass>,
+    /// Same as `render_pass`, but keeps what was drawn before it began.
+    load_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    /// Runs post-processing chains, into `render_pass`.
+    post_pipeline: Arc<GraphicsPipeline>,
+    /// Draws glyphs out of a glyph atlas, with the quad pipeline's instances.
+    text_pipeline: Arc<GraphicsPipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    sampler: Arc<Sampler>,
+    white_view: Arc<ImageView>,
+    /// Bound for solid draws: a single white texel.
+    white: Arc<PersistentDescriptorSet>,
+    /// A one-texel lookup table, bound while an output's colors aren't corrected.
+    no_lut_view: Arc<ImageView>,
+    /// Uploaded window contents, keyed by X window id; those no output shows are kept
+    /// while the memory config lets them.
+    textures: HashMap<u32, Texture>,
+    /// Contents that couldn't be allocated, by window and serial, so they aren't tried
+    /// again every frame; those windows keep their last texture, if they had one.
+    failed_uploads: HashMap<u32, u64>,
+    /// Windows each output showed in its last frame; textures of the rest are cached.
+    visible: HashMap<NodeId, Vec<u32>>,
+    /// Frames drawn so far, which textures are stamped with as they are shown.
+    frame: u64,
+    memory: MemoryConfig,
+    /// The device's memory budget as last read, and when.
+    budget: Option<(Instant, Option<Budget>)>,
+    /// Cached textures dropped for the cache or the budget so far.
+    evicted: u64,
+    targets: HashMap<NodeId, Target>,
+    /// Blur for offscreen captures, created by the first one.
+    capture_blur: Option<Blur>,
+    /// Glyph atlas for offscreen captures, created by the first one that draws text.
+    capture_atlas: Option<GlyphAtlas>,
+    /// The mode last asked for, which a swapchain may not support.
+    present_mode: PresentMode,
+    hdr: HdrConfig,
+    /// The shaders pipelines are currently built from.
+    sources: ShaderSources,
+    shader_dir: Option<ShaderDir>,
+    /// Set once the device is lost; nothing drawn with it shows up any more.
+    lost: bool,
+    /// Skips what opaque fullscreen windows hide, and blits them when alone.
+    direct_scanout: bool,
+    /// Background behind every output.
+    clear_color: [f32; 4],
+}
+
+/// The uploaded contents of one window.
+struct Texture {
+    /// The serial the contents were uploaded from.
+    serial: u64,
+    image: Arc<Image>,
+    set: Arc<PersistentDescriptorSet>,
+    bytes: u64,
+    /// The frame it was last shown in.
+    shown: u64,
+    /// The pixels it was uploaded from, gone once the scene drops them.
+    contents: Weak<Vec<u8>>,
+}
+
+/// The swapchain of one output's window.
+struct Target {
+    window: Arc<Window>,
+    swapchain: Arc<Swapchain>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+    /// Unavailable when swapchain images can't be copied from.
+    blur: Option<Blur>,
+    /// Created by the first frame that draws text.
+    atlas: Option<GlyphAtlas>,
+    /// Whether SDR swapchain images can be copied from.
+    copyable: bool,
+    /// Whether SDR swapchain images can be blitted into.
+    blittable: bool,
+    /// Set while the output shows HDR; frames are then composited into its image.
+    hdr: Option<HdrTarget>,
+    /// Set while the output has post-processing passes or a color lookup table; frames
+    /// are then composited into its image first.
+    post: Option<PostFrame>,
+    /// The extent a post frame last failed to allocate at, so it isn't tried again
+    /// every frame; frames are drawn without post-processing meanwhile.
+    failed_post: Option<[u32; 2]>,
+    /// The serial of the lookup table last uploaded, and its set unless the post shader
+    /// samples none.
+    lut: Option<(u64, Option<Arc<PersistentDescriptorSet>>)>,
+    /// Unavailable when the queue can't write timestamps.
+    timestamps: Option<Timestamps>,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    recreate_swapchain: bool,
+}
+
+impl Renderer {
+    /// Creates the Vulkan instance and device, and a swapchain for `window` showing `output`.
+    /// The device is the first of the kind `gpu` asks for, or any other that can present.
+    /// Shaders are loaded from `shader_dir`, if given, and reloaded as they are edited.
+    pub fn new(
+        window: Arc<Window>,
+        output: NodeId,
+        present_mode: PresentMode,
+        gpu: GpuPreference,
+        shader_dir: Option<&Path>,
+    ) -> Result<Self, String> {
+        let library = VulkanLibrary::new().map_err(|e| e.to_string())?;
+        // HDR color spaces are only listed with this extension.
+        let colorspace_extension = InstanceExtensions {
+            ext_swapchain_colorspace: library.supported_extensions().ext_swapchain_colorspace,
+            ..InstanceExtensions::empty()
+        };
+        let instance = Instance::new(
+            library,
+            InstanceCreateInfo {
+                enabled_extensions: Surface::required_extensions(&*window)
+                    .union(&colorspace_extension),
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+        let surface =
+            Surface::from_window(instance.clone(), window.clone()).map_err(|e| e.to_string())?;
+
+        let device_extensions = DeviceExtensions {
+            khr_swapchain: true,
+            ..DeviceExtensions::empty()
+        };
+
+        // Pick the preferred device with a queue family that supports graphics and
+        // presentation, taking the first one listed among equals.
+        let (physical, queue_family_index) = instance
+            .enumerate_physical_devices()
+            .map_err(|e| e.to_string())?
+            .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .filter_map(|p| {
+                p.queue_family_properties()
+                    .iter()
+                    .enumerate()
+                    .position(|(index, q)| {
+                        q.queue_flags.contains(QueueFlags::GRAPHICS)
+                            && p.surface_support(index as u32, &surface).unwrap_or(false)
+                    })
+                    .map(|index| (p.clone(), index as u32))
+            })
+            .min_by_key(|(p, _)| gpu_rank(gpu, p.properties().device_type))
+            .ok_or("no graphical queue family supports presentation")?;
+
+        let device_extensions = device_extensions.union(&memory::budget_extension(&physical));
+        let (device, mut queues) = Device::new(
+            physical,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_extensions: device_extensions,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        let queue = queues.next().unwrap();
+
+        // Every later output renders through the same passes, so they share this format.
+        let format = device
+            .physical_device()
+            .surface_formats(&surface, Default::default())
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|(_, color_space)| *color_space == ColorSpace::SrgbNonLinear)
+            .ok_or("the window supports no SDR format")?
+            .0;
+        let mut renderer = Self::with_device(instance, device, queue, format, present_mode)?;
+        let target = renderer.create_target(window, surface)?;
+        renderer.targets.insert(output, target);
+        renderer.watch_shaders(shader_dir);
+        Ok(renderer)
+    }
+
+    /// Creates a renderer without any window, which only draws offscreen through
+    /// `capture`; for tests, and machines without a display. Fails when there is no
+    /// Vulkan device that can draw.
+    pub fn headless(shader_dir: Option<&Path>) -> Result<Self, String> {
+        let library = VulkanLibrary::new().map_err(|e| e.to_string())?;
+        let instance =
+            Instance::new(library, InstanceCreateInfo::default()).map_err(|e| e.to_string())?;
+        let (physical, queue_family_index) = instance
+            .enumerate_physical_devices()
+            .map_err(|e| e.to_string())?
+            .find_map(|p| {
+                p.queue_family_properties()
+                    .iter()
+                    .position(|q| q.queue_flags.contains(QueueFlags::GRAPHICS))
+                    .map(|index| (p.clone(), index as u32))
+            })
+            .ok_or("no Vulkan device can draw")?;
+        let enabled_extensions = memory::budget_extension(&physical);
+        let (device, mut queues) = Device::new(
+            physical,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_extensions,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        let queue = queues.next().unwrap();
+        let mut renderer =
+            Self::with_device(instance, device, queue, HEADLESS_FORMAT, PresentMode::default())?;
+        renderer.watch_shaders(shader_dir);
+        Ok(renderer)
+    }
+
+    /// Creates everything but the outputs on `device`, drawing in `format`.
+    fn with_device(
+        instance: Arc<Instance>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        format: Format,
+        present_mode: PresentMode,
+    ) -> Result<Self, String> {
+        let render_pass = create_render_pass(device.clone(), format, false)?;
+        let load_pass = create_render_pass(device.clone(), format, true)?;
+        // Shaders from the shader directory replace these once the first output is up.
+        let sources = ShaderSources::default();
+        let pipeline = create_quad_pipeline(device.clone(), render_pass.clone(), &sources)?;
+        let post_pipeline = create_post_pipeline(device.clone(), render_pass.clone(), &sources)?;
+        let text_pipeline = create_text_pipeline(device.clone(), render_pass.clone(), &sources)?;
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+        // Upload the white texel used by solid draws before the first frame.
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| e.to_string())?;
+        let white_view = upload_texture(&memory_allocator, &mut builder, 1, 1, vec![255; 4])?;
+        let white = texture_set(&descriptor_set_allocator, &pipeline, white_view.clone(), &sampler)
+            .map_err(|e| e.to_string())?;
+        let no_lut_view = upload_lut(&memory_allocator, &mut builder, 1, &[0; 4])?;
+        builder
+            .build()
+            .map_err(|e| e.to_string())?
+            .execute(queue.clone())
+            .map_err(|e| e.to_string())?
+            .then_signal_fence_and_flush()
+            .and_then(|upload| upload.wait(None))
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            instance,
+            device,
+            queue,
+            render_pass,
+            load_pass,
+            pipeline,
+            post_pipeline,
+            text_pipeline,
+            memory_allocator,
+            command_buffer_allocator,
+            descriptor_set_allocator,
+            sampler,
+            white_view,
+            white,
+            no_lut_view,
+            textures: HashMap::new(),
+            failed_uploads: HashMap::new(),
+            visible: HashMap::new(),
+            frame: 0,
+            memory: MemoryConfig::default(),
+            budget: None,
+            evicted: 0,
+            targets: HashMap::new(),
+            capture_blur: None,
+            capture_atlas: None,
+            present_mode,
+            hdr: HdrConfig::default(),
+            sources,
+            shader_dir: None,
+            lost: false,
+            direct_scanout: false,
+            clear_color: CLEAR_COLOR,
+        })
+    }
+
+    /// Loads shaders from `shader_dir`, if given, and reloads them as they are edited.
+    fn watch_shaders(&mut self, shader_dir: Option<&Path>) {
+        let Some(path) = shader_dir else {
+            return;
+        };
+        match ShaderDir::open(path) {
+            Ok(dir) => {
+                println!("Watching {} for shader edits.", path.display());
+                self.shader_dir = Some(dir);
+                self.reload_shaders();
+            }
+            Err(e) => eprintln!("Using the built-in shaders; {}: {}", path.display(), e),
+        }
+    }
+
+    /// Whether the device was lost, after which the renderer has to be replaced.
+    pub fn lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Drops a renderer whose device was lost.
+    pub fn discard(mut self) {
+        // A frame still in flight is waited for as its future drops, which panics when
+        // the wait fails, as it does on a lost device. Those futures are leaked instead,
+        // along with what their frames drew with; the rest is destroyed as usual.
+        for target in self.targets.values_mut() {
+            mem::forget(target.previous_frame_end.take());
+        }
+    }
+
+    /// Rebuilds the pipelines of shaders edited in the shader directory; returns whether
+    /// anything changed. Shaders that fail to compile or link leave every pipeline as
+    /// it was.
+    pub fn reload_shaders(&mut self) -> bool {
+        let Some(dir) = self.shader_dir.as_mut() else {
+            return false;
+        };
+        let changed = dir.poll(Instant::now());
+        if changed.is_empty() {
+            return false;
+        }
+        let path = dir.path().display().to_string();
+        let names: Vec<&str> = changed.iter().map(|(file, _)| file.file_name()).collect();
+        let mut sources = self.sources.clone();
+        for (file, source) in &changed {
+            sources.set(*file, source.clone());
+        }
+        match self.set_shaders(sources) {
+            Ok(()) => {
+                println!("Loaded {} from {}.", names.join(", "), path);
+                true
+            }
+            Err(e) => {
+                eprintln!("Keeping the last shaders that built: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Switches every pipeline to `sources` once all of them build.
+    fn set_shaders(&mut self, sources: ShaderSources) -> Result<(), String> {
+        let pipeline = create_quad_pipeline(self.device.clone(), self.render_pass.clone(), &sources)?;
+        let post_pipeline =
+            create_post_pipeline(self.device.clone(), self.render_pass.clone(), &sources)?;
+        let text_pipeline =
+            create_text_pipeline(self.device.clone(), self.render_pass.clone(), &sources)?;
+        let format = self.render_pass.attachments()[0].format;
+        Blur::new(self.device.clone(), format, &sources)?;
+        hdr::check_shaders(self.device.clone(), &sources)?;
+
+        self.sources = sources;
+        self.white = texture_set(
+            &self.descriptor_set_allocator,
+            &pipeline,
+            self.white_view.clone(),
+            &self.sampler,
+        )
+        .map_err(|e| e.to_string())?;
+        self.pipeline = pipeline;
+        self.post_pipeline = post_pipeline;
+        self.text_pipeline = text_pipeline;
+        // Sets made for the old layouts are made again on the next frame.
+        self.textures.clear();
+        for target in self.targets.values_mut() {
+            target.post = None;
+            target.failed_post = None;
+            target.lut = None;
+            target.atlas = None;
+        }
+        self.capture_blur = None;
+        self.capture_atlas = None;
+        let blurs: Vec<NodeId> = self
+            .targets
+            .iter()
+            .filter(|(_, target)| target.blur.is_some())
+            .map(|(output, _)| *output)
+            .collect();
+        for output in blurs {
+            let blur = self.create_blur();
+            self.targets.get_mut(&output).unwrap().blur = blur;
+        }
+        for target in self.targets.values_mut() {
+            if let Some(hdr) = target.hdr.as_mut() {
+                hdr.set_shaders(
+                    self.device.clone(),
+                    &self.descriptor_set_allocator,
+                    &self.sampler,
+                    &self.sources,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a swapchain for `window`, which shows `output`.
+    pub fn add_output(&mut self, output: NodeId, window: Arc<Window>) -> Result<(), String> {
+        let surface =
+            Surface::from_window(self.instance.clone(), window.clone()).map_err(|e| e.to_string())?;
+        let supported = self
+            .device
+            .physical_device()
+            .surface_support(self.queue.queue_family_index(), &surface)
+            .unwrap_or(false);
+        if !supported {
+            return Err("the device can't present to this window".into());
+        }
+        let target = self.create_target(window, surface)?;
+        self.targets.insert(output, target);
+        Ok(())
+    }
+
+    /// Drops the swapchain of `output`.
+    pub fn remove_output(&mut self, output: NodeId) {
+        self.targets.remove(&output);
+        self.visible.remove(&output);
+    }
+
+    /// Marks the swapchain of `output` as stale after its window was resized.
+    pub fn resize(&mut self, output: NodeId) {
+        if let Some(target) = self.targets.get_mut(&output) {
+            target.recreate_swapchain = true;
+        }
+    }
+
+    /// Switches to `mode`, or the closest supported one, from the next frame.
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if mode != self.present_mode {
+            self.present_mode = mode;
+            for target in self.targets.values_mut() {
+                target.recreate_swapchain = true;
+            }
+        }
+    }
+
+    /// Draws `color` behind every output from the next frame.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Turns the composition bypass for opaque fullscreen windows on or off.
+    pub fn set_direct_scanout(&mut self, enabled: bool) {
+        self.direct_scanout = enabled;
+    }
+
+    /// Caches textures of windows no output shows as `config` has it, from the next frame.
+    pub fn set_memory_config(&mut self, config: MemoryConfig) {
+        self.memory = config;
+    }
+
+    /// The GPU memory of window textures, and of the device, as of the last frame.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mib = |bytes: u64| bytes as f32 / MIB as f32;
+        let mut usage = MemoryUsage {
+            textures: self.textures.len(),
+            evicted: self.evicted,
+            ..MemoryUsage::default()
+        };
+        let mut bytes = [0; 2];
+        for (id, texture) in &self.textures {
+            bytes[0] += texture.bytes;
+            if !self.shown(*id) {
+                usage.cached += 1;
+                bytes[1] += texture.bytes;
+            }
+        }
+        usage.texture_mib = mib(bytes[0]);
+        usage.cached_mib = mib(bytes[1]);
+        if let Some((_, Some(budget))) = self.budget {
+            usage.device_mib = Some(mib(budget.usage));
+            usage.budget_mib = Some(mib(budget.budget));
+        }
+        usage
+    }
+
+    /// Applies `hdr`, switching outputs that support it in or out of HDR from the next
+    /// frame.
+    pub fn set_hdr(&mut self, hdr: HdrConfig) {
+        if hdr == self.hdr {
+            return;
+        }
+        if hdr.enabled != self.hdr.enabled {
+            for target in self.targets.values_mut() {
+                target.recreate_swapchain = true;
+                let formats = self
+                    .device
+                    .physical_device()
+                    .surface_formats(target.swapchain.surface(), Default::default())
+                    .unwrap_or_default();
+                if hdr.enabled && hdr::choose_format(&formats).is_none() {
+                    eprintln!("An output has no HDR format; it stays SDR.");
+                }
+            }
+        }
+        self.hdr = hdr;
+    }
+
+    /// Renders one frame of `output` made of `items`, drawn in order, then applies the
+    /// `post` passes and the output's color lookup table `lut` to it; returns how long
+    /// it took, unless no frame could be drawn. Lookup tables are for SDR only.
+    pub fn draw_frame(
+        &mut self,
+        output: NodeId,
+        items: &[DrawItem],
+        post: &[PostPass],
+        lut: Option<&ColorLut>,
+    ) -> Option<FrameTiming> {
+        let target = self.targets.get_mut(&output)?;
+        let extent: [u32; 2] = target.window.inner_size().into();
+        if extent.contains(&0) {
+            return None;
+        }
+        let items = if self.direct_scanout {
+            unoccluded(items, extent)
+        } else {
+            items
+        };
+
+        if let Some(previous) = target.previous_frame_end.as_mut() {
+            previous.cleanup_finished();
+        }
+
+        if target.recreate_swapchain && !self.recreate_swapchain(output, extent) {
+            return None;
+        }
+        let target = self.targets.get_mut(&output).unwrap();
+
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(target.swapchain.clone(), None).map_err(Validated::unwrap) {
+                Ok(r) => r,
+                Err(VulkanError::OutOfDate) => {
+                    target.recreate_swapchain = true;
+                    return None;
+                }
+                Err(VulkanError::DeviceLost) => {
+                    self.lost = true;
+                    return None;
+                }
+                Err(e) => {
+                    eprintln!("Failed to acquire next image: {}", e);
+                    return None;
+                }
+            };
+        let started = Instant::now();
+        if suboptimal {
+            target.recreate_swapchain = true;
+        }
+        let output_frame = match &target.hdr {
+            Some(hdr) => hdr.frame().clone(),
+            None => target.framebuffers[image_index as usize].clone(),
+        };
+        let lut = lut.filter(|_| target.hdr.is_none());
+        let extent = output_frame.extent();
+        if post.is_empty() && lut.is_none() {
+            target.post = None;
+            target.failed_post = None;
+        } else if target.post.as_ref().map(|p| p.frame().extent()) != Some(extent)
+            && target.failed_post != Some(extent)
+        {
+            let frame = PostFrame::new(
+                &self.memory_allocator,
+                &self.descriptor_set_allocator,
+                &self.sampler,
+                &self.render_pass,
+                &self.post_pipeline,
+                extent,
+                &self.no_lut_view,
+            );
+            target.post = match frame {
+                Ok(frame) => Some(frame),
+                Err(FrameError::Lost) => {
+                    self.lost = true;
+                    return None;
+                }
+                Err(e) => {
+                    eprintln!("Failed to allocate post-processing; drawing without it: {}", e);
+                    target.failed_post = Some(extent);
+                    None
+                }
+            };
+        }
+        let framebuffer = match &target.post {
+            Some(post) => post.frame().clone(),
+            None => output_frame.clone(),
+        };
+        // Taken out while recording, which needs the rest of the renderer.
+        let mut blur = target.blur.take();
+        let mut atlas = target.atlas.take();
+
+        let mut record = || -> Result<_, FrameError> {
+            let mut builder = AutoCommandBufferBuilder::primary(
+                &self.command_buffer_allocator,
+                self.queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+            )?;
+            let gpu = self
+                .targets
+                .get_mut(&output)
+                .unwrap()
+                .timestamps
+                .as_mut()
+                .and_then(|timestamps| timestamps.begin(&mut builder, image_index));
+
+            self.frame += 1;
+            let visible = self.upload_window_textures(&mut builder, items);
+            self.visible.insert(output, visible);
+            self.evict_textures();
+            let visible = &self.visible;
+            self.failed_uploads
+                .retain(|id, _| visible.values().any(|ids| ids.contains(id)));
+            self.prepare_atlas(&mut builder, &mut atlas, items)?;
+            self.prepare_lut(&mut builder, output, lut);
+
+            let target = &self.targets[&output];
+            let blittable = self.direct_scanout
+                && target.blittable
+                && target.post.is_none()
+                && target.hdr.is_none();
+            let scanout = match items {
+                [item] if blittable => self.scanout_image(item, extent),
+                _ => None,
+            };
+            match scanout {
+                // Nothing else shows, so the window is copied out as it is, without a pass.
+                Some(image) => {
+                    let swapchain_image = output_frame.attachments()[0].image().clone();
+                    builder.blit_image(BlitImageInfo {
+                        filter: Filter::Nearest,
+                        ..BlitImageInfo::images(image, swapchain_image)
+                    })?;
+                }
+                _ => self.record_items(
+                    &mut builder,
+                    &framebuffer,
+                    items,
+                    blur.as_mut(),
+                    atlas.as_ref(),
+                )?,
+            }
+            let target = &self.targets[&output];
+            if let Some(frame) = &target.post {
+                let lut = target.lut.as_ref().and_then(|(_, set)| set.as_ref());
+                frame.record(&mut builder, &self.post_pipeline, &output_frame, post, lut)?;
+            }
+            if let Some(hdr) = &target.hdr {
+                hdr.record(&mut builder, image_index, self.hdr.sdr_white_nits)?;
+            }
+            if let Some(timestamps) = &target.timestamps {
+                timestamps.end(&mut builder, image_index);
+            }
+            Ok((builder.build()?, gpu))
+        };
+        let recorded = record();
+
+        let target = self.targets.get_mut(&output).unwrap();
+        target.blur = blur;
+        target.atlas = atlas;
+        let (command_buffer, gpu) = match recorded {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                self.drop_frame(output, e);
+                return None;
+            }
+        };
+        let executed = target
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed())
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer);
+        let future = match executed {
+            Ok(executed) => executed,
+            Err(e) => {
+                self.drop_frame(output, e.into());
+                return None;
+            }
+        };
+        let target = self.targets.get_mut(&output).unwrap();
+        let future = future
+            .then_swapchain_present(
+                self.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(target.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush();
+
+        target.previous_frame_end = match future.map_err(Validated::unwrap) {
+            Ok(future) => Some(future.boxed()),
+            Err(VulkanError::OutOfDate) => {
+                target.recreate_swapchain = true;
+                Some(sync::now(self.device.clone()).boxed())
+            }
+            Err(VulkanError::DeviceLost) => {
+                self.lost = true;
+                None
+            }
+            Err(e) => {
+                eprintln!("Failed to flush frame: {}", e);
+                Some(sync::now(self.device.clone()).boxed())
+            }
+        };
+        Some(FrameTiming {
+            cpu: started.elapsed(),
+            gpu,
+            memory: self.memory_usage(),
+        })
+    }
+
+    /// Gives up on a frame of `output` that failed. The image it acquired is never
+    /// presented, so the swapchain is recreated before the next one, which waits on
+    /// nothing the frame submitted.
+    fn drop_frame(&mut self, output: NodeId, e: FrameError) {
+        match e {
+            FrameError::Lost => self.lost = true,
+            FrameError::Failed(e) => {
+                eprintln!("Failed to draw a frame: {}", e);
+                if let Some(target) = self.targets.get_mut(&output) {
+                    target.recreate_swapchain = true;
+                    let device = &self.device;
+                    target
+                        .previous_frame_end
+                        .get_or_insert_with(|| sync::now(device.clone()).boxed());
+                }
+            }
+        }
+    }
+
+    /// Renders `items` into an offscreen image of `extent` and reads it back.
+    pub fn capture(&mut self, items: &[DrawItem], extent: [u32; 2]) -> Result<Capture, String> {
+        if extent.contains(&0) {
+            return Err("nothing to capture".into());
+        }
+        let format = self.render_pass.attachments()[0].format;
+        let channels = match format {
+            Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => [2, 1, 0],
+            Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => [0, 1, 2],
+            format => return Err(format!("can't read back {:?} frames", format)),
+        };
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .map_err(|e| e.to_string())?;
+        let framebuffer =
+            create_framebuffers(std::slice::from_ref(&image), self.render_pass.clone())
+                .map_err(|e| e.to_string())?
+                .remove(0);
+        let readback = Buffer::new_slice::<u8>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            extent[0] as u64 * extent[1] as u64 * 4,
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| e.to_string())?;
+        // Textures only uploaded here are dropped again by the next frame drawn.
+        self.upload_window_textures(&mut builder, items);
+        let mut blur = self.capture_blur.take().or_else(|| self.create_blur());
+        let mut atlas = self.capture_atlas.take();
+        let recorded = self
+            .prepare_atlas(&mut builder, &mut atlas, items)
+            .and_then(|()| {
+                self.record_items(&mut builder, &framebuffer, items, blur.as_mut(), atlas.as_ref())
+            });
+        self.capture_blur = blur;
+        self.capture_atlas = atlas;
+        if let Err(e) = recorded {
+            if matches!(e, FrameError::Lost) {
+                self.lost = true;
+            }
+            return Err(e.to_string());
+        }
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, readback.clone()))
+            .map_err(|e| e.to_string())?;
+        let finished = builder
+            .build()
+            .map_err(|e| e.to_string())?
+            .execute(self.queue.clone())
+            .map_err(|e| e.to_string())?
+            .then_signal_fence_and_flush()
+            .and_then(|fence| fence.wait(None));
+        if let Err(e) = finished {
+            if matches!(e, Validated::Error(VulkanError::DeviceLost)) {
+                self.lost = true;
+            }
+            return Err(e.to_string());
+        }
+
+        let pixels = readback.read().map_err(|e| e.to_string())?;
+        let mut rgba = Vec::with_capacity(pixels.len());
+        for pixel in pixels.chunks_exact(4) {
+            // The clear color is opaque, so every pixel of the frame is too.
+            let [r, g, b] = channels.map(|c| pixel[c]);
+            rgba.extend_from_slice(&[r, g, b, 255]);
+        }
+        Ok(Capture {
+            width: extent[0],
+            height: extent[1],
+            rgba,
+        })
+    }
+}
+
+impl Renderer {
+    /// Records one render pass drawing `items` into `framebuffer`, which it clears first.
+    fn record_items(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        framebuffer: &Arc<Framebuffer>,
+        items: &[DrawItem],
+        mut blur: Option<&mut Blur>,
+        atlas: Option<&GlyphAtlas>,
+    ) -> Result<(), FrameError> {
+        let [width, height] = framebuffer.extent();
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [width as f32, height as f32],
+            depth_range: 0.0..=1.0,
+        };
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(self.clear_color.into())],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(0, [viewport.clone()].into_iter().collect())?;
+
+        if !items.is_empty() {
+            let instances = Buffer::from_iter(
+                self.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::VERTEX_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                items.iter().map(|item| match &item.source {
+                    DrawSource::Glyph { font, index, px } => {
+                        match atlas.and_then(|atlas| atlas.uv(font, *index, *px)) {
+                            Some(uv) => QuadInstance {
+                                rect: item.rect,
+                                color: item.color,
+                                params: uv,
+                                part: WHOLE_CONTENTS,
+                            },
+                            // Glyphs that didn't make it into the atlas draw nothing.
+                            None => QuadInstance {
+                                rect: [0.0; 4],
+                                color: [0.0; 4],
+                                params: [0.0; 4],
+                                part: WHOLE_CONTENTS,
+                            },
+                        }
+                    }
+                    _ => QuadInstance {
+                        rect: item.rect,
+                        color: item.color,
+                        params: [
+                            match &item.source {
+                                DrawSource::Window { contents, .. } if contents.opaque => 1.0,
+                                DrawSource::Backdrop(_) => 1.0,
+                                _ => 0.0,
+                            },
+                            item.corner_radius,
+                            match item.source {
+                                DrawSource::Shadow(spread) => spread,
+                                _ => item.border,
+                            },
+                            match item.source {
+                                DrawSource::Backdrop(_) => 1.0,
+                                DrawSource::Shadow(_) => 2.0,
+                                DrawSource::Window { frozen: true, .. } => 4.0,
+                                DrawSource::Window { .. } => 3.0,
+                                DrawSource::Solid | DrawSource::Glyph { .. } => 0.0,
+                            },
+                        ],
+                        part: match item.source {
+                            DrawSource::Window { part, .. } => part,
+                            _ => WHOLE_CONTENTS,
+                        },
+                    },
+                }),
+            )?;
+            let bind_quads = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>| {
+                builder
+                    .bind_pipeline_graphics(self.pipeline.clone())?
+                    .push_constants(
+                        self.pipeline.layout().clone(),
+                        0,
+                        PushConstants {
+                            viewport: [width as f32, height as f32],
+                        },
+                    )?
+                    .bind_vertex_buffers(0, instances.clone())?;
+                Ok::<_, FrameError>(())
+            };
+            bind_quads(builder)?;
+
+            // Consecutive untextured items share the white texel and draw as one batch.
+            let mut first = 0;
+            while first < items.len() {
+                let glyph = |item: &DrawItem| matches!(item.source, DrawSource::Glyph { .. });
+                if glyph(&items[first]) {
+                    // A run of glyphs draws as one batch out of the atlas.
+                    let mut count = 1;
+                    while first + count < items.len() && glyph(&items[first + count]) {
+                        count += 1;
+                    }
+                    if let Some(atlas) = atlas {
+                        builder
+                            .bind_pipeline_graphics(self.text_pipeline.clone())?
+                            .push_constants(
+                                self.text_pipeline.layout().clone(),
+                                0,
+                                PushConstants {
+                                    viewport: [width as f32, height as f32],
+                                },
+                            )?
+                            .bind_descriptor_sets(
+                                PipelineBindPoint::Graphics,
+                                self.text_pipeline.layout().clone(),
+                                0,
+                                atlas.set().clone(),
+                            )?
+                            .draw(4, count as u32, 0, first as u32)?;
+                        bind_quads(builder)?;
+                    }
+                    first += count;
+                    continue;
+                }
+                let set = match items[first].source {
+                    DrawSource::Backdrop(level) => {
+                        let source = framebuffer.attachments()[0].image().clone();
+                        let Some(blur) = blur.as_deref_mut() else {
+                            first += 1;
+                            continue;
+                        };
+                        let prepared = blur.prepare(
+                            &self.memory_allocator,
+                            &self.descriptor_set_allocator,
+                            &self.sampler,
+                            &self.pipeline.layout().set_layouts()[0],
+                            &source,
+                            level,
+                        )?;
+                        if !prepared {
+                            first += 1;
+                            continue;
+                        }
+                        // Blur what has been drawn so far, then carry on drawing over it.
+                        builder.end_render_pass(SubpassEndInfo::default())?;
+                        let set = blur.record(builder, source, level)?;
+                        builder
+                            .begin_render_pass(
+                                RenderPassBeginInfo {
+                                    render_pass: self.load_pass.clone(),
+                                    clear_values: vec![None],
+                                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                                },
+                                SubpassBeginInfo {
+                                    contents: SubpassContents::Inline,
+                                    ..Default::default()
+                                },
+                            )?
+                            .set_viewport(0, [viewport.clone()].into_iter().collect())?;
+                        bind_quads(builder)?;
+                        set
+                    }
+                    _ => descriptor_set(&self.textures, &self.white, &items[first]),
+                };
+                let mut count = 1;
+                let untextured = |item: &DrawItem| {
+                    matches!(item.source, DrawSource::Solid | DrawSource::Shadow(_))
+                };
+                if untextured(&items[first]) {
+                    while first + count < items.len() && untextured(&items[first + count]) {
+                        count += 1;
+                    }
+                }
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        self.pipeline.layout().clone(),
+                        0,
+                        set,
+                    )?
+                    .draw(4, count as u32, 0, first as u32)?;
+                first += count;
+            }
+        }
+
+        builder.end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+
+    fn create_target(&self, window: Arc<Window>, surface: Arc<Surface>) -> Result<Target, String> {
+        let capabilities = self
+            .device
+            .physical_device()
+            .surface_capabilities(&surface, Default::default())
+            .map_err(|e| e.to_string())?;
+        // Blurring needs to copy what has been drawn out of the swapchain image.
+        let copyable = capabilities
+            .supported_usage_flags
+            .intersects(ImageUsage::TRANSFER_SRC);
+        if !copyable {
+            eprintln!("Swapchain images can't be copied from; blur is disabled outside HDR.");
+        }
+        let (format, color_space, hdr) = self.swapchain_format(&surface)?;
+        // Fullscreen windows shown alone are blitted into swapchain images directly.
+        let sdr_format = self.render_pass.attachments()[0].format;
+        let blittable = capabilities
+            .supported_usage_flags
+            .intersects(ImageUsage::TRANSFER_DST)
+            && self
+                .device
+                .physical_device()
+                .format_properties(sdr_format)
+                .is_ok_and(|properties| {
+                    properties
+                        .optimal_tiling_features
+                        .intersects(FormatFeatures::BLIT_DST)
+                });
+
+        let present_mode = choose_present_mode(&self.device, &surface, self.present_mode);
+        let (swapchain, images) = Swapchain::new(
+            self.device.clone(),
+            surface,
+            SwapchainCreateInfo {
+                min_image_count: capabilities.min_image_count.max(2),
+                image_format: format,
+                image_color_space: color_space,
+                image_extent: window.inner_size().into(),
+                image_usage: swapchain_usage(hdr.is_some(), copyable, blittable),
+                composite_alpha: capabilities
+                    .supported_composite_alpha
+                    .into_iter()
+                    .next()
+                    .unwrap(),
+                present_mode,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut target = Target {
+            window,
+            swapchain,
+            framebuffers: Vec::new(),
+            blur: None,
+            atlas: None,
+            copyable,
+            blittable,
+            hdr: None,
+            post: None,
+            failed_post: None,
+            lut: None,
+            timestamps: None,
+            previous_frame_end: None,
+            recreate_swapchain: false,
+        };
+        self.attach_images(&mut target, &images, hdr)
+            .map_err(|e| e.to_string())?;
+        Ok(target)
+    }
+
+    /// Recreates the swapchain of `output` at `extent`; returns whether it has one.
+    fn recreate_swapchain(&mut self, output: NodeId, extent: [u32; 2]) -> bool {
+        let Some(mut target) = self.targets.remove(&output) else {
+            return false;
+        };
+        let surface = target.swapchain.surface().clone();
+        let recreated = self.swapchain_format(&surface).and_then(|(format, color_space, hdr)| {
+            let present_mode = choose_present_mode(&self.device, &surface, self.present_mode);
+            target
+                .swapchain
+                .recreate(SwapchainCreateInfo {
+                    image_extent: extent,
+                    image_format: format,
+                    image_color_space: color_space,
+                    image_usage: swapchain_usage(hdr.is_some(), target.copyable, target.blittable),
+                    present_mode,
+                    ..target.swapchain.create_info()
+                })
+                .map(|recreated| (recreated, hdr))
+                .map_err(|e| e.to_string())
+        });
+        let ok = match recreated {
+            Ok(((swapchain, images), hdr)) => {
+                target.swapchain = swapchain;
+                // Until the images are attached, the swapchain is recreated every frame.
+                match self.attach_images(&mut target, &images, hdr) {
+                    Ok(()) => {
+                        target.recreate_swapchain = false;
+                        true
+                    }
+                    Err(FrameError::Lost) => {
+                        self.lost = true;
+                        false
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to attach swapchain images: {}", e);
+                        false
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to recreate swapchain: {}", e);
+                false
+            }
+        };
+        self.targets.insert(output, target);
+        ok
+    }
+
+    /// The swapchain format and color space for `surface`: an HDR one if HDR is on and
+    /// the surface has one, otherwise the SDR format every pass renders in.
+    fn swapchain_format(
+        &self,
+        surface: &Surface,
+    ) -> Result<(Format, ColorSpace, Option<HdrFormat>), String> {
+        let formats = self
+            .device
+            .physical_device()
+            .surface_formats(surface, Default::default())
+            .map_err(|e| e.to_string())?;
+        if self.hdr.enabled {
+            if let Some(hdr) = hdr::choose_format(&formats) {
+                return Ok((hdr.format, hdr.color_space, Some(hdr)));
+            }
+        }
+        let format = self.render_pass.attachments()[0].format;
+        if !formats.contains(&(format, ColorSpace::SrgbNonLinear)) {
+            return Err(format!("the window doesn't support {:?}", format));
+        }
+        Ok((format, ColorSpace::SrgbNonLinear, None))
+    }
+
+    /// Points `target` at new swapchain `images`, converting into them if they're HDR.
+    /// Fails if what frames are drawn into can't be allocated; blur is only dropped.
+    fn attach_images(
+        &self,
+        target: &mut Target,
+        images: &[Arc<Image>],
+        hdr: Option<HdrFormat>,
+    ) -> Result<(), FrameError> {
+        match hdr {
+            Some(format) => {
+                match target.hdr.as_mut() {
+                    Some(current) if current.format() == format => current.resize(
+                        &self.memory_allocator,
+                        &self.descriptor_set_allocator,
+                        &self.sampler,
+                        &self.render_pass,
+                        images,
+                    )?,
+                    _ => {
+                        println!("Output switched to HDR ({:?}).", format.color_space);
+                        target.hdr = Some(HdrTarget::new(
+                            self.device.clone(),
+                            &self.memory_allocator,
+                            &self.descriptor_set_allocator,
+                            &self.sampler,
+                            &self.render_pass,
+                            &self.sources,
+                            format,
+                            images,
+                        )?);
+                    }
+                }
+                target.framebuffers.clear();
+            }
+            None => {
+                if target.hdr.take().is_some() {
+                    println!("Output switched to SDR.");
+                }
+                target.framebuffers = create_framebuffers(images, self.render_pass.clone())?;
+            }
+        }
+        if target.timestamps.as_ref().map(Timestamps::images) != Some(images.len()) {
+            target.timestamps = Timestamps::new(
+                self.device.clone(),
+                self.queue.queue_family_index(),
+                images.len(),
+            );
+        }
+        // In HDR the frame is composited into an image blur can always copy from.
+        let blurrable = target.hdr.is_some() || target.copyable;
+        if !blurrable {
+            target.blur = None;
+        } else if target.blur.is_none() {
+            target.blur = self.create_blur();
+        }
+        Ok(())
+    }
+
+    /// Uploads the glyphs `items` draw into `atlas`, creating it for the first text.
+    fn prepare_atlas(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        atlas: &mut Option<GlyphAtlas>,
+        items: &[DrawItem],
+    ) -> Result<(), FrameError> {
+        let text = items
+            .iter()
+            .any(|item| matches!(item.source, DrawSource::Glyph { .. }));
+        if atlas.is_none() && !text {
+            return Ok(());
+        }
+        if atlas.is_none() {
+            *atlas = Some(GlyphAtlas::new(
+                &self.memory_allocator,
+                &self.descriptor_set_allocator,
+                &self.sampler,
+                &self.text_pipeline,
+            )?);
+        }
+        match atlas {
+            Some(atlas) => atlas.prepare(&self.memory_allocator, builder, items),
+            None => Ok(()),
+        }
+    }
+
+    /// Uploads `lut` for `output` unless it already was; without one, the output's last
+    /// table is dropped.
+    fn prepare_lut(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        output: NodeId,
+        lut: Option<&ColorLut>,
+    ) {
+        let target = self.targets.get_mut(&output).unwrap();
+        let Some(lut) = lut else {
+            target.lut = None;
+            return;
+        };
+        if target.lut.as_ref().is_some_and(|(serial, _)| *serial == lut.serial) {
+            return;
+        }
+        // Tables that fail are remembered too, so they aren't tried again every frame.
+        let set = upload_lut(&self.memory_allocator, builder, lut.size, &lut.data).and_then(|view| {
+            lut_set(&self.descriptor_set_allocator, &self.post_pipeline, view)
+                .map_err(|e| e.to_string())
+        });
+        let set = match set {
+            Ok(set) => set,
+            Err(e) => {
+                eprintln!("Failed to upload color lookup table: {}", e);
+                None
+            }
+        };
+        target.lut = Some((lut.serial, set));
+    }
+
+    /// A blur built from the current shaders, which are known to build; without one,
+    /// what's behind backdrops is drawn unblurred.
+    fn create_blur(&self) -> Option<Blur> {
+        let format = self.render_pass.attachments()[0].format;
+        Blur::new(self.device.clone(), format, &self.sources)
+            .map_err(|e| eprintln!("Failed to create blur; drawing without it: {}", e))
+            .ok()
+    }
+
+    /// Uploads contents of windows that changed since they were last uploaded; returns
+    /// the windows `items` show.
+    fn upload_window_textures(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        items: &[DrawItem],
+    ) -> Vec<u32> {
+        let mut visible = Vec::new();
+        let frame = self.frame;
+        for item in items {
+            let DrawSource::Window {
+                id,
+                serial,
+                contents,
+                ..
+            } = &item.source
+            else {
+                continue;
+            };
+            visible.push(*id);
+            if let Some(texture) = self.textures.get_mut(id).filter(|t| t.serial == *serial) {
+                texture.shown = frame;
+                continue;
+            }
+            if self.failed_uploads.get(id) == Some(serial) {
+                continue;
+            }
+            let view = match upload_contents(&self.memory_allocator, builder, contents) {
+                Ok(view) => view,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to upload {}x{} contents of window {}: {}",
+                        contents.width, contents.height, id, e
+                    );
+                    self.failed_uploads.insert(*id, *serial);
+                    continue;
+                }
+            };
+            let image = view.image().clone();
+            let set = match texture_set(
+                &self.descriptor_set_allocator,
+                &self.pipeline,
+                view,
+                &self.sampler,
+            ) {
+                Ok(set) => set,
+                Err(e) => {
+                    eprintln!("Failed to bind the contents of window {}: {}", id, e);
+                    self.failed_uploads.insert(*id, *serial);
+                    continue;
+                }
+            };
+            self.failed_uploads.remove(id);
+            self.textures.insert(
+                *id,
+                Texture {
+                    serial: *serial,
+                    image,
+                    set,
+                    bytes: u64::from(contents.width) * u64::from(contents.height) * 4,
+                    shown: frame,
+                    contents: Arc::downgrade(&contents.data),
+                },
+            );
+        }
+        visible
+    }
+
+    /// Whether an output showed `window` in its last frame.
+    fn shown(&self, window: u32) -> bool {
+        self.visible.values().any(|ids| ids.contains(&window))
+    }
+
+    /// Drops textures of contents the scene let go of, then cached ones, least
+    /// recently shown first, while there are more than the cache holds or the process
+    /// uses more of the device's memory than the budget fraction.
+    fn evict_textures(&mut self) {
+        let visible = &self.visible;
+        let shown = |id: &u32| visible.values().any(|ids| ids.contains(id));
+        self.textures
+            .retain(|id, texture| shown(id) || texture.contents.strong_count() > 0);
+
+        if self.budget.is_none_or(|(read, _)| read.elapsed() >= BUDGET_POLL) {
+            let budget = memory::device_budget(self.device.physical_device());
+            self.budget = Some((Instant::now(), budget));
+        }
+        let mut excess = match self.budget {
+            Some((_, Some(budget))) => {
+                let fraction = f64::from(self.memory.budget_fraction);
+                budget.usage.saturating_sub((budget.budget as f64 * fraction) as u64)
+            }
+            _ => 0,
+        };
+        let mut cached: Vec<(u64, u32, u64)> = self
+            .textures
+            .iter()
+            .filter(|(id, _)| !shown(id))
+            .map(|(id, texture)| (texture.shown, *id, texture.bytes))
+            .collect();
+        cached.sort_unstable();
+        let mut cached_bytes: u64 = cached.iter().map(|&(_, _, bytes)| bytes).sum();
+        let limit = u64::from(self.memory.cache_mib) * MIB;
+        for (_, id, bytes) in cached {
+            if cached_bytes <= limit && excess == 0 {
+                break;
+            }
+            self.textures.remove(&id);
+            cached_bytes -= bytes;
+            // The budget is read again later; until then, count what is freed.
+            excess = excess.saturating_sub(bytes);
+            self.evicted += 1;
+        }
+    }
+
+    /// The texture of `item` if it's a window filling an `extent` output pixel for pixel.
+    fn scanout_image(&self, item: &DrawItem, extent: [u32; 2]) -> Option<Arc<Image>> {
+        let DrawSource::Window {
+            id, contents, part, ..
+        } = &item.source
+        else {
+            return None;
+        };
+        let [width, height] = extent.map(|v| v as f32);
+        let exact = item.rect == [0.0, 0.0, width, height]
+            && *part == WHOLE_CONTENTS
+            && [contents.width, contents.height] == extent
+            && item.color == [1.0; 4]
+            && item.corner_radius == 0.0
+            && item.border == 0.0;
+        exact
+            .then(|| self.textures.get(id))
+            .flatten()
+            .map(|texture| texture.image.clone())
+    }
+}
+
+/// Usage of swapchain images; HDR ones are only drawn into by the conversion pass.
+fn swapchain_usage(hdr: bool, copyable: bool, blittable: bool) -> ImageUsage {
+    let mut usage = ImageUsage::COLOR_ATTACHMENT;
+    if copyable && !hdr {
+        usage |= ImageUsage::TRANSFER_SRC;
+    }
+    if blittable && !hdr {
+        usage |= ImageUsage::TRANSFER_DST;
+    }
+    usage
+}
+
+/// What of `items` shows on an `extent` output: everything from the last opaque window
+/// covering all of it on, since that hides whatever is drawn before it.
+fn unoccluded(items: &[DrawItem], extent: [u32; 2]) -> &[DrawItem] {
+    let [width, height] = extent.map(|v| v as f32);
+    let covers = |item: &DrawItem| {
+        let [x, y, w, h] = item.rect;
+        matches!(&item.source, DrawSource::Window { contents, .. } if contents.opaque)
+            && item.color[3] >= 1.0
+            && item.corner_radius == 0.0
+            && x <= 0.0
+            && y <= 0.0
+            && x + w >= width
+            && y + h >= height
+    };
+    match items.iter().rposition(covers) {
+        Some(first) => &items[first..],
+        None => items,
+    }
+}
+
+fn descriptor_set(
+    textures: &HashMap<u32, Texture>,
+    white: &Arc<PersistentDescriptorSet>,
+    item: &DrawItem,
+) -> Arc<PersistentDescriptorSet> {
+    match &item.source {
+        DrawSource::Window { id, .. } => textures
+            .get(id)
+            .map(|texture| texture.set.clone())
+            .unwrap_or_else(|| white.clone()),
+        DrawSource::Solid
+        | DrawSource::Backdrop(_)
+        | DrawSource::Shadow(_)
+        | DrawSource::Glyph { .. } => white.clone(),
+    }
+}
+
+/// Creates the single-subpass pass drawing into an output, clearing it first unless `load`.
+fn create_render_pass(
+    device: Arc<Device>,
+    format: Format,
+    load: bool,
+) -> Result<Arc<RenderPass>, String> {
+    let render_pass = if load {
+        vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+    } else {
+        vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+    };
+    render_pass.map_err(|e| e.to_string())
+}
+
+/// Picks the supported Vulkan mode closest to `mode`, never trading tear-free for tearing.
+fn choose_present_mode(device: &Arc<Device>, surface: &Surface, mode: PresentMode) -> VkPresentMode {
+    let preferences: &[VkPresentMode] = match mode {
+        PresentMode::Fifo => &[VkPresentMode::Fifo],
+        PresentMode::Mailbox => &[VkPresentMode::Mailbox, VkPresentMode::Fifo],
+        PresentMode::Immediate => &[
+            VkPresentMode::Immediate,
+            VkPresentMode::FifoRelaxed,
+            VkPresentMode::Mailbox,
+            VkPresentMode::Fifo,
+        ],
+    };
+    let supported: Vec<VkPresentMode> = device
+        .physical_device()
+        .surface_present_modes(surface, Default::default())
+        .map(Iterator::collect)
+        .unwrap_or_default();
+    // FIFO support is required by the spec.
+    let chosen = preferences
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(VkPresentMode::Fifo);
+    if chosen != preferences[0] {
+        eprintln!("Present mode {:?} is unsupported; using {:?}", mode, chosen);
+    }
+    chosen
+}
+
+fn upload_contents(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    contents: &WindowContents,
+) -> Result<Arc<ImageView>, String> {
+    upload_texture(
+        memory_allocator,
+        builder,
+        contents.width,
+        contents.height,
+        contents.data.iter().copied(),
+    )
+}
+
+/// Records a copy of BGRA `pixels` into a new sampled image, unless there is no memory
+/// for it.
+fn upload_texture(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    width: u32,
+    height: u32,
+    pixels: impl IntoIterator<Item = u8, IntoIter = impl ExactSizeIterator<Item = u8>>,
+) -> Result<Arc<ImageView>, String> {
+    let staging = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        pixels,
+    )
+    .map_err(|e| e.to_string())?;
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::B8G8R8A8_UNORM,
+            extent: [width.max(1), height.max(1), 1],
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+        .map_err(|e| e.to_string())?;
+    ImageView::new_default(image).map_err(|e| e.to_string())
+}
+
+/// Where devices of `device_type` come for `gpu`, lowest first.
+fn gpu_rank(gpu: GpuPreference, device_type: PhysicalDeviceType) -> u8 {
+    let preferred = match gpu {
+        GpuPreference::Any => return 0,
+        GpuPreference::Discrete => PhysicalDeviceType::DiscreteGpu,
+        GpuPreference::Integrated => PhysicalDeviceType::IntegratedGpu,
+    };
+    match device_type {
+        t if t == preferred => 0,
+        PhysicalDeviceType::DiscreteGpu | PhysicalDeviceType::IntegratedGpu => 1,
+        _ => 2,
+    }
+}
+
+fn texture_set(
+    allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<GraphicsPipeline>,
+    view: Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+) -> Result<Arc<PersistentDescriptorSet>, Validated<VulkanError>> {
+    PersistentDescriptorSet::new(
+        allocator,
+        pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::image_view(0, view),
+            WriteDescriptorSet::sampler(1, sampler.clone()),
+        ],
+        [],
+    )
+}
+
+/// Builds the instanced quad pipeline used for windows and decorations.
+fn create_quad_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    sources: &ShaderSources,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = sources.load(device.clone(), ShaderFile::QuadVert)?;
+    let fs = sources.load(device.clone(), ShaderFile::QuadFrag)?;
+    create_instanced_pipeline(device, render_pass, vs, fs)
+}
+
+/// Builds the pipeline drawing glyphs, which takes the same instances as quads.
+fn create_text_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    sources: &ShaderSources,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = sources.load(device.clone(), ShaderFile::TextVert)?;
+    let fs = sources.load(device.clone(), ShaderFile::TextFrag)?;
+    create_instanced_pipeline(device, render_pass, vs, fs)
+}
+
+/// Builds a pipeline drawing one premultiplied, blended quad per `QuadInstance`.
+fn create_instanced_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, String> {
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let vertex_input_state = VertexInputState::new()
+        .binding(
+            0,
+            VertexInputBindingDescription {
+                stride: std::mem::size_of::<QuadInstance>() as u32,
+                input_rate: VertexInputRate::Instance { divisor: 1 },
+            },
+        )
+        .attribute(
+            0,
+            VertexInputAttributeDescription {
+                binding: 0,
+                format: Format::R32G32B32A32_SFLOAT,
+                offset: 0,
+            },
+        )
+        .attribute(
+            1,
+            VertexInputAttributeDescription {
+                binding: 0,
+                format: Format::R32G32B32A32_SFLOAT,
+                offset: 16,
+            },
+        )
+        .attribute(
+            2,
+            VertexInputAttributeDescription {
+                binding: 0,
+                format: Format::R32G32B32A32_SFLOAT,
+                offset: 32,
+            },
+        )
+        .attribute(
+            3,
+            VertexInputAttributeDescription {
+                binding: 0,
+                format: Format::R32G32B32A32_SFLOAT,
+                offset: 48,
+            },
+        );
+
+    // Colors are premultiplied by the scene traversal.
+    let blend = AttachmentBlend {
+        src_color_blend_factor: BlendFactor::One,
+        dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+        color_blend_op: BlendOp::Add,
+        src_alpha_blend_factor: BlendFactor::One,
+        dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+        alpha_blend_op: BlendOp::Add,
+    };
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(blend),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn create_framebuffers(
+    images: &[Arc<Image>],
+    render_pass: Arc<RenderPass>,
+) -> Result<Vec<Arc<Framebuffer>>, Validated<VulkanError>> {
+    images
+        .iter()
+        .map(|image| {
+            let view = ImageView::new_default(image.clone())?;
+            Framebuffer::new(
+                render_pass.clone(),
+                FramebufferCreateInfo {
+                    attachments: vec![view],
+                    ..Default::default()
+                },
+            )
+        })
+        .collect()
+}
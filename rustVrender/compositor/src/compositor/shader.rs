@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use naga::back::spv;
+use naga::front::glsl;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+
+use vulkano::device::Device;
+use vulkano::shader::{EntryPoint, ShaderModule, ShaderModuleCreateInfo};
+
+/// GLSL sources built into the binary.
+pub const QUAD_VERT: &str = include_str!("shaders/quad.vert");
+pub const QUAD_FRAG: &str = include_str!("shaders/quad.frag");
+pub const FULLSCREEN_VERT: &str = include_str!("shaders/fullscreen.vert");
+pub const KAWASE_DOWN_FRAG: &str = include_str!("shaders/kawase_down.frag");
+pub const KAWASE_UP_FRAG: &str = include_str!("shaders/kawase_up.frag");
+pub const HDR_FRAG: &str = include_str!("shaders/hdr.frag");
+pub const POST_FRAG: &str = include_str!("shaders/post.frag");
+pub const TEXT_VERT: &str = include_str!("shaders/text.vert");
+pub const TEXT_FRAG: &str = include_str!("shaders/text.frag");
+
+/// One of the shaders the compositor runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderFile {
+    QuadVert,
+    QuadFrag,
+    FullscreenVert,
+    KawaseDownFrag,
+    KawaseUpFrag,
+    HdrFrag,
+    PostFrag,
+    TextVert,
+    TextFrag,
+}
+
+impl ShaderFile {
+    pub const ALL: [ShaderFile; 9] = [
+        Self::QuadVert,
+        Self::QuadFrag,
+        Self::FullscreenVert,
+        Self::KawaseDownFrag,
+        Self::KawaseUpFrag,
+        Self::HdrFrag,
+        Self::PostFrag,
+        Self::TextVert,
+        Self::TextFrag,
+    ];
+
+    /// The file's name, in the source tree and in a shader directory alike.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::QuadVert => "quad.vert",
+            Self::QuadFrag => "quad.frag",
+            Self::FullscreenVert => "fullscreen.vert",
+            Self::KawaseDownFrag => "kawase_down.frag",
+            Self::KawaseUpFrag => "kawase_up.frag",
+            Self::HdrFrag => "hdr.frag",
+            Self::PostFrag => "post.frag",
+            Self::TextVert => "text.vert",
+            Self::TextFrag => "text.frag",
+        }
+    }
+
+    pub fn builtin(self) -> &'static str {
+        match self {
+            Self::QuadVert => QUAD_VERT,
+            Self::QuadFrag => QUAD_FRAG,
+            Self::FullscreenVert => FULLSCREEN_VERT,
+            Self::KawaseDownFrag => KAWASE_DOWN_FRAG,
+            Self::KawaseUpFrag => KAWASE_UP_FRAG,
+            Self::HdrFrag => HDR_FRAG,
+            Self::PostFrag => POST_FRAG,
+            Self::TextVert => TEXT_VERT,
+            Self::TextFrag => TEXT_FRAG,
+        }
+    }
+
+    pub fn stage(self) -> ShaderStage {
+        match self {
+            Self::QuadVert | Self::FullscreenVert | Self::TextVert => ShaderStage::Vertex,
+            _ => ShaderStage::Fragment,
+        }
+    }
+}
+
+/// GLSL source of every shader, starting out as the built-in ones.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderSources {
+    overrides: HashMap<ShaderFile, String>,
+}
+
+impl ShaderSources {
+    pub fn get(&self, file: ShaderFile) -> &str {
+        self.overrides
+            .get(&file)
+            .map_or(file.builtin(), String::as_str)
+    }
+
+    pub fn set(&mut self, file: ShaderFile, source: String) {
+        self.overrides.insert(file, source);
+    }
+
+    /// Compiles and loads `file` on `device`.
+    pub fn load(&self, device: Arc<Device>, file: ShaderFile) -> Result<EntryPoint, String> {
+        load(device, self.get(file), file.stage()).map_err(|e| format!("{}: {}", file.file_name(), e))
+    }
+}
+
+/// Compiles GLSL source into SPIR-V words.
+pub fn compile(source: &str, stage: ShaderStage) -> Result<Vec<u32>, String> {
+    let module = glsl::Frontend::default()
+        .parse(&glsl::Options::from(stage), source)
+        .map_err(|e| e.emit_to_string(source))?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(source))?;
+    // The sources are written against Vulkan's coordinate space already.
+    let options = spv::Options {
+        flags: spv::WriterFlags::empty(),
+        ..Default::default()
+    };
+    spv::write_vec(&module, &info, &options, None).map_err(|e| e.to_string())
+}
+
+/// Compiles GLSL source and loads its `main` entry point on `device`.
+pub fn load(device: Arc<Device>, source: &str, stage: ShaderStage) -> Result<EntryPoint, String> {
+    let words = compile(source, stage)?;
+    // SAFETY: the words were produced and validated by naga above.
+    let module = unsafe { ShaderModule::new(device, ShaderModuleCreateInfo::new(&words)) }
+        .map_err(|e| e.to_string())?;
+    module
+        .entry_point("main")
+        .ok_or_else(|| "shader has no main entry point".to_string())
+}
+
+/// How often a shader directory is checked for edits.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A directory of shader sources, watched for edits.
+pub struct ShaderDir {
+    path: PathBuf,
+    /// Modification time of each file when it was last read.
+    modified: HashMap<ShaderFile, SystemTime>,
+    next_poll: Instant,
+}
+
+impl ShaderDir {
+    /// Opens `path`, first writing the built-in source of every shader missing there.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        fs::create_dir_all(path)?;
+        for file in ShaderFile::ALL {
+            let file_path = path.join(file.file_name());
+            if !file_path.exists() {
+                fs::write(&file_path, file.builtin())?;
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified: HashMap::new(),
+            next_poll: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sources of the shaders edited since they were last read, at most every
+    /// `POLL_INTERVAL`; every shader the first time.
+    pub fn poll(&mut self, now: Instant) -> Vec<(ShaderFile, String)> {
+        if now < self.next_poll {
+            return Vec::new();
+        }
+        self.next_poll = now + POLL_INTERVAL;
+        let mut changed = Vec::new();
+        for file in ShaderFile::ALL {
+            let path = self.path.join(file.file_name());
+            // Files that went missing keep their last source.
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if self.modified.get(&file) == Some(&modified) {
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(source) => {
+                    self.modified.insert(file, modified);
+                    changed.push((file, source));
+                }
+                Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+        changed
+    }
+}
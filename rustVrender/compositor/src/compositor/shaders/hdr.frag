@@ -0,0 +1,53 @@
+#version 450
+
+// Maps the SDR-composited frame into an HDR swapchain: sRGB content is decoded,
+// placed at the SDR white level, and encoded for HDR10 (PQ, BT.2020) or scRGB.
+
+layout(push_constant) uniform PushConstants {
+    // Brightness of SDR white, in nits.
+    float sdr_white;
+    // 0: the frame holds sRGB-encoded values, 1: it was sampled as linear already.
+    uint linear_input;
+    // 0: HDR10 (PQ over BT.2020 primaries), 1: scRGB (linear BT.709, 1.0 = 80 nits).
+    uint transfer;
+} pc;
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout(location = 0) out vec4 f_color;
+
+vec3 srgb_to_linear(vec3 c) {
+    vec3 low = c / 12.92;
+    vec3 high = pow((c + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.04045))));
+}
+
+// SMPTE ST 2084 inverse EOTF; `y` is luminance relative to 10000 nits.
+vec3 pq(vec3 y) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 p = pow(clamp(y, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+}
+
+void main() {
+    vec4 frame = texture(sampler2D(tex, samp), v_uv);
+    vec3 rgb = pc.linear_input == 1u ? frame.rgb : srgb_to_linear(frame.rgb);
+    if (pc.transfer == 0u) {
+        // BT.709 to BT.2020 primaries (ITU-R BT.2087), columns first.
+        const mat3 to_2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956);
+        rgb = pq(to_2020 * rgb * (pc.sdr_white / 10000.0));
+    } else {
+        rgb *= pc.sdr_white / 80.0;
+    }
+    f_color = vec4(rgb, 1.0);
+}
@@ -0,0 +1,29 @@
+#version 450
+
+// Dual-kawase upsample: a tent of eight taps around the target texel.
+
+layout(push_constant) uniform PushConstants {
+    // Half a texel of the target, in UV units.
+    vec2 half_pixel;
+    float offset;
+} pc;
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec2 o = pc.half_pixel * pc.offset;
+    vec4 sum = texture(sampler2D(tex, samp), v_uv + vec2(-o.x * 2.0, 0.0));
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(-o.x, o.y)) * 2.0;
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(0.0, o.y * 2.0));
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(o.x, o.y)) * 2.0;
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(o.x * 2.0, 0.0));
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(o.x, -o.y)) * 2.0;
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(0.0, -o.y * 2.0));
+    sum += texture(sampler2D(tex, samp), v_uv + vec2(-o.x, -o.y)) * 2.0;
+    f_color = sum / 12.0;
+}
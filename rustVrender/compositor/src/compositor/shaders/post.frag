@@ -0,0 +1,76 @@
+#version 450
+
+// Runs an output's post-processing chain over the composited frame. The passes only
+// change colors, so they are applied one after another within a single draw.
+
+layout(push_constant) uniform PushConstants {
+    // x: the effect (0 gamma, 1 invert, 2 grayscale, 3 color temperature, 4 CRT),
+    // yzw: its parameters.
+    vec4 stages[6];
+    uint count;
+    // 1 if the frame is sampled as linear values and written back as sRGB.
+    uint linear_input;
+    // 1 if the output's color lookup table applies after the passes.
+    uint use_lut;
+} pc;
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+// Maps sRGB values to the ones the display needs to show them right.
+layout(set = 1, binding = 0) uniform texture3D lut;
+
+layout(location = 0) out vec4 f_color;
+
+vec3 srgb_to_linear(vec3 c) {
+    vec3 low = c / 12.92;
+    vec3 high = pow((c + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.04045))));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    vec3 low = c * 12.92;
+    vec3 high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.0031308))));
+}
+
+void main() {
+    vec4 frame = texture(sampler2D(tex, samp), v_uv);
+    vec3 rgb = clamp(frame.rgb, 0.0, 1.0);
+    if (pc.linear_input == 1u) {
+        rgb = linear_to_srgb(rgb);
+    }
+
+    for (uint i = 0u; i < pc.count; i++) {
+        vec4 stage = pc.stages[i];
+        if (stage.x < 0.5) {
+            rgb = pow(clamp(rgb * stage.z, 0.0, 1.0), vec3(1.0 / stage.y));
+        } else if (stage.x < 1.5) {
+            rgb = 1.0 - rgb;
+        } else if (stage.x < 2.5) {
+            float luma = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+            rgb = mix(rgb, vec3(luma), stage.y);
+        } else if (stage.x < 3.5) {
+            rgb *= stage.yzw;
+        } else {
+            // Every other row dims, and the corners fall off with the squared distance.
+            float row = mod(floor(gl_FragCoord.y), 2.0);
+            rgb *= 1.0 - stage.y * row;
+            vec2 from_center = v_uv - 0.5;
+            rgb *= 1.0 - stage.z * dot(from_center, from_center) * 2.0;
+        }
+    }
+
+    if (pc.use_lut == 1u) {
+        // Scaled so the outermost texels are sampled at their centers.
+        float size = float(textureSize(sampler3D(lut, samp), 0).x);
+        vec3 at = clamp(rgb, 0.0, 1.0) * ((size - 1.0) / size) + 0.5 / size;
+        rgb = texture(sampler3D(lut, samp), at).rgb;
+    }
+
+    if (pc.linear_input == 1u) {
+        rgb = srgb_to_linear(clamp(rgb, 0.0, 1.0));
+    }
+    f_color = vec4(rgb, 1.0);
+}
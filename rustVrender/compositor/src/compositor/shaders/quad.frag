@@ -0,0 +1,64 @@
+#version 450
+
+layout(location = 0) in vec4 v_color;
+layout(location = 1) in vec2 v_uv;
+layout(location = 2) in vec4 v_params;
+layout(location = 3) in vec2 v_local;
+layout(location = 4) in vec2 v_size;
+
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout(location = 0) out vec4 f_color;
+
+// Signed distance from `p` to a rounded box centered on the origin.
+float rounded_box(vec2 p, vec2 half_size, float radius) {
+    vec2 q = abs(p) - half_size + radius;
+    return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+}
+
+// Moves `uv` towards the center of its texel wherever a texel covers more than one
+// pixel, so scaled-up contents keep crisp edges blended over a single pixel instead of
+// being smeared by bilinear filtering. Scaling down keeps plain bilinear filtering.
+vec2 sharp_uv(vec2 uv) {
+    vec2 size = vec2(textureSize(sampler2D(tex, samp), 0));
+    vec2 texel = uv * size;
+    vec2 pixels_per_texel = max(1.0 / max(fwidth(texel), vec2(1e-6)), vec2(1.0));
+    // Inside a texel, all but the pixel nearest each edge samples its center.
+    vec2 inner = 0.5 - 0.5 / pixels_per_texel;
+    vec2 from_center = fract(texel) - 0.5;
+    vec2 edge = (from_center - clamp(from_center, -inner, inner)) * pixels_per_texel;
+    return (floor(texel) + 0.5 + edge) / size;
+}
+
+void main() {
+    vec2 uv = v_params.w > 2.5 ? sharp_uv(v_uv) : v_uv;
+    vec4 texel = texture(sampler2D(tex, samp), uv);
+    if (v_params.x > 0.5) {
+        texel.a = 1.0;
+    }
+    if (v_params.w > 3.5) {
+        // Premultiplied, so the luma stays within the alpha.
+        float luma = dot(texel.rgb, vec3(0.2126, 0.7152, 0.0722));
+        texel.rgb = vec3(luma * 0.6);
+    }
+
+    // Coverage of the rounded shape, anti-aliased over one pixel.
+    vec2 half_size = v_size * 0.5;
+    vec2 p = v_local - half_size;
+    float radius = min(v_params.y, min(half_size.x, half_size.y));
+    float coverage = clamp(0.5 - rounded_box(p, half_size, radius), 0.0, 1.0);
+    float ring = v_params.z;
+    if (v_params.w > 1.5 && v_params.w < 2.5) {
+        // A shadow: the shape inset by its spread, faded out across twice that.
+        float spread = max(ring, 0.5);
+        float d = rounded_box(p, half_size - spread, max(radius - spread, 0.0));
+        coverage = 1.0 - smoothstep(-spread, spread, d);
+    } else if (ring > 0.0) {
+        float inner = rounded_box(p, half_size - ring, max(radius - ring, 0.0));
+        coverage *= clamp(0.5 + inner, 0.0, 1.0);
+    }
+
+    // Colors are premultiplied, so coverage scales every channel.
+    f_color = texel * v_color * coverage;
+}
@@ -0,0 +1,39 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec2 viewport;
+} pc;
+
+// Per-instance rectangle in output pixels (x, y, width, height).
+layout(location = 0) in vec4 rect;
+// Per-instance premultiplied color, multiplied with the texture.
+layout(location = 1) in vec4 color;
+// x: the texture has no alpha channel.
+// y: corner radius in pixels.
+// z: border thickness in pixels; when set only a ring of this width is drawn.
+//    For shadows, how far the shadow spreads beyond the shape instead.
+// w: 0 for a plain quad, 1 to sample the texture in output space instead of
+//    across the rect, 2 for a shadow, 3 for window contents, which stay sharp
+//    when scaled up, and 4 for window contents drawn gray and dimmed.
+layout(location = 2) in vec4 params;
+// The part of the texture stretched over the rect (x, y, width, height in texture
+// coordinates), for window contents drawn in pieces.
+layout(location = 3) in vec4 part;
+
+layout(location = 0) out vec4 v_color;
+layout(location = 1) out vec2 v_uv;
+layout(location = 2) out vec4 v_params;
+layout(location = 3) out vec2 v_local;
+layout(location = 4) out vec2 v_size;
+
+void main() {
+    // Four vertices per instance, drawn as a triangle strip.
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float(gl_VertexIndex >> 1));
+    vec2 pixel = rect.xy + corner * rect.zw;
+    gl_Position = vec4(pixel / pc.viewport * 2.0 - 1.0, 0.0, 1.0);
+    v_color = color;
+    v_uv = params.w > 0.5 && params.w < 1.5 ? pixel / pc.viewport : part.xy + corner * part.zw;
+    v_params = params;
+    v_local = corner * rect.zw;
+    v_size = rect.zw;
+}
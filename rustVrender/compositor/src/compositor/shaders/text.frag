@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec4 v_color;
+layout(location = 1) in vec2 v_uv;
+
+// Glyph coverage in the red channel.
+layout(set = 0, binding = 0) uniform texture2D atlas;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    float coverage = texture(sampler2D(atlas, samp), v_uv).r;
+    // Colors are premultiplied, so coverage scales every channel.
+    f_color = v_color * coverage;
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec2 viewport;
+} pc;
+
+// Per-instance rectangle in output pixels (x, y, width, height).
+layout(location = 0) in vec4 rect;
+// Per-instance premultiplied color of the text.
+layout(location = 1) in vec4 color;
+// The glyph's rect in the atlas, in texture coordinates (u, v, width, height).
+layout(location = 2) in vec4 params;
+
+layout(location = 0) out vec4 v_color;
+layout(location = 1) out vec2 v_uv;
+
+void main() {
+    // Four vertices per instance, drawn as a triangle strip.
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float(gl_VertexIndex >> 1));
+    vec2 pixel = rect.xy + corner * rect.zw;
+    gl_Position = vec4(pixel / pc.viewport * 2.0 - 1.0, 0.0, 1.0);
+    v_color = color;
+    v_uv = params.xy + corner * params.zw;
+}
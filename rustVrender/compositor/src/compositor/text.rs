@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BufferImageCopy, ClearColorImageInfo, CopyBufferToImageInfo,
+    PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline};
+
+use super::renderer::FrameError;
+use crate::scene::{DrawItem, DrawSource};
+use crate::text::Font;
+
+/// Width and height of the atlas in texels.
+const ATLAS_SIZE: u32 = 1024;
+/// Empty texels kept between glyphs, so filtering never picks up a neighbour.
+const PADDING: u32 = 1;
+/// Copies out of the staging buffer start at multiples of this.
+const COPY_ALIGNMENT: usize = 4;
+
+/// A glyph of one font at one size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: u64,
+    index: u16,
+    px: u16,
+}
+
+/// The row of the atlas glyphs are currently packed into.
+#[derive(Debug, Default)]
+struct Shelf {
+    x: u32,
+    y: u32,
+    height: u32,
+}
+
+/// Coverage of every glyph drawn lately, packed into one texture that `text.frag`
+/// samples. When it fills up it starts over with the glyphs of the frame at hand.
+pub struct GlyphAtlas {
+    image: Arc<Image>,
+    set: Arc<PersistentDescriptorSet>,
+    /// Where each glyph is, in texels; empty for glyphs without pixels.
+    glyphs: HashMap<GlyphKey, [u32; 4]>,
+    shelf: Shelf,
+    /// Whether the image was cleared since it was created or started over.
+    cleared: bool,
+}
+
+impl GlyphAtlas {
+    /// Allocates an empty atlas sampled through `pipeline`'s first set.
+    pub fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        sampler: &Arc<Sampler>,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<Self, FrameError> {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8_UNORM,
+                extent: [ATLAS_SIZE, ATLAS_SIZE, 1],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        let set = PersistentDescriptorSet::new(
+            set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, ImageView::new_default(image.clone())?),
+                WriteDescriptorSet::sampler(1, sampler.clone()),
+            ],
+            [],
+        )?;
+        Ok(Self {
+            image,
+            set,
+            glyphs: HashMap::new(),
+            shelf: Shelf::default(),
+            cleared: false,
+        })
+    }
+
+    pub fn set(&self) -> &Arc<PersistentDescriptorSet> {
+        &self.set
+    }
+
+    /// Records uploads of the glyphs `items` draw that aren't in the atlas yet. If that
+    /// fails the frame can't be drawn, and the atlas starts over with the next one.
+    pub fn prepare(
+        &mut self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        items: &[DrawItem],
+    ) -> Result<(), FrameError> {
+        let recorded = self.record_uploads(memory_allocator, builder, items);
+        if recorded.is_err() {
+            self.start_over();
+        }
+        recorded
+    }
+
+    /// Forgets every glyph, which are uploaded again as they are drawn.
+    fn start_over(&mut self) {
+        self.glyphs.clear();
+        self.shelf = Shelf::default();
+        self.cleared = false;
+    }
+
+    fn record_uploads(
+        &mut self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        items: &[DrawItem],
+    ) -> Result<(), FrameError> {
+        let mut needed: HashMap<GlyphKey, &Arc<Font>> = HashMap::new();
+        for item in items {
+            if let DrawSource::Glyph { font, index, px } = &item.source {
+                let key = GlyphKey {
+                    font: font.id(),
+                    index: *index,
+                    px: *px,
+                };
+                needed.entry(key).or_insert(font);
+            }
+        }
+        let mut uploads = Vec::new();
+        if !self.place(&needed, &mut uploads) {
+            // Full: start over with only what this frame draws.
+            self.start_over();
+            uploads.clear();
+            if !self.place(&needed, &mut uploads) {
+                eprintln!("Too many glyphs for the glyph atlas; some text isn't drawn.");
+            }
+        }
+        if !self.cleared {
+            builder.clear_color_image(ClearColorImageInfo::image(self.image.clone()))?;
+            self.cleared = true;
+        }
+        if uploads.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = Vec::new();
+        let mut regions = Vec::with_capacity(uploads.len());
+        for ([x, y, width, height], coverage) in uploads {
+            data.resize(data.len().next_multiple_of(COPY_ALIGNMENT), 0);
+            regions.push(BufferImageCopy {
+                buffer_offset: data.len() as u64,
+                image_subresource: self.image.subresource_layers(),
+                image_offset: [x, y, 0],
+                image_extent: [width, height, 1],
+                ..Default::default()
+            });
+            data.extend_from_slice(&coverage);
+        }
+        let staging = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            data,
+        )?;
+        builder.copy_buffer_to_image(CopyBufferToImageInfo {
+            regions: regions.into(),
+            ..CopyBufferToImageInfo::buffer_image(staging, self.image.clone())
+        })?;
+        Ok(())
+    }
+
+    /// Rasterizes and packs the `needed` glyphs missing from the atlas, adding their
+    /// pixels to `uploads`; returns false as soon as one doesn't fit.
+    fn place(
+        &mut self,
+        needed: &HashMap<GlyphKey, &Arc<Font>>,
+        uploads: &mut Vec<([u32; 4], Vec<u8>)>,
+    ) -> bool {
+        for (key, font) in needed {
+            if self.glyphs.contains_key(key) {
+                continue;
+            }
+            let ([width, height], coverage) = font.rasterize(key.index, key.px);
+            if width == 0 || height == 0 {
+                self.glyphs.insert(*key, [0; 4]);
+                continue;
+            }
+            let Some([x, y]) = self.allocate(width, height) else {
+                return false;
+            };
+            let rect = [x, y, width, height];
+            self.glyphs.insert(*key, rect);
+            uploads.push((rect, coverage));
+        }
+        true
+    }
+
+    /// Finds room for a glyph of `width` by `height` texels, starting a new shelf below
+    /// the current one when it runs out.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        let (width, height) = (width + PADDING, height + PADDING);
+        if self.shelf.x + width > ATLAS_SIZE {
+            self.shelf = Shelf {
+                x: 0,
+                y: self.shelf.y + self.shelf.height,
+                height: 0,
+            };
+        }
+        if width > ATLAS_SIZE || self.shelf.y + height > ATLAS_SIZE {
+            return None;
+        }
+        let position = [self.shelf.x, self.shelf.y];
+        self.shelf.x += width;
+        self.shelf.height = self.shelf.height.max(height);
+        Some(position)
+    }
+
+    /// Where glyph `index` of `font` at `px` is, in texture coordinates, once prepared
+    /// and if it has pixels.
+    pub fn uv(&self, font: &Font, index: u16, px: u16) -> Option<[f32; 4]> {
+        let key = GlyphKey {
+            font: font.id(),
+            index,
+            px,
+        };
+        let [x, y, width, height] = *self.glyphs.get(&key)?;
+        if width == 0 {
+            return None;
+        }
+        let size = ATLAS_SIZE as f32;
+        Some([
+            x as f32 / size,
+            y as f32 / size,
+            width as f32 / size,
+            height as f32 / size,
+        ])
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::device::Device;
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+
+/// GPU timestamp queries around each frame of one output, two per swapchain image.
+pub struct Timestamps {
+    pool: Arc<QueryPool>,
+    /// Whether each image's pair of queries has been written since it was reset.
+    written: Vec<bool>,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+    /// Timestamps wrap around above this many bits.
+    valid_bits: u32,
+}
+
+impl Timestamps {
+    /// Returns `None` if queues of `queue_family_index` can't write timestamps.
+    pub fn new(device: Arc<Device>, queue_family_index: u32, images: usize) -> Option<Self> {
+        let valid_bits = device.physical_device().queue_family_properties()
+            [queue_family_index as usize]
+            .timestamp_valid_bits?;
+        let period = device.physical_device().properties().timestamp_period as f64;
+        let pool = QueryPool::new(
+            device,
+            QueryPoolCreateInfo {
+                query_count: 2 * images as u32,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        )
+        .ok()?;
+        Some(Self {
+            pool,
+            written: vec![false; images],
+            period,
+            valid_bits,
+        })
+    }
+
+    /// Number of swapchain images this has queries for.
+    pub fn images(&self) -> usize {
+        self.written.len()
+    }
+
+    /// Starts timing a frame drawn into `image`; returns how long the GPU took the last
+    /// time it drew into that image, if the result is in.
+    pub fn begin(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: u32,
+    ) -> Option<Duration> {
+        let queries = 2 * image..2 * image + 2;
+        let previous = if self.written[image as usize] {
+            let mut results = [0u64; 2];
+            match self
+                .pool
+                .get_results(queries.clone(), &mut results, QueryResultFlags::empty())
+            {
+                Ok(true) => {
+                    let mask = u64::MAX >> (64 - self.valid_bits.min(64));
+                    let ticks = results[1].wrapping_sub(results[0]) & mask;
+                    Some(Duration::from_nanos((ticks as f64 * self.period) as u64))
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+        // Both queries were reset before being written and are only used by this frame.
+        unsafe {
+            builder
+                .reset_query_pool(self.pool.clone(), queries)
+                .unwrap()
+                .write_timestamp(self.pool.clone(), 2 * image, PipelineStage::TopOfPipe)
+                .unwrap();
+        }
+        self.written[image as usize] = true;
+        previous
+    }
+
+    /// Ends timing the frame `begin` started on `image`.
+    pub fn end(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: u32,
+    ) {
+        unsafe {
+            builder
+                .write_timestamp(
+                    self.pool.clone(),
+                    2 * image + 1,
+                    PipelineStage::BottomOfPipe,
+                )
+                .unwrap();
+        }
+    }
+}
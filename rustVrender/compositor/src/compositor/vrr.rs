@@ -0,0 +1,169 @@
+// Variable refresh rate on X: the driver runs a CRTC at the rate frames come in while a
+// fullscreen window with _VARIABLE_REFRESH set is flipped on it, here an output window.
+// RandR reports which connectors support it with their vrr_capable property.
+
+use winit::window::Window;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, PropMode};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+/// A connection to the X server the output windows are on.
+pub struct Vrr {
+    conn: RustConnection,
+    root: u32,
+    vrr_capable: Atom,
+    variable_refresh: Atom,
+}
+
+impl Vrr {
+    pub fn connect() -> Result<Self, String> {
+        let (conn, screen) = RustConnection::connect(None).map_err(|e| e.to_string())?;
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &str| -> Result<Atom, String> {
+            conn.intern_atom(false, name.as_bytes())
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map(|reply| reply.atom)
+                .map_err(|e| e.to_string())
+        };
+        let vrr_capable = atom("vrr_capable")?;
+        let variable_refresh = atom("_VARIABLE_REFRESH")?;
+        Ok(Self {
+            conn,
+            root,
+            vrr_capable,
+            variable_refresh,
+        })
+    }
+
+    /// Whether the RandR output called `connector` can refresh at a variable rate.
+    pub fn capable(&self, connector: &str) -> bool {
+        self.query_capable(connector).unwrap_or_else(|e| {
+            eprintln!("Failed to query variable refresh of {}: {}", connector, e);
+            false
+        })
+    }
+
+    fn query_capable(&self, connector: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let resources = self
+            .conn
+            .randr_get_screen_resources_current(self.root)?
+            .reply()?;
+        for output in resources.outputs {
+            let info = self
+                .conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()?;
+            if info.name != connector.as_bytes() {
+                continue;
+            }
+            let property = self
+                .conn
+                .randr_get_output_property(
+                    output,
+                    self.vrr_capable,
+                    AtomEnum::ANY,
+                    0,
+                    1,
+                    false,
+                    false,
+                )?
+                .reply()?;
+            return Ok(property.data.iter().any(|byte| *byte != 0));
+        }
+        Ok(false)
+    }
+
+    /// Whether X window `window` asks for variable refresh, as Mesa's Vulkan and GL
+    /// drivers do for games that have it enabled.
+    pub fn requested(&self, window: u32) -> bool {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.variable_refresh,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        reply
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()))
+            .is_some_and(|value| value != 0)
+    }
+
+    /// Sets or clears _VARIABLE_REFRESH on output window `window`.
+    fn set_enabled(&self, window: u32, enabled: bool) -> Result<(), String> {
+        if enabled {
+            self.conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    window,
+                    self.variable_refresh,
+                    AtomEnum::CARDINAL,
+                    &[1],
+                )
+                .map_err(|e| e.to_string())?;
+        } else {
+            self.conn
+                .delete_property(window, self.variable_refresh)
+                .map_err(|e| e.to_string())?;
+        }
+        self.conn.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Variable refresh of one output window.
+#[derive(Debug, Default)]
+pub struct OutputVrr {
+    /// Whether its monitor supports it, once known.
+    capable: Option<bool>,
+    active: bool,
+    /// The window last asked about, and whether it asks for variable refresh.
+    client: Option<(u32, bool)>,
+}
+
+impl OutputVrr {
+    /// Whether the output currently refreshes at the rate it draws.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Turns variable refresh on for output window `window`, whose X id is `id`, while
+    /// `client`, the window filling it, asks for it, and off otherwise; returns whether
+    /// that changed.
+    pub fn update(&mut self, vrr: &Vrr, window: &Window, id: u32, client: Option<u32>) -> bool {
+        let capable = *self.capable.get_or_insert_with(|| {
+            window
+                .current_monitor()
+                .and_then(|monitor| monitor.name())
+                .is_some_and(|connector| vrr.capable(&connector))
+        });
+        let requested = match (client, self.client) {
+            (Some(client), Some((asked, requested))) if asked == client => requested,
+            (Some(client), _) => {
+                let requested = vrr.requested(client);
+                self.client = Some((client, requested));
+                requested
+            }
+            (None, _) => {
+                self.client = None;
+                false
+            }
+        };
+        let active = capable && requested;
+        if active == self.active {
+            return false;
+        }
+        if let Err(e) = vrr.set_enabled(id, active) {
+            eprintln!("Failed to switch variable refresh: {}", e);
+            return false;
+        }
+        self.active = active;
+        true
+    }
+}
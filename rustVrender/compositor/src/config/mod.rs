@@ -0,0 +1,573 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use crate::compositor::renderer::CLEAR_COLOR;
+use crate::idle::IdleConfig;
+use crate::input::{same_sequence, Gesture, InputConfig, KeyBinding};
+use crate::lock::LockConfig;
+use crate::profile::PowerConfig;
+use crate::scene::accessibility::AccessibilityConfig;
+use crate::scene::animation::{AnimationConfig, Curve};
+use crate::scene::annotate::AnnotateConfig;
+use crate::scene::bar::widgets::WidgetSpec;
+use crate::scene::bar::BarConfig;
+use crate::scene::color::ColorConfig;
+use crate::scene::cursor::{CursorConfig, MAX_CURSOR_SIZE, MIN_CURSOR_SIZE};
+use crate::scene::effects::{EffectsConfig, WindowRule};
+use crate::scene::hint::HintConfig;
+use crate::scene::letterbox::LetterboxConfig;
+use crate::scene::modal::ModalConfig;
+use crate::scene::night_light::{NightLightConfig, MAX_KELVIN, MIN_KELVIN};
+use crate::scene::osd::OsdConfig;
+use crate::scene::pip::PipConfig;
+use crate::scene::post::{PostConfig, PostPass};
+use crate::scene::select::SelectConfig;
+use crate::scene::theme::ThemeConfig;
+use crate::scene::wallpaper::WallpaperConfig;
+use crate::scene::workspace::WorkspaceConfig;
+use crate::scene::zoom::ZoomConfig;
+use crate::scene::{Scene, MAX_OUTPUT_SCALE, MIN_OUTPUT_SCALE};
+
+pub use qtilerugo_ipc::renderer::PresentMode;
+
+/// Where the renderer reads its settings, relative to its working directory.
+pub const CONFIG_PATH: &str = "renderer_config.toml";
+/// Longest any animation may run.
+const MAX_DURATION_MS: u32 = 10_000;
+
+/// Which GPU the renderer picks when there are several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum GpuPreference {
+    /// The first one Vulkan lists that can present to the outputs.
+    #[default]
+    Any,
+    /// A discrete GPU, for speed.
+    Discrete,
+    /// An integrated GPU, for battery life.
+    Integrated,
+}
+
+/// High dynamic range output, on monitors and drivers that support it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HdrConfig {
+    /// Outputs without an HDR format stay SDR either way.
+    pub enabled: bool,
+    /// How bright SDR content, which is everything so far, is shown in HDR.
+    pub sdr_white_nits: f32,
+}
+
+impl Default for HdrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            // The reference white of ITU-R BT.2408.
+            sdr_white_nits: 203.0,
+        }
+    }
+}
+
+/// Variable refresh rate, on monitors and drivers that support it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VrrConfig {
+    /// Lets every output follow the frame rate of a fullscreen game asking for it.
+    pub enabled: bool,
+    /// Overrides `enabled` for outputs by name.
+    pub outputs: HashMap<String, bool>,
+}
+
+/// How much GPU memory window textures are kept in while no output shows them, so
+/// they show again without an upload.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// The most they take together, in MiB.
+    pub cache_mib: u32,
+    /// They go, least recently shown first, while the process uses more than this much
+    /// of the device's memory budget.
+    pub budget_fraction: f32,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            cache_mib: 256,
+            budget_fraction: 0.9,
+        }
+    }
+}
+
+impl VrrConfig {
+    pub fn enabled_for(&self, output: &str) -> bool {
+        self.outputs.get(output).copied().unwrap_or(self.enabled)
+    }
+}
+
+/// Renderer settings, all optional.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where commands are taken; read at startup only.
+    pub socket_path: PathBuf,
+    /// The GPU to render with; applies when a renderer is next created.
+    pub gpu: GpuPreference,
+    /// Background behind every output, as RGBA.
+    pub clear_color: [f32; 4],
+    pub present_mode: PresentMode,
+    /// Frames each output draws a second at most, below its refresh rate.
+    pub max_fps: Option<u32>,
+    /// Lets outputs showing only an opaque fullscreen window skip composition.
+    pub direct_scanout: bool,
+    pub hdr: HdrConfig,
+    pub vrr: VrrConfig,
+    pub memory: MemoryConfig,
+    /// Draws the frame statistics HUD from startup.
+    pub hud: bool,
+    /// Loads shaders from this directory and reloads them when they are edited; the
+    /// built-in ones are written there first if missing.
+    pub shader_dir: Option<PathBuf>,
+    /// The font compositor-drawn text is set in; a common sans-serif font otherwise.
+    pub font: Option<PathBuf>,
+    /// Scale factors by output name, for HiDPI monitors.
+    pub output_scales: HashMap<String, f32>,
+    pub animations: AnimationConfig,
+    /// The palette colors elsewhere can name slots of, like "base0D".
+    pub theme: ThemeConfig,
+    pub effects: EffectsConfig,
+    pub post: PostConfig,
+    pub night_light: NightLightConfig,
+    pub color: ColorConfig,
+    pub wallpaper: WallpaperConfig,
+    pub zoom: ZoomConfig,
+    pub workspaces: WorkspaceConfig,
+    pub pip: PipConfig,
+    pub osd: OsdConfig,
+    pub hint: HintConfig,
+    pub annotate: AnnotateConfig,
+    pub select: SelectConfig,
+    pub accessibility: AccessibilityConfig,
+    pub power: PowerConfig,
+    pub letterbox: LetterboxConfig,
+    pub modal: ModalConfig,
+    pub bar: BarConfig,
+    pub lock: LockConfig,
+    pub idle: IdleConfig,
+    pub cursor: CursorConfig,
+    /// Read when the Wayland frontend starts, except bindings, which apply right away.
+    pub input: InputConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            socket_path: PathBuf::from(qtilerugo_ipc::renderer::SOCKET),
+            gpu: GpuPreference::default(),
+            clear_color: CLEAR_COLOR,
+            present_mode: PresentMode::default(),
+            max_fps: None,
+            direct_scanout: false,
+            hdr: HdrConfig::default(),
+            vrr: VrrConfig::default(),
+            memory: MemoryConfig::default(),
+            hud: false,
+            shader_dir: None,
+            font: None,
+            output_scales: HashMap::new(),
+            animations: AnimationConfig::default(),
+            theme: ThemeConfig::default(),
+            effects: EffectsConfig::default(),
+            post: PostConfig::default(),
+            night_light: NightLightConfig::default(),
+            color: ColorConfig::default(),
+            wallpaper: WallpaperConfig::default(),
+            zoom: ZoomConfig::default(),
+            workspaces: WorkspaceConfig::default(),
+            pip: PipConfig::default(),
+            osd: OsdConfig::default(),
+            hint: HintConfig::default(),
+            annotate: AnnotateConfig::default(),
+            select: SelectConfig::default(),
+            accessibility: AccessibilityConfig::default(),
+            power: PowerConfig::default(),
+            letterbox: LetterboxConfig::default(),
+            modal: ModalConfig::default(),
+            bar: BarConfig::default(),
+            lock: LockConfig::default(),
+            idle: IdleConfig::default(),
+            cursor: CursorConfig::default(),
+            input: InputConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from a TOML file, rejecting unknown settings and values
+    /// out of range.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks every value against its range; the error lists each one that is off.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Problems::default();
+        if self.socket_path.as_os_str().is_empty() {
+            problems.0.push("socket_path is empty".to_string());
+        }
+        problems.color("clear_color", &self.clear_color);
+        if let Some(max_fps) = self.max_fps {
+            problems.range("max_fps", max_fps as f32, 1.0..=1000.0);
+        }
+        problems.range("hdr.sdr_white_nits", self.hdr.sdr_white_nits, 1.0..=10_000.0);
+        problems.range("memory.budget_fraction", self.memory.budget_fraction, 0.1..=1.0);
+        for (output, scale) in &self.output_scales {
+            let setting = format!("output_scales.{}", output);
+            problems.range(&setting, *scale, MIN_OUTPUT_SCALE..=MAX_OUTPUT_SCALE);
+        }
+
+        let animations = &self.animations;
+        problems.curve("animations.map", &animations.map);
+        problems.range("animations.map_scale", animations.map_scale, 0.0..=2.0);
+        problems.curve("animations.unmap", &animations.unmap);
+        problems.curve("animations.geometry", &animations.geometry);
+
+        if self.theme.palette_named(&self.theme.name).is_none() {
+            let name = &self.theme.name;
+            problems.0.push(format!("theme.name {:?} is not a palette", name));
+        }
+
+        let effects = &self.effects;
+        problems.at_least("effects.corner_radius", effects.corner_radius, 0.0);
+        problems.range("effects.blur_strength", effects.blur_strength as f32, 0.0..=20.0);
+        problems.at_least("effects.shadow_radius", effects.shadow_radius, 0.0);
+        problems.range("effects.shadow_opacity", effects.shadow_opacity, 0.0..=1.0);
+        problems.color("effects.shadow_color", &effects.shadow_color);
+        problems.color("effects.focused_border", &effects.focused_border.get());
+        problems.color("effects.unfocused_border", &effects.unfocused_border.get());
+        for (i, rule) in effects.rules.iter().enumerate() {
+            if let WindowRule {
+                corner_radius: Some(radius),
+                ..
+            } = rule
+            {
+                problems.at_least(&format!("effects.rules[{}].corner_radius", i), *radius, 0.0);
+            }
+        }
+
+        for (i, pass) in self.post.passes.iter().enumerate() {
+            let setting = |name: &str| format!("post.passes[{}].{}", i, name);
+            match *pass {
+                PostPass::Gamma { gamma, brightness } => {
+                    problems.range(&setting("gamma"), gamma, 0.1..=10.0);
+                    problems.range(&setting("brightness"), brightness, 0.0..=10.0);
+                }
+                PostPass::Invert => {}
+                PostPass::Grayscale { amount } => {
+                    problems.range(&setting("amount"), amount, 0.0..=1.0);
+                }
+                PostPass::ColorTemperature { kelvin } => {
+                    problems.range(&setting("kelvin"), kelvin, MIN_KELVIN..=MAX_KELVIN);
+                }
+                PostPass::Crt { scanlines, vignette } => {
+                    problems.range(&setting("scanlines"), scanlines, 0.0..=1.0);
+                    problems.range(&setting("vignette"), vignette, 0.0..=1.0);
+                }
+            }
+        }
+
+        let night_light = &self.night_light;
+        let kelvin = MIN_KELVIN..=MAX_KELVIN;
+        problems.range("night_light.day_kelvin", night_light.day_kelvin, kelvin.clone());
+        problems.range("night_light.night_kelvin", night_light.night_kelvin, kelvin);
+        if let Some(latitude) = night_light.latitude {
+            problems.range("night_light.latitude", latitude as f32, -90.0..=90.0);
+        }
+        if let Some(longitude) = night_light.longitude {
+            problems.range("night_light.longitude", longitude as f32, -180.0..=180.0);
+        }
+        problems.curve("night_light.transition", &night_light.transition);
+
+        for (output, color) in &self.color.outputs {
+            for gamma in color.gamma.iter().flatten() {
+                let setting = format!("color.outputs.{}.gamma", output);
+                problems.range(&setting, *gamma, 0.1..=10.0);
+            }
+        }
+
+        problems.at_least(
+            "wallpaper.interval_s",
+            self.wallpaper.interval_s as f32,
+            1.0,
+        );
+        problems.curve("wallpaper.fade", &self.wallpaper.fade);
+
+        let zoom = &self.zoom;
+        problems.range("zoom.step", zoom.step, 1.01..=10.0);
+        problems.range("zoom.max_level", zoom.max_level, 1.0..=100.0);
+        problems.curve("zoom.animation", &zoom.animation);
+        problems.curve("workspaces.animation", &self.workspaces.animation);
+
+        let pip = &self.pip;
+        problems.range("pip.width", pip.width, 0.05..=1.0);
+        problems.at_least("pip.margin", pip.margin, 0.0);
+        problems.at_least("pip.corner_radius", pip.corner_radius, 0.0);
+        problems.range("pip.opacity", pip.opacity, 0.1..=1.0);
+
+        let osd = &self.osd;
+        problems.curve("osd.fade", &osd.fade);
+        problems.range("osd.max_shown", osd.max_shown as f32, 1.0..=16.0);
+        problems.range("osd.text_px", osd.text_px, 4.0..=256.0);
+        problems.color("osd.background", &osd.background.get());
+        problems.color("osd.foreground", &osd.foreground.get());
+        problems.color("osd.gauge", &osd.gauge.get());
+
+        let hint = &self.hint;
+        problems.curve("hint.fade", &hint.fade);
+        problems.color("hint.outline", &hint.outline);
+        problems.color("hint.fill", &hint.fill);
+        problems.at_least("hint.outline_width", hint.outline_width, 0.0);
+        problems.at_least("hint.corner_radius", hint.corner_radius, 0.0);
+        let annotate = &self.annotate;
+        if annotate.colors.is_empty() {
+            problems.0.push("annotate.colors is empty".to_string());
+        }
+        for (i, color) in annotate.colors.iter().enumerate() {
+            problems.color(&format!("annotate.colors[{}]", i), &color.get());
+        }
+        problems.range("annotate.line_width", annotate.line_width, 1.0..=32.0);
+        problems.range("annotate.text_px", annotate.text_px, 4.0..=256.0);
+        if annotate.copy_command.trim().is_empty() {
+            problems.0.push("annotate.copy_command is empty".to_string());
+        }
+
+        let select = &self.select;
+        problems.color("select.dim", &select.dim.get());
+        problems.color("select.outline", &select.outline.get());
+        problems.range("select.outline_width", select.outline_width, 0.0..=16.0);
+        problems.at_least("select.snap_px", select.snap_px, 0.0);
+        problems.range("select.text_px", select.text_px, 4.0..=256.0);
+
+        let access = &self.accessibility;
+        problems.range("accessibility.focus_outline_width", access.focus_outline_width, 0.0..=32.0);
+        problems.color("accessibility.focus_outline", &access.focus_outline);
+        problems.color("accessibility.focus_halo", &access.focus_halo);
+        if access.narrate.as_ref().is_some_and(|narrate| narrate.trim().is_empty()) {
+            problems.0.push("accessibility.narrate is empty".to_string());
+        }
+        let power = &self.power;
+        problems.range("power.poll_ms", power.poll_ms as f32, 100.0..=3_600_000.0);
+        if let Some(max_fps) = power.power_saver.max_fps {
+            problems.range("power.power_saver.max_fps", max_fps as f32, 1.0..=1000.0);
+        }
+        problems.color("letterbox.color", &self.letterbox.color);
+        problems.color("modal.dim", &self.modal.dim);
+
+        let bar = &self.bar;
+        problems.range("bar.height", bar.height, 8.0..=256.0);
+        problems.range("bar.text_px", bar.text_px, 4.0..=256.0);
+        problems.color("bar.background", &bar.background.get());
+        problems.color("bar.foreground", &bar.foreground.get());
+        problems.at_least("bar.pager.spacing", bar.pager.spacing, 0.0);
+        problems.color("bar.pager.workspace", &bar.pager.workspace.get());
+        problems.color("bar.pager.current", &bar.pager.current.get());
+        problems.color("bar.pager.window", &bar.pager.window.get());
+        problems.at_least("bar.taskbar.max_width", bar.taskbar.max_width, 1.0);
+        problems.color("bar.taskbar.button", &bar.taskbar.button.get());
+        problems.color("bar.taskbar.focused", &bar.taskbar.focused.get());
+        problems.color("bar.taskbar.urgent", &bar.taskbar.urgent.get());
+        problems.at_least("bar.calendar.width", bar.calendar.width, 100.0);
+        problems.at_least("bar.calendar.history", bar.calendar.history as f32, 1.0);
+        problems.at_least("bar.calendar.rows", bar.calendar.rows as f32, 1.0);
+        problems.color("bar.calendar.background", &bar.calendar.background.get());
+        problems.color("bar.calendar.today", &bar.calendar.today.get());
+        problems.color("bar.calendar.dim", &bar.calendar.dim.get());
+        problems.at_least("bar.preview.size", bar.preview.size, 32.0);
+        problems.color("bar.preview.background", &bar.preview.background.get());
+        for spec in &bar.widgets {
+            if let Err(e) = WidgetSpec::parse(spec) {
+                problems.0.push(format!("bar.widgets: {}", e));
+            }
+        }
+
+        problems.color("lock.color", &self.lock.color);
+        problems.range("idle.dim_brightness", self.idle.dim_brightness, 0.0..=1.0);
+        let cursor_size = MIN_CURSOR_SIZE as f32..=MAX_CURSOR_SIZE as f32;
+        problems.range("cursor.size", self.cursor.size as f32, cursor_size);
+
+        let input = &self.input;
+        problems.range("input.repeat_delay_ms", input.repeat_delay_ms as f32, 1.0..=5000.0);
+        problems.range("input.repeat_rate", input.repeat_rate as f32, 0.0..=1000.0);
+        let mut sequences = Vec::new();
+        for (keys, command) in &input.bindings {
+            match KeyBinding::parse_sequence(keys) {
+                Ok(sequence) => sequences.push((keys, sequence)),
+                Err(e) => problems.0.push(format!("input.bindings: {}", e)),
+            }
+            if command.is_empty() {
+                problems.0.push(format!("input.bindings.{:?} is empty", keys));
+            }
+        }
+        if let Some(prefix) = &input.count_prefix {
+            match KeyBinding::parse(prefix) {
+                Ok(binding) => {
+                    let bound = sequences.iter().find(|(_, seq)| seq[0].same_keys(&binding));
+                    if let Some((keys, _)) = bound {
+                        problems.0.push(format!("input.count_prefix is bound by {:?}", keys));
+                    }
+                }
+                Err(e) => problems.0.push(format!("input.count_prefix: {}", e)),
+            }
+        }
+        // Keys only one binding can have, and a binding that starts a chord would be
+        // typed instead of waiting on the chord.
+        for (i, (keys, sequence)) in sequences.iter().enumerate() {
+            let later = &sequences[i + 1..];
+            let same = later.iter().find(|(_, other)| same_sequence(sequence, other, false));
+            if let Some((other, _)) = same {
+                problems.0.push(format!("input.bindings.{:?} and {:?} are the same", keys, other));
+            }
+            let chord = sequences.iter().find(|(_, longer)| {
+                longer.len() > sequence.len() && same_sequence(sequence, longer, true)
+            });
+            if let Some((chord, _)) = chord {
+                problems.0.push(format!("input.bindings.{:?} starts the chord {:?}", keys, chord));
+            }
+        }
+        problems.range("input.chord_timeout_ms", input.chord_timeout_ms as f32, 100.0..=60000.0);
+        let gestures = &input.gestures;
+        problems.range("input.gestures.swipe_distance", gestures.swipe_distance, 10.0..=2000.0);
+        problems.range("input.gestures.pinch_threshold", gestures.pinch_threshold, 0.05..=0.9);
+        problems.curve("input.gestures.settle", &gestures.settle);
+        let mut parsed: Vec<(&String, Gesture)> = Vec::new();
+        for (name, command) in &gestures.bindings {
+            match Gesture::parse(name) {
+                Ok(gesture) => {
+                    if let Some((other, _)) = parsed.iter().find(|(_, g)| *g == gesture) {
+                        let same = format!("{:?} and {:?} are the same", other, name);
+                        problems.0.push(format!("input.gestures.bindings.{}", same));
+                    }
+                    parsed.push((name, gesture));
+                }
+                Err(e) => problems.0.push(format!("input.gestures.bindings: {}", e)),
+            }
+            if command.is_empty() {
+                problems.0.push(format!("input.gestures.bindings.{:?} is empty", name));
+            }
+        }
+
+        if problems.0.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.0.join("; "))
+        }
+    }
+
+    /// Loads `path` if it exists, falling back to the defaults otherwise.
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Self {
+        if !path.as_ref().exists() {
+            return Self::default();
+        }
+        Self::load(&path).unwrap_or_else(|e| {
+            eprintln!("Ignoring {}: {}", path.as_ref().display(), e);
+            Self::default()
+        })
+    }
+
+    /// Hands every setting to `scene`, except the socket path, which only the command
+    /// listener uses.
+    pub fn apply(self, scene: &mut Scene) {
+        scene.set_gpu(self.gpu);
+        scene.set_clear_color(self.clear_color);
+        scene.set_present_mode(self.present_mode);
+        scene.set_max_fps(self.max_fps);
+        scene.set_direct_scanout(self.direct_scanout);
+        scene.set_hdr(self.hdr);
+        scene.set_vrr_config(self.vrr);
+        scene.set_memory_config(self.memory);
+        scene.set_hud(self.hud);
+        scene.set_shader_dir(self.shader_dir);
+        scene.set_font(self.font.as_deref());
+        for (output, scale) in &self.output_scales {
+            if let Err(e) = scene.set_output_scale(output, *scale) {
+                eprintln!("Ignoring scale for {}: {}", output, e);
+            }
+        }
+        scene.set_animation_config(self.animations);
+        scene.set_theme_config(self.theme);
+        scene.set_effects_config(self.effects);
+        scene.set_post_config(self.post);
+        scene.set_night_light_config(self.night_light);
+        if let Err(e) = scene.set_color_config(self.color) {
+            eprintln!("Not correcting colors of {}", e);
+        }
+        if let Err(e) = scene.set_wallpaper_config(self.wallpaper) {
+            eprintln!("Not showing a wallpaper: {}", e);
+        }
+        scene.set_zoom_config(self.zoom);
+        scene.set_workspace_config(self.workspaces);
+        scene.set_pip_config(self.pip);
+        scene.set_lock_config(self.lock);
+        if let Err(e) = scene.set_idle_config(self.idle) {
+            eprintln!("Ignoring idle settings: {}", e);
+        }
+        scene.set_osd_config(self.osd);
+        scene.set_hint_config(self.hint);
+        scene.set_annotate_config(self.annotate);
+        scene.set_select_config(self.select);
+        scene.set_accessibility_config(self.accessibility);
+        scene.set_power_config(self.power);
+        scene.set_letterbox_config(self.letterbox);
+        scene.set_modal_config(self.modal);
+        scene.set_bar_config(self.bar);
+        if let Err(e) = scene.set_cursor_config(self.cursor) {
+            eprintln!("Not drawing the cursor: {}", e);
+        }
+        scene.set_input_config(self.input);
+    }
+}
+
+/// What `Config::validate` found wrong so far.
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn range(&mut self, setting: &str, value: f32, range: RangeInclusive<f32>) {
+        if !range.contains(&value) {
+            self.0.push(format!(
+                "{} = {} is outside {}..={}",
+                setting,
+                value,
+                range.start(),
+                range.end()
+            ));
+        }
+    }
+
+    fn at_least(&mut self, setting: &str, value: f32, min: f32) {
+        if value.is_nan() || value < min {
+            self.0.push(format!("{} = {} is below {}", setting, value, min));
+        }
+    }
+
+    /// Color components run from 0 to 1.
+    fn color(&mut self, setting: &str, color: &[f32]) {
+        if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            self.0.push(format!("{} = {:?} has components outside 0..=1", setting, color));
+        }
+    }
+
+    fn curve(&mut self, setting: &str, curve: &Curve) {
+        if curve.duration_ms > MAX_DURATION_MS {
+            self.0.push(format!(
+                "{}.duration_ms = {} is over {}",
+                setting, curve.duration_ms, MAX_DURATION_MS
+            ));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Idle timeouts: without input for a while, outputs dim, then the screen locks, then
+// the displays are powered down through DPMS. Input reported by the WM or seen by the
+// output windows starts the count over, and inhibitors, held by clients like video
+// players, stop it altogether.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// When each idle stage starts, in seconds without input; stages left out never do.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleConfig {
+    pub dim_after: Option<u64>,
+    /// How bright dimmed outputs are, from 0 to 1.
+    pub dim_brightness: f32,
+    pub lock_after: Option<u64>,
+    pub dpms_after: Option<u64>,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            dim_after: None,
+            dim_brightness: 0.4,
+            lock_after: None,
+            dpms_after: None,
+        }
+    }
+}
+
+/// A client keeping the screen awake.
+#[derive(Debug, Clone)]
+struct Inhibitor {
+    reason: String,
+}
+
+/// What changed in an `Idle::update`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdleChanges {
+    /// Outputs dimmed.
+    pub dimmed: bool,
+    /// The lock timeout passed; the screen should be locked.
+    pub lock: bool,
+}
+
+/// How long it has been since the last input, and which stages have started.
+#[derive(Debug, Default)]
+pub struct Idle {
+    config: IdleConfig,
+    /// The last input, or when counting started; unset until then.
+    last_activity: Option<Instant>,
+    dimmed: bool,
+    /// Whether this idle period has locked the screen already.
+    locked: bool,
+    displays_off: bool,
+    inhibitors: HashMap<u32, Inhibitor>,
+    next_inhibitor: u32,
+}
+
+impl Idle {
+    pub fn config(&self) -> &IdleConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: IdleConfig) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&config.dim_brightness) {
+            return Err(format!(
+                "dim_brightness {} is outside 0..=1",
+                config.dim_brightness
+            ));
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Whether outputs are dimmed.
+    pub fn dimmed(&self) -> bool {
+        self.dimmed
+    }
+
+    pub fn dim_brightness(&self) -> f32 {
+        self.config.dim_brightness
+    }
+
+    /// Whether the displays should be powered down.
+    pub fn displays_off(&self) -> bool {
+        self.displays_off
+    }
+
+    /// Restarts the count from input at `at`; returns whether that brightened outputs
+    /// again.
+    pub fn activity(&mut self, at: Instant) -> bool {
+        self.last_activity = Some(self.last_activity.map_or(at, |last| last.max(at)));
+        self.locked = false;
+        self.displays_off = false;
+        std::mem::take(&mut self.dimmed)
+    }
+
+    /// Keeps the screen awake until `uninhibit` is called with the returned id; ends
+    /// the stages already started, like input does.
+    pub fn inhibit(&mut self, reason: String, now: Instant) -> (u32, bool) {
+        self.next_inhibitor = self.next_inhibitor.wrapping_add(1);
+        let id = self.next_inhibitor;
+        self.inhibitors.insert(id, Inhibitor { reason });
+        (id, self.activity(now))
+    }
+
+    /// Drops inhibitor `id`; once none is left, the count starts from `now`.
+    pub fn uninhibit(&mut self, id: u32, now: Instant) -> Result<(), String> {
+        let inhibitor = self
+            .inhibitors
+            .remove(&id)
+            .ok_or_else(|| format!("no idle inhibitor {}", id))?;
+        println!("Idle inhibitor {} ({}) released.", id, inhibitor.reason);
+        if self.inhibitors.is_empty() {
+            self.last_activity = Some(now);
+        }
+        Ok(())
+    }
+
+    /// Starts the stages whose timeouts passed by `now`.
+    pub fn update(&mut self, now: Instant) -> IdleChanges {
+        let last = *self.last_activity.get_or_insert(now);
+        let mut changes = IdleChanges::default();
+        if !self.inhibitors.is_empty() {
+            return changes;
+        }
+        let idle = now.saturating_duration_since(last);
+        let passed = |after: Option<u64>| after.is_some_and(|s| idle >= Duration::from_secs(s));
+        if !self.dimmed && passed(self.config.dim_after) {
+            self.dimmed = true;
+            changes.dimmed = true;
+        }
+        if !self.locked && passed(self.config.lock_after) {
+            self.locked = true;
+            changes.lock = true;
+        }
+        if passed(self.config.dpms_after) {
+            self.displays_off = true;
+        }
+        changes
+    }
+}
@@ -0,0 +1,349 @@
+// Settings for input the compositor reads from the devices itself, which it does with
+// the libinput feature when no X server owns them: the keymap, and key bindings, chords
+// of them, and touchpad gestures that become WM commands instead of reaching clients.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use qtilerugo_ipc::wm::{self, WmCommand};
+use serde::{Deserialize, Serialize};
+
+use crate::scene::animation::{Curve, Easing};
+use crate::scene::bar::{BarAction, BarButton};
+use crate::scene::SharedScene;
+
+/// How keyboards and pointers are read, and which keys the WM gets.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Reads input through libinput; the X server gets the same events, so this is for
+    /// sessions without one.
+    pub enabled: bool,
+    /// The udev seat whose devices are read.
+    pub seat: String,
+    /// XKB layouts, variants, and options, as in setxkbmap.
+    pub layout: String,
+    pub variant: String,
+    pub options: Option<String>,
+    /// Delay before a held key repeats, and repeats per second after that.
+    pub repeat_delay_ms: u32,
+    pub repeat_rate: u32,
+    /// The WM's command socket, which bindings are sent to.
+    pub wm_socket: PathBuf,
+    /// WM commands by key combination, like "Super+Shift+Return" = "SpawnTerminal", as
+    /// `binding_command` reads them, or by a chord of them separated by spaces, like
+    /// "Super+a t".
+    pub bindings: BTreeMap<String, String>,
+    /// How long a chord waits for its next key, in milliseconds.
+    pub chord_timeout_ms: u64,
+    /// A key combination after which digits make a count, like "Super+u" then "3",
+    /// that the next bound command is repeated by.
+    pub count_prefix: Option<String>,
+    /// Lists a chord's continuations in an OSD while it waits.
+    pub chord_hints: bool,
+    pub gestures: GestureConfig,
+    /// Interactive resizes only move an outline, configuring the client once, on
+    /// release, instead of at every step of the drag.
+    pub outline_resize: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seat: "seat0".to_string(),
+            layout: "us".to_string(),
+            variant: String::new(),
+            options: None,
+            repeat_delay_ms: 600,
+            repeat_rate: 25,
+            wm_socket: PathBuf::from(wm::SOCKET),
+            bindings: BTreeMap::new(),
+            chord_timeout_ms: 2000,
+            count_prefix: None,
+            chord_hints: true,
+            gestures: GestureConfig::default(),
+            outline_resize: false,
+        }
+    }
+}
+
+/// Touchpad swipes and pinches, and the WM commands they send.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GestureConfig {
+    /// How far fingers travel, in pointer pixels, for a swipe to count.
+    pub swipe_distance: f32,
+    /// How far the fingers' spread has to change, as a fraction, for a pinch to count.
+    pub pinch_threshold: f32,
+    /// Slides the windows along with swipes that have a command.
+    pub slide: bool,
+    /// How slid windows return once the fingers lift.
+    pub settle: Curve,
+    /// WM commands by gesture, like "swipe-3-left" = "NextLayout" or "pinch-2-in".
+    pub bindings: BTreeMap<String, String>,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            swipe_distance: 120.0,
+            pinch_threshold: 0.25,
+            slide: true,
+            settle: Curve::new(250, Easing::EaseOutCubic),
+            bindings: BTreeMap::new(),
+        }
+    }
+}
+
+/// Which way a swipe's fingers moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A gesture from `GestureConfig::bindings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Swipe {
+        fingers: u32,
+        direction: SwipeDirection,
+    },
+    /// Fingers drawn together (`inward`) or spread apart.
+    Pinch { fingers: u32, inward: bool },
+}
+
+impl Gesture {
+    /// Parses a kind, a finger count, and a direction joined by `-`, like
+    /// "swipe-4-up" or "pinch-2-out".
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let parts: Vec<String> = name
+            .split('-')
+            .map(|p| p.trim().to_ascii_lowercase())
+            .collect();
+        let [kind, fingers, direction] = parts.as_slice() else {
+            return Err(format!("{:?} is not kind-fingers-direction", name));
+        };
+        let fingers = match fingers.parse() {
+            Ok(fingers @ 2..=5) => fingers,
+            _ => return Err(format!("{:?} needs 2 to 5 fingers", name)),
+        };
+        let swipe = |direction| Ok(Self::Swipe { fingers, direction });
+        match (kind.as_str(), direction.as_str()) {
+            ("pinch", "in") => Ok(Self::Pinch {
+                fingers,
+                inward: true,
+            }),
+            ("pinch", "out") => Ok(Self::Pinch {
+                fingers,
+                inward: false,
+            }),
+            // Two fingers scroll instead.
+            ("swipe", _) if fingers < 3 => Err(format!("{:?} needs 3 to 5 fingers", name)),
+            ("swipe", "left") => swipe(SwipeDirection::Left),
+            ("swipe", "right") => swipe(SwipeDirection::Right),
+            ("swipe", "up") => swipe(SwipeDirection::Up),
+            ("swipe", "down") => swipe(SwipeDirection::Down),
+            _ => Err(format!("{:?} is not a swipe or pinch direction", name)),
+        }
+    }
+}
+
+/// Modifiers a binding needs held, and no others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
+/// A key combination from `InputConfig::bindings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub modifiers: Modifiers,
+    /// An XKB keysym name, matched without regard to case or shift level.
+    pub key: String,
+}
+
+impl KeyBinding {
+    /// Parses modifiers and a key joined by `+`, like "Ctrl+Alt+Delete".
+    pub fn parse(combination: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = combination.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        if key.is_empty() {
+            return Err(format!("{:?} has no key", combination));
+        }
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            let held = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "alt" | "mod1" => &mut modifiers.alt,
+                "shift" => &mut modifiers.shift,
+                "super" | "logo" | "mod4" => &mut modifiers.logo,
+                _ => return Err(format!("{:?} is not a modifier", part)),
+            };
+            *held = true;
+        }
+        Ok(Self {
+            modifiers,
+            key: key.to_string(),
+        })
+    }
+
+    /// Parses a chord, combinations separated by spaces like "Super+a t", or a single
+    /// combination.
+    pub fn parse_sequence(keys: &str) -> Result<Vec<Self>, String> {
+        let combinations = split_sequence(keys);
+        if combinations.is_empty() {
+            return Err(format!("{:?} has no key", keys));
+        }
+        combinations.iter().map(|c| Self::parse(c)).collect()
+    }
+
+    /// Whether `other` is pressed the same way, its key differing at most in case.
+    pub fn same_keys(&self, other: &Self) -> bool {
+        self.modifiers == other.modifiers && self.key.eq_ignore_ascii_case(&other.key)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    /// Modifiers in one order, whichever the config used, then the key.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Modifiers {
+            ctrl,
+            alt,
+            shift,
+            logo,
+        } = self.modifiers;
+        for (held, name) in [(logo, "Super"), (ctrl, "Ctrl"), (alt, "Alt"), (shift, "Shift")] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+/// Whether chords `a` and `b` are pressed the same way, or `a` is where `b` starts
+/// when `prefix` is set.
+pub fn same_sequence(a: &[KeyBinding], b: &[KeyBinding], prefix: bool) -> bool {
+    let len = if prefix { a.len() } else { b.len() };
+    a.len() == len && b.len() >= len && a.iter().zip(b).all(|(a, b)| a.same_keys(b))
+}
+
+/// A binding as it takes effect, for listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct BoundKeys {
+    /// The chord, with its modifiers in one order.
+    pub keys: String,
+    /// What it sends the WM.
+    pub command: serde_json::Value,
+}
+
+/// The bindings of `config` that parse, by their keys; a count prefix is listed with
+/// "Repeat" for a command.
+pub fn binding_table(config: &InputConfig) -> Vec<BoundKeys> {
+    let mut table: Vec<BoundKeys> = config
+        .bindings
+        .iter()
+        .filter_map(|(keys, command)| {
+            let sequence = KeyBinding::parse_sequence(keys).ok()?;
+            let keys: Vec<String> = sequence.iter().map(ToString::to_string).collect();
+            Some(BoundKeys {
+                keys: keys.join(" "),
+                command: binding_command(command),
+            })
+        })
+        .collect();
+    if let Some(prefix) = config.count_prefix.as_ref() {
+        if let Ok(binding) = KeyBinding::parse(prefix) {
+            table.push(BoundKeys {
+                keys: binding.to_string(),
+                command: "Repeat".into(),
+            });
+        }
+    }
+    table.sort_by(|a, b| a.keys.cmp(&b.keys));
+    table
+}
+
+/// The combinations of a chord as written, keeping spaces around `+` within them.
+pub fn split_sequence(keys: &str) -> Vec<String> {
+    let mut combinations: Vec<String> = Vec::new();
+    for word in keys.split_whitespace() {
+        match combinations.last_mut() {
+            Some(last) if last.ends_with('+') || word.starts_with('+') => last.push_str(word),
+            _ => combinations.push(word.to_string()),
+        }
+    }
+    combinations
+}
+
+/// What a binding's value sends the WM: a command's name, like "SpawnTerminal", or a
+/// whole command as a JSON object, like {"SwitchWorkspace": "2"}.
+pub fn binding_command(value: &str) -> serde_json::Value {
+    match serde_json::from_str(value) {
+        Ok(command @ serde_json::Value::Object(_)) => command,
+        _ => value.into(),
+    }
+}
+
+/// `command` for the WM to run `count` times.
+pub fn repeated(command: serde_json::Value, count: u32) -> serde_json::Value {
+    serde_json::json!({ "Repeat": { "count": count, "command": command } })
+}
+
+/// Sends `command`, a `WmCommand` or one as a binding writes it, to the WM listening on
+/// `socket`.
+pub fn send_to_wm(socket: &Path, command: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(qtilerugo_ipc::encode(command).as_bytes())?;
+    Ok(())
+}
+
+/// Hands a press or release of `button` to the bar, which has the WM do what a click
+/// on it asks; returns whether it was the bar's.
+pub fn bar_button(scene: &SharedScene, button: BarButton, pressed: bool) -> bool {
+    let action = {
+        let mut scene = scene.lock().unwrap();
+        if pressed {
+            return scene.bar_pressed(button);
+        }
+        if !scene.bar_held() {
+            return false;
+        }
+        scene.bar_released()
+    };
+    if let Some(action) = action {
+        if let Err(e) = run_bar_action(scene, &action) {
+            eprintln!("Not sending {:?} to the WM: {}", action, e);
+        }
+    }
+    true
+}
+
+/// Has the WM do what a click on the bar asks.
+pub fn run_bar_action(scene: &SharedScene, action: &BarAction) -> Result<(), String> {
+    let socket = scene.lock().unwrap().input_config().wm_socket.clone();
+    send_to_wm(&socket, &action.command()).map_err(|e| e.to_string())
+}
+
+/// Has the WM kill the client of `window`, which has to have stopped responding.
+pub fn force_close(scene: &SharedScene, window: u32) -> Result<(), String> {
+    let socket = {
+        let scene = scene.lock().unwrap();
+        if !scene.unresponsive(window) {
+            return Err("it is responding".to_string());
+        }
+        scene.input_config().wm_socket.clone()
+    };
+    send_to_wm(&socket, &WmCommand::KillClient(window)).map_err(|e| e.to_string())
+}
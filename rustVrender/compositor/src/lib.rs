@@ -0,0 +1,61 @@
+//! The qtilerugo compositor: a scene graph of outputs, layers, windows and decorations,
+//! drawn with Vulkan into one window per monitor, with its effects, animations and
+//! compositor-drawn UI. It knows nothing of any window manager; whoever embeds it feeds
+//! it window state and contents, and serves commands on top of it if it likes.
+//!
+//! - [`scene::Scene`], shared as a [`scene::SharedScene`], holds everything drawn. Window
+//!   managers report their windows to it with `sync_windows` or the surface calls,
+//!   hand it pixels with `set_window_contents`, and change its settings through setters.
+//! - [`config::Config`] reads renderer_config.toml, and [`config::Config::apply`] hands
+//!   it to a scene.
+//! - [`compositor::RenderLoop`] takes the main thread and draws the scene until the
+//!   process exits; other threads wake it through its proxy, with
+//!   [`compositor::LoopRequest`]s to open a window or follow new outputs, and ask for
+//!   screenshots, previews and screencasts through the scene, which it serves.
+//! - [`shm::Ring`] carries window updates from another process, as the bridge sends
+//!   them, straight into a scene.
+//!
+//! The rust_qtile_helper binary serves it on a command socket; the bridge's unified
+//! mode embeds it in the window manager's own process:
+//!
+//! ```no_run
+//! use qtilerugo_compositor::compositor::{LoopRequest, RenderLoop};
+//! use qtilerugo_compositor::config::{Config, CONFIG_PATH};
+//! use qtilerugo_compositor::scene::Scene;
+//!
+//! let scene = Scene::shared();
+//! Config::load_or_default(CONFIG_PATH).apply(&mut scene.lock().unwrap());
+//! let render_loop = RenderLoop::new();
+//! render_loop.proxy().send_event(LoopRequest::SpawnWindow).unwrap();
+//! render_loop.run(scene)
+//! ```
+
+/// The render loop, and the Vulkan renderer every output shares.
+pub mod compositor;
+/// Renderer settings, as renderer_config.toml has them.
+pub mod config;
+/// Dimming, locking and powering displays down without input.
+pub mod idle;
+/// Key bindings and gestures, for input read from the devices themselves.
+pub mod input;
+/// The lock screen.
+pub mod lock;
+/// Scaled-down pictures of single windows.
+pub mod preview;
+/// Power profiles that follow the power supply.
+pub mod profile;
+/// The scene graph, and everything the compositor draws of its own.
+pub mod scene;
+/// Streams of outputs, windows and regions.
+pub mod screencast;
+/// Pictures of outputs, windows and regions.
+pub mod screenshot;
+/// The shared-memory ring window updates come in through.
+pub mod shm;
+/// Frame timing and GPU memory statistics, and their HUD.
+pub mod stats;
+/// Fonts, and text laid out into glyph draw items.
+pub mod text;
+/// Native Wayland clients, and Xwayland.
+#[cfg(feature = "wayland")]
+pub mod wayland;
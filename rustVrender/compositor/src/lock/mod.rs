@@ -0,0 +1,242 @@
+// Screen locking: `Lock` replaces every output's contents with an opaque lock surface
+// until the user's password checks out. Passwords are typed into the output windows
+// and checked by a separate helper process that talks to PAM, so PAM modules never
+// run inside the renderer.
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::Deserialize;
+
+use crate::scene::{DrawItem, DrawSource, SharedScene};
+
+/// Dots shown for a password at most; longer ones don't add more.
+const MAX_DOTS: usize = 24;
+const DOT_SIZE: f32 = 12.0;
+const DOT_SPACING: f32 = 8.0;
+/// Dots shown while a password is checked, or after it failed.
+const STATUS_DOTS: usize = 8;
+/// Room reserved for a password up front, so typing doesn't leave copies behind in
+/// reallocated memory.
+const PASSWORD_CAPACITY: usize = 256;
+
+const DOT: [f32; 4] = [0.85, 0.85, 0.85, 1.0];
+const CHECKING: [f32; 4] = [0.9, 0.75, 0.3, 1.0];
+const FAILED: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+/// How the lock screen looks and checks passwords.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockConfig {
+    /// Fills every output while locked.
+    pub color: [f32; 3],
+    /// The helper that checks passwords, looked up on PATH unless it has a slash.
+    pub auth_helper: PathBuf,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            color: [0.08, 0.08, 0.1],
+            auth_helper: PathBuf::from("qtilerugo-auth"),
+        }
+    }
+}
+
+/// Where unlocking stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Status {
+    #[default]
+    Typing,
+    /// The helper is checking the password typed last.
+    Checking,
+    /// The last password was wrong; cleared as soon as typing starts again.
+    Failed,
+}
+
+/// Whether the screen is locked, and the password typed so far.
+#[derive(Debug, Default)]
+pub struct LockScreen {
+    config: LockConfig,
+    locked: bool,
+    password: String,
+    status: Status,
+}
+
+impl LockScreen {
+    pub fn config(&self) -> &LockConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: LockConfig) {
+        self.config = config;
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Locks the screen, unless no helper could ever unlock it again.
+    pub fn lock(&mut self) -> Result<(), String> {
+        if self.locked {
+            return Ok(());
+        }
+        find_helper(&self.config.auth_helper).ok_or_else(|| {
+            format!(
+                "{} isn't installed, so the screen couldn't be unlocked",
+                self.config.auth_helper.display()
+            )
+        })?;
+        self.locked = true;
+        self.status = Status::Typing;
+        wipe(&mut self.password);
+        Ok(())
+    }
+
+    /// Handles a character typed into an output; returns the password to check once
+    /// Enter is pressed.
+    pub fn type_char(&mut self, c: char) -> Option<String> {
+        if !self.locked || self.status == Status::Checking {
+            return None;
+        }
+        self.status = Status::Typing;
+        match c {
+            '\r' | '\n' => {
+                if self.password.is_empty() {
+                    return None;
+                }
+                self.status = Status::Checking;
+                Some(std::mem::take(&mut self.password))
+            }
+            '\u{8}' | '\u{7f}' => {
+                self.password.pop();
+                None
+            }
+            // Escape starts over.
+            '\u{1b}' => {
+                wipe(&mut self.password);
+                None
+            }
+            c if !c.is_control() => {
+                if self.password.capacity() == 0 {
+                    self.password.reserve(PASSWORD_CAPACITY);
+                }
+                self.password.push(c);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Unlocks if the password checked out, or shows that it didn't.
+    pub fn finish(&mut self, unlocked: bool) {
+        if unlocked {
+            self.locked = false;
+            self.status = Status::Typing;
+        } else {
+            self.status = Status::Failed;
+        }
+    }
+
+    /// The lock surface for an output `width` by `height` pixels: the fill, and a row
+    /// of dots for the password typed so far.
+    pub fn items(&self, width: f32, height: f32, scale: f32) -> Vec<DrawItem> {
+        let [r, g, b] = self.config.color;
+        let mut items = vec![solid([0.0, 0.0, width, height], [r, g, b, 1.0], 0.0)];
+        let (count, color) = match self.status {
+            Status::Typing => (self.password.chars().count().min(MAX_DOTS), DOT),
+            Status::Checking => (STATUS_DOTS, CHECKING),
+            Status::Failed => (STATUS_DOTS, FAILED),
+        };
+        let size = DOT_SIZE * scale;
+        let step = (DOT_SIZE + DOT_SPACING) * scale;
+        let row = count as f32 * step - DOT_SPACING * scale;
+        let (left, top) = ((width - row) / 2.0, (height - size) / 2.0);
+        for i in 0..count {
+            let rect = [left + i as f32 * step, top, size, size];
+            items.push(solid(rect, color, size / 2.0));
+        }
+        items
+    }
+}
+
+fn solid(rect: [f32; 4], color: [f32; 4], corner_radius: f32) -> DrawItem {
+    DrawItem {
+        rect,
+        color,
+        source: DrawSource::Solid,
+        corner_radius,
+        border: 0.0,
+    }
+}
+
+/// Handles `c` typed into an output window, checking the password on another thread
+/// once it is complete.
+pub fn key_typed(scene: &SharedScene, c: char) {
+    let (password, helper) = {
+        let mut scene = scene.lock().unwrap();
+        let Some(password) = scene.lock_typed(c) else {
+            return;
+        };
+        (password, scene.lock_screen().config().auth_helper.clone())
+    };
+    let scene = scene.clone();
+    thread::spawn(move || {
+        let result = authenticate(&helper, password);
+        if let Err(e) = &result {
+            eprintln!("Unlocking failed: {}", e);
+        }
+        scene.lock().unwrap().finish_unlock(result.is_ok());
+    });
+}
+
+/// Has a fresh `helper` process check `password` with PAM.
+fn authenticate(helper: &Path, mut password: String) -> Result<(), String> {
+    let helper = find_helper(helper).ok_or("the authentication helper went away")?;
+    let mut child = Command::new(helper)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let mut stdin = child.stdin.take().unwrap();
+    let written = stdin
+        .write_all(password.as_bytes())
+        .and_then(|()| stdin.write_all(b"\n"))
+        .map_err(|e| e.to_string());
+    drop(stdin);
+    wipe(&mut password);
+    let mut reply = String::new();
+    let read = BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut reply)
+        .map_err(|e| e.to_string());
+    let status = child.wait().map_err(|e| e.to_string())?;
+    written?;
+    read?;
+    match reply.trim_end() {
+        "ok" if status.success() => Ok(()),
+        "" => Err(format!("the helper exited with {}", status)),
+        reply => Err(reply.trim_start_matches("error: ").to_string()),
+    }
+}
+
+/// Resolves `helper` like a shell would.
+fn find_helper(helper: &Path) -> Option<PathBuf> {
+    if helper.components().count() > 1 {
+        return helper.is_file().then(|| helper.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(helper))
+        .find(|path| path.is_file())
+}
+
+/// Overwrites a password, and whatever its buffer held before, before letting it go.
+fn wipe(password: &mut String) {
+    let mut bytes = std::mem::take(password).into_bytes();
+    let capacity = bytes.capacity();
+    bytes.clear();
+    bytes.resize(capacity, 0);
+    std::hint::black_box(&bytes);
+}
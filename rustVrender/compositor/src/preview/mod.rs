@@ -0,0 +1,57 @@
+// Live previews of single windows, for WM overviews and alt-tab switchers: the render
+// loop draws one window node scaled down into an offscreen image, and the command
+// listener hands the pixels to the client in a memfd passed over the socket.
+
+use std::fs::File;
+use std::io::{self, IoSlice, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::screenshot::Capture;
+
+/// A preview waiting for a render loop to pick it up.
+#[derive(Debug)]
+pub struct PreviewRequest {
+    pub window: u32,
+    /// The preview fits in this many pixels, and is never bigger than the window.
+    pub max_size: [u32; 2],
+    pub reply: oneshot::Sender<Result<Capture, String>>,
+}
+
+/// Describes the pixels in the memfd sent with it.
+#[derive(Debug, Clone, Serialize)]
+struct PreviewHeader {
+    width: u32,
+    height: u32,
+    /// Bytes from one row to the next.
+    stride: u32,
+    format: &'static str,
+}
+
+/// Sends `capture` to the peer on `socket` as one JSON header line, with a memfd
+/// holding its RGBA rows, top first.
+pub fn send(socket: RawFd, capture: &Capture) -> io::Result<()> {
+    let memfd: OwnedFd = memfd_create(c"qtilerugo-preview", MemFdCreateFlag::MFD_CLOEXEC)?;
+    let mut memfd = File::from(memfd);
+    memfd.write_all(&capture.rgba)?;
+
+    let header = PreviewHeader {
+        width: capture.width,
+        height: capture.height,
+        stride: capture.width * 4,
+        format: "Rgba8",
+    };
+    let line = format!("{}\n", serde_json::to_string(&header)?);
+    sendmsg::<()>(
+        socket,
+        &[IoSlice::new(line.as_bytes())],
+        &[ControlMessage::ScmRights(&[memfd.as_raw_fd()])],
+        MsgFlags::empty(),
+        None,
+    )?;
+    Ok(())
+}
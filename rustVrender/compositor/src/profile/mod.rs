@@ -0,0 +1,172 @@
+// Power profiles: on battery the compositor trades smoothness for power, capping its
+// frame rate, drawing without blur or animations, and presenting in FIFO, as the
+// power_saver profile has it, and on AC it goes back to the performance profile, the
+// config as written. The power supply is read from sysfs, or from UPower where there
+// is no AC adapter there; SetPowerProfile forces either profile.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::config::PresentMode;
+use crate::scene::SharedScene;
+
+pub use qtilerugo_ipc::renderer::Profile;
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// When the power saver profile is used, and what it turns down.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// Switches to the power saver profile on battery, and back on AC.
+    pub automatic: bool,
+    /// How often the power supply is read.
+    pub poll_ms: u64,
+    pub power_saver: PowerSaverConfig,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            automatic: true,
+            poll_ms: 5000,
+            power_saver: PowerSaverConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerSaverConfig {
+    /// Frames each output draws a second at most; none keeps its refresh rate.
+    pub max_fps: Option<u32>,
+    pub blur: bool,
+    pub animations: bool,
+    /// Replaces present_mode; none keeps it.
+    pub present_mode: Option<PresentMode>,
+}
+
+impl Default for PowerSaverConfig {
+    fn default() -> Self {
+        Self {
+            max_fps: Some(30),
+            blur: false,
+            animations: false,
+            present_mode: Some(PresentMode::Fifo),
+        }
+    }
+}
+
+/// Which profile is in use, from the power supply or as forced.
+#[derive(Debug, Default)]
+pub struct PowerProfiles {
+    config: PowerConfig,
+    on_battery: bool,
+    forced: Option<Profile>,
+}
+
+impl PowerProfiles {
+    pub fn config(&self) -> &PowerConfig {
+        &self.config
+    }
+
+    /// Takes `config`; returns whether that changed the profile in use.
+    pub fn set_config(&mut self, config: PowerConfig) -> bool {
+        let profile = self.profile();
+        self.config = config;
+        self.profile() != profile
+    }
+
+    pub fn profile(&self) -> Profile {
+        match self.forced {
+            Some(profile) => profile,
+            None if self.config.automatic && self.on_battery => Profile::PowerSaver,
+            None => Profile::Performance,
+        }
+    }
+
+    /// Follows the power supply; returns whether that changed the profile in use.
+    pub fn set_on_battery(&mut self, on_battery: bool) -> bool {
+        let profile = self.profile();
+        self.on_battery = on_battery;
+        self.profile() != profile
+    }
+
+    /// Keeps to `profile` whatever the power supply, or follows it again with `None`;
+    /// returns whether that changed the profile in use.
+    pub fn force(&mut self, profile: Option<Profile>) -> bool {
+        let before = self.profile();
+        self.forced = profile;
+        self.profile() != before
+    }
+
+    fn saving(&self) -> Option<&PowerSaverConfig> {
+        (self.profile() == Profile::PowerSaver).then_some(&self.config.power_saver)
+    }
+
+    pub fn max_fps(&self) -> Option<u32> {
+        self.saving()
+            .and_then(|saver| saver.max_fps)
+            .filter(|&fps| fps > 0)
+    }
+
+    pub fn blur(&self) -> bool {
+        self.saving().is_none_or(|saver| saver.blur)
+    }
+
+    pub fn animations(&self) -> bool {
+        self.saving().is_none_or(|saver| saver.animations)
+    }
+
+    /// The present mode to use instead of `configured`.
+    pub fn present_mode(&self, configured: PresentMode) -> PresentMode {
+        self.saving()
+            .and_then(|saver| saver.present_mode)
+            .unwrap_or(configured)
+    }
+}
+
+/// Whether the machine runs on battery: no AC adapter in sysfs is online, or, without
+/// one there, UPower says so; `None` when neither knows.
+pub fn on_battery() -> Option<bool> {
+    let adapters: Vec<_> = fs::read_dir(POWER_SUPPLY)
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            fs::read_to_string(path.join("type")).is_ok_and(|kind| kind.trim() == "Mains")
+        })
+        .collect();
+    if !adapters.is_empty() {
+        let online = |path: &Path| {
+            fs::read_to_string(path.join("online")).is_ok_and(|online| online.trim() == "1")
+        };
+        return Some(!adapters.iter().any(|path| online(path)));
+    }
+    let output = Command::new("upower")
+        .args(["-i", "/org/freedesktop/UPower"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .find_map(|line| line.trim().strip_prefix("on-battery:"))
+        .map(|value| value.trim() == "yes")
+}
+
+/// Follows the power supply for the power profiles of `scene`, as often as their config
+/// asks, forever; for a thread of its own.
+pub fn watch(scene: &SharedScene) -> ! {
+    loop {
+        if let Some(on_battery) = on_battery() {
+            scene.lock().unwrap().set_on_battery(on_battery);
+        }
+        let poll = scene.lock().unwrap().power_config().poll_ms;
+        thread::sleep(Duration::from_millis(poll));
+    }
+}
@@ -0,0 +1,123 @@
+// Accessibility settings: a thick, high-contrast outline the compositor draws around
+// the focused window whatever border the WM gives it, a reduce-motion switch that has
+// every animation and fade jump to its end, and focus announcements, an OSD with the
+// title of each newly focused window, a speech command run with it, or both.
+
+use std::process::{Child, Command};
+
+use serde::Deserialize;
+
+use super::{DrawItem, DrawSource};
+
+/// How the focused window is marked and announced, and whether anything moves.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessibilityConfig {
+    /// Makes every animation and fade finish as it starts.
+    pub reduce_motion: bool,
+    /// Width of the outline around the focused window at scale 1, in output pixels;
+    /// 0 draws none.
+    pub focus_outline_width: f32,
+    /// Colors, not premultiplied: the outline, and the thin ring along its inside that
+    /// keeps it standing out from light and dark windows alike.
+    pub focus_outline: [f32; 4],
+    pub focus_halo: [f32; 4],
+    /// Shows the title of each window as it is focused in an OSD.
+    pub announce_focus: bool,
+    /// Run by `sh -c` with the title of each window as it is focused as $1, like
+    /// `espeak-ng "$1"`.
+    pub narrate: Option<String>,
+}
+
+impl Default for AccessibilityConfig {
+    fn default() -> Self {
+        Self {
+            reduce_motion: false,
+            focus_outline_width: 0.0,
+            focus_outline: [1.0, 0.85, 0.0, 1.0],
+            focus_halo: [0.0, 0.0, 0.0, 1.0],
+            announce_focus: false,
+            narrate: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Accessibility {
+    config: AccessibilityConfig,
+    /// The window last announced, and by what title.
+    announced: Option<(u32, String)>,
+    /// The narration command still saying it, which the next one cuts off.
+    speaking: Option<Child>,
+}
+
+impl Accessibility {
+    pub fn config(&self) -> &AccessibilityConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: AccessibilityConfig) {
+        self.config = config;
+    }
+
+    /// Follows the focus to `window`, announcing it once its title is known and runs
+    /// the narration command; returns the title for an OSD, when one is asked for.
+    pub fn focused(&mut self, window: Option<u32>, title: Option<&str>) -> Option<String> {
+        let (Some(window), Some(title)) = (window, title.filter(|title| !title.is_empty()))
+        else {
+            return None;
+        };
+        if self
+            .announced
+            .as_ref()
+            .is_some_and(|(announced, said)| *announced == window && said == title)
+        {
+            return None;
+        }
+        self.announced = Some((window, title.to_string()));
+        if let Some(mut speaking) = self.speaking.take() {
+            let _ = speaking.kill();
+            let _ = speaking.wait();
+        }
+        if let Some(narrate) = &self.config.narrate {
+            let spawned = Command::new("sh")
+                .arg("-c")
+                .arg(narrate)
+                .arg("sh")
+                .arg(title)
+                .spawn();
+            match spawned {
+                Ok(child) => self.speaking = Some(child),
+                Err(e) => eprintln!("Failed to run {:?}: {}", narrate, e),
+            }
+        }
+        self.config.announce_focus.then(|| title.to_string())
+    }
+
+    /// The outline around `rect`, the focused window in output pixels at `scale`.
+    pub fn outline(&self, rect: [f32; 4], corner_radius: f32, scale: f32) -> Vec<DrawItem> {
+        let width = self.config.focus_outline_width * scale;
+        if width <= 0.0 {
+            return Vec::new();
+        }
+        let halo = (width / 3.0).max(scale);
+        let [x, y, w, h] = rect;
+        let around = |by: f32| [x - by, y - by, w + 2.0 * by, h + 2.0 * by];
+        vec![
+            ring(around(width), self.config.focus_outline, corner_radius + width, width),
+            ring(around(halo), self.config.focus_halo, corner_radius + halo, halo),
+        ]
+    }
+}
+
+/// The edge of `rect`, `border` pixels wide, in `color`, which isn't premultiplied.
+fn ring(rect: [f32; 4], color: [f32; 4], corner_radius: f32, border: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border,
+    }
+}
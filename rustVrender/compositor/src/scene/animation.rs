@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use super::WindowType;
+
+/// How an animation progresses over its duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Easing {
+    Linear,
+    #[default]
+    EaseOutCubic,
+    EaseInOutCubic,
+    /// Overshoots slightly before settling.
+    EaseOutBack,
+}
+
+impl Easing {
+    /// Maps linear progress `t` in `0..=1` onto the curve.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOutCubic => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Easing::EaseOutBack => {
+                const C1: f32 = 1.70158;
+                const C3: f32 = C1 + 1.0;
+                1.0 + C3 * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+/// A duration and easing pair.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Curve {
+    pub duration_ms: u32,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+impl Curve {
+    pub const fn new(duration_ms: u32, easing: Easing) -> Self {
+        Self {
+            duration_ms,
+            easing,
+        }
+    }
+
+    /// How long the curve takes, which is no time at all with reduce motion on.
+    pub fn duration(&self) -> Duration {
+        if REDUCE_MOTION.load(Ordering::Relaxed) {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(self.duration_ms as u64)
+    }
+}
+
+/// Set from the accessibility config and the power profile, for every curve at once.
+static REDUCE_MOTION: AtomicBool = AtomicBool::new(false);
+
+/// Has every curve finish as it starts, or take its time again.
+pub fn set_reduce_motion(reduce: bool) {
+    REDUCE_MOTION.store(reduce, Ordering::Relaxed);
+}
+
+/// Which window changes animate, and how.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationConfig {
+    pub enabled: bool,
+    /// Fade and scale in when a window is mapped.
+    pub map: Curve,
+    /// Scale a window starts at when it is mapped.
+    pub map_scale: f32,
+    /// Fade out when a window is unmapped.
+    pub unmap: Curve,
+    /// Movement between two positions or sizes.
+    pub geometry: Curve,
+    /// Window types that never animate.
+    pub disabled_types: Vec<WindowType>,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            map: Curve::new(180, Easing::EaseOutCubic),
+            map_scale: 0.9,
+            unmap: Curve::new(120, Easing::Linear),
+            geometry: Curve::new(200, Easing::EaseOutCubic),
+            disabled_types: vec![WindowType::Dock, WindowType::Popup],
+        }
+    }
+}
+
+/// A window rectangle: x, y, width, height in output pixels.
+pub type Rect = [f32; 4];
+
+/// Values an animation can interpolate.
+trait Lerp: Copy {
+    fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Lerp for Rect {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        std::array::from_fn(|i| f32::lerp(from[i], to[i], t))
+    }
+}
+
+/// A value moving from `from` to `to` along `curve`.
+#[derive(Debug, Clone, Copy)]
+struct Tween<T> {
+    from: T,
+    to: T,
+    start: Instant,
+    curve: Curve,
+}
+
+impl<T: Lerp> Tween<T> {
+    fn new(from: T, to: T, start: Instant, curve: Curve) -> Self {
+        Self {
+            from,
+            to,
+            start,
+            curve,
+        }
+    }
+
+    fn sample(&self, now: Instant) -> T {
+        let duration = self.curve.duration();
+        if duration.is_zero() {
+            return self.to;
+        }
+        let t = now.saturating_duration_since(self.start).as_secs_f32() / duration.as_secs_f32();
+        T::lerp(self.from, self.to, self.curve.easing.apply(t))
+    }
+
+    fn done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.curve.duration()
+    }
+}
+
+/// How an animated window differs from its resting state at one instant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub rect: Rect,
+    pub opacity: f32,
+    /// Scale around the window's center.
+    pub scale: f32,
+}
+
+#[derive(Debug)]
+struct WindowAnimation {
+    window_type: WindowType,
+    /// 0 while hidden, 1 once fully shown.
+    presence: Option<Tween<f32>>,
+    geometry: Option<Tween<Rect>>,
+    closing: bool,
+    /// Contents were cleared while closing; drop them once the window is gone.
+    clear_contents: bool,
+}
+
+/// Tracks running window animations against a frame clock.
+#[derive(Debug)]
+pub struct Animator {
+    config: AnimationConfig,
+    windows: HashMap<u32, WindowAnimation>,
+    /// Time of the frame being drawn.
+    now: Instant,
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self {
+            config: AnimationConfig::default(),
+            windows: HashMap::new(),
+            now: Instant::now(),
+        }
+    }
+}
+
+impl Animator {
+    pub fn config(&self) -> &AnimationConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: AnimationConfig) {
+        self.config = config;
+    }
+
+    /// Advances the frame clock; returns windows whose closing animation finished,
+    /// with whether their contents should be dropped.
+    pub fn advance(&mut self, now: Instant) -> Vec<(u32, bool)> {
+        self.now = self.now.max(now);
+        let now = self.now;
+        let mut closed = Vec::new();
+        self.windows.retain(|id, anim| {
+            if anim.presence.is_some_and(|t| t.done(now)) {
+                if anim.closing {
+                    closed.push((*id, anim.clear_contents));
+                    return false;
+                }
+                anim.presence = None;
+            }
+            if anim.geometry.is_some_and(|t| t.done(now)) {
+                anim.geometry = None;
+            }
+            true
+        });
+        closed
+    }
+
+    /// Whether any animation still needs frames.
+    pub fn active(&self) -> bool {
+        self.windows
+            .values()
+            .any(|anim| anim.presence.is_some() || anim.geometry.is_some())
+    }
+
+    /// Records a newly mapped or updated window at `rect`; `previous` is where it was
+    /// drawn so far, if it was already shown.
+    pub fn update(&mut self, id: u32, window_type: WindowType, previous: Option<Rect>, rect: Rect) {
+        let animate = self.animates(window_type);
+        let now = self.now.max(Instant::now());
+        let config = &self.config;
+        let anim = self.windows.entry(id).or_insert(WindowAnimation {
+            window_type,
+            presence: None,
+            geometry: None,
+            closing: false,
+            clear_contents: false,
+        });
+        anim.window_type = window_type;
+        if !animate {
+            anim.presence = None;
+            anim.geometry = None;
+            anim.closing = false;
+            return;
+        }
+
+        let shown_from = if anim.closing {
+            // Mapped again while fading out: fade back in from where it is.
+            anim.closing = false;
+            anim.clear_contents = false;
+            Some(anim.presence.map_or(1.0, |t| t.sample(now)))
+        } else if previous.is_none() {
+            Some(0.0)
+        } else {
+            None
+        };
+        if let Some(from) = shown_from {
+            anim.presence = Some(Tween::new(from, 1.0, now, config.map));
+        }
+
+        if let Some(previous) = previous {
+            let from = anim.geometry.map_or(previous, |t| t.sample(now));
+            if from != rect && anim.geometry.is_none_or(|t| t.to != rect) {
+                anim.geometry = Some(Tween::new(from, rect, now, config.geometry));
+            }
+        }
+    }
+
+    /// Starts fading out `id`; returns whether the window must stay in the scene for it.
+    pub fn close(&mut self, id: u32) -> bool {
+        let now = self.now.max(Instant::now());
+        let animate = self
+            .windows
+            .get(&id)
+            .is_some_and(|anim| self.animates(anim.window_type));
+        if !animate {
+            self.windows.remove(&id);
+            return false;
+        }
+        let unmap = self.config.unmap;
+        let anim = self.windows.get_mut(&id).unwrap();
+        if !anim.closing {
+            anim.closing = true;
+            let from = anim.presence.map_or(1.0, |t| t.sample(now));
+            anim.presence = Some(Tween::new(from, 0.0, now, unmap));
+        }
+        true
+    }
+
+    /// Drops every animation of `id`, so it is drawn at rest, or goes at once when
+    /// removed.
+    pub fn cancel(&mut self, id: u32) {
+        self.windows.remove(&id);
+    }
+
+    /// Asks for the contents of a closing window to be dropped when it is gone;
+    /// returns `false` if the window is not closing.
+    pub fn defer_clear(&mut self, id: u32) -> bool {
+        match self.windows.get_mut(&id) {
+            Some(anim) if anim.closing => {
+                anim.clear_contents = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Where and how to draw `id`, which rests at `rect`, in the current frame.
+    pub fn frame(&self, id: u32, rect: Rect) -> Frame {
+        let Some(anim) = self.windows.get(&id) else {
+            return Frame {
+                rect,
+                opacity: 1.0,
+                scale: 1.0,
+            };
+        };
+        let presence = anim.presence.map_or(1.0, |t| t.sample(self.now));
+        let scale = if anim.closing {
+            1.0
+        } else {
+            f32::lerp(self.config.map_scale, 1.0, presence)
+        };
+        Frame {
+            rect: anim.geometry.map_or(rect, |t| t.sample(self.now)),
+            opacity: presence.clamp(0.0, 1.0),
+            scale,
+        }
+    }
+
+    fn animates(&self, window_type: WindowType) -> bool {
+        self.config.enabled && !self.config.disabled_types.contains(&window_type)
+    }
+}
@@ -0,0 +1,570 @@
+// Annotating screenshots: Annotate freezes an output on the frame it showed, and
+// dragging with the left button draws rectangles or arrows on it and a click starts a
+// line of text, in the colors of annotate.colors, until Enter saves the image or c
+// copies it to the clipboard with annotate.copy_command. r, a, and t pick the tool,
+// digits the color, and u or Backspace takes back the last shape; Escape drops the
+// text being typed, or else the whole annotation. What is drawn is in the output's
+// pixels, as the image is, and rendered over it like the rest of the compositor's
+// own drawing.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+use super::animation::Rect;
+use super::bar::taskbar::ICON_ID_BASE;
+use super::theme::{Color, Palette};
+use super::{DrawItem, DrawSource, NodeId, WindowContents, WHOLE_CONTENTS};
+use crate::screenshot::Capture;
+use crate::text::{self, Font};
+
+pub use qtilerugo_ipc::renderer::Annotate;
+
+/// The texture id of the frozen frame, below those of window icons.
+pub const FROZEN_ID: u32 = ICON_ID_BASE - 1;
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 6.0;
+const CORNER_RADIUS: f32 = 6.0;
+/// Between the help and the bottom of the output.
+const MARGIN: f32 = 16.0;
+const HELP_PX: f32 = 13.0;
+const SWATCH: f32 = 12.0;
+/// How long the arrowhead's sides are, in line widths, and how far they open.
+const HEAD: f32 = 4.0;
+const HEAD_ANGLE: f32 = 0.5;
+
+/// The help is in the OSD's colors, not premultiplied.
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.12, 0.9];
+const FOREGROUND: [f32; 4] = [0.92, 0.92, 0.94, 1.0];
+const HELP: &str =
+    "r rect · a arrow · t text · 1-9 color · u undo · Enter save · c copy · Esc cancel";
+
+/// Characters typed, as the frontends deliver them.
+const ENTER: char = '\r';
+const ESCAPE: char = '\u{1b}';
+const BACKSPACE: char = '\u{8}';
+const DELETE: char = '\u{7f}';
+/// Ctrl+C and Ctrl+S.
+const CTRL_C: char = '\u{3}';
+const CTRL_S: char = '\u{13}';
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnnotateConfig {
+    /// The colors 1 to 9 pick, the first to start with.
+    pub colors: Vec<Color>,
+    /// Of rectangles and arrows, in logical pixels.
+    pub line_width: f32,
+    pub text_px: f32,
+    /// Run with sh -c and the PNG on its standard input, to copy it to the clipboard.
+    pub copy_command: String,
+}
+
+impl Default for AnnotateConfig {
+    fn default() -> Self {
+        Self {
+            colors: vec![
+                Color::base(0x08, 1.0),
+                Color::base(0x0A, 1.0),
+                Color::base(0x0B, 1.0),
+                Color::base(0x0D, 1.0),
+                Color::base(0x07, 1.0),
+                Color::base(0x00, 1.0),
+            ],
+            line_width: 3.0,
+            text_px: 20.0,
+            copy_command: "xclip -selection clipboard -t image/png".to_string(),
+        }
+    }
+}
+
+impl AnnotateConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        for color in &mut self.colors {
+            color.retheme(palette);
+        }
+    }
+}
+
+/// What drawing with the pointer makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Rectangle,
+    Arrow,
+    Text,
+}
+
+/// How an annotation ends, once its image is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finish {
+    Save,
+    Copy,
+}
+
+/// The rendered image, and what to do with it.
+#[derive(Debug)]
+pub struct Annotated {
+    pub capture: Capture,
+    pub finish: Finish,
+}
+
+/// Where an Annotate command waits for its image.
+pub type AnnotateReply = oneshot::Sender<Result<Annotated, String>>;
+
+/// An Annotate command waiting for a render loop to freeze its output.
+#[derive(Debug)]
+pub struct AnnotateRequest {
+    pub output: Option<String>,
+    pub reply: AnnotateReply,
+}
+
+/// Something drawn, in the output's pixels.
+#[derive(Debug, Clone)]
+enum Shape {
+    Rectangle {
+        from: [f32; 2],
+        to: [f32; 2],
+        color: [f32; 4],
+    },
+    Arrow {
+        from: [f32; 2],
+        to: [f32; 2],
+        color: [f32; 4],
+    },
+    Text {
+        at: [f32; 2],
+        text: String,
+        color: [f32; 4],
+    },
+}
+
+/// The annotation of one output's frozen frame.
+#[derive(Debug)]
+struct Annotation {
+    output: NodeId,
+    /// The output in root window coordinates, and its scale then.
+    bounds: Rect,
+    scale: f32,
+    frozen: WindowContents,
+    serial: u64,
+    shapes: Vec<Shape>,
+    tool: Tool,
+    color: usize,
+    /// Where the drag began, in the output's pixels.
+    drag: Option<[f32; 2]>,
+    /// The text being typed, where it starts.
+    typing: Option<([f32; 2], String)>,
+    reply: AnnotateReply,
+    /// Set once it should be rendered and handed back.
+    finish: Option<Finish>,
+}
+
+/// A finished annotation, for a render loop to render and hand back.
+pub struct Rendering {
+    pub items: Vec<DrawItem>,
+    pub extent: [u32; 2],
+    pub finish: Finish,
+    pub reply: AnnotateReply,
+}
+
+/// The Annotate commands waiting, and the annotation under way.
+#[derive(Debug, Default)]
+pub struct Annotator {
+    config: AnnotateConfig,
+    requests: Vec<AnnotateRequest>,
+    active: Option<Annotation>,
+    serial: u64,
+}
+
+impl Annotator {
+    pub fn config(&self) -> &AnnotateConfig {
+        &self.config
+    }
+
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.config.retheme(palette);
+    }
+
+    pub fn set_config(&mut self, config: AnnotateConfig) {
+        if let Some(annotation) = &mut self.active {
+            annotation.color = annotation.color.min(config.colors.len().saturating_sub(1));
+        }
+        self.config = config;
+    }
+
+    pub fn request(&mut self, request: AnnotateRequest) {
+        if self.busy() {
+            let _ = request.reply.send(Err("already annotating".into()));
+            return;
+        }
+        self.requests.push(request);
+    }
+
+    /// Whether an annotation is under way or waits to start.
+    pub fn busy(&self) -> bool {
+        self.active.is_some() || !self.requests.is_empty()
+    }
+
+    /// Takes the commands waiting for their outputs to freeze, dropping those nobody
+    /// waits for anymore.
+    pub fn take_requests(&mut self) -> Vec<AnnotateRequest> {
+        self.requests.retain(|request| !request.reply.is_closed());
+        std::mem::take(&mut self.requests)
+    }
+
+    /// Starts annotating `frame`, that of `output` at `bounds` and `scale`, for `reply`.
+    pub fn start(
+        &mut self,
+        output: NodeId,
+        bounds: Rect,
+        scale: f32,
+        frame: &Capture,
+        reply: AnnotateReply,
+    ) {
+        self.serial += 1;
+        self.active = Some(Annotation {
+            output,
+            bounds,
+            scale,
+            frozen: frame.to_contents(),
+            serial: self.serial,
+            shapes: Vec::new(),
+            tool: Tool::Rectangle,
+            color: 0,
+            drag: None,
+            typing: None,
+            reply,
+            finish: None,
+        });
+    }
+
+    /// The output being annotated.
+    pub fn output(&self) -> Option<NodeId> {
+        self.active.as_ref().map(|annotation| annotation.output)
+    }
+
+    /// Drops an annotation nobody waits for anymore; returns whether it did.
+    pub fn prune(&mut self) -> bool {
+        let gone = self
+            .active
+            .as_ref()
+            .is_some_and(|annotation| annotation.reply.is_closed());
+        if gone {
+            self.active = None;
+        }
+        gone
+    }
+
+    /// Ends the annotation, answering it with `reason`.
+    pub fn cancel(&mut self, reason: &str) {
+        if let Some(annotation) = self.active.take() {
+            let _ = annotation.reply.send(Err(reason.to_string()));
+        }
+    }
+
+    /// Takes a press or release of the left button with the pointer at `pointer`, in
+    /// root window coordinates; returns whether an annotation took it.
+    pub fn button(&mut self, pressed: bool, pointer: Option<[f32; 2]>) -> bool {
+        let Some(annotation) = &mut self.active else {
+            return false;
+        };
+        let Some(at) = pointer.map(|pointer| annotation.at(pointer)) else {
+            return true;
+        };
+        let color = self
+            .config
+            .colors
+            .get(annotation.color)
+            .map_or(FOREGROUND, Color::get);
+        match (annotation.tool, pressed) {
+            (Tool::Text, true) => {
+                annotation.commit_text(color);
+                annotation.typing = Some((at, String::new()));
+            }
+            (_, true) => annotation.drag = Some(at),
+            (tool, false) => {
+                let Some(from) = annotation.drag.take() else {
+                    return true;
+                };
+                if (at[0] - from[0]).abs() < 2.0 && (at[1] - from[1]).abs() < 2.0 {
+                    return true;
+                }
+                annotation.shapes.push(match tool {
+                    Tool::Arrow => Shape::Arrow {
+                        from,
+                        to: at,
+                        color,
+                    },
+                    _ => Shape::Rectangle {
+                        from,
+                        to: at,
+                        color,
+                    },
+                });
+            }
+        }
+        true
+    }
+
+    /// Whether a shape follows the pointer, so it is drawn again as it moves.
+    pub fn dragging(&self) -> bool {
+        self.active
+            .as_ref()
+            .is_some_and(|annotation| annotation.drag.is_some())
+    }
+
+    /// Takes a typed character; returns whether an annotation took it.
+    pub fn typed(&mut self, c: char) -> bool {
+        let Some(annotation) = &mut self.active else {
+            return false;
+        };
+        let color = self
+            .config
+            .colors
+            .get(annotation.color)
+            .map_or(FOREGROUND, Color::get);
+        if let Some((_, text)) = &mut annotation.typing {
+            match c {
+                ESCAPE => annotation.typing = None,
+                ENTER => annotation.commit_text(color),
+                BACKSPACE | DELETE => {
+                    text.pop();
+                }
+                c if !c.is_control() => text.push(c),
+                _ => {}
+            }
+            return true;
+        }
+        match c {
+            'r' => annotation.tool = Tool::Rectangle,
+            'a' => annotation.tool = Tool::Arrow,
+            't' => annotation.tool = Tool::Text,
+            '1'..='9' => {
+                let index = c as usize - '1' as usize;
+                if index < self.config.colors.len() {
+                    annotation.color = index;
+                }
+            }
+            'u' | BACKSPACE | DELETE => {
+                annotation.shapes.pop();
+            }
+            ENTER | CTRL_S => annotation.finish = Some(Finish::Save),
+            'c' | CTRL_C => annotation.finish = Some(Finish::Copy),
+            ESCAPE => self.cancel("cancelled"),
+            _ => {}
+        }
+        true
+    }
+
+    /// The finished annotation, with what renders its image, if there is one.
+    pub fn take_finished(&mut self, font: Option<&Arc<Font>>) -> Option<Rendering> {
+        let finish = self.active.as_ref()?.finish?;
+        let annotation = self.active.take()?;
+        let items = annotation.image_items(&self.config, font);
+        Some(Rendering {
+            items,
+            extent: [annotation.frozen.width, annotation.frozen.height],
+            finish,
+            reply: annotation.reply,
+        })
+    }
+
+    /// The frozen frame, what is drawn on it, and the help, for `output`, with the
+    /// pointer at `pointer`, in root window coordinates.
+    pub fn items(
+        &self,
+        output: NodeId,
+        pointer: Option<[f32; 2]>,
+        font: Option<&Arc<Font>>,
+    ) -> Option<Vec<DrawItem>> {
+        let annotation = self.active.as_ref().filter(|a| a.output == output)?;
+        let config = &self.config;
+        let mut items = annotation.image_items(config, font);
+        let color = config
+            .colors
+            .get(annotation.color)
+            .map_or(FOREGROUND, Color::get);
+        let scale = annotation.scale;
+        let line = config.line_width * scale;
+
+        // The shape being dragged, and the caret after the text being typed.
+        if let (Some(from), Some(pointer)) = (annotation.drag, pointer) {
+            let to = annotation.at(pointer);
+            items.extend(match annotation.tool {
+                Tool::Arrow => arrow(from, to, line, color),
+                _ => vec![rectangle(from, to, line, color)],
+            });
+        }
+        if let (Some((at, text)), Some(font)) = (&annotation.typing, font) {
+            let px = config.text_px * scale;
+            let width = font.layout(text, px).width;
+            items.push(solid(
+                [at[0] + width, at[1], scale.max(1.0) * 2.0, px * 1.2],
+                color,
+            ));
+        }
+
+        // The help along the bottom, after a swatch of the color and the tool's name.
+        let Some(font) = font else {
+            return Some(items);
+        };
+        let tool = match annotation.tool {
+            Tool::Rectangle => "Rectangle",
+            Tool::Arrow => "Arrow",
+            Tool::Text => "Text",
+        };
+        let label = font.layout(&format!("{} · {}", tool, HELP), HELP_PX * scale);
+        let padding = PADDING * scale;
+        let swatch = SWATCH * scale;
+        let width = swatch + padding + label.width + 2.0 * padding;
+        let height = label.height.max(swatch) + 2.0 * padding;
+        let [_, _, output_width, output_height] = annotation.bounds;
+        let left = ((output_width * scale - width) / 2.0).round();
+        let top = (output_height * scale - height - MARGIN * scale).round();
+        let [r, g, b, a] = BACKGROUND;
+        items.push(DrawItem {
+            rect: [left, top, width, height],
+            color: [r * a, g * a, b * a, a],
+            source: DrawSource::Solid,
+            corner_radius: CORNER_RADIUS * scale,
+            border: 0.0,
+        });
+        let swatch_top = top + (height - swatch) / 2.0;
+        items.push(solid([left + padding, swatch_top, swatch, swatch], color));
+        items.extend(text::draw(
+            font,
+            &label,
+            left + 2.0 * padding + swatch,
+            top + (height - label.height) / 2.0,
+            FOREGROUND,
+        ));
+        Some(items)
+    }
+}
+
+impl Annotation {
+    /// `pointer`, in root window coordinates, in the output's pixels.
+    fn at(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [
+            (x - self.bounds[0]) * self.scale,
+            (y - self.bounds[1]) * self.scale,
+        ]
+    }
+
+    /// Keeps the text being typed as a shape, unless it is empty.
+    fn commit_text(&mut self, color: [f32; 4]) {
+        if let Some((at, text)) = self.typing.take().filter(|(_, text)| !text.is_empty()) {
+            self.shapes.push(Shape::Text { at, text, color });
+        }
+    }
+
+    /// The frozen frame and the shapes on it, as the saved image shows them.
+    fn image_items(&self, config: &AnnotateConfig, font: Option<&Arc<Font>>) -> Vec<DrawItem> {
+        let mut items = vec![DrawItem {
+            rect: [
+                0.0,
+                0.0,
+                self.frozen.width as f32,
+                self.frozen.height as f32,
+            ],
+            color: [1.0; 4],
+            source: DrawSource::Window {
+                id: FROZEN_ID,
+                serial: self.serial,
+                contents: self.frozen.clone(),
+                frozen: false,
+                part: WHOLE_CONTENTS,
+            },
+            corner_radius: 0.0,
+            border: 0.0,
+        }];
+        let line = config.line_width * self.scale;
+        let px = config.text_px * self.scale;
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rectangle { from, to, color } => {
+                    items.push(rectangle(*from, *to, line, *color));
+                }
+                Shape::Arrow { from, to, color } => items.extend(arrow(*from, *to, line, *color)),
+                Shape::Text { at, text, color } => {
+                    if let Some(font) = font {
+                        let label = font.layout(text, px);
+                        items.extend(text::draw(font, &label, at[0], at[1], *color));
+                    }
+                }
+            }
+        }
+        if let (Some((at, text)), Some(font)) = (&self.typing, font) {
+            let color = config.colors.get(self.color).map_or(FOREGROUND, Color::get);
+            let label = font.layout(text, px);
+            items.extend(text::draw(font, &label, at[0], at[1], color));
+        }
+        items
+    }
+}
+
+/// The outline of the rectangle between corners `from` and `to`.
+fn rectangle(from: [f32; 2], to: [f32; 2], line: f32, color: [f32; 4]) -> DrawItem {
+    let left = from[0].min(to[0]);
+    let top = from[1].min(to[1]);
+    let rect = [
+        left - line / 2.0,
+        top - line / 2.0,
+        (from[0] - to[0]).abs() + line,
+        (from[1] - to[1]).abs() + line,
+    ];
+    DrawItem {
+        border: line,
+        ..solid(rect, color)
+    }
+}
+
+/// A line from `from` to `to` with a head at `to`, as round dots close enough to
+/// touch, since draw items are upright rects.
+fn arrow(from: [f32; 2], to: [f32; 2], line: f32, color: [f32; 4]) -> Vec<DrawItem> {
+    let [dx, dy] = [to[0] - from[0], to[1] - from[1]];
+    let angle = dy.atan2(dx);
+    let head = (HEAD * line).max(8.0).min(dx.hypot(dy));
+    let mut items = segment(from, to, line, color);
+    for side in [-HEAD_ANGLE, HEAD_ANGLE] {
+        let back = angle + std::f32::consts::PI + side;
+        let end = [to[0] + head * back.cos(), to[1] + head * back.sin()];
+        items.extend(segment(to, end, line, color));
+    }
+    items
+}
+
+fn segment(from: [f32; 2], to: [f32; 2], line: f32, color: [f32; 4]) -> Vec<DrawItem> {
+    let length = (to[0] - from[0]).hypot(to[1] - from[1]);
+    let step = (line / 3.0).max(0.5);
+    let dots = (length / step).ceil() as usize;
+    (0..=dots)
+        .map(|i| {
+            let t = if dots == 0 {
+                0.0
+            } else {
+                i as f32 / dots as f32
+            };
+            let x = from[0] + (to[0] - from[0]) * t;
+            let y = from[1] + (to[1] - from[1]) * t;
+            DrawItem {
+                corner_radius: line / 2.0,
+                ..solid([x - line / 2.0, y - line / 2.0, line, line], color)
+            }
+        })
+        .collect()
+}
+
+/// A rect in `color`, which isn't premultiplied.
+fn solid(rect: Rect, color: [f32; 4]) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius: 0.0,
+        border: 0.0,
+    }
+}
@@ -0,0 +1,337 @@
+// The popup a click on the bar's clock opens: the month, with today marked, over the
+// notifications that came in lately, newest first. Scrolling over the month turns it,
+// and over the notifications moves through them; Escape or a click anywhere else puts
+// it away.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeDelta};
+use serde::Deserialize;
+
+use super::taskbar::fit;
+use super::{contains, solid, BarConfig, BarPosition};
+use crate::scene::animation::Rect;
+use crate::scene::theme::{Color, Palette};
+use crate::scene::DrawItem;
+use crate::text::{self, Font};
+
+pub use qtilerugo_ipc::renderer::Notification;
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 10.0;
+const CORNER_RADIUS: f32 = 6.0;
+/// Between the popup and the bar it opens from.
+const GAP: f32 = 4.0;
+/// A line of text is this many times the text size high.
+const LINE: f32 = 1.5;
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+/// Enough rows for any month starting on any day.
+const WEEKS: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    pub enabled: bool,
+    /// In logical pixels.
+    pub width: f32,
+    /// How many notifications are kept.
+    pub history: usize,
+    /// How many of them show at once.
+    pub rows: usize,
+    /// Text is in the bar's foreground.
+    pub background: Color,
+    pub today: Color,
+    /// Weekdays, days of the months around, and what notifications say past their
+    /// summary.
+    pub dim: Color,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            width: 280.0,
+            history: 50,
+            rows: 5,
+            background: Color::base(0x00, 0.96),
+            today: Color::base(0x03, 1.0),
+            dim: Color::base(0x04, 1.0),
+        }
+    }
+}
+
+impl CalendarConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        for color in [&mut self.background, &mut self.today, &mut self.dim] {
+            color.retheme(palette);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Received {
+    notification: Notification,
+    at: DateTime<Local>,
+}
+
+/// Where the popup is open.
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    /// The output it is on, and the popup, in root window coordinates.
+    bounds: Rect,
+    rect: Rect,
+    /// Months after the current one shown, or before if negative.
+    month: i32,
+    /// Notifications scrolled past.
+    scrolled: usize,
+    /// Scrolling not yet a whole line, from touchpads.
+    scroll_rest: f32,
+}
+
+/// The popup, and the notifications it lists.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    history: VecDeque<Received>,
+    open: Option<Open>,
+    /// Whether a press the popup took waits for its release.
+    held: bool,
+}
+
+impl Calendar {
+    /// Keeps `notification` at the top of the history, dropping the oldest past the
+    /// configured length.
+    pub(super) fn push(&mut self, config: &CalendarConfig, notification: Notification) {
+        self.history.push_front(Received {
+            notification,
+            at: Local::now(),
+        });
+        self.history.truncate(config.history);
+    }
+
+    pub(super) fn shown(&self) -> bool {
+        self.open.is_some()
+    }
+
+    /// Opens the popup from the clock at `slot` on the bar at `bar`, on an output at
+    /// `bounds`, all in root window coordinates; or closes it if it is open.
+    pub(super) fn toggle(&mut self, config: &BarConfig, bar: Rect, bounds: Rect, slot: Rect) {
+        if self.close() {
+            return;
+        }
+        let [left, top, width, _] = bounds;
+        let (popup_width, popup_height) = (config.calendar.width.min(width), height_of(config));
+        let x = (slot[0] + slot[2] / 2.0 - popup_width / 2.0)
+            .min(left + width - popup_width - GAP)
+            .max(left + GAP);
+        let y = match config.position {
+            BarPosition::Top => bar[1] + bar[3] + GAP,
+            BarPosition::Bottom => bar[1] - popup_height - GAP,
+        };
+        self.open = Some(Open {
+            bounds,
+            rect: [x, y.max(top), popup_width, popup_height],
+            month: 0,
+            scrolled: 0,
+            scroll_rest: 0.0,
+        });
+    }
+
+    /// Puts the popup away; returns whether it was open.
+    pub(super) fn close(&mut self) -> bool {
+        self.open.take().is_some()
+    }
+
+    /// Takes a press at `pointer`, in root window coordinates, while the popup is open,
+    /// which puts it away unless it is on it; returns whether it was open.
+    pub(super) fn press(&mut self, pointer: [f32; 2]) -> bool {
+        let Some(open) = &self.open else {
+            return false;
+        };
+        if !contains(open.rect, pointer) {
+            self.open = None;
+        }
+        self.held = true;
+        true
+    }
+
+    /// Ends a press the popup took; returns whether there was one.
+    pub(super) fn release(&mut self) -> bool {
+        std::mem::take(&mut self.held)
+    }
+
+    pub(super) fn held(&self) -> bool {
+        self.held
+    }
+
+    /// Scrolls by `lines`, down if positive, what is under `pointer`: the month or the
+    /// notifications; returns whether the pointer is on the popup.
+    pub(super) fn scroll(&mut self, config: &BarConfig, pointer: [f32; 2], lines: f32) -> bool {
+        let Some(open) = &mut self.open else {
+            return false;
+        };
+        if !contains(open.rect, pointer) {
+            return false;
+        }
+        open.scroll_rest += lines;
+        let steps = open.scroll_rest.trunc();
+        open.scroll_rest -= steps;
+        let steps = steps as i32;
+        if pointer[1] < open.rect[1] + PADDING + month_height(config) {
+            open.month += steps;
+        } else {
+            let last = self.history.len().saturating_sub(config.calendar.rows);
+            open.scrolled = (open.scrolled as i32 + steps).clamp(0, last as i32) as usize;
+        }
+        true
+    }
+
+    /// The popup, if it is open on the output at `bounds`, in the pixels of the output
+    /// at `scale`.
+    pub(super) fn items(
+        &self,
+        config: &BarConfig,
+        bounds: Rect,
+        scale: f32,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let (Some(open), Some(font)) = (&self.open, font) else {
+            return Vec::new();
+        };
+        if open.bounds != bounds {
+            return Vec::new();
+        }
+        let calendar = &config.calendar;
+        let [x, y, width, height] = open.rect;
+        let left = ((x - bounds[0]) * scale).round();
+        let top = ((y - bounds[1]) * scale).round();
+        let padding = PADDING * scale;
+        let px = config.text_px * scale;
+        let line = config.text_px * LINE * scale;
+        let inner = width * scale - 2.0 * padding;
+        let mut items = vec![solid(
+            [left, top, width * scale, height * scale],
+            calendar.background.get(),
+            CORNER_RADIUS * scale,
+            0.0,
+        )];
+
+        // The month, under its name and the weekdays.
+        let today = Local::now().date_naive();
+        let first = first_of_month(today, open.month);
+        let mut row = top + padding;
+        let title = first.format("%B %Y").to_string();
+        let cell = [left + padding, row, inner, line];
+        centered(&mut items, font, &title, px, cell, config.foreground.get());
+        row += line;
+        let column = inner / 7.0;
+        for (i, weekday) in WEEKDAYS.iter().enumerate() {
+            let cell = [left + padding + i as f32 * column, row, column, line];
+            centered(&mut items, font, weekday, px, cell, calendar.dim.get());
+        }
+        row += line;
+        let lead = first.weekday().num_days_from_monday() as i64;
+        for i in 0..(WEEKS * 7) as i64 {
+            let Some(date) = first.checked_add_signed(TimeDelta::days(i - lead)) else {
+                continue;
+            };
+            let (week, weekday) = (i / 7, i % 7);
+            let cell = [
+                left + padding + weekday as f32 * column,
+                row + week as f32 * line,
+                column,
+                line,
+            ];
+            if date == today {
+                let [cx, cy, cw, ch] = cell;
+                let inset = scale;
+                let mark = [cx + inset, cy + inset, cw - 2.0 * inset, ch - 2.0 * inset];
+                items.push(solid(mark, calendar.today.get(), CORNER_RADIUS * scale, 0.0));
+            }
+            let color = if date.month() == first.month() {
+                config.foreground.get()
+            } else {
+                calendar.dim.get()
+            };
+            centered(&mut items, font, &date.day().to_string(), px, cell, color);
+        }
+        row += WEEKS as f32 * line + padding;
+
+        // The notifications, newest first, each a summary over who sent it when and
+        // what it says.
+        if self.history.is_empty() {
+            let cell = [left + padding, row, inner, line];
+            centered(&mut items, font, "No notifications", px, cell, calendar.dim.get());
+            return items;
+        }
+        let shown = self.history.iter().skip(open.scrolled).take(calendar.rows);
+        for received in shown {
+            let notification = &received.notification;
+            let when = if received.at.date_naive() == today {
+                received.at.format("%H:%M")
+            } else {
+                received.at.format("%d %b")
+            };
+            let mut details = when.to_string();
+            for part in [&notification.app, &notification.body] {
+                if !part.is_empty() {
+                    details.push_str(" · ");
+                    details.push_str(part);
+                }
+            }
+            let lines = [
+                (&notification.summary, config.foreground.get()),
+                (&details, calendar.dim.get()),
+            ];
+            for (text, color) in lines {
+                // Bodies may run over several lines, which are shown as one.
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if let Some(text) = fit(font, &text, px, inner) {
+                    let label = font.layout(&text, px);
+                    let text_top = (row + (line - label.height) / 2.0).round();
+                    items.extend(text::draw(font, &label, left + padding, text_top, color));
+                }
+                row += line;
+            }
+        }
+        items
+    }
+}
+
+/// Draws `text` in the middle of `cell`.
+fn centered(
+    items: &mut Vec<DrawItem>,
+    font: &Arc<Font>,
+    text: &str,
+    px: f32,
+    cell: Rect,
+    color: [f32; 4],
+) {
+    let label = font.layout(text, px);
+    let [x, y, width, height] = cell;
+    items.extend(text::draw(
+        font,
+        &label,
+        (x + (width - label.width) / 2.0).round(),
+        (y + (height - label.height) / 2.0).round(),
+        color,
+    ));
+}
+
+/// How high the month is, with its name and the weekdays, at scale 1.
+fn month_height(config: &BarConfig) -> f32 {
+    config.text_px * LINE * (2 + WEEKS) as f32
+}
+
+/// How high the popup is at scale 1.
+fn height_of(config: &BarConfig) -> f32 {
+    let notifications = config.text_px * LINE * 2.0 * config.calendar.rows.max(1) as f32;
+    3.0 * PADDING + month_height(config) + notifications
+}
+
+/// The first day of the month `offset` months after the one `today` is in.
+fn first_of_month(today: NaiveDate, offset: i32) -> NaiveDate {
+    let months = today.year() * 12 + today.month0() as i32 + offset;
+    NaiveDate::from_ymd_opt(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1)
+        .unwrap_or(today)
+}
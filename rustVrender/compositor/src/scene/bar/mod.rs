@@ -0,0 +1,521 @@
+// The bar: a strip along the top or bottom of outputs that the compositor draws above
+// every window. It holds the pager, which shows the WM's workspaces in miniature, then
+// the taskbar, with a button per window, then widgets like the clock at the right end,
+// and turns clicks on the first two into commands for the WM; resting the pointer on
+// them previews their windows. The WM has to keep its windows out of the bar's way,
+// with a gap as tall as the bar.
+
+pub mod calendar;
+pub mod pager;
+pub mod preview;
+pub mod taskbar;
+pub mod widgets;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use qtilerugo_ipc::wm::WmCommand;
+use serde::Deserialize;
+
+use super::animation::Rect;
+use super::osd::Level;
+use super::theme::{Color, Palette};
+use super::{DrawItem, DrawSource};
+use crate::text::Font;
+use calendar::{Calendar, CalendarConfig, Notification};
+use pager::{Pager, PagerConfig, WorkspaceInfo};
+use preview::{Preview, PreviewConfig, Shown, Target, Thumbnail};
+use taskbar::{TaskWindow, Taskbar, TaskbarConfig, WindowInfo};
+use widgets::{MediaInfo, WidgetSpec, Widgets};
+
+/// Which edge of its outputs the bar runs along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum BarPosition {
+    #[default]
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    pub enabled: bool,
+    pub position: BarPosition,
+    /// In logical pixels.
+    pub height: f32,
+    /// Names of the outputs showing the bar; every output when empty.
+    pub outputs: Vec<String>,
+    pub text_px: f32,
+    pub background: Color,
+    pub foreground: Color,
+    pub pager: PagerConfig,
+    pub taskbar: TaskbarConfig,
+    /// The popup a click on the clock opens.
+    pub calendar: CalendarConfig,
+    /// The popup resting the pointer on a button or miniature opens.
+    pub preview: PreviewConfig,
+    /// Widgets at the right end, left to right, as `WidgetSpec::parse` reads them.
+    pub widgets: Vec<String>,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: BarPosition::Top,
+            height: 28.0,
+            outputs: Vec::new(),
+            text_px: 13.0,
+            background: Color::base(0x01, 0.9),
+            foreground: Color::base(0x05, 1.0),
+            pager: PagerConfig::default(),
+            taskbar: TaskbarConfig::default(),
+            calendar: CalendarConfig::default(),
+            preview: PreviewConfig::default(),
+            widgets: vec!["clock".to_string()],
+        }
+    }
+}
+
+impl BarConfig {
+    /// Gives its colors, and those of the pager, taskbar, calendar, and previews,
+    /// `palette`'s.
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.background.retheme(palette);
+        self.foreground.retheme(palette);
+        self.pager.retheme(palette);
+        self.taskbar.retheme(palette);
+        self.calendar.retheme(palette);
+        self.preview.retheme(palette);
+    }
+}
+
+/// The pointer buttons the bar takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarButton {
+    Left,
+    Middle,
+    Right,
+}
+
+/// What a click on the bar asks the WM to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BarAction {
+    SwitchWorkspace(String),
+    MoveToWorkspace {
+        window: u32,
+        workspace: String,
+    },
+    FocusWindow(u32),
+    CloseWindow(u32),
+    /// From the media widget, for the player the WM follows.
+    MediaPlayPause,
+    MediaNext,
+    MediaPrevious,
+}
+
+impl BarAction {
+    /// The command the WM is sent for it.
+    pub fn command(&self) -> WmCommand {
+        match self {
+            Self::SwitchWorkspace(workspace) => WmCommand::SwitchWorkspace(workspace.clone()),
+            Self::MoveToWorkspace { window, workspace } => WmCommand::MoveToWorkspace {
+                window: *window,
+                workspace: workspace.clone(),
+            },
+            Self::FocusWindow(window) => WmCommand::FocusWindow(*window),
+            Self::CloseWindow(window) => WmCommand::CloseWindow(*window),
+            Self::MediaPlayPause => WmCommand::MediaPlayPause,
+            Self::MediaNext => WmCommand::MediaNext,
+            Self::MediaPrevious => WmCommand::MediaPrevious,
+        }
+    }
+}
+
+/// The bar, and what its widgets show.
+#[derive(Debug, Default)]
+pub struct Bar {
+    config: BarConfig,
+    pager: Pager,
+    taskbar: Taskbar,
+    widgets: Widgets,
+    calendar: Calendar,
+    preview: Preview,
+    /// What a press on a widget asks, sent once the button comes up.
+    clicked: Option<BarAction>,
+}
+
+impl Bar {
+    pub fn config(&self) -> &BarConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BarConfig) {
+        // Config::validate turned down widgets that don't parse.
+        let specs = config
+            .widgets
+            .iter()
+            .filter_map(|spec| WidgetSpec::parse(spec).ok())
+            .collect();
+        self.widgets.set_specs(specs);
+        self.config = config;
+        self.pager.cancel();
+        self.taskbar.cancel();
+        self.clicked = None;
+        self.calendar.close();
+        self.preview.close();
+    }
+
+    /// Gives the bar and its widgets the colors of `palette`, keeping what they show.
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.config.retheme(palette);
+        self.widgets.retheme(palette);
+    }
+
+    /// Has the widgets due at `now` read again, in `font`, and opens the preview the
+    /// pointer rested long enough for; returns whether the bar changed.
+    pub fn update(&mut self, now: Instant, font: Option<&Arc<Font>>) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let opened = self.preview.update(&self.config.preview, now);
+        self.widgets.update(now, self.config.text_px, font) || opened
+    }
+
+    /// Shows the volume or brightness the WM reported in the widgets showing them.
+    pub fn set_level(&mut self, level: Level) {
+        self.widgets.set_level(level);
+    }
+
+    /// Shows the track of the WM's media player, or that it has none, in the widgets
+    /// showing it.
+    pub fn set_media(&mut self, info: Option<MediaInfo>) {
+        self.widgets.set_media(info);
+    }
+
+    /// Keeps `notification` in the history the calendar popup lists.
+    pub fn add_notification(&mut self, notification: Notification) {
+        self.calendar.push(&self.config.calendar, notification);
+    }
+
+    /// Takes a press at `pointer`, in root window coordinates, while the calendar
+    /// popup is open, putting it away unless it is on it; returns whether it was open,
+    /// in which case the press is the popup's.
+    pub fn calendar_pressed(&mut self, pointer: [f32; 2]) -> bool {
+        self.calendar.press(pointer)
+    }
+
+    pub fn calendar_shown(&self) -> bool {
+        self.calendar.shown()
+    }
+
+    /// Puts the calendar popup away; returns whether it was open.
+    pub fn close_calendar(&mut self) -> bool {
+        self.calendar.close()
+    }
+
+    /// Scrolls the calendar popup by `lines` if it is under `pointer`; returns whether
+    /// it was.
+    pub fn calendar_scrolled(&mut self, pointer: [f32; 2], lines: f32) -> bool {
+        self.calendar.scroll(&self.config, pointer, lines)
+    }
+
+    /// Replaces the workspaces the pager shows.
+    pub fn set_workspaces(&mut self, workspaces: Vec<WorkspaceInfo>) {
+        self.pager.set_workspaces(workspaces);
+    }
+
+    /// Takes what the bridge reports of a window; returns whether the bar changed.
+    pub fn set_window_info(&mut self, info: WindowInfo) -> bool {
+        self.taskbar.set_info(info)
+    }
+
+    /// The title the bridge reported for `window`, if any.
+    pub fn title(&self, window: u32) -> Option<&str> {
+        self.taskbar.title(window)
+    }
+
+    /// The WM_CLASS class and instance the bridge reported for `window`, if any.
+    pub fn class(&self, window: u32) -> Option<(&str, &str)> {
+        self.taskbar.class(window)
+    }
+
+    /// Forgets what the bridge reported of `window`, which went away.
+    pub fn forget(&mut self, window: u32) {
+        self.taskbar.forget(window);
+        self.preview.forget(window);
+    }
+
+    /// Where the WM lists `window` among the windows of workspace `index`; windows it
+    /// doesn't list there come last.
+    pub fn listed_at(&self, index: Option<i32>, window: u32) -> usize {
+        self.pager.listed_at(index, window)
+    }
+
+    /// Records where the WM placed `window`, in root window coordinates.
+    pub fn saw(&mut self, window: u32, rect: Rect) {
+        self.pager.saw(window, rect);
+    }
+
+    /// Whether the output called `name` shows the bar.
+    pub fn shown_on(&self, name: &str) -> bool {
+        self.config.enabled
+            && (self.config.outputs.is_empty() || self.config.outputs.iter().any(|o| o == name))
+    }
+
+    /// Where the bar is on an output at `bounds`, in the same coordinates, which are
+    /// `scale` times root window coordinates.
+    pub fn rect(&self, bounds: Rect, scale: f32) -> Rect {
+        let [x, y, width, height] = bounds;
+        let bar_height = (self.config.height * scale).min(height);
+        let top = match self.config.position {
+            BarPosition::Top => y,
+            BarPosition::Bottom => y + height - bar_height,
+        };
+        [x, top, width, bar_height]
+    }
+
+    /// Takes a press of `button` at `pointer`, in root window coordinates, on an
+    /// output at `bounds` whose taskbar shows `windows`; returns whether it landed on
+    /// the bar.
+    pub fn press(
+        &mut self,
+        pointer: [f32; 2],
+        bounds: Rect,
+        button: BarButton,
+        windows: &[TaskWindow],
+    ) -> bool {
+        let rect = self.rect(bounds, 1.0);
+        if !contains(rect, pointer) {
+            return false;
+        }
+        self.preview.close();
+        let (pager, taskbar, widgets) = self.split(rect, bounds, 1.0);
+        if contains(widgets, pointer) {
+            match self.widgets.clock_at(widgets, pointer) {
+                Some(clock) if button == BarButton::Left && self.config.calendar.enabled => {
+                    self.calendar.toggle(&self.config, rect, bounds, clock);
+                }
+                _ => self.clicked = self.widgets.press(widgets, pointer, button),
+            }
+        } else if self.config.pager.enabled && button == BarButton::Left && contains(pager, pointer)
+        {
+            self.pager.press(&self.config.pager, rect, bounds, pointer);
+        } else if self.config.taskbar.enabled {
+            let config = &self.config.taskbar;
+            self.taskbar
+                .press(config, taskbar, windows, pointer, button);
+        }
+        true
+    }
+
+    /// Follows the pointer to `pointer` at `now`, over the bar of an output at `bounds`
+    /// whose taskbar shows `windows` if `under` has them; returns whether the bar has
+    /// to be drawn again.
+    pub fn pointer_moved(
+        &mut self,
+        pointer: [f32; 2],
+        under: Option<(Rect, &[TaskWindow])>,
+        now: Instant,
+    ) -> bool {
+        let dragging = self.pager.moved(pointer);
+        let Some((bounds, windows)) = under else {
+            return self.preview.close() || dragging;
+        };
+        let target = self.hovered(pointer, bounds, windows);
+        let closed = self
+            .preview
+            .hover(target, self.rect(bounds, 1.0), bounds, now);
+        closed || dragging
+    }
+
+    /// The button or miniature at `pointer` with a preview, on an output at `bounds`
+    /// whose taskbar shows `windows`.
+    fn hovered(
+        &self,
+        pointer: [f32; 2],
+        bounds: Rect,
+        windows: &[TaskWindow],
+    ) -> Option<(Target, Rect)> {
+        if !self.config.preview.enabled || self.pressed() || self.calendar.shown() {
+            return None;
+        }
+        let rect = self.rect(bounds, 1.0);
+        if !contains(rect, pointer) {
+            return None;
+        }
+        let (pager, taskbar, _) = self.split(rect, bounds, 1.0);
+        if self.config.pager.enabled && contains(pager, pointer) {
+            let (index, slot) = self.pager.at(&self.config.pager, rect, bounds, pointer)?;
+            Some((Target::Workspace(index), slot))
+        } else if self.config.taskbar.enabled && contains(taskbar, pointer) {
+            let (window, slot) = self
+                .taskbar
+                .at(&self.config.taskbar, taskbar, windows, pointer)?;
+            Some((Target::Window(window), slot))
+        } else {
+            None
+        }
+    }
+
+    /// Whether a press on the bar waits for its release.
+    pub fn pressed(&self) -> bool {
+        self.pager.pressed()
+            || self.taskbar.pressed()
+            || self.clicked.is_some()
+            || self.calendar.held()
+    }
+
+    /// Ends the press on the bar of an output at `bounds` with the button released at
+    /// `pointer`, while its taskbar shows `windows`.
+    pub fn release(
+        &mut self,
+        pointer: [f32; 2],
+        bounds: Rect,
+        windows: &[TaskWindow],
+    ) -> Option<BarAction> {
+        if self.calendar.release() {
+            return None;
+        }
+        if self.pager.pressed() {
+            return self.pager.release(&self.config.pager, pointer);
+        }
+        if let Some(action) = self.clicked.take() {
+            return Some(action);
+        }
+        let (_, taskbar, _) = self.split(self.rect(bounds, 1.0), bounds, 1.0);
+        self.taskbar
+            .release(&self.config.taskbar, taskbar, windows, pointer)
+    }
+
+    /// The bar on an output at `bounds`, in root window coordinates, in the pixels of
+    /// the output at `scale`, with the pointer at `pointer`, while the output shows
+    /// workspace `current` and `windows` on it.
+    pub fn items(
+        &self,
+        bounds: Rect,
+        scale: f32,
+        current: Option<i32>,
+        windows: &[TaskWindow],
+        pointer: Option<[f32; 2]>,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let [x, y, width, height] = bounds;
+        let rect = self.rect([0.0, 0.0, width * scale, height * scale], scale);
+        let mut items = vec![solid(rect, self.config.background.get(), 0.0, 0.0)];
+        if self.config.pager.enabled {
+            let view = pager::View {
+                bar: rect,
+                bounds,
+                scale,
+                current,
+                pointer: pointer.map(|[px, py]| [(px - x) * scale, (py - y) * scale]),
+            };
+            items.extend(self.pager.items(&self.config, &view, font));
+        }
+        let (_, taskbar, widgets) = self.split(rect, bounds, scale);
+        if self.config.taskbar.enabled {
+            let view = taskbar::View {
+                rect: taskbar,
+                scale,
+                windows,
+            };
+            items.extend(self.taskbar.items(&self.config, &view, font));
+        }
+        items.extend(self.widgets.items(&self.config, widgets, scale, font));
+        items.extend(self.calendar.items(&self.config, bounds, scale, font));
+        items
+    }
+
+    /// The preview open on the output at `bounds`, in the pixels of the output at
+    /// `scale`, with windows drawn as `thumbnail` has them.
+    pub fn preview_items(
+        &self,
+        bounds: Rect,
+        scale: f32,
+        font: Option<&Arc<Font>>,
+        thumbnail: impl Fn(u32) -> Option<Thumbnail>,
+    ) -> Vec<DrawItem> {
+        let Some(target) = self.preview.shown(bounds) else {
+            return Vec::new();
+        };
+        let shown = match target {
+            Target::Window(window) => {
+                let Some(thumbnail) = thumbnail(window) else {
+                    return Vec::new();
+                };
+                Shown {
+                    bounds,
+                    scale,
+                    label: self
+                        .taskbar
+                        .title(window)
+                        .map_or_else(|| format!("{:#x}", window), str::to_string),
+                    size: thumbnail.size,
+                    thumbnails: vec![(Some(thumbnail), [0.0, 0.0, 1.0, 1.0])],
+                }
+            }
+            Target::Workspace(index) => {
+                let Some(name) = self.pager.name(index) else {
+                    return Vec::new();
+                };
+                let thumbnails = self
+                    .pager
+                    .placed(index, bounds)
+                    .into_iter()
+                    .map(|(window, rect)| (thumbnail(window), rect))
+                    .collect();
+                Shown {
+                    bounds,
+                    scale,
+                    label: name.to_string(),
+                    size: [bounds[2], bounds[3]],
+                    thumbnails,
+                }
+            }
+        };
+        self.preview.items(&self.config, shown, font)
+    }
+
+    /// The parts of `bar` the pager, the taskbar, and the widgets have, at `scale`.
+    fn split(&self, bar: Rect, bounds: Rect, scale: f32) -> (Rect, Rect, Rect) {
+        let [x, y, width, height] = bar;
+        let spacing = self.config.pager.spacing * scale;
+        let pager = if self.config.pager.enabled {
+            self.pager.width(bar, bounds, spacing).min(width)
+        } else {
+            0.0
+        };
+        let widgets = self.widgets.width(scale).min(width - pager);
+        // The taskbar's buttons stand as far from the bar's edges as the miniatures.
+        let inset = spacing.max(1.0);
+        let taskbar = [
+            x + pager + inset,
+            y + inset,
+            (width - pager - widgets - 2.0 * inset).max(0.0),
+            (height - 2.0 * inset).max(0.0),
+        ];
+        (
+            [x, y, pager, height],
+            taskbar,
+            [x + width - widgets, y, widgets, height],
+        )
+    }
+}
+
+fn contains(rect: Rect, [x, y]: [f32; 2]) -> bool {
+    let [left, top, width, height] = rect;
+    x >= left && y >= top && x < left + width && y < top + height
+}
+
+/// A rect in `color`, which isn't premultiplied.
+fn solid(rect: Rect, color: [f32; 4], corner_radius: f32, border: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border,
+    }
+}
@@ -0,0 +1,332 @@
+// The pager: a miniature of every workspace the WM reports, outlining where the WM
+// placed its windows. Windows of workspaces not shown keep the outlines they had when
+// they were last placed. A click on a miniature has the WM switch to its workspace,
+// and dragging a window's outline onto another miniature has it move the window there.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::{contains, solid, BarAction, BarConfig};
+use crate::scene::animation::Rect;
+use crate::scene::theme::{Color, Palette};
+use crate::scene::DrawItem;
+use crate::text::{self, Font};
+
+pub use qtilerugo_ipc::renderer::WorkspaceInfo;
+
+/// Presses that move less than this far, in logical pixels, are clicks.
+const DRAG_THRESHOLD: f32 = 4.0;
+/// Widths at scale 1, in output pixels.
+const OUTLINE: f32 = 1.0;
+const TARGET: f32 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PagerConfig {
+    pub enabled: bool,
+    /// Between miniatures and around them, in logical pixels.
+    pub spacing: f32,
+    /// Shows workspace names in their miniatures.
+    pub names: bool,
+    pub workspace: Color,
+    /// The miniature of the workspace the output shows.
+    pub current: Color,
+    pub window: Color,
+}
+
+impl Default for PagerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spacing: 3.0,
+            names: true,
+            workspace: Color::base(0x02, 1.0),
+            current: Color::base(0x0D, 1.0),
+            window: Color::base(0x06, 0.9),
+        }
+    }
+}
+
+impl PagerConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        for color in [&mut self.workspace, &mut self.current, &mut self.window] {
+            color.retheme(palette);
+        }
+    }
+}
+
+/// Where the pager is drawn.
+pub(super) struct View {
+    /// The bar, in output pixels.
+    pub bar: Rect,
+    /// The output, in root window coordinates.
+    pub bounds: Rect,
+    pub scale: f32,
+    /// The workspace the output shows.
+    pub current: Option<i32>,
+    /// In output pixels.
+    pub pointer: Option<[f32; 2]>,
+}
+
+#[derive(Debug)]
+struct Press {
+    workspace: usize,
+    /// The window whose outline was grabbed.
+    window: Option<u32>,
+    /// Where the button went down, in root window coordinates.
+    at: [f32; 2],
+    /// The bar and the output pressed on, in root window coordinates.
+    bar: Rect,
+    bounds: Rect,
+    /// Whether the pointer went far enough to drag.
+    moved: bool,
+}
+
+/// The WM's workspaces and their windows, and the press on one of them.
+#[derive(Debug, Default)]
+pub struct Pager {
+    workspaces: Vec<WorkspaceInfo>,
+    /// Where the WM last placed each window, in root window coordinates.
+    seen: HashMap<u32, Rect>,
+    press: Option<Press>,
+}
+
+impl Pager {
+    pub fn set_workspaces(&mut self, workspaces: Vec<WorkspaceInfo>) {
+        self.seen
+            .retain(|window, _| workspaces.iter().any(|w| w.windows.contains(window)));
+        self.workspaces = workspaces;
+        // What it pressed on may be gone, or somewhere else.
+        self.press = None;
+    }
+
+    pub fn listed_at(&self, index: Option<i32>, window: u32) -> usize {
+        index
+            .and_then(|index| self.workspaces.get(usize::try_from(index).ok()?))
+            .and_then(|workspace| workspace.windows.iter().position(|w| *w == window))
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn saw(&mut self, window: u32, rect: Rect) {
+        self.seen.insert(window, rect);
+    }
+
+    pub fn cancel(&mut self) {
+        self.press = None;
+    }
+
+    /// Takes a press at `pointer` on `bar`, on an output at `bounds`, all in root
+    /// window coordinates.
+    pub fn press(&mut self, config: &PagerConfig, bar: Rect, bounds: Rect, pointer: [f32; 2]) {
+        let miniatures = self.miniatures(bar, bounds, config.spacing);
+        let Some(workspace) = miniatures.iter().position(|m| contains(*m, pointer)) else {
+            return;
+        };
+        let window = self.workspaces[workspace]
+            .windows
+            .iter()
+            .rev()
+            .copied()
+            .find(|window| {
+                self.outline(miniatures[workspace], bounds, *window)
+                    .is_some_and(|outline| contains(outline, pointer))
+            });
+        self.press = Some(Press {
+            workspace,
+            window,
+            at: pointer,
+            bar,
+            bounds,
+            moved: false,
+        });
+    }
+
+    /// The workspace whose miniature is at `pointer` on `bar`, on an output at
+    /// `bounds`, and its miniature, all in root window coordinates.
+    pub(super) fn at(
+        &self,
+        config: &PagerConfig,
+        bar: Rect,
+        bounds: Rect,
+        pointer: [f32; 2],
+    ) -> Option<(usize, Rect)> {
+        self.miniatures(bar, bounds, config.spacing)
+            .into_iter()
+            .enumerate()
+            .find(|(_, miniature)| contains(*miniature, pointer))
+    }
+
+    pub(super) fn name(&self, index: usize) -> Option<&str> {
+        Some(self.workspaces.get(index)?.name.as_str())
+    }
+
+    /// The windows of workspace `index`, bottom first, where they were last placed on
+    /// an output at `bounds`, as fractions of it.
+    pub(super) fn placed(&self, index: usize, bounds: Rect) -> Vec<(u32, Rect)> {
+        let Some(workspace) = self.workspaces.get(index) else {
+            return Vec::new();
+        };
+        let [bx, by, bw, bh] = bounds;
+        let (bw, bh) = (bw.max(1.0), bh.max(1.0));
+        workspace
+            .windows
+            .iter()
+            .filter_map(|window| {
+                let [x, y, width, height] = *self.seen.get(window)?;
+                Some((*window, [(x - bx) / bw, (y - by) / bh, width / bw, height / bh]))
+            })
+            .collect()
+    }
+
+    /// Follows the pointer to `pointer`; returns whether a window's outline is dragged.
+    pub fn moved(&mut self, [x, y]: [f32; 2]) -> bool {
+        let Some(press) = &mut self.press else {
+            return false;
+        };
+        let [from_x, from_y] = press.at;
+        press.moved |= (x - from_x).hypot(y - from_y) >= DRAG_THRESHOLD;
+        self.dragging()
+    }
+
+    pub fn pressed(&self) -> bool {
+        self.press.is_some()
+    }
+
+    pub fn dragging(&self) -> bool {
+        self.press
+            .as_ref()
+            .is_some_and(|press| press.moved && press.window.is_some())
+    }
+
+    /// Ends the press with the button released at `pointer`: a click on a miniature
+    /// switches to its workspace, and a window dropped on another one moves there.
+    pub fn release(&mut self, config: &PagerConfig, pointer: [f32; 2]) -> Option<BarAction> {
+        let press = self.press.take()?;
+        let target = self
+            .miniatures(press.bar, press.bounds, config.spacing)
+            .iter()
+            .position(|m| contains(*m, pointer))?;
+        let workspace = self.workspaces.get(target)?.name.clone();
+        match press.window {
+            Some(window) if press.moved && target != press.workspace => {
+                Some(BarAction::MoveToWorkspace { window, workspace })
+            }
+            _ if !press.moved && target == press.workspace => {
+                Some(BarAction::SwitchWorkspace(workspace))
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn items(
+        &self,
+        bar_config: &BarConfig,
+        view: &View,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let config = &bar_config.pager;
+        let scale = view.scale;
+        let miniatures = self.miniatures(view.bar, view.bounds, config.spacing * scale);
+        // The press, if it drags a window on this output.
+        let drag = self
+            .press
+            .as_ref()
+            .filter(|press| self.dragging() && press.bounds == view.bounds);
+        let mut items = Vec::new();
+        for (i, (miniature, workspace)) in miniatures.iter().zip(&self.workspaces).enumerate() {
+            let color = if view.current == Some(i as i32) {
+                config.current.get()
+            } else {
+                config.workspace.get()
+            };
+            items.push(solid(*miniature, color, 0.0, 0.0));
+            if let Some(font) = font.filter(|_| config.names) {
+                let label = font.layout(&workspace.name, bar_config.text_px * scale);
+                if label.width <= miniature[2] {
+                    items.extend(text::draw(
+                        font,
+                        &label,
+                        miniature[0] + (miniature[2] - label.width) / 2.0,
+                        miniature[1] + (miniature[3] - label.height) / 2.0,
+                        bar_config.foreground.get(),
+                    ));
+                }
+            }
+            for window in &workspace.windows {
+                if drag.is_some_and(|press| press.window == Some(*window)) {
+                    continue;
+                }
+                if let Some(outline) = self.outline(*miniature, view.bounds, *window) {
+                    items.push(solid(outline, config.window.get(), 0.0, OUTLINE * scale));
+                }
+            }
+        }
+
+        // The dragged outline follows the pointer, over the miniature it would drop on.
+        let (Some(press), Some(pointer)) = (drag, view.pointer) else {
+            return items;
+        };
+        if let Some(target) = miniatures
+            .iter()
+            .position(|m| contains(*m, pointer))
+            .filter(|target| *target != press.workspace)
+        {
+            items.push(solid(
+                miniatures[target],
+                config.current.get(),
+                0.0,
+                TARGET * scale,
+            ));
+        }
+        let outline = press
+            .window
+            .zip(miniatures.get(press.workspace))
+            .and_then(|(window, miniature)| self.outline(*miniature, view.bounds, window));
+        if let Some([left, top, width, height]) = outline {
+            let [x, y, ..] = view.bounds;
+            let at = [(press.at[0] - x) * scale, (press.at[1] - y) * scale];
+            let offset = [pointer[0] - at[0], pointer[1] - at[1]];
+            items.push(solid(
+                [left + offset[0], top + offset[1], width, height],
+                config.window.get(),
+                0.0,
+                TARGET * scale,
+            ));
+        }
+        items
+    }
+
+    /// How much of `bar`, from its left edge, the miniatures take.
+    pub(super) fn width(&self, bar: Rect, bounds: Rect, spacing: f32) -> f32 {
+        self.miniatures(bar, bounds, spacing)
+            .last()
+            .map_or(0.0, |last| last[0] + last[2] + spacing - bar[0])
+    }
+
+    /// One rect per workspace in `bar`, shaped like the output at `bounds`.
+    fn miniatures(&self, bar: Rect, bounds: Rect, spacing: f32) -> Vec<Rect> {
+        let height = (bar[3] - 2.0 * spacing).max(1.0);
+        let width = height * bounds[2] / bounds[3].max(1.0);
+        (0..self.workspaces.len())
+            .map(|i| {
+                let left = bar[0] + spacing + i as f32 * (width + spacing);
+                [left, bar[1] + spacing, width, height]
+            })
+            .collect()
+    }
+
+    /// The outline of `window` in `miniature`, where it was last placed on an output
+    /// at `bounds`, cut off at the miniature's edges.
+    fn outline(&self, miniature: Rect, bounds: Rect, window: u32) -> Option<Rect> {
+        let [x, y, width, height] = *self.seen.get(&window)?;
+        let [mx, my, mw, mh] = miniature;
+        let (sx, sy) = (mw / bounds[2].max(1.0), mh / bounds[3].max(1.0));
+        let left = (mx + (x - bounds[0]) * sx).max(mx);
+        let top = (my + (y - bounds[1]) * sy).max(my);
+        let right = (mx + (x + width - bounds[0]) * sx).min(mx + mw);
+        let bottom = (my + (y + height - bounds[1]) * sy).min(my + mh);
+        (right > left && bottom > top).then_some([left, top, right - left, bottom - top])
+    }
+}
@@ -0,0 +1,280 @@
+// Previews: resting the pointer on a taskbar button or a pager miniature for a moment
+// opens a popup under it, or above it with the bar at the bottom, with a live
+// thumbnail of the window, or of every window of the workspace where the WM placed
+// them. Thumbnails sample the windows' own textures, so they keep up with the windows
+// as they draw, and windows without contents yet show as outlines. Moving off, or
+// pressing, puts the popup away.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use super::taskbar::fit;
+use super::{solid, BarConfig, BarPosition};
+use crate::scene::animation::Rect;
+use crate::scene::theme::{Color, Palette};
+use crate::scene::{DrawItem, DrawSource};
+use crate::text::{self, Font};
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 6.0;
+const CORNER_RADIUS: f32 = 6.0;
+/// Between the popup and the bar it opens from.
+const GAP: f32 = 4.0;
+const OUTLINE: f32 = 1.0;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    pub enabled: bool,
+    /// How long the pointer rests on a button or miniature before it opens, in
+    /// milliseconds.
+    pub delay_ms: u64,
+    /// The longest side of a thumbnail, in logical pixels; windows smaller than that
+    /// aren't enlarged.
+    pub size: f32,
+    /// The title or workspace name under the thumbnail is in the bar's foreground.
+    pub background: Color,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay_ms: 500,
+            size: 240.0,
+            background: Color::base(0x00, 0.96),
+        }
+    }
+}
+
+impl PreviewConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.background.retheme(palette);
+    }
+}
+
+/// What a preview shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Window(u32),
+    /// The workspace at this index of those the WM reported.
+    Workspace(usize),
+}
+
+/// A window's texture, as the scene draws it, and its size in root window pixels.
+pub struct Thumbnail {
+    pub source: DrawSource,
+    pub size: [f32; 2],
+}
+
+/// What the pointer rests on.
+#[derive(Debug, Clone, Copy)]
+struct Hover {
+    target: Target,
+    /// The button or miniature, the bar, and the output, in root window coordinates.
+    slot: Rect,
+    bar: Rect,
+    bounds: Rect,
+    since: Instant,
+    shown: bool,
+}
+
+/// What a shown preview is of, for the bar to fill in.
+pub(super) struct Shown {
+    pub bounds: Rect,
+    pub scale: f32,
+    pub label: String,
+    /// The size of what is shown, in root window pixels: the window, or the output.
+    pub size: [f32; 2],
+    /// The thumbnails, and where they go as fractions of the preview.
+    pub thumbnails: Vec<(Option<Thumbnail>, Rect)>,
+}
+
+/// The button or miniature the pointer rests on, and whether its preview is open.
+#[derive(Debug, Default)]
+pub struct Preview {
+    hover: Option<Hover>,
+}
+
+impl Preview {
+    /// Follows the pointer onto `target` at `slot`, on the bar at `bar` of an output at
+    /// `bounds`, or off any; returns whether a preview that was open closed.
+    pub(super) fn hover(
+        &mut self,
+        target: Option<(Target, Rect)>,
+        bar: Rect,
+        bounds: Rect,
+        now: Instant,
+    ) -> bool {
+        let Some((target, slot)) = target else {
+            return self.close();
+        };
+        if self
+            .hover
+            .is_some_and(|hover| hover.target == target && hover.bounds == bounds)
+        {
+            return false;
+        }
+        let hover = Hover {
+            target,
+            slot,
+            bar,
+            bounds,
+            since: now,
+            shown: false,
+        };
+        self.hover.replace(hover).is_some_and(|hover| hover.shown)
+    }
+
+    /// Opens the preview once the pointer rested long enough by `now`; returns whether
+    /// it did.
+    pub(super) fn update(&mut self, config: &PreviewConfig, now: Instant) -> bool {
+        let Some(hover) = &mut self.hover else {
+            return false;
+        };
+        let due = !hover.shown && now >= hover.since + Duration::from_millis(config.delay_ms);
+        hover.shown |= due;
+        due
+    }
+
+    /// Forgets what the pointer rests on; returns whether its preview was open.
+    pub(super) fn close(&mut self) -> bool {
+        self.hover.take().is_some_and(|hover| hover.shown)
+    }
+
+    /// Puts away the preview of `window`, which went away.
+    pub(super) fn forget(&mut self, window: u32) {
+        if self
+            .hover
+            .is_some_and(|hover| hover.target == Target::Window(window))
+        {
+            self.hover = None;
+        }
+    }
+
+    /// What the open preview on the output at `bounds` shows.
+    pub(super) fn shown(&self, bounds: Rect) -> Option<Target> {
+        self.hover
+            .filter(|hover| hover.shown && hover.bounds == bounds)
+            .map(|hover| hover.target)
+    }
+
+    /// The open preview of `shown`, in the pixels of its output.
+    pub(super) fn items(
+        &self,
+        config: &BarConfig,
+        shown: Shown,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let Some(hover) = self.hover.filter(|hover| hover.shown) else {
+            return Vec::new();
+        };
+        let preview = &config.preview;
+        let scale = shown.scale;
+        let [left, top, width, height] = shown.bounds;
+        let [shown_width, shown_height] = [shown.size[0].max(1.0), shown.size[1].max(1.0)];
+        let fits = (preview.size / shown_width.max(shown_height)).min(1.0);
+        let thumb = [shown_width * fits, shown_height * fits];
+        let line = font.map_or(0.0, |_| config.text_px * 1.5);
+        let popup_width = (thumb[0] + 2.0 * PADDING).min(width);
+        let popup_height = thumb[1] + 2.0 * PADDING + line;
+        let [slot_x, _, slot_width, _] = hover.slot;
+        let x = (slot_x + slot_width / 2.0 - popup_width / 2.0)
+            .min(left + width - popup_width - GAP)
+            .max(left + GAP);
+        let y = match config.position {
+            BarPosition::Top => hover.bar[1] + hover.bar[3] + GAP,
+            BarPosition::Bottom => hover.bar[1] - popup_height - GAP,
+        }
+        .clamp(top, (top + height - popup_height).max(top));
+
+        let px_x = ((x - left) * scale).round();
+        let px_y = ((y - top) * scale).round();
+        let mut items = vec![solid(
+            [px_x, px_y, popup_width * scale, popup_height * scale],
+            preview.background.get(),
+            CORNER_RADIUS * scale,
+            0.0,
+        )];
+        let frame = [
+            px_x + PADDING * scale,
+            px_y + PADDING * scale,
+            (popup_width - 2.0 * PADDING).max(0.0) * scale,
+            thumb[1] * scale,
+        ];
+        if matches!(hover.target, Target::Workspace(_)) {
+            items.push(solid(frame, config.pager.workspace.get(), 0.0, 0.0));
+        }
+        for (thumbnail, [fx, fy, fw, fh]) in shown.thumbnails {
+            let rect = [
+                frame[0] + fx * frame[2],
+                frame[1] + fy * frame[3],
+                fw * frame[2],
+                fh * frame[3],
+            ];
+            let Some((rect, part)) = clip(rect, frame) else {
+                continue;
+            };
+            items.push(match thumbnail.map(|thumbnail| thumbnail.source) {
+                Some(DrawSource::Window {
+                    id,
+                    serial,
+                    contents,
+                    frozen,
+                    ..
+                }) => DrawItem {
+                    rect,
+                    color: [1.0; 4],
+                    source: DrawSource::Window {
+                        id,
+                        serial,
+                        contents,
+                        frozen,
+                        part,
+                    },
+                    corner_radius: 0.0,
+                    border: 0.0,
+                },
+                _ => solid(rect, config.pager.window.get(), 0.0, OUTLINE * scale),
+            });
+        }
+        if let Some(font) = font {
+            let px = config.text_px * scale;
+            if let Some(label) = fit(font, &shown.label, px, frame[2]) {
+                let label = font.layout(&label, px);
+                let label_top = frame[1] + frame[3] + (line * scale - label.height) / 2.0;
+                items.extend(text::draw(
+                    font,
+                    &label,
+                    (frame[0] + (frame[2] - label.width) / 2.0).round(),
+                    label_top.round(),
+                    config.foreground.get(),
+                ));
+            }
+        }
+        items
+    }
+}
+
+/// `rect` cut off at the edges of `within`, with the part of it that is left, as
+/// fractions of it.
+fn clip(rect: Rect, within: Rect) -> Option<(Rect, [f32; 4])> {
+    let [x, y, width, height] = rect;
+    let [wx, wy, ww, wh] = within;
+    let left = x.max(wx);
+    let top = y.max(wy);
+    let right = (x + width).min(wx + ww);
+    let bottom = (y + height).min(wy + wh);
+    if right <= left || bottom <= top || width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    let part = [
+        (left - x) / width,
+        (top - y) / height,
+        (right - left) / width,
+        (bottom - top) / height,
+    ];
+    Some(([left, top, right - left, bottom - top], part))
+}
@@ -0,0 +1,332 @@
+// The taskbar: one button per window on the workspace an output shows, with the
+// window's icon and as much of its title as fits, as the bridge reports them. Clicking
+// a button has the WM focus its window, and a middle click has it close the window.
+// Windows asking for attention are highlighted until they stop.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::{contains, solid, BarAction, BarButton, BarConfig};
+use crate::scene::animation::Rect;
+use crate::scene::theme::{Color, Palette};
+use crate::scene::wallpaper::WALLPAPER_ID_BASE;
+use crate::scene::{DrawItem, DrawSource, WindowContents, WHOLE_CONTENTS};
+use crate::text::{self, Font};
+
+pub use qtilerugo_ipc::renderer::{IconData, WindowInfo};
+
+/// Texture ids of window icons start here, below those of wallpapers.
+pub const ICON_ID_BASE: u32 = 0xfff0_0000;
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 4.0;
+const CORNER_RADIUS: f32 = 4.0;
+/// Between buttons, and between the icon and the title.
+const SPACING: f32 = 4.0;
+const ELLIPSIS: char = '…';
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskbarConfig {
+    pub enabled: bool,
+    /// Widest a button gets, in logical pixels; they narrow to fit.
+    pub max_width: f32,
+    pub button: Color,
+    pub focused: Color,
+    pub urgent: Color,
+}
+
+impl Default for TaskbarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_width: 220.0,
+            button: Color::base(0x02, 1.0),
+            focused: Color::base(0x03, 1.0),
+            urgent: Color::base(0x09, 1.0),
+        }
+    }
+}
+
+impl TaskbarConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        for color in [&mut self.button, &mut self.focused, &mut self.urgent] {
+            color.retheme(palette);
+        }
+    }
+}
+
+/// A window a button is shown for, in the order buttons are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskWindow {
+    pub window: u32,
+    pub focused: bool,
+}
+
+#[derive(Debug, Default)]
+struct Client {
+    title: String,
+    class: String,
+    instance: String,
+    urgent: bool,
+    icon: Option<(u32, WindowContents)>,
+}
+
+/// Where the taskbar is drawn.
+pub(super) struct View<'a> {
+    /// The part of the bar it has, in output pixels.
+    pub rect: Rect,
+    pub scale: f32,
+    pub windows: &'a [TaskWindow],
+}
+
+/// What the bridge reported of every window, and the button pressed.
+#[derive(Debug, Default)]
+pub struct Taskbar {
+    clients: HashMap<u32, Client>,
+    press: Option<(u32, BarButton)>,
+}
+
+impl Taskbar {
+    /// Takes what the bridge reports of a window; returns whether that changes what is
+    /// drawn.
+    pub fn set_info(&mut self, info: WindowInfo) -> bool {
+        let client = self.clients.entry(info.window).or_default();
+        let mut changed = false;
+        if let Some(title) = info.title.filter(|title| *title != client.title) {
+            client.title = title;
+            changed = true;
+        }
+        if let Some(class) = info.class.filter(|class| *class != client.class) {
+            client.class = class;
+            changed = true;
+        }
+        if let Some(instance) = info.instance.filter(|instance| *instance != client.instance) {
+            client.instance = instance;
+            changed = true;
+        }
+        if let Some(urgent) = info.urgent.filter(|urgent| *urgent != client.urgent) {
+            client.urgent = urgent;
+            changed = true;
+        }
+        if let Some(icon) = info.icon {
+            client.icon = icon_contents(&icon).map(|contents| (next_icon_id(), contents));
+            changed = true;
+        }
+        changed
+    }
+
+    pub fn title(&self, window: u32) -> Option<&str> {
+        let title = &self.clients.get(&window)?.title;
+        Some(title.as_str()).filter(|title| !title.is_empty())
+    }
+
+    pub fn class(&self, window: u32) -> Option<(&str, &str)> {
+        let client = self.clients.get(&window)?;
+        Some((client.class.as_str(), client.instance.as_str()))
+    }
+
+    pub fn forget(&mut self, window: u32) {
+        self.clients.remove(&window);
+        if self.press.is_some_and(|(pressed, _)| pressed == window) {
+            self.press = None;
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.press = None;
+    }
+
+    /// Takes a press of `button` at `pointer` in `rect`, with the buttons of `windows`.
+    pub fn press(
+        &mut self,
+        config: &TaskbarConfig,
+        rect: Rect,
+        windows: &[TaskWindow],
+        pointer: [f32; 2],
+        button: BarButton,
+    ) {
+        self.press = self
+            .at(config, rect, windows, pointer)
+            .map(|(window, _)| (window, button));
+    }
+
+    /// The window whose button is at `pointer` in `rect`, with the buttons of
+    /// `windows`, and its button.
+    pub(super) fn at(
+        &self,
+        config: &TaskbarConfig,
+        rect: Rect,
+        windows: &[TaskWindow],
+        pointer: [f32; 2],
+    ) -> Option<(u32, Rect)> {
+        buttons(config, rect, windows.len(), 1.0)
+            .into_iter()
+            .zip(windows)
+            .find(|(rect, _)| contains(*rect, pointer))
+            .map(|(rect, task)| (task.window, rect))
+    }
+
+    pub fn pressed(&self) -> bool {
+        self.press.is_some()
+    }
+
+    /// Ends the press at `pointer`: clicks on a button focus its window, and middle
+    /// clicks close it, if the button is still under the pointer.
+    pub fn release(
+        &mut self,
+        config: &TaskbarConfig,
+        rect: Rect,
+        windows: &[TaskWindow],
+        pointer: [f32; 2],
+    ) -> Option<BarAction> {
+        let (window, button) = self.press.take()?;
+        let (rect, _) = buttons(config, rect, windows.len(), 1.0)
+            .into_iter()
+            .zip(windows)
+            .find(|(_, task)| task.window == window)?;
+        if !contains(rect, pointer) {
+            return None;
+        }
+        Some(match button {
+            BarButton::Left => BarAction::FocusWindow(window),
+            BarButton::Middle => BarAction::CloseWindow(window),
+            BarButton::Right => return None,
+        })
+    }
+
+    pub(super) fn items(
+        &self,
+        bar_config: &BarConfig,
+        view: &View,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let config = &bar_config.taskbar;
+        let scale = view.scale;
+        let padding = (PADDING * scale).round();
+        let mut items = Vec::new();
+        let rects = buttons(config, view.rect, view.windows.len(), scale);
+        for (rect, task) in rects.into_iter().zip(view.windows) {
+            let client = self.clients.get(&task.window);
+            let color = if client.is_some_and(|client| client.urgent) {
+                config.urgent.get()
+            } else if task.focused {
+                config.focused.get()
+            } else {
+                config.button.get()
+            };
+            items.push(solid(rect, color, CORNER_RADIUS * scale, 0.0));
+            let [x, y, width, height] = rect;
+            let mut left = x + padding;
+            let right = x + width - padding;
+            let icon_size = height - 2.0 * padding;
+            if let Some((id, contents)) = client.and_then(|client| client.icon.as_ref()) {
+                if left + icon_size <= right {
+                    items.push(DrawItem {
+                        rect: [left, y + padding, icon_size, icon_size],
+                        color: [1.0; 4],
+                        source: DrawSource::Window {
+                            id: *id,
+                            serial: 0,
+                            contents: contents.clone(),
+                            frozen: false,
+                            part: WHOLE_CONTENTS,
+                        },
+                        corner_radius: 0.0,
+                        border: 0.0,
+                    });
+                    left += icon_size + SPACING * scale;
+                }
+            }
+            let Some(font) = font else {
+                continue;
+            };
+            let title = match client {
+                Some(client) if !client.title.is_empty() => client.title.clone(),
+                _ => format!("{:#x}", task.window),
+            };
+            let px = bar_config.text_px * scale;
+            if let Some(label) = fit(font, &title, px, right - left) {
+                let label = font.layout(&label, px);
+                items.extend(text::draw(
+                    font,
+                    &label,
+                    left,
+                    (y + (height - label.height) / 2.0).round(),
+                    bar_config.foreground.get(),
+                ));
+            }
+        }
+        items
+    }
+}
+
+/// One rect per button, left to right in `rect`, at `scale`.
+fn buttons(config: &TaskbarConfig, rect: Rect, count: usize, scale: f32) -> Vec<Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let spacing = SPACING * scale;
+    let [x, y, width, height] = rect;
+    let available = (width - spacing * (count - 1) as f32) / count as f32;
+    let button_width = available.min(config.max_width * scale).floor();
+    if button_width <= 0.0 {
+        return Vec::new();
+    }
+    (0..count)
+        .map(|i| {
+            [
+                x + i as f32 * (button_width + spacing),
+                y,
+                button_width,
+                height,
+            ]
+        })
+        .collect()
+}
+
+/// `title`, cut short with an ellipsis if it is wider than `width` at `px`; `None` if
+/// not even the ellipsis fits.
+pub(super) fn fit(font: &Font, title: &str, px: f32, width: f32) -> Option<String> {
+    if font.layout(title, px).width <= width {
+        return Some(title.to_string());
+    }
+    let mut chars: Vec<char> = title.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let cut: String = chars.iter().chain([&ELLIPSIS]).collect();
+        if font.layout(&cut, px).width <= width {
+            return Some(cut);
+        }
+    }
+    (font.layout(&ELLIPSIS.to_string(), px).width <= width).then(|| ELLIPSIS.to_string())
+}
+
+/// `icon` as premultiplied BGRA, like window contents, if its size matches its pixels.
+fn icon_contents(icon: &IconData) -> Option<WindowContents> {
+    let pixels = icon.width as usize * icon.height as usize;
+    if pixels == 0 || icon.argb.len() != pixels {
+        return None;
+    }
+    let mut data = Vec::with_capacity(pixels * 4);
+    for argb in &icon.argb {
+        let [b, g, r, a] = argb.to_le_bytes();
+        let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+        data.extend([premultiply(b), premultiply(g), premultiply(r), a]);
+    }
+    Some(WindowContents {
+        width: icon.width,
+        height: icon.height,
+        data: Arc::new(data),
+        opaque: false,
+    })
+}
+
+/// A texture id no other icon has, so a new icon is uploaded again.
+fn next_icon_id() -> u32 {
+    static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+    ICON_ID_BASE + NEXT_ID.fetch_add(1, Ordering::Relaxed) % (WALLPAPER_ID_BASE - ICON_ID_BASE)
+}
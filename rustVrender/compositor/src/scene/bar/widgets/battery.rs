@@ -0,0 +1,101 @@
+// The battery widget: its charge, and whether it is charging, from
+// /sys/class/power_supply, or from UPower on systems whose batteries aren't there.
+// UPower is asked by running upower on a thread, so the frame it would hold up
+// isn't.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+/// UPower's summary of every battery.
+const DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+
+#[derive(Debug)]
+pub(super) struct Battery {
+    /// The power supply asked for, or the first battery there.
+    device: Option<String>,
+    /// The last reading from UPower, and the one on its way.
+    upower: Option<String>,
+    asking: Option<Receiver<Option<String>>>,
+}
+
+impl Battery {
+    pub(super) fn new(device: Option<String>) -> Self {
+        Self {
+            device,
+            upower: None,
+            asking: None,
+        }
+    }
+
+    pub(super) fn read(&mut self) -> Option<String> {
+        if let Some(path) = self.sysfs() {
+            let read = |name: &str| fs::read_to_string(path.join(name)).ok();
+            let capacity: u32 = read("capacity")?.trim().parse().ok()?;
+            let charging = read("status").is_some_and(|status| status.trim() == "Charging");
+            return Some(charge(capacity, charging));
+        }
+        match self.asking.as_ref().map(Receiver::try_recv) {
+            Some(Ok(read)) => {
+                self.upower = read;
+                self.asking = None;
+            }
+            Some(Err(TryRecvError::Disconnected)) => self.asking = None,
+            Some(Err(TryRecvError::Empty)) => return self.upower.clone(),
+            None => {}
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.asking = Some(receiver);
+        thread::spawn(move || {
+            let _ = sender.send(upower());
+        });
+        self.upower.clone()
+    }
+
+    /// Where the battery shown is in sysfs, if it is there.
+    fn sysfs(&self) -> Option<PathBuf> {
+        if let Some(device) = &self.device {
+            let path = Path::new(POWER_SUPPLY).join(device);
+            return path.exists().then_some(path);
+        }
+        let mut batteries: Vec<PathBuf> = fs::read_dir(POWER_SUPPLY)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                fs::read_to_string(path.join("type")).is_ok_and(|kind| kind.trim() == "Battery")
+            })
+            .collect();
+        batteries.sort();
+        batteries.into_iter().next()
+    }
+}
+
+/// What UPower reports of every battery together; `None` when there are none.
+fn upower() -> Option<String> {
+    let output = Command::new("upower")
+        .args(["-i", DISPLAY_DEVICE])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let report = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        report.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim().to_string())
+        })
+    };
+    if field("present").is_some_and(|present| present == "no") {
+        return None;
+    }
+    let percentage: f32 = field("percentage")?.trim_end_matches('%').parse().ok()?;
+    let charging = field("state").is_some_and(|state| state == "charging");
+    Some(charge(percentage.round() as u32, charging))
+}
+
+/// Like "85%", with a "+" while charging.
+fn charge(percent: u32, charging: bool) -> String {
+    format!("{}%{}", percent, if charging { "+" } else { "" })
+}
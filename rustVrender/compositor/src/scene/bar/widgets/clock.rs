@@ -0,0 +1,17 @@
+// The clock widget: the local time, formatted as strftime does.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::Local;
+
+/// Whether `format` is one the clock can show.
+pub(super) fn check(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("{:?} is not a strftime format", format));
+    }
+    Ok(())
+}
+
+/// The time now, in `format`, which `check` accepted.
+pub(super) fn now(format: &str) -> String {
+    Local::now().format(format).to_string()
+}
@@ -0,0 +1,122 @@
+// The part of Pango markup bar widgets take from scripts: <span> colors, and the
+// entities. Other tags, like <b> and <i>, are dropped, keeping their text, since the
+// bar has a single face to draw in.
+
+use super::Span;
+use crate::scene::theme::parse_hex;
+
+type Style = (Option<[f32; 4]>, Option<[f32; 4]>);
+
+/// The runs of `markup`, each in the colors of the spans around it.
+pub(super) fn parse(markup: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    // Colors and backgrounds of the open tags, innermost last.
+    let mut styles: Vec<Style> = Vec::new();
+    let mut text = String::new();
+    let mut rest = markup;
+    while let Some(c) = rest.chars().next() {
+        let style = styles.last().copied().unwrap_or_default();
+        if c == '<' {
+            let Some(end) = rest.find('>') else {
+                // Not a tag after all.
+                text.push_str(rest);
+                break;
+            };
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            push(&mut spans, &mut text, style);
+            if tag.starts_with('/') {
+                styles.pop();
+                continue;
+            }
+            let (name, attributes) = tag
+                .trim_end_matches('/')
+                .split_once(char::is_whitespace)
+                .unwrap_or((tag.trim_end_matches('/'), ""));
+            let mut style = style;
+            if name == "span" {
+                for (key, value) in attributes_of(attributes) {
+                    let color = parse_hex(&value).ok();
+                    match key {
+                        "foreground" | "fgcolor" | "color" => style.0 = color.or(style.0),
+                        "background" | "bgcolor" => style.1 = color.or(style.1),
+                        _ => {}
+                    }
+                }
+            }
+            if !tag.ends_with('/') {
+                styles.push(style);
+            }
+        } else if c == '&' {
+            let (decoded, length) = entity(rest).unwrap_or(('&', 1));
+            text.push(decoded);
+            rest = &rest[length..];
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    let style = styles.last().copied().unwrap_or_default();
+    push(&mut spans, &mut text, style);
+    spans
+}
+
+/// Ends the run of `text` so far, in `style`.
+fn push(spans: &mut Vec<Span>, text: &mut String, (color, background): Style) {
+    if !text.is_empty() {
+        spans.push(Span {
+            text: std::mem::take(text),
+            color,
+            background,
+        });
+    }
+}
+
+/// The `key="value"` pairs of a tag, with single or double quotes.
+fn attributes_of(mut text: &str) -> Vec<(&str, String)> {
+    let mut attributes = Vec::new();
+    while let Some((key, rest)) = text.split_once('=') {
+        let rest = rest.trim_start();
+        let Some(quote) = rest.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            break;
+        };
+        let value = &rest[1..end + 1];
+        let mut decoded = String::new();
+        let mut chars = value;
+        while let Some(c) = chars.chars().next() {
+            let (c, length) = match c {
+                '&' => entity(chars).unwrap_or(('&', 1)),
+                c => (c, c.len_utf8()),
+            };
+            decoded.push(c);
+            chars = &chars[length..];
+        }
+        attributes.push((key.trim(), decoded));
+        text = &rest[end + 2..];
+    }
+    attributes
+}
+
+/// The character the entity at the start of `text` stands for, and its length.
+fn entity(text: &str) -> Option<(char, usize)> {
+    let end = text.find(';').filter(|&end| end <= 10)?;
+    let name = &text[1..end];
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 1))
+}
@@ -0,0 +1,28 @@
+// The media widget, which shows what the WM reports of the MPRIS player it follows:
+// the artist and title of the track, after a symbol for whether it plays.
+
+pub use qtilerugo_ipc::renderer::{MediaInfo, Playback};
+
+/// What the widget shows of `info`, at most `max_length` characters of the track;
+/// `None` while stopped.
+pub(super) fn text(info: &MediaInfo, max_length: usize) -> Option<String> {
+    let symbol = match info.status {
+        Playback::Playing => "▶",
+        Playback::Paused => "⏸",
+        Playback::Stopped => return None,
+    };
+    // Players without metadata, like a browser tab, still show their name.
+    let track = match (info.artist.is_empty(), info.title.is_empty()) {
+        (false, false) => format!("{} - {}", info.artist, info.title),
+        (true, false) => info.title.clone(),
+        (false, true) => info.artist.clone(),
+        (true, true) => info.player.clone(),
+    };
+    let track = if track.chars().count() > max_length {
+        let kept: String = track.chars().take(max_length.saturating_sub(1)).collect();
+        format!("{}…", kept.trim_end())
+    } else {
+        track
+    };
+    Some(format!("{} {}", symbol, track))
+}
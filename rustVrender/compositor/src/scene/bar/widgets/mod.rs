@@ -0,0 +1,644 @@
+// Widgets at the right end of the bar, each showing a line of text that it reads again
+// on its own interval: the clock, the battery, CPU and memory use, network
+// throughput, the levels and track the WM reports, and what user scripts print. The bar lists them in order as strings
+// like `clock(format = "%H:%M", interval = 1s, color = #88c0d0)`: a kind, then
+// optionally its settings in parentheses.
+
+mod battery;
+mod clock;
+mod markup;
+mod media;
+mod network;
+mod script;
+mod system;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use media::{MediaInfo, Playback};
+pub use script::{Schedule, ScriptFormat};
+
+use super::{contains, solid, BarAction, BarButton, BarConfig};
+use crate::scene::animation::Rect;
+use crate::scene::osd::{Control, Level};
+use crate::scene::theme::{Color, Palette};
+use crate::scene::DrawItem;
+use crate::text::{self, Font};
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 6.0;
+const CORNER_RADIUS: f32 = 4.0;
+/// Between widgets.
+const SPACING: f32 = 4.0;
+/// Shortest interval a widget may read on.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a widget shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidgetKind {
+    /// The local time, in a strftime `format`.
+    Clock {
+        format: String,
+    },
+    /// The charge of `device` under /sys/class/power_supply, or of the first battery
+    /// there, or what UPower reports without one.
+    Battery {
+        device: Option<String>,
+    },
+    Cpu,
+    Memory,
+    /// Bytes received and sent each second on `interface`, or on all but loopback.
+    Network {
+        interface: Option<String>,
+    },
+    /// The volume or the brightness, as the WM last reported it.
+    Level(Control),
+    /// The track of the MPRIS player the WM follows, cut to `max_length` characters.
+    Media {
+        max_length: usize,
+    },
+    /// What `command` prints, as an i3blocks block.
+    Script {
+        command: String,
+        schedule: Schedule,
+        format: ScriptFormat,
+        /// Whether its text is Pango markup.
+        markup: bool,
+    },
+}
+
+/// A widget as the bar's `widgets` list gives it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WidgetSpec {
+    pub kind: WidgetKind,
+    pub interval: Duration,
+    /// Shown before what the widget reads.
+    pub label: String,
+    /// Colors, not premultiplied; the bar's foreground, and nothing behind the text,
+    /// without them.
+    pub color: Option<Color>,
+    pub background: Option<Color>,
+}
+
+impl WidgetSpec {
+    /// Parses a kind, then optionally `key = value` settings in parentheses, separated
+    /// by commas, like `battery(device = BAT1, label = "bat ")`. Values with commas,
+    /// parentheses, or spaces at the ends go in double quotes, where `\"` and `\\`
+    /// stand for a quote and a backslash.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, settings) = match spec.split_once('(') {
+            Some((kind, rest)) => {
+                let Some(settings) = rest.trim_end().strip_suffix(')') else {
+                    return Err(format!("{:?} has no closing parenthesis", spec));
+                };
+                (
+                    kind.trim(),
+                    settings_of(settings).map_err(|e| format!("{:?}: {}", spec, e))?,
+                )
+            }
+            None => (spec.trim(), Vec::new()),
+        };
+        let (mut kind, mut interval, label) = match kind {
+            "clock" => (
+                WidgetKind::Clock {
+                    format: "%a %d %b %H:%M".to_string(),
+                },
+                Duration::from_secs(1),
+                "",
+            ),
+            "battery" => (
+                WidgetKind::Battery { device: None },
+                Duration::from_secs(30),
+                "bat ",
+            ),
+            "cpu" => (WidgetKind::Cpu, Duration::from_secs(2), "cpu "),
+            "memory" => (WidgetKind::Memory, Duration::from_secs(5), "mem "),
+            "network" => (
+                WidgetKind::Network { interface: None },
+                Duration::from_secs(2),
+                "",
+            ),
+            "volume" => (
+                WidgetKind::Level(Control::Volume),
+                Duration::from_secs(1),
+                "vol ",
+            ),
+            "brightness" => (
+                WidgetKind::Level(Control::Brightness),
+                Duration::from_secs(1),
+                "light ",
+            ),
+            "media" => (
+                WidgetKind::Media { max_length: 40 },
+                Duration::from_secs(1),
+                "",
+            ),
+            "script" => (
+                WidgetKind::Script {
+                    command: String::new(),
+                    schedule: Schedule::Interval,
+                    format: ScriptFormat::Text,
+                    markup: false,
+                },
+                Duration::from_secs(5),
+                "",
+            ),
+            _ => return Err(format!(
+                "{:?} is not clock, battery, cpu, memory, network, volume, brightness, media, or script",
+                kind
+            )),
+        };
+        let mut label = label.to_string();
+        let (mut color, mut background) = (None, None);
+        for (key, value) in settings {
+            let wrong = |e: String| format!("{:?}: {} {}", spec, key, e);
+            match (key.as_str(), &mut kind) {
+                ("interval", WidgetKind::Script { schedule, .. })
+                    if value == "once" || value == "persist" =>
+                {
+                    *schedule = match value.as_str() {
+                        "once" => Schedule::Once,
+                        _ => Schedule::Persist,
+                    };
+                }
+                ("interval", _) => {
+                    interval = duration(&value).map_err(wrong)?;
+                    if interval < MIN_INTERVAL {
+                        return Err(wrong(format!("is under {:?}", MIN_INTERVAL)));
+                    }
+                }
+                ("label", _) => label = value,
+                ("color", _) => color = Some(Color::parse(&value).map_err(wrong)?),
+                ("background", _) => background = Some(Color::parse(&value).map_err(wrong)?),
+                ("format", WidgetKind::Clock { format }) => {
+                    clock::check(&value).map_err(wrong)?;
+                    *format = value;
+                }
+                ("device", WidgetKind::Battery { device }) => *device = Some(value),
+                ("interface", WidgetKind::Network { interface }) => *interface = Some(value),
+                ("max_length", WidgetKind::Media { max_length }) => {
+                    *max_length = match value.parse() {
+                        Ok(length) if length > 0 => length,
+                        _ => return Err(wrong(format!("{:?} is not a positive number", value))),
+                    };
+                }
+                ("command", WidgetKind::Script { command, .. }) => *command = value,
+                ("format", WidgetKind::Script { format, .. }) => {
+                    *format = ScriptFormat::parse(&value).map_err(wrong)?;
+                }
+                ("markup", WidgetKind::Script { markup, .. }) => {
+                    *markup = match value.as_str() {
+                        "pango" => true,
+                        "none" => false,
+                        _ => return Err(wrong(format!("{:?} is not pango or none", value))),
+                    };
+                }
+                _ => return Err(format!("{:?}: {} is not a setting of it", spec, key)),
+            }
+        }
+        if let WidgetKind::Script { command, .. } = &kind {
+            if command.trim().is_empty() {
+                return Err(format!("{:?} has no command", spec));
+            }
+        }
+        Ok(Self {
+            kind,
+            interval,
+            label,
+            color,
+            background,
+        })
+    }
+}
+
+/// Splits `key = value, ...` into its settings.
+fn settings_of(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut settings = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        let mut key = String::new();
+        let mut assigned = false;
+        for c in chars.by_ref() {
+            if c == '=' {
+                assigned = true;
+                break;
+            }
+            key.push(c);
+        }
+        let key = key.trim();
+        if !assigned {
+            if key.is_empty() && settings.is_empty() {
+                return Ok(settings);
+            }
+            return Err(format!("has {:?} without a value", key));
+        }
+        if key.is_empty() {
+            return Err("has a setting without a name".to_string());
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let value = if chars.next_if_eq(&'"').is_some() {
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    // Other backslashes stay, for the shell commands of scripts.
+                    Some('\\') => match chars.next_if(|&c| c == '"' || c == '\\') {
+                        Some(c) => value.push(c),
+                        None => value.push('\\'),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(format!("has an unclosed quote after {}", key)),
+                }
+            }
+            let rest: String = chars.by_ref().take_while(|&c| c != ',').collect();
+            if !rest.trim().is_empty() {
+                return Err(format!("has {:?} after the quotes of {}", rest.trim(), key));
+            }
+            value
+        } else {
+            let value: String = chars.by_ref().take_while(|&c| c != ',').collect();
+            value.trim().to_string()
+        };
+        settings.push((key.to_string(), value));
+        if chars.peek().is_none() {
+            return Ok(settings);
+        }
+    }
+}
+
+/// Parses a number of milliseconds, seconds, or minutes, like "500ms", "2s", or "1m".
+fn duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or((value, ""), |i| value.split_at(i));
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("{:?} is not a duration like 500ms, 2s, or 1m", value))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => {
+            return Err(format!(
+                "{:?} is not a duration like 500ms, 2s, or 1m",
+                value
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("{:?} is too long", value))
+}
+
+/// What a widget shows: runs of text, each in its own colors.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Reading {
+    spans: Vec<Span>,
+    /// Instead of the widget's, which scripts may ask for.
+    color: Option<[f32; 4]>,
+    background: Option<[f32; 4]>,
+    /// Drawn on the taskbar's urgent color, for scripts asking for attention.
+    urgent: bool,
+}
+
+impl Reading {
+    fn plain(text: String) -> Self {
+        Self {
+            spans: vec![Span::plain(text)],
+            ..Self::default()
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.spans.iter().all(|span| span.text.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    text: String,
+    color: Option<[f32; 4]>,
+    background: Option<[f32; 4]>,
+}
+
+impl Span {
+    fn plain(text: String) -> Self {
+        Self {
+            text,
+            color: None,
+            background: None,
+        }
+    }
+}
+
+/// Where a widget reads what it shows.
+#[derive(Debug)]
+enum Probe {
+    Clock,
+    Battery(battery::Battery),
+    Cpu(system::Cpu),
+    Memory,
+    Network(network::Network),
+    /// What the WM last reported.
+    Level(Option<Level>),
+    Media(Option<MediaInfo>),
+    Script(script::Script),
+}
+
+impl Probe {
+    fn new(kind: &WidgetKind) -> Self {
+        match kind {
+            WidgetKind::Clock { .. } => Self::Clock,
+            WidgetKind::Battery { device } => Self::Battery(battery::Battery::new(device.clone())),
+            WidgetKind::Cpu => Self::Cpu(system::Cpu::default()),
+            WidgetKind::Memory => Self::Memory,
+            WidgetKind::Network { interface } => {
+                Self::Network(network::Network::new(interface.clone()))
+            }
+            WidgetKind::Level(_) => Self::Level(None),
+            WidgetKind::Media { .. } => Self::Media(None),
+            WidgetKind::Script {
+                command,
+                schedule,
+                format,
+                markup,
+            } => Self::Script(script::Script::new(
+                command.clone(),
+                *schedule,
+                *format,
+                *markup,
+            )),
+        }
+    }
+
+    /// Whether it is read on every update, to pick up what came in since, rather than
+    /// only when due.
+    fn polled(&self) -> bool {
+        matches!(self, Self::Script(_))
+    }
+
+    /// What the widget shows now, reading again if it is `due`; `None` if it can't be
+    /// read.
+    fn read(&mut self, kind: &WidgetKind, now: Instant, due: bool) -> Option<Reading> {
+        let text = match (self, kind) {
+            (Self::Clock, WidgetKind::Clock { format }) => Some(clock::now(format)),
+            (Self::Battery(battery), _) => battery.read(),
+            (Self::Cpu(cpu), _) => cpu.read(),
+            (Self::Memory, _) => system::memory(),
+            (Self::Network(network), _) => network.read(now),
+            (Self::Level(level), _) => level.map(|level| match level.muted {
+                true => "muted".to_string(),
+                false => format!("{:.0}%", level.level.clamp(0.0, 1.0) * 100.0),
+            }),
+            (Self::Media(info), WidgetKind::Media { max_length }) => {
+                info.as_ref().and_then(|info| media::text(info, *max_length))
+            }
+            (Self::Script(script), _) => return script.read(due),
+            _ => None,
+        };
+        text.map(Reading::plain)
+    }
+}
+
+#[derive(Debug)]
+struct Widget {
+    spec: WidgetSpec,
+    probe: Probe,
+    reading: Reading,
+    /// Of each span, at scale 1.
+    widths: Vec<f32>,
+    next_at: Option<Instant>,
+}
+
+impl Widget {
+    /// Its width at `scale`.
+    fn width(&self, scale: f32) -> f32 {
+        ((self.widths.iter().sum::<f32>() + 2.0 * PADDING) * scale).ceil()
+    }
+}
+
+/// The bar's widgets, and what they last read.
+#[derive(Debug, Default)]
+pub struct Widgets {
+    widgets: Vec<Widget>,
+    /// The font widths were measured in.
+    measured: Option<Arc<Font>>,
+}
+
+impl Widgets {
+    /// Replaces the widgets, which read again right away.
+    pub(super) fn set_specs(&mut self, specs: Vec<WidgetSpec>) {
+        self.widgets = specs
+            .into_iter()
+            .map(|spec| Widget {
+                probe: Probe::new(&spec.kind),
+                spec,
+                reading: Reading::default(),
+                widths: Vec::new(),
+                next_at: None,
+            })
+            .collect();
+    }
+
+    /// Gives the widgets whose colors name palette slots `palette`'s.
+    pub(super) fn retheme(&mut self, palette: &Palette) {
+        for widget in &mut self.widgets {
+            let spec = &mut widget.spec;
+            for color in spec.color.iter_mut().chain(&mut spec.background) {
+                color.retheme(palette);
+            }
+        }
+    }
+
+    /// Shows `level` in the widgets showing its control, on the next update.
+    pub(super) fn set_level(&mut self, level: Level) {
+        for widget in &mut self.widgets {
+            if widget.spec.kind == WidgetKind::Level(level.control) {
+                widget.probe = Probe::Level(Some(level));
+                widget.next_at = None;
+            }
+        }
+    }
+
+    /// Shows what the WM reports of its media player, or that there is none, in the
+    /// media widgets, on the next update.
+    pub(super) fn set_media(&mut self, info: Option<MediaInfo>) {
+        for widget in &mut self.widgets {
+            if let Probe::Media(shown) = &mut widget.probe {
+                shown.clone_from(&info);
+                widget.next_at = None;
+            }
+        }
+    }
+
+    /// Reads the widgets that are due at `now`, measuring text at `px` in `font`;
+    /// returns whether what they show changed.
+    pub(super) fn update(&mut self, now: Instant, px: f32, font: Option<&Arc<Font>>) -> bool {
+        let font_changed = match (&self.measured, font) {
+            (Some(measured), Some(font)) => !Arc::ptr_eq(measured, font),
+            (None, None) => false,
+            _ => true,
+        };
+        self.measured = font.cloned();
+        let mut changed = false;
+        for widget in &mut self.widgets {
+            let mut measure = font_changed;
+            let due = widget.next_at.is_none_or(|at| now >= at);
+            if due {
+                widget.next_at = Some(now + widget.spec.interval);
+            }
+            if due || widget.probe.polled() {
+                // Widgets that can't be read, like the battery of a desktop, hide.
+                let mut reading = widget
+                    .probe
+                    .read(&widget.spec.kind, now, due)
+                    .unwrap_or_default();
+                if !reading.is_empty() && !widget.spec.label.is_empty() {
+                    reading
+                        .spans
+                        .insert(0, Span::plain(widget.spec.label.clone()));
+                }
+                if reading != widget.reading {
+                    widget.reading = reading;
+                    changed = true;
+                    measure = true;
+                }
+            }
+            if measure {
+                widget.widths = widget
+                    .reading
+                    .spans
+                    .iter()
+                    .map(|span| font.map_or(0.0, |font| font.layout(&span.text, px).width))
+                    .collect();
+            }
+        }
+        changed || font_changed
+    }
+
+    /// How much of the bar they take at `scale`.
+    pub(super) fn width(&self, scale: f32) -> f32 {
+        self.widgets
+            .iter()
+            .filter(|widget| !widget.reading.is_empty())
+            .map(|widget| widget.width(scale) + SPACING * scale)
+            .sum()
+    }
+
+    /// Takes a press of `button` at `pointer`, in root window coordinates, on the
+    /// widgets in `rect`; returns what it asks the WM to do.
+    pub(super) fn press(
+        &mut self,
+        rect: Rect,
+        pointer: [f32; 2],
+        button: BarButton,
+    ) -> Option<BarAction> {
+        let (index, rect) = self
+            .slots(rect, 1.0)
+            .find(|(_, slot)| contains(*slot, pointer))?;
+        match &mut self.widgets[index].probe {
+            Probe::Script(script) => {
+                let button = match button {
+                    BarButton::Left => 1,
+                    BarButton::Middle => 2,
+                    BarButton::Right => 3,
+                };
+                script.click(script::Click {
+                    button,
+                    pointer,
+                    rect,
+                });
+                None
+            }
+            Probe::Media(_) => Some(match button {
+                BarButton::Left => BarAction::MediaPlayPause,
+                BarButton::Middle => BarAction::MediaPrevious,
+                BarButton::Right => BarAction::MediaNext,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The clock under `pointer`, in root window coordinates, among the widgets in
+    /// `rect`, if there is one there.
+    pub(super) fn clock_at(&self, rect: Rect, pointer: [f32; 2]) -> Option<Rect> {
+        self.slots(rect, 1.0)
+            .find(|(index, slot)| {
+                matches!(self.widgets[*index].probe, Probe::Clock) && contains(*slot, pointer)
+            })
+            .map(|(_, slot)| slot)
+    }
+
+    /// The widgets, left to right in `rect` at `scale`.
+    pub(super) fn items(
+        &self,
+        config: &BarConfig,
+        rect: Rect,
+        scale: f32,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let Some(font) = font else {
+            return Vec::new();
+        };
+        let px = config.text_px * scale;
+        let inset = SPACING * scale;
+        let mut items = Vec::new();
+        for (index, slot) in self.slots(rect, scale) {
+            let widget = &self.widgets[index];
+            let reading = &widget.reading;
+            let [left, y, width, height] = slot;
+            let (top, box_height) = (y + inset, (height - 2.0 * inset).max(0.0));
+            let background = if reading.urgent {
+                Some(config.taskbar.urgent.get())
+            } else {
+                reading
+                    .background
+                    .or(widget.spec.background.map(|color| color.get()))
+            };
+            if let Some(background) = background {
+                let rect = [left, top, width, box_height];
+                items.push(solid(rect, background, CORNER_RADIUS * scale, 0.0));
+            }
+            let color = reading
+                .color
+                .or(widget.spec.color.map(|color| color.get()))
+                .unwrap_or(config.foreground.get());
+            let labels: Vec<_> = reading
+                .spans
+                .iter()
+                .map(|span| font.layout(&span.text, px))
+                .collect();
+            let total: f32 = labels.iter().map(|label| label.width).sum();
+            let mut x = (left + (width - total) / 2.0).round();
+            for (span, label) in reading.spans.iter().zip(&labels) {
+                if let Some(background) = span.background {
+                    let rect = [x, top, label.width, box_height];
+                    items.push(solid(rect, background, 0.0, 0.0));
+                }
+                items.extend(text::draw(
+                    font,
+                    label,
+                    x,
+                    (y + (height - label.height) / 2.0).round(),
+                    span.color.unwrap_or(color),
+                ));
+                x += label.width;
+            }
+        }
+        items
+    }
+
+    /// The shown widgets' indices and rects, left to right in `rect` at `scale`.
+    fn slots(&self, rect: Rect, scale: f32) -> impl Iterator<Item = (usize, Rect)> + '_ {
+        let [x, y, _, height] = rect;
+        let mut left = x + SPACING * scale;
+        self.widgets
+            .iter()
+            .enumerate()
+            .filter(|(_, widget)| !widget.reading.is_empty())
+            .map(move |(index, widget)| {
+                let width = widget.width(scale);
+                let slot = [left, y, width, height];
+                left += width + SPACING * scale;
+                (index, slot)
+            })
+    }
+}
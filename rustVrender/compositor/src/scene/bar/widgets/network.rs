@@ -0,0 +1,82 @@
+// The network widget: bytes received and sent each second, from /proc/net/dev.
+
+use std::fs;
+use std::time::Instant;
+
+#[derive(Debug)]
+pub(super) struct Network {
+    /// Every interface but loopback when `None`.
+    interface: Option<String>,
+    /// Bytes received and sent in total at the last reading, and when.
+    last: Option<(Instant, u64, u64)>,
+}
+
+impl Network {
+    pub(super) fn new(interface: Option<String>) -> Self {
+        Self {
+            interface,
+            last: None,
+        }
+    }
+
+    pub(super) fn read(&mut self, now: Instant) -> Option<String> {
+        let (received, sent) = self.totals()?;
+        let Some((at, last_received, last_sent)) = self.last.replace((now, received, sent)) else {
+            // Rates need a second reading.
+            return Some(format!("rx {} tx {}", rate(0.0), rate(0.0)));
+        };
+        let seconds = now.saturating_duration_since(at).as_secs_f64().max(0.001);
+        let per_second = |total: u64, last: u64| total.saturating_sub(last) as f64 / seconds;
+        Some(format!(
+            "rx {} tx {}",
+            rate(per_second(received, last_received)),
+            rate(per_second(sent, last_sent))
+        ))
+    }
+
+    /// Bytes received and sent since boot on the interfaces shown.
+    fn totals(&self) -> Option<(u64, u64)> {
+        let dev = fs::read_to_string("/proc/net/dev").ok()?;
+        let mut totals = None;
+        // Two header lines, then `name: rx_bytes ... (8 fields) tx_bytes ...`.
+        for line in dev.lines().skip(2) {
+            let Some((name, counters)) = line.split_once(':') else {
+                continue;
+            };
+            let name = name.trim();
+            let shown = match &self.interface {
+                Some(interface) => name == interface,
+                None => name != "lo",
+            };
+            if !shown {
+                continue;
+            }
+            let counters: Vec<u64> = counters
+                .split_whitespace()
+                .filter_map(|counter| counter.parse().ok())
+                .collect();
+            if let (Some(received), Some(sent)) = (counters.first(), counters.get(8)) {
+                let (total_received, total_sent) = totals.get_or_insert((0, 0));
+                *total_received += received;
+                *total_sent += sent;
+            }
+        }
+        totals
+    }
+}
+
+/// `bytes` per second, shortened with binary prefixes.
+fn rate(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 || value >= 10.0 {
+        format!("{:.0}{}", value, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,305 @@
+// The script widget, which speaks the i3blocks protocol, so blocks written for it port
+// over. Its command runs with `sh -c` on every interval, once, or, with
+// `interval = persist`, for as long as the bar shows it, each line it prints replacing
+// the last. Text output is i3blocks': the full text, the short text, which the bar
+// doesn't use, the color, and the background, a line each; an exit status of 33 marks
+// the widget urgent. JSON output is an object per run or line, with i3bar's full_text
+// (or waybar's text), color, background, urgent, and markup. Clicks run the command
+// again with BLOCK_BUTTON and the pointer in the environment, or are written to a
+// persistent command's standard input as a line of JSON.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use super::{markup, Reading, Span};
+use crate::scene::animation::Rect;
+use crate::scene::theme::parse_hex;
+
+/// The exit status with which i3blocks scripts ask for attention.
+const URGENT_STATUS: i32 = 33;
+
+/// When a script widget runs its command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Again on every interval, and on clicks.
+    Interval,
+    /// When the bar starts, and on clicks.
+    Once,
+    /// Once, reading every line it prints; again an interval after it exits.
+    Persist,
+}
+
+/// How a script widget's command prints what it shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    /// i3blocks' lines.
+    Text,
+    /// A JSON object.
+    Json,
+}
+
+impl ScriptFormat {
+    pub(super) fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("{:?} is not text or json", value)),
+        }
+    }
+}
+
+/// A click on the widget, as the command is told of it.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Click {
+    /// 1 for the left button, 2 for the middle, 3 for the right.
+    pub(super) button: u8,
+    /// The pointer, in root window coordinates.
+    pub(super) pointer: [f32; 2],
+    /// The widget, in the same coordinates.
+    pub(super) rect: Rect,
+}
+
+impl Click {
+    fn fields(&self) -> [(&'static str, i64); 7] {
+        let [x, y] = self.pointer;
+        let [left, top, width, height] = self.rect;
+        [
+            ("button", self.button as i64),
+            ("x", x as i64),
+            ("y", y as i64),
+            ("relative_x", (x - left) as i64),
+            ("relative_y", (y - top) as i64),
+            ("width", width as i64),
+            ("height", height as i64),
+        ]
+    }
+}
+
+/// What a run of the command printed, and whether it asked for attention.
+type Output = (String, bool);
+
+#[derive(Debug)]
+pub(super) struct Script {
+    command: String,
+    schedule: Schedule,
+    format: ScriptFormat,
+    markup: bool,
+    /// The run on its way, for commands that aren't persistent.
+    running: Option<Receiver<Output>>,
+    /// A click that came in while the command ran, for the next run.
+    click: Option<Click>,
+    ran: bool,
+    persistent: Option<Persistent>,
+    shown: Option<Reading>,
+}
+
+/// A persistent command, and the newest line it printed that isn't shown yet.
+#[derive(Debug)]
+struct Persistent {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    latest: Arc<Mutex<Option<String>>>,
+}
+
+impl Drop for Persistent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Script {
+    pub(super) fn new(
+        command: String,
+        schedule: Schedule,
+        format: ScriptFormat,
+        markup: bool,
+    ) -> Self {
+        Self {
+            command,
+            schedule,
+            format,
+            markup,
+            running: None,
+            click: None,
+            ran: false,
+            persistent: None,
+            shown: None,
+        }
+    }
+
+    /// What the command printed last, running it again if it is `due`.
+    pub(super) fn read(&mut self, due: bool) -> Option<Reading> {
+        match self.schedule {
+            Schedule::Persist => return self.read_persistent(due),
+            Schedule::Interval | Schedule::Once => {
+                match self.running.as_ref().map(Receiver::try_recv) {
+                    Some(Ok((output, urgent))) => {
+                        self.shown = self.reading(&output, urgent);
+                        self.running = None;
+                    }
+                    Some(Err(TryRecvError::Disconnected)) => self.running = None,
+                    Some(Err(TryRecvError::Empty)) | None => {}
+                }
+                let again = self.schedule == Schedule::Interval || !self.ran;
+                if self.running.is_none() && ((due && again) || self.click.is_some()) {
+                    let click = self.click.take();
+                    self.run(click);
+                }
+            }
+        }
+        self.shown.clone()
+    }
+
+    /// Tells the command of `click`.
+    pub(super) fn click(&mut self, click: Click) {
+        if self.schedule != Schedule::Persist {
+            // The next read runs the command with it.
+            self.click = Some(click);
+            return;
+        }
+        let Some(stdin) = self.persistent.as_mut().and_then(|p| p.stdin.as_mut()) else {
+            return;
+        };
+        let event: serde_json::Map<String, Value> = click
+            .fields()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect();
+        if writeln!(stdin, "{}", Value::Object(event)).is_err() {
+            // It doesn't read its input anymore.
+            self.persistent.as_mut().unwrap().stdin = None;
+        }
+    }
+
+    fn run(&mut self, click: Option<Click>) {
+        self.ran = true;
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&self.command).stdin(Stdio::null());
+        if let Some(click) = click {
+            for (name, value) in click.fields() {
+                command.env(
+                    format!("BLOCK_{}", name.to_ascii_uppercase()),
+                    value.to_string(),
+                );
+            }
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.running = Some(receiver);
+        let name = self.command.clone();
+        thread::spawn(move || match command.output() {
+            Ok(output) => {
+                let urgent = output.status.code() == Some(URGENT_STATUS);
+                let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+                let _ = sender.send((stdout, urgent));
+            }
+            Err(e) => eprintln!("Failed to run bar script {:?}: {}", name, e),
+        });
+    }
+
+    fn read_persistent(&mut self, due: bool) -> Option<Reading> {
+        let Some(persistent) = &mut self.persistent else {
+            if due {
+                self.persistent = self.spawn();
+            }
+            return self.shown.clone();
+        };
+        let exited = !matches!(persistent.child.try_wait(), Ok(None));
+        let line = persistent.latest.lock().unwrap().take();
+        if let Some(line) = line {
+            self.shown = self.reading(&line, false);
+        }
+        if exited {
+            // Runs again on the next interval.
+            self.persistent = None;
+        }
+        self.shown.clone()
+    }
+
+    fn spawn(&self) -> Option<Persistent> {
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Failed to run bar script {:?}: {}", self.command, e);
+                return None;
+            }
+        };
+        let stdout = child.stdout.take()?;
+        let latest = Arc::new(Mutex::new(None));
+        {
+            let latest = latest.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    let Ok(line) = line else {
+                        return;
+                    };
+                    *latest.lock().unwrap() = Some(line);
+                }
+            });
+        }
+        Some(Persistent {
+            stdin: child.stdin.take(),
+            child,
+            latest,
+        })
+    }
+
+    /// What `output` shows, in the command's format; `None` to hide the widget.
+    fn reading(&self, output: &str, urgent: bool) -> Option<Reading> {
+        let (text, color, background, urgent, markup) = match self.format {
+            ScriptFormat::Text => {
+                // The second line is the short text.
+                let lines: Vec<&str> = output.lines().collect();
+                let color = |i: usize| lines.get(i).and_then(|line| parse_hex(line.trim()).ok());
+                let text = lines.first().copied().unwrap_or_default().to_string();
+                (text, color(2), color(3), urgent, self.markup)
+            }
+            ScriptFormat::Json => {
+                let object: Value = match serde_json::from_str(output.trim()) {
+                    Ok(object @ Value::Object(_)) => object,
+                    _ => {
+                        eprintln!("Bar script {:?} printed no JSON object", self.command);
+                        return None;
+                    }
+                };
+                let field = |name: &str| object.get(name).and_then(Value::as_str);
+                let color = |name: &str| field(name).and_then(|c| parse_hex(c).ok());
+                (
+                    field("full_text")
+                        .or(field("text"))
+                        .unwrap_or_default()
+                        .to_string(),
+                    color("color"),
+                    color("background"),
+                    urgent || object.get("urgent").and_then(Value::as_bool) == Some(true),
+                    field("markup").map_or(self.markup, |markup| markup == "pango"),
+                )
+            }
+        };
+        if text.is_empty() {
+            return None;
+        }
+        let spans = if markup {
+            markup::parse(&text)
+        } else {
+            vec![Span::plain(text)]
+        };
+        Some(Reading {
+            spans,
+            color,
+            background,
+            urgent,
+        })
+    }
+}
@@ -0,0 +1,55 @@
+// The CPU and memory widgets, which read /proc.
+
+use std::fs;
+
+/// What share of the time since the last reading the CPUs were busy.
+#[derive(Debug, Default)]
+pub(super) struct Cpu {
+    /// Time spent busy and in total at the last reading, in clock ticks.
+    last: Option<(u64, u64)>,
+}
+
+impl Cpu {
+    pub(super) fn read(&mut self) -> Option<String> {
+        let stat = fs::read_to_string("/proc/stat").ok()?;
+        // cpu  user nice system idle iowait irq softirq steal ...
+        let times: Vec<u64> = stat
+            .lines()
+            .next()?
+            .strip_prefix("cpu ")?
+            .split_whitespace()
+            .take(8)
+            .map(|time| time.parse().ok())
+            .collect::<Option<_>>()?;
+        let total: u64 = times.iter().sum();
+        let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+        let busy = total - idle;
+        // The first reading covers the time since boot.
+        let (last_busy, last_total) = self.last.replace((busy, total)).unwrap_or((0, 0));
+        let elapsed = total.saturating_sub(last_total);
+        let share = if elapsed == 0 {
+            0.0
+        } else {
+            busy.saturating_sub(last_busy) as f64 / elapsed as f64
+        };
+        Some(format!("{:.0}%", share * 100.0))
+    }
+}
+
+/// What share of memory is in use, leaving out what the kernel could reclaim.
+pub(super) fn memory() -> Option<String> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| -> Option<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    let total = field("MemTotal").filter(|&total| total > 0)?;
+    let available = field("MemAvailable")?;
+    let used = total.saturating_sub(available) as f64 / total as f64;
+    Some(format!("{:.0}%", used * 100.0))
+}
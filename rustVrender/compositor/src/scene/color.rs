@@ -0,0 +1,190 @@
+// Color correction per output: the display's ICC profile, the calibration curves stored
+// with it, and plain gamma ramps, baked together into one 3D lookup table that the
+// post-processing pass applies last. Outputs are X windows, so there is no CRTC gamma
+// LUT to load them into instead.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use qcms::{DataType, Intent, Profile, Transform};
+use serde::Deserialize;
+
+/// Points along each axis of a lookup table.
+pub const LUT_SIZE: u32 = 33;
+
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Color correction by output name.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub outputs: HashMap<String, OutputColor>,
+}
+
+/// How one output's colors are corrected; nothing is done without either field.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputColor {
+    /// The display's ICC profile: sRGB is converted into it, then its vcgt calibration
+    /// curves, if it has any, are applied.
+    pub icc: Option<PathBuf>,
+    /// Red, green, and blue gamma, applied last like xgamma's; above 1 brightens.
+    pub gamma: Option<[f32; 3]>,
+}
+
+/// A 3D table mapping the sRGB values an output would show to the ones it should.
+#[derive(Debug)]
+pub struct ColorLut {
+    /// Points along each axis.
+    pub size: u32,
+    /// RGBA8 entries, red varying fastest, then green, then blue.
+    pub data: Vec<u8>,
+    /// Changes with every table built, so renderers know to upload it again.
+    pub serial: u64,
+}
+
+impl ColorLut {
+    /// The table for `color`, or none if it leaves colors alone.
+    pub fn build(color: &OutputColor) -> Result<Option<Self>, String> {
+        if color.icc.is_none() && color.gamma.is_none() {
+            return Ok(None);
+        }
+        if let Some(gamma) = color.gamma {
+            if gamma.iter().any(|g| !(0.1..=10.0).contains(g)) {
+                return Err(format!("gamma {:?} is outside 0.1..=10", gamma));
+            }
+        }
+        let mut data = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+        for b in 0..LUT_SIZE {
+            for g in 0..LUT_SIZE {
+                for r in 0..LUT_SIZE {
+                    data.extend([r, g, b].map(grid_value));
+                    data.push(255);
+                }
+            }
+        }
+        if let Some(path) = &color.icc {
+            apply_profile(path, &mut data)?;
+        }
+        if let Some(gamma) = color.gamma {
+            for entry in data.chunks_exact_mut(4) {
+                for (value, gamma) in entry.iter_mut().zip(gamma) {
+                    let v = (*value as f32 / 255.0).powf(1.0 / gamma);
+                    *value = (v * 255.0).round() as u8;
+                }
+            }
+        }
+        Ok(Some(Self {
+            size: LUT_SIZE,
+            data,
+            serial: NEXT_SERIAL.fetch_add(1, Ordering::Relaxed),
+        }))
+    }
+}
+
+/// The 8-bit value of grid point `i`.
+fn grid_value(i: u32) -> u8 {
+    (i as f32 * 255.0 / (LUT_SIZE - 1) as f32).round() as u8
+}
+
+/// Converts the RGBA8 `data` from sRGB into the profile at `path`, then through its
+/// calibration curves.
+fn apply_profile(path: &Path, data: &mut [u8]) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let profile = Profile::new_from_slice(&bytes, false)
+        .ok_or_else(|| format!("{} is not an ICC profile qcms can read", path.display()))?;
+    let transform = Transform::new(
+        &Profile::new_sRGB(),
+        &profile,
+        DataType::RGBA8,
+        Intent::default(),
+    )
+    .ok_or_else(|| format!("{} can't be converted into from sRGB", path.display()))?;
+    transform.apply(data);
+    if let Some(curves) = Calibration::parse(&bytes) {
+        for entry in data.chunks_exact_mut(4) {
+            for (channel, value) in entry.iter_mut().take(3).enumerate() {
+                let v = curves.apply(channel, *value as f32 / 255.0);
+                *value = (v * 255.0).round() as u8;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The video card gamma curves of a profile's vcgt tag, which calibration tools store
+/// for loading into the display's gamma ramps.
+enum Calibration {
+    /// Evenly spaced samples of each channel's curve, from 0 to 1.
+    Table([Vec<f32>; 3]),
+    /// min + (max - min) * v^gamma, each as gamma, min, and max per channel.
+    Formula([[f32; 3]; 3]),
+}
+
+impl Calibration {
+    /// Reads the vcgt tag of ICC profile `icc`, if it has a usable one.
+    fn parse(icc: &[u8]) -> Option<Self> {
+        let u16_at = |at: usize| Some(u16::from_be_bytes(icc.get(at..at + 2)?.try_into().ok()?));
+        let u32_at = |at: usize| Some(u32::from_be_bytes(icc.get(at..at + 4)?.try_into().ok()?));
+        // Tags follow the 128-byte header as (signature, offset, size) triples.
+        let tags = u32_at(128)? as usize;
+        let tag = (0..tags.min(256)).find_map(|i| {
+            let entry = 132 + i * 12;
+            (&icc.get(entry..entry + 4)? == b"vcgt").then_some(u32_at(entry + 4)? as usize)
+        })?;
+        match u32_at(tag + 8)? {
+            0 => {
+                let channels = u16_at(tag + 12)? as usize;
+                let entries = u16_at(tag + 14)? as usize;
+                let entry_size = u16_at(tag + 16)? as usize;
+                if entries < 2 || !(1..=2).contains(&entry_size) {
+                    return None;
+                }
+                let value = |at: usize| -> Option<f32> {
+                    Some(match entry_size {
+                        1 => *icc.get(at)? as f32 / 255.0,
+                        _ => u16_at(at)? as f32 / 65535.0,
+                    })
+                };
+                let curve = |channel: usize| -> Option<Vec<f32>> {
+                    // Single-channel tables apply to all three.
+                    let start = tag + 18 + channel.min(channels - 1) * entries * entry_size;
+                    (0..entries)
+                        .map(|i| value(start + i * entry_size))
+                        .collect()
+                };
+                if channels != 1 && channels != 3 {
+                    return None;
+                }
+                Some(Self::Table([curve(0)?, curve(1)?, curve(2)?]))
+            }
+            1 => {
+                let fixed = |i: usize| Some(u32_at(tag + 12 + i * 4)? as i32 as f32 / 65536.0);
+                let channel = |c: usize| -> Option<[f32; 3]> {
+                    Some([fixed(c * 3)?, fixed(c * 3 + 1)?, fixed(c * 3 + 2)?])
+                };
+                Some(Self::Formula([channel(0)?, channel(1)?, channel(2)?]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Maps `v`, from 0 to 1, through `channel`'s curve.
+    fn apply(&self, channel: usize, v: f32) -> f32 {
+        match self {
+            Self::Table(curves) => {
+                let curve = &curves[channel];
+                let at = v.clamp(0.0, 1.0) * (curve.len() - 1) as f32;
+                let i = (at as usize).min(curve.len() - 2);
+                let t = at - i as f32;
+                curve[i] + (curve[i + 1] - curve[i]) * t
+            }
+            Self::Formula(formulas) => {
+                let [gamma, min, max] = formulas[channel];
+                (min + (max - min) * v.clamp(0.0, 1.0).powf(gamma)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
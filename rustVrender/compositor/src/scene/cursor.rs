@@ -0,0 +1,254 @@
+// The software cursor: the pointer image of an XCursor theme, drawn by the compositor
+// above everything else on the output under the pointer, in place of the X server's,
+// or the theme's busy pointer while the WM waits for an application it launched.
+
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use xcursor::parser::parse_xcursor;
+use xcursor::CursorTheme;
+
+use super::{DrawItem, DrawSource, WindowContents, WHOLE_CONTENTS};
+
+/// Cursor images are uploaded like window contents, under this id, which no X or
+/// Wayland window has.
+pub const CURSOR_ID: u32 = u32::MAX;
+
+/// Cursor sizes accepted by `Cursor::set_size`.
+pub const MIN_CURSOR_SIZE: u32 = 8;
+pub const MAX_CURSOR_SIZE: u32 = 256;
+
+/// Names themes give the plain arrow, tried in order.
+const POINTER_NAMES: &[&str] = &["left_ptr", "default", "arrow"];
+/// Names themes give the arrow with a busy mark, tried in order.
+const BUSY_NAMES: &[&str] = &["left_ptr_watch", "progress", "half-busy", "watch", "wait"];
+
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Which cursor is drawn, and how big.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CursorConfig {
+    /// Draws the cursor in the compositor and hides the X server's over the outputs.
+    pub enabled: bool,
+    /// An XCursor theme, looked up in the usual icon directories.
+    pub theme: String,
+    /// Nominal size at scale 1; scaled outputs use images that many times bigger.
+    pub size: u32,
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            theme: "default".into(),
+            size: 24,
+        }
+    }
+}
+
+/// One image of the cursor, of one nominal size.
+#[derive(Debug, Clone)]
+struct Image {
+    size: u32,
+    hotspot: [u32; 2],
+    /// Shown this long before the next image of the same size; zero for still cursors.
+    delay: Duration,
+    /// Premultiplied BGRA, like window contents.
+    contents: WindowContents,
+    serial: u64,
+}
+
+/// The loaded theme's pointer images, and which of them is shown.
+#[derive(Debug, Default)]
+pub struct Cursor {
+    config: CursorConfig,
+    /// Every image of the pointer cursor, of every size the theme has, in file order.
+    images: Vec<Image>,
+    /// When the images were loaded, which animations start from.
+    loaded_at: Option<Instant>,
+    /// How far along animations are, at the last `advance`.
+    elapsed: Duration,
+    /// Whether some size has more than one image.
+    animated: bool,
+    /// The busy pointer's images, which themes without one leave empty.
+    busy_images: Vec<Image>,
+    busy_animated: bool,
+    /// Whether the busy pointer is shown.
+    busy: bool,
+}
+
+impl Cursor {
+    pub fn config(&self) -> &CursorConfig {
+        &self.config
+    }
+
+    /// Takes `config`, loading its theme if it's enabled.
+    pub fn set_config(&mut self, config: CursorConfig) -> Result<(), String> {
+        let (images, busy_images) = if config.enabled {
+            (load(&config.theme, POINTER_NAMES)?, load_busy(&config.theme))
+        } else {
+            Default::default()
+        };
+        self.config = config;
+        self.set_images(images, busy_images);
+        Ok(())
+    }
+
+    /// Switches to `theme`, keeping the current one if it has no pointer cursor.
+    pub fn set_theme(&mut self, theme: &str) -> Result<(), String> {
+        let images = load(theme, POINTER_NAMES)?;
+        self.config.theme = theme.to_string();
+        self.set_images(images, load_busy(theme));
+        Ok(())
+    }
+
+    pub fn set_size(&mut self, size: u32) -> Result<(), String> {
+        if !(MIN_CURSOR_SIZE..=MAX_CURSOR_SIZE).contains(&size) {
+            return Err(format!(
+                "cursor size {} is outside {}..={}",
+                size, MIN_CURSOR_SIZE, MAX_CURSOR_SIZE
+            ));
+        }
+        self.config.size = size;
+        Ok(())
+    }
+
+    fn set_images(&mut self, images: Vec<Image>, busy_images: Vec<Image>) {
+        self.animated = animated(&images);
+        self.busy_animated = animated(&busy_images);
+        self.images = images;
+        self.busy_images = busy_images;
+        self.loaded_at = Some(Instant::now());
+        self.elapsed = Duration::ZERO;
+    }
+
+    /// Shows the busy pointer, or the plain one again; returns whether that changes
+    /// what is drawn.
+    pub fn set_busy(&mut self, busy: bool) -> bool {
+        let changed = self.busy != busy;
+        self.busy = busy;
+        changed && self.drawn() && !self.busy_images.is_empty()
+    }
+
+    /// The images shown now: the busy pointer's while busy, if the theme has one.
+    fn shown(&self) -> (&[Image], bool) {
+        if self.busy && !self.busy_images.is_empty() {
+            (&self.busy_images, self.busy_animated)
+        } else {
+            (&self.images, self.animated)
+        }
+    }
+
+    /// Whether the compositor draws the cursor.
+    pub fn drawn(&self) -> bool {
+        self.config.enabled && !self.images.is_empty()
+    }
+
+    /// Moves animated cursors on to `now`; returns whether one is shown.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let Some(loaded_at) = self.loaded_at else {
+            return false;
+        };
+        self.elapsed = now.saturating_duration_since(loaded_at);
+        self.drawn() && self.shown().1
+    }
+
+    /// The cursor with its hotspot at `x`, `y` in the pixels of an output at `scale`.
+    pub fn items(&self, x: f32, y: f32, scale: f32) -> Vec<DrawItem> {
+        if !self.drawn() {
+            return Vec::new();
+        }
+        let wanted = self.config.size as f32 * scale;
+        let Some(image) = self.frame(wanted) else {
+            return Vec::new();
+        };
+        // Themes without an image of the right size get the nearest one scaled.
+        let k = wanted / image.size.max(1) as f32;
+        let [hot_x, hot_y] = image.hotspot.map(|h| h as f32 * k);
+        vec![DrawItem {
+            rect: [
+                (x - hot_x).round(),
+                (y - hot_y).round(),
+                image.contents.width as f32 * k,
+                image.contents.height as f32 * k,
+            ],
+            color: [1.0; 4],
+            source: DrawSource::Window {
+                id: CURSOR_ID,
+                serial: image.serial,
+                contents: image.contents.clone(),
+                frozen: false,
+                part: WHOLE_CONTENTS,
+            },
+            corner_radius: 0.0,
+            border: 0.0,
+        }]
+    }
+
+    /// The image shown now of the size nearest to `wanted` pixels.
+    fn frame(&self, wanted: f32) -> Option<&Image> {
+        let (images, _) = self.shown();
+        let size = images
+            .iter()
+            .map(|image| image.size)
+            .min_by(|a, b| (*a as f32 - wanted).abs().total_cmp(&(*b as f32 - wanted).abs()))?;
+        let frames: Vec<_> = images.iter().filter(|i| i.size == size).collect();
+        let cycle: Duration = frames.iter().map(|frame| frame.delay).sum();
+        if frames.len() == 1 || cycle.is_zero() {
+            return frames.first().copied();
+        }
+        let mut at = Duration::from_nanos((self.elapsed.as_nanos() % cycle.as_nanos()) as u64);
+        frames.into_iter().find(|frame| {
+            if at < frame.delay {
+                return true;
+            }
+            at -= frame.delay;
+            false
+        })
+    }
+}
+
+/// Whether some size has more than one image.
+fn animated(images: &[Image]) -> bool {
+    images
+        .iter()
+        .any(|a| images.iter().filter(|b| b.size == a.size).count() > 1)
+}
+
+/// `theme`'s busy pointer, or nothing, for the plain pointer to stand in.
+fn load_busy(theme: &str) -> Vec<Image> {
+    load(theme, BUSY_NAMES).unwrap_or_default()
+}
+
+/// Loads every image of the first of `names` in `theme`.
+fn load(theme: &str, names: &[&str]) -> Result<Vec<Image>, String> {
+    let cursors = CursorTheme::load(theme);
+    let path = names
+        .iter()
+        .find_map(|name| cursors.load_icon(name))
+        .ok_or_else(|| format!("cursor theme {} has no pointer cursor", theme))?;
+    let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let images = parse_xcursor(&bytes)
+        .filter(|images| !images.is_empty())
+        .ok_or_else(|| format!("{} is not an XCursor file", path.display()))?;
+    Ok(images
+        .into_iter()
+        .map(|image| Image {
+            size: image.size,
+            hotspot: [image.xhot, image.yhot],
+            delay: Duration::from_millis(image.delay as u64),
+            // XCursor pixels are premultiplied ARGB words, so BGRA bytes in the file.
+            contents: WindowContents {
+                width: image.width,
+                height: image.height,
+                data: Arc::new(image.pixels_rgba),
+                opaque: false,
+            },
+            serial: NEXT_SERIAL.fetch_add(1, Ordering::Relaxed),
+        })
+        .collect())
+}
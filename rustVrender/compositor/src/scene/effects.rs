@@ -0,0 +1,276 @@
+use serde::Deserialize;
+
+use super::theme::{Color, Palette};
+use super::{WindowState, WindowType};
+
+/// Per-window visual effects, with rules that override them for matching windows.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectsConfig {
+    /// Corner radius in pixels for windows no rule matches.
+    pub corner_radius: f32,
+    /// Blur behind see-through windows, on a scale of 1 to 20 like picom's
+    /// dual_kawase `strength`; 0 turns blur off.
+    pub blur_strength: u32,
+    /// How far shadows spread beyond their window, in pixels; 0 turns shadows off.
+    pub shadow_radius: f32,
+    pub shadow_opacity: f32,
+    /// Shadow displacement in pixels; positive values move it right and down.
+    pub shadow_offset: [f32; 2],
+    /// Unpremultiplied RGB.
+    pub shadow_color: [f32; 3],
+    /// Borders of the focused window and every other one, in the width qtile gives.
+    pub focused_border: Color,
+    pub unfocused_border: Color,
+    /// Checked in order; every matching rule applies, later ones winning.
+    pub rules: Vec<WindowRule>,
+}
+
+impl Default for EffectsConfig {
+    fn default() -> Self {
+        Self {
+            corner_radius: 8.0,
+            blur_strength: 5,
+            shadow_radius: 16.0,
+            shadow_opacity: 0.5,
+            shadow_offset: [0.0, 4.0],
+            shadow_color: [0.0, 0.0, 0.0],
+            focused_border: Color::base(0x0D, 1.0),
+            unfocused_border: Color::base(0x03, 1.0),
+            rules: vec![
+                WindowRule {
+                    window_type: Some(WindowType::Popup),
+                    corner_radius: Some(0.0),
+                    blur: Some(false),
+                    shadow: Some(false),
+                    ..WindowRule::default()
+                },
+                // Panels and bars, which _NET_WM_WINDOW_TYPE_DOCK or TOOLBAR marks.
+                WindowRule {
+                    window_type: Some(WindowType::Dock),
+                    shadow: Some(false),
+                    ..WindowRule::default()
+                },
+            ],
+        }
+    }
+}
+
+impl EffectsConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.focused_border.retheme(palette);
+        self.unfocused_border.retheme(palette);
+    }
+}
+
+/// Overrides effects for windows matching every field that is set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    pub window_type: Option<WindowType>,
+    pub window: Option<u32>,
+    /// The WM_CLASS class and instance, matched whole, as the bridge reports them.
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub corner_radius: Option<f32>,
+    /// `false` excludes matching windows from blur.
+    pub blur: Option<bool>,
+    pub shadow: Option<bool>,
+}
+
+impl WindowRule {
+    /// Whether the rule applies to `state`, of `class` and instance if those are known.
+    fn matches(&self, state: &WindowState, class: Option<(&str, &str)>) -> bool {
+        let (class_name, instance) = class.unzip();
+        let named =
+            |wanted: &Option<String>, name| wanted.as_deref().is_none_or(|w| name == Some(w));
+        self.window_type.is_none_or(|t| t == state.window_type)
+            && self.window.is_none_or(|id| id == state.id)
+            && named(&self.class, class_name)
+            && named(&self.instance, instance)
+    }
+}
+
+/// Effects resolved for one window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowEffects {
+    pub corner_radius: f32,
+    /// Blur the backdrop if the window lets it show through.
+    pub blur: bool,
+    pub shadow: bool,
+}
+
+/// Dual-kawase parameters for one blur strength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlurLevel {
+    /// Downsample (and upsample) passes.
+    pub passes: u32,
+    /// Sample offset, in half pixels of each pass's target.
+    pub offset: f32,
+}
+
+impl BlurLevel {
+    /// Maps a 1–20 strength onto passes and offsets, close to picom's levels.
+    pub fn from_strength(strength: u32) -> Option<Self> {
+        const LEVELS: [(u32, f32); 20] = [
+            (1, 1.25),
+            (1, 2.25),
+            (2, 2.0),
+            (2, 3.0),
+            (2, 4.25),
+            (3, 2.5),
+            (3, 3.25),
+            (3, 4.25),
+            (3, 5.5),
+            (4, 3.25),
+            (4, 4.0),
+            (4, 5.0),
+            (4, 6.0),
+            (4, 7.25),
+            (4, 8.25),
+            (5, 4.5),
+            (5, 5.25),
+            (5, 6.25),
+            (5, 7.25),
+            (5, 8.5),
+        ];
+        let (passes, offset) = *LEVELS.get(strength.checked_sub(1)? as usize)?;
+        Some(Self { passes, offset })
+    }
+}
+
+impl EffectsConfig {
+    /// The blur to run this frame, if any.
+    pub fn blur_level(&self) -> Option<BlurLevel> {
+        BlurLevel::from_strength(self.blur_strength.min(20))
+    }
+
+    /// Resolves the effects for `state`, of WM_CLASS `class` and instance if the bridge
+    /// reported them; fullscreen windows get no rounding or shadow.
+    pub fn resolve(
+        &self,
+        state: &WindowState,
+        class: Option<(&str, &str)>,
+        fullscreen: bool,
+    ) -> WindowEffects {
+        let mut effects = WindowEffects {
+            corner_radius: self.corner_radius,
+            blur: self.blur_strength > 0,
+            shadow: self.shadow_radius > 0.0,
+        };
+        for rule in self.rules.iter().filter(|rule| rule.matches(state, class)) {
+            if let Some(radius) = rule.corner_radius {
+                effects.corner_radius = radius;
+            }
+            if let Some(blur) = rule.blur {
+                effects.blur = blur && self.blur_strength > 0;
+            }
+            if let Some(shadow) = rule.shadow {
+                effects.shadow = shadow && self.shadow_radius > 0.0;
+            }
+        }
+        if fullscreen {
+            effects.corner_radius = 0.0;
+            effects.shadow = false;
+        }
+        effects.corner_radius = effects.corner_radius.max(0.0);
+        effects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Layer;
+
+    fn state(window_type: WindowType) -> WindowState {
+        WindowState {
+            id: 1,
+            x: 0,
+            y: 0,
+            width: 400,
+            height: 30,
+            border_width: 0,
+            focused: false,
+            opacity: 1.0,
+            window_type,
+            fullscreen: false,
+            blur: false,
+            layer: Layer::Normal,
+            visible: true,
+        }
+    }
+
+    #[test]
+    fn default_rules_match_docks_and_popups() {
+        let config = EffectsConfig::default();
+        let normal = config.resolve(&state(WindowType::Normal), None, false);
+        assert!(normal.shadow && normal.blur);
+        assert_eq!(normal.corner_radius, 8.0);
+
+        let dock = config.resolve(&state(WindowType::Dock), None, false);
+        assert!(!dock.shadow);
+        assert!(dock.blur);
+        assert_eq!(dock.corner_radius, 8.0);
+
+        let popup = config.resolve(&state(WindowType::Popup), None, false);
+        assert!(!popup.shadow && !popup.blur);
+        assert_eq!(popup.corner_radius, 0.0);
+    }
+
+    #[test]
+    fn rules_match_the_window_class() {
+        let rule = WindowRule {
+            class: Some("Alacritty".to_string()),
+            ..WindowRule::default()
+        };
+        let normal = state(WindowType::Normal);
+        assert!(rule.matches(&normal, Some(("Alacritty", "alacritty"))));
+        // Matched whole, and case matters.
+        assert!(!rule.matches(&normal, Some(("alacritty", "alacritty"))));
+        assert!(!rule.matches(&normal, Some(("Alacritty-dev", "alacritty"))));
+        // Windows the bridge reported no class for match no class rule.
+        assert!(!rule.matches(&normal, None));
+        assert!(WindowRule::default().matches(&normal, None));
+    }
+
+    #[test]
+    fn rules_match_every_field_set() {
+        let rule = WindowRule {
+            window_type: Some(WindowType::Dialog),
+            class: Some("firefox".to_string()),
+            instance: Some("Places".to_string()),
+            ..WindowRule::default()
+        };
+        let dialog = state(WindowType::Dialog);
+        assert!(rule.matches(&dialog, Some(("firefox", "Places"))));
+        assert!(!rule.matches(&dialog, Some(("firefox", "Navigator"))));
+        assert!(!rule.matches(&state(WindowType::Normal), Some(("firefox", "Places"))));
+
+        let instance = WindowRule {
+            instance: Some("scratchpad".to_string()),
+            ..WindowRule::default()
+        };
+        assert!(instance.matches(&dialog, Some(("Alacritty", "scratchpad"))));
+        assert!(!instance.matches(&dialog, Some(("scratchpad", "alacritty"))));
+    }
+
+    #[test]
+    fn class_rules_override_effects() {
+        let mut config = EffectsConfig::default();
+        config.rules.push(WindowRule {
+            class: Some("mpv".to_string()),
+            corner_radius: Some(0.0),
+            shadow: Some(false),
+            ..WindowRule::default()
+        });
+        let normal = state(WindowType::Normal);
+        let mpv = config.resolve(&normal, Some(("mpv", "gl")), false);
+        assert_eq!(mpv.corner_radius, 0.0);
+        assert!(!mpv.shadow && mpv.blur);
+
+        let other = config.resolve(&normal, Some(("Alacritty", "alacritty")), false);
+        assert_eq!(other.corner_radius, 8.0);
+        assert!(other.shadow);
+    }
+}
@@ -0,0 +1,151 @@
+// "Insert here" hints: an outline flashed where a window is about to go, or just went,
+// so the eye finds a new window, or the spot a preselection leaves for the next one,
+// without looking for it. Outline resizes draw the same outline where the window being
+// resized will go, for as long as the drag lasts.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing};
+use super::{DrawItem, DrawSource, Transform};
+
+/// How hints look and how long they stay up.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HintConfig {
+    /// Whether windows flash one where they land as they map.
+    pub on_map: bool,
+    /// How long a hint stays up when `ShowInsertHint` doesn't say, in milliseconds.
+    pub duration_ms: u64,
+    /// How hints fade in, and out again.
+    pub fade: Curve,
+    /// Colors, not premultiplied.
+    pub outline: [f32; 4],
+    pub fill: [f32; 4],
+    /// Sizes at scale 1, in output pixels.
+    pub outline_width: f32,
+    pub corner_radius: f32,
+}
+
+impl Default for HintConfig {
+    fn default() -> Self {
+        Self {
+            on_map: true,
+            duration_ms: 600,
+            fade: Curve::new(150, Easing::EaseOutCubic),
+            outline: [0.35, 0.6, 0.95, 0.9],
+            fill: [0.35, 0.6, 0.95, 0.15],
+            outline_width: 3.0,
+            corner_radius: 6.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Hint {
+    /// x, y, width, height in root window coordinates.
+    rect: [f32; 4],
+    shown_at: Instant,
+    expires: Instant,
+    /// How far it had faded in, or out, at the last `advance`.
+    opacity: f32,
+}
+
+/// The hints on screen.
+#[derive(Debug, Default)]
+pub struct Hints {
+    config: HintConfig,
+    hints: Vec<Hint>,
+    /// Where the window being resized goes, in root window coordinates.
+    outline: Option<[f32; 4]>,
+}
+
+impl Hints {
+    pub fn config(&self) -> &HintConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: HintConfig) {
+        self.config = config;
+    }
+
+    /// Outlines `rect`, in root window coordinates, for `duration`, or the configured
+    /// time. A hint already up over the same rect starts over instead of doubling.
+    pub fn show(&mut self, rect: [f32; 4], duration: Option<Duration>, now: Instant) {
+        let expires = now + duration.unwrap_or(Duration::from_millis(self.config.duration_ms));
+        match self.hints.iter_mut().find(|hint| hint.rect == rect) {
+            Some(hint) => hint.expires = expires,
+            None => self.hints.push(Hint {
+                rect,
+                shown_at: now,
+                expires,
+                opacity: 0.0,
+            }),
+        }
+    }
+
+    /// Outlines `rect` until it is moved again, or taken away with `None`.
+    pub fn set_outline(&mut self, rect: Option<[f32; 4]>) {
+        self.outline = rect;
+    }
+
+    /// Fades hints on to `now` and drops those that expired; returns whether any are
+    /// still up.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        self.hints.retain(|hint| hint.expires > now);
+        let duration = self.config.fade.duration().as_secs_f32();
+        let easing = self.config.fade.easing;
+        for hint in &mut self.hints {
+            hint.opacity = if duration == 0.0 {
+                1.0
+            } else {
+                let fade_in = now.saturating_duration_since(hint.shown_at).as_secs_f32();
+                let fade_out = hint.expires.saturating_duration_since(now).as_secs_f32();
+                easing.apply(fade_in.min(fade_out) / duration)
+            };
+        }
+        !self.hints.is_empty()
+    }
+
+    /// The hints, placed into an output's pixels by `transform`, which includes its
+    /// scale.
+    pub fn items(&self, transform: &Transform) -> Vec<DrawItem> {
+        let config = &self.config;
+        let scale = transform.scale_x;
+        let mut items = Vec::new();
+        let outline = self.outline.map(|rect| (rect, 1.0));
+        let hints = self.hints.iter().map(|hint| (hint.rect, hint.opacity));
+        for ([x, y, width, height], opacity) in hints.chain(outline) {
+            let rect = [
+                transform.x + x * transform.scale_x,
+                transform.y + y * transform.scale_y,
+                width * transform.scale_x,
+                height * transform.scale_y,
+            ];
+            let fade = |[r, g, b, a]: [f32; 4]| [r, g, b, a * opacity];
+            let radius = config.corner_radius * scale;
+            items.push(solid(rect, fade(config.fill), radius, 0.0));
+            items.push(solid(
+                rect,
+                fade(config.outline),
+                radius,
+                config.outline_width * scale,
+            ));
+        }
+        items
+    }
+}
+
+/// A rounded rect in `color`, which isn't premultiplied, or only its edge `border`
+/// pixels wide when that isn't zero.
+fn solid(rect: [f32; 4], color: [f32; 4], corner_radius: f32, border: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border,
+    }
+}
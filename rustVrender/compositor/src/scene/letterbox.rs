@@ -0,0 +1,77 @@
+// Letterboxing for windows the WM keeps centered in their tiles at their own
+// resolution, or a whole multiple of it, like emulators and pixel-art games: the rest
+// of the tile is filled with bars, drawn under the window and moving with it.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::{DrawItem, DrawSource, Transform};
+
+/// How the bars around centered windows look.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LetterboxConfig {
+    /// Not premultiplied.
+    pub color: [f32; 4],
+}
+
+impl Default for LetterboxConfig {
+    fn default() -> Self {
+        Self {
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Letterboxes {
+    config: LetterboxConfig,
+    /// The tiles of centered windows, x, y, width, height in root window coordinates.
+    tiles: HashMap<u32, [i32; 4]>,
+}
+
+impl Letterboxes {
+    pub fn config(&self) -> &LetterboxConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: LetterboxConfig) {
+        self.config = config;
+    }
+
+    /// Letterboxes `window` in `tile`, or stops with `None`; returns whether that
+    /// changed anything.
+    pub fn set(&mut self, window: u32, tile: Option<[i32; 4]>) -> bool {
+        match tile {
+            Some(tile) => self.tiles.insert(window, tile) != Some(tile),
+            None => self.tiles.remove(&window).is_some(),
+        }
+    }
+
+    /// The bars of `window`, at `position` in root window coordinates, placed into an
+    /// output's pixels by `transform`, the window's own.
+    pub fn item(
+        &self,
+        window: u32,
+        position: [i32; 2],
+        transform: &Transform,
+        opacity: f32,
+    ) -> Option<DrawItem> {
+        let [x, y, width, height] = *self.tiles.get(&window)?;
+        let [r, g, b, a] = self.config.color;
+        let a = a * opacity;
+        Some(DrawItem {
+            rect: [
+                transform.x + (x - position[0]) as f32 * transform.scale_x,
+                transform.y + (y - position[1]) as f32 * transform.scale_y,
+                width as f32 * transform.scale_x,
+                height as f32 * transform.scale_y,
+            ],
+            color: [r * a, g * a, b * a, a],
+            source: DrawSource::Solid,
+            corner_radius: 0.0,
+            border: 0.0,
+        })
+    }
+}
@@ -0,0 +1,2939 @@
+pub mod accessibility;
+pub mod animation;
+pub mod annotate;
+pub mod bar;
+pub mod color;
+pub mod cursor;
+pub mod effects;
+pub mod hint;
+pub mod letterbox;
+pub mod modal;
+pub mod night_light;
+pub mod osd;
+pub mod picker;
+pub mod pip;
+pub mod post;
+pub mod select;
+pub mod shape;
+pub mod slide;
+#[cfg(test)]
+mod tests;
+pub mod theme;
+pub mod unresponsive;
+pub mod wallpaper;
+pub mod workspace;
+pub mod zoom;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{GpuPreference, HdrConfig, MemoryConfig, PresentMode, VrrConfig};
+use crate::idle::{Idle, IdleConfig};
+use crate::input::InputConfig;
+use crate::lock::{LockConfig, LockScreen};
+use crate::preview::PreviewRequest;
+use crate::profile::{PowerConfig, PowerProfiles, Profile};
+use crate::screencast::Screencasts;
+use crate::screenshot::{Capture, ScreenshotRequest};
+use crate::stats::{FrameStats, FrameTiming};
+use crate::text::Font;
+use accessibility::{Accessibility, AccessibilityConfig};
+use animation::{AnimationConfig, Animator, Rect};
+use annotate::{AnnotateConfig, AnnotateReply, AnnotateRequest, Annotator, Rendering};
+use bar::calendar::Notification;
+use bar::pager::WorkspaceInfo;
+use bar::preview::Thumbnail;
+use bar::taskbar::{TaskWindow, WindowInfo};
+use bar::widgets::MediaInfo;
+use bar::{Bar, BarAction, BarButton, BarConfig};
+use color::{ColorConfig, ColorLut, OutputColor};
+use cursor::{Cursor, CursorConfig};
+use effects::{BlurLevel, EffectsConfig, WindowEffects};
+use night_light::{NightLight, NightLightConfig, NEUTRAL_KELVIN};
+use hint::{HintConfig, Hints};
+use letterbox::{LetterboxConfig, Letterboxes};
+use modal::{Dimmed, ModalConfig};
+use osd::{Level, OsdConfig, Osds};
+use picker::{PickReply, Picker, Sample};
+use pip::{Corner, PipConfig, Pins};
+use post::{PostConfig, PostPass, MAX_PASSES};
+use select::{Edges, SelectConfig, SelectReply, Selector};
+use shape::Shapes;
+use slide::Slide;
+use theme::ThemeConfig;
+use unresponsive::Unresponsive;
+use wallpaper::{WallpaperConfig, Wallpapers};
+use workspace::{WorkspaceConfig, Workspaces};
+use zoom::{Zoom, ZoomConfig};
+
+pub use qtilerugo_ipc::renderer::{Layer, OutputInfo, SurfaceGeometry, WindowState, WindowType};
+
+/// Scene shared between the command listener and the render loops.
+pub type SharedScene = Arc<Mutex<Scene>>;
+
+/// Identifies a node in the scene graph.
+pub type NodeId = u64;
+
+/// Window ids from here up belong to Wayland surfaces; X resource ids always leave the
+/// top three bits clear.
+pub const WAYLAND_ID_BASE: u32 = 0x8000_0000;
+
+/// Output scale factors accepted by `Scene::set_output_scale`.
+pub const MIN_OUTPUT_SCALE: f32 = 0.5;
+pub const MAX_OUTPUT_SCALE: f32 = 4.0;
+
+/// Fill color drawn for windows whose contents are not available.
+const WINDOW_FILL: [f32; 4] = [0.12, 0.12, 0.14, 1.0];
+
+/// A 2D transform: a scale followed by a translation, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub x: f32,
+    pub y: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        x: 0.0,
+        y: 0.0,
+        scale_x: 1.0,
+        scale_y: 1.0,
+    };
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self {
+            x,
+            y,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Applies `child` inside the coordinate space of `self`.
+    pub fn then(&self, child: &Transform) -> Transform {
+        Transform {
+            x: self.x + child.x * self.scale_x,
+            y: self.y + child.y * self.scale_y,
+            scale_x: self.scale_x * child.scale_x,
+            scale_y: self.scale_y * child.scale_y,
+        }
+    }
+}
+
+/// Decorations drawn around a window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoration {
+    Border { width: f32, color: [f32; 4] },
+}
+
+/// What a node represents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    /// `window` is the X window presenting this output, which is never composited.
+    Output {
+        name: String,
+        width: u32,
+        height: u32,
+        window: Option<u32>,
+    },
+    Layer(Layer),
+    Window { id: u32, width: u32, height: u32 },
+    Decoration(Decoration),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub transform: Transform,
+    pub opacity: f32,
+    /// Order among siblings; negative values draw behind the parent.
+    pub z: i32,
+    pub visible: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// Whether `state` covers all of `output`, in root window coordinates.
+fn covers(state: &WindowState, output: Rect) -> bool {
+    let [x, y, width, height] = output.map(|v| v as i32);
+    state.x <= x
+        && state.y <= y
+        && state.x + state.width as i32 >= x + width
+        && state.y + state.height as i32 >= y + height
+}
+
+/// Wakes a render loop when window contents change.
+pub struct DamageWaker(pub Box<dyn Fn() + Send>);
+
+impl fmt::Debug for DamageWaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DamageWaker")
+    }
+}
+
+/// Pixels of a client window: premultiplied BGRA rows, tightly packed.
+#[derive(Clone)]
+pub struct WindowContents {
+    pub width: u32,
+    pub height: u32,
+    pub data: Arc<Vec<u8>>,
+    /// The window has no alpha channel, so the alpha bytes are undefined.
+    pub opaque: bool,
+}
+
+impl fmt::Debug for WindowContents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WindowContents")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("opaque", &self.opaque)
+            .finish()
+    }
+}
+
+/// All of a window's contents, as the part of them a draw item shows.
+pub const WHOLE_CONTENTS: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// What fills a draw item.
+#[derive(Debug, Clone)]
+pub enum DrawSource {
+    Solid,
+    /// Samples the window's contents; `serial` changes whenever they are replaced.
+    Window {
+        id: u32,
+        serial: u64,
+        contents: WindowContents,
+        /// Drawn gray and dimmed, for a client that stopped responding.
+        frozen: bool,
+        /// The part of the contents stretched over the rect, x, y, width, height as
+        /// fractions of them; `WHOLE_CONTENTS` for all of them.
+        part: [f32; 4],
+    },
+    /// Blurs everything drawn so far and shows it inside the rect.
+    Backdrop(BlurLevel),
+    /// A soft shadow that fades out over this many pixels inside the rect's edge.
+    Shadow(f32),
+    /// Glyph `index` of `font` rasterized at `px`, drawn 1:1 into the rect as coverage
+    /// of the color.
+    Glyph { font: Arc<Font>, index: u16, px: u16 },
+}
+
+/// A flattened, ready-to-draw rectangle produced by traversing the scene.
+#[derive(Debug, Clone)]
+pub struct DrawItem {
+    /// x, y, width, height in output pixels.
+    pub rect: [f32; 4],
+    /// Premultiplied color, multiplied with the source.
+    pub color: [f32; 4],
+    pub source: DrawSource,
+    /// Corner radius in output pixels.
+    pub corner_radius: f32,
+    /// When non-zero, only a ring this many pixels wide along the edge is drawn.
+    pub border: f32,
+}
+
+/// A tree of outputs → layers → windows → decorations.
+#[derive(Debug, Default)]
+pub struct Scene {
+    nodes: HashMap<NodeId, Node>,
+    outputs: Vec<NodeId>,
+    contents: HashMap<u32, (u64, WindowContents)>,
+    /// Last reported state of every window in the scene.
+    states: HashMap<u32, WindowState>,
+    animator: Animator,
+    effects: EffectsConfig,
+    /// Background behind every output; the renderer's own without one.
+    clear_color: Option<[f32; 4]>,
+    gpu: GpuPreference,
+    present_mode: PresentMode,
+    max_fps: Option<u32>,
+    /// Whether outputs showing just an opaque fullscreen window skip composition.
+    direct_scanout: bool,
+    hdr: HdrConfig,
+    vrr: VrrConfig,
+    memory: MemoryConfig,
+    /// Called on every change of window contents, by render loops drawing on damage.
+    damage_waker: Option<DamageWaker>,
+    post: PostConfig,
+    night_light: NightLight,
+    color: ColorConfig,
+    /// Lookup tables built from `color`, by output name.
+    color_luts: HashMap<String, Arc<ColorLut>>,
+    wallpapers: Wallpapers,
+    zoom: Zoom,
+    /// Where a swipe has slid the windows.
+    slide: Slide,
+    workspaces: Workspaces,
+    pins: Pins,
+    unresponsive: Unresponsive,
+    picker: Picker,
+    annotator: Annotator,
+    selector: Selector,
+    lock: LockScreen,
+    idle: Idle,
+    input: InputConfig,
+    osds: Osds,
+    hints: Hints,
+    accessibility: Accessibility,
+    power: PowerProfiles,
+    letterboxes: Letterboxes,
+    shapes: Shapes,
+    dimmed: Dimmed,
+    /// Windows the WM made click-through, which pointer handling passes over.
+    click_through: HashSet<u32>,
+    bar: Bar,
+    /// The palette colors naming a slot take theirs from.
+    theme: ThemeConfig,
+    /// Where the pointer was last seen, in root window coordinates.
+    pointer: Option<[f32; 2]>,
+    cursor: Cursor,
+    /// Monitors the window manager wants an output on.
+    monitors: Vec<OutputInfo>,
+    /// Scale factors by output name, kept for outputs that open later.
+    scales: HashMap<String, f32>,
+    /// Screenshots waiting for a render loop.
+    screenshots: Vec<ScreenshotRequest>,
+    previews: Vec<PreviewRequest>,
+    screencasts: Screencasts,
+    stats: FrameStats,
+    /// Whether the frame statistics HUD is drawn over every output.
+    hud: bool,
+    /// Where the renderer loads shaders from, instead of the built-in ones.
+    shader_dir: Option<PathBuf>,
+    /// The font compositor-drawn text is set in, if one could be loaded.
+    font: Option<Arc<Font>>,
+    next_id: NodeId,
+    generation: u64,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared() -> SharedScene {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Incremented on every change, so render loops can skip redundant frames.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn animation_config(&self) -> &AnimationConfig {
+        self.animator.config()
+    }
+
+    pub fn set_animation_config(&mut self, config: AnimationConfig) {
+        self.animator.set_config(config);
+        self.generation += 1;
+    }
+
+    pub fn effects_config(&self) -> &EffectsConfig {
+        &self.effects
+    }
+
+    pub fn set_effects_config(&mut self, mut config: EffectsConfig) {
+        config.retheme(self.theme.palette());
+        self.effects = config;
+        self.restyle_borders();
+        self.generation += 1;
+    }
+
+    /// The background behind every output, once the config set one.
+    pub fn clear_color(&self) -> Option<[f32; 4]> {
+        self.clear_color
+    }
+
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = Some(color);
+        self.generation += 1;
+    }
+
+    /// The GPU renderers created from now on pick.
+    pub fn gpu(&self) -> GpuPreference {
+        self.gpu
+    }
+
+    pub fn set_gpu(&mut self, gpu: GpuPreference) {
+        self.gpu = gpu;
+    }
+
+    /// The present mode every output should use, as the power profile has it.
+    pub fn present_mode(&self) -> PresentMode {
+        self.power.present_mode(self.present_mode)
+    }
+
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        self.present_mode = mode;
+        self.generation += 1;
+    }
+
+    pub fn direct_scanout(&self) -> bool {
+        self.direct_scanout
+    }
+
+    pub fn set_direct_scanout(&mut self, enabled: bool) {
+        self.direct_scanout = enabled;
+        self.generation += 1;
+    }
+
+    pub fn hdr(&self) -> HdrConfig {
+        self.hdr
+    }
+
+    pub fn set_hdr(&mut self, hdr: HdrConfig) {
+        self.hdr = hdr;
+        self.generation += 1;
+    }
+
+    pub fn memory_config(&self) -> MemoryConfig {
+        self.memory
+    }
+
+    pub fn set_memory_config(&mut self, config: MemoryConfig) {
+        self.memory = config;
+    }
+
+    pub fn vrr_config(&self) -> &VrrConfig {
+        &self.vrr
+    }
+
+    pub fn set_vrr_config(&mut self, config: VrrConfig) {
+        self.vrr = config;
+        self.generation += 1;
+    }
+
+    /// Allows or forbids variable refresh on outputs called `output`, or on every output,
+    /// dropping the overrides of single ones.
+    pub fn set_vrr_enabled(&mut self, output: Option<&str>, enabled: bool) {
+        match output {
+            Some(name) => {
+                self.vrr.outputs.insert(name.to_string(), enabled);
+            }
+            None => {
+                self.vrr.enabled = enabled;
+                self.vrr.outputs.clear();
+            }
+        }
+        self.generation += 1;
+    }
+
+    /// The focused window filling `output`, or with the focus on another output, a
+    /// fullscreen one filling it, if that may switch to variable refresh; the output
+    /// then refreshes as the window draws, if the window asks for it.
+    pub fn vrr_window(&self, output: NodeId) -> Option<u32> {
+        let Some(NodeKind::Output { name, .. }) = self.nodes.get(&output).map(|n| &n.kind) else {
+            return None;
+        };
+        // The lock surface is drawn on the compositor's own clock.
+        if !self.vrr.enabled_for(name) || self.locked() {
+            return None;
+        }
+        let (x, y, width, height) = self.output_rect(output)?;
+        let rect = [x as f32, y as f32, width as f32, height as f32];
+        let shown = || self.states.values().filter(|state| state.visible);
+        if let Some(focused) = shown().find(|state| state.focused && covers(state, rect)) {
+            return Some(focused.id);
+        }
+        // A smaller window focused here is drawn over whatever fills the output.
+        let focused_here = shown().any(|state| {
+            state.focused
+                && self.output_at(
+                    state.x + state.width as i32 / 2,
+                    state.y + state.height as i32 / 2,
+                ) == Some(output)
+        });
+        if focused_here {
+            return None;
+        }
+        shown()
+            .filter(|state| state.fullscreen && covers(state, rect))
+            .map(|state| state.id)
+            .min()
+    }
+
+    /// Has `waker` called whenever window contents change, until replaced by `None`.
+    pub fn set_damage_waker(&mut self, waker: Option<DamageWaker>) {
+        self.damage_waker = waker;
+    }
+
+    pub fn post_config(&self) -> &PostConfig {
+        &self.post
+    }
+
+    pub fn set_post_config(&mut self, config: PostConfig) {
+        self.post = config;
+        self.generation += 1;
+    }
+
+    /// Turns the post-processing chain on or off for outputs called `name`.
+    pub fn set_post_enabled(&mut self, name: &str, enabled: bool) {
+        self.post.disabled_outputs.retain(|n| n != name);
+        if !enabled {
+            self.post.disabled_outputs.push(name.to_string());
+        }
+        self.generation += 1;
+    }
+
+    /// The passes `output` applies to its frames, at most `MAX_PASSES` of them, ending
+    /// with the night light's while it warms the output.
+    pub fn post_passes(&self, output: NodeId) -> Vec<PostPass> {
+        let kelvin = self.night_light.kelvin(output);
+        let night_light =
+            (kelvin != NEUTRAL_KELVIN).then_some(PostPass::ColorTemperature { kelvin });
+        let disabled = matches!(
+            self.nodes.get(&output).map(|node| &node.kind),
+            Some(NodeKind::Output { name, .. }) if self.post.disabled_outputs.contains(name)
+        );
+        let mut passes: Vec<PostPass> = if disabled {
+            Vec::new()
+        } else {
+            self.post.passes.clone()
+        };
+        // Dimming goes last, so it darkens the night light's colors too.
+        let dim = self.idle.dimmed().then(|| PostPass::Gamma {
+            gamma: 1.0,
+            brightness: self.idle.dim_brightness(),
+        });
+        passes.truncate(MAX_PASSES - night_light.is_some() as usize - dim.is_some() as usize);
+        passes.extend(night_light);
+        passes.extend(dim);
+        passes
+    }
+
+    pub fn night_light_config(&self) -> &NightLightConfig {
+        self.night_light.config()
+    }
+
+    pub fn set_night_light_config(&mut self, config: NightLightConfig) {
+        self.night_light.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Holds outputs called `output`, or all of them, at `kelvin`, fading there; `None`
+    /// returns them to the night light schedule.
+    pub fn set_color_temperature(
+        &mut self,
+        output: Option<&str>,
+        kelvin: Option<f32>,
+    ) -> Result<(), String> {
+        self.night_light.set_kelvin(output, kelvin)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Lets the night light follow the sun; called every frame clock tick, it only does
+    /// work about once a minute.
+    pub fn update_night_light(&mut self, now: Instant) {
+        if self.night_light.update_schedule(now) {
+            self.generation += 1;
+        }
+    }
+
+    pub fn color_config(&self) -> &ColorConfig {
+        &self.color
+    }
+
+    /// Takes `config`, building every output's lookup table; outputs whose profile
+    /// can't be loaded are left uncorrected, and named in the error.
+    pub fn set_color_config(&mut self, config: ColorConfig) -> Result<(), String> {
+        let mut errors = Vec::new();
+        self.color_luts.clear();
+        for (output, color) in &config.outputs {
+            match ColorLut::build(color) {
+                Ok(lut) => {
+                    self.color_luts.extend(lut.map(|lut| (output.clone(), Arc::new(lut))));
+                }
+                Err(e) => errors.push(format!("{}: {}", output, e)),
+            }
+        }
+        self.color = config;
+        self.generation += 1;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Corrects outputs called `output` for the ICC profile at `path`, or stops
+    /// applying one; the last profile stays if this one can't be loaded.
+    pub fn set_icc_profile(&mut self, output: &str, path: Option<PathBuf>) -> Result<(), String> {
+        let color = OutputColor {
+            icc: path,
+            ..self.color.outputs.get(output).cloned().unwrap_or_default()
+        };
+        match ColorLut::build(&color)? {
+            Some(lut) => self.color_luts.insert(output.to_string(), Arc::new(lut)),
+            None => self.color_luts.remove(output),
+        };
+        self.color.outputs.insert(output.to_string(), color);
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// The lookup table `output`'s frames go through last, if it has one.
+    pub fn color_lut(&self, output: NodeId) -> Option<Arc<ColorLut>> {
+        match self.nodes.get(&output).map(|node| &node.kind) {
+            Some(NodeKind::Output { name, .. }) => self.color_luts.get(name).cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn zoom_config(&self) -> &ZoomConfig {
+        self.zoom.config()
+    }
+
+    pub fn set_zoom_config(&mut self, config: ZoomConfig) {
+        self.zoom.set_config(config);
+        self.generation += 1;
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom.zoom_in(self.pointer);
+        self.generation += 1;
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom.zoom_out();
+        self.generation += 1;
+    }
+
+    pub fn toggle_zoom(&mut self) {
+        self.zoom.toggle(self.pointer);
+        self.generation += 1;
+    }
+
+    /// Records the pointer at `x`, `y` in root window coordinates.
+    pub fn set_pointer(&mut self, x: f32, y: f32) {
+        self.report_activity(Instant::now());
+        self.pointer = Some([x, y]);
+        // A dragged pinned window follows the pointer without easing after it.
+        if let Some(window) = self.pins.drag([x, y]) {
+            self.show_pinned(window);
+            self.animator.cancel(window);
+        }
+        // A cursor drawn here moves with it, and so does a window dragged on the pager.
+        // Resting on the bar's buttons and miniatures opens their previews.
+        let under = self.bar_under([x, y]);
+        let under = under
+            .as_ref()
+            .map(|(bounds, windows)| (*bounds, windows.as_slice()));
+        let dragging = self.bar.pointer_moved([x, y], under, Instant::now());
+        let drawing = self.annotator.dragging() || self.selector.active();
+        if self.zoom.pointer_moved([x, y]) || self.cursor.drawn() || dragging || drawing {
+            self.generation += 1;
+        }
+    }
+
+    /// Records the pointer at `x`, `y` in the pixels of `output`.
+    pub fn set_output_pointer(&mut self, output: NodeId, x: f32, y: f32) {
+        if let Some((left, top, ..)) = self.output_rect(output) {
+            let scale = self.output_scale(output);
+            self.set_pointer(left as f32 + x / scale, top as f32 + y / scale);
+        }
+    }
+
+    /// Magnifies `output`, whose root window rect is `bounds`, if it holds the point
+    /// zoom is centered on, keeping that point in place.
+    fn zoom_transform(&self, output: NodeId, bounds: Rect) -> Transform {
+        let Some((level, center)) = self.zoom.view() else {
+            return Transform::IDENTITY;
+        };
+        let [x, y, width, height] = bounds;
+        // Without a pointer to go by, each output zooms around its middle.
+        let [cx, cy] = center.unwrap_or([x + width / 2.0, y + height / 2.0]);
+        if cx < x || cy < y || cx >= x + width || cy >= y + height {
+            return Transform::IDENTITY;
+        }
+        let scale = self.output_scale(output);
+        let (px, py) = ((cx - x) * scale, (cy - y) * scale);
+        Transform {
+            x: px * (1.0 - level),
+            y: py * (1.0 - level),
+            scale_x: level,
+            scale_y: level,
+        }
+    }
+
+    pub fn cursor_config(&self) -> &CursorConfig {
+        self.cursor.config()
+    }
+
+    pub fn set_cursor_config(&mut self, config: CursorConfig) -> Result<(), String> {
+        self.generation += 1;
+        self.cursor.set_config(config)
+    }
+
+    pub fn set_cursor_theme(&mut self, theme: &str) -> Result<(), String> {
+        self.cursor.set_theme(theme)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn set_cursor_size(&mut self, size: u32) -> Result<(), String> {
+        self.cursor.set_size(size)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Shows the cursor's busy pointer while the WM waits for an application it
+    /// launched, or the plain one again.
+    pub fn set_busy(&mut self, busy: bool) {
+        if self.cursor.set_busy(busy) {
+            self.generation += 1;
+        }
+    }
+
+    /// Whether the compositor draws the cursor, so render loops hide the X server's.
+    pub fn cursor_drawn(&self) -> bool {
+        self.cursor.drawn()
+    }
+
+    /// The cursor on `output`, whose root window rect is `bounds`, if the pointer is there.
+    fn cursor_items(&self, output: NodeId, bounds: Rect) -> Vec<DrawItem> {
+        let [x, y, width, height] = bounds;
+        match self.pointer {
+            Some([px, py]) if px >= x && py >= y && px < x + width && py < y + height => {
+                let scale = self.output_scale(output);
+                self.cursor.items((px - x) * scale, (py - y) * scale, scale)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn loupe_items(&self, output: NodeId, bounds: Rect) -> Vec<DrawItem> {
+        let [x, y, width, height] = bounds;
+        match self.pointer {
+            Some([px, py]) if px >= x && py >= y && px < x + width && py < y + height => {
+                let scale = self.output_scale(output);
+                self.picker.items(
+                    [(px - x) * scale, (py - y) * scale],
+                    [width * scale, height * scale],
+                    scale,
+                    self.font.as_ref(),
+                )
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Has a loupe follow the pointer until a click picks a color for `reply`.
+    pub fn pick_color(&mut self, reply: PickReply) {
+        if self.lock.locked() {
+            let _ = reply.send(Err("the screen is locked".into()));
+            return;
+        }
+        if self.annotator.busy() {
+            let _ = reply.send(Err("annotating a screenshot".into()));
+            return;
+        }
+        if self.selector.active() {
+            let _ = reply.send(Err("selecting a region".into()));
+            return;
+        }
+        self.picker.start(reply);
+        self.generation += 1;
+    }
+
+    pub fn picking(&self) -> bool {
+        self.picker.active()
+    }
+
+    /// Picks the color under the pointer; returns whether a color was being picked,
+    /// in which case the click is the compositor's.
+    pub fn picker_clicked(&mut self) -> bool {
+        self.picker.click();
+        self.picker.active()
+    }
+
+    /// Stops picking a color; returns whether one was being picked.
+    pub fn cancel_picking(&mut self) -> bool {
+        if !self.picker.active() {
+            return false;
+        }
+        self.picker.cancel("cancelled");
+        self.generation += 1;
+        true
+    }
+
+    /// The output under the pointer, the pixel of it the pointer is on, and the
+    /// pointer, if the loupe needs the pixels around it read.
+    pub fn picker_target(&mut self, now: Instant) -> Option<(NodeId, [i32; 2], [f32; 2])> {
+        if self.picker.prune() {
+            self.generation += 1;
+        }
+        if self.picker.active() && self.lock.locked() {
+            self.picker.cancel("the screen is locked");
+            self.generation += 1;
+        }
+        let [px, py] = self.pointer?;
+        if !self.picker.due([px, py], now) {
+            return None;
+        }
+        let output = self.output_at(px as i32, py as i32)?;
+        let (x, y, ..) = self.output_rect(output)?;
+        let scale = self.output_scale(output);
+        let pixel = [
+            ((px - x as f32) * scale).floor() as i32,
+            ((py - y as f32) * scale).floor() as i32,
+        ];
+        Some((output, pixel, [px, py]))
+    }
+
+    /// Shows the pixels read around the pointer in the loupe, or picks from them after
+    /// a click; picking ends if they couldn't be read.
+    pub fn set_picker_sample(&mut self, sample: Result<Sample, String>) {
+        let changed = match sample {
+            Ok(sample) => self.picker.set_sample(sample),
+            Err(e) => {
+                self.picker.cancel(&e);
+                true
+            }
+        };
+        if changed {
+            self.generation += 1;
+        }
+    }
+
+    pub fn select_config(&self) -> &SelectConfig {
+        self.selector.config()
+    }
+
+    pub fn set_select_config(&mut self, mut config: SelectConfig) {
+        config.retheme(self.theme.palette());
+        self.selector.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Dims the outputs until dragging or a click selects a region for `reply`.
+    pub fn select_region(&mut self, reply: SelectReply) {
+        let busy = if self.lock.locked() {
+            Some("the screen is locked")
+        } else if self.picker.active() {
+            Some("picking a color")
+        } else if self.annotator.busy() {
+            Some("annotating a screenshot")
+        } else {
+            None
+        };
+        if let Some(busy) = busy {
+            let _ = reply.send(Err(busy.into()));
+            return;
+        }
+        self.selector.start(reply);
+        self.generation += 1;
+    }
+
+    pub fn selecting(&self) -> bool {
+        self.selector.active()
+    }
+
+    /// Passes a press or release of the left button to the selection; returns whether
+    /// a region was being selected, in which case the button is the compositor's.
+    pub fn selection_button(&mut self, pressed: bool) -> bool {
+        if !self.selector.active() {
+            return false;
+        }
+        if let Some(pointer) = self.pointer {
+            let windows = self.stacked_windows();
+            let edges = self.snap_edges(&windows);
+            if pressed {
+                self.selector.press(pointer, &edges);
+            } else {
+                self.selector.release(pointer, &windows, &edges);
+            }
+        }
+        self.generation += 1;
+        true
+    }
+
+    /// Stops selecting a region; returns whether one was being selected.
+    pub fn cancel_selecting(&mut self) -> bool {
+        if !self.selector.active() {
+            return false;
+        }
+        self.selector.cancel("cancelled");
+        self.generation += 1;
+        true
+    }
+
+    /// Ends selecting when nobody waits for the region anymore, or the screen locks;
+    /// called every frame clock tick.
+    pub fn update_selection(&mut self) {
+        if self.selector.prune() {
+            self.generation += 1;
+        }
+        if self.selector.active() && self.lock.locked() {
+            self.selector.cancel("the screen is locked");
+            self.generation += 1;
+        }
+    }
+
+    /// The windows shown, bottom first, in root window coordinates.
+    fn stacked_windows(&self) -> Vec<Rect> {
+        let Some(output) = self.outputs.first().and_then(|output| self.nodes.get(output))
+        else {
+            return Vec::new();
+        };
+        let mut windows = Vec::new();
+        for layer in self.sorted_children(output) {
+            for child in self.sorted_children(&self.nodes[&layer]) {
+                let node = &self.nodes[&child];
+                if let NodeKind::Window { width, height, .. } = node.kind {
+                    if node.visible {
+                        let [x, y] = [node.transform.x, node.transform.y];
+                        windows.push([x, y, width as f32, height as f32]);
+                    }
+                }
+            }
+        }
+        windows
+    }
+
+    /// The edges of `windows` and of every output, which selections snap to.
+    fn snap_edges(&self, windows: &[Rect]) -> Edges {
+        let outputs = self.outputs.iter().filter_map(|output| {
+            let (x, y, width, height) = self.output_rect(*output)?;
+            Some([x as f32, y as f32, width as f32, height as f32])
+        });
+        Edges::of(windows.iter().copied().chain(outputs))
+    }
+
+    fn selection_items(&self, output: NodeId, bounds: Rect) -> Vec<DrawItem> {
+        let windows = self.stacked_windows();
+        let edges = self.snap_edges(&windows);
+        let selection = self
+            .pointer
+            .and_then(|pointer| self.selector.selection(pointer, &windows, &edges));
+        self.selector.items(
+            bounds,
+            self.output_scale(output),
+            self.pointer,
+            selection,
+            self.font.as_ref(),
+        )
+    }
+
+    pub fn annotate_config(&self) -> &AnnotateConfig {
+        self.annotator.config()
+    }
+
+    pub fn set_annotate_config(&mut self, mut config: AnnotateConfig) {
+        config.retheme(self.theme.palette());
+        self.annotator.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Queues an Annotate command for a render loop to freeze its output.
+    pub fn annotate(&mut self, request: AnnotateRequest) {
+        if self.lock.locked() {
+            let _ = request.reply.send(Err("the screen is locked".into()));
+            return;
+        }
+        if self.picker.active() {
+            let _ = request.reply.send(Err("picking a color".into()));
+            return;
+        }
+        if self.selector.active() {
+            let _ = request.reply.send(Err("selecting a region".into()));
+            return;
+        }
+        self.annotator.request(request);
+    }
+
+    /// Takes the Annotate commands waiting for their outputs to freeze.
+    pub fn take_annotation_requests(&mut self) -> Vec<AnnotateRequest> {
+        self.annotator.take_requests()
+    }
+
+    /// The output an Annotate command for `name` freezes: the one called that, or else
+    /// the one under the pointer, or the first.
+    pub fn annotation_output(&self, name: Option<&str>) -> Option<NodeId> {
+        if name.is_some() {
+            return self.output_named(name);
+        }
+        self.pointer
+            .and_then(|[x, y]| self.output_at(x as i32, y as i32))
+            .or_else(|| self.output_named(None))
+    }
+
+    /// Freezes `output` on `frame`, its composited frame, for drawing on until the
+    /// annotation is saved or copied for `reply`.
+    pub fn start_annotation(&mut self, output: NodeId, frame: &Capture, reply: AnnotateReply) {
+        let Some((x, y, width, height)) = self.output_rect(output) else {
+            let _ = reply.send(Err("the output went away".into()));
+            return;
+        };
+        let bounds = [x as f32, y as f32, width as f32, height as f32];
+        let scale = self.output_scale(output);
+        self.annotator.start(output, bounds, scale, frame, reply);
+        self.generation += 1;
+    }
+
+    /// The output being annotated, which takes the keyboard and the left button.
+    pub fn annotating(&self) -> Option<NodeId> {
+        self.annotator.output()
+    }
+
+    /// Passes a press or release of the left button to the annotation; returns whether
+    /// there is one, in which case the button is the compositor's.
+    pub fn annotation_button(&mut self, pressed: bool) -> bool {
+        let taken = self.annotator.button(pressed, self.pointer);
+        if taken {
+            self.generation += 1;
+        }
+        taken
+    }
+
+    /// Passes a typed character to the annotation; returns whether there is one.
+    pub fn annotation_typed(&mut self, c: char) -> bool {
+        let taken = self.annotator.typed(c);
+        if taken {
+            self.generation += 1;
+        }
+        taken
+    }
+
+    /// The annotation saved or copied, to render and hand back; annotations end
+    /// without one when the screen locks or nobody waits for them anymore.
+    pub fn take_finished_annotation(&mut self) -> Option<Rendering> {
+        if self.annotator.prune() {
+            self.generation += 1;
+        }
+        if self.annotator.output().is_some() && self.lock.locked() {
+            self.annotator.cancel("the screen is locked");
+            self.generation += 1;
+        }
+        let rendering = self.annotator.take_finished(self.font.as_ref())?;
+        self.generation += 1;
+        Some(rendering)
+    }
+
+    pub fn wallpaper_config(&self) -> &WallpaperConfig {
+        self.wallpapers.config()
+    }
+
+    /// Takes `config`; outputs whose wallpaper can't be shown are left blank.
+    pub fn set_wallpaper_config(&mut self, config: WallpaperConfig) -> Result<(), String> {
+        self.generation += 1;
+        self.wallpapers.set_config(config)
+    }
+
+    /// Shows the image, directory of images, or video at `path` under the output called
+    /// `output`, or under outputs without their own without one.
+    pub fn set_wallpaper(
+        &mut self,
+        output: Option<&str>,
+        path: Option<PathBuf>,
+    ) -> Result<(), String> {
+        self.wallpapers.set(output, path)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Takes decoded wallpapers and video frames, and moves slideshows on by `now`.
+    pub fn update_wallpapers(&mut self, now: Instant) {
+        if self.wallpapers.update(now) {
+            self.generation += 1;
+        }
+    }
+
+    /// Has the bar's widgets read what is due at `now`.
+    pub fn update_bar(&mut self, now: Instant) {
+        if self.bar.update(now, self.font.as_ref()) {
+            self.generation += 1;
+        }
+    }
+
+    pub fn osd_config(&self) -> &OsdConfig {
+        self.osds.config()
+    }
+
+    pub fn set_osd_config(&mut self, mut config: OsdConfig) {
+        config.retheme(self.theme.palette());
+        self.osds.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Shows an OSD on every output for `timeout`, or the configured time.
+    pub fn show_osd(
+        &mut self,
+        icon: Option<String>,
+        text: String,
+        gauge: Option<f32>,
+        timeout: Option<Duration>,
+    ) {
+        self.osds.show(icon, text, gauge, timeout, Instant::now());
+        self.generation += 1;
+    }
+
+    pub fn hint_config(&self) -> &HintConfig {
+        self.hints.config()
+    }
+
+    pub fn set_hint_config(&mut self, config: HintConfig) {
+        self.hints.set_config(config);
+        self.generation += 1;
+    }
+
+    pub fn letterbox_config(&self) -> &LetterboxConfig {
+        self.letterboxes.config()
+    }
+
+    pub fn set_letterbox_config(&mut self, config: LetterboxConfig) {
+        self.letterboxes.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Fills `tile`, in root window coordinates, with bars around `window`, which the
+    /// WM keeps centered in it, or stops with `None`.
+    pub fn set_letterbox(&mut self, window: u32, tile: Option<[i32; 4]>) {
+        if self.letterboxes.set(window, tile) {
+            self.generation += 1;
+        }
+    }
+
+    pub fn modal_config(&self) -> &ModalConfig {
+        self.dimmed.config()
+    }
+
+    pub fn set_modal_config(&mut self, config: ModalConfig) {
+        self.dimmed.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Dims `window` while the WM has a modal dialog of its own open, or stops; kept
+    /// while it is unmapped, until the WM says otherwise.
+    pub fn set_dimmed(&mut self, window: u32, dimmed: bool) {
+        if self.dimmed.set(window, dimmed) {
+            self.generation += 1;
+        }
+    }
+
+    /// Draws `window` as just `rects`, x, y, width, height inside it, the WM's
+    /// reading of its X shape, or as all of it again with `None`; kept while it is
+    /// unmapped, until the WM says otherwise.
+    pub fn set_window_shape(&mut self, window: u32, rects: Option<Vec<[i32; 4]>>) {
+        if self.shapes.set(window, rects) {
+            self.generation += 1;
+        }
+    }
+
+    /// Records whether the WM made `window` click-through, so clicks go to what is
+    /// under it; kept while it is unmapped, until the WM says otherwise.
+    pub fn set_click_through(&mut self, window: u32, click_through: bool) {
+        if click_through {
+            self.click_through.insert(window);
+        } else {
+            self.click_through.remove(&window);
+        }
+    }
+
+    pub fn accessibility_config(&self) -> &AccessibilityConfig {
+        self.accessibility.config()
+    }
+
+    pub fn set_accessibility_config(&mut self, config: AccessibilityConfig) {
+        self.accessibility.set_config(config);
+        self.update_reduce_motion();
+        self.generation += 1;
+    }
+
+    pub fn power_config(&self) -> &PowerConfig {
+        self.power.config()
+    }
+
+    pub fn set_power_config(&mut self, config: PowerConfig) {
+        if self.power.set_config(config) {
+            self.power_profile_changed();
+        }
+        self.generation += 1;
+    }
+
+    pub fn power_profile(&self) -> Profile {
+        self.power.profile()
+    }
+
+    /// Follows the power supply, switching profiles if the config has it do so.
+    pub fn set_on_battery(&mut self, on_battery: bool) {
+        if self.power.set_on_battery(on_battery) {
+            self.power_profile_changed();
+        }
+    }
+
+    /// Keeps to `profile` whatever the power supply, or follows it again with `None`.
+    pub fn set_power_profile(&mut self, profile: Option<Profile>) {
+        if self.power.force(profile) {
+            self.power_profile_changed();
+        }
+    }
+
+    fn power_profile_changed(&mut self) {
+        println!("Power profile: {:?}", self.power.profile());
+        self.update_reduce_motion();
+        self.generation += 1;
+    }
+
+    /// Frames each output draws a second at most: the configured cap, or the power
+    /// profile's where that is lower.
+    pub fn max_fps(&self) -> Option<u32> {
+        [self.max_fps, self.power.max_fps()].into_iter().flatten().min()
+    }
+
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.max_fps = max_fps.filter(|&fps| fps > 0);
+        self.generation += 1;
+    }
+
+    fn update_reduce_motion(&self) {
+        let reduce = self.accessibility.config().reduce_motion || !self.power.animations();
+        animation::set_reduce_motion(reduce);
+    }
+
+    /// Announces the focused window, if it changed and its title is known.
+    fn announce_focus(&mut self) {
+        let focused = self.states.values().find(|state| state.focused).map(|state| state.id);
+        let title = focused.and_then(|window| self.bar.title(window));
+        if let Some(title) = self.accessibility.focused(focused, title) {
+            self.osds.show(Some("▣".to_string()), title, None, None, Instant::now());
+        }
+    }
+
+    /// Outlines `rect`, in root window coordinates, where a window will go, for
+    /// `duration`, or the configured time.
+    pub fn show_insert_hint(&mut self, rect: [f32; 4], duration: Option<Duration>) {
+        self.hints.show(rect, duration, Instant::now());
+        self.generation += 1;
+    }
+
+    /// Lists what can follow a key chord typed so far in an OSD, for the timeout with
+    /// it, or has it fade out with `None`.
+    pub fn show_chord_hint(&mut self, hint: Option<(String, Duration)>) {
+        const ICON: &str = "⌨";
+        match hint {
+            Some((text, timeout)) => {
+                let icon = Some(ICON.to_string());
+                self.osds.show(icon, text, None, Some(timeout), Instant::now());
+            }
+            None => self.osds.dismiss(ICON, Instant::now()),
+        }
+        self.generation += 1;
+    }
+
+    /// Outlines `rect`, in root window coordinates, where the window being resized
+    /// will go, or stops with `None`.
+    pub fn set_resize_outline(&mut self, rect: Option<[f32; 4]>) {
+        self.hints.set_outline(rect);
+        self.generation += 1;
+    }
+
+    /// Shows the OSD for a volume or brightness change of the WM's, and the new level
+    /// in the bar.
+    pub fn set_level(&mut self, level: Level) {
+        let (icon, text, gauge) = osd::level_osd(&level);
+        self.show_osd(Some(icon), text, Some(gauge), None);
+        self.bar.set_level(level);
+    }
+
+    /// Takes what the WM reports of the MPRIS player it follows, for the bar.
+    pub fn set_media(&mut self, info: Option<MediaInfo>) {
+        self.bar.set_media(info);
+    }
+
+    pub fn lock_screen(&self) -> &LockScreen {
+        &self.lock
+    }
+
+    pub fn set_lock_config(&mut self, config: LockConfig) {
+        self.lock.set_config(config);
+        self.generation += 1;
+    }
+
+    /// Whether outputs show the lock surface instead of the scene.
+    pub fn locked(&self) -> bool {
+        self.lock.locked()
+    }
+
+    pub fn input_config(&self) -> &InputConfig {
+        &self.input
+    }
+
+    pub fn set_input_config(&mut self, config: InputConfig) {
+        self.slide.set_settle(config.gestures.settle);
+        self.input = config;
+    }
+
+    /// Slides the windows of every output by `offset`, following a swipe.
+    pub fn slide_windows(&mut self, offset: [f32; 2]) {
+        self.slide.follow(offset);
+        self.generation += 1;
+    }
+
+    /// Lets windows slid by a swipe settle back into place.
+    pub fn release_slide(&mut self) {
+        self.slide.release(Instant::now());
+        self.generation += 1;
+    }
+
+    pub fn workspace_config(&self) -> &WorkspaceConfig {
+        self.workspaces.config()
+    }
+
+    pub fn set_workspace_config(&mut self, config: WorkspaceConfig) {
+        self.workspaces.set_config(config);
+    }
+
+    /// Records that outputs called `output`, or every output, show workspace `index`,
+    /// animating away from the windows they show now; the WM sends this before it
+    /// changes which windows are shown.
+    pub fn set_workspace(&mut self, output: Option<&str>, index: i32) -> Result<(), String> {
+        let outputs: Vec<(NodeId, String)> = self
+            .outputs
+            .iter()
+            .filter_map(|id| match &self.nodes[id].kind {
+                NodeKind::Output { name, .. } if output.is_none_or(|o| o == name) => {
+                    Some((*id, name.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        if let (Some(name), true) = (output, outputs.is_empty()) {
+            return Err(format!("no output called {}", name));
+        }
+        let now = Instant::now();
+        for (output, name) in outputs {
+            let Some(forward) = self.workspaces.switch(&name, index) else {
+                continue;
+            };
+            let outgoing = self.normal_layer_items(output);
+            self.workspaces.start(output, outgoing, forward, now);
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn bar_config(&self) -> &BarConfig {
+        self.bar.config()
+    }
+
+    pub fn set_bar_config(&mut self, config: BarConfig) {
+        self.bar.set_config(config);
+        self.bar.retheme(self.theme.palette());
+        self.generation += 1;
+    }
+
+    pub fn theme_config(&self) -> &ThemeConfig {
+        &self.theme
+    }
+
+    pub fn set_theme_config(&mut self, config: ThemeConfig) {
+        self.theme = config;
+        self.theme_changed();
+    }
+
+    /// Switches to the palette named `name`, restyling what is on screen.
+    pub fn set_theme(&mut self, name: &str) -> Result<(), String> {
+        if self.theme.palette_named(name).is_none() {
+            return Err(format!("no palette is named {:?}", name));
+        }
+        self.theme.name = name.to_string();
+        self.theme_changed();
+        Ok(())
+    }
+
+    /// Gives every color naming a palette slot the theme's, from the bar and its
+    /// widgets to OSDs up and the borders of windows shown.
+    fn theme_changed(&mut self) {
+        let palette = *self.theme.palette();
+        self.bar.retheme(&palette);
+        self.osds.retheme(&palette);
+        self.effects.retheme(&palette);
+        self.annotator.retheme(&palette);
+        self.selector.retheme(&palette);
+        self.restyle_borders();
+        self.generation += 1;
+    }
+
+    /// Colors the borders of windows shown as their focus and the effects say.
+    fn restyle_borders(&mut self) {
+        let borders: Vec<(NodeId, bool)> = self
+            .nodes
+            .iter()
+            .filter_map(|(&id, node)| {
+                let NodeKind::Decoration(Decoration::Border { .. }) = node.kind else {
+                    return None;
+                };
+                let NodeKind::Window { id: window, .. } = self.nodes.get(&node.parent?)?.kind
+                else {
+                    return None;
+                };
+                Some((id, self.states.get(&window).is_some_and(|s| s.focused)))
+            })
+            .collect();
+        for (id, focused) in borders {
+            let color = self.border_color(focused);
+            if let Some(NodeKind::Decoration(Decoration::Border { color: border, .. })) =
+                self.nodes.get_mut(&id).map(|node| &mut node.kind)
+            {
+                *border = color;
+            }
+        }
+    }
+
+    fn border_color(&self, focused: bool) -> [f32; 4] {
+        if focused {
+            self.effects.focused_border.get()
+        } else {
+            self.effects.unfocused_border.get()
+        }
+    }
+
+    /// Replaces the workspaces the bar's pager shows, as the WM lists them.
+    pub fn set_workspaces(&mut self, workspaces: Vec<WorkspaceInfo>) {
+        self.bar.set_workspaces(workspaces);
+        // Windows shown now keep their outlines even if the WM didn't list them yet.
+        for state in self.states.values() {
+            if !self.pins.pinned(state.id) {
+                let rect = [
+                    state.x as f32,
+                    state.y as f32,
+                    state.width as f32,
+                    state.height as f32,
+                ];
+                self.bar.saw(state.id, rect);
+            }
+        }
+        self.generation += 1;
+    }
+
+    /// Takes what the bridge reports of a window's client, for the taskbar.
+    pub fn set_window_info(&mut self, info: WindowInfo) {
+        if self.bar.set_window_info(info) {
+            self.announce_focus();
+            self.generation += 1;
+        }
+    }
+
+    /// Takes a press of `button` on the bar, if the pointer is on one; returns whether
+    /// it was, in which case the press is the compositor's.
+    pub fn bar_pressed(&mut self, button: BarButton) -> bool {
+        let Some(pointer) = self.pointer else {
+            return false;
+        };
+        if self.bar.calendar_pressed(pointer) {
+            self.generation += 1;
+            return true;
+        }
+        let Some((bounds, windows)) = self.bar_under(pointer) else {
+            return false;
+        };
+        // A preview the pointer rested on goes away.
+        let pressed = self.bar.press(pointer, bounds, button, &windows);
+        if pressed {
+            self.generation += 1;
+        }
+        pressed
+    }
+
+    /// Whether the bar's calendar popup is open, so Escape puts it away.
+    pub fn calendar_shown(&self) -> bool {
+        self.bar.calendar_shown()
+    }
+
+    /// Puts the bar's calendar popup away, as Escape does; returns whether it was open.
+    pub fn close_calendar(&mut self) -> bool {
+        let closed = self.bar.close_calendar();
+        if closed {
+            self.generation += 1;
+        }
+        closed
+    }
+
+    /// Scrolls the bar's calendar popup by `lines`, down if positive, if the pointer is
+    /// on it; returns whether it was, in which case the scrolling is the compositor's.
+    pub fn calendar_scrolled(&mut self, lines: f32) -> bool {
+        let Some(pointer) = self.pointer else {
+            return false;
+        };
+        let scrolled = self.bar.calendar_scrolled(pointer, lines);
+        if scrolled {
+            self.generation += 1;
+        }
+        scrolled
+    }
+
+    /// Keeps a notification in the history the bar's calendar popup lists.
+    pub fn add_notification(&mut self, notification: Notification) {
+        self.bar.add_notification(notification);
+        self.generation += 1;
+    }
+
+    /// Whether a press on the bar waits for the button to come up.
+    pub fn bar_held(&self) -> bool {
+        self.bar.pressed()
+    }
+
+    /// Ends the press on the bar; returns what it asks the WM to do.
+    pub fn bar_released(&mut self) -> Option<BarAction> {
+        let pointer = self.pointer?;
+        let (bounds, windows) = self.bar_under(pointer).unwrap_or_default();
+        let action = self.bar.release(pointer, bounds, &windows);
+        self.generation += 1;
+        action
+    }
+
+    /// The output at `pointer`, if it shows the bar, with the windows on its taskbar.
+    fn bar_under(&self, [x, y]: [f32; 2]) -> Option<(Rect, Vec<TaskWindow>)> {
+        let output = self.output_at(x as i32, y as i32)?;
+        let name = self.output_name(output)?;
+        if !self.bar.shown_on(&name) {
+            return None;
+        }
+        let (left, top, width, height) = self.output_rect(output)?;
+        let bounds = [left as f32, top as f32, width as f32, height as f32];
+        let windows = self.task_windows(bounds, self.workspaces.current(&name));
+        Some((bounds, windows))
+    }
+
+    /// The texture of `window`, for the bar's previews, or a fill until it has contents.
+    fn thumbnail(&self, window: u32) -> Option<Thumbnail> {
+        let state = self.states.get(&window)?;
+        let source = match self.contents.get(&window) {
+            Some((serial, contents)) => DrawSource::Window {
+                id: window,
+                serial: *serial,
+                contents: contents.clone(),
+                frozen: self.unresponsive.contains(window),
+                part: WHOLE_CONTENTS,
+            },
+            None => DrawSource::Solid,
+        };
+        Some(Thumbnail {
+            source,
+            size: [state.width as f32, state.height as f32],
+        })
+    }
+
+    /// The windows shown on an output at `bounds`, which shows workspace `current`, in
+    /// the order the WM lists them there, then the order they were made in.
+    fn task_windows(&self, bounds: Rect, current: Option<i32>) -> Vec<TaskWindow> {
+        let [x, y, width, height] = bounds;
+        let mut windows: Vec<&WindowState> = self
+            .states
+            .values()
+            .filter(|state| {
+                state.visible
+                    && state.layer == Layer::Normal
+                    && matches!(state.window_type, WindowType::Normal | WindowType::Dialog)
+                    && !self.is_output_window(state.id)
+            })
+            .filter(|state| {
+                let cx = state.x as f32 + state.width as f32 / 2.0;
+                let cy = state.y as f32 + state.height as f32 / 2.0;
+                cx >= x && cy >= y && cx < x + width && cy < y + height
+            })
+            .collect();
+        windows.sort_by_key(|state| (self.bar.listed_at(current, state.id), state.id));
+        windows
+            .into_iter()
+            .map(|state| TaskWindow {
+                window: state.id,
+                focused: state.focused,
+            })
+            .collect()
+    }
+
+    pub fn pip_config(&self) -> &PipConfig {
+        self.pins.config()
+    }
+
+    pub fn set_pip_config(&mut self, config: PipConfig) {
+        self.pins.set_config(config);
+        let pinned: Vec<u32> = self
+            .states
+            .keys()
+            .copied()
+            .filter(|window| self.pins.pinned(*window))
+            .collect();
+        for window in pinned {
+            self.show_pinned(window);
+        }
+    }
+
+    /// Shrinks `window`, or the focused window, into `corner` of its output above
+    /// every other window; pinned already, it moves to that corner and to the top.
+    pub fn pin_window(&mut self, window: Option<u32>, corner: Option<Corner>) -> Result<(), String> {
+        let window = self.window_or_focused(window)?;
+        // Pinned on the output it is shown on, which one pinned already stays on.
+        let shown = &self.states[&window];
+        let output = self
+            .output_at(
+                shown.x + shown.width as i32 / 2,
+                shown.y + shown.height as i32 / 2,
+            )
+            .and_then(|output| self.output_name(output));
+        let (tile, tile_z) = match self.pins.unpin(window) {
+            Some(tile) => tile,
+            None => {
+                let z = self
+                    .outputs
+                    .iter()
+                    .find_map(|output| self.window_node(*output, window))
+                    .map_or(0, |node| self.nodes[&node].z);
+                (shown.clone(), z)
+            }
+        };
+        let z = self.top_z(Layer::Pip);
+        self.pins.pin(tile, tile_z, z, corner, output);
+        self.show_pinned(window);
+        Ok(())
+    }
+
+    /// Returns pinned `window`, or the focused window, to where the WM places it.
+    pub fn unpin_window(&mut self, window: Option<u32>) -> Result<(), String> {
+        let window = self.window_or_focused(window)?;
+        let (tile, tile_z) = self
+            .pins
+            .unpin(window)
+            .ok_or_else(|| format!("window {} is not pinned", window))?;
+        self.upsert_window(&tile, tile_z);
+        Ok(())
+    }
+
+    /// Starts dragging the pinned window under the pointer; returns whether there was
+    /// one, in which case the press is the compositor's.
+    pub fn pointer_pressed(&mut self) -> bool {
+        let Some([x, y]) = self.pointer else {
+            return false;
+        };
+        let grabbed = self
+            .states
+            .values()
+            .filter(|state| self.pins.pinned(state.id) && state.visible)
+            .filter(|state| !self.click_through.contains(&state.id))
+            .filter(|state| {
+                let (left, top) = (state.x as f32, state.y as f32);
+                x >= left
+                    && y >= top
+                    && x < left + state.width as f32
+                    && y < top + state.height as f32
+            })
+            .max_by_key(|state| {
+                self.outputs
+                    .iter()
+                    .find_map(|output| self.window_node(*output, state.id))
+                    .map_or(0, |node| self.nodes[&node].z)
+            })
+            .map(|state| (state.id, [state.x as f32, state.y as f32]));
+        let Some((window, at)) = grabbed else {
+            return false;
+        };
+        self.pins.begin_drag(window, [x, y], at);
+        true
+    }
+
+    /// Drops the pinned window being dragged into the nearest corner of the output
+    /// it is over; returns whether one was dragged.
+    pub fn pointer_released(&mut self) -> bool {
+        let Some(window) = self.pins.dragging() else {
+            return false;
+        };
+        let state = &self.states[&window];
+        let center = [
+            state.x as f32 + state.width as f32 / 2.0,
+            state.y as f32 + state.height as f32 / 2.0,
+        ];
+        let output = self
+            .output_at(center[0] as i32, center[1] as i32)
+            .or_else(|| self.outputs.first().copied());
+        let dropped = output.and_then(|output| {
+            let name = self.output_name(output)?;
+            let (x, y, width, height) = self.output_rect(output)?;
+            let bounds = [x as f32, y as f32, width as f32, height as f32];
+            Some((name, bounds))
+        });
+        if let Some((name, bounds)) = dropped {
+            self.pins.end_drag(center, &name, bounds);
+        }
+        // Snapping animates like any other move.
+        self.show_pinned(window);
+        true
+    }
+
+    /// Records whether the client of `window` answers the WM's pings; windows of
+    /// clients that don't are drawn gray, offering to force them closed.
+    pub fn set_responsive(&mut self, window: u32, responsive: bool) {
+        if !responsive && !self.states.contains_key(&window) {
+            return;
+        }
+        if self.unresponsive.set(window, responsive) {
+            self.generation += 1;
+        }
+    }
+
+    pub fn unresponsive(&self, window: u32) -> bool {
+        self.unresponsive.contains(window)
+    }
+
+    /// The unresponsive window whose force close button is under the pointer.
+    pub fn force_close_at_pointer(&self) -> Option<u32> {
+        let [x, y] = self.pointer?;
+        self.states
+            .values()
+            .filter(|state| state.visible && self.unresponsive.contains(state.id))
+            .find(|state| {
+                let rect = [
+                    state.x as f32,
+                    state.y as f32,
+                    state.width as f32,
+                    state.height as f32,
+                ];
+                let [left, top, width, height] =
+                    Unresponsive::panel(rect, 1.0, 1.0, self.font.as_ref()).button;
+                x >= left && y >= top && x < left + width && y < top + height
+            })
+            .map(|state| state.id)
+    }
+
+    /// `window`, or the focused window without one, if the scene has it.
+    fn window_or_focused(&self, window: Option<u32>) -> Result<u32, String> {
+        match window {
+            Some(window) if self.states.contains_key(&window) => Ok(window),
+            Some(window) => Err(format!("no window {}", window)),
+            None => self
+                .states
+                .values()
+                .find(|state| state.focused)
+                .map(|state| state.id)
+                .ok_or_else(|| "no window is focused".to_string()),
+        }
+    }
+
+    /// Places pinned `window` again, as the WM last placed it.
+    fn show_pinned(&mut self, window: u32) {
+        if let Some((tile, tile_z)) = self.pins.tile(window) {
+            let (tile, tile_z) = (tile.clone(), tile_z);
+            self.upsert_window(&tile, tile_z);
+        }
+    }
+
+    /// How pinned `state` is shown, and its place among pinned windows, or `None` if
+    /// it isn't pinned.
+    fn pinned_state(&mut self, state: &WindowState, z: i32) -> Option<(WindowState, i32)> {
+        let pin_z = self.pins.set_tile(state, z)?;
+        let output = self
+            .pins
+            .output(state.id)
+            .and_then(|name| {
+                self.outputs
+                    .iter()
+                    .copied()
+                    .find(|output| self.output_name(*output).as_deref() == Some(name))
+            })
+            .or_else(|| self.outputs.first().copied())?;
+        let (x, y, width, height) = self.output_rect(output)?;
+        let bounds = [x as f32, y as f32, width as f32, height as f32];
+        Some((self.pins.shown(state.id, bounds)?, pin_z))
+    }
+
+    fn output_name(&self, output: NodeId) -> Option<String> {
+        match &self.nodes.get(&output)?.kind {
+            NodeKind::Output { name, .. } => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// One above the highest window in `layer` on any output.
+    fn top_z(&self, layer: Layer) -> i32 {
+        self.outputs
+            .iter()
+            .filter_map(|output| self.layer(*output, layer))
+            .flat_map(|layer| self.nodes[&layer].children.iter())
+            .map(|child| self.nodes[child].z + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn idle_config(&self) -> &IdleConfig {
+        self.idle.config()
+    }
+
+    pub fn set_idle_config(&mut self, config: IdleConfig) -> Result<(), String> {
+        self.idle.set_config(config)
+    }
+
+    /// Records input at `at`, which ends dimming and powers the displays up.
+    pub fn report_activity(&mut self, at: Instant) {
+        if self.idle.activity(at) {
+            self.generation += 1;
+        }
+    }
+
+    /// Keeps outputs from dimming, locking, or powering down until `uninhibit_idle` is
+    /// called with the returned id.
+    pub fn inhibit_idle(&mut self, reason: String) -> u32 {
+        let (id, brightened) = self.idle.inhibit(reason, Instant::now());
+        if brightened {
+            self.generation += 1;
+        }
+        id
+    }
+
+    pub fn uninhibit_idle(&mut self, id: u32) -> Result<(), String> {
+        self.idle.uninhibit(id, Instant::now())
+    }
+
+    /// Dims outputs or locks the screen once their idle timeouts pass; called every
+    /// frame clock tick.
+    pub fn update_idle(&mut self, now: Instant) {
+        let changes = self.idle.update(now);
+        if changes.dimmed {
+            self.generation += 1;
+        }
+        if changes.lock && !self.locked() {
+            if let Err(e) = self.lock() {
+                eprintln!("Not locking the idle screen: {}", e);
+            }
+        }
+    }
+
+    /// Whether the idle timeout has the displays powered down.
+    pub fn displays_off(&self) -> bool {
+        self.idle.displays_off()
+    }
+
+    pub fn lock(&mut self) -> Result<(), String> {
+        self.lock.lock()?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Types `c` into the lock surface; returns the password once it is complete.
+    pub fn lock_typed(&mut self, c: char) -> Option<String> {
+        if !self.lock.locked() {
+            return None;
+        }
+        self.generation += 1;
+        self.lock.type_char(c)
+    }
+
+    pub fn finish_unlock(&mut self, unlocked: bool) {
+        self.lock.finish(unlocked);
+        self.generation += 1;
+    }
+
+    pub fn monitors(&self) -> &[OutputInfo] {
+        &self.monitors
+    }
+
+    pub fn set_monitors(&mut self, monitors: Vec<OutputInfo>) {
+        self.monitors = monitors;
+        self.generation += 1;
+    }
+
+    /// Queues a screenshot for the next render loop tick.
+    pub fn request_screenshot(&mut self, request: ScreenshotRequest) {
+        self.screenshots.push(request);
+    }
+
+    /// Takes the queued screenshots, dropping those nobody waits for anymore.
+    pub fn take_screenshots(&mut self) -> Vec<ScreenshotRequest> {
+        self.screenshots.retain(|request| !request.reply.is_closed());
+        std::mem::take(&mut self.screenshots)
+    }
+
+    pub fn request_preview(&mut self, request: PreviewRequest) {
+        self.previews.push(request);
+    }
+
+    /// Takes the queued previews, dropping those nobody waits for anymore.
+    pub fn take_previews(&mut self) -> Vec<PreviewRequest> {
+        self.previews.retain(|request| !request.reply.is_closed());
+        std::mem::take(&mut self.previews)
+    }
+
+    /// Draws `window` with its border, as on the first output showing it, scaled down
+    /// to fit in `max_size` pixels; returns the items and the preview's size.
+    pub fn preview_list(
+        &self,
+        window: u32,
+        max_size: [u32; 2],
+    ) -> Result<(Vec<DrawItem>, [u32; 2]), String> {
+        if self.lock.locked() {
+            return Err("the screen is locked".into());
+        }
+        let (output, node) = self
+            .outputs
+            .iter()
+            .find_map(|output| Some((*output, self.window_node(*output, window)?)))
+            .ok_or_else(|| format!("no output shows window {}", window))?;
+        let (x, y, width, height) = self.output_rect(output).ok_or("the output went away")?;
+        let node = &self.nodes[&node];
+        let NodeKind::Window {
+            width: window_width,
+            height: window_height,
+            ..
+        } = node.kind
+        else {
+            unreachable!("window_node only finds windows");
+        };
+        let border = self.states.get(&window).map_or(0.0, |s| s.border_width as f32);
+        let size = [
+            window_width as f32 + 2.0 * border,
+            window_height as f32 + 2.0 * border,
+        ];
+        let k = (max_size[0] as f32 / size[0])
+            .min(max_size[1] as f32 / size[1])
+            .min(1.0);
+        if k <= 0.0 {
+            return Err("the preview would be empty".into());
+        }
+        // The window's border lands at the preview's top left.
+        let transform = Transform {
+            x: (border - node.transform.x) * k,
+            y: (border - node.transform.y) * k,
+            scale_x: k,
+            scale_y: k,
+        };
+        let bounds = [x as f32, y as f32, width as f32, height as f32];
+        let mut items = Vec::new();
+        self.collect(node, bounds, transform, 1.0, &mut items);
+        Ok((items, size.map(|v| ((v * k).round() as u32).max(1))))
+    }
+
+    pub fn screencasts_mut(&mut self) -> &mut Screencasts {
+        &mut self.screencasts
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    /// Records a frame of `output` started at `now`; see `FrameStats::record`.
+    pub fn record_frame(
+        &mut self,
+        output: NodeId,
+        now: Instant,
+        timing: FrameTiming,
+        refresh: Duration,
+        continuous: bool,
+    ) {
+        if let Some(NodeKind::Output { name, .. }) = self.nodes.get(&output).map(|n| &n.kind) {
+            self.stats
+                .record(output, name, now, timing, refresh, continuous);
+        }
+    }
+
+    pub fn shader_dir(&self) -> Option<&Path> {
+        self.shader_dir.as_deref()
+    }
+
+    /// Takes effect when the renderer starts.
+    pub fn set_shader_dir(&mut self, dir: Option<PathBuf>) {
+        self.shader_dir = dir;
+    }
+
+    pub fn font(&self) -> Option<&Arc<Font>> {
+        self.font.as_ref()
+    }
+
+    /// Loads the font at `path`, or a common one installed on the system without one;
+    /// text isn't drawn if neither loads.
+    pub fn set_font(&mut self, path: Option<&Path>) {
+        let font = match path {
+            Some(path) => Font::load(path).map_err(|e| format!("{}: {}", path.display(), e)),
+            None => Font::load_default(),
+        };
+        match font {
+            Ok(font) => {
+                println!("Drawing text in {}.", font.path().display());
+                self.font = Some(Arc::new(font));
+            }
+            Err(e) => {
+                eprintln!("No font for compositor text: {}", e);
+                self.font = None;
+            }
+        }
+        self.generation += 1;
+    }
+
+    pub fn set_hud(&mut self, hud: bool) {
+        self.hud = hud;
+        self.generation += 1;
+    }
+
+    /// Moves the frame clock to `now`; returns whether animations still need frames.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        for (window, clear_contents) in self.animator.advance(now) {
+            self.remove_window_nodes(window);
+            if clear_contents {
+                self.contents.remove(&window);
+            }
+        }
+        let mut fading = false;
+        for output in &self.outputs {
+            if let NodeKind::Output { name, .. } = &self.nodes[output].kind {
+                fading |= self.night_light.advance(*output, name, now).1;
+            }
+        }
+        let zooming = self.zoom.advance(now);
+        // OSDs stay up without redraws of their own, so frames keep coming until they go.
+        let osds = self.osds.advance(now);
+        let hints = self.hints.advance(now);
+        let cursor = self.cursor.advance(now);
+        let sliding = self.slide.advance(now);
+        let switching = self.workspaces.advance(now);
+        let wallpapers = self.wallpapers.advance(now);
+        self.animator.active()
+            || fading
+            || zooming
+            || osds
+            || hints
+            || cursor
+            || sliding
+            || switching
+            || wallpapers
+    }
+
+    /// Adds an output together with one child node per layer.
+    pub fn add_output(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        window: Option<u32>,
+    ) -> NodeId {
+        let output = self.insert_node(
+            None,
+            NodeKind::Output {
+                name: name.to_string(),
+                width,
+                height,
+                window,
+            },
+            0,
+        );
+        let scale = self.scales.get(name).copied().unwrap_or(1.0);
+        if let Some(node) = self.nodes.get_mut(&output) {
+            node.transform.scale_x = scale;
+            node.transform.scale_y = scale;
+        }
+        self.outputs.push(output);
+        for (z, layer) in Layer::ALL.iter().enumerate() {
+            self.insert_node(Some(output), NodeKind::Layer(*layer), z as i32);
+        }
+        output
+    }
+
+    pub fn resize_output(&mut self, output: NodeId, new_width: u32, new_height: u32) {
+        if let Some(Node {
+            kind: NodeKind::Output { width, height, .. },
+            ..
+        }) = self.nodes.get_mut(&output)
+        {
+            *width = new_width;
+            *height = new_height;
+            self.generation += 1;
+        }
+    }
+
+    /// Places `output` at `x`, `y` in root window coordinates, so it shows the
+    /// windows there.
+    pub fn set_output_position(&mut self, output: NodeId, x: i32, y: i32) {
+        if let Some(node) = self.nodes.get_mut(&output) {
+            let scale = node.transform.scale_x;
+            node.transform.x = -x as f32 * scale;
+            node.transform.y = -y as f32 * scale;
+            self.generation += 1;
+        }
+    }
+
+    /// Shows everything on outputs called `name` `scale` times larger, so each one
+    /// covers its size divided by `scale` in root window coordinates.
+    pub fn set_output_scale(&mut self, name: &str, scale: f32) -> Result<(), String> {
+        if !(MIN_OUTPUT_SCALE..=MAX_OUTPUT_SCALE).contains(&scale) {
+            return Err(format!(
+                "{} is not between {} and {}",
+                scale, MIN_OUTPUT_SCALE, MAX_OUTPUT_SCALE
+            ));
+        }
+        self.scales.insert(name.to_string(), scale);
+        for output in self.outputs.clone() {
+            if !matches!(&self.nodes[&output].kind, NodeKind::Output { name: n, .. } if n == name)
+            {
+                continue;
+            }
+            let Some((x, y, ..)) = self.output_rect(output) else {
+                continue;
+            };
+            let node = self.nodes.get_mut(&output).unwrap();
+            node.transform.scale_x = scale;
+            node.transform.scale_y = scale;
+            self.set_output_position(output, x, y);
+        }
+        Ok(())
+    }
+
+    /// How many output pixels one root window pixel covers on `output`.
+    pub fn output_scale(&self, output: NodeId) -> f32 {
+        self.nodes.get(&output).map_or(1.0, |node| node.transform.scale_x)
+    }
+
+    /// Size of `output` in its own pixels.
+    pub fn output_size(&self, output: NodeId) -> Option<(u32, u32)> {
+        match self.nodes.get(&output)?.kind {
+            NodeKind::Output { width, height, .. } => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// The output called `name`, or the first output if no name is given.
+    pub fn output_named(&self, name: Option<&str>) -> Option<NodeId> {
+        self.outputs.iter().copied().find(|output| match name {
+            Some(name) => {
+                matches!(&self.nodes[output].kind, NodeKind::Output { name: n, .. } if n == name)
+            }
+            None => true,
+        })
+    }
+
+    /// Position and size of `output` in root window coordinates.
+    pub fn output_rect(&self, output: NodeId) -> Option<(i32, i32, u32, u32)> {
+        let node = self.nodes.get(&output)?;
+        let scale = node.transform.scale_x;
+        match node.kind {
+            NodeKind::Output { width, height, .. } => Some((
+                (-node.transform.x / scale).round() as i32,
+                (-node.transform.y / scale).round() as i32,
+                (width as f32 / scale).round() as u32,
+                (height as f32 / scale).round() as u32,
+            )),
+            _ => None,
+        }
+    }
+
+    /// The output showing root window position `x`, `y`.
+    pub fn output_at(&self, x: i32, y: i32) -> Option<NodeId> {
+        self.outputs.iter().copied().find(|output| {
+            self.output_rect(*output).is_some_and(|(ox, oy, w, h)| {
+                x >= ox && y >= oy && x < ox + w as i32 && y < oy + h as i32
+            })
+        })
+    }
+
+    /// The pixels last reported for `window`.
+    pub fn window_contents(&self, window: u32) -> Option<&WindowContents> {
+        self.contents.get(&window).map(|(_, contents)| contents)
+    }
+
+    /// Returns the node for `layer` under `output`.
+    pub fn layer(&self, output: NodeId, layer: Layer) -> Option<NodeId> {
+        self.nodes.get(&output)?.children.iter().copied().find(|id| {
+            matches!(self.nodes.get(id), Some(Node { kind: NodeKind::Layer(l), .. }) if *l == layer)
+        })
+    }
+
+    /// Finds the node showing X window `window` under `output`.
+    pub fn window_node(&self, output: NodeId, window: u32) -> Option<NodeId> {
+        let layers = &self.nodes.get(&output)?.children;
+        layers
+            .iter()
+            .flat_map(|layer| self.nodes[layer].children.iter().copied())
+            .find(|id| {
+                matches!(self.nodes[id].kind, NodeKind::Window { id, .. } if id == window)
+            })
+    }
+
+    /// Removes a node and its whole subtree.
+    pub fn remove(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.remove(&id) else {
+            return;
+        };
+        match node.parent {
+            Some(parent) => {
+                if let Some(parent) = self.nodes.get_mut(&parent) {
+                    parent.children.retain(|child| *child != id);
+                }
+            }
+            None => {
+                self.outputs.retain(|output| *output != id);
+                self.stats.remove(id);
+                self.night_light.remove(id);
+                self.workspaces.remove_output(id);
+            }
+        }
+        for child in node.children {
+            self.remove(child);
+        }
+        self.generation += 1;
+    }
+
+    /// Replaces the pixels shown for X window `window`.
+    pub fn set_window_contents(&mut self, window: u32, contents: WindowContents) {
+        let serial = self.generation + 1;
+        self.contents.insert(window, (serial, contents));
+        self.generation = serial;
+        if let Some(waker) = &self.damage_waker {
+            (waker.0)();
+        }
+    }
+
+    /// Forgets the pixels of `window`; it is drawn as a plain fill until replaced.
+    pub fn clear_window_contents(&mut self, window: u32) {
+        // A closing window keeps its last pixels until it has faded out.
+        if self.animator.defer_clear(window) {
+            return;
+        }
+        if self.contents.remove(&window).is_some() {
+            self.generation += 1;
+        }
+    }
+
+    /// Reconciles the X windows of every output with the window manager's view;
+    /// Wayland windows are left alone.
+    ///
+    /// `windows` is in stacking order, bottom first.
+    pub fn sync_windows(&mut self, windows: &[WindowState]) {
+        let mut stale = Vec::new();
+        for output in &self.outputs {
+            for layer in &self.nodes[output].children {
+                stale.extend(self.nodes[layer].children.iter().filter_map(|id| {
+                    match self.nodes[id].kind {
+                        NodeKind::Window { id, .. }
+                            if id < WAYLAND_ID_BASE && !windows.iter().any(|w| w.id == id) =>
+                        {
+                            Some(id)
+                        }
+                        _ => None,
+                    }
+                }));
+            }
+        }
+        stale.sort_unstable();
+        stale.dedup();
+        for window in stale {
+            self.remove_window(window);
+        }
+        for (z, state) in windows.iter().enumerate() {
+            self.upsert_window(state, z as i32);
+        }
+    }
+
+    /// Adds or updates one window in its layer of every output.
+    pub fn upsert_window(&mut self, state: &WindowState, z: i32) {
+        if self.is_output_window(state.id) {
+            return;
+        }
+        // The WM's placement of a pinned window is kept for when it is unpinned.
+        let pinned = self.pinned_state(state, z);
+        let (state, z) = match &pinned {
+            Some((shown, pin_z)) => (shown, *pin_z),
+            None => (state, z),
+        };
+        let previous = self.window_rect(state.id);
+        let rect = [
+            state.x as f32,
+            state.y as f32,
+            state.width as f32,
+            state.height as f32,
+        ];
+        if pinned.is_none() {
+            self.bar.saw(state.id, rect);
+        }
+        self.animator.update(state.id, state.window_type, previous, rect);
+        // Windows a workspace switch brings in arrive with their workspace.
+        if previous.is_none() && self.workspaces.switching() {
+            self.animator.cancel(state.id);
+        } else if previous.is_none()
+            && self.hints.config().on_map
+            && matches!(state.window_type, WindowType::Normal | WindowType::Dialog)
+        {
+            self.hints.show(rect, None, Instant::now());
+        }
+        self.states.insert(state.id, state.clone());
+        for output in self.outputs.clone() {
+            let Some(layer) = self.layer(output, state.layer) else {
+                continue;
+            };
+            let node = match self.window_node(output, state.id) {
+                Some(node) if self.nodes[&node].parent == Some(layer) => node,
+                existing => {
+                    // Windows change layers by being made again in the new one.
+                    if let Some(node) = existing {
+                        self.remove(node);
+                    }
+                    let kind = NodeKind::Window {
+                        id: state.id,
+                        width: state.width,
+                        height: state.height,
+                    };
+                    self.insert_node(Some(layer), kind, z)
+                }
+            };
+            self.update_window(node, state, z);
+        }
+        if state.focused {
+            self.announce_focus();
+        }
+    }
+
+    /// Removes one window from every output, once it has animated out.
+    pub fn remove_window(&mut self, window: u32) {
+        self.pins.unpin(window);
+        self.unresponsive.remove(window);
+        self.letterboxes.set(window, None);
+        // Windows a workspace switch takes away leave with their workspace.
+        if self.workspaces.switching() {
+            self.animator.cancel(window);
+        } else if self.animator.close(window) {
+            self.generation += 1;
+            return;
+        }
+        self.remove_window_nodes(window);
+    }
+
+    /// Adds surface `id`, an X or Wayland window whose contents it shows, on top of
+    /// `layer`.
+    pub fn create_surface(
+        &mut self,
+        id: u32,
+        geometry: SurfaceGeometry,
+        layer: Layer,
+    ) -> Result<(), String> {
+        if self.states.contains_key(&id) {
+            return Err(format!("surface {} already exists", id));
+        }
+        let z = self.top_z(layer);
+        let state = WindowState {
+            id,
+            x: geometry.x,
+            y: geometry.y,
+            width: geometry.width,
+            height: geometry.height,
+            border_width: 0,
+            focused: false,
+            opacity: 1.0,
+            window_type: WindowType::Normal,
+            fullscreen: false,
+            blur: false,
+            layer,
+            visible: true,
+        };
+        self.upsert_window(&state, z);
+        Ok(())
+    }
+
+    pub fn destroy_surface(&mut self, id: u32) -> Result<(), String> {
+        self.surface_state(id)?;
+        self.remove_window(id);
+        Ok(())
+    }
+
+    pub fn move_surface(&mut self, id: u32, geometry: SurfaceGeometry) -> Result<(), String> {
+        self.edit_surface(id, |state| {
+            state.x = geometry.x;
+            state.y = geometry.y;
+            state.width = geometry.width;
+            state.height = geometry.height;
+        })
+    }
+
+    pub fn set_surface_opacity(&mut self, id: u32, opacity: f32) -> Result<(), String> {
+        self.edit_surface(id, |state| state.opacity = opacity.clamp(0.0, 1.0))
+    }
+
+    pub fn set_surface_visible(&mut self, id: u32, visible: bool) -> Result<(), String> {
+        self.edit_surface(id, |state| state.visible = visible)
+    }
+
+    fn surface_state(&self, id: u32) -> Result<&WindowState, String> {
+        self.states
+            .get(&id)
+            .ok_or_else(|| format!("no surface {}", id))
+    }
+
+    /// Changes the last reported state of surface `id` in place, keeping its stacking.
+    fn edit_surface(
+        &mut self,
+        id: u32,
+        edit: impl FnOnce(&mut WindowState),
+    ) -> Result<(), String> {
+        let mut state = self.surface_state(id)?.clone();
+        edit(&mut state);
+        let z = self
+            .outputs
+            .iter()
+            .find_map(|output| self.window_node(*output, id))
+            .map_or(0, |node| self.nodes[&node].z);
+        self.upsert_window(&state, z);
+        Ok(())
+    }
+
+    /// Whether `window` presents one of the outputs.
+    fn is_output_window(&self, window: u32) -> bool {
+        self.outputs.iter().any(|output| {
+            matches!(self.nodes[output].kind, NodeKind::Output { window: w, .. } if w == Some(window))
+        })
+    }
+
+    /// Where `window` currently rests, taken from its first node.
+    fn window_rect(&self, window: u32) -> Option<Rect> {
+        self.outputs.iter().find_map(|output| {
+            let node = &self.nodes[&self.window_node(*output, window)?];
+            let NodeKind::Window { width, height, .. } = node.kind else {
+                return None;
+            };
+            Some([node.transform.x, node.transform.y, width as f32, height as f32])
+        })
+    }
+
+    fn remove_window_nodes(&mut self, window: u32) {
+        self.states.remove(&window);
+        self.bar.forget(window);
+        for output in self.outputs.clone() {
+            if let Some(node) = self.window_node(output, window) {
+                self.remove(node);
+            }
+        }
+    }
+
+    /// Flattens the subtree of `output` into draw order, bottom first.
+    pub fn draw_list(&self, output: NodeId) -> Vec<DrawItem> {
+        let mut items = self.frame_list(output);
+        if let Some((x, y, width, height)) = self.output_rect(output) {
+            let bounds = [x as f32, y as f32, width as f32, height as f32];
+            // The loupe stands in for the cursor while picking a color, and the
+            // crosshair while selecting a region.
+            if self.picker.active() {
+                items.extend(self.loupe_items(output, bounds));
+            } else if self.selector.active() {
+                items.extend(self.selection_items(output, bounds));
+            } else {
+                items.extend(self.cursor_items(output, bounds));
+            }
+        }
+        items
+    }
+
+    /// What `draw_list` draws under the cursor and the color picker's loupe, which is
+    /// what colors are picked from.
+    pub fn frame_list(&self, output: NodeId) -> Vec<DrawItem> {
+        let mut items = Vec::new();
+        if let (Some(node), Some((x, y, width, height))) =
+            (self.nodes.get(&output), self.output_rect(output))
+        {
+            let bounds = [x as f32, y as f32, width as f32, height as f32];
+            // Nothing of the scene shows through the lock surface.
+            if self.lock.locked() {
+                let scale = node.transform.scale_x;
+                return self
+                    .lock
+                    .items(width as f32 * scale, height as f32 * scale, scale);
+            }
+            // An output being annotated stays on the frame it froze on.
+            let annotation = self.annotator.items(output, self.pointer, self.font.as_ref());
+            if let Some(annotation) = annotation {
+                return annotation;
+            }
+            // Windows are placed in root coordinates; the output's transform moves its
+            // origin to the top left and scales them into its pixels.
+            let zoom = self.zoom_transform(output, bounds);
+            let scale = node.transform.scale_x;
+            let name = self.output_name(output);
+            let size = [width as f32 * scale, height as f32 * scale];
+            items.extend(
+                self.wallpapers
+                    .items(name.as_deref(), size)
+                    .into_iter()
+                    .map(|mut item| {
+                        let [x, y, width, height] = item.rect;
+                        item.rect = [
+                            zoom.x + x * zoom.scale_x,
+                            zoom.y + y * zoom.scale_y,
+                            width * zoom.scale_x,
+                            height * zoom.scale_y,
+                        ];
+                        item
+                    }),
+            );
+            self.collect(node, bounds, zoom, 1.0, &mut items);
+            items.extend(self.hints.items(&zoom.then(&node.transform)));
+            if let Some(name) = name.as_deref().filter(|name| self.bar.shown_on(name)) {
+                let current = self.workspaces.current(name);
+                items.extend(self.bar.items(
+                    bounds,
+                    scale,
+                    current,
+                    &self.task_windows(bounds, current),
+                    self.pointer,
+                    self.font.as_ref(),
+                ));
+                items.extend(self.bar.preview_items(
+                    bounds,
+                    scale,
+                    self.font.as_ref(),
+                    |window| self.thumbnail(window),
+                ));
+            }
+            items.extend(self.osds.items(
+                width as f32 * scale,
+                height as f32 * scale,
+                scale,
+                self.font.as_ref(),
+            ));
+            if self.hud {
+                items.extend(self.stats.hud_items(output, scale));
+            }
+        }
+        items
+    }
+
+    fn update_window(&mut self, node: NodeId, state: &WindowState, z: i32) {
+        let border_width = state.border_width as f32;
+        let border_color = self.border_color(state.focused);
+
+        if let Some(window) = self.nodes.get_mut(&node) {
+            window.kind = NodeKind::Window {
+                id: state.id,
+                width: state.width,
+                height: state.height,
+            };
+            window.transform = Transform::translate(state.x as f32, state.y as f32);
+            window.opacity = state.opacity.clamp(0.0, 1.0);
+            window.z = z;
+            window.visible = state.visible;
+        }
+
+        let border = self.nodes[&node].children.iter().copied().find(|id| {
+            matches!(
+                self.nodes[id].kind,
+                NodeKind::Decoration(Decoration::Border { .. })
+            )
+        });
+        let decoration = NodeKind::Decoration(Decoration::Border {
+            width: border_width,
+            color: border_color,
+        });
+        match border {
+            Some(border) if border_width > 0.0 => {
+                if let Some(border) = self.nodes.get_mut(&border) {
+                    border.kind = decoration;
+                }
+            }
+            Some(border) => self.remove(border),
+            None if border_width > 0.0 => {
+                self.insert_node(Some(node), decoration, -1);
+            }
+            None => {}
+        }
+        self.generation += 1;
+    }
+
+    fn insert_node(&mut self, parent: Option<NodeId>, kind: NodeKind, z: i32) -> NodeId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(
+            id,
+            Node {
+                kind,
+                transform: Transform::IDENTITY,
+                opacity: 1.0,
+                z,
+                visible: true,
+                parent,
+                children: Vec::new(),
+            },
+        );
+        if let Some(parent) = parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            parent.children.push(id);
+        }
+        self.generation += 1;
+        id
+    }
+
+    fn sorted_children(&self, node: &Node) -> Vec<NodeId> {
+        let mut children = node.children.clone();
+        children.sort_by_key(|id| self.nodes[id].z);
+        children
+    }
+
+    fn collect(
+        &self,
+        node: &Node,
+        output: Rect,
+        parent_transform: Transform,
+        parent_opacity: f32,
+        items: &mut Vec<DrawItem>,
+    ) {
+        if !node.visible {
+            return;
+        }
+        let (local, animated_opacity) = self.animated(node);
+        let transform = parent_transform.then(&local);
+        if !self.overlaps(node, output, &transform) {
+            return;
+        }
+        let opacity = parent_opacity * node.opacity * animated_opacity;
+        let children = self.sorted_children(node);
+        let (behind, in_front): (Vec<NodeId>, Vec<NodeId>) =
+            children.into_iter().partition(|child| self.nodes[child].z < 0);
+        let outgoing = self.outgoing_items(node, output);
+        let (under, over) = if self.workspaces.outgoing_on_top() {
+            (Vec::new(), outgoing)
+        } else {
+            (outgoing, Vec::new())
+        };
+
+        items.extend(under);
+        if let NodeKind::Window { id, .. } = node.kind {
+            let position = self.states.get(&id).map(|state| [state.x, state.y]);
+            let bars = position
+                .and_then(|position| self.letterboxes.item(id, position, &transform, opacity));
+            items.extend(bars);
+        }
+        if let Some(item) = self.shadow_item(node, output, &transform, opacity) {
+            items.push(item);
+        }
+        for child in behind {
+            self.collect(&self.nodes[&child], output, transform, opacity, items);
+        }
+        if let Some(item) = self.backdrop_item(node, output, &transform, opacity) {
+            items.push(item);
+        }
+        let drawn = self.draw_item(node, output, &transform, opacity);
+        let corner_radius = drawn.as_ref().map_or(0.0, |item| item.corner_radius);
+        match (drawn, &node.kind) {
+            (Some(item), &NodeKind::Window { id, width, height }) => {
+                let veil = self.dimmed.item(id, item.rect, item.corner_radius, opacity);
+                items.extend(self.shapes.pieces(id, [width, height], item));
+                if let Some(veil) = veil {
+                    items.extend(self.shapes.pieces(id, [width, height], veil));
+                }
+            }
+            (drawn, _) => items.extend(drawn),
+        }
+        let window = match node.kind {
+            NodeKind::Window { id, width, height } => Some((
+                id,
+                [
+                    transform.x,
+                    transform.y,
+                    width as f32 * transform.scale_x,
+                    height as f32 * transform.scale_y,
+                ],
+            )),
+            _ => None,
+        };
+        if let Some((_, rect)) = window.filter(|(id, _)| self.unresponsive.contains(*id)) {
+            let panel = Unresponsive::panel(rect, transform.scale_x, opacity, self.font.as_ref());
+            items.extend(panel.items);
+        }
+        for child in in_front {
+            self.collect(&self.nodes[&child], output, transform, opacity, items);
+        }
+        // Over the WM's border, and under the windows above.
+        let focused = |id: &u32| self.states.get(id).is_some_and(|state| state.focused);
+        if let Some((_, rect)) = window.filter(|(id, _)| focused(id)) {
+            items.extend(self.accessibility.outline(rect, corner_radius, transform.scale_x));
+        }
+        items.extend(over);
+    }
+
+    /// The windows `node` showed before its output switched workspaces, if it is that
+    /// output's normal layer and the switch is still animating.
+    fn outgoing_items(&self, node: &Node, output: Rect) -> Vec<DrawItem> {
+        match (&node.kind, node.parent) {
+            (NodeKind::Layer(Layer::Normal), Some(parent)) => {
+                self.workspaces
+                    .outgoing(parent, output, self.root_scale(node))
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// What the normal layer of `output` draws now, in its pixels.
+    fn normal_layer_items(&self, output: NodeId) -> Vec<DrawItem> {
+        let mut items = Vec::new();
+        if let (Some(node), Some(layer), Some((x, y, width, height))) = (
+            self.nodes.get(&output),
+            self.layer(output, Layer::Normal),
+            self.output_rect(output),
+        ) {
+            let bounds = [x as f32, y as f32, width as f32, height as f32];
+            let transform = self.zoom_transform(output, bounds).then(&node.transform);
+            self.collect(&self.nodes[&layer], bounds, transform, 1.0, &mut items);
+        }
+        items
+    }
+
+    /// Whether `node`, with its border and shadow, reaches into `output`; only windows
+    /// are ever left out.
+    fn overlaps(&self, node: &Node, output: Rect, transform: &Transform) -> bool {
+        let NodeKind::Window { id, width, height } = node.kind else {
+            return true;
+        };
+        let border = self.states.get(&id).map_or(0.0, |s| s.border_width as f32);
+        let [offset_x, offset_y] = self.effects.shadow_offset;
+        let margin = border + self.effects.shadow_radius + offset_x.abs().max(offset_y.abs());
+        // `transform` maps into output pixels, whose origin is the output's top left.
+        let x = transform.x - margin * transform.scale_x;
+        let y = transform.y - margin * transform.scale_y;
+        let w = (width as f32 + 2.0 * margin) * transform.scale_x;
+        let h = (height as f32 + 2.0 * margin) * transform.scale_y;
+        let scale = self.root_scale(node);
+        x < output[2] * scale && y < output[3] * scale && x + w > 0.0 && y + h > 0.0
+    }
+
+    /// Scale of the output `node` is drawn on.
+    fn root_scale(&self, node: &Node) -> f32 {
+        let mut node = node;
+        while let Some(parent) = node.parent.and_then(|parent| self.nodes.get(&parent)) {
+            node = parent;
+        }
+        node.transform.scale_x
+    }
+
+    /// The transform and extra opacity of `node` in the current frame.
+    fn animated(&self, node: &Node) -> (Transform, f32) {
+        if let NodeKind::Layer(Layer::Normal) = node.kind {
+            let mut transform = node.transform;
+            let mut opacity = 1.0;
+            if let Some((incoming, faded)) = node.parent.and_then(|output| self.incoming(output)) {
+                transform = transform.then(&incoming);
+                opacity = faded;
+            }
+            if let Some([x, y]) = self.slide.offset() {
+                transform = transform.then(&Transform::translate(x, y));
+            }
+            return (transform, opacity);
+        }
+        let NodeKind::Window { id, width, height } = node.kind else {
+            return (node.transform, 1.0);
+        };
+        if width == 0 || height == 0 {
+            return (node.transform, 1.0);
+        }
+        let (width, height) = (width as f32, height as f32);
+        let frame = self
+            .animator
+            .frame(id, [node.transform.x, node.transform.y, width, height]);
+        let [x, y, w, h] = frame.rect;
+        let transform = Transform {
+            x: x + w * (1.0 - frame.scale) / 2.0,
+            y: y + h * (1.0 - frame.scale) / 2.0,
+            scale_x: frame.scale * w / width,
+            scale_y: frame.scale * h / height,
+        };
+        (transform, frame.opacity)
+    }
+
+    /// How the workspace `output` switches to is moved and faded in the current frame.
+    fn incoming(&self, output: NodeId) -> Option<(Transform, f32)> {
+        let (x, y, width, height) = self.output_rect(output)?;
+        let bounds = [x as f32, y as f32, width as f32, height as f32];
+        self.workspaces.incoming(output, bounds)
+    }
+
+    /// Effects for `window` at rest, before any scaling.
+    fn window_effects(&self, window: u32, output: Rect) -> Option<WindowEffects> {
+        let state = self.states.get(&window)?;
+        // Effects follow the window's rectangle, which a shaped one doesn't fill.
+        if self.shapes.shaped(window) {
+            return Some(WindowEffects {
+                corner_radius: 0.0,
+                blur: false,
+                shadow: false,
+            });
+        }
+        if self.pins.pinned(window) {
+            let effects = self.effects.resolve(state, self.bar.class(window), false);
+            return Some(WindowEffects {
+                corner_radius: self.pins.config().corner_radius,
+                ..effects
+            });
+        }
+        // Windows covering the whole output count as fullscreen even if not marked so.
+        let fullscreen = state.fullscreen || covers(state, output);
+        Some(self.effects.resolve(state, self.bar.class(window), fullscreen))
+    }
+
+    fn corner_radius(&self, window: u32, output: Rect) -> f32 {
+        self.window_effects(window, output)
+            .map_or(0.0, |effects| effects.corner_radius)
+    }
+
+    /// A shadow under `node` and its border, if it is a window that casts one.
+    fn shadow_item(
+        &self,
+        node: &Node,
+        output: Rect,
+        transform: &Transform,
+        opacity: f32,
+    ) -> Option<DrawItem> {
+        let NodeKind::Window { id, width, height } = node.kind else {
+            return None;
+        };
+        let effects = self.window_effects(id, output)?;
+        if !effects.shadow {
+            return None;
+        }
+        let border = self.states[&id].border_width as f32;
+        let spread = self.effects.shadow_radius;
+        let [offset_x, offset_y] = self.effects.shadow_offset;
+        let [r, g, b] = self.effects.shadow_color;
+        let alpha = self.effects.shadow_opacity.clamp(0.0, 1.0) * opacity;
+        let corner_radius = if effects.corner_radius > 0.0 {
+            effects.corner_radius + border
+        } else {
+            0.0
+        };
+        let extent = border + spread;
+        Some(DrawItem {
+            rect: [
+                transform.x + (offset_x - extent) * transform.scale_x,
+                transform.y + (offset_y - extent) * transform.scale_y,
+                (width as f32 + 2.0 * extent) * transform.scale_x,
+                (height as f32 + 2.0 * extent) * transform.scale_y,
+            ],
+            color: [r * alpha, g * alpha, b * alpha, alpha],
+            source: DrawSource::Shadow(spread * transform.scale_x),
+            corner_radius: (corner_radius + spread) * transform.scale_x,
+            border: 0.0,
+        })
+    }
+
+    /// A blurred backdrop under `node`, if it is a window that lets one show through.
+    fn backdrop_item(
+        &self,
+        node: &Node,
+        output: Rect,
+        transform: &Transform,
+        opacity: f32,
+    ) -> Option<DrawItem> {
+        let NodeKind::Window { id, width, height } = node.kind else {
+            return None;
+        };
+        let effects = self.window_effects(id, output)?;
+        let level = self.effects.blur_level().filter(|_| self.power.blur())?;
+        let state = &self.states[&id];
+        let translucent = state.opacity < 1.0
+            || self.contents.get(&id).is_some_and(|(_, c)| !c.opaque);
+        if !effects.blur || !(translucent || state.blur) {
+            return None;
+        }
+        // The window's own opacity only affects the window; fades still apply.
+        let alpha = if node.opacity > 0.0 {
+            opacity / node.opacity
+        } else {
+            0.0
+        };
+        Some(DrawItem {
+            rect: [
+                transform.x,
+                transform.y,
+                width as f32 * transform.scale_x,
+                height as f32 * transform.scale_y,
+            ],
+            color: [alpha; 4],
+            source: DrawSource::Backdrop(level),
+            corner_radius: effects.corner_radius * transform.scale_x,
+            border: 0.0,
+        })
+    }
+
+    fn draw_item(
+        &self,
+        node: &Node,
+        output: Rect,
+        transform: &Transform,
+        opacity: f32,
+    ) -> Option<DrawItem> {
+        let premultiply = |color: [f32; 4]| {
+            let alpha = color[3] * opacity;
+            [color[0] * alpha, color[1] * alpha, color[2] * alpha, alpha]
+        };
+
+        match &node.kind {
+            NodeKind::Window { id, width, height } => {
+                let rect = [
+                    transform.x,
+                    transform.y,
+                    *width as f32 * transform.scale_x,
+                    *height as f32 * transform.scale_y,
+                ];
+                let corner_radius = self.corner_radius(*id, output) * transform.scale_x;
+                Some(match self.contents.get(id) {
+                    Some((serial, contents)) => DrawItem {
+                        rect,
+                        color: [opacity; 4],
+                        source: DrawSource::Window {
+                            id: *id,
+                            serial: *serial,
+                            contents: contents.clone(),
+                            frozen: self.unresponsive.contains(*id),
+                            part: WHOLE_CONTENTS,
+                        },
+                        corner_radius,
+                        border: 0.0,
+                    },
+                    None => DrawItem {
+                        rect,
+                        color: premultiply(WINDOW_FILL),
+                        source: DrawSource::Solid,
+                        corner_radius,
+                        border: 0.0,
+                    },
+                })
+            }
+            NodeKind::Decoration(Decoration::Border { width, color }) => {
+                // Borders are sized from the window they decorate.
+                let parent = node.parent.and_then(|parent| self.nodes.get(&parent))?;
+                let NodeKind::Window {
+                    id,
+                    width: window_width,
+                    height: window_height,
+                } = parent.kind
+                else {
+                    return None;
+                };
+                // A shaped window's border would outline the rectangle it cut away.
+                if self.shapes.shaped(id) {
+                    return None;
+                }
+                // The border follows the window's corners from just outside them.
+                let radius = self.corner_radius(id, output);
+                let corner_radius = if radius > 0.0 {
+                    (radius + width) * transform.scale_x
+                } else {
+                    0.0
+                };
+                // Only a see-through window needs the area under it left empty.
+                let translucent = opacity < 1.0
+                    || self.contents.get(&id).is_some_and(|(_, c)| !c.opaque);
+                Some(DrawItem {
+                    rect: [
+                        transform.x - width * transform.scale_x,
+                        transform.y - width * transform.scale_y,
+                        (window_width as f32 + 2.0 * width) * transform.scale_x,
+                        (window_height as f32 + 2.0 * width) * transform.scale_y,
+                    ],
+                    color: premultiply(*color),
+                    source: DrawSource::Solid,
+                    corner_radius,
+                    border: if translucent {
+                        width * transform.scale_x
+                    } else {
+                        0.0
+                    },
+                })
+            }
+            NodeKind::Output { .. } | NodeKind::Layer(_) => None,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Dimming for windows a modal dialog of their own is open on: the WM reports them
+// while the dialog is mapped, and they are drawn under a veil in the dim color, so the
+// dialog stands out as what takes the input.
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use super::{DrawItem, DrawSource};
+
+/// How windows under modal dialogs look.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModalConfig {
+    /// Drawn over them, not premultiplied; a zero alpha leaves them be.
+    pub dim: [f32; 4],
+}
+
+impl Default for ModalConfig {
+    fn default() -> Self {
+        Self {
+            dim: [0.0, 0.0, 0.0, 0.4],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Dimmed {
+    config: ModalConfig,
+    windows: HashSet<u32>,
+}
+
+impl Dimmed {
+    pub fn config(&self) -> &ModalConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ModalConfig) {
+        self.config = config;
+    }
+
+    /// Dims `window`, or stops; returns whether that changed anything.
+    pub fn set(&mut self, window: u32, dimmed: bool) -> bool {
+        if dimmed {
+            self.windows.insert(window)
+        } else {
+            self.windows.remove(&window)
+        }
+    }
+
+    /// The veil over `window`, covering `rect` in output pixels, if it is dimmed.
+    pub fn item(
+        &self,
+        window: u32,
+        rect: [f32; 4],
+        corner_radius: f32,
+        opacity: f32,
+    ) -> Option<DrawItem> {
+        if !self.windows.contains(&window) || self.config.dim[3] <= 0.0 {
+            return None;
+        }
+        let [r, g, b, a] = self.config.dim;
+        let a = a * opacity;
+        Some(DrawItem {
+            rect,
+            color: [r * a, g * a, b * a, a],
+            source: DrawSource::Solid,
+            corner_radius,
+            border: 0.0,
+        })
+    }
+}
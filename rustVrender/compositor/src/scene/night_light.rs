@@ -0,0 +1,222 @@
+// Night light: warms every output's colors at night, following the sun at a configured
+// location, or as `SetColorTemp` asks, fading between temperatures.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing};
+use super::NodeId;
+
+/// The temperature that leaves colors as they are.
+pub const NEUTRAL_KELVIN: f32 = 6500.0;
+pub const MIN_KELVIN: f32 = 1000.0;
+pub const MAX_KELVIN: f32 = 10000.0;
+
+/// How often the scheduled temperature follows the sun.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sun elevations, in degrees, above which it is day and below which it is night;
+/// the temperature moves between the two in between, through dusk and dawn.
+const DAY_ELEVATION: f64 = 3.0;
+const NIGHT_ELEVATION: f64 = -6.0;
+
+/// When and how much outputs are warmed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightLightConfig {
+    pub day_kelvin: f32,
+    pub night_kelvin: f32,
+    /// Where the sun is followed from, in degrees north and east; without both, the
+    /// temperature only changes through `SetColorTemp`.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// How a change of temperature fades in.
+    pub transition: Curve,
+    /// Outputs, by name, that are never warmed.
+    pub disabled_outputs: Vec<String>,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        Self {
+            day_kelvin: NEUTRAL_KELVIN,
+            night_kelvin: 4500.0,
+            latitude: None,
+            longitude: None,
+            transition: Curve::new(2000, Easing::EaseInOutCubic),
+            disabled_outputs: Vec::new(),
+        }
+    }
+}
+
+/// A fade from one temperature to another.
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+    from: f32,
+    to: f32,
+    start: Instant,
+    /// Where the fade was at the last `advance`.
+    shown: f32,
+}
+
+/// The temperature of every output, and what it is fading towards.
+#[derive(Debug, Default)]
+pub struct NightLight {
+    config: NightLightConfig,
+    /// Set by `SetColorTemp` for every output at once.
+    manual: Option<f32>,
+    /// Set by `SetColorTemp` for single outputs, by name, over `manual`.
+    manual_outputs: HashMap<String, f32>,
+    /// What the sun's position asks for, once the schedule has run.
+    scheduled: Option<f32>,
+    next_schedule: Option<Instant>,
+    fades: HashMap<NodeId, Fade>,
+}
+
+impl NightLight {
+    pub fn config(&self) -> &NightLightConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: NightLightConfig) {
+        self.config = config;
+        self.next_schedule = None;
+        self.scheduled = None;
+    }
+
+    /// Holds outputs called `output`, or every output, at `kelvin`; `None` hands them
+    /// back to the schedule.
+    pub fn set_kelvin(&mut self, output: Option<&str>, kelvin: Option<f32>) -> Result<(), String> {
+        if let Some(kelvin) = kelvin {
+            if !(MIN_KELVIN..=MAX_KELVIN).contains(&kelvin) {
+                return Err(format!(
+                    "{} K is not between {} and {}",
+                    kelvin, MIN_KELVIN, MAX_KELVIN
+                ));
+            }
+        }
+        match (output, kelvin) {
+            (Some(name), Some(kelvin)) => {
+                self.manual_outputs.insert(name.to_string(), kelvin);
+            }
+            (Some(name), None) => {
+                self.manual_outputs.remove(name);
+            }
+            (None, kelvin) => {
+                self.manual = kelvin;
+                self.manual_outputs.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows the sun if a location is configured; returns whether the scheduled
+    /// temperature changed.
+    pub fn update_schedule(&mut self, now: Instant) -> bool {
+        if self.next_schedule.is_some_and(|next| now < next) {
+            return false;
+        }
+        self.next_schedule = Some(now + SCHEDULE_INTERVAL);
+        let (Some(latitude), Some(longitude)) = (self.config.latitude, self.config.longitude)
+        else {
+            return false;
+        };
+        let unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let elevation = solar_elevation(unix, latitude, longitude);
+        let day =
+            ((elevation - NIGHT_ELEVATION) / (DAY_ELEVATION - NIGHT_ELEVATION)).clamp(0.0, 1.0);
+        let kelvin = self.config.night_kelvin
+            + (self.config.day_kelvin - self.config.night_kelvin) * day as f32;
+        // Steps too small to see aren't worth a redraw.
+        let changed = self.scheduled.is_none_or(|s| (s - kelvin).abs() >= 1.0);
+        if changed {
+            self.scheduled = Some(kelvin);
+        }
+        changed
+    }
+
+    /// The temperature outputs called `name` are headed for.
+    fn target(&self, name: &str) -> f32 {
+        if self.config.disabled_outputs.iter().any(|n| n == name) {
+            return NEUTRAL_KELVIN;
+        }
+        self.manual_outputs
+            .get(name)
+            .copied()
+            .or(self.manual)
+            .or(self.scheduled)
+            .unwrap_or(NEUTRAL_KELVIN)
+    }
+
+    /// Moves `output`, called `name`, on to `now`; returns its temperature and whether
+    /// it is still fading.
+    pub fn advance(&mut self, output: NodeId, name: &str, now: Instant) -> (f32, bool) {
+        let target = self.target(name);
+        let duration = self.config.transition.duration();
+        let easing = self.config.transition.easing;
+        let kelvin_at = |fade: &Fade| {
+            let t = if duration.is_zero() {
+                1.0
+            } else {
+                now.duration_since(fade.start).as_secs_f32() / duration.as_secs_f32()
+            };
+            fade.from + (fade.to - fade.from) * easing.apply(t)
+        };
+        // Outputs start at their target; later changes fade from wherever they are.
+        let fade = self.fades.entry(output).or_insert(Fade {
+            from: target,
+            to: target,
+            start: now,
+            shown: target,
+        });
+        if fade.to != target {
+            *fade = Fade {
+                from: kelvin_at(fade),
+                to: target,
+                start: now,
+                shown: fade.shown,
+            };
+        }
+        let fading = now.duration_since(fade.start) < duration && fade.from != fade.to;
+        fade.shown = if fading { kelvin_at(fade) } else { fade.to };
+        (fade.shown, fading)
+    }
+
+    /// The temperature `output` was at when it last advanced.
+    pub fn kelvin(&self, output: NodeId) -> f32 {
+        self.fades
+            .get(&output)
+            .map_or(NEUTRAL_KELVIN, |fade| fade.shown)
+    }
+
+    pub fn remove(&mut self, output: NodeId) {
+        self.fades.remove(&output);
+    }
+}
+
+/// The sun's elevation above the horizon in degrees, `unix` seconds after the epoch,
+/// seen from `latitude` and `longitude` (after the USNO's low-precision formulas).
+pub fn solar_elevation(unix: f64, latitude: f64, longitude: f64) -> f64 {
+    // Days since J2000.0.
+    let d = unix / 86400.0 + 2440587.5 - 2451545.0;
+    let mean_anomaly = (357.529 + 0.98560028 * d).to_radians();
+    let mean_longitude = 280.459 + 0.98564736 * d;
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.00000036 * d).to_radians();
+    let right_ascension =
+        (obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    let sidereal_degrees = (18.697374558 + 24.06570982441908 * d) * 15.0 + longitude;
+    let hour_angle = sidereal_degrees.to_radians() - right_ascension;
+    let latitude = latitude.to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
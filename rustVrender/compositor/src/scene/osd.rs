@@ -0,0 +1,296 @@
+// On-screen displays: short-lived overlays like a volume gauge, a workspace name or a
+// keyboard layout, centered on every output, fading in and out and stacking while more
+// than one is up.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing};
+use super::theme::{Color, Palette};
+use super::{DrawItem, DrawSource};
+use crate::text::{self, Font};
+
+pub use qtilerugo_ipc::renderer::{Control, Level};
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 16.0;
+const SPACING: f32 = 12.0;
+/// Between the icon and the text.
+const ICON_GAP: f32 = 12.0;
+const CORNER_RADIUS: f32 = 12.0;
+const GAUGE_WIDTH: f32 = 200.0;
+const GAUGE_HEIGHT: f32 = 6.0;
+/// Between the text and the gauge below it.
+const GAUGE_GAP: f32 = 10.0;
+/// Icons are drawn this much bigger than text.
+const ICON_SCALE: f32 = 1.5;
+
+/// How OSDs look and how long they stay up.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OsdConfig {
+    /// How long an OSD stays up when `ShowOsd` doesn't say, in milliseconds.
+    pub timeout_ms: u64,
+    /// How OSDs fade in, and out again.
+    pub fade: Curve,
+    /// OSDs up at once; the oldest goes when another one comes.
+    pub max_shown: usize,
+    pub text_px: f32,
+    pub background: Color,
+    pub foreground: Color,
+    pub gauge: Color,
+}
+
+impl Default for OsdConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 1500,
+            fade: Curve::new(150, Easing::EaseOutCubic),
+            max_shown: 4,
+            text_px: 24.0,
+            background: Color::base(0x01, 0.85),
+            foreground: Color::base(0x05, 1.0),
+            gauge: Color::base(0x0D, 1.0),
+        }
+    }
+}
+
+impl OsdConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        for color in [&mut self.background, &mut self.foreground, &mut self.gauge] {
+            color.retheme(palette);
+        }
+    }
+}
+
+/// One OSD on screen.
+#[derive(Debug, Clone)]
+struct Osd {
+    /// Drawn bigger, left of the text; a symbol character, for instance.
+    icon: Option<String>,
+    text: String,
+    /// How full the gauge under the text is, from 0 to 1.
+    gauge: Option<f32>,
+    shown_at: Instant,
+    expires: Instant,
+    /// How far it had faded in, or out, at the last `advance`.
+    opacity: f32,
+}
+
+impl Osd {
+    /// OSDs with the same icon, or without icons the same text, replace each other
+    /// instead of stacking, so holding a volume key keeps one gauge up.
+    fn replaces(&self, icon: Option<&str>, text: &str) -> bool {
+        match (self.icon.as_deref(), icon) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.text == text,
+            _ => false,
+        }
+    }
+}
+
+/// The icon, the text, and the gauge of the OSD showing `level`.
+pub fn level_osd(level: &Level) -> (String, String, f32) {
+    let gauge = level.level.clamp(0.0, 1.0);
+    let percent = (gauge * 100.0).round();
+    match (level.control, level.muted) {
+        (Control::Volume, true) => ("🔇".to_string(), "Muted".to_string(), gauge),
+        (Control::Volume, false) => ("🔊".to_string(), format!("Volume {}%", percent), gauge),
+        (Control::Brightness, _) => {
+            ("☀".to_string(), format!("Brightness {}%", percent), gauge)
+        }
+    }
+}
+
+/// The OSDs on screen, oldest first.
+#[derive(Debug, Default)]
+pub struct Osds {
+    config: OsdConfig,
+    osds: Vec<Osd>,
+}
+
+impl Osds {
+    pub fn config(&self) -> &OsdConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: OsdConfig) {
+        self.config = config;
+    }
+
+    /// Gives OSDs, including those up, the colors of `palette`.
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.config.retheme(palette);
+    }
+
+    /// Puts an OSD up for `timeout`, or the configured time.
+    pub fn show(
+        &mut self,
+        icon: Option<String>,
+        text: String,
+        gauge: Option<f32>,
+        timeout: Option<Duration>,
+        now: Instant,
+    ) {
+        let expires = now + timeout.unwrap_or(Duration::from_millis(self.config.timeout_ms));
+        let gauge = gauge.map(|g| g.clamp(0.0, 1.0));
+        let existing = self
+            .osds
+            .iter_mut()
+            .find(|osd| osd.replaces(icon.as_deref(), &text));
+        match existing {
+            // It stays where it is in the stack, without fading in again.
+            Some(osd) => {
+                osd.icon = icon;
+                osd.text = text;
+                osd.gauge = gauge;
+                osd.expires = expires;
+            }
+            None => {
+                self.osds.push(Osd {
+                    icon,
+                    text,
+                    gauge,
+                    shown_at: now,
+                    expires,
+                    opacity: 0.0,
+                });
+                let excess = self.osds.len().saturating_sub(self.config.max_shown.max(1));
+                self.osds.drain(..excess);
+            }
+        }
+    }
+
+    /// Has the OSD with `icon` fade out from `now`, if one is up.
+    pub fn dismiss(&mut self, icon: &str, now: Instant) {
+        let gone = now + self.config.fade.duration();
+        for osd in &mut self.osds {
+            if osd.icon.as_deref() == Some(icon) {
+                osd.expires = osd.expires.min(gone);
+            }
+        }
+    }
+
+    /// Fades OSDs on to `now` and drops those that expired; returns whether any are
+    /// still up.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        self.osds.retain(|osd| osd.expires > now);
+        let duration = self.config.fade.duration().as_secs_f32();
+        let easing = self.config.fade.easing;
+        for osd in &mut self.osds {
+            osd.opacity = if duration == 0.0 {
+                1.0
+            } else {
+                let fade_in = now.saturating_duration_since(osd.shown_at).as_secs_f32();
+                let fade_out = osd.expires.saturating_duration_since(now).as_secs_f32();
+                easing.apply(fade_in.min(fade_out) / duration)
+            };
+        }
+        !self.osds.is_empty()
+    }
+
+    /// The OSDs stacked in the middle of an output `width` by `height` pixels, newest
+    /// at the bottom; text is left out without a `font`.
+    pub fn items(
+        &self,
+        width: f32,
+        height: f32,
+        scale: f32,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let config = &self.config;
+        let px = config.text_px * scale;
+        let layout = |text: &str, px: f32| font.map(|font| font.layout(text, px));
+        let panels: Vec<_> = self
+            .osds
+            .iter()
+            .map(|osd| {
+                let icon = osd
+                    .icon
+                    .as_deref()
+                    .and_then(|icon| layout(icon, px * ICON_SCALE));
+                let text = layout(&osd.text, px);
+                // The text, with the gauge under it, sits right of the icon.
+                let (mut body_width, mut body_height) =
+                    text.as_ref().map_or((0.0, 0.0), |t| (t.width, t.height));
+                if osd.gauge.is_some() {
+                    if text.is_some() {
+                        body_height += GAUGE_GAP * scale;
+                    }
+                    body_width = body_width.max(GAUGE_WIDTH * scale);
+                    body_height += GAUGE_HEIGHT * scale;
+                }
+                let icon_size = icon.as_ref().map_or([0.0, 0.0], |i| [i.width, i.height]);
+                let gap = if icon.is_some() && body_width > 0.0 {
+                    ICON_GAP * scale
+                } else {
+                    0.0
+                };
+                let content = [
+                    icon_size[0] + gap + body_width,
+                    icon_size[1].max(body_height),
+                ];
+                (osd, icon, text, body_height, content)
+            })
+            .collect();
+
+        let padding = PADDING * scale;
+        let spacing = SPACING * scale;
+        let total = panels
+            .iter()
+            .map(|(.., content)| content[1] + 2.0 * padding)
+            .sum::<f32>()
+            + spacing * panels.len().saturating_sub(1) as f32;
+        let mut top = ((height - total) / 2.0).round();
+        let mut items = Vec::new();
+        for (osd, icon, text, body_height, [content_width, content_height]) in panels {
+            let fade = |[r, g, b, a]: [f32; 4]| [r, g, b, a * osd.opacity];
+            let panel = [
+                content_width + 2.0 * padding,
+                content_height + 2.0 * padding,
+            ];
+            let left = ((width - panel[0]) / 2.0).round();
+            items.push(solid(
+                [left, top, panel[0], panel[1]],
+                fade(config.background.get()),
+                CORNER_RADIUS * scale,
+            ));
+            let (mut x, y) = (left + padding, top + padding);
+            if let (Some(font), Some(icon)) = (font, &icon) {
+                let icon_top = y + (content_height - icon.height) / 2.0;
+                items.extend(text::draw(font, icon, x, icon_top, fade(config.foreground.get())));
+                x += icon.width + ICON_GAP * scale;
+            }
+            let body_top = y + (content_height - body_height) / 2.0;
+            let mut gauge_top = body_top;
+            if let (Some(font), Some(text)) = (font, &text) {
+                items.extend(text::draw(font, text, x, body_top, fade(config.foreground.get())));
+                gauge_top += text.height + GAUGE_GAP * scale;
+            }
+            if let Some(level) = osd.gauge {
+                let [r, g, b, a] = config.foreground.get();
+                let (bar_width, bar_height) = (GAUGE_WIDTH * scale, GAUGE_HEIGHT * scale);
+                let track = [x, gauge_top.round(), bar_width, bar_height];
+                items.push(solid(track, fade([r, g, b, a * 0.25]), bar_height / 2.0));
+                let fill = [x, gauge_top.round(), bar_width * level, bar_height];
+                items.push(solid(fill, fade(config.gauge.get()), bar_height / 2.0));
+            }
+            top += panel[1] + spacing;
+        }
+        items
+    }
+}
+
+/// A rounded rect in `color`, which isn't premultiplied.
+fn solid(rect: [f32; 4], color: [f32; 4], corner_radius: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border: 0.0,
+    }
+}
@@ -0,0 +1,269 @@
+// Color picking: PickColor has a loupe follow the pointer, showing the pixels around
+// it magnified, until a left click picks the one under the pointer, or Escape or any
+// other button cancels. Pixels are read back from the output's composited frame, as a
+// screenshot of it would show them, without the loupe and the cursor.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use super::{DrawItem, DrawSource};
+use crate::screenshot::Capture;
+use crate::text::{self, Font};
+
+/// Pixels shown on each side of the one under the pointer.
+const RADIUS: i32 = 7;
+const CELLS: i32 = 2 * RADIUS + 1;
+/// Sizes at scale 1, in output pixels.
+const CELL: f32 = 8.0;
+/// Between the pointer and the loupe.
+const OFFSET: f32 = 24.0;
+const PADDING: f32 = 6.0;
+const CORNER_RADIUS: f32 = 8.0;
+const TEXT_PX: f32 = 14.0;
+/// Width of the ring around the pixel under the pointer.
+const MARK: f32 = 2.0;
+
+/// Colors, not premultiplied.
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.12, 0.95];
+const FOREGROUND: [f32; 4] = [0.92, 0.92, 0.94, 1.0];
+
+/// Pixels around the pointer are read again this often while it rests, so the loupe
+/// keeps up with what changes under it.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// A picked color, as sRGB bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    pub fn hex(self) -> String {
+        let [r, g, b] = self.0;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// `#rrggbb r g b`, as PickColor replies.
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "{} {} {} {}", self.hex(), r, g, b)
+    }
+}
+
+/// Where a PickColor command waits for its color.
+pub type PickReply = oneshot::Sender<Result<Color, String>>;
+
+/// The pixels around the pointer, read from one frame.
+#[derive(Debug)]
+pub struct Sample {
+    /// Rows of `CELLS` pixels each; `None` past the edges of the output.
+    pixels: Vec<Option<[u8; 3]>>,
+    /// The pointer when they were read, in root window coordinates.
+    at: [f32; 2],
+    taken: Instant,
+}
+
+impl Sample {
+    /// The pixels of `capture` around `center`, in its pixels, read with the pointer
+    /// at `at`.
+    pub fn read(capture: &Capture, center: [i32; 2], at: [f32; 2], now: Instant) -> Self {
+        let mut pixels = Vec::with_capacity((CELLS * CELLS) as usize);
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let (x, y) = (center[0] + dx, center[1] + dy);
+                let inside =
+                    x >= 0 && y >= 0 && (x as u32) < capture.width && (y as u32) < capture.height;
+                pixels.push(inside.then(|| {
+                    let i = (y as usize * capture.width as usize + x as usize) * 4;
+                    [capture.rgba[i], capture.rgba[i + 1], capture.rgba[i + 2]]
+                }));
+            }
+        }
+        Self {
+            pixels,
+            at,
+            taken: now,
+        }
+    }
+
+    /// The pixel under the pointer.
+    fn center(&self) -> Option<Color> {
+        self.pixels[(RADIUS * CELLS + RADIUS) as usize].map(Color)
+    }
+}
+
+/// The PickColor commands waiting for a click, and what the loupe shows.
+#[derive(Debug, Default)]
+pub struct Picker {
+    waiting: Vec<PickReply>,
+    sample: Option<Sample>,
+    /// Whether a click asked for the pixel under the pointer.
+    clicked: bool,
+}
+
+impl Picker {
+    pub fn start(&mut self, reply: PickReply) {
+        self.waiting.push(reply);
+    }
+
+    /// Whether the loupe is up.
+    pub fn active(&self) -> bool {
+        !self.waiting.is_empty()
+    }
+
+    /// Forgets commands nobody waits for anymore; returns whether that ended picking.
+    pub fn prune(&mut self) -> bool {
+        let active = self.active();
+        self.waiting.retain(|reply| !reply.is_closed());
+        if active && !self.active() {
+            self.sample = None;
+            self.clicked = false;
+            return true;
+        }
+        false
+    }
+
+    /// Picks the pixel under the pointer once it has been read.
+    pub fn click(&mut self) {
+        self.clicked = self.active();
+    }
+
+    /// Ends picking, answering every command with `reason`.
+    pub fn cancel(&mut self, reason: &str) {
+        self.finish(Err(reason.to_string()));
+    }
+
+    /// Whether the pixels around the pointer at `pointer` need reading.
+    pub fn due(&self, pointer: [f32; 2], now: Instant) -> bool {
+        self.active()
+            && (self.clicked
+                || self.sample.as_ref().is_none_or(|sample| {
+                    sample.at != pointer || now.saturating_duration_since(sample.taken) >= REFRESH
+                }))
+    }
+
+    /// Shows `sample` in the loupe, or answers with its middle pixel after a click;
+    /// returns whether what is drawn changed.
+    pub fn set_sample(&mut self, sample: Sample) -> bool {
+        if self.clicked {
+            let color = sample
+                .center()
+                .ok_or_else(|| "the pointer is off the output".to_string());
+            self.finish(color);
+            return true;
+        }
+        let changed = self
+            .sample
+            .as_ref()
+            .is_none_or(|shown| shown.pixels != sample.pixels);
+        self.sample = Some(sample);
+        changed
+    }
+
+    fn finish(&mut self, result: Result<Color, String>) {
+        for reply in self.waiting.drain(..) {
+            // The requester may have given up waiting.
+            let _ = reply.send(result.clone());
+        }
+        self.sample = None;
+        self.clicked = false;
+    }
+
+    /// The loupe beside the pointer at `pointer`, in the pixels of an output `size`
+    /// big at `scale`.
+    pub fn items(
+        &self,
+        pointer: [f32; 2],
+        size: [f32; 2],
+        scale: f32,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let Some(sample) = &self.sample else {
+            return Vec::new();
+        };
+        let cell = CELL * scale;
+        let grid = CELLS as f32 * cell;
+        let padding = PADDING * scale;
+        let center = sample.center();
+        let label = font
+            .zip(center)
+            .map(|(font, color)| font.layout(&color.hex(), TEXT_PX * scale));
+        let label_height = label.as_ref().map_or(0.0, |l| l.height + padding);
+        let width = grid + 2.0 * padding;
+        let height = grid + label_height + 2.0 * padding;
+        // Below and to the right of the pointer, unless that leaves the output.
+        let place = |pointer: f32, length: f32, size: f32| {
+            let offset = OFFSET * scale;
+            let after = pointer + offset;
+            if after + length <= size {
+                after
+            } else {
+                (pointer - offset - length).max(0.0)
+            }
+        };
+        let left = place(pointer[0], width, size[0]).round();
+        let top = place(pointer[1], height, size[1]).round();
+
+        let mut items = vec![solid(
+            [left, top, width, height],
+            BACKGROUND,
+            CORNER_RADIUS * scale,
+            0.0,
+        )];
+        let (grid_left, grid_top) = (left + padding, top + padding);
+        for (i, pixel) in sample.pixels.iter().enumerate() {
+            let Some([r, g, b]) = pixel else {
+                continue;
+            };
+            let (column, row) = (i as i32 % CELLS, i as i32 / CELLS);
+            items.push(solid(
+                [
+                    grid_left + column as f32 * cell,
+                    grid_top + row as f32 * cell,
+                    cell,
+                    cell,
+                ],
+                [*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0, 1.0],
+                0.0,
+                0.0,
+            ));
+        }
+        let mark = MARK * scale;
+        items.push(solid(
+            [
+                grid_left + RADIUS as f32 * cell - mark,
+                grid_top + RADIUS as f32 * cell - mark,
+                cell + 2.0 * mark,
+                cell + 2.0 * mark,
+            ],
+            FOREGROUND,
+            0.0,
+            mark,
+        ));
+        if let (Some(font), Some(label)) = (font, &label) {
+            items.extend(text::draw(
+                font,
+                label,
+                left + (width - label.width) / 2.0,
+                grid_top + grid + padding,
+                FOREGROUND,
+            ));
+        }
+        items
+    }
+}
+
+/// A rect in `color`, which isn't premultiplied.
+fn solid(rect: [f32; 4], color: [f32; 4], corner_radius: f32, border: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border,
+    }
+}
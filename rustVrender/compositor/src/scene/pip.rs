@@ -0,0 +1,206 @@
+// Picture-in-picture: a pinned window is shown shrunk into a corner of its output, in
+// a layer above every other window, until it is unpinned back into its tile. The WM
+// keeps placing the window as before; its placement is only kept for the unpin.
+// Pins can be dragged with the pointer, and snap to the nearest corner when dropped.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::animation::Rect;
+use super::{Layer, WindowState};
+
+pub use qtilerugo_ipc::renderer::Corner;
+
+/// How pinned windows are shown.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipConfig {
+    /// Width of a pinned window as a fraction of its output's; its height keeps the
+    /// window's aspect ratio.
+    pub width: f32,
+    /// Space between a pinned window and the edges of its output.
+    pub margin: f32,
+    pub corner_radius: f32,
+    pub opacity: f32,
+    /// Where windows are pinned without a corner given.
+    pub corner: Corner,
+}
+
+impl Default for PipConfig {
+    fn default() -> Self {
+        Self {
+            width: 0.25,
+            margin: 24.0,
+            corner_radius: 12.0,
+            opacity: 1.0,
+            corner: Corner::BottomRight,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pin {
+    /// Where the WM places the window, and its place in the WM's stack.
+    tile: WindowState,
+    tile_z: i32,
+    /// Its place among pinned windows.
+    z: i32,
+    corner: Corner,
+    /// The output it is pinned on; the first one if that is gone.
+    output: Option<String>,
+}
+
+#[derive(Debug)]
+struct Drag {
+    window: u32,
+    /// The pointer, relative to the window's top left when it was grabbed.
+    grab: [f32; 2],
+    /// Where the window's top left is held, in root window coordinates.
+    at: [f32; 2],
+}
+
+/// The pinned windows, and the one being dragged.
+#[derive(Debug, Default)]
+pub struct Pins {
+    config: PipConfig,
+    pins: HashMap<u32, Pin>,
+    drag: Option<Drag>,
+}
+
+impl Pins {
+    pub fn config(&self) -> &PipConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: PipConfig) {
+        self.config = config;
+    }
+
+    /// Pins the window the WM places as `tile`, at `tile_z` in its stack, to `corner`
+    /// of `output`, at `z` among pinned windows.
+    pub fn pin(
+        &mut self,
+        tile: WindowState,
+        tile_z: i32,
+        z: i32,
+        corner: Option<Corner>,
+        output: Option<String>,
+    ) {
+        let pin = Pin {
+            tile,
+            tile_z,
+            z,
+            corner: corner.unwrap_or(self.config.corner),
+            output,
+        };
+        self.pins.insert(pin.tile.id, pin);
+    }
+
+    /// Unpins `window`; returns where the WM places it, and its place in the stack.
+    pub fn unpin(&mut self, window: u32) -> Option<(WindowState, i32)> {
+        if self.dragging() == Some(window) {
+            self.drag = None;
+        }
+        let pin = self.pins.remove(&window)?;
+        Some((pin.tile, pin.tile_z))
+    }
+
+    pub fn pinned(&self, window: u32) -> bool {
+        self.pins.contains_key(&window)
+    }
+
+    /// The output `window` is pinned on.
+    pub fn output(&self, window: u32) -> Option<&str> {
+        self.pins.get(&window)?.output.as_deref()
+    }
+
+    /// Records where the WM now places pinned `window`; returns its place among pinned
+    /// windows, or `None` if it isn't pinned.
+    pub fn set_tile(&mut self, tile: &WindowState, tile_z: i32) -> Option<i32> {
+        let pin = self.pins.get_mut(&tile.id)?;
+        pin.tile = tile.clone();
+        pin.tile_z = tile_z;
+        Some(pin.z)
+    }
+
+    /// Where the WM places pinned `window`, and its place in the stack.
+    pub fn tile(&self, window: u32) -> Option<(&WindowState, i32)> {
+        self.pins.get(&window).map(|pin| (&pin.tile, pin.tile_z))
+    }
+
+    /// How pinned `window` is shown on its output, whose root window coordinates are
+    /// `bounds`.
+    pub fn shown(&self, window: u32, bounds: Rect) -> Option<WindowState> {
+        let pin = self.pins.get(&window)?;
+        let [x, y, width, height] = bounds;
+        let tile = &pin.tile;
+        let margin = self.config.margin;
+        let shown_width = (width * self.config.width).max(1.0);
+        let shown_height =
+            (shown_width * tile.height.max(1) as f32 / tile.width.max(1) as f32).max(1.0);
+        let [left, top] = match &self.drag {
+            Some(drag) if drag.window == window => drag.at,
+            _ => {
+                let left = x + margin;
+                let right = x + width - margin - shown_width;
+                let top = y + margin;
+                let bottom = y + height - margin - shown_height;
+                match pin.corner {
+                    Corner::TopLeft => [left, top],
+                    Corner::TopRight => [right, top],
+                    Corner::BottomLeft => [left, bottom],
+                    Corner::BottomRight => [right, bottom],
+                }
+            }
+        };
+        Some(WindowState {
+            x: left.round() as i32,
+            y: top.round() as i32,
+            width: shown_width.round() as u32,
+            height: shown_height.round() as u32,
+            border_width: 0,
+            opacity: tile.opacity * self.config.opacity,
+            fullscreen: false,
+            layer: Layer::Pip,
+            ..tile.clone()
+        })
+    }
+
+    /// Grabs `window`, shown with its top left at `at`, by the pointer at `pointer`.
+    pub fn begin_drag(&mut self, window: u32, pointer: [f32; 2], at: [f32; 2]) {
+        let grab = [pointer[0] - at[0], pointer[1] - at[1]];
+        self.drag = Some(Drag { window, grab, at });
+    }
+
+    /// The window being dragged.
+    pub fn dragging(&self) -> Option<u32> {
+        self.drag.as_ref().map(|drag| drag.window)
+    }
+
+    /// Moves the window being dragged with the pointer at `pointer`; returns it.
+    pub fn drag(&mut self, pointer: [f32; 2]) -> Option<u32> {
+        let drag = self.drag.as_mut()?;
+        drag.at = [pointer[0] - drag.grab[0], pointer[1] - drag.grab[1]];
+        Some(drag.window)
+    }
+
+    /// Drops the window being dragged, with its middle at `center`, onto the output
+    /// called `output` with root window coordinates `bounds`, snapping it to the
+    /// nearest corner; returns it.
+    pub fn end_drag(&mut self, center: [f32; 2], output: &str, bounds: Rect) -> Option<u32> {
+        let drag = self.drag.take()?;
+        let pin = self.pins.get_mut(&drag.window)?;
+        let [x, y, width, height] = bounds;
+        let left = center[0] < x + width / 2.0;
+        let top = center[1] < y + height / 2.0;
+        pin.corner = match (left, top) {
+            (true, true) => Corner::TopLeft,
+            (false, true) => Corner::TopRight,
+            (true, false) => Corner::BottomLeft,
+            (false, false) => Corner::BottomRight,
+        };
+        pin.output = Some(output.to_string());
+        Some(drag.window)
+    }
+}
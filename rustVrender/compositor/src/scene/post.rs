@@ -0,0 +1,107 @@
+use serde::Deserialize;
+
+/// Most passes one output applies; the rest of a longer chain is dropped.
+pub const MAX_PASSES: usize = 6;
+
+/// Full-screen passes applied to each output's composited frame, in order.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostConfig {
+    pub passes: Vec<PostPass>,
+    /// Outputs, by name, that show the frame without the chain.
+    pub disabled_outputs: Vec<String>,
+}
+
+/// One post-processing pass. Colors are handled as the display shows them, in sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum PostPass {
+    /// Scales colors by `brightness`, then raises them to 1 / `gamma`.
+    Gamma {
+        #[serde(default = "one")]
+        gamma: f32,
+        #[serde(default = "one")]
+        brightness: f32,
+    },
+    Invert,
+    /// Removes `amount` of the color saturation, all of it by default.
+    Grayscale {
+        #[serde(default = "one")]
+        amount: f32,
+    },
+    /// Tints white towards a black body at `kelvin`; 6500 is neutral, lower is warmer.
+    ColorTemperature {
+        kelvin: f32,
+    },
+    /// Darkens every other row by `scanlines` and the corners by `vignette`.
+    Crt {
+        #[serde(default = "default_scanlines")]
+        scanlines: f32,
+        #[serde(default = "default_vignette")]
+        vignette: f32,
+    },
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn default_scanlines() -> f32 {
+    0.3
+}
+
+fn default_vignette() -> f32 {
+    0.4
+}
+
+impl PostPass {
+    /// The pass as `post.frag` reads it: the effect, then up to three parameters.
+    pub fn stage(&self) -> [f32; 4] {
+        match *self {
+            Self::Gamma { gamma, brightness } => [0.0, gamma.max(0.01), brightness.max(0.0), 0.0],
+            Self::Invert => [1.0, 0.0, 0.0, 0.0],
+            Self::Grayscale { amount } => [2.0, amount.clamp(0.0, 1.0), 0.0, 0.0],
+            Self::ColorTemperature { kelvin } => {
+                let [r, g, b] = white_point(kelvin);
+                [3.0, r, g, b]
+            }
+            Self::Crt {
+                scanlines,
+                vignette,
+            } => [
+                4.0,
+                scanlines.clamp(0.0, 1.0),
+                vignette.clamp(0.0, 1.0),
+                0.0,
+            ],
+        }
+    }
+}
+
+/// RGB multipliers that make white look like a black body at `kelvin`, with 6500 K
+/// mapping to white (after Tanner Helland's fit of the black-body locus).
+pub fn white_point(kelvin: f32) -> [f32; 3] {
+    let rgb = |kelvin: f32| {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.699 * (t - 60.0).powf(-0.133_204_76)
+        };
+        let g = if t <= 66.0 {
+            99.470_8 * t.ln() - 161.119_57
+        } else {
+            288.122_16 * (t - 60.0).powf(-0.075_514_846)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * (t - 10.0).ln() - 305.044_8
+        };
+        [r, g, b].map(|c: f32| c.clamp(0.0, 255.0) / 255.0)
+    };
+    let target = rgb(kelvin);
+    let neutral = rgb(6500.0);
+    [0, 1, 2].map(|i| (target[i] / neutral[i]).min(1.0))
+}
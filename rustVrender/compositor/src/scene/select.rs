@@ -0,0 +1,319 @@
+// Selecting a region: SelectRegion dims every output and draws a crosshair through the
+// pixel under the pointer, with the size of the selection beside it, until dragging
+// with the left button selects a rectangle, or a click selects the window under the
+// pointer, which is outlined before anything is dragged. The selection's edges snap to
+// those of windows and outputs nearby. The reply is the rectangle in root window
+// coordinates, as a Screenshot or StartScreencast region takes it. Escape or any other
+// button cancels.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+use super::animation::Rect;
+use super::theme::{Color, Palette};
+use super::{DrawItem, DrawSource, SurfaceGeometry};
+use crate::text::{self, Font};
+
+/// Sizes at scale 1, in output pixels.
+const PADDING: f32 = 4.0;
+const CORNER_RADIUS: f32 = 4.0;
+/// Between the pointer and the readout.
+const OFFSET: f32 = 16.0;
+/// A drag shorter than this, in root window pixels, is a click.
+const CLICK: f32 = 3.0;
+
+/// The readout is in the OSD's colors, not premultiplied.
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.12, 0.9];
+const FOREGROUND: [f32; 4] = [0.92, 0.92, 0.94, 1.0];
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectConfig {
+    /// Over everything but the selection.
+    pub dim: Color,
+    /// Of the selection and the crosshair.
+    pub outline: Color,
+    pub outline_width: f32,
+    /// How close an edge comes to a window's or output's before it snaps to it, in
+    /// logical pixels; 0 never snaps.
+    pub snap_px: f32,
+    pub text_px: f32,
+}
+
+impl Default for SelectConfig {
+    fn default() -> Self {
+        Self {
+            dim: Color::base(0x00, 0.5),
+            outline: Color::base(0x0D, 1.0),
+            outline_width: 2.0,
+            snap_px: 8.0,
+            text_px: 14.0,
+        }
+    }
+}
+
+impl SelectConfig {
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.dim.retheme(palette);
+        self.outline.retheme(palette);
+    }
+}
+
+/// Where a SelectRegion command waits for its rectangle.
+pub type SelectReply = oneshot::Sender<Result<SurfaceGeometry, String>>;
+
+/// The SelectRegion commands waiting, and where the drag began.
+#[derive(Debug, Default)]
+pub struct Selector {
+    config: SelectConfig,
+    waiting: Vec<SelectReply>,
+    /// Snapped, in root window coordinates.
+    anchor: Option<[f32; 2]>,
+}
+
+impl Selector {
+    pub fn config(&self) -> &SelectConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SelectConfig) {
+        self.config = config;
+    }
+
+    pub fn retheme(&mut self, palette: &Palette) {
+        self.config.retheme(palette);
+    }
+
+    pub fn start(&mut self, reply: SelectReply) {
+        self.waiting.push(reply);
+    }
+
+    /// Whether the screen is dimmed for selecting.
+    pub fn active(&self) -> bool {
+        !self.waiting.is_empty()
+    }
+
+    /// Forgets commands nobody waits for anymore; returns whether that ended selecting.
+    pub fn prune(&mut self) -> bool {
+        let active = self.active();
+        self.waiting.retain(|reply| !reply.is_closed());
+        if active && !self.active() {
+            self.anchor = None;
+            return true;
+        }
+        false
+    }
+
+    /// Ends selecting, answering every command with `reason`.
+    pub fn cancel(&mut self, reason: &str) {
+        self.finish(Err(reason.to_string()));
+    }
+
+    /// Starts dragging at `pointer`, between `edges`.
+    pub fn press(&mut self, pointer: [f32; 2], edges: &Edges) {
+        if self.active() {
+            self.anchor = Some(edges.snap(pointer, self.config.snap_px));
+        }
+    }
+
+    /// Selects the rectangle dragged to `pointer`, or the window under it after a
+    /// click, answering every command; `windows` are bottom first.
+    pub fn release(&mut self, pointer: [f32; 2], windows: &[Rect], edges: &Edges) {
+        if self.anchor.is_none() {
+            return;
+        }
+        match self.selection(pointer, windows, edges) {
+            Some([x, y, width, height]) if width >= 1.0 && height >= 1.0 => {
+                self.finish(Ok(SurfaceGeometry {
+                    x: x as i32,
+                    y: y as i32,
+                    width: width as u32,
+                    height: height as u32,
+                }));
+            }
+            // A click where there is no window starts over.
+            _ => self.anchor = None,
+        }
+    }
+
+    /// The rectangle selected with the pointer at `pointer`, in whole root window
+    /// pixels: the one dragged, or the window under the pointer before any drag.
+    pub fn selection(&self, pointer: [f32; 2], windows: &[Rect], edges: &Edges) -> Option<Rect> {
+        let dragged = self.anchor.filter(|anchor| {
+            (pointer[0] - anchor[0]).abs() >= CLICK || (pointer[1] - anchor[1]).abs() >= CLICK
+        });
+        let Some(anchor) = dragged else {
+            let [x, y] = pointer;
+            return windows
+                .iter()
+                .rev()
+                .find(|[left, top, width, height]| {
+                    x >= *left && y >= *top && x < left + width && y < top + height
+                })
+                .map(|rect| rect.map(f32::round));
+        };
+        let [px, py] = edges.snap(pointer, self.config.snap_px);
+        let left = anchor[0].min(px).round();
+        let top = anchor[1].min(py).round();
+        let right = anchor[0].max(px).round();
+        let bottom = anchor[1].max(py).round();
+        Some([left, top, right - left, bottom - top])
+    }
+
+    /// The dimming, selection, crosshair, and readout on the output at `bounds`, in its
+    /// pixels; `pointer` is in root window coordinates.
+    pub fn items(
+        &self,
+        bounds: Rect,
+        scale: f32,
+        pointer: Option<[f32; 2]>,
+        selection: Option<Rect>,
+        font: Option<&Arc<Font>>,
+    ) -> Vec<DrawItem> {
+        let config = &self.config;
+        let [ox, oy, width, height] = bounds;
+        let size = [width * scale, height * scale];
+        let to_output = |[x, y, w, h]: Rect| {
+            [
+                ((x - ox) * scale).round(),
+                ((y - oy) * scale).round(),
+                (w * scale).round(),
+                (h * scale).round(),
+            ]
+        };
+        let mut items = Vec::new();
+
+        // Everything but the selection is dimmed, and the selection outlined.
+        let dim = config.dim.get();
+        match selection.map(to_output) {
+            Some([x, y, w, h]) => {
+                let left = x.clamp(0.0, size[0]);
+                let top = y.clamp(0.0, size[1]);
+                let right = (x + w).clamp(0.0, size[0]);
+                let bottom = (y + h).clamp(0.0, size[1]);
+                for rect in [
+                    [0.0, 0.0, size[0], top],
+                    [0.0, bottom, size[0], size[1] - bottom],
+                    [0.0, top, left, bottom - top],
+                    [right, top, size[0] - right, bottom - top],
+                ] {
+                    if rect[2] > 0.0 && rect[3] > 0.0 {
+                        items.push(solid(rect, dim, 0.0));
+                    }
+                }
+                let line = config.outline_width * scale;
+                if line > 0.0 && right > left && bottom > top {
+                    let outline = [x - line, y - line, w + 2.0 * line, h + 2.0 * line];
+                    items.push(solid(outline, config.outline.get(), line));
+                }
+            }
+            None => items.push(solid([0.0, 0.0, size[0], size[1]], dim, 0.0)),
+        }
+
+        // The crosshair runs through the pixel under the pointer, one pixel wide.
+        let Some([px, py]) =
+            pointer.filter(|[x, y]| *x >= ox && *y >= oy && *x < ox + width && *y < oy + height)
+        else {
+            return items;
+        };
+        let cx = ((px - ox) * scale).floor();
+        let cy = ((py - oy) * scale).floor();
+        let color = config.outline.get();
+        items.push(solid([cx, 0.0, 1.0, size[1]], color, 0.0));
+        items.push(solid([0.0, cy, size[0], 1.0], color, 0.0));
+
+        // Beside it, the size of the selection, or where the pointer is before one.
+        let Some(font) = font else {
+            return items;
+        };
+        let readout = match selection {
+            Some([x, y, w, h]) => format!("{} × {}  +{}+{}", w, h, x, y),
+            None => format!("{}, {}", px.floor(), py.floor()),
+        };
+        let label = font.layout(&readout, config.text_px * scale);
+        let padding = PADDING * scale;
+        let box_width = label.width + 2.0 * padding;
+        let box_height = label.height + 2.0 * padding;
+        let offset = OFFSET * scale;
+        // Kept on the output, flipping to the pointer's other side near its edges.
+        let mut x = cx + offset;
+        if x + box_width > size[0] {
+            x = cx - offset - box_width;
+        }
+        let mut y = cy + offset;
+        if y + box_height > size[1] {
+            y = cy - offset - box_height;
+        }
+        let [r, g, b, a] = BACKGROUND;
+        items.push(DrawItem {
+            rect: [x.round(), y.round(), box_width, box_height],
+            color: [r * a, g * a, b * a, a],
+            source: DrawSource::Solid,
+            corner_radius: CORNER_RADIUS * scale,
+            border: 0.0,
+        });
+        items.extend(text::draw(
+            font,
+            &label,
+            (x + padding).round(),
+            (y + padding).round(),
+            FOREGROUND,
+        ));
+        items
+    }
+
+    fn finish(&mut self, result: Result<SurfaceGeometry, String>) {
+        self.anchor = None;
+        for reply in self.waiting.drain(..) {
+            // The requester may have given up waiting.
+            let _ = reply.send(result.clone());
+        }
+    }
+}
+
+/// The edges of windows and outputs the selection snaps to, in root window
+/// coordinates.
+#[derive(Debug, Default)]
+pub struct Edges {
+    pub xs: Vec<f32>,
+    pub ys: Vec<f32>,
+}
+
+impl Edges {
+    /// The edges of every rect of `rects`.
+    pub fn of(rects: impl IntoIterator<Item = Rect>) -> Self {
+        let mut edges = Self::default();
+        for [x, y, width, height] in rects {
+            edges.xs.extend([x, x + width]);
+            edges.ys.extend([y, y + height]);
+        }
+        edges
+    }
+
+    /// `point`, moved onto the nearest edges within `distance`.
+    fn snap(&self, [x, y]: [f32; 2], distance: f32) -> [f32; 2] {
+        let nearest = |value: f32, edges: &[f32]| {
+            edges
+                .iter()
+                .copied()
+                .filter(|edge| (edge - value).abs() <= distance)
+                .min_by(|a, b| (a - value).abs().total_cmp(&(b - value).abs()))
+                .unwrap_or(value)
+        };
+        [nearest(x, &self.xs), nearest(y, &self.ys)]
+    }
+}
+
+/// A rect in `color`, which isn't premultiplied, outlined `border` wide if not 0.
+fn solid(rect: Rect, color: [f32; 4], border: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius: 0.0,
+        border,
+    }
+}
@@ -0,0 +1,64 @@
+// Shaped windows, like xeyes or launchers cut to the outline of their artwork: the WM
+// reports the rectangles a window's X shape leaves of it, and the window is drawn as
+// just those parts of its contents, with no border, shadow, blur, or rounded corners,
+// which would outline the rectangle it doesn't have.
+
+use std::collections::HashMap;
+
+use super::{DrawItem, DrawSource};
+
+#[derive(Debug, Default)]
+pub struct Shapes {
+    /// The rectangles of shaped windows, x, y, width, height inside them.
+    rects: HashMap<u32, Vec<[i32; 4]>>,
+}
+
+impl Shapes {
+    /// Shapes `window` to `rects`, or gives it back its whole rectangle with `None`;
+    /// returns whether that changed anything.
+    pub fn set(&mut self, window: u32, rects: Option<Vec<[i32; 4]>>) -> bool {
+        match rects {
+            Some(rects) => self.rects.insert(window, rects.clone()) != Some(rects),
+            None => self.rects.remove(&window).is_some(),
+        }
+    }
+
+    pub fn shaped(&self, window: u32) -> bool {
+        self.rects.contains_key(&window)
+    }
+
+    /// `item`, drawing all of a `size` window, cut down to the window's rectangles;
+    /// unshaped windows keep it whole.
+    pub fn pieces(&self, window: u32, size: [u32; 2], item: DrawItem) -> Vec<DrawItem> {
+        let Some(rects) = self.rects.get(&window) else {
+            return vec![item];
+        };
+        let [width, height] = size.map(|v| v.max(1) as i32);
+        let [x, y, w, h] = item.rect;
+        rects
+            .iter()
+            .filter_map(|&[left, top, rect_width, rect_height]| {
+                // The shape may reach over the border, which isn't part of the contents.
+                let right = (left + rect_width).min(width);
+                let bottom = (top + rect_height).min(height);
+                let (left, top) = (left.max(0), top.max(0));
+                if right <= left || bottom <= top {
+                    return None;
+                }
+                let part = [
+                    left as f32 / width as f32,
+                    top as f32 / height as f32,
+                    (right - left) as f32 / width as f32,
+                    (bottom - top) as f32 / height as f32,
+                ];
+                let mut piece = item.clone();
+                piece.rect = [x + part[0] * w, y + part[1] * h, part[2] * w, part[3] * h];
+                piece.corner_radius = 0.0;
+                if let DrawSource::Window { part: drawn, .. } = &mut piece.source {
+                    *drawn = part;
+                }
+                Some(piece)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,77 @@
+// The live slide of a touchpad swipe: while the fingers move, the windows of every
+// output follow them, and once they lift the windows settle back into place, whether
+// or not the swipe went far enough to send its command.
+
+use std::time::Instant;
+
+use super::animation::{Curve, Easing};
+
+/// How far windows are slid, in root window coordinates.
+#[derive(Debug)]
+pub struct Slide {
+    /// Where the fingers put the windows, or where settling started from.
+    offset: [f32; 2],
+    /// When the fingers lifted; unset while they are down.
+    released: Option<Instant>,
+    settle: Curve,
+    /// The offset at the last `advance`.
+    shown: [f32; 2],
+}
+
+impl Default for Slide {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 2],
+            released: None,
+            settle: Curve::new(250, Easing::EaseOutCubic),
+            shown: [0.0; 2],
+        }
+    }
+}
+
+impl Slide {
+    pub fn set_settle(&mut self, curve: Curve) {
+        self.settle = curve;
+    }
+
+    /// Moves the windows to `offset`, following the fingers.
+    pub fn follow(&mut self, offset: [f32; 2]) {
+        self.offset = offset;
+        self.released = None;
+        self.shown = offset;
+    }
+
+    /// Lets the windows settle back from wherever they are at `now`.
+    pub fn release(&mut self, now: Instant) {
+        if self.released.is_none() && self.shown != [0.0; 2] {
+            self.offset = self.shown;
+            self.released = Some(now);
+        }
+    }
+
+    /// Moves settling on to `now`; returns whether the windows are still off place.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let Some(released) = self.released else {
+            return false;
+        };
+        let duration = self.settle.duration();
+        let t = if duration.is_zero() {
+            1.0
+        } else {
+            now.saturating_duration_since(released).as_secs_f32() / duration.as_secs_f32()
+        };
+        let left = 1.0 - self.settle.easing.apply(t);
+        self.shown = self.offset.map(|v| v * left);
+        if t >= 1.0 {
+            self.released = None;
+            self.offset = [0.0; 2];
+            self.shown = [0.0; 2];
+        }
+        t < 1.0
+    }
+
+    /// The offset at the last `advance`, if windows are off place.
+    pub fn offset(&self) -> Option<[f32; 2]> {
+        (self.shown != [0.0; 2]).then_some(self.shown)
+    }
+}
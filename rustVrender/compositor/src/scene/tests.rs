@@ -0,0 +1,233 @@
+// The scene as the bridge drives it, with windows reported focused or not.
+
+use super::effects::WindowRule;
+use super::*;
+
+const OUTPUT: (u32, u32) = (1920, 1080);
+
+fn window(id: u32, rect: [i32; 4], focused: bool) -> WindowState {
+    let [x, y, width, height] = rect;
+    WindowState {
+        id,
+        x,
+        y,
+        width: width as u32,
+        height: height as u32,
+        border_width: 2,
+        focused,
+        opacity: 1.0,
+        window_type: WindowType::Normal,
+        fullscreen: false,
+        blur: false,
+        layer: Layer::Normal,
+        visible: true,
+    }
+}
+
+/// A scene with one output, and the node of that.
+fn scene() -> (Scene, NodeId) {
+    let mut scene = Scene::new();
+    let output = scene.add_output("DP-1", OUTPUT.0, OUTPUT.1, None);
+    (scene, output)
+}
+
+fn border_color(scene: &Scene, output: NodeId, window: u32) -> [f32; 4] {
+    let node = scene.window_node(output, window).unwrap();
+    scene.nodes[&node]
+        .children
+        .iter()
+        .find_map(|child| match scene.nodes[child].kind {
+            NodeKind::Decoration(Decoration::Border { color, .. }) => Some(color),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn focused_window_has_the_focused_border() {
+    let (mut scene, output) = scene();
+    let focused = scene.effects.focused_border.get();
+    let unfocused = scene.effects.unfocused_border.get();
+    assert_ne!(focused, unfocused);
+
+    scene.sync_windows(&[
+        window(1, [0, 0, 960, 1080], true),
+        window(2, [960, 0, 960, 1080], false),
+    ]);
+    assert_eq!(border_color(&scene, output, 1), focused);
+    assert_eq!(border_color(&scene, output, 2), unfocused);
+
+    // The focus moving recolors both.
+    scene.sync_windows(&[
+        window(1, [0, 0, 960, 1080], false),
+        window(2, [960, 0, 960, 1080], true),
+    ]);
+    assert_eq!(border_color(&scene, output, 1), unfocused);
+    assert_eq!(border_color(&scene, output, 2), focused);
+}
+
+#[test]
+fn pinning_without_a_window_pins_the_focused_one() {
+    let (mut scene, _) = scene();
+    assert_eq!(
+        scene.pin_window(None, None),
+        Err("no window is focused".to_string())
+    );
+
+    scene.sync_windows(&[
+        window(1, [0, 0, 960, 1080], false),
+        window(2, [960, 0, 960, 1080], true),
+    ]);
+    scene.pin_window(None, None).unwrap();
+    assert!(scene.pins.pinned(2));
+    assert!(!scene.pins.pinned(1));
+
+    scene.unpin_window(None).unwrap();
+    assert!(!scene.pins.pinned(2));
+    assert_eq!(
+        scene.unpin_window(None),
+        Err("window 2 is not pinned".to_string())
+    );
+}
+
+#[test]
+fn taskbar_highlights_the_focused_window() {
+    let (mut scene, output) = scene();
+    scene.set_bar_config(BarConfig {
+        enabled: true,
+        ..BarConfig::default()
+    });
+    let bounds = [0.0, 0.0, OUTPUT.0 as f32, OUTPUT.1 as f32];
+    let tiles = [[0, 0, 960, 1080], [960, 0, 960, 1080]];
+
+    scene.sync_windows(&[window(1, tiles[0], false), window(2, tiles[1], true)]);
+    let tasks: Vec<_> = scene
+        .task_windows(bounds, None)
+        .iter()
+        .map(|task| (task.window, task.focused))
+        .collect();
+    assert_eq!(tasks, [(1, false), (2, true)]);
+    let focused = scene.draw_list(output);
+
+    scene.sync_windows(&[window(1, tiles[0], false), window(2, tiles[1], false)]);
+    let unfocused = scene.draw_list(output);
+
+    // The windows are still fading in, so of what is drawn, only the button of the one
+    // that lost the focus changed.
+    let taskbar = &scene.bar_config().taskbar;
+    let changed: Vec<_> = focused
+        .iter()
+        .zip(&unfocused)
+        .filter(|(focused, unfocused)| focused.color != unfocused.color)
+        .map(|(focused, unfocused)| (focused.color, unfocused.color))
+        .collect();
+    assert_eq!(changed, [(taskbar.focused.get(), taskbar.button.get())]);
+}
+
+#[test]
+fn focus_is_announced_and_outlined() {
+    let (mut scene, output) = scene();
+    scene.set_accessibility_config(AccessibilityConfig {
+        announce_focus: true,
+        focus_outline_width: 4.0,
+        ..AccessibilityConfig::default()
+    });
+    let outline = scene.accessibility_config().focus_outline;
+    // The centers of the outlines drawn.
+    let outlined = |scene: &Scene| -> Vec<[f32; 2]> {
+        scene
+            .draw_list(output)
+            .iter()
+            .filter(|item| item.color == outline && item.border == 4.0)
+            .map(|item| {
+                [
+                    item.rect[0] + item.rect[2] / 2.0,
+                    item.rect[1] + item.rect[3] / 2.0,
+                ]
+            })
+            .collect()
+    };
+    let tiles = [[0, 0, 960, 1080], [960, 0, 960, 1080]];
+    scene.set_window_info(WindowInfo {
+        window: 2,
+        title: Some("notes".to_string()),
+        ..WindowInfo::default()
+    });
+
+    scene.sync_windows(&[window(1, tiles[0], false), window(2, tiles[1], false)]);
+    // Once the windows opened.
+    scene.advance(Instant::now() + Duration::from_secs(10));
+    assert!(!scene.osds.advance(Instant::now()));
+    assert!(outlined(&scene).is_empty());
+
+    scene.sync_windows(&[window(1, tiles[0], false), window(2, tiles[1], true)]);
+    assert!(scene.osds.advance(Instant::now()));
+    assert_eq!(outlined(&scene), [[1440.0, 540.0]]);
+
+    // Window 1 has no title to announce, but the outline follows the focus.
+    scene.sync_windows(&[window(1, tiles[0], true), window(2, tiles[1], false)]);
+    assert_eq!(outlined(&scene), [[480.0, 540.0]]);
+}
+
+#[test]
+fn variable_refresh_follows_the_window_filling_an_output() {
+    let (mut scene, left) = scene();
+    let right = scene.add_output("HDMI-1", OUTPUT.0, OUTPUT.1, None);
+    scene.set_output_position(right, OUTPUT.0 as i32, 0);
+    scene.set_vrr_enabled(None, true);
+    let game = |focused| WindowState {
+        fullscreen: true,
+        ..window(1, [0, 0, 1920, 1080], focused)
+    };
+    let video = |focused| WindowState {
+        fullscreen: true,
+        ..window(2, [1920, 0, 1920, 1080], focused)
+    };
+
+    scene.sync_windows(&[game(true), video(false)]);
+    assert_eq!(scene.vrr_window(left), Some(1));
+    // The focus is on the other output.
+    assert_eq!(scene.vrr_window(right), Some(2));
+
+    // A dialog focused over the game is drawn on top of it.
+    let dialog = window(3, [760, 440, 400, 200], true);
+    scene.sync_windows(&[game(false), video(false), dialog]);
+    assert_eq!(scene.vrr_window(left), None);
+    assert_eq!(scene.vrr_window(right), Some(2));
+
+    // Only fullscreen windows stand in for the focus.
+    let tiled = window(2, [1920, 0, 1920, 1080], false);
+    scene.sync_windows(&[game(true), tiled]);
+    assert_eq!(scene.vrr_window(right), None);
+    // Once the dialog closed.
+    scene.advance(Instant::now() + Duration::from_secs(10));
+
+    scene.set_vrr_enabled(Some("HDMI-1"), false);
+    scene.sync_windows(&[game(true), video(false)]);
+    assert_eq!(scene.vrr_window(left), Some(1));
+    assert_eq!(scene.vrr_window(right), None);
+}
+
+#[test]
+fn effects_rules_match_the_reported_class() {
+    let (mut scene, output) = scene();
+    let mut effects = EffectsConfig::default();
+    effects.rules.push(WindowRule {
+        class: Some("mpv".to_string()),
+        corner_radius: Some(0.0),
+        ..WindowRule::default()
+    });
+    scene.set_effects_config(effects);
+    scene.sync_windows(&[window(1, [0, 0, 960, 540], false)]);
+    let (x, y, width, height) = scene.output_rect(output).unwrap();
+    let bounds = [x as f32, y as f32, width as f32, height as f32];
+    assert_eq!(scene.window_effects(1, bounds).unwrap().corner_radius, 8.0);
+
+    scene.set_window_info(WindowInfo {
+        window: 1,
+        class: Some("mpv".to_string()),
+        instance: Some("gl".to_string()),
+        ..WindowInfo::default()
+    });
+    assert_eq!(scene.window_effects(1, bounds).unwrap().corner_radius, 0.0);
+}
@@ -0,0 +1,213 @@
+// Themes: named base16 palettes of sixteen colors, base00 to base0F, that the colors of
+// the bar, its widgets and calendar, OSDs, and window borders can name instead of
+// giving RGBA, like "base0D" or "base01/0.9" with an alpha of its own. Switching the
+// theme, from the config or with SetTheme, gives every color that names a slot the new
+// palette's, so what is on screen restyles on the next frame. Colors given as RGBA or
+// hex stay as they are.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// The theme the config starts with.
+pub const DEFAULT_THEME: &str = "qtilerugo";
+
+/// Slots of a palette, as base16 names them.
+pub const SLOTS: usize = 16;
+
+/// Sixteen RGB colors, not premultiplied.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct Palette([[f32; 3]; SLOTS]);
+
+impl Palette {
+    const fn from_hex(colors: [u32; SLOTS]) -> Self {
+        let mut rgb = [[0.0; 3]; SLOTS];
+        let mut i = 0;
+        while i < SLOTS {
+            let color = colors[i];
+            rgb[i] = [
+                ((color >> 16) & 0xff) as f32 / 255.0,
+                ((color >> 8) & 0xff) as f32 / 255.0,
+                (color & 0xff) as f32 / 255.0,
+            ];
+            i += 1;
+        }
+        Self(rgb)
+    }
+}
+
+impl TryFrom<HashMap<String, String>> for Palette {
+    type Error = String;
+
+    /// Reads every slot, as `base00 = "#181818"`, from a table of the config.
+    fn try_from(mut slots: HashMap<String, String>) -> Result<Self, String> {
+        let mut palette = [[0.0; 3]; SLOTS];
+        for (slot, color) in palette.iter_mut().enumerate() {
+            let name = format!("base{:02X}", slot);
+            let value = slots.remove(&name).ok_or_else(|| format!("{} is missing", name))?;
+            let [r, g, b, _] = parse_hex(&value).map_err(|e| format!("{}: {}", name, e))?;
+            *color = [r, g, b];
+        }
+        let mut unknown: Vec<_> = slots.into_keys().collect();
+        unknown.sort();
+        if let Some(slot) = unknown.first() {
+            return Err(format!("{} is not a slot, base00 to base0F", slot));
+        }
+        Ok(Self(palette))
+    }
+}
+
+/// The palettes the renderer comes with, by name: its own look, and base16's defaults.
+const BUILT_IN: [(&str, Palette); 3] = [
+    (
+        DEFAULT_THEME,
+        Palette::from_hex([
+            0x1f1f24, 0x1a1a1f, 0x2e2e36, 0x474d61, 0x9999a6, 0xebebf0, 0xd9d9e6, 0xf5f5f7,
+            0xd94d4d, 0xcc5933, 0xe6bf59, 0x8cbf66, 0x66bfcc, 0x5999f2, 0xb380d9, 0xa6734d,
+        ]),
+    ),
+    (
+        "default-dark",
+        Palette::from_hex([
+            0x181818, 0x282828, 0x383838, 0x585858, 0xb8b8b8, 0xd8d8d8, 0xe8e8e8, 0xf8f8f8,
+            0xab4642, 0xdc9656, 0xf7ca88, 0xa1b56c, 0x86c1b9, 0x7cafc2, 0xba8baf, 0xa16946,
+        ]),
+    ),
+    (
+        "default-light",
+        Palette::from_hex([
+            0xf8f8f8, 0xe8e8e8, 0xd8d8d8, 0xb8b8b8, 0x585858, 0x383838, 0x282828, 0x181818,
+            0xab4642, 0xdc9656, 0xf7ca88, 0xa1b56c, 0x86c1b9, 0x7cafc2, 0xba8baf, 0xa16946,
+        ]),
+    ),
+];
+
+/// The theme in use, and palettes beyond the built-in ones.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// A palette of `palettes`, or a built-in one: qtilerugo, default-dark, or
+    /// default-light.
+    pub name: String,
+    /// Palettes by name, which take precedence over built-in ones of the same name.
+    pub palettes: HashMap<String, Palette>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_THEME.to_string(),
+            palettes: HashMap::new(),
+        }
+    }
+}
+
+impl ThemeConfig {
+    /// The palette named `name`, if there is one.
+    pub fn palette_named(&self, name: &str) -> Option<&Palette> {
+        self.palettes.get(name).or_else(|| {
+            BUILT_IN
+                .iter()
+                .find(|(built_in, _)| *built_in == name)
+                .map(|(_, palette)| palette)
+        })
+    }
+
+    /// The palette of the theme in use, or the default one should it be unknown.
+    pub fn palette(&self) -> &Palette {
+        self.palette_named(&self.name).unwrap_or(&BUILT_IN[0].1)
+    }
+}
+
+/// A color, not premultiplied, that may name a slot of the theme's palette.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "ColorValue")]
+pub struct Color {
+    rgba: [f32; 4],
+    /// The palette slot its RGB comes from.
+    slot: Option<usize>,
+}
+
+/// A color as the config writes it: RGBA, or a string `Color::parse` reads.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Rgba([f32; 4]),
+    Named(String),
+}
+
+impl TryFrom<ColorValue> for Color {
+    type Error = String;
+
+    fn try_from(value: ColorValue) -> Result<Self, String> {
+        match value {
+            ColorValue::Rgba(rgba) => Ok(Self::rgba(rgba)),
+            ColorValue::Named(name) => Self::parse(&name),
+        }
+    }
+}
+
+impl Color {
+    pub const fn rgba(rgba: [f32; 4]) -> Self {
+        Self { rgba, slot: None }
+    }
+
+    /// Slot `slot` of the theme's palette, with `alpha`; the default palette's color
+    /// until the theme is applied.
+    pub const fn base(slot: usize, alpha: f32) -> Self {
+        let [r, g, b] = BUILT_IN[0].1 .0[slot];
+        Self {
+            rgba: [r, g, b, alpha],
+            slot: Some(slot),
+        }
+    }
+
+    /// Parses `#rrggbb` or `#rrggbbaa`, or a slot like `base0D`, opaque, or with an
+    /// alpha after a slash, like `base01/0.9`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let Some(slot) = value.strip_prefix("base") else {
+            return parse_hex(value).map(Self::rgba);
+        };
+        let wrong = || format!("{:?} is not a slot like base0D or base01/0.9", value);
+        let (slot, alpha) = match slot.split_once('/') {
+            Some((slot, alpha)) => (slot, alpha.trim().parse().map_err(|_| wrong())?),
+            None => (slot, 1.0),
+        };
+        let slot = match u8::from_str_radix(slot, 16) {
+            Ok(index) if slot.len() == 2 && (index as usize) < SLOTS => index as usize,
+            _ => return Err(wrong()),
+        };
+        if !(0.0..=1.0).contains(&alpha) {
+            return Err(wrong());
+        }
+        Ok(Self::base(slot, alpha))
+    }
+
+    pub fn get(&self) -> [f32; 4] {
+        self.rgba
+    }
+
+    /// Takes its RGB from `palette`, if it names a slot.
+    pub fn retheme(&mut self, palette: &Palette) {
+        if let Some(slot) = self.slot {
+            let [r, g, b] = palette.0[slot];
+            self.rgba = [r, g, b, self.rgba[3]];
+        }
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+pub fn parse_hex(value: &str) -> Result<[f32; 4], String> {
+    let wrong = || format!("{:?} is not a color like #rrggbb or #rrggbbaa", value);
+    let hex = value.strip_prefix('#').ok_or_else(wrong)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(wrong());
+    }
+    let mut color = [1.0; 4];
+    for (channel, i) in color.iter_mut().zip((0..hex.len()).step_by(2)) {
+        let byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| wrong())?;
+        *channel = byte as f32 / 255.0;
+    }
+    Ok(color)
+}
@@ -0,0 +1,135 @@
+// Windows whose clients stopped answering the WM's pings: they are drawn gray and
+// dimmed, under a panel saying so with a button that has the WM kill the client. A
+// client that answers again gets its window back as it was.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::animation::Rect;
+use super::{DrawItem, DrawSource};
+use crate::text::{self, Font};
+
+const MESSAGE: &str = "Not responding — force close?";
+const BUTTON: &str = "Force close";
+
+/// Sizes at scale 1, in output pixels.
+const TEXT_PX: f32 = 16.0;
+const PADDING: f32 = 16.0;
+const BUTTON_PADDING: [f32; 2] = [14.0, 6.0];
+/// Between the message and the button under it.
+const BUTTON_GAP: f32 = 12.0;
+const CORNER_RADIUS: f32 = 10.0;
+/// Without a font there is only the button, this big.
+const BARE_BUTTON: [f32; 2] = [120.0, 32.0];
+
+/// Colors, not premultiplied.
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.12, 0.9];
+const FOREGROUND: [f32; 4] = [0.92, 0.92, 0.94, 1.0];
+const BUTTON_COLOR: [f32; 4] = [0.72, 0.2, 0.2, 1.0];
+
+/// The windows drawn as not responding.
+#[derive(Debug, Default)]
+pub struct Unresponsive {
+    windows: HashSet<u32>,
+}
+
+/// The panel over one unresponsive window.
+pub struct Panel {
+    pub items: Vec<DrawItem>,
+    /// Where the force close button is, in the coordinates the panel was laid out in.
+    pub button: Rect,
+}
+
+impl Unresponsive {
+    /// Records whether the client of `window` answers; returns whether that changed.
+    pub fn set(&mut self, window: u32, responsive: bool) -> bool {
+        if responsive {
+            self.windows.remove(&window)
+        } else {
+            self.windows.insert(window)
+        }
+    }
+
+    pub fn contains(&self, window: u32) -> bool {
+        self.windows.contains(&window)
+    }
+
+    pub fn remove(&mut self, window: u32) {
+        self.windows.remove(&window);
+    }
+
+    /// The panel centered over a window at `rect`, at `scale` pixels per unit of
+    /// `rect`, faded by `opacity`; without a `font` it is only the button.
+    pub fn panel(rect: Rect, scale: f32, opacity: f32, font: Option<&Arc<Font>>) -> Panel {
+        let fade = |[r, g, b, a]: [f32; 4]| [r, g, b, a * opacity];
+        let px = TEXT_PX * scale;
+        let message = font.map(|font| font.layout(MESSAGE, px));
+        let label = font.map(|font| font.layout(BUTTON, px));
+        let button_size = label.as_ref().map_or(BARE_BUTTON.map(|v| v * scale), |l| {
+            [
+                l.width + 2.0 * BUTTON_PADDING[0] * scale,
+                l.height + 2.0 * BUTTON_PADDING[1] * scale,
+            ]
+        });
+        let (message_width, message_height) = message
+            .as_ref()
+            .map_or((0.0, 0.0), |m| (m.width, m.height + BUTTON_GAP * scale));
+        let padding = PADDING * scale;
+        let size = [
+            message_width.max(button_size[0]) + 2.0 * padding,
+            message_height + button_size[1] + 2.0 * padding,
+        ];
+        let [x, y, width, height] = rect;
+        let left = (x + (width - size[0]) / 2.0).round();
+        let top = (y + (height - size[1]) / 2.0).round();
+
+        let mut items = vec![solid(
+            [left, top, size[0], size[1]],
+            fade(BACKGROUND),
+            CORNER_RADIUS * scale,
+        )];
+        if let (Some(font), Some(message)) = (font, &message) {
+            let message_left = left + (size[0] - message.width) / 2.0;
+            items.extend(text::draw(
+                font,
+                message,
+                message_left,
+                top + padding,
+                fade(FOREGROUND),
+            ));
+        }
+        let button = [
+            (left + (size[0] - button_size[0]) / 2.0).round(),
+            (top + padding + message_height).round(),
+            button_size[0],
+            button_size[1],
+        ];
+        items.push(solid(
+            button,
+            fade(BUTTON_COLOR),
+            CORNER_RADIUS * scale / 2.0,
+        ));
+        if let (Some(font), Some(label)) = (font, &label) {
+            items.extend(text::draw(
+                font,
+                label,
+                button[0] + BUTTON_PADDING[0] * scale,
+                button[1] + BUTTON_PADDING[1] * scale,
+                fade(FOREGROUND),
+            ));
+        }
+        Panel { items, button }
+    }
+}
+
+/// A rounded rect in `color`, which isn't premultiplied.
+fn solid(rect: Rect, color: [f32; 4], corner_radius: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border: 0.0,
+    }
+}
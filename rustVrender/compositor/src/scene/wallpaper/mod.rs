@@ -0,0 +1,459 @@
+// Wallpapers: an image, a slideshow of a directory's images, or a looping video (with
+// the video feature), drawn under everything else an output shows. Outputs showing
+// the same path share one wallpaper, and so one texture. Images are decoded off the
+// render thread, and a slideshow crossfades into each next image.
+
+#[cfg(feature = "video")]
+mod video;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing};
+use super::cursor::CURSOR_ID;
+use super::{DrawItem, DrawSource, WindowContents, WHOLE_CONTENTS};
+
+/// Wallpapers are uploaded like window contents, under ids from here up to the
+/// cursor's, which no X or Wayland window has.
+pub const WALLPAPER_ID_BASE: u32 = 0xffff_0000;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+/// Played as videos, by ffmpeg, rather than decoded as images.
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "avi", "gif"];
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+/// How a wallpaper is fit to an output of another shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum WallpaperMode {
+    /// Covers the output, cutting off what sticks out.
+    #[default]
+    Fill,
+    /// Shows all of it, leaving bars of the clear color.
+    Fit,
+    /// Covers the output, out of proportion.
+    Stretch,
+    /// Pixel for pixel, in the middle.
+    Center,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallpaperConfig {
+    /// An image, a directory of images to cycle through, or a video, for outputs
+    /// without one of their own.
+    pub path: Option<PathBuf>,
+    /// Paths by output name.
+    pub outputs: HashMap<String, PathBuf>,
+    pub mode: WallpaperMode,
+    /// Seconds each image of a directory is shown.
+    pub interval_s: u64,
+    /// Cycles through a directory in random order instead of by name.
+    pub shuffle: bool,
+    /// The crossfade into the next image.
+    pub fade: Curve,
+}
+
+impl Default for WallpaperConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            outputs: HashMap::new(),
+            mode: WallpaperMode::Fill,
+            interval_s: 300,
+            shuffle: false,
+            fade: Curve::new(1000, Easing::EaseInOutCubic),
+        }
+    }
+}
+
+/// One image of a wallpaper, as uploaded.
+#[derive(Debug, Clone)]
+struct Frame {
+    id: u32,
+    serial: u64,
+    contents: WindowContents,
+}
+
+impl Frame {
+    fn new(id: u32, contents: WindowContents) -> Self {
+        Self {
+            id,
+            serial: NEXT_SERIAL.fetch_add(1, Ordering::Relaxed),
+            contents,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    Image,
+    Slideshow {
+        images: Vec<PathBuf>,
+        /// The image shown next.
+        next: usize,
+    },
+    #[cfg(feature = "video")]
+    Video(video::Video),
+}
+
+type Loading = Receiver<Result<WindowContents, String>>;
+
+#[derive(Debug)]
+struct Wallpaper {
+    source: Source,
+    current: Option<Frame>,
+    /// The image `current` fades in over.
+    previous: Option<Frame>,
+    fade_start: Instant,
+    /// Eased progress of the fade at the last `advance`.
+    faded: f32,
+    /// When a slideshow moves on to its next image.
+    next_at: Option<Instant>,
+    /// The image being decoded, and where it is from.
+    loading: Option<(PathBuf, Loading)>,
+}
+
+impl Wallpaper {
+    fn open(path: &Path, config: &WallpaperConfig) -> Result<Self, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut wallpaper = Self {
+            source: Source::Image,
+            current: None,
+            previous: None,
+            fade_start: Instant::now(),
+            faded: 1.0,
+            next_at: None,
+            loading: None,
+        };
+        if metadata.is_dir() {
+            let mut images = images_in(path)?;
+            if config.shuffle {
+                shuffle(&mut images);
+            }
+            wallpaper.load(&images[0]);
+            wallpaper.source = Source::Slideshow { images, next: 1 };
+        } else if has_extension(path, VIDEO_EXTENSIONS) {
+            wallpaper.source = open_video(path)?;
+        } else {
+            wallpaper.load(path);
+        }
+        Ok(wallpaper)
+    }
+
+    /// Decodes the image at `path` on a thread of its own.
+    fn load(&mut self, path: &Path) {
+        let (send, receive) = mpsc::channel();
+        let decoding = path.to_path_buf();
+        thread::spawn(move || {
+            let _ = send.send(decode(&decoding));
+        });
+        self.loading = Some((path.to_path_buf(), receive));
+    }
+
+    /// Takes a decoded image or video frame, or starts decoding the next image of a
+    /// slideshow, by `now`; returns whether what is drawn changed.
+    fn update(&mut self, now: Instant, config: &WallpaperConfig) -> bool {
+        let interval = Duration::from_secs(config.interval_s.max(1));
+        let mut changed = false;
+        let received = self
+            .loading
+            .as_ref()
+            .map(|(path, loading)| (path.clone(), loading.try_recv()));
+        match received {
+            Some((_, Ok(Ok(contents)))) => {
+                self.previous = self.current.take();
+                self.current = Some(Frame::new(next_id(), contents));
+                self.fade_start = now;
+                self.faded = if self.previous.is_some() { 0.0 } else { 1.0 };
+                self.loading = None;
+                self.next_at = Some(now + interval);
+                changed = true;
+            }
+            Some((path, Ok(Err(e)))) => {
+                eprintln!("Failed to load wallpaper {}: {}", path.display(), e);
+                self.loading = None;
+                self.next_at = Some(now + interval);
+            }
+            Some((_, Err(TryRecvError::Disconnected))) => self.loading = None,
+            Some((_, Err(TryRecvError::Empty))) | None => {}
+        }
+        let mut next_image = None;
+        match &mut self.source {
+            Source::Image => {}
+            Source::Slideshow { images, next } => {
+                let due = self.next_at.is_some_and(|at| now >= at);
+                // A directory of one image has nothing to move on to.
+                if due && self.loading.is_none() && (images.len() > 1 || self.current.is_none()) {
+                    next_image = Some(images[*next % images.len()].clone());
+                    *next = (*next + 1) % images.len();
+                }
+            }
+            #[cfg(feature = "video")]
+            Source::Video(video) => {
+                if let Some(contents) = video.take_frame() {
+                    let id = self.current.as_ref().map_or_else(next_id, |frame| frame.id);
+                    self.current = Some(Frame::new(id, contents));
+                    changed = true;
+                }
+            }
+        }
+        if let Some(path) = next_image {
+            self.next_at = None;
+            self.load(&path);
+        }
+        changed
+    }
+
+    /// Moves the crossfade on to `now`; returns whether it is still running.
+    fn advance(&mut self, now: Instant, fade: Curve) -> bool {
+        if self.previous.is_none() {
+            return false;
+        }
+        let duration = fade.duration();
+        let t = if duration.is_zero() {
+            1.0
+        } else {
+            now.saturating_duration_since(self.fade_start).as_secs_f32() / duration.as_secs_f32()
+        };
+        self.faded = fade.easing.apply(t);
+        if t >= 1.0 {
+            self.previous = None;
+        }
+        self.previous.is_some()
+    }
+
+    fn items(&self, size: [f32; 2], mode: WallpaperMode) -> Vec<DrawItem> {
+        let mut items = Vec::new();
+        if let Some(previous) = &self.previous {
+            items.push(item(previous, size, mode, 1.0));
+        }
+        if let Some(current) = &self.current {
+            items.push(item(current, size, mode, self.faded));
+        }
+        items
+    }
+}
+
+/// The wallpaper of every output.
+#[derive(Debug, Default)]
+pub struct Wallpapers {
+    config: WallpaperConfig,
+    /// By path, shared by the outputs showing it.
+    shown: HashMap<PathBuf, Wallpaper>,
+}
+
+impl Wallpapers {
+    pub fn config(&self) -> &WallpaperConfig {
+        &self.config
+    }
+
+    /// Takes `config`, keeping wallpapers whose paths it still shows; paths that can't
+    /// be shown are left blank and reported.
+    pub fn set_config(&mut self, config: WallpaperConfig) -> Result<(), String> {
+        self.config = config;
+        let paths: Vec<PathBuf> = self.paths().cloned().collect();
+        self.shown.retain(|path, _| paths.contains(path));
+        let mut problems = Vec::new();
+        for path in paths {
+            if let Err(e) = self.open(&path) {
+                problems.push(e);
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    /// Shows `path` on the output called `output`, or on outputs without their own
+    /// without one; no path leaves it blank, or showing the others' wallpaper.
+    pub fn set(&mut self, output: Option<&str>, path: Option<PathBuf>) -> Result<(), String> {
+        if let Some(path) = &path {
+            self.open(path)?;
+        }
+        match (output, path) {
+            (Some(output), Some(path)) => {
+                self.config.outputs.insert(output.to_string(), path);
+            }
+            (Some(output), None) => {
+                self.config.outputs.remove(output);
+            }
+            (None, path) => self.config.path = path,
+        }
+        let paths: Vec<PathBuf> = self.paths().cloned().collect();
+        self.shown.retain(|path, _| paths.contains(path));
+        Ok(())
+    }
+
+    fn open(&mut self, path: &Path) -> Result<(), String> {
+        if !self.shown.contains_key(path) {
+            let wallpaper = Wallpaper::open(path, &self.config)?;
+            self.shown.insert(path.to_path_buf(), wallpaper);
+        }
+        Ok(())
+    }
+
+    /// Every path some output shows.
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.config.path.iter().chain(self.config.outputs.values())
+    }
+
+    /// Takes what was decoded and moves slideshows on by `now`; returns whether what
+    /// is drawn changed.
+    pub fn update(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        for wallpaper in self.shown.values_mut() {
+            changed |= wallpaper.update(now, &self.config);
+        }
+        changed
+    }
+
+    /// Moves crossfades on to `now`; returns whether any are still running.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let fade = self.config.fade;
+        let mut fading = false;
+        for wallpaper in self.shown.values_mut() {
+            fading |= wallpaper.advance(now, fade);
+        }
+        fading
+    }
+
+    /// The wallpaper of the output called `output`, `size` pixels big.
+    pub fn items(&self, output: Option<&str>, size: [f32; 2]) -> Vec<DrawItem> {
+        let path = output
+            .and_then(|name| self.config.outputs.get(name))
+            .or(self.config.path.as_ref());
+        path.and_then(|path| self.shown.get(path))
+            .map_or_else(Vec::new, |wallpaper| {
+                wallpaper.items(size, self.config.mode)
+            })
+    }
+}
+
+/// `frame` fit to an output `size` pixels big by `mode`, faded by `opacity`.
+fn item(frame: &Frame, size: [f32; 2], mode: WallpaperMode, opacity: f32) -> DrawItem {
+    let [width, height] = size;
+    let (image_width, image_height) = (
+        frame.contents.width.max(1) as f32,
+        frame.contents.height.max(1) as f32,
+    );
+    let rect = match mode {
+        WallpaperMode::Stretch => [0.0, 0.0, width, height],
+        mode => {
+            let scale = match mode {
+                WallpaperMode::Fill => (width / image_width).max(height / image_height),
+                WallpaperMode::Fit => (width / image_width).min(height / image_height),
+                _ => 1.0,
+            };
+            let (shown_width, shown_height) = (image_width * scale, image_height * scale);
+            [
+                ((width - shown_width) / 2.0).round(),
+                ((height - shown_height) / 2.0).round(),
+                shown_width.round(),
+                shown_height.round(),
+            ]
+        }
+    };
+    DrawItem {
+        rect,
+        color: [opacity; 4],
+        source: DrawSource::Window {
+            id: frame.id,
+            serial: frame.serial,
+            contents: frame.contents.clone(),
+            frozen: false,
+            part: WHOLE_CONTENTS,
+        },
+        corner_radius: 0.0,
+        border: 0.0,
+    }
+}
+
+fn next_id() -> u32 {
+    WALLPAPER_ID_BASE + NEXT_ID.fetch_add(1, Ordering::Relaxed) % (CURSOR_ID - WALLPAPER_ID_BASE)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// The images in `dir`, by name.
+fn images_in(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut images: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && has_extension(path, IMAGE_EXTENSIONS))
+        .collect();
+    if images.is_empty() {
+        return Err(format!("{} has no PNG or JPEG images", dir.display()));
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Shuffles `paths` well enough for a slideshow, without pulling in a random number
+/// generator.
+fn shuffle(paths: &mut [PathBuf]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |d| d.as_nanos() as u64)
+        | 1;
+    for i in (1..paths.len()).rev() {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        paths.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Decodes the image at `path` into premultiplied BGRA, like window contents.
+fn decode(path: &Path) -> Result<WindowContents, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let mut opaque = true;
+    let mut data = Vec::with_capacity(image.as_raw().len());
+    for pixel in image.as_raw().chunks_exact(4) {
+        let alpha = pixel[3] as u32;
+        opaque &= alpha == 255;
+        let premultiply = |c: u8| ((c as u32 * alpha + 127) / 255) as u8;
+        data.extend_from_slice(&[
+            premultiply(pixel[2]),
+            premultiply(pixel[1]),
+            premultiply(pixel[0]),
+            pixel[3],
+        ]);
+    }
+    Ok(WindowContents {
+        width,
+        height,
+        data: Arc::new(data),
+        opaque,
+    })
+}
+
+#[cfg(feature = "video")]
+fn open_video(path: &Path) -> Result<Source, String> {
+    video::Video::open(path).map(Source::Video)
+}
+
+#[cfg(not(feature = "video"))]
+fn open_video(path: &Path) -> Result<Source, String> {
+    Err(format!(
+        "{}: video wallpapers need the video feature",
+        path.display()
+    ))
+}
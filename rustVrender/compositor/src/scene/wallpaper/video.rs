@@ -0,0 +1,114 @@
+// Video wallpapers: an ffmpeg process decodes the video into raw BGRA frames at its
+// own frame rate, looping forever, and a thread keeps the newest one for the next
+// frame the compositor draws. ffprobe reads the video's size first, which splits the
+// stream into frames. Both must be on PATH.
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::scene::WindowContents;
+
+pub struct Video {
+    ffmpeg: Child,
+    width: u32,
+    height: u32,
+    /// The newest frame not shown yet.
+    latest: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl fmt::Debug for Video {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Video")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Video {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let (width, height) = probe_size(path)?;
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args([
+                "-loglevel",
+                "error",
+                "-nostdin",
+                "-stream_loop",
+                "-1",
+                "-re",
+                "-i",
+            ])
+            .arg(path)
+            .args(["-an", "-f", "rawvideo", "-pix_fmt", "bgra", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run ffmpeg: {}", e))?;
+        let mut stdout = ffmpeg.stdout.take().ok_or("ffmpeg has no output")?;
+        let latest = Arc::new(Mutex::new(None));
+        {
+            let latest = latest.clone();
+            let path = path.to_path_buf();
+            let size = width as usize * height as usize * 4;
+            thread::spawn(move || loop {
+                let mut frame = vec![0; size];
+                if let Err(e) = stdout.read_exact(&mut frame) {
+                    eprintln!("Video wallpaper {} stopped: {}", path.display(), e);
+                    return;
+                }
+                *latest.lock().unwrap() = Some(frame);
+            });
+        }
+        Ok(Self {
+            ffmpeg,
+            width,
+            height,
+            latest,
+        })
+    }
+
+    /// The newest frame decoded since the last call.
+    pub fn take_frame(&self) -> Option<WindowContents> {
+        let data = self.latest.lock().unwrap().take()?;
+        Some(WindowContents {
+            width: self.width,
+            height: self.height,
+            data: Arc::new(data),
+            opaque: true,
+        })
+    }
+}
+
+impl Drop for Video {
+    fn drop(&mut self) {
+        let _ = self.ffmpeg.kill();
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+/// The width and height of the first video stream in `path`.
+fn probe_size(path: &Path) -> Result<(u32, u32), String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height", "-of", "csv=p=0:s=x"])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("failed to run ffprobe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe can't read {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let size = String::from_utf8_lossy(&output.stdout);
+    size.trim()
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("{} has no video stream", path.display()))
+}
@@ -0,0 +1,223 @@
+// Workspace switches: when the WM reports that an output shows another workspace,
+// the windows it drew until then are kept as flattened draw items and animated out
+// while the new workspace's windows animate in, by sliding, fading, or zooming.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing, Rect};
+use super::{DrawItem, DrawSource, NodeId, Transform};
+
+/// How far zoom transitions scale the workspace leaving and the one arriving.
+const ZOOM_DEPTH: f32 = 0.15;
+
+/// How one workspace gives way to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum TransitionStyle {
+    /// Pushes the old workspace off one edge as the new one comes in from the other.
+    #[default]
+    Slide,
+    /// Crossfades between the two.
+    Fade,
+    /// Zooms the old workspace out of the way and the new one in, fading both.
+    Zoom,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    pub style: TransitionStyle,
+    /// A zero duration switches without animating.
+    pub animation: Curve,
+    /// Slides up and down instead of sideways.
+    pub vertical: bool,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            style: TransitionStyle::Slide,
+            animation: Curve::new(250, Easing::EaseOutCubic),
+            vertical: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Transition {
+    /// What the output drew of the old workspace, in its pixels.
+    outgoing: Vec<DrawItem>,
+    start: Instant,
+    /// Whether the new workspace comes after the old one.
+    forward: bool,
+    /// Eased progress at the last `advance`.
+    shown: f32,
+}
+
+/// The workspace every output shows, and the switches still animating.
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    config: WorkspaceConfig,
+    /// Workspace indices by output name.
+    current: HashMap<String, i32>,
+    transitions: HashMap<NodeId, Transition>,
+}
+
+impl Workspaces {
+    pub fn config(&self) -> &WorkspaceConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: WorkspaceConfig) {
+        self.config = config;
+    }
+
+    /// Records that the output called `name` shows workspace `index`; returns whether
+    /// it moved forward, or `None` if it showed no other workspace before.
+    pub fn switch(&mut self, name: &str, index: i32) -> Option<bool> {
+        let previous = self.current.insert(name.to_string(), index)?;
+        (previous != index).then_some(index > previous)
+    }
+
+    /// The workspace the output called `name` shows, once the WM said.
+    pub fn current(&self, name: &str) -> Option<i32> {
+        self.current.get(name).copied()
+    }
+
+    /// Starts animating `output` away from the workspace it drew as `outgoing`.
+    pub fn start(&mut self, output: NodeId, outgoing: Vec<DrawItem>, forward: bool, now: Instant) {
+        if self.config.animation.duration().is_zero() {
+            return;
+        }
+        self.transitions.insert(
+            output,
+            Transition {
+                outgoing,
+                start: now,
+                forward,
+                shown: 0.0,
+            },
+        );
+    }
+
+    /// Moves transitions on to `now`; returns whether any are still running.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let curve = self.config.animation;
+        let duration = curve.duration();
+        self.transitions.retain(|_, transition| {
+            let t = if duration.is_zero() {
+                1.0
+            } else {
+                now.saturating_duration_since(transition.start)
+                    .as_secs_f32()
+                    / duration.as_secs_f32()
+            };
+            transition.shown = curve.easing.apply(t);
+            t < 1.0
+        });
+        !self.transitions.is_empty()
+    }
+
+    /// Whether some output is switching workspaces, during which windows mapped and
+    /// unmapped by the switch don't animate on their own.
+    pub fn switching(&self) -> bool {
+        !self.transitions.is_empty()
+    }
+
+    pub fn remove_output(&mut self, output: NodeId) {
+        self.transitions.remove(&output);
+    }
+
+    /// Whether the old workspace is drawn over the new one rather than under it.
+    pub fn outgoing_on_top(&self) -> bool {
+        self.config.style == TransitionStyle::Fade
+    }
+
+    /// How the new workspace of `output`, whose root window coordinates are `bounds`,
+    /// is moved in root coordinates, and faded.
+    pub fn incoming(&self, output: NodeId, bounds: Rect) -> Option<(Transform, f32)> {
+        let transition = self.transitions.get(&output)?;
+        let left = 1.0 - transition.shown;
+        Some(self.placement(bounds, transition.forward, -left, transition.shown))
+    }
+
+    /// The old workspace of `output`, moved along in its pixels; `bounds` are its root
+    /// window coordinates and `scale` its pixels per root unit.
+    pub fn outgoing(&self, output: NodeId, bounds: Rect, scale: f32) -> Vec<DrawItem> {
+        let Some(transition) = self.transitions.get(&output) else {
+            return Vec::new();
+        };
+        let [_, _, width, height] = bounds;
+        let pixels = [0.0, 0.0, width * scale, height * scale];
+        let (transform, opacity) = self.placement(
+            pixels,
+            transition.forward,
+            transition.shown,
+            1.0 - transition.shown,
+        );
+        transition
+            .outgoing
+            .iter()
+            .map(|item| place(item, &transform, opacity))
+            .collect()
+    }
+
+    /// Where a workspace filling `bounds` is drawn `progress` of the way out of view,
+    /// negative for coming in, and how opaque it is then.
+    fn placement(
+        &self,
+        bounds: Rect,
+        forward: bool,
+        progress: f32,
+        opacity: f32,
+    ) -> (Transform, f32) {
+        let [x, y, width, height] = bounds;
+        let direction = if forward { -1.0 } else { 1.0 };
+        match self.config.style {
+            TransitionStyle::Slide if self.config.vertical => (
+                Transform::translate(0.0, direction * progress * height),
+                1.0,
+            ),
+            TransitionStyle::Slide => {
+                (Transform::translate(direction * progress * width, 0.0), 1.0)
+            }
+            TransitionStyle::Fade => (Transform::IDENTITY, opacity),
+            TransitionStyle::Zoom => {
+                // Going forward, each workspace grows past the viewer; back, it shrinks.
+                let scale = 1.0 - direction * progress * ZOOM_DEPTH;
+                let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+                let transform = Transform {
+                    x: cx * (1.0 - scale),
+                    y: cy * (1.0 - scale),
+                    scale_x: scale,
+                    scale_y: scale,
+                };
+                (transform, opacity)
+            }
+        }
+    }
+}
+
+/// `item` moved by `transform` within output pixels and faded by `opacity`.
+fn place(item: &DrawItem, transform: &Transform, opacity: f32) -> DrawItem {
+    let [x, y, width, height] = item.rect;
+    let scale = transform.scale_x;
+    let source = match &item.source {
+        DrawSource::Shadow(spread) => DrawSource::Shadow(spread * scale),
+        source => source.clone(),
+    };
+    DrawItem {
+        rect: [
+            transform.x + x * scale,
+            transform.y + y * transform.scale_y,
+            width * scale,
+            height * transform.scale_y,
+        ],
+        color: item.color.map(|c| c * opacity),
+        source,
+        corner_radius: item.corner_radius * scale,
+        border: item.border * scale,
+    }
+}
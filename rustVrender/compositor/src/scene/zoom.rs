@@ -0,0 +1,145 @@
+// Zoom: magnifies the output under the pointer around the pointer, for accessibility
+// and presentations, animating between levels.
+
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use super::animation::{Curve, Easing};
+
+/// How far zoom goes, and how it gets there.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZoomConfig {
+    /// Factor each `ZoomIn` multiplies the level by, and `ZoomOut` divides it by.
+    pub step: f32,
+    pub max_level: f32,
+    /// Keeps zooming around the pointer as it moves, so the whole output stays in
+    /// reach; otherwise the view stays where it was zoomed in.
+    pub follow_pointer: bool,
+    pub animation: Curve,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> Self {
+        Self {
+            step: 1.5,
+            max_level: 16.0,
+            follow_pointer: true,
+            animation: Curve::new(200, Easing::EaseOutCubic),
+        }
+    }
+}
+
+/// The zoom level, as it animates towards the last one asked for, and where it is
+/// centered.
+#[derive(Debug)]
+pub struct Zoom {
+    config: ZoomConfig,
+    from: f32,
+    to: f32,
+    start: Instant,
+    /// The level at the last `advance`.
+    shown: f32,
+    /// Where `ZoomToggle` zooms back in to.
+    restore: f32,
+    /// The point, in root window coordinates, that stays put while zooming.
+    center: Option<[f32; 2]>,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        let config = ZoomConfig::default();
+        let restore = config.step;
+        Self {
+            config,
+            from: 1.0,
+            to: 1.0,
+            start: Instant::now(),
+            shown: 1.0,
+            restore,
+            center: None,
+        }
+    }
+}
+
+impl Zoom {
+    pub fn config(&self) -> &ZoomConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ZoomConfig) {
+        self.restore = config.step.clamp(1.0, config.max_level.max(1.0));
+        self.config = config;
+        let to = self.to;
+        self.set_level(to);
+    }
+
+    pub fn zoom_in(&mut self, pointer: Option<[f32; 2]>) {
+        let level = self.to * self.config.step.max(1.0);
+        self.zoom_to(level, pointer);
+    }
+
+    pub fn zoom_out(&mut self) {
+        let level = self.to / self.config.step.max(1.0);
+        self.set_level(level);
+    }
+
+    /// Zooms all the way out, or back in to where it was.
+    pub fn toggle(&mut self, pointer: Option<[f32; 2]>) {
+        if self.to > 1.0 {
+            self.restore = self.to;
+            self.set_level(1.0);
+        } else {
+            let level = self.restore;
+            self.zoom_to(level, pointer);
+        }
+    }
+
+    /// Follows the pointer to `pointer`; returns whether the view moved.
+    pub fn pointer_moved(&mut self, pointer: [f32; 2]) -> bool {
+        if !self.config.follow_pointer || (self.to <= 1.0 && self.shown <= 1.0) {
+            return false;
+        }
+        let moved = self.center != Some(pointer);
+        self.center = Some(pointer);
+        moved
+    }
+
+    fn zoom_to(&mut self, level: f32, pointer: Option<[f32; 2]>) {
+        // Zooming in from 1 picks the point to zoom around.
+        if self.to <= 1.0 || self.config.follow_pointer {
+            self.center = pointer.or(self.center);
+        }
+        self.set_level(level);
+    }
+
+    fn set_level(&mut self, level: f32) {
+        let level = level.clamp(1.0, self.config.max_level.max(1.0));
+        if level != self.to {
+            self.from = self.shown;
+            self.to = level;
+            self.start = Instant::now();
+        }
+    }
+
+    /// Moves the animation on to `now`; returns whether it is still running.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let duration = self.config.animation.duration();
+        let t = if duration.is_zero() {
+            1.0
+        } else {
+            now.saturating_duration_since(self.start).as_secs_f32() / duration.as_secs_f32()
+        };
+        // Interpolated in log space, so each step looks as fast as the last.
+        let eased = self.config.animation.easing.apply(t);
+        self.shown = (self.from.ln() + (self.to.ln() - self.from.ln()) * eased).exp();
+        t < 1.0 && self.from != self.to
+    }
+
+    /// The level at the last `advance`, and the point zoomed around; `None` when not
+    /// zoomed in.
+    pub fn view(&self) -> Option<(f32, Option<[f32; 2]>)> {
+        (self.shown > 1.0).then_some((self.shown, self.center))
+    }
+}
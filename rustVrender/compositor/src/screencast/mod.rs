@@ -0,0 +1,150 @@
+// Screencasts: render loops capture an output, window, or region at a fixed rate and
+// hand each frame to a sink. Built with the screencast feature, every sink is a
+// PipeWire video source that OBS, GStreamer, or a portal backend can connect to.
+
+#[cfg(feature = "screencast")]
+mod stream;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::scene::{Scene, SharedScene};
+use crate::screenshot::{Capture, ScreenshotTarget};
+
+pub use qtilerugo_ipc::renderer::Screencast;
+
+/// Frame rate of screencasts that don't ask for one.
+const DEFAULT_FPS: u32 = 30;
+
+/// How long frames of `screencast` are apart at least.
+fn interval(screencast: &Screencast) -> Duration {
+    let fps = screencast.fps.filter(|f| *f > 0).unwrap_or(DEFAULT_FPS);
+    Duration::from_secs_f64(1.0 / fps as f64)
+}
+
+/// Takes captured frames; returns `false` once nobody consumes them anymore.
+pub type FrameSink = Box<dyn FnMut(Capture) -> bool + Send>;
+
+struct Feed {
+    id: u32,
+    target: ScreenshotTarget,
+    interval: Duration,
+    next_frame: Instant,
+    /// Scene generation of the last frame sent.
+    sent_generation: Option<u64>,
+    sink: FrameSink,
+}
+
+/// Running screencasts, keyed by the id `start` returned.
+#[derive(Default)]
+pub struct Screencasts {
+    feeds: Vec<Feed>,
+}
+
+impl fmt::Debug for Screencasts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.feeds.iter().map(|feed| (feed.id, &feed.target)))
+            .finish()
+    }
+}
+
+impl Screencasts {
+    /// Feeds `sink` with captures of `screencast.target`, replacing any screencast with
+    /// the same id.
+    pub fn add(&mut self, id: u32, screencast: &Screencast, sink: FrameSink) {
+        self.remove(id);
+        self.feeds.push(Feed {
+            id,
+            target: screencast.target.clone(),
+            interval: interval(screencast),
+            next_frame: Instant::now(),
+            sent_generation: None,
+            sink,
+        });
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.feeds.len();
+        self.feeds.retain(|feed| feed.id != id);
+        self.feeds.len() != count
+    }
+
+    /// Screencasts due a frame at `now`, given the scene is at `generation`.
+    pub fn due(&mut self, now: Instant, generation: u64) -> Vec<(u32, ScreenshotTarget)> {
+        let mut due = Vec::new();
+        for feed in &mut self.feeds {
+            if now < feed.next_frame || feed.sent_generation == Some(generation) {
+                continue;
+            }
+            feed.next_frame = (feed.next_frame + feed.interval).max(now);
+            feed.sent_generation = Some(generation);
+            due.push((feed.id, feed.target.clone()));
+        }
+        due
+    }
+
+    /// Hands `capture` to screencast `id`, ending it if its consumer went away.
+    pub fn send(&mut self, id: u32, capture: Capture) {
+        let Some(index) = self.feeds.iter().position(|feed| feed.id == id) else {
+            return;
+        };
+        if !(self.feeds[index].sink)(capture) {
+            println!("Screencast {} ended.", id);
+            self.feeds.remove(index);
+        }
+    }
+}
+
+/// Size of the frames a screencast of `target` starts with.
+pub fn target_size(scene: &Scene, target: &ScreenshotTarget) -> Result<(u32, u32), String> {
+    match target {
+        ScreenshotTarget::Output(name) => scene
+            .output_named(name.as_deref())
+            .and_then(|output| scene.output_size(output))
+            .ok_or_else(|| match name {
+                Some(name) => format!("no output {}", name),
+                None => "no outputs".into(),
+            }),
+        ScreenshotTarget::Window(window) => scene
+            .window_contents(*window)
+            .map(|contents| (contents.width, contents.height))
+            .ok_or_else(|| format!("window {} has no contents", window)),
+        ScreenshotTarget::Region {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            // Regions are captured in the pixels of the output they start on.
+            let scale = scene.output_at(*x, *y).map_or(1.0, |o| scene.output_scale(o));
+            Ok((
+                (*width as f32 * scale).round() as u32,
+                (*height as f32 * scale).round() as u32,
+            ))
+        }
+    }
+}
+
+/// Starts streaming `screencast`; returns its id, which is the PipeWire node id.
+pub fn start(scene: &SharedScene, screencast: Screencast) -> Result<u32, String> {
+    #[cfg(feature = "screencast")]
+    {
+        stream::start(scene, screencast)
+    }
+    #[cfg(not(feature = "screencast"))]
+    {
+        let _ = (scene, screencast);
+        Err("built without the screencast feature".into())
+    }
+}
+
+/// Ends screencast `id`.
+pub fn stop(scene: &SharedScene, id: u32) -> Result<(), String> {
+    // Dropping the sink tells the stream to shut down.
+    if scene.lock().unwrap().screencasts_mut().remove(id) {
+        Ok(())
+    } else {
+        Err(format!("no screencast {}", id))
+    }
+}
@@ -0,0 +1,225 @@
+// PipeWire side of screencasts: every stream runs its own main loop on a thread. The
+// stream is the driver of its graph, so queueing a buffer with a new frame is what
+// starts a cycle; frames arriving while the consumer holds every buffer are dropped.
+
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use pipewire as pw;
+use pw::properties::properties;
+use pw::spa::param::format::{FormatProperties, MediaSubtype, MediaType};
+use pw::spa::param::video::VideoFormat;
+use pw::spa::param::ParamType;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{ChoiceValue, Object, Pod, Property, Value};
+use pw::spa::utils::{Choice, ChoiceEnum, ChoiceFlags, Direction, Fraction, Rectangle, SpaTypes};
+use pw::stream::{Stream, StreamFlags, StreamRef, StreamState};
+
+use super::{target_size, Screencast};
+use crate::scene::SharedScene;
+use crate::screenshot::Capture;
+
+/// How long PipeWire may take to give a new stream its node id.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Message {
+    Frame(Capture),
+    Stop,
+}
+
+/// Stops the stream when the render loops drop its sink.
+struct StopOnDrop(pw::channel::Sender<Message>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.send(Message::Stop);
+    }
+}
+
+pub(super) fn start(scene: &SharedScene, screencast: Screencast) -> Result<u32, String> {
+    let (width, height) = target_size(&scene.lock().unwrap(), &screencast.target)?;
+    let fps = (1.0 / super::interval(&screencast).as_secs_f64()).round() as u32;
+    let (sender, receiver) = pw::channel::channel();
+    let stop = StopOnDrop(sender);
+    let (ready, node) = mpsc::channel();
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        thread::spawn(move || {
+            if let Err(e) = run(width, height, fps, receiver, ready.clone()) {
+                let _ = ready.send(Err(e.to_string()));
+            }
+            running.store(false, Ordering::Relaxed);
+        });
+    }
+    let id = node
+        .recv_timeout(STARTUP_TIMEOUT)
+        .map_err(|_| "PipeWire did not set up the stream".to_string())??;
+
+    println!("Screencast {} streams {}x{} frames.", id, width, height);
+    let sink = move |capture| {
+        running.load(Ordering::Relaxed) && stop.0.send(Message::Frame(capture)).is_ok()
+    };
+    scene
+        .lock()
+        .unwrap()
+        .screencasts_mut()
+        .add(id, &screencast, Box::new(sink));
+    Ok(id)
+}
+
+fn run(
+    width: u32,
+    height: u32,
+    fps: u32,
+    receiver: pw::channel::Receiver<Message>,
+    ready: mpsc::Sender<Result<u32, String>>,
+) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+    let stream = Rc::new(Stream::new(
+        &core,
+        "qtilerugo-screencast",
+        properties! {
+            *pw::keys::MEDIA_CLASS => "Video/Source",
+            *pw::keys::MEDIA_TYPE => "Video",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Screen",
+        },
+    )?);
+
+    let mut ready = Some(ready);
+    let quit = mainloop.clone();
+    let _listener = stream
+        .add_local_listener()
+        .state_changed(move |stream, _: &mut (), _, state| match state {
+            StreamState::Paused | StreamState::Streaming => {
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(Ok(stream.node_id()));
+                }
+            }
+            StreamState::Error(e) => {
+                eprintln!("Screencast stream failed: {}", e);
+                quit.quit();
+            }
+            _ => {}
+        })
+        .param_changed(move |stream, _, id, param| {
+            if id != ParamType::Format.as_raw() || param.is_none() {
+                return;
+            }
+            // Ask for buffers PipeWire allocates as memfds, sized for one frame. Only
+            // memfds: frames are read back from the GPU, and nothing is exported as a
+            // DMA-BUF, which would need exportable images and a negotiated modifier.
+            let buffers = serialize(Object {
+                type_: SpaTypes::ObjectParamBuffers.as_raw(),
+                id: ParamType::Buffers.as_raw(),
+                properties: vec![
+                    Property::new(
+                        pw::spa::sys::SPA_PARAM_BUFFERS_buffers,
+                        Value::Choice(ChoiceValue::Int(Choice(
+                            ChoiceFlags::empty(),
+                            ChoiceEnum::Range {
+                                default: 4,
+                                min: 2,
+                                max: 16,
+                            },
+                        ))),
+                    ),
+                    Property::new(pw::spa::sys::SPA_PARAM_BUFFERS_blocks, Value::Int(1)),
+                    Property::new(
+                        pw::spa::sys::SPA_PARAM_BUFFERS_size,
+                        Value::Int((width * height * 4) as i32),
+                    ),
+                    Property::new(
+                        pw::spa::sys::SPA_PARAM_BUFFERS_stride,
+                        Value::Int((width * 4) as i32),
+                    ),
+                    Property::new(
+                        pw::spa::sys::SPA_PARAM_BUFFERS_dataType,
+                        Value::Int(1 << pw::spa::sys::SPA_DATA_MemFd),
+                    ),
+                ],
+            });
+            if let Err(e) = stream.update_params(&mut [Pod::from_bytes(&buffers).unwrap()]) {
+                eprintln!("Failed to set screencast buffers: {}", e);
+            }
+        })
+        .register()?;
+
+    let format = serialize(pw::spa::pod::object!(
+        SpaTypes::ObjectParamFormat,
+        ParamType::EnumFormat,
+        pw::spa::pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
+        pw::spa::pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+        pw::spa::pod::property!(FormatProperties::VideoFormat, Id, VideoFormat::RGBA),
+        pw::spa::pod::property!(
+            FormatProperties::VideoSize,
+            Rectangle,
+            Rectangle { width, height }
+        ),
+        // Frames come whenever the scene changes, up to the screencast's rate.
+        pw::spa::pod::property!(
+            FormatProperties::VideoFramerate,
+            Fraction,
+            Fraction { num: 0, denom: 1 }
+        ),
+        pw::spa::pod::property!(
+            FormatProperties::VideoMaxFramerate,
+            Fraction,
+            Fraction { num: fps, denom: 1 }
+        ),
+    ));
+    stream.connect(
+        Direction::Output,
+        None,
+        StreamFlags::DRIVER | StreamFlags::MAP_BUFFERS,
+        &mut [Pod::from_bytes(&format).unwrap()],
+    )?;
+
+    let quit = mainloop.clone();
+    let _receiver = receiver.attach(mainloop.loop_(), move |message| match message {
+        Message::Frame(capture) => queue_frame(&stream, &capture, width, height),
+        Message::Stop => quit.quit(),
+    });
+    mainloop.run();
+    Ok(())
+}
+
+/// Copies `capture` into a free buffer and queues it; a capture of another size is
+/// clipped or padded with black to the negotiated one.
+fn queue_frame(stream: &StreamRef, capture: &Capture, width: u32, height: u32) {
+    let Some(mut buffer) = stream.dequeue_buffer() else {
+        return;
+    };
+    let Some(data) = buffer.datas_mut().first_mut() else {
+        return;
+    };
+    let stride = width as usize * 4;
+    if let Some(pixels) = data.data() {
+        let rows = height.min(capture.height) as usize;
+        let row = width.min(capture.width) as usize * 4;
+        pixels.fill(0);
+        for y in 0..rows.min(pixels.len() / stride) {
+            let source = y * capture.width as usize * 4;
+            pixels[y * stride..y * stride + row]
+                .copy_from_slice(&capture.rgba[source..source + row]);
+        }
+    }
+    let chunk = data.chunk_mut();
+    *chunk.offset_mut() = 0;
+    *chunk.stride_mut() = stride as i32;
+    *chunk.size_mut() = (stride * height as usize) as u32;
+    // Dropping the buffer queues it, which drives the next cycle.
+}
+
+fn serialize(object: Object) -> Vec<u8> {
+    PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(object))
+        .expect("failed to serialize a pod")
+        .0
+        .into_inner()
+}
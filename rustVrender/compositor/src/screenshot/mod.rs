@@ -0,0 +1,211 @@
+// Screenshots without an external tool: render loops capture the requested pixels
+// and hand them back through a oneshot, and the command listener encodes the file.
+
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::ImageEncoder;
+
+use tokio::sync::oneshot;
+
+use crate::scene::WindowContents;
+
+pub use qtilerugo_ipc::renderer::{ImageFormat, Screenshot, ScreenshotTarget};
+
+/// The extension of images written in `format`.
+fn extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpg",
+    }
+}
+
+/// The path and format the image `screenshot` asks for will be written with.
+pub fn destination(screenshot: &Screenshot) -> (PathBuf, ImageFormat) {
+    let format = screenshot.format.unwrap_or_else(|| {
+        match screenshot
+            .path
+            .as_deref()
+            .and_then(Path::extension)
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("jpg" | "jpeg") => ImageFormat::Jpeg,
+            _ => ImageFormat::Png,
+        }
+    });
+    let path = screenshot.path.clone().unwrap_or_else(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        PathBuf::from(format!(
+            "/tmp/qtilerugo-screenshot-{}.{}",
+            secs,
+            extension(format)
+        ))
+    });
+    (path, format)
+}
+
+/// Captured pixels: unpremultiplied RGBA rows, tightly packed.
+#[derive(Clone)]
+pub struct Capture {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl fmt::Debug for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Capture {
+    /// Converts a window's premultiplied BGRA contents.
+    pub fn from_contents(contents: &WindowContents) -> Self {
+        let mut rgba = Vec::with_capacity(contents.data.len());
+        for pixel in contents.data.chunks_exact(4) {
+            let alpha = if contents.opaque { 255 } else { pixel[3] };
+            let unpremultiply = |c: u8| match alpha {
+                0 => 0,
+                255 => c,
+                a => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+            };
+            rgba.extend_from_slice(&[
+                unpremultiply(pixel[2]),
+                unpremultiply(pixel[1]),
+                unpremultiply(pixel[0]),
+                alpha,
+            ]);
+        }
+        Self {
+            width: contents.width,
+            height: contents.height,
+            rgba,
+        }
+    }
+
+    /// Converts back to premultiplied BGRA, to draw as a window's contents.
+    pub fn to_contents(&self) -> WindowContents {
+        let mut data = Vec::with_capacity(self.rgba.len());
+        for pixel in self.rgba.chunks_exact(4) {
+            let alpha = pixel[3] as u32;
+            let premultiply = |c: u8| ((c as u32 * alpha + 127) / 255) as u8;
+            data.extend_from_slice(&[
+                premultiply(pixel[2]),
+                premultiply(pixel[1]),
+                premultiply(pixel[0]),
+                pixel[3],
+            ]);
+        }
+        WindowContents {
+            width: self.width,
+            height: self.height,
+            data: Arc::new(data),
+            opaque: false,
+        }
+    }
+
+    /// Cuts out the part of the rectangle at `x`, `y` that lies inside the capture.
+    pub fn crop(&self, x: i32, y: i32, width: u32, height: u32) -> Result<Self, String> {
+        let left = x.clamp(0, self.width as i32) as u32;
+        let top = y.clamp(0, self.height as i32) as u32;
+        let right = (x as i64 + width as i64).clamp(0, self.width as i64) as u32;
+        let bottom = (y as i64 + height as i64).clamp(0, self.height as i64) as u32;
+        if right <= left || bottom <= top {
+            return Err("the region is empty".into());
+        }
+        let row = self.width as usize * 4;
+        let mut rgba = Vec::with_capacity((right - left) as usize * (bottom - top) as usize * 4);
+        for y in top..bottom {
+            let start = y as usize * row + left as usize * 4;
+            rgba.extend_from_slice(&self.rgba[start..start + (right - left) as usize * 4]);
+        }
+        Ok(Self {
+            width: right - left,
+            height: bottom - top,
+            rgba,
+        })
+    }
+
+    /// Encodes the capture to `path`; JPEG drops the alpha channel.
+    pub fn save(&self, path: &Path, format: ImageFormat) -> Result<(), String> {
+        let result = match format {
+            ImageFormat::Png => image::save_buffer_with_format(
+                path,
+                &self.rgba,
+                self.width,
+                self.height,
+                image::ExtendedColorType::Rgba8,
+                image::ImageFormat::Png,
+            ),
+            ImageFormat::Jpeg => {
+                let rgb: Vec<u8> = self
+                    .rgba
+                    .chunks_exact(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect();
+                image::save_buffer_with_format(
+                    path,
+                    &rgb,
+                    self.width,
+                    self.height,
+                    image::ExtendedColorType::Rgb8,
+                    image::ImageFormat::Jpeg,
+                )
+            }
+        };
+        result.map_err(|e| e.to_string())
+    }
+
+    /// Encodes the capture as a PNG in memory.
+    pub fn png(&self) -> Result<Vec<u8>, String> {
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png)
+            .write_image(
+                &self.rgba,
+                self.width,
+                self.height,
+                image::ExtendedColorType::Rgba8,
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(png)
+    }
+
+    /// Pipes the capture as a PNG to `command`, run with sh -c, like a clipboard tool.
+    pub fn copy(&self, command: &str) -> Result<(), String> {
+        let png = self.png()?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let mut stdin = child.stdin.take().unwrap();
+        let written = stdin.write_all(&png).map_err(|e| e.to_string());
+        drop(stdin);
+        let status = child.wait().map_err(|e| e.to_string())?;
+        written?;
+        if !status.success() {
+            return Err(format!("{} exited with {}", command, status));
+        }
+        Ok(())
+    }
+}
+
+/// A capture waiting for a render loop to pick it up.
+#[derive(Debug)]
+pub struct ScreenshotRequest {
+    pub target: ScreenshotTarget,
+    pub reply: oneshot::Sender<Result<Capture, String>>,
+}
@@ -0,0 +1,106 @@
+// Shared-memory ring carrying window updates from the bridge: a memfd holding a
+// `Header` and `CAPACITY` records, plus an eventfd doorbell. Both are handed to the
+// bridge over the control socket with `AttachShm`. The layout is qtilerugo-ipc's,
+// which the bridge writes it by.
+
+use std::fs::File;
+use std::io::{self, IoSlice};
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::atomic::Ordering;
+
+use memmap2::MmapMut;
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+
+use crate::scene::SharedScene;
+
+pub use qtilerugo_ipc::ring::{
+    Header, Record, MAGIC, RECORD_CLEAR, RECORD_REMOVE, RECORD_UPSERT, VERSION,
+};
+
+/// Number of records the ring holds.
+pub const CAPACITY: usize = 1024;
+
+/// The consumer end of the ring.
+pub struct Ring {
+    map: MmapMut,
+    memfd: File,
+    doorbell: EventFd,
+}
+
+impl Ring {
+    pub fn create() -> io::Result<Self> {
+        let memfd: OwnedFd = memfd_create(c"qtilerugo-ring", MemFdCreateFlag::MFD_CLOEXEC)?;
+        let memfd = File::from(memfd);
+        memfd.set_len((size_of::<Header>() + CAPACITY * size_of::<Record>()) as u64)?;
+        // SAFETY: the memfd is private to us until handed out, and only ever resized here.
+        let map = unsafe { MmapMut::map_mut(&memfd)? };
+        let doorbell = EventFd::from_value_and_flags(0, EfdFlags::EFD_CLOEXEC)?;
+
+        let ring = Self {
+            map,
+            memfd,
+            doorbell,
+        };
+        let header = ring.header_ptr();
+        // SAFETY: the mapping is large enough for the header and nobody else sees it yet.
+        unsafe {
+            (*header).magic = MAGIC;
+            (*header).version = VERSION;
+            (*header).capacity = CAPACITY as u32;
+        }
+        Ok(ring)
+    }
+
+    /// Passes the memfd and eventfd to the peer on `socket`.
+    pub fn send_fds(&self, socket: RawFd) -> io::Result<()> {
+        let fds = [self.memfd.as_raw_fd(), self.doorbell.as_raw_fd()];
+        let payload = [0u8];
+        sendmsg::<()>(
+            socket,
+            &[IoSlice::new(&payload)],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Applies published records to `scene` until the doorbell fails.
+    pub fn run(&self, scene: &SharedScene) {
+        loop {
+            if let Err(e) = self.doorbell.read() {
+                eprintln!("Shared-memory doorbell failed: {}", e);
+                return;
+            }
+            let mut scene = scene.lock().unwrap();
+            self.drain(|record| match record.kind {
+                RECORD_UPSERT => scene.upsert_window(&record.window_state(), record.z),
+                RECORD_REMOVE => scene.remove_window(record.window),
+                RECORD_CLEAR => scene.sync_windows(&[]),
+                kind => eprintln!("Unknown shared-memory record kind: {}", kind),
+            });
+        }
+    }
+
+    fn header_ptr(&self) -> *mut Header {
+        self.map.as_ptr() as *mut Header
+    }
+
+    fn drain(&self, mut apply: impl FnMut(Record)) {
+        // SAFETY: the header lives at the start of the mapping; head and tail are atomics.
+        let header = unsafe { &*self.header_ptr() };
+        let head = header.head.load(Ordering::Acquire);
+        let mut tail = header.tail.load(Ordering::Relaxed);
+        let records = unsafe { self.header_ptr().add(1) as *const Record };
+        while tail < head {
+            // SAFETY: the producer never overwrites slots in [tail, head).
+            let record = unsafe { records.add((tail % CAPACITY as u64) as usize).read() };
+            apply(record);
+            tail += 1;
+        }
+        header.tail.store(tail, Ordering::Release);
+    }
+}
@@ -0,0 +1,161 @@
+// The frame statistics HUD: a few lines of text in a built-in 3x5 pixel font over a
+// graph of recent frame times, drawn as solid quads in the top left of an output.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::{OutputReport, Sample};
+use crate::scene::{DrawItem, DrawSource};
+
+/// Size of one font pixel, in output pixels at scale 1.
+const PIXEL: f32 = 2.0;
+/// Padding around the panel's contents, in font pixels.
+const PADDING: f32 = 3.0;
+const GRAPH_HEIGHT: f32 = 40.0;
+/// Width of one frame's bar at scale 1.
+const BAR_WIDTH: f32 = 1.0;
+
+const PANEL: [f32; 4] = [0.05, 0.05, 0.06, 0.75];
+const TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const ON_TIME: [f32; 4] = [0.35, 0.8, 0.45, 1.0];
+const MISSED: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+const BUDGET: [f32; 4] = [0.9, 0.9, 0.9, 0.4];
+
+/// Rows of a glyph, top first, three bits each with the leftmost pixel highest.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' | 'S' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        '.' => [0, 0, 0, 0, 2],
+        '/' => [1, 1, 2, 4, 4],
+        'A' => [2, 5, 7, 5, 5],
+        'C' => [7, 4, 4, 4, 7],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 7, 4, 7],
+        'F' => [7, 4, 7, 4, 4],
+        'G' => [7, 4, 5, 5, 7],
+        'I' => [7, 2, 2, 2, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [7, 5, 5, 5, 5],
+        'P' => [7, 5, 7, 4, 4],
+        'R' => [7, 5, 6, 5, 5],
+        'U' => [5, 5, 5, 5, 7],
+        _ => [0; 5],
+    }
+}
+
+fn solid(rect: [f32; 4], color: [f32; 4], corner_radius: f32) -> DrawItem {
+    let [r, g, b, a] = color;
+    DrawItem {
+        rect,
+        color: [r * a, g * a, b * a, a],
+        source: DrawSource::Solid,
+        corner_radius,
+        border: 0.0,
+    }
+}
+
+/// Draws `text` with its top left at `x`, `y`, merging lit pixels within a row.
+fn text(items: &mut Vec<DrawItem>, text: &str, x: f32, y: f32, pixel: f32) {
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as f32 * 4.0 * pixel;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            let top = y + row as f32 * pixel;
+            let mut column = 0;
+            while column < 3 {
+                if bits & (4 >> column) == 0 {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < 3 && bits & (4 >> column) != 0 {
+                    column += 1;
+                }
+                let rect = [
+                    left + start as f32 * pixel,
+                    top,
+                    (column - start) as f32 * pixel,
+                    pixel,
+                ];
+                items.push(solid(rect, TEXT, 0.0));
+            }
+        }
+    }
+}
+
+fn lines(report: &OutputReport) -> Vec<String> {
+    let optional = |value: Option<f32>, unit: &str| match value {
+        Some(value) => format!("{:.1}{}", value, unit),
+        None => "N/A".into(),
+    };
+    vec![
+        format!("FPS {}", optional(report.fps, "")),
+        format!("FRAME {}", optional(report.frame_ms, " MS")),
+        format!("CPU {:.2} MS", report.cpu_ms),
+        format!(
+            "GPU {}",
+            report
+                .gpu_ms
+                .map_or("N/A".into(), |ms| format!("{:.2} MS", ms))
+        ),
+        format!("MISSED {}", report.missed_vblanks),
+        match (report.memory.device_mib, report.memory.budget_mib) {
+            (Some(used), Some(budget)) => format!("MEM {:.0}/{:.0} M", used, budget),
+            _ => format!("MEM {:.0} M", report.memory.texture_mib),
+        },
+    ]
+}
+
+pub(super) fn items(
+    report: &OutputReport,
+    samples: &VecDeque<Sample>,
+    refresh: Duration,
+    scale: f32,
+) -> Vec<DrawItem> {
+    let pixel = PIXEL * scale;
+    let padding = PADDING * pixel;
+    let lines = lines(report);
+    let text_width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as f32 * 4.0 * pixel;
+    let graph_width = super::HISTORY as f32 * BAR_WIDTH * scale;
+    let graph_height = GRAPH_HEIGHT * scale;
+    let text_height = lines.len() as f32 * 7.0 * pixel;
+    let width = text_width.max(graph_width) + 2.0 * padding;
+    let height = text_height + graph_height + 3.0 * padding;
+
+    let mut items = vec![solid([padding, padding, width, height], PANEL, 2.0 * pixel)];
+    let (x, y) = (2.0 * padding, 2.0 * padding);
+    for (i, line) in lines.iter().enumerate() {
+        text(&mut items, line, x, y + i as f32 * 7.0 * pixel, pixel);
+    }
+
+    // One bar per frame; the refresh interval reaches halfway up.
+    let bottom = y + text_height + padding + graph_height;
+    let budget = refresh.as_secs_f32().max(f32::EPSILON);
+    for (i, sample) in samples.iter().enumerate() {
+        let time = sample
+            .interval
+            .unwrap_or_else(|| sample.cpu.max(sample.gpu.unwrap_or_default()));
+        let bar = (time.as_secs_f32() / budget / 2.0).min(1.0) * graph_height;
+        let color = if sample.missed { MISSED } else { ON_TIME };
+        let left = x + i as f32 * BAR_WIDTH * scale;
+        items.push(solid(
+            [left, bottom - bar, BAR_WIDTH * scale, bar],
+            color,
+            0.0,
+        ));
+    }
+    items.push(solid(
+        [x, bottom - graph_height / 2.0, graph_width, scale],
+        BUDGET,
+        0.0,
+    ));
+    items
+}
@@ -0,0 +1,148 @@
+// Frame timing statistics: render loops record how long each frame took on the CPU
+// and GPU, whether it missed its vblank, and the GPU memory the renderer held after
+// it, `GetFrameStats` reports them, and the HUD draws them over each output.
+
+mod hud;
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::scene::{DrawItem, NodeId};
+
+pub use qtilerugo_ipc::renderer::{MemoryUsage, OutputReport};
+
+/// Frames kept per output; reports and the HUD graph cover this many.
+const HISTORY: usize = 120;
+
+/// How long the renderer spent on one frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTiming {
+    /// Recording and submitting, not waiting for a swapchain image.
+    pub cpu: Duration,
+    /// Executing an earlier frame of the same output, once its timestamps are in.
+    pub gpu: Option<Duration>,
+    pub memory: MemoryUsage,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Time since the previous frame, if the output was drawing continuously.
+    interval: Option<Duration>,
+    cpu: Duration,
+    gpu: Option<Duration>,
+    missed: bool,
+}
+
+#[derive(Debug)]
+struct OutputStats {
+    name: String,
+    frames: u64,
+    missed_vblanks: u64,
+    /// The refresh interval frames are measured against.
+    refresh: Duration,
+    last_frame: Option<Instant>,
+    samples: VecDeque<Sample>,
+    memory: MemoryUsage,
+}
+
+/// Frame statistics of every output, keyed by scene node.
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    outputs: HashMap<NodeId, OutputStats>,
+}
+
+impl FrameStats {
+    /// Records a frame of `output` that started at `now`. `refresh` is the output's
+    /// refresh interval, and `continuous` whether the previous frame was drawn because
+    /// something kept changing, so the time since it counts towards missed vblanks.
+    pub fn record(
+        &mut self,
+        output: NodeId,
+        name: &str,
+        now: Instant,
+        timing: FrameTiming,
+        refresh: Duration,
+        continuous: bool,
+    ) {
+        let stats = self.outputs.entry(output).or_insert_with(|| OutputStats {
+            name: name.to_string(),
+            frames: 0,
+            missed_vblanks: 0,
+            refresh,
+            last_frame: None,
+            samples: VecDeque::with_capacity(HISTORY),
+            memory: MemoryUsage::default(),
+        });
+        stats.refresh = refresh;
+        stats.memory = timing.memory;
+        let interval = stats
+            .last_frame
+            .filter(|_| continuous)
+            .map(|last| now.duration_since(last));
+        // A frame more than half an interval late took the next vblank or later.
+        let missed = interval.map_or(0, |interval| {
+            (interval.as_secs_f64() / refresh.as_secs_f64() - 0.5)
+                .floor()
+                .max(0.0) as u64
+        });
+        stats.frames += 1;
+        stats.missed_vblanks += missed;
+        stats.last_frame = Some(now);
+        if stats.samples.len() == HISTORY {
+            stats.samples.pop_front();
+        }
+        stats.samples.push_back(Sample {
+            interval,
+            cpu: timing.cpu,
+            gpu: timing.gpu,
+            missed: missed > 0,
+        });
+    }
+
+    pub fn remove(&mut self, output: NodeId) {
+        self.outputs.remove(&output);
+    }
+
+    /// Statistics of every output that has drawn a frame, by name.
+    pub fn report(&self) -> Vec<OutputReport> {
+        let mut reports: Vec<OutputReport> =
+            self.outputs.values().map(OutputStats::report).collect();
+        reports.sort_by(|a, b| a.output.cmp(&b.output));
+        reports
+    }
+
+    /// The HUD for `output`, drawn at `scale` times its normal size.
+    pub fn hud_items(&self, output: NodeId, scale: f32) -> Vec<DrawItem> {
+        match self.outputs.get(&output) {
+            Some(stats) => hud::items(&stats.report(), &stats.samples, stats.refresh, scale),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl OutputStats {
+    fn report(&self) -> OutputReport {
+        let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+        let average = |times: &[Duration]| {
+            (!times.is_empty()).then(|| ms(times.iter().sum::<Duration>()) / times.len() as f32)
+        };
+        let maximum = |times: &[Duration]| times.iter().copied().max().map(ms);
+        let intervals: Vec<Duration> = self.samples.iter().filter_map(|s| s.interval).collect();
+        let cpu: Vec<Duration> = self.samples.iter().map(|s| s.cpu).collect();
+        let gpu: Vec<Duration> = self.samples.iter().filter_map(|s| s.gpu).collect();
+        let frame_ms = average(&intervals);
+        OutputReport {
+            output: self.name.clone(),
+            frames: self.frames,
+            missed_vblanks: self.missed_vblanks,
+            refresh_ms: ms(self.refresh),
+            fps: frame_ms.filter(|ms| *ms > 0.0).map(|ms| 1000.0 / ms),
+            frame_ms,
+            cpu_ms: average(&cpu).unwrap_or(0.0),
+            cpu_ms_max: maximum(&cpu).unwrap_or(0.0),
+            gpu_ms: average(&gpu),
+            gpu_ms_max: maximum(&gpu),
+            memory: self.memory,
+        }
+    }
+}
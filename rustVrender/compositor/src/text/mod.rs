@@ -0,0 +1,176 @@
+// Text for compositor UI like bars, tab titles, notifications and OSDs: fonts are
+// rasterized with fontdue, and text is laid out here into glyph draw items that the
+// renderer draws from a glyph atlas.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use fontdue::FontSettings;
+
+use crate::scene::{DrawItem, DrawSource};
+
+/// Tried in order when no font is configured.
+const DEFAULT_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+];
+
+/// Largest size glyphs are rasterized at; bigger text is clamped to it.
+pub const MAX_PX: f32 = 256.0;
+
+static NEXT_FONT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A loaded font face.
+pub struct Font {
+    /// Tells glyphs of different fonts apart in glyph caches.
+    id: u64,
+    path: PathBuf,
+    font: fontdue::Font,
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("id", &self.id)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// One glyph of laid out text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    pub index: u16,
+    /// The glyph's bitmap, in whole pixels from the top left of the text.
+    pub rect: [f32; 4],
+}
+
+/// Text laid out in lines, at one size.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextLayout {
+    /// The size glyphs are rasterized at.
+    pub px: u16,
+    pub glyphs: Vec<PlacedGlyph>,
+    /// The widest line's advance.
+    pub width: f32,
+    /// From the first line's ascent to the last line's descent.
+    pub height: f32,
+}
+
+impl Font {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let font = fontdue::Font::from_bytes(bytes, FontSettings::default())?;
+        Ok(Self {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            path: path.to_path_buf(),
+            font,
+        })
+    }
+
+    /// Loads the first of a few common sans-serif fonts that is installed.
+    pub fn load_default() -> Result<Self, String> {
+        DEFAULT_FONTS
+            .iter()
+            .map(Path::new)
+            .find(|path| path.is_file())
+            .ok_or_else(|| "none of the usual fonts is installed".to_string())
+            .and_then(Self::load)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rasterizes glyph `index` at `px`; returns its bitmap's size and one coverage
+    /// byte per pixel, rows top first.
+    pub fn rasterize(&self, index: u16, px: u16) -> ([u32; 2], Vec<u8>) {
+        let (metrics, coverage) = self.font.rasterize_indexed(index, px as f32);
+        ([metrics.width as u32, metrics.height as u32], coverage)
+    }
+
+    /// Lays `text` out at `px`, breaking lines at newlines only.
+    pub fn layout(&self, text: &str, px: f32) -> TextLayout {
+        let px = px.clamp(1.0, MAX_PX).round() as u16;
+        let size = px as f32;
+        let (ascent, line_height) = match self.font.horizontal_line_metrics(size) {
+            Some(line) => (line.ascent, line.new_line_size),
+            None => (size, size * 1.2),
+        };
+        let mut layout = TextLayout {
+            px,
+            ..Default::default()
+        };
+        for (line, text) in text.split('\n').enumerate() {
+            let baseline = ascent + line as f32 * line_height;
+            let mut x = 0.0;
+            let mut previous = None;
+            for c in text.chars() {
+                let index = self.font.lookup_glyph_index(c);
+                if let Some(previous) = previous {
+                    x += self
+                        .font
+                        .horizontal_kern_indexed(previous, index, size)
+                        .unwrap_or(0.0);
+                }
+                let metrics = self.font.metrics_indexed(index, size);
+                if metrics.width > 0 && metrics.height > 0 {
+                    // Bitmaps sit on whole pixels, so the atlas maps onto them 1:1.
+                    let left = (x + metrics.xmin as f32).round();
+                    let top = (baseline - metrics.ymin as f32 - metrics.height as f32).round();
+                    layout.glyphs.push(PlacedGlyph {
+                        index,
+                        rect: [left, top, metrics.width as f32, metrics.height as f32],
+                    });
+                }
+                x += metrics.advance_width;
+                previous = Some(index);
+            }
+            layout.width = layout.width.max(x.ceil());
+            layout.height = (baseline + line_height - ascent).ceil();
+        }
+        layout
+    }
+}
+
+/// Draws `layout`, laid out with `font`, with its top left at `x`, `y` in output
+/// pixels, in `color` (not premultiplied).
+pub fn draw(
+    font: &Arc<Font>,
+    layout: &TextLayout,
+    x: f32,
+    y: f32,
+    color: [f32; 4],
+) -> Vec<DrawItem> {
+    let [r, g, b, a] = color;
+    let (x, y) = (x.round(), y.round());
+    layout
+        .glyphs
+        .iter()
+        .map(|glyph| {
+            let [left, top, width, height] = glyph.rect;
+            DrawItem {
+                rect: [x + left, y + top, width, height],
+                color: [r * a, g * a, b * a, a],
+                source: DrawSource::Glyph {
+                    font: font.clone(),
+                    index: glyph.index,
+                    px: layout.px,
+                },
+                corner_radius: 0.0,
+                border: 0.0,
+            }
+        })
+        .collect()
+}
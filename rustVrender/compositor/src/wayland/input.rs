@@ -0,0 +1,806 @@
+// Input read from the devices through libinput, for sessions where the compositor owns
+// them. Keys go through the configured XKB keymap; combinations bound in the config
+// become WM commands on its socket, and everything else reaches the Wayland client
+// with keyboard focus, or the lock screen while locked. A combination that starts a
+// chord takes the keyboard until the chord is finished, broken, or times out, as does
+// the count prefix, after which digits make a count the WM repeats the next command
+// by. The pointer moves across the outputs, and clicks focus the window under it. Touchpad
+// swipes and pinches bound in the config become WM commands too, with the windows
+// sliding along during swipes.
+// Resizes clients ask for follow the pointer until a button is released, live or, with
+// outline_resize, as an outline the client is only configured to on release.
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use nix::libc::{O_ACCMODE, O_RDONLY, O_RDWR, O_WRONLY};
+use smithay::backend::input::{
+    Axis, AxisSource, ButtonState, Event, GestureBeginEvent, GestureEndEvent,
+    GesturePinchUpdateEvent, GestureSwipeUpdateEvent, InputEvent, KeyState, KeyboardKeyEvent,
+    PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
+};
+use smithay::backend::libinput::LibinputInputBackend;
+use smithay::input::keyboard::{xkb, FilterResult, Keysym, ModifiersState, XkbConfig};
+use smithay::input::pointer::{AxisFrame, ButtonEvent, MotionEvent};
+use smithay::reexports::calloop::LoopHandle;
+use smithay::reexports::input::{Libinput, LibinputInterface};
+use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
+use smithay::utils::{Logical, Point, SERIAL_COUNTER};
+
+use super::Frontend;
+use crate::input::{
+    self, Gesture, GestureConfig, InputConfig, KeyBinding, Modifiers, SwipeDirection,
+};
+use crate::lock;
+use crate::scene::bar::BarButton;
+
+/// Scroll distance of one wheel click, for devices that only report clicks.
+const WHEEL_STEP: f64 = 15.0;
+/// The evdev code of the left mouse button, which drags pinned windows.
+const BTN_LEFT: u32 = 0x110;
+/// The middle button, which closes windows from the bar's taskbar.
+const BTN_MIDDLE: u32 = 0x112;
+/// The right button, which bar scripts may take.
+const BTN_RIGHT: u32 = 0x111;
+/// The smallest size an interactive resize leaves a window, in each direction.
+const MIN_SIZE: i32 = 32;
+
+/// Opens devices directly, so the renderer needs read access to /dev/input.
+struct Interface;
+
+impl LibinputInterface for Interface {
+    fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<OwnedFd, i32> {
+        let access = flags & O_ACCMODE;
+        OpenOptions::new()
+            .custom_flags(flags & !O_ACCMODE)
+            .read(access == O_RDONLY || access == O_RDWR)
+            .write(access == O_WRONLY || access == O_RDWR)
+            .open(path)
+            .map(OwnedFd::from)
+            .map_err(|e| e.raw_os_error().unwrap_or(nix::libc::EIO))
+    }
+
+    fn close_restricted(&mut self, fd: OwnedFd) {
+        drop(File::from(fd));
+    }
+}
+
+/// A touchpad gesture whose fingers are still down.
+pub(super) enum TrackedGesture {
+    /// How far the fingers moved so far.
+    Swipe {
+        fingers: u32,
+        delta: Point<f64, Logical>,
+    },
+    /// The fingers' spread relative to where they started.
+    Pinch { fingers: u32, scale: f64 },
+}
+
+/// Which edges of a window an interactive resize moves.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Edges {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
+/// An interactive resize following the pointer.
+pub(super) struct Resize {
+    id: u32,
+    edges: Edges,
+    /// Where the pointer and the window were when it started.
+    pointer: Point<f64, Logical>,
+    start: [i32; 4],
+    /// Where the window is going.
+    target: [i32; 4],
+    /// Whether only an outline follows the pointer until the release.
+    outline: bool,
+}
+
+/// The combinations of a chord typed so far.
+pub(super) struct Chord {
+    keys: Vec<KeyBinding>,
+    /// As the config writes them, after the count prefix and count if there are any,
+    /// for the hint.
+    written: Vec<String>,
+    /// The count typed after the count prefix, 0 until a digit is.
+    count: Option<u32>,
+    /// When the last one was typed.
+    at: Instant,
+}
+
+/// What a pressed combination is bound to.
+enum Bound {
+    /// Sent to the WM.
+    Command(String),
+    /// The next combination of chords, which wait for more: what follows it in each, as
+    /// written, and their commands.
+    Chord {
+        key: KeyBinding,
+        written: String,
+        continuations: Vec<(String, String)>,
+    },
+    /// The count prefix, as written.
+    Count(String),
+    /// A digit of the count.
+    Digit(u32),
+}
+
+/// What a key press does instead of reaching a client.
+enum KeyAction {
+    /// A command sent to the WM, or the next combination of a chord.
+    Bound(Bound),
+    /// Breaking off the chord being typed.
+    EndChord,
+    /// Typed into the lock screen.
+    Type(char),
+    /// Escape, putting the color picker away.
+    CancelPicking,
+    /// Escape, putting the region selection away.
+    CancelSelecting,
+    /// Escape, putting the bar's calendar popup away.
+    CloseCalendar,
+    /// Typed into the screenshot being annotated.
+    Annotate(char),
+}
+
+/// Adds a keyboard and pointer to the frontend's seat and starts reading the devices of
+/// `config.seat` into it.
+pub fn start(
+    handle: &LoopHandle<'static, Frontend>,
+    frontend: &mut Frontend,
+    config: &InputConfig,
+) -> Result<(), Box<dyn Error>> {
+    let xkb_config = XkbConfig {
+        layout: &config.layout,
+        variant: &config.variant,
+        options: config.options.clone(),
+        ..XkbConfig::default()
+    };
+    frontend
+        .seat
+        .add_keyboard(
+            xkb_config,
+            config.repeat_delay_ms as i32,
+            config.repeat_rate as i32,
+        )
+        .map_err(|e| format!("failed to load the keymap: {}", e))?;
+    frontend.seat.add_pointer();
+
+    let mut context = Libinput::new_with_udev(Interface);
+    context
+        .udev_assign_seat(&config.seat)
+        .map_err(|()| format!("failed to read the devices of {}", config.seat))?;
+    handle.insert_source(LibinputInputBackend::new(context), |event, _, frontend| {
+        frontend.process_input(event);
+    })?;
+    println!("Reading input devices of {}", config.seat);
+    Ok(())
+}
+
+impl Frontend {
+    fn process_input(&mut self, event: InputEvent<LibinputInputBackend>) {
+        match event {
+            InputEvent::Keyboard { event } => {
+                self.key(event.key_code(), event.state(), event.time_msec())
+            }
+            InputEvent::PointerMotion { event } => {
+                self.pointer_motion(event.delta(), event.time_msec())
+            }
+            InputEvent::PointerButton { event } => {
+                self.pointer_button(event.button_code(), event.state(), event.time_msec())
+            }
+            InputEvent::PointerAxis { event } => self.pointer_axis(&event),
+            InputEvent::GestureSwipeBegin { event } => {
+                self.gesture = Some(TrackedGesture::Swipe {
+                    fingers: event.fingers(),
+                    delta: Point::default(),
+                });
+            }
+            InputEvent::GestureSwipeUpdate { event } => self.swipe_update(event.delta()),
+            InputEvent::GestureSwipeEnd { event } => self.gesture_end(event.cancelled()),
+            InputEvent::GesturePinchBegin { event } => {
+                self.gesture = Some(TrackedGesture::Pinch {
+                    fingers: event.fingers(),
+                    scale: 1.0,
+                });
+            }
+            InputEvent::GesturePinchUpdate { event } => {
+                if let Some(TrackedGesture::Pinch { scale, .. }) = &mut self.gesture {
+                    *scale = event.scale();
+                }
+            }
+            InputEvent::GesturePinchEnd { event } => self.gesture_end(event.cancelled()),
+            _ => {}
+        }
+    }
+
+    fn key(&mut self, code: xkb::Keycode, state: KeyState, time: u32) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+        let (locked, picking, selecting, calendar, annotating) = {
+            let mut scene = self.scene.lock().unwrap();
+            scene.report_activity(Instant::now());
+            let timeout = Duration::from_millis(scene.input_config().chord_timeout_ms);
+            if self.chord.as_ref().is_some_and(|chord| chord.at.elapsed() >= timeout) {
+                self.chord = None;
+            }
+            (
+                scene.locked(),
+                scene.picking(),
+                scene.selecting(),
+                scene.calendar_shown(),
+                scene.annotating().is_some(),
+            )
+        };
+        let serial = SERIAL_COUNTER.next_serial();
+        let action = keyboard.input(
+            self,
+            code,
+            state,
+            serial,
+            time,
+            |frontend, modifiers, key| {
+                if state != KeyState::Pressed {
+                    return FilterResult::Forward;
+                }
+                if picking && key.modified_sym() == Keysym::Escape {
+                    return FilterResult::Intercept(Some(KeyAction::CancelPicking));
+                }
+                if selecting && key.modified_sym() == Keysym::Escape {
+                    return FilterResult::Intercept(Some(KeyAction::CancelSelecting));
+                }
+                if calendar && key.modified_sym() == Keysym::Escape {
+                    return FilterResult::Intercept(Some(KeyAction::CloseCalendar));
+                }
+                if locked {
+                    return match key.modified_sym().key_char() {
+                        Some(c) => FilterResult::Intercept(Some(KeyAction::Type(c))),
+                        None => FilterResult::Intercept(None),
+                    };
+                }
+                if annotating {
+                    return match key.modified_sym().key_char() {
+                        Some(c) => FilterResult::Intercept(Some(KeyAction::Annotate(c))),
+                        None => FilterResult::Intercept(None),
+                    };
+                }
+                match frontend.bound(modifiers, &key.raw_syms()) {
+                    Some(bound) => FilterResult::Intercept(Some(KeyAction::Bound(bound))),
+                    // Modifiers are held for the next combination of a chord.
+                    None if frontend.chord.is_some() && !key.modified_sym().is_modifier_key() => {
+                        FilterResult::Intercept(Some(KeyAction::EndChord))
+                    }
+                    None => FilterResult::Forward,
+                }
+            },
+        );
+        if locked {
+            self.end_chord();
+        }
+        match action.flatten() {
+            Some(KeyAction::Bound(Bound::Command(command))) => {
+                let count = self.chord.as_ref().and_then(|chord| chord.count);
+                self.end_chord();
+                self.send_to_wm(&command, count);
+            }
+            Some(KeyAction::Bound(Bound::Chord {
+                key,
+                written,
+                continuations,
+            })) => self.continue_chord(key, written, continuations),
+            Some(KeyAction::Bound(Bound::Count(written))) => {
+                self.chord = Some(Chord {
+                    keys: Vec::new(),
+                    written: vec![written],
+                    count: Some(0),
+                    at: Instant::now(),
+                });
+                self.show_chord_hint(&[]);
+            }
+            Some(KeyAction::Bound(Bound::Digit(digit))) => {
+                if let Some(chord) = &mut self.chord {
+                    let count = chord.count.unwrap_or_default();
+                    chord.count = Some(count.saturating_mul(10).saturating_add(digit));
+                    // The count's digits are written together, after the prefix.
+                    match chord.written.get_mut(1) {
+                        Some(digits) => digits.push_str(&digit.to_string()),
+                        None => chord.written.push(digit.to_string()),
+                    }
+                    chord.at = Instant::now();
+                }
+                self.show_chord_hint(&[]);
+            }
+            Some(KeyAction::EndChord) => self.end_chord(),
+            Some(KeyAction::Type(c)) => lock::key_typed(&self.scene, c),
+            Some(KeyAction::CancelPicking) => {
+                self.scene.lock().unwrap().cancel_picking();
+            }
+            Some(KeyAction::CancelSelecting) => {
+                self.scene.lock().unwrap().cancel_selecting();
+            }
+            Some(KeyAction::CloseCalendar) => {
+                self.scene.lock().unwrap().close_calendar();
+            }
+            Some(KeyAction::Annotate(c)) => {
+                self.scene.lock().unwrap().annotation_typed(c);
+            }
+            None => {}
+        }
+    }
+
+    /// Sends a bound `command`, repeated `count` times if that is more than once.
+    fn send_to_wm(&self, command: &str, count: Option<u32>) {
+        let socket = self.scene.lock().unwrap().input_config().wm_socket.clone();
+        let mut value = input::binding_command(command);
+        if let Some(count) = count.filter(|&count| count > 1) {
+            value = input::repeated(value, count);
+        }
+        if let Err(e) = input::send_to_wm(&socket, &value) {
+            eprintln!("Failed to send {} to the WM: {}", command, e);
+        }
+    }
+
+    /// Adds `moved` to the swipe in progress, sliding the windows along if it has a
+    /// command.
+    fn swipe_update(&mut self, moved: Point<f64, Logical>) {
+        let Some(TrackedGesture::Swipe { fingers, delta }) = &mut self.gesture else {
+            return;
+        };
+        *delta += moved;
+        let (fingers, delta) = (*fingers, *delta);
+        let mut scene = self.scene.lock().unwrap();
+        let config = &scene.input_config().gestures;
+        let direction = swipe_direction(delta);
+        let gesture = Gesture::Swipe { fingers, direction };
+        if !config.slide || scene.locked() || bound_command(config, gesture).is_none() {
+            return;
+        }
+        // Past the distance that sends the command, the windows stop following.
+        let limit = 2.0 * config.swipe_distance as f64;
+        let offset = match direction {
+            SwipeDirection::Left | SwipeDirection::Right => [delta.x.clamp(-limit, limit), 0.0],
+            SwipeDirection::Up | SwipeDirection::Down => [0.0, delta.y.clamp(-limit, limit)],
+        };
+        scene.slide_windows(offset.map(|v| v as f32));
+    }
+
+    /// Ends the gesture in progress, sending its command if it went far enough.
+    fn gesture_end(&mut self, cancelled: bool) {
+        let Some(tracked) = self.gesture.take() else {
+            return;
+        };
+        let command = {
+            let mut scene = self.scene.lock().unwrap();
+            scene.report_activity(Instant::now());
+            scene.release_slide();
+            let config = &scene.input_config().gestures;
+            let gesture = match tracked {
+                TrackedGesture::Swipe { fingers, delta } => {
+                    let distance = delta.x.abs().max(delta.y.abs());
+                    (distance >= config.swipe_distance as f64).then(|| Gesture::Swipe {
+                        fingers,
+                        direction: swipe_direction(delta),
+                    })
+                }
+                TrackedGesture::Pinch { fingers, scale } => {
+                    let change = (scale - 1.0).abs();
+                    (change >= config.pinch_threshold as f64).then_some(Gesture::Pinch {
+                        fingers,
+                        inward: scale < 1.0,
+                    })
+                }
+            };
+            gesture
+                .filter(|_| !cancelled && !scene.locked())
+                .and_then(|gesture| bound_command(config, gesture))
+        };
+        if let Some(command) = command {
+            self.send_to_wm(&command, None);
+        }
+    }
+
+    /// What `syms`, the pressed key's unshifted keysyms, with `modifiers` held, are
+    /// bound to after the chord typed so far.
+    fn bound(&self, modifiers: &ModifiersState, syms: &[Keysym]) -> Option<Bound> {
+        let held = Modifiers {
+            ctrl: modifiers.ctrl,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            logo: modifiers.logo,
+        };
+        let pressed = |binding: &KeyBinding| {
+            let sym = xkb::keysym_from_name(&binding.key, xkb::KEYSYM_CASE_INSENSITIVE);
+            binding.modifiers == held && syms.contains(&sym)
+        };
+        let typed = self.chord.as_ref().map_or(&[][..], |chord| &chord.keys);
+        let scene = self.scene.lock().unwrap();
+        let config = scene.input_config();
+        if typed.is_empty() {
+            let counting = self.chord.as_ref().is_some_and(|chord| chord.count.is_some());
+            let digit = syms
+                .iter()
+                .find_map(|sym| sym.key_char().and_then(|c| c.to_digit(10)));
+            let prefix = config.count_prefix.as_ref();
+            let binding = prefix.and_then(|prefix| KeyBinding::parse(prefix).ok());
+            // Digits count with the prefix's modifiers still held, or none.
+            let bare = held == Modifiers::default()
+                || binding.as_ref().is_some_and(|b| b.modifiers == held);
+            if let Some(digit) = digit.filter(|_| counting && bare) {
+                return Some(Bound::Digit(digit));
+            }
+            if self.chord.is_none() && binding.is_some_and(|binding| pressed(&binding)) {
+                return prefix.cloned().map(Bound::Count);
+            }
+        }
+        let mut chord = None;
+        let mut continuations = Vec::new();
+        for (keys, command) in &config.bindings {
+            let Ok(sequence) = KeyBinding::parse_sequence(keys) else {
+                continue;
+            };
+            let Some(key) = sequence.get(typed.len()) else {
+                continue;
+            };
+            if !input::same_sequence(typed, &sequence, true) || !pressed(key) {
+                continue;
+            }
+            if sequence.len() == typed.len() + 1 {
+                return Some(Bound::Command(command.clone()));
+            }
+            let written = input::split_sequence(keys);
+            continuations.push((written[typed.len() + 1..].join(" "), command.clone()));
+            chord.get_or_insert((key.clone(), written[typed.len()].clone()));
+        }
+        chord.map(|(key, written)| Bound::Chord {
+            key,
+            written,
+            continuations,
+        })
+    }
+
+    /// Adds `key` to the chord being typed, listing its `continuations` if hints are
+    /// on.
+    fn continue_chord(
+        &mut self,
+        key: KeyBinding,
+        written: String,
+        continuations: Vec<(String, String)>,
+    ) {
+        let chord = self.chord.get_or_insert(Chord {
+            keys: Vec::new(),
+            written: Vec::new(),
+            count: None,
+            at: Instant::now(),
+        });
+        chord.keys.push(key);
+        chord.written.push(written);
+        chord.at = Instant::now();
+        self.show_chord_hint(&continuations);
+    }
+
+    /// Shows what the chord being typed has so far, and its `continuations`, if
+    /// hints are on.
+    fn show_chord_hint(&self, continuations: &[(String, String)]) {
+        let Some(chord) = &self.chord else {
+            return;
+        };
+        let mut scene = self.scene.lock().unwrap();
+        let config = scene.input_config();
+        if !config.chord_hints {
+            return;
+        }
+        let timeout = Duration::from_millis(config.chord_timeout_ms);
+        let mut hint = format!("{} …", chord.written.join(" "));
+        for (keys, command) in continuations {
+            hint.push_str(&format!("\n{}  {}", keys, command));
+        }
+        scene.show_chord_hint(Some((hint, timeout)));
+    }
+
+    /// Gives the keyboard back after a chord that was finished or broken off.
+    fn end_chord(&mut self) {
+        if self.chord.take().is_some() {
+            self.scene.lock().unwrap().show_chord_hint(None);
+        }
+    }
+
+    fn pointer_motion(&mut self, delta: Point<f64, Logical>, time: u32) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+        self.pointer = self.confine(self.pointer + delta);
+        self.scene
+            .lock()
+            .unwrap()
+            .set_pointer(self.pointer.x as f32, self.pointer.y as f32);
+        // The window being resized is the compositor's until the button comes up.
+        if self.resize_motion() {
+            return;
+        }
+        let event = MotionEvent {
+            location: self.pointer,
+            serial: SERIAL_COUNTER.next_serial(),
+            time,
+        };
+        let focus = self.surface_under(self.pointer);
+        pointer.motion(self, focus, &event);
+        pointer.frame(self);
+    }
+
+    /// `location` if it is on an output; otherwise as far along toward it as the
+    /// outputs go.
+    fn confine(&self, location: Point<f64, Logical>) -> Point<f64, Logical> {
+        let scene = self.scene.lock().unwrap();
+        let on_output = |p: Point<f64, Logical>| scene.output_at(p.x as i32, p.y as i32).is_some();
+        [
+            location,
+            (location.x, self.pointer.y).into(),
+            (self.pointer.x, location.y).into(),
+        ]
+        .into_iter()
+        .find(|p| on_output(*p))
+        .unwrap_or(self.pointer)
+    }
+
+    fn pointer_button(&mut self, button: u32, state: ButtonState, time: u32) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+        // The client still gets the release, so it doesn't think the button is held.
+        if state == ButtonState::Released {
+            self.end_resize();
+        }
+        {
+            let mut scene = self.scene.lock().unwrap();
+            scene.report_activity(Instant::now());
+            // The color picker takes every click: the left button picks, others cancel.
+            if scene.picking() {
+                if state == ButtonState::Pressed {
+                    if button == BTN_LEFT {
+                        scene.picker_clicked();
+                    } else {
+                        scene.cancel_picking();
+                    }
+                }
+                return;
+            }
+            // So does a region selection, the left button selecting and others cancelling.
+            if scene.selecting() {
+                if button == BTN_LEFT {
+                    scene.selection_button(state == ButtonState::Pressed);
+                } else if state == ButtonState::Pressed {
+                    scene.cancel_selecting();
+                }
+                return;
+            }
+            // So does an annotation, drawing with the left button.
+            if scene.annotating().is_some() {
+                if button == BTN_LEFT {
+                    scene.annotation_button(state == ButtonState::Pressed);
+                }
+                return;
+            }
+            // The bar takes presses on it, and their releases wherever they are.
+            let bar_button = match button {
+                BTN_LEFT => Some(BarButton::Left),
+                BTN_MIDDLE => Some(BarButton::Middle),
+                BTN_RIGHT => Some(BarButton::Right),
+                _ => None,
+            };
+            if let Some(bar_button) = bar_button {
+                drop(scene);
+                let pressed = state == ButtonState::Pressed;
+                if input::bar_button(&self.scene, bar_button, pressed) {
+                    return;
+                }
+                scene = self.scene.lock().unwrap();
+            }
+            if button == BTN_LEFT && state == ButtonState::Pressed {
+                if let Some(window) = scene.force_close_at_pointer() {
+                    drop(scene);
+                    if let Err(e) = input::force_close(&self.scene, window) {
+                        eprintln!("Not closing window {}: {}", window, e);
+                    }
+                    return;
+                }
+            }
+            // Pinned windows are the compositor's to drag, so their clients miss it.
+            let dragged = button == BTN_LEFT
+                && match state {
+                    ButtonState::Pressed => scene.pointer_pressed(),
+                    ButtonState::Released => scene.pointer_released(),
+                };
+            if dragged {
+                return;
+            }
+        }
+        let serial = SERIAL_COUNTER.next_serial();
+        if state == ButtonState::Pressed {
+            if let Some(keyboard) = self.seat.get_keyboard() {
+                let focus = self.surface_under(self.pointer).map(|(surface, _)| surface);
+                if focus.is_some() {
+                    keyboard.set_focus(self, focus, serial);
+                }
+            }
+        }
+        let event = ButtonEvent {
+            serial,
+            time,
+            button,
+            state,
+        };
+        pointer.button(self, &event);
+        pointer.frame(self);
+    }
+
+    fn pointer_axis(&mut self, event: &impl PointerAxisEvent<LibinputInputBackend>) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+        {
+            let mut scene = self.scene.lock().unwrap();
+            scene.report_activity(Instant::now());
+            let lines = event
+                .amount_v120(Axis::Vertical)
+                .map(|v120| v120 / 120.0)
+                .or(event.amount(Axis::Vertical).map(|amount| amount / WHEEL_STEP));
+            // The calendar popup scrolls instead of the window under it.
+            if lines.is_some_and(|lines| scene.calendar_scrolled(lines as f32)) {
+                return;
+            }
+        }
+        let source = event.source();
+        let mut frame = AxisFrame::new(event.time_msec()).source(source);
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            let clicks = event.amount_v120(axis);
+            let amount = event
+                .amount(axis)
+                .or(clicks.map(|v120| v120 / 120.0 * WHEEL_STEP));
+            if let Some(amount) = amount {
+                if amount != 0.0 {
+                    frame = frame.value(axis, amount);
+                } else if source == AxisSource::Finger {
+                    // Fingers lifted, so kinetic scrolling can take over.
+                    frame = frame.stop(axis);
+                }
+            }
+            if let Some(v120) = clicks {
+                frame = frame.v120(axis, v120 as i32);
+            }
+        }
+        pointer.axis(self, frame);
+        pointer.frame(self);
+    }
+
+    /// Starts resizing the window at `index` by `edges` with the pointer, as its client
+    /// asked.
+    pub(super) fn begin_resize(&mut self, index: usize, edges: Edges) {
+        let window = &self.windows[index];
+        let Some((width, height)) = window.size else {
+            return;
+        };
+        if !(edges.left || edges.right || edges.top || edges.bottom) {
+            return;
+        }
+        let start = [window.x, window.y, width as i32, height as i32];
+        let mut scene = self.scene.lock().unwrap();
+        let outline = scene.input_config().outline_resize;
+        if outline {
+            scene.set_resize_outline(Some(start.map(|v| v as f32)));
+        }
+        self.resize = Some(Resize {
+            id: window.id,
+            edges,
+            pointer: self.pointer,
+            start,
+            target: start,
+            outline,
+        });
+    }
+
+    /// Moves the edges being resized with the pointer; returns whether a resize is
+    /// going on.
+    fn resize_motion(&mut self) -> bool {
+        let Some(resize) = &mut self.resize else {
+            return false;
+        };
+        let dx = (self.pointer.x - resize.pointer.x).round() as i32;
+        let dy = (self.pointer.y - resize.pointer.y).round() as i32;
+        let [x, y, width, height] = resize.start;
+        let (mut left, mut top, mut right, mut bottom) = (x, y, x + width, y + height);
+        let edges = resize.edges;
+        if edges.left {
+            left = (left + dx).min(right - MIN_SIZE);
+        }
+        if edges.right {
+            right = (right + dx).max(left + MIN_SIZE);
+        }
+        if edges.top {
+            top = (top + dy).min(bottom - MIN_SIZE);
+        }
+        if edges.bottom {
+            bottom = (bottom + dy).max(top + MIN_SIZE);
+        }
+        let target = [left, top, right - left, bottom - top];
+        if target == resize.target {
+            return true;
+        }
+        resize.target = target;
+        let (id, outline) = (resize.id, resize.outline);
+        if outline {
+            let rect = target.map(|v| v as f32);
+            self.scene.lock().unwrap().set_resize_outline(Some(rect));
+        } else {
+            self.configure(id, target, edges, true);
+        }
+        true
+    }
+
+    /// Leaves the window being resized where the pointer took it.
+    fn end_resize(&mut self) {
+        let Some(resize) = self.resize.take() else {
+            return;
+        };
+        if resize.outline {
+            self.scene.lock().unwrap().set_resize_outline(None);
+        }
+        self.configure(resize.id, resize.target, resize.edges, false);
+    }
+
+    /// The topmost drawn window at `location`, with its origin.
+    fn surface_under(
+        &self,
+        location: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        self.windows.iter().rev().find_map(|window| {
+            let (width, height) = window.size?;
+            let (x, y) = (window.x as f64, window.y as f64);
+            let inside = location.x >= x
+                && location.y >= y
+                && location.x < x + width as f64
+                && location.y < y + height as f64;
+            inside.then(|| Some((window.shell.wl_surface()?, (x, y).into())))?
+        })
+    }
+
+    /// Gives keyboard focus to the topmost drawn window, as happens whenever windows
+    /// come and go.
+    pub(super) fn focus_top(&mut self) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+        let focus = self
+            .windows
+            .iter()
+            .rev()
+            .filter(|window| window.size.is_some())
+            .find_map(|window| window.shell.wl_surface());
+        keyboard.set_focus(self, focus, SERIAL_COUNTER.next_serial());
+    }
+}
+
+/// Which way fingers that moved by `delta` went, by the axis they moved along most.
+fn swipe_direction(delta: Point<f64, Logical>) -> SwipeDirection {
+    match (delta.x.abs() >= delta.y.abs(), delta.x < 0.0, delta.y < 0.0) {
+        (true, true, _) => SwipeDirection::Left,
+        (true, false, _) => SwipeDirection::Right,
+        (false, _, true) => SwipeDirection::Up,
+        (false, _, false) => SwipeDirection::Down,
+    }
+}
+
+/// The WM command bound to `gesture`.
+fn bound_command(config: &GestureConfig, gesture: Gesture) -> Option<String> {
+    config
+        .bindings
+        .iter()
+        .find(|(name, _)| Gesture::parse(name) == Ok(gesture))
+        .map(|(_, command)| command.clone())
+}
@@ -0,0 +1,610 @@
+// Wayland frontend: a wayland-server display (through smithay) that accepts native
+// clients, hands out xdg_shell toplevels, and copies their wl_shm buffers into the
+// scene next to the X windows the WM reports. With the xwayland feature it also runs
+// Xwayland and acts as its window manager. With the libinput feature it reads the input
+// devices too, and routes their events to clients.
+
+#[cfg(feature = "libinput")]
+mod input;
+#[cfg(feature = "xwayland")]
+mod xwayland;
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use smithay::delegate_compositor;
+use smithay::delegate_seat;
+use smithay::delegate_shm;
+use smithay::delegate_xdg_shell;
+use smithay::input::pointer::CursorImageStatus;
+use smithay::input::{Seat, SeatHandler, SeatState};
+use smithay::reexports::calloop::generic::Generic;
+use smithay::reexports::calloop::timer::{TimeoutAction, Timer};
+use smithay::reexports::calloop::{EventLoop, Interest, Mode, PostAction};
+use smithay::reexports::wayland_server::backend::{ClientData, ClientId, DisconnectReason};
+use smithay::reexports::wayland_server::protocol::wl_buffer::WlBuffer;
+use smithay::reexports::wayland_server::protocol::wl_seat::WlSeat;
+use smithay::reexports::wayland_server::protocol::wl_shm::Format;
+use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
+use smithay::reexports::wayland_server::{Client, Display, DisplayHandle};
+#[cfg(feature = "libinput")]
+use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel;
+#[cfg(feature = "libinput")]
+use smithay::utils::{Logical, Point};
+#[cfg(all(feature = "libinput", feature = "xwayland"))]
+use smithay::utils::Rectangle;
+use smithay::utils::Serial;
+use smithay::wayland::buffer::BufferHandler;
+use smithay::wayland::compositor::{
+    with_states, with_surface_tree_downward, BufferAssignment, CompositorClientState,
+    CompositorHandler, CompositorState, SurfaceAttributes, TraversalAction,
+};
+use smithay::wayland::shell::xdg::{
+    PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
+    XdgToplevelSurfaceData,
+};
+use smithay::wayland::shm::{with_buffer_contents, ShmHandler, ShmState};
+use smithay::wayland::socket::ListeningSocketSource;
+
+#[cfg(feature = "xwayland")]
+use smithay::wayland::xwayland_shell::XWaylandShellState;
+#[cfg(feature = "xwayland")]
+use smithay::xwayland::{X11Surface, X11Wm};
+
+use crate::scene::{Layer, SharedScene, WindowContents, WindowState, WindowType, WAYLAND_ID_BASE};
+
+/// How often clients are told to draw their next frame.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Stacking index of the first toplevel, so Wayland windows sit above X windows.
+const BASE_Z: i32 = 1 << 16;
+/// Offset between successively mapped toplevels.
+const CASCADE: i32 = 32;
+
+/// Runs the Wayland display until it fails, adding every toplevel to `scene`.
+pub fn run(scene: SharedScene) -> Result<(), Box<dyn Error>> {
+    let mut event_loop: EventLoop<'static, Frontend> = EventLoop::try_new()?;
+    let display: Display<Frontend> = Display::new()?;
+    let display_handle = display.handle();
+
+    let socket = ListeningSocketSource::new_auto()?;
+    println!(
+        "Wayland clients can connect with WAYLAND_DISPLAY={}",
+        socket.socket_name().to_string_lossy()
+    );
+    event_loop
+        .handle()
+        .insert_source(socket, |stream, _, frontend| {
+            if let Err(e) = frontend
+                .display_handle
+                .insert_client(stream, Arc::new(ClientState::default()))
+            {
+                eprintln!("Failed to add Wayland client: {}", e);
+            }
+        })?;
+    event_loop.handle().insert_source(
+        Generic::new(display, Interest::READ, Mode::Level),
+        |_, display, frontend| {
+            // SAFETY: the display is never dropped while its source is registered.
+            unsafe { display.get_mut().dispatch_clients(frontend)? };
+            Ok(PostAction::Continue)
+        },
+    )?;
+    event_loop
+        .handle()
+        .insert_source(Timer::from_duration(FRAME_INTERVAL), |_, _, frontend| {
+            frontend.send_frames();
+            TimeoutAction::ToDuration(FRAME_INTERVAL)
+        })?;
+
+    // Clients expect a seat even while it has no devices.
+    let mut seat_state = SeatState::new();
+    #[cfg_attr(not(feature = "libinput"), allow(unused_variables))]
+    let seat = seat_state.new_wl_seat(&display_handle, "seat0");
+    let mut frontend = Frontend {
+        compositor_state: CompositorState::new::<Frontend>(&display_handle),
+        xdg_shell_state: XdgShellState::new::<Frontend>(&display_handle),
+        shm_state: ShmState::new::<Frontend>(&display_handle, []),
+        seat_state,
+        #[cfg(feature = "libinput")]
+        seat,
+        #[cfg(feature = "libinput")]
+        pointer: Point::default(),
+        #[cfg(feature = "libinput")]
+        gesture: None,
+        #[cfg(feature = "libinput")]
+        resize: None,
+        #[cfg(feature = "libinput")]
+        chord: None,
+        #[cfg(feature = "xwayland")]
+        xwayland_shell_state: XWaylandShellState::new::<Frontend>(&display_handle),
+        #[cfg(feature = "xwayland")]
+        xwm: None,
+        display_handle,
+        scene,
+        windows: Vec::new(),
+        next_id: WAYLAND_ID_BASE,
+        mapped_count: 0,
+        start: Instant::now(),
+    };
+    #[cfg(feature = "libinput")]
+    {
+        let config = frontend.scene.lock().unwrap().input_config().clone();
+        if config.enabled {
+            if let Err(e) = input::start(&event_loop.handle(), &mut frontend, &config) {
+                eprintln!("Not reading input devices: {}", e);
+            }
+        }
+    }
+    #[cfg(feature = "xwayland")]
+    xwayland::spawn(&event_loop.handle(), &frontend.display_handle)?;
+    event_loop.run(None, &mut frontend, |frontend| {
+        if let Err(e) = frontend.display_handle.flush_clients() {
+            eprintln!("Failed to flush Wayland clients: {}", e);
+        }
+    })?;
+    Ok(())
+}
+
+/// The shell role a window came from.
+enum Shell {
+    Xdg(ToplevelSurface),
+    #[cfg(feature = "xwayland")]
+    X11(X11Surface),
+}
+
+impl Shell {
+    fn is(&self, surface: &WlSurface) -> bool {
+        match self {
+            Shell::Xdg(toplevel) => toplevel.wl_surface() == surface,
+            #[cfg(feature = "xwayland")]
+            Shell::X11(window) => window.wl_surface().as_ref() == Some(surface),
+        }
+    }
+
+    /// The surface drawn for the window, which X windows only have once Xwayland
+    /// pairs them up.
+    fn wl_surface(&self) -> Option<WlSurface> {
+        match self {
+            Shell::Xdg(toplevel) => Some(toplevel.wl_surface().clone()),
+            #[cfg(feature = "xwayland")]
+            Shell::X11(window) => window.wl_surface(),
+        }
+    }
+}
+
+/// A toplevel and where it sits in the scene.
+struct Window {
+    shell: Shell,
+    id: u32,
+    x: i32,
+    y: i32,
+    window_type: WindowType,
+    /// Set while the window has a buffer attached.
+    size: Option<(u32, u32)>,
+    /// Where an interactive resize left the window, until a buffer its size comes.
+    #[cfg(feature = "libinput")]
+    resized: Option<Resized>,
+}
+
+/// A resized window's rect, in root window coordinates, and whether its right and
+/// bottom edges stay there while its client catches up, as when resized from the left
+/// or the top.
+#[cfg(feature = "libinput")]
+struct Resized {
+    rect: [i32; 4],
+    keep_right: bool,
+    keep_bottom: bool,
+}
+
+struct Frontend {
+    display_handle: DisplayHandle,
+    compositor_state: CompositorState,
+    xdg_shell_state: XdgShellState,
+    shm_state: ShmState,
+    seat_state: SeatState<Self>,
+    #[cfg(feature = "libinput")]
+    seat: Seat<Self>,
+    /// Where libinput has moved the pointer, in root window coordinates.
+    #[cfg(feature = "libinput")]
+    pointer: Point<f64, Logical>,
+    #[cfg(feature = "libinput")]
+    gesture: Option<input::TrackedGesture>,
+    #[cfg(feature = "libinput")]
+    resize: Option<input::Resize>,
+    /// The chord being typed.
+    #[cfg(feature = "libinput")]
+    chord: Option<input::Chord>,
+    #[cfg(feature = "xwayland")]
+    xwayland_shell_state: XWaylandShellState,
+    /// Set once Xwayland is ready.
+    #[cfg(feature = "xwayland")]
+    xwm: Option<X11Wm>,
+    scene: SharedScene,
+    /// Bottom first.
+    windows: Vec<Window>,
+    next_id: u32,
+    /// xdg_shell toplevels mapped so far, for cascading new ones.
+    mapped_count: i32,
+    start: Instant,
+}
+
+impl Frontend {
+    /// Tracks a new window, which is drawn once it has a buffer.
+    fn add_window(&mut self, shell: Shell, window_type: WindowType) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(WAYLAND_ID_BASE);
+        self.windows.push(Window {
+            shell,
+            id,
+            x: 0,
+            y: 0,
+            window_type,
+            size: None,
+            #[cfg(feature = "libinput")]
+            resized: None,
+        });
+    }
+
+    fn remove_window(&mut self, index: usize) {
+        self.hide(index);
+        self.windows.remove(index);
+        // Windows above it move down in the stack.
+        for index in index..self.windows.len() {
+            self.upsert(index);
+        }
+    }
+
+    /// Shows the window at `index` with new contents.
+    fn show(&mut self, index: usize, contents: WindowContents) {
+        let window = &mut self.windows[index];
+        let mapped = window.size.is_none();
+        if mapped && matches!(window.shell, Shell::Xdg(_)) {
+            // X clients place themselves; xdg_shell ones are cascaded.
+            let offset = CASCADE * (self.mapped_count % 10 + 1);
+            window.x = offset;
+            window.y = offset;
+            self.mapped_count += 1;
+        }
+        #[cfg(feature = "libinput")]
+        if let Some(resized) = &window.resized {
+            let [x, y, width, height] = resized.rect;
+            let (drawn_width, drawn_height) = (contents.width as i32, contents.height as i32);
+            window.x = if resized.keep_right {
+                x + width - drawn_width
+            } else {
+                x
+            };
+            window.y = if resized.keep_bottom {
+                y + height - drawn_height
+            } else {
+                y
+            };
+            if (drawn_width, drawn_height) == (width, height) {
+                window.resized = None;
+            }
+        }
+        window.size = Some((contents.width, contents.height));
+        let id = window.id;
+        self.upsert(index);
+        self.scene.lock().unwrap().set_window_contents(id, contents);
+        #[cfg(feature = "libinput")]
+        if mapped {
+            self.focus_top();
+        }
+    }
+
+    fn hide(&mut self, index: usize) {
+        let window = &mut self.windows[index];
+        if window.size.take().is_some() {
+            let mut scene = self.scene.lock().unwrap();
+            scene.remove_window(window.id);
+            scene.clear_window_contents(window.id);
+            drop(scene);
+            #[cfg(feature = "libinput")]
+            self.focus_top();
+        }
+    }
+
+    /// Reports the window at `index` to the scene with its current geometry.
+    fn upsert(&self, index: usize) {
+        let window = &self.windows[index];
+        let Some((width, height)) = window.size else {
+            return;
+        };
+        let state = WindowState {
+            id: window.id,
+            x: window.x,
+            y: window.y,
+            width,
+            height,
+            border_width: 0,
+            focused: false,
+            opacity: 1.0,
+            window_type: window.window_type,
+            fullscreen: false,
+            blur: false,
+            layer: Layer::Normal,
+            visible: true,
+        };
+        self.scene
+            .lock()
+            .unwrap()
+            .upsert_window(&state, BASE_Z + index as i32);
+    }
+
+    /// Asks the client of window `id` for the size of `rect`, which the window is put at
+    /// once it draws at that size, keeping the edges opposite those in `edges` in
+    /// place meanwhile; `resizing` while the pointer is still going.
+    #[cfg(feature = "libinput")]
+    fn configure(&mut self, id: u32, rect: [i32; 4], edges: input::Edges, resizing: bool) {
+        let Some(window) = self.windows.iter_mut().find(|w| w.id == id) else {
+            return;
+        };
+        let [.., width, height] = rect;
+        window.resized = Some(Resized {
+            rect,
+            keep_right: edges.left,
+            keep_bottom: edges.top,
+        });
+        match &window.shell {
+            Shell::Xdg(toplevel) => {
+                toplevel.with_pending_state(|state| {
+                    state.size = Some((width, height).into());
+                    if resizing {
+                        state.states.set(xdg_toplevel::State::Resizing);
+                    } else {
+                        state.states.unset(xdg_toplevel::State::Resizing);
+                    }
+                });
+                toplevel.send_pending_configure();
+            }
+            #[cfg(feature = "xwayland")]
+            Shell::X11(surface) => {
+                let geometry = Rectangle::new((rect[0], rect[1]).into(), (width, height).into());
+                if let Err(e) = surface.configure(geometry) {
+                    eprintln!("Failed to resize X window {}: {}", id, e);
+                }
+            }
+        }
+    }
+
+    /// Lets every window know it may draw its next frame.
+    fn send_frames(&self) {
+        let time = self.start.elapsed().as_millis() as u32;
+        for window in &self.windows {
+            let Some(surface) = window.shell.wl_surface() else {
+                continue;
+            };
+            with_surface_tree_downward(
+                &surface,
+                (),
+                |_, _, _| TraversalAction::DoChildren(()),
+                |_, states, _| {
+                    let mut attributes = states.cached_state.get::<SurfaceAttributes>();
+                    for callback in attributes.current().frame_callbacks.drain(..) {
+                        callback.done(time);
+                    }
+                },
+                |_, _, _| true,
+            );
+        }
+    }
+}
+
+impl CompositorHandler for Frontend {
+    fn compositor_state(&mut self) -> &mut CompositorState {
+        &mut self.compositor_state
+    }
+
+    fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
+        &client.get_data::<ClientState>().unwrap().compositor_state
+    }
+
+    fn commit(&mut self, surface: &WlSurface) {
+        let update = take_buffer(surface);
+
+        let toplevel = self.windows.iter().find_map(|w| match &w.shell {
+            Shell::Xdg(toplevel) if toplevel.wl_surface() == surface => Some(toplevel),
+            _ => None,
+        });
+        if let Some(toplevel) = toplevel {
+            let configured = with_states(surface, |states| {
+                states
+                    .data_map
+                    .get::<XdgToplevelSurfaceData>()
+                    .is_some_and(|data| data.lock().unwrap().initial_configure_sent)
+            });
+            if !configured {
+                // Clients pick their own size until there is a layout for them.
+                toplevel.send_configure();
+            }
+        }
+
+        let Some(index) = self.windows.iter().position(|w| w.shell.is(surface)) else {
+            return;
+        };
+        match update {
+            Some(Some(contents)) => self.show(index, contents),
+            Some(None) => self.hide(index),
+            None => {}
+        }
+    }
+}
+
+impl XdgShellHandler for Frontend {
+    fn xdg_shell_state(&mut self) -> &mut XdgShellState {
+        &mut self.xdg_shell_state
+    }
+
+    fn new_toplevel(&mut self, surface: ToplevelSurface) {
+        self.add_window(Shell::Xdg(surface), WindowType::Normal);
+    }
+
+    fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        let index = self
+            .windows
+            .iter()
+            .position(|w| matches!(&w.shell, Shell::Xdg(toplevel) if *toplevel == surface));
+        if let Some(index) = index {
+            self.remove_window(index);
+        }
+    }
+
+    fn new_popup(&mut self, surface: PopupSurface, _positioner: PositionerState) {
+        // Popups are configured so their clients carry on, but not drawn yet.
+        if let Err(e) = surface.send_configure() {
+            eprintln!("Failed to configure Wayland popup: {}", e);
+        }
+    }
+
+    fn grab(&mut self, _surface: PopupSurface, _seat: WlSeat, _serial: Serial) {}
+
+    #[cfg(feature = "libinput")]
+    fn resize_request(
+        &mut self,
+        surface: ToplevelSurface,
+        _seat: WlSeat,
+        _serial: Serial,
+        edges: xdg_toplevel::ResizeEdge,
+    ) {
+        let index = self
+            .windows
+            .iter()
+            .position(|w| matches!(&w.shell, Shell::Xdg(toplevel) if *toplevel == surface));
+        let bits = edges as u32;
+        let edges = input::Edges {
+            top: bits & 1 != 0,
+            bottom: bits & 2 != 0,
+            left: bits & 4 != 0,
+            right: bits & 8 != 0,
+        };
+        if let Some(index) = index {
+            self.begin_resize(index, edges);
+        }
+    }
+
+    fn reposition_request(
+        &mut self,
+        _surface: PopupSurface,
+        _positioner: PositionerState,
+        _token: u32,
+    ) {
+    }
+}
+
+impl BufferHandler for Frontend {
+    fn buffer_destroyed(&mut self, _buffer: &WlBuffer) {}
+}
+
+impl ShmHandler for Frontend {
+    fn shm_state(&self) -> &ShmState {
+        &self.shm_state
+    }
+}
+
+impl SeatHandler for Frontend {
+    type KeyboardFocus = WlSurface;
+    type PointerFocus = WlSurface;
+    type TouchFocus = WlSurface;
+
+    fn seat_state(&mut self) -> &mut SeatState<Self> {
+        &mut self.seat_state
+    }
+
+    fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&WlSurface>) {}
+
+    fn cursor_image(&mut self, _seat: &Seat<Self>, _image: CursorImageStatus) {}
+}
+
+delegate_compositor!(Frontend);
+delegate_seat!(Frontend);
+delegate_shm!(Frontend);
+delegate_xdg_shell!(Frontend);
+
+#[derive(Default)]
+struct ClientState {
+    compositor_state: CompositorClientState,
+}
+
+impl ClientData for ClientState {
+    fn initialized(&self, _client_id: ClientId) {}
+
+    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
+}
+
+/// Takes the buffer `surface` just committed: `Some(None)` if it was removed, `None` if
+/// it is unchanged.
+///
+/// Pixels are copied out right away, so the buffer goes straight back to its client.
+/// The copy also stays with the surface, for windows that are matched to it later.
+fn take_buffer(surface: &WlSurface) -> Option<Option<WindowContents>> {
+    let buffer = with_states(surface, |states| {
+        states
+            .cached_state
+            .get::<SurfaceAttributes>()
+            .current()
+            .buffer
+            .take()
+    })?;
+    let contents = match buffer {
+        BufferAssignment::NewBuffer(buffer) => {
+            // A buffer that can't be read leaves the window as it was.
+            let contents = read_buffer(&buffer);
+            buffer.release();
+            Some(contents?)
+        }
+        BufferAssignment::Removed => None,
+    };
+    with_states(surface, |states| {
+        let last = states
+            .data_map
+            .get_or_insert_threadsafe(LastContents::default);
+        *last.0.lock().unwrap() = contents.clone();
+    });
+    Some(contents)
+}
+
+/// The contents a surface committed last.
+#[derive(Default)]
+struct LastContents(Mutex<Option<WindowContents>>);
+
+/// Copies a wl_shm buffer into tightly packed premultiplied BGRA rows.
+fn read_buffer(buffer: &WlBuffer) -> Option<WindowContents> {
+    let result = with_buffer_contents(buffer, |ptr, len, data| {
+        let opaque = match data.format {
+            Format::Argb8888 => false,
+            Format::Xrgb8888 => true,
+            _ => return None,
+        };
+        let (width, height, stride) = (
+            data.width as usize,
+            data.height as usize,
+            data.stride as usize,
+        );
+        let offset = data.offset as usize;
+        if width == 0 || height == 0 || stride < width * 4 || offset + stride * height > len {
+            return None;
+        }
+        // SAFETY: the range was checked against the pool's length above.
+        let pool = unsafe { std::slice::from_raw_parts(ptr.add(offset), stride * height) };
+        // Both formats are little-endian words, so BGRA in memory, and already premultiplied.
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for row in pool.chunks_exact(stride) {
+            pixels.extend_from_slice(&row[..width * 4]);
+        }
+        Some(WindowContents {
+            width: width as u32,
+            height: height as u32,
+            data: Arc::new(pixels),
+            opaque,
+        })
+    });
+    match result {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read Wayland buffer: {}", e);
+            None
+        }
+    }
+}
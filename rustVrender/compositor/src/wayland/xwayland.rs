@@ -0,0 +1,220 @@
+// Xwayland integration: spawns Xwayland as a client of the frontend's display and
+// runs smithay's X11Wm on its privileged connection. X windows then reach the scene
+// like xdg_shell toplevels, their pixels arriving as commits on the wl_surface
+// xwayland_shell matches them up with.
+
+use std::error::Error;
+use std::process::Stdio;
+
+use smithay::delegate_xwayland_shell;
+use smithay::reexports::calloop::LoopHandle;
+use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
+use smithay::reexports::wayland_server::DisplayHandle;
+use smithay::utils::{Logical, Rectangle};
+use smithay::wayland::compositor::with_states;
+use smithay::wayland::xwayland_shell::{XWaylandShellHandler, XWaylandShellState};
+use smithay::xwayland::xwm::{Reorder, ResizeEdge, WmWindowType, XwmId};
+use smithay::xwayland::{X11Surface, X11Wm, XWayland, XWaylandEvent, XwmHandler};
+
+use super::{Frontend, LastContents, Shell};
+use crate::scene::WindowType;
+
+/// Starts Xwayland; its window manager attaches once it reports ready.
+pub(super) fn spawn(
+    handle: &LoopHandle<'static, Frontend>,
+    display_handle: &DisplayHandle,
+) -> Result<(), Box<dyn Error>> {
+    let (xwayland, client) = XWayland::spawn(
+        display_handle,
+        None,
+        std::iter::empty::<(String, String)>(),
+        true,
+        Stdio::null(),
+        Stdio::inherit(),
+        |_| (),
+    )?;
+    let wm_handle = handle.clone();
+    handle.insert_source(xwayland, move |event, _, frontend| match event {
+        XWaylandEvent::Ready {
+            x11_socket,
+            display_number,
+        } => match X11Wm::start_wm(wm_handle.clone(), x11_socket, client.clone()) {
+            Ok(wm) => {
+                println!("X clients can connect with DISPLAY=:{}", display_number);
+                frontend.xwm = Some(wm);
+            }
+            Err(e) => eprintln!("Failed to start the Xwayland window manager: {}", e),
+        },
+        XWaylandEvent::Error => eprintln!("Xwayland failed to start"),
+    })?;
+    Ok(())
+}
+
+impl Frontend {
+    fn x11_index(&self, window: &X11Surface) -> Option<usize> {
+        self.windows
+            .iter()
+            .position(|w| matches!(&w.shell, Shell::X11(x11) if x11 == window))
+    }
+}
+
+/// Maps EWMH window types onto the ones effects and animations know about.
+fn window_type(window: &X11Surface) -> WindowType {
+    if window.is_override_redirect() {
+        return WindowType::Popup;
+    }
+    match window.window_type() {
+        Some(WmWindowType::Dialog) => WindowType::Dialog,
+        Some(WmWindowType::Notification) => WindowType::Notification,
+        Some(WmWindowType::Toolbar) => WindowType::Dock,
+        Some(
+            WmWindowType::DropdownMenu
+            | WmWindowType::Menu
+            | WmWindowType::PopupMenu
+            | WmWindowType::Tooltip,
+        ) => WindowType::Popup,
+        _ => WindowType::Normal,
+    }
+}
+
+impl XwmHandler for Frontend {
+    fn xwm_state(&mut self, _xwm: XwmId) -> &mut X11Wm {
+        self.xwm
+            .as_mut()
+            .expect("X11 event before the window manager started")
+    }
+
+    fn new_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        let window_type = window_type(&window);
+        self.add_window(Shell::X11(window), window_type);
+    }
+
+    fn new_override_redirect_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        self.add_window(Shell::X11(window), WindowType::Popup);
+    }
+
+    fn map_window_request(&mut self, _xwm: XwmId, window: X11Surface) {
+        // There is no layout yet, so windows keep the geometry they asked for.
+        if let Err(e) = window.set_mapped(true) {
+            eprintln!("Failed to map X window {}: {}", window.window_id(), e);
+            return;
+        }
+        if let Some(index) = self.x11_index(&window) {
+            let geometry = window.geometry();
+            let entry = &mut self.windows[index];
+            entry.x = geometry.loc.x;
+            entry.y = geometry.loc.y;
+            entry.window_type = window_type(&window);
+        }
+        if let Err(e) = window.configure(window.geometry()) {
+            eprintln!("Failed to configure X window {}: {}", window.window_id(), e);
+        }
+    }
+
+    fn mapped_override_redirect_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        if let Some(index) = self.x11_index(&window) {
+            let geometry = window.geometry();
+            self.windows[index].x = geometry.loc.x;
+            self.windows[index].y = geometry.loc.y;
+        }
+    }
+
+    fn unmapped_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        if let Some(index) = self.x11_index(&window) {
+            self.hide(index);
+        }
+        if !window.is_override_redirect() {
+            let _ = window.set_mapped(false);
+        }
+    }
+
+    fn destroyed_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        if let Some(index) = self.x11_index(&window) {
+            self.remove_window(index);
+        }
+    }
+
+    fn configure_request(
+        &mut self,
+        _xwm: XwmId,
+        window: X11Surface,
+        x: Option<i32>,
+        y: Option<i32>,
+        w: Option<u32>,
+        h: Option<u32>,
+        _reorder: Option<Reorder>,
+    ) {
+        let mut geometry = window.geometry();
+        geometry.loc.x = x.unwrap_or(geometry.loc.x);
+        geometry.loc.y = y.unwrap_or(geometry.loc.y);
+        geometry.size.w = w.map_or(geometry.size.w, |w| w as i32);
+        geometry.size.h = h.map_or(geometry.size.h, |h| h as i32);
+        if let Err(e) = window.configure(geometry) {
+            eprintln!("Failed to configure X window {}: {}", window.window_id(), e);
+        }
+    }
+
+    fn configure_notify(
+        &mut self,
+        _xwm: XwmId,
+        window: X11Surface,
+        geometry: Rectangle<i32, Logical>,
+        _above: Option<u32>,
+    ) {
+        let Some(index) = self.x11_index(&window) else {
+            return;
+        };
+        self.windows[index].x = geometry.loc.x;
+        self.windows[index].y = geometry.loc.y;
+        // The size follows the next buffer.
+        self.upsert(index);
+    }
+
+    #[cfg_attr(not(feature = "libinput"), allow(unused_variables))]
+    fn resize_request(&mut self, _xwm: XwmId, window: X11Surface, _button: u32, edge: ResizeEdge) {
+        #[cfg(feature = "libinput")]
+        if let Some(index) = self.x11_index(&window) {
+            use ResizeEdge::*;
+            let edges = super::input::Edges {
+                left: matches!(edge, Left | TopLeft | BottomLeft),
+                right: matches!(edge, Right | TopRight | BottomRight),
+                top: matches!(edge, Top | TopLeft | TopRight),
+                bottom: matches!(edge, Bottom | BottomLeft | BottomRight),
+            };
+            self.begin_resize(index, edges);
+        }
+    }
+
+    fn move_request(&mut self, _xwm: XwmId, _window: X11Surface, _button: u32) {}
+
+    fn disconnected(&mut self, _xwm: XwmId) {
+        eprintln!("Lost the connection to Xwayland");
+        for index in (0..self.windows.len()).rev() {
+            if matches!(self.windows[index].shell, Shell::X11(_)) {
+                self.remove_window(index);
+            }
+        }
+        self.xwm = None;
+    }
+}
+
+impl XWaylandShellHandler for Frontend {
+    fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
+        &mut self.xwayland_shell_state
+    }
+
+    fn surface_associated(&mut self, _xwm: XwmId, wl_surface: WlSurface, window: X11Surface) {
+        // Xwayland may have committed the window's first buffer before the match.
+        let contents = with_states(&wl_surface, |states| {
+            states
+                .data_map
+                .get::<LastContents>()
+                .and_then(|last| last.0.lock().unwrap().clone())
+        });
+        if let (Some(index), Some(contents)) = (self.x11_index(&window), contents) {
+            self.show(index, contents);
+        }
+    }
+}
+
+delegate_xwayland_shell!(Frontend);
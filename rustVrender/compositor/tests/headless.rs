@@ -0,0 +1,264 @@
+// Golden-image tests of the renderer, drawing offscreen with a headless renderer and
+// reading the frames back. They need a Vulkan device, and lavapipe will do; without
+// one each says it is skipped and checks nothing, unless REQUIRE_VULKAN is set, which
+// makes it fail instead, as CI should.
+//
+// Each test checks a few pixels it knows the value of, and compares the whole frame
+// with tests/golden/<name>.png, failing if there is none. UPDATE_GOLDEN=1 writes the
+// frames there, to check in after looking at them.
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use qtilerugo_compositor::compositor::renderer::{Renderer, CLEAR_COLOR};
+use qtilerugo_compositor::scene::effects::BlurLevel;
+use qtilerugo_compositor::scene::{DrawItem, DrawSource, WindowContents, WHOLE_CONTENTS};
+use qtilerugo_compositor::screenshot::{Capture, ImageFormat};
+
+const SIZE: [u32; 2] = [64, 64];
+/// How far a channel may be from the expected value, for differences in rasterization
+/// and rounding between implementations.
+const TOLERANCE: u8 = 2;
+
+fn renderer() -> Option<Renderer> {
+    match Renderer::headless(None) {
+        Ok(renderer) => Some(renderer),
+        Err(e) if env::var_os("REQUIRE_VULKAN").is_some() => {
+            panic!("No Vulkan device, and REQUIRE_VULKAN is set: {}", e)
+        }
+        Err(e) => {
+            eprintln!(
+                "SKIPPED, no Vulkan device (set REQUIRE_VULKAN to fail): {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+fn solid(rect: [f32; 4], color: [f32; 4]) -> DrawItem {
+    DrawItem {
+        rect,
+        color,
+        source: DrawSource::Solid,
+        corner_radius: 0.0,
+        border: 0.0,
+    }
+}
+
+fn to_u8(color: [f32; 4]) -> [u8; 3] {
+    [color[0], color[1], color[2]].map(|c| (c * 255.0).round() as u8)
+}
+
+fn pixel(capture: &Capture, x: u32, y: u32) -> [u8; 3] {
+    let i = ((y * capture.width + x) * 4) as usize;
+    [capture.rgba[i], capture.rgba[i + 1], capture.rgba[i + 2]]
+}
+
+#[track_caller]
+fn assert_pixel(capture: &Capture, x: u32, y: u32, expected: [u8; 3]) {
+    let actual = pixel(capture, x, y);
+    let close = actual
+        .iter()
+        .zip(expected)
+        .all(|(a, e)| a.abs_diff(e) <= TOLERANCE);
+    assert!(
+        close,
+        "pixel {},{} is {:?}, expected {:?}",
+        x, y, actual, expected
+    );
+}
+
+/// Compares `capture` with its golden image, or writes that with UPDATE_GOLDEN set.
+#[track_caller]
+fn assert_golden(name: &str, capture: &Capture) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        capture.save(&path, ImageFormat::Png).unwrap();
+        return;
+    }
+    let golden = match image::open(&path) {
+        Ok(golden) => golden.to_rgba8(),
+        Err(e) => panic!(
+            "No golden image at {} ({}); UPDATE_GOLDEN=1 writes it",
+            path.display(),
+            e
+        ),
+    };
+    assert_eq!(
+        [golden.width(), golden.height()],
+        [capture.width, capture.height],
+        "{} has another size",
+        path.display()
+    );
+    let differing = golden
+        .as_raw()
+        .iter()
+        .zip(&capture.rgba)
+        .filter(|(g, c)| g.abs_diff(**c) > TOLERANCE)
+        .count();
+    assert_eq!(
+        differing,
+        0,
+        "{} channels differ from {}",
+        differing,
+        path.display()
+    );
+}
+
+#[test]
+fn clear_color() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let capture = renderer.capture(&[], SIZE).unwrap();
+    assert_eq!([capture.width, capture.height], SIZE);
+    for (x, y) in [(0, 0), (32, 32), (63, 63)] {
+        assert_pixel(&capture, x, y, to_u8(CLEAR_COLOR));
+    }
+    assert_golden("clear_color", &capture);
+}
+
+#[test]
+fn quads() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let items = [
+        solid([8.0, 8.0, 32.0, 32.0], [1.0, 0.0, 0.0, 1.0]),
+        // Half transparent, premultiplied, over the red quad's corner.
+        solid([24.0, 24.0, 32.0, 32.0], [0.0, 0.0, 0.5, 0.5]),
+    ];
+    let capture = renderer.capture(&items, SIZE).unwrap();
+    let clear = to_u8(CLEAR_COLOR);
+    assert_pixel(&capture, 4, 4, clear);
+    assert_pixel(&capture, 16, 16, [255, 0, 0]);
+    assert_pixel(&capture, 32, 32, [128, 0, 128]);
+    let over_clear = CLEAR_COLOR.map(|c| c * 0.5);
+    assert_pixel(
+        &capture,
+        48,
+        48,
+        to_u8([over_clear[0], over_clear[1], over_clear[2] + 0.5, 1.0]),
+    );
+    assert_golden("quads", &capture);
+}
+
+#[test]
+fn window_contents() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    // Two by two BGRA texels filling the frame, which the sharp filter keeps solid
+    // away from where they meet.
+    let data = [
+        [0, 0, 255, 255],
+        [0, 255, 0, 255],
+        [255, 0, 0, 255],
+        [255, 255, 255, 255],
+    ];
+    let contents = WindowContents {
+        width: 2,
+        height: 2,
+        data: Arc::new(data.concat()),
+        opaque: true,
+    };
+    let items = [DrawItem {
+        rect: [0.0, 0.0, 64.0, 64.0],
+        color: [1.0; 4],
+        source: DrawSource::Window {
+            id: 1,
+            serial: 1,
+            contents,
+            frozen: false,
+            part: WHOLE_CONTENTS,
+        },
+        corner_radius: 0.0,
+        border: 0.0,
+    }];
+    let capture = renderer.capture(&items, SIZE).unwrap();
+    assert_pixel(&capture, 4, 4, [255, 0, 0]);
+    assert_pixel(&capture, 60, 4, [0, 255, 0]);
+    assert_pixel(&capture, 4, 60, [0, 0, 255]);
+    assert_pixel(&capture, 60, 60, [255, 255, 255]);
+    assert_golden("window_contents", &capture);
+}
+
+#[test]
+fn rounded_corners_and_borders() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let white = [1.0; 4];
+    let items = [
+        DrawItem {
+            corner_radius: 12.0,
+            ..solid([0.0, 0.0, 32.0, 64.0], white)
+        },
+        DrawItem {
+            border: 4.0,
+            ..solid([32.0, 0.0, 32.0, 64.0], white)
+        },
+    ];
+    let capture = renderer.capture(&items, SIZE).unwrap();
+    let clear = to_u8(CLEAR_COLOR);
+    // Cut off by the corner, and inside it.
+    assert_pixel(&capture, 1, 1, clear);
+    assert_pixel(&capture, 16, 32, [255; 3]);
+    // The ring along the edge, and the middle it leaves out.
+    assert_pixel(&capture, 33, 32, [255; 3]);
+    assert_pixel(&capture, 48, 32, clear);
+    assert_golden("rounded_corners_and_borders", &capture);
+}
+
+#[test]
+fn shadow_fades_out() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let items = [DrawItem {
+        source: DrawSource::Shadow(16.0),
+        ..solid([0.0, 0.0, 64.0, 64.0], [0.0, 0.0, 0.0, 1.0])
+    }];
+    let capture = renderer.capture(&items, SIZE).unwrap();
+    // Darker towards the middle, where it's fully opaque.
+    let edge = pixel(&capture, 1, 32)[2];
+    let inside = pixel(&capture, 12, 32)[2];
+    assert!(edge > inside, "{} at the edge, {} further in", edge, inside);
+    assert_pixel(&capture, 32, 32, [0; 3]);
+    assert_golden("shadow_fades_out", &capture);
+}
+
+#[test]
+fn blur_behind() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    // White on the left, black on the right, blurred across the middle.
+    let items = [
+        solid([0.0, 0.0, 32.0, 64.0], [1.0; 4]),
+        solid([32.0, 0.0, 32.0, 64.0], [0.0, 0.0, 0.0, 1.0]),
+        DrawItem {
+            source: DrawSource::Backdrop(BlurLevel::from_strength(5).unwrap()),
+            ..solid([16.0, 0.0, 32.0, 64.0], [1.0; 4])
+        },
+    ];
+    let capture = renderer.capture(&items, SIZE).unwrap();
+    // Outside the backdrop, the stripes are as they were drawn.
+    assert_pixel(&capture, 4, 32, [255; 3]);
+    assert_pixel(&capture, 60, 32, [0; 3]);
+    // Inside, they fade into each other, lighter towards the white side.
+    let row: Vec<u8> = (18..46).map(|x| pixel(&capture, x, 32)[0]).collect();
+    assert!(row.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", row);
+    let [left, right] = [pixel(&capture, 31, 32)[0], pixel(&capture, 32, 32)[0]];
+    assert!(
+        left < 255 - TOLERANCE && right > TOLERANCE,
+        "{} and {}",
+        left,
+        right
+    );
+    assert_golden("blur_behind", &capture);
+}
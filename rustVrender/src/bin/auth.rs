@@ -0,0 +1,148 @@
+// qtilerugo-auth: checks the password of the user running it with PAM, for the lock
+// screen. It reads one line, the password, on stdin and replies "ok" or "error: ..."
+// on stdout, exiting with success only when the password was right. The renderer
+// starts a fresh one per attempt, so PAM modules run in a process of their own.
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+use std::ptr;
+
+use nix::libc;
+use nix::unistd::{getuid, User};
+
+/// The PAM service to authenticate against, configured in /etc/pam.d/qtilerugo.
+const SERVICE: &str = "qtilerugo";
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_CONV_ERR: c_int = 19;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+#[repr(C)]
+struct PamConv {
+    conv: extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int,
+    appdata_ptr: *mut c_void,
+}
+
+#[link(name = "pam")]
+extern "C" {
+    fn pam_start(
+        service: *const c_char,
+        user: *const c_char,
+        conv: *const PamConv,
+        handle: *mut *mut c_void,
+    ) -> c_int;
+    fn pam_authenticate(handle: *mut c_void, flags: c_int) -> c_int;
+    fn pam_acct_mgmt(handle: *mut c_void, flags: c_int) -> c_int;
+    fn pam_end(handle: *mut c_void, status: c_int) -> c_int;
+    fn pam_strerror(handle: *mut c_void, error: c_int) -> *const c_char;
+}
+
+/// Answers every prompt with the password `appdata` points to; PAM frees the answers.
+extern "C" fn converse(
+    count: c_int,
+    messages: *mut *const PamMessage,
+    responses: *mut *mut PamResponse,
+    appdata: *mut c_void,
+) -> c_int {
+    if count <= 0 {
+        return PAM_CONV_ERR;
+    }
+    // SAFETY: PAM passes `count` messages and expects `count` responses allocated with
+    // malloc, and `appdata` is the CString `authenticate` keeps alive.
+    unsafe {
+        let password = &*(appdata as *const CString);
+        let answers =
+            libc::calloc(count as usize, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+        if answers.is_null() {
+            return PAM_BUF_ERR;
+        }
+        for i in 0..count as usize {
+            let message = &**messages.add(i);
+            if matches!(message.msg_style, PAM_PROMPT_ECHO_OFF | PAM_PROMPT_ECHO_ON) {
+                (*answers.add(i)).resp = libc::strdup(password.as_ptr());
+            }
+        }
+        *responses = answers;
+    }
+    PAM_SUCCESS
+}
+
+fn authenticate(user: &str, password: &CString) -> Result<(), String> {
+    let service = CString::new(SERVICE).unwrap();
+    let user = CString::new(user).map_err(|e| e.to_string())?;
+    let conv = PamConv {
+        conv: converse,
+        appdata_ptr: password as *const CString as *mut c_void,
+    };
+    let mut handle = ptr::null_mut();
+    // SAFETY: every pointer outlives the PAM transaction, which ends below.
+    unsafe {
+        let status = pam_start(service.as_ptr(), user.as_ptr(), &conv, &mut handle);
+        if status != PAM_SUCCESS {
+            return Err(format!("pam_start failed ({})", status));
+        }
+        let mut status = pam_authenticate(handle, 0);
+        if status == PAM_SUCCESS {
+            status = pam_acct_mgmt(handle, 0);
+        }
+        let result = if status == PAM_SUCCESS {
+            Ok(())
+        } else {
+            Err(CStr::from_ptr(pam_strerror(handle, status))
+                .to_string_lossy()
+                .into_owned())
+        };
+        pam_end(handle, status);
+        result
+    }
+}
+
+fn main() -> ExitCode {
+    let user = match User::from_uid(getuid()) {
+        Ok(Some(user)) => user.name,
+        _ => {
+            println!("error: unknown user");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).is_err() {
+        println!("error: no password");
+        return ExitCode::FAILURE;
+    }
+    let password = CString::new(line.trim_end_matches('\n')).unwrap_or_default();
+    let mut bytes = line.into_bytes();
+    bytes.fill(0);
+    std::hint::black_box(&bytes);
+
+    let result = authenticate(&user, &password);
+    let mut password = password.into_bytes();
+    password.fill(0);
+    std::hint::black_box(&password);
+    let mut stdout = io::stdout();
+    match result {
+        Ok(()) => {
+            let _ = writeln!(stdout, "ok");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            let _ = writeln!(stdout, "error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// The renderer's command line. Without flags it runs as it always has: renderer_config.toml
+// from the working directory, the socket its socket_path names, every message printed.
+// A renderer already listening on the socket is left alone unless --replace is given,
+// which stops it first; --daemonize detaches from the terminal once the socket is ours,
+// keeping the working directory, so relative paths still resolve.
+
+use std::fs::{File, OpenOptions};
+use std::io::IsTerminal;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+use nix::unistd::Pid;
+use qtilerugo_compositor::config::{Config, CONFIG_PATH};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How long a replaced renderer gets to exit.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLACE_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Composites and draws the desktop for qtile")]
+pub struct Args {
+    /// The config file.
+    #[arg(long, default_value = CONFIG_PATH)]
+    pub config: PathBuf,
+    /// Where to listen for commands, over the config's socket_path.
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+    /// What is printed: nothing, only errors, or everything.
+    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+    /// Stops a renderer already listening on the socket, rather than refusing to start.
+    #[arg(long)]
+    pub replace: bool,
+    /// Detaches from the terminal and runs in the background.
+    #[arg(long)]
+    pub daemonize: bool,
+    /// Prints the key and gesture bindings of the config, and exits.
+    #[arg(long)]
+    pub list_binds: bool,
+}
+
+impl Args {
+    /// Where to listen with `config`.
+    pub fn socket_path(&self, config: &Config) -> PathBuf {
+        self.socket
+            .clone()
+            .unwrap_or_else(|| config.socket_path.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Info,
+}
+
+impl LogLevel {
+    /// Silences what this level leaves out. Errors go to stderr and everything else to
+    /// stdout, so each level is a stream pointed at /dev/null.
+    pub fn apply(self) -> Result<()> {
+        if self == Self::Info {
+            return Ok(());
+        }
+        let null = OpenOptions::new().write(true).open("/dev/null")?;
+        nix::unistd::dup2(null.as_raw_fd(), std::io::stdout().as_raw_fd())?;
+        if self == Self::Off {
+            nix::unistd::dup2(null.as_raw_fd(), std::io::stderr().as_raw_fd())?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes way for this renderer on `socket`: refuses while another listens there, or
+/// with `replace` stops it and waits for it to exit. A stale socket file is left for
+/// the command listener to remove.
+pub fn take_socket(socket: &Path, replace: bool) -> Result<()> {
+    let Ok(stream) = UnixStream::connect(socket) else {
+        return Ok(());
+    };
+    if !replace {
+        return Err(format!(
+            "another renderer is listening on {}; pass --replace to take over",
+            socket.display()
+        )
+        .into());
+    }
+    // The credentials of a listening socket's peer are those of whoever listens.
+    let pid = Pid::from_raw(getsockopt(&stream, PeerCredentials)?.pid());
+    drop(stream);
+    println!("Replacing the renderer running as process {}", pid);
+    kill(pid, Signal::SIGTERM)?;
+    // Gone once nothing listens, whether or not its parent has reaped it.
+    let started = Instant::now();
+    while UnixStream::connect(socket).is_ok() {
+        if started.elapsed() > REPLACE_TIMEOUT {
+            return Err(format!("the renderer running as process {} did not exit", pid).into());
+        }
+        thread::sleep(REPLACE_POLL);
+    }
+    Ok(())
+}
+
+/// Forks into the background, in a session of its own. Standard streams that are the
+/// terminal's are pointed at /dev/null, and the rest, like a log file, kept.
+pub fn daemonize() -> Result<()> {
+    nix::unistd::daemon(true, true)?;
+    let null = OpenOptions::new().read(true).write(true).open("/dev/null")?;
+    detach(&null, std::io::stdin())?;
+    detach(&null, std::io::stdout())?;
+    detach(&null, std::io::stderr())?;
+    Ok(())
+}
+
+fn detach<F: AsRawFd + IsTerminal>(null: &File, stream: F) -> Result<()> {
+    if stream.is_terminal() {
+        nix::unistd::dup2(null.as_raw_fd(), stream.as_raw_fd())?;
+    }
+    Ok(())
+}
@@ -1,53 +1,74 @@
+mod cli;
+
 use std::fs;
-use std::path::Path;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncBufReadExt, BufReader};
+use clap::Parser;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixListener;
-use serde::Deserialize;
-use serde_json;
+use tokio::sync::oneshot;
 
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::event_loop::EventLoopProxy;
 
-// Vulkan and Vulkano imports:
-use vulkano::VulkanLibrary;
-use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
-use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo};
-use vulkano::swapchain::Surface; // New API for surface creation
+use qtilerugo_compositor::compositor::{LoopRequest, RenderLoop};
+use qtilerugo_compositor::config::{Config, HdrConfig};
+use qtilerugo_compositor::input;
+use qtilerugo_compositor::preview::{self, PreviewRequest};
+use qtilerugo_compositor::profile;
+use qtilerugo_compositor::scene::animation::AnimationConfig;
+use qtilerugo_compositor::scene::annotate::{Annotate, AnnotateRequest, Annotated, Finish};
+use qtilerugo_compositor::scene::effects::EffectsConfig;
+use qtilerugo_compositor::scene::picker::Color;
+use qtilerugo_compositor::scene::post::PostPass;
+use qtilerugo_compositor::scene::{Scene, SharedScene, SurfaceGeometry};
+use qtilerugo_compositor::screencast;
+use qtilerugo_compositor::screenshot::{self, Capture, Screenshot, ScreenshotRequest};
+use qtilerugo_compositor::shm::Ring;
+use qtilerugo_ipc::renderer::RendererCommand;
+use qtilerugo_ipc::reply;
 
-/// Defines commands that the window renderer understands.
-#[derive(Debug, Deserialize)]
-enum RendererCommand {
-    SpawnWindow,
-    SpawnStatusBar,
-    // You can add more commands here.
-}
+/// How long a screenshot may wait for a render loop before giving up.
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Listens for JSON-encoded commands on a Unix socket.
-async fn listen_for_commands(socket_path: &str) -> tokio::io::Result<()> {
+async fn listen_for_commands(
+    socket_path: &Path,
+    args: Arc<cli::Args>,
+    scene: SharedScene,
+    ring: Arc<Ring>,
+    render_loop: EventLoopProxy<LoopRequest>,
+) -> tokio::io::Result<()> {
     if Path::new(socket_path).exists() {
         fs::remove_file(socket_path).expect("failed to remove existing socket file");
     }
     let listener = UnixListener::bind(socket_path)?;
-    println!("Listening on Unix socket: {}", socket_path);
+    println!("Listening on Unix socket: {}", socket_path.display());
 
     loop {
         let (stream, _) = listener.accept().await?;
+        let scene = scene.clone();
+        let ring = ring.clone();
+        let render_loop = render_loop.clone();
+        let socket_path = socket_path.to_path_buf();
+        let args = args.clone();
         tokio::spawn(async move {
             let reader = BufReader::new(stream);
             let mut lines = reader.lines();
+            // Idle inhibitors this client still holds.
+            let mut inhibitors = Vec::new();
             while let Ok(Some(line)) = lines.next_line().await {
                 let trimmed = line.trim();
                 println!("Received raw command: {}", trimmed);
                 match serde_json::from_str::<RendererCommand>(trimmed) {
                     Ok(RendererCommand::SpawnWindow) => {
                         println!("Spawning window...");
-                        thread::spawn(|| {
-                            create_window();
-                        });
+                        if render_loop.send_event(LoopRequest::SpawnWindow).is_err() {
+                            println!("Failed to spawn window: the render loop exited");
+                        }
                     }
                     Ok(RendererCommand::SpawnStatusBar) => {
                         println!("Spawning Go status bar...");
@@ -56,103 +77,594 @@ async fn listen_for_commands(socket_path: &str) -> tokio::io::Result<()> {
                             println!("Failed to spawn go_status_bar: {}", e);
                         }
                     }
+                    Ok(RendererCommand::UpdateWindows(windows)) => {
+                        scene.lock().unwrap().sync_windows(&windows);
+                    }
+                    Ok(RendererCommand::AttachShm) => {
+                        let socket = lines.get_ref().get_ref().as_raw_fd();
+                        if let Err(e) = ring.send_fds(socket) {
+                            println!("Failed to send shared-memory ring: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetAnimations(config)) => {
+                        match serde_json::from_value::<AnimationConfig>(config) {
+                            Ok(config) => scene.lock().unwrap().set_animation_config(config),
+                            Err(e) => eprintln!("Ignoring SetAnimations: {}", e),
+                        }
+                    }
+                    Ok(RendererCommand::SetEffects(config)) => {
+                        match serde_json::from_value::<EffectsConfig>(config) {
+                            Ok(config) => scene.lock().unwrap().set_effects_config(config),
+                            Err(e) => eprintln!("Ignoring SetEffects: {}", e),
+                        }
+                    }
+                    Ok(RendererCommand::SetPresentMode(mode)) => {
+                        scene.lock().unwrap().set_present_mode(mode);
+                    }
+                    Ok(RendererCommand::SetPostProcessing(passes)) => {
+                        let passes: Result<Vec<PostPass>, _> =
+                            passes.into_iter().map(serde_json::from_value).collect();
+                        let passes = match passes {
+                            Ok(passes) => passes,
+                            Err(e) => {
+                                eprintln!("Ignoring SetPostProcessing: {}", e);
+                                continue;
+                            }
+                        };
+                        let mut scene = scene.lock().unwrap();
+                        let mut config = scene.post_config().clone();
+                        config.passes = passes;
+                        scene.set_post_config(config);
+                    }
+                    Ok(RendererCommand::SetPostProcessingEnabled { output, enabled }) => {
+                        scene.lock().unwrap().set_post_enabled(&output, enabled);
+                    }
+                    Ok(RendererCommand::SetColorTemp { kelvin, output }) => {
+                        let set = scene
+                            .lock()
+                            .unwrap()
+                            .set_color_temperature(output.as_deref(), kelvin);
+                        if let Err(e) = set {
+                            eprintln!("Ignoring color temperature: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetWorkspace { output, index }) => {
+                        let set = scene.lock().unwrap().set_workspace(output.as_deref(), index);
+                        if let Err(e) = set {
+                            eprintln!("Ignoring workspace switch: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetWorkspaces(workspaces)) => {
+                        scene.lock().unwrap().set_workspaces(workspaces);
+                    }
+                    Ok(RendererCommand::SetWindowInfo(info)) => {
+                        scene.lock().unwrap().set_window_info(info);
+                    }
+                    Ok(RendererCommand::SetLevel(level)) => {
+                        scene.lock().unwrap().set_level(level);
+                    }
+                    Ok(RendererCommand::SetMedia(info)) => {
+                        scene.lock().unwrap().set_media(info);
+                    }
+                    Ok(RendererCommand::AddNotification(notification)) => {
+                        scene.lock().unwrap().add_notification(notification);
+                    }
+                    Ok(RendererCommand::PinWindow { window, corner }) => {
+                        if let Err(e) = scene.lock().unwrap().pin_window(window, corner) {
+                            eprintln!("Not pinning: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::UnpinWindow { window }) => {
+                        if let Err(e) = scene.lock().unwrap().unpin_window(window) {
+                            eprintln!("Not unpinning: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetResponsive { window, responsive }) => {
+                        scene.lock().unwrap().set_responsive(window, responsive);
+                    }
+                    Ok(RendererCommand::ForceClose(window)) => {
+                        if let Err(e) = input::force_close(&scene, window) {
+                            eprintln!("Not closing window {}: {}", window, e);
+                        }
+                    }
+                    Ok(RendererCommand::PickColor) => {
+                        let reply = match pick_color(&scene).await {
+                            Ok(color) => reply::text(color),
+                            Err(e) => {
+                                println!("Color picking failed: {}", e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to PickColor: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SelectRegion) => {
+                        let reply = match select_region(&scene).await {
+                            Ok(region) => reply::json(&region),
+                            Err(e) => {
+                                println!("Selecting a region failed: {}", e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to SelectRegion: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::ZoomIn) => {
+                        scene.lock().unwrap().zoom_in();
+                    }
+                    Ok(RendererCommand::ZoomOut) => {
+                        scene.lock().unwrap().zoom_out();
+                    }
+                    Ok(RendererCommand::ZoomToggle) => {
+                        scene.lock().unwrap().toggle_zoom();
+                    }
+                    Ok(RendererCommand::SetPointer { x, y }) => {
+                        scene.lock().unwrap().set_pointer(x as f32, y as f32);
+                    }
+                    Ok(RendererCommand::Lock) => {
+                        if let Err(e) = scene.lock().unwrap().lock() {
+                            eprintln!("Not locking: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::ShowOsd {
+                        icon,
+                        text,
+                        timeout,
+                        gauge,
+                    }) => {
+                        scene.lock().unwrap().show_osd(
+                            icon,
+                            text,
+                            gauge,
+                            timeout.map(Duration::from_millis),
+                        );
+                    }
+                    Ok(RendererCommand::ShowInsertHint {
+                        x,
+                        y,
+                        width,
+                        height,
+                        duration_ms,
+                    }) => {
+                        scene.lock().unwrap().show_insert_hint(
+                            [x as f32, y as f32, width as f32, height as f32],
+                            duration_ms.map(Duration::from_millis),
+                        );
+                    }
+                    Ok(RendererCommand::SetLetterbox { window, tile }) => {
+                        scene.lock().unwrap().set_letterbox(window, tile);
+                    }
+                    Ok(RendererCommand::SetDimmed { window, dimmed }) => {
+                        scene.lock().unwrap().set_dimmed(window, dimmed);
+                    }
+                    Ok(RendererCommand::SetWindowShape { window, rects }) => {
+                        scene.lock().unwrap().set_window_shape(window, rects);
+                    }
+                    Ok(RendererCommand::SetClickThrough { window, click_through }) => {
+                        scene.lock().unwrap().set_click_through(window, click_through);
+                    }
+                    Ok(RendererCommand::SetCursorTheme(theme)) => {
+                        if let Err(e) = scene.lock().unwrap().set_cursor_theme(&theme) {
+                            eprintln!("Ignoring SetCursorTheme: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetCursorSize(size)) => {
+                        if let Err(e) = scene.lock().unwrap().set_cursor_size(size) {
+                            eprintln!("Ignoring SetCursorSize: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetBusy(busy)) => {
+                        scene.lock().unwrap().set_busy(busy);
+                    }
+                    Ok(RendererCommand::SetTheme(name)) => {
+                        if let Err(e) = scene.lock().unwrap().set_theme(&name) {
+                            eprintln!("Ignoring SetTheme: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetPowerProfile(profile)) => {
+                        scene.lock().unwrap().set_power_profile(profile);
+                    }
+                    Ok(RendererCommand::SetHdr(enabled)) => {
+                        let mut scene = scene.lock().unwrap();
+                        let hdr = HdrConfig {
+                            enabled,
+                            ..scene.hdr()
+                        };
+                        scene.set_hdr(hdr);
+                    }
+                    Ok(RendererCommand::SetDirectScanout(enabled)) => {
+                        scene.lock().unwrap().set_direct_scanout(enabled);
+                    }
+                    Ok(RendererCommand::SetVrr { enabled, output }) => {
+                        scene
+                            .lock()
+                            .unwrap()
+                            .set_vrr_enabled(output.as_deref(), enabled);
+                    }
+                    Ok(RendererCommand::CreateSurface {
+                        id,
+                        geometry,
+                        layer,
+                    }) => {
+                        let created = scene.lock().unwrap().create_surface(id, geometry, layer);
+                        if let Err(e) = created {
+                            eprintln!("Ignoring CreateSurface: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::DestroySurface { id }) => {
+                        if let Err(e) = scene.lock().unwrap().destroy_surface(id) {
+                            eprintln!("Ignoring DestroySurface: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::MoveSurface { id, geometry }) => {
+                        if let Err(e) = scene.lock().unwrap().move_surface(id, geometry) {
+                            eprintln!("Ignoring MoveSurface: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetOpacity { id, opacity }) => {
+                        if let Err(e) = scene.lock().unwrap().set_surface_opacity(id, opacity) {
+                            eprintln!("Ignoring SetOpacity: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetVisible { id, visible }) => {
+                        if let Err(e) = scene.lock().unwrap().set_surface_visible(id, visible) {
+                            eprintln!("Ignoring SetVisible: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetOutputScale { output, scale }) => {
+                        if let Err(e) = scene.lock().unwrap().set_output_scale(&output, scale) {
+                            eprintln!("Ignoring scale for {}: {}", output, e);
+                        }
+                    }
+                    Ok(RendererCommand::SetOutputs(outputs)) => {
+                        scene.lock().unwrap().set_monitors(outputs);
+                        // The render loop is only gone while the process exits.
+                        let _ = render_loop.send_event(LoopRequest::OutputsChanged);
+                    }
+                    Ok(RendererCommand::Screenshot(screenshot)) => {
+                        let reply = match take_screenshot(&scene, screenshot).await {
+                            Ok(path) => reply::text(path.display()),
+                            Err(e) => {
+                                println!("Screenshot failed: {}", e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to Screenshot: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::Annotate(request)) => {
+                        let reply = match annotate(&scene, request).await {
+                            Ok(destination) => reply::text(destination),
+                            Err(e) => {
+                                println!("Annotating failed: {}", e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to Annotate: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::StartScreencast(request)) => {
+                        // Blocks until PipeWire has set the stream up.
+                        let scene = scene.clone();
+                        let started =
+                            tokio::task::spawn_blocking(move || screencast::start(&scene, request))
+                                .await
+                                .map_err(|e| e.to_string())
+                                .and_then(|started| started);
+                        let reply = match started {
+                            Ok(id) => reply::text(id),
+                            Err(e) => {
+                                println!("Screencast failed: {}", e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to StartScreencast: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::StopScreencast(id)) => {
+                        if let Err(e) = screencast::stop(&scene, id) {
+                            println!("Failed to stop screencast: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::GetPreview {
+                        window,
+                        max_width,
+                        max_height,
+                    }) => match take_preview(&scene, window, [max_width, max_height]).await {
+                        Ok(capture) => {
+                            let socket = lines.get_ref().get_ref().as_raw_fd();
+                            if let Err(e) = preview::send(socket, &capture) {
+                                println!("Failed to send preview: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Preview failed: {}", e);
+                            let reply = reply::error(e);
+                            let stream = lines.get_mut().get_mut();
+                            if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                                println!("Failed to reply to GetPreview: {}", e);
+                            }
+                        }
+                    },
+                    Ok(RendererCommand::ReloadConfig) => {
+                        let path = args.config.display();
+                        let reply = match Config::load(&args.config) {
+                            Ok(config) => {
+                                if args.socket_path(&config) != socket_path {
+                                    println!("The new socket_path applies after a restart.");
+                                }
+                                config.apply(&mut scene.lock().unwrap());
+                                println!("Reloaded {}.", path);
+                                reply::ok()
+                            }
+                            Err(e) => {
+                                println!("Keeping the current settings; {}: {}", path, e);
+                                reply::error(e)
+                            }
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to ReloadConfig: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::Ping) => {
+                        let (reply, pong) = oneshot::channel();
+                        let windows = match render_loop.send_event(LoopRequest::Ping(reply)) {
+                            Ok(()) => pong.await.ok(),
+                            Err(_) => None,
+                        };
+                        let reply = match windows {
+                            Some(windows) => reply::json(&windows),
+                            None => reply::error("the render loop exited"),
+                        };
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to Ping: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::GetFrameStats) => {
+                        let report = scene.lock().unwrap().frame_stats().report();
+                        let reply = reply::json(&report);
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to GetFrameStats: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::GetKeybinds) => {
+                        let table = input::binding_table(scene.lock().unwrap().input_config());
+                        let reply = reply::json(&table);
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                            println!("Failed to reply to GetKeybinds: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetHud(hud)) => {
+                        scene.lock().unwrap().set_hud(hud);
+                    }
+                    Ok(RendererCommand::SetIccProfile { output, path }) => {
+                        if let Err(e) = scene.lock().unwrap().set_icc_profile(&output, path) {
+                            eprintln!("Ignoring SetIccProfile: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::SetWallpaper { output, path }) => {
+                        let mut scene = scene.lock().unwrap();
+                        if let Err(e) = scene.set_wallpaper(output.as_deref(), path) {
+                            eprintln!("Ignoring SetWallpaper: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::ReportActivity) => {
+                        scene.lock().unwrap().report_activity(Instant::now());
+                    }
+                    Ok(RendererCommand::InhibitIdle { reason }) => {
+                        println!("Idle inhibited: {}", reason);
+                        let id = scene.lock().unwrap().inhibit_idle(reason);
+                        inhibitors.push(id);
+                        let stream = lines.get_mut().get_mut();
+                        if let Err(e) = stream.write_all(reply::text(id).as_bytes()).await {
+                            println!("Failed to reply to InhibitIdle: {}", e);
+                        }
+                    }
+                    Ok(RendererCommand::UninhibitIdle(id)) => {
+                        inhibitors.retain(|held| *held != id);
+                        if let Err(e) = scene.lock().unwrap().uninhibit_idle(id) {
+                            eprintln!("Ignoring UninhibitIdle: {}", e);
+                        }
+                    }
                     Err(e) => {
                         println!("Invalid command: {}. Received: {}", e, trimmed);
                     }
                 }
             }
+            let mut scene = scene.lock().unwrap();
+            for id in inhibitors {
+                let _ = scene.uninhibit_idle(id);
+            }
         });
     }
 }
 
-/// Creates a window with Vulkan support.
-fn create_window() {
-    // Create the event loop and window.
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("Rust-Created Window")
-        .build(&event_loop)
-        .expect("Failed to create window");
-    let window = Arc::new(window);
-
-    // Create Vulkan instance.
-    let library = VulkanLibrary::new().expect("failed to load Vulkan library");
-    let instance = Instance::new(
-        library,
-        InstanceCreateInfo {
-            enabled_extensions: InstanceExtensions::empty(),
-            ..Default::default()
-        },
-    )
-    .expect("failed to create Vulkan instance");
+/// Waits for the user to click the pixel whose color to pick, for as long as it takes.
+async fn pick_color(scene: &SharedScene) -> Result<Color, String> {
+    let (reply, color) = oneshot::channel();
+    {
+        let mut scene = scene.lock().unwrap();
+        if scene.output_named(None).is_none() {
+            return Err("no output is rendering".into());
+        }
+        scene.pick_color(reply);
+    }
+    color
+        .await
+        .map_err(|_| "the render loop went away".to_string())?
+}
 
-    // Create a Vulkan surface from the window using the new API.
-    let surface = Surface::from_window(instance.clone(), window.clone())
-        .expect("failed to create Vulkan surface");
+/// Waits for a region dragged or a window clicked on the dimmed outputs.
+async fn select_region(scene: &SharedScene) -> Result<SurfaceGeometry, String> {
+    let (reply, region) = oneshot::channel();
+    {
+        let mut scene = scene.lock().unwrap();
+        if scene.output_named(None).is_none() {
+            return Err("no output is rendering".into());
+        }
+        scene.select_region(reply);
+    }
+    region
+        .await
+        .map_err(|_| "the render loop went away".to_string())?
+}
 
-    // Enumerate physical devices.
-    let physical = instance
-        .enumerate_physical_devices()
-        .expect("Failed to enumerate physical devices")
-        .next()
-        .expect("No physical device found");
+/// Has a render loop draw a preview of `window` that fits in `max_size`.
+async fn take_preview(
+    scene: &SharedScene,
+    window: u32,
+    max_size: [u32; 2],
+) -> Result<Capture, String> {
+    let (reply, capture) = oneshot::channel();
+    scene.lock().unwrap().request_preview(PreviewRequest {
+        window,
+        max_size,
+        reply,
+    });
+    tokio::time::timeout(SCREENSHOT_TIMEOUT, capture)
+        .await
+        .map_err(|_| "timed out waiting for a render loop".to_string())?
+        .map_err(|_| "the render loop went away".to_string())?
+}
 
-    // Find a queue family that supports graphics and presentation.
-    let queue_family = physical
-        .queue_family_properties()
-        .iter()
-        .enumerate()
-        .find(|(index, q)| {
-            q.queue_flags.contains(vulkano::device::QueueFlags::GRAPHICS)
-                && physical
-                    .surface_support(*index as u32, &*surface)
-                    .unwrap_or(false)
-        })
-        .map(|(index, _)| index as u32)
-        .expect("Couldn't find a graphical queue family that supports presentation");
+/// Has a render loop capture `screenshot.target`, then encodes it off the runtime.
+async fn take_screenshot(scene: &SharedScene, screenshot: Screenshot) -> Result<PathBuf, String> {
+    let (reply, capture) = oneshot::channel();
+    {
+        let mut scene = scene.lock().unwrap();
+        if scene.output_named(None).is_none() {
+            return Err("no output is rendering".into());
+        }
+        scene.request_screenshot(ScreenshotRequest {
+            target: screenshot.target.clone(),
+            reply,
+        });
+    }
+    let capture = tokio::time::timeout(SCREENSHOT_TIMEOUT, capture)
+        .await
+        .map_err(|_| "timed out waiting for a render loop".to_string())?
+        .map_err(|_| "the render loop went away".to_string())??;
+    let (path, format) = screenshot::destination(&screenshot);
+    tokio::task::spawn_blocking(move || capture.save(&path, format).map(|()| path))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    // Create the queue.
-    let queue_create_info = QueueCreateInfo {
-        queue_family_index: queue_family,
-        queues: vec![1.0],
-        ..Default::default()
+/// Freezes an output for drawing on until the annotation is saved or copied, which
+/// happens off the runtime; replies with the path, or `clipboard`.
+async fn annotate(scene: &SharedScene, request: Annotate) -> Result<String, String> {
+    let (reply, annotated) = oneshot::channel();
+    {
+        let mut scene = scene.lock().unwrap();
+        if scene.output_named(None).is_none() {
+            return Err("no output is rendering".into());
+        }
+        scene.annotate(AnnotateRequest {
+            output: request.output.clone(),
+            reply,
+        });
+    }
+    let Annotated { capture, finish } = annotated
+        .await
+        .map_err(|_| "the render loop went away".to_string())??;
+    let copy_command = scene.lock().unwrap().annotate_config().copy_command.clone();
+    let screenshot = Screenshot {
+        path: request.path,
+        format: request.format,
+        ..Screenshot::default()
     };
+    tokio::task::spawn_blocking(move || match finish {
+        Finish::Save => {
+            let (path, format) = screenshot::destination(&screenshot);
+            capture
+                .save(&path, format)
+                .map(|()| path.display().to_string())
+        }
+        Finish::Copy => capture.copy(&copy_command).map(|()| "clipboard".to_string()),
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    // Create the logical device.
-    let (device, mut queues) = Device::new(
-        physical,
-        DeviceCreateInfo {
-            queue_create_infos: vec![queue_create_info],
-            enabled_extensions: DeviceExtensions {
-                khr_swapchain: true,
-                ..DeviceExtensions::empty()
-            },
-            ..Default::default()
-        },
-    )
-    .expect("failed to create device");
-    let _queue = queues.next().unwrap();
-
-    println!("Created a new window with Vulkan support.");
-
-    // Run the event loop.
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
-        if let Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
-        } = event
-        {
-            println!("Window closed.");
-            *control_flow = ControlFlow::Exit;
+/// Prints the bindings of `config` as `--list-binds` asks, one per line.
+fn list_binds(config: &Config) {
+    let table = input::binding_table(&config.input);
+    let width = table.iter().map(|bound| bound.keys.chars().count()).max().unwrap_or(0);
+    for bound in table {
+        match &bound.command {
+            serde_json::Value::String(name) => println!("{:<width$}  {}", bound.keys, name),
+            command => println!("{:<width$}  {}", bound.keys, command),
         }
-    });
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let socket_path = "/tmp/rust_qtile_helper.sock";
-    if let Err(e) = listen_for_commands(socket_path).await {
+fn main() {
+    let args = cli::Args::parse();
+    let config = Config::load_or_default(&args.config);
+    if args.list_binds {
+        return list_binds(&config);
+    }
+    let socket_path = args.socket_path(&config);
+    let started = args
+        .log_level
+        .apply()
+        .and_then(|()| cli::take_socket(&socket_path, args.replace))
+        // Before any thread starts, since a fork leaves them behind.
+        .and_then(|()| if args.daemonize { cli::daemonize() } else { Ok(()) });
+    if let Err(e) = started {
         eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let args = Arc::new(args);
+    let scene = Scene::shared();
+    config.apply(&mut scene.lock().unwrap());
+    let ring = Arc::new(Ring::create().expect("failed to create shared-memory ring"));
+    {
+        let ring = ring.clone();
+        let scene = scene.clone();
+        thread::spawn(move || ring.run(&scene));
+    }
+    {
+        let scene = scene.clone();
+        thread::spawn(move || profile::watch(&scene));
+    }
+    #[cfg(feature = "wayland")]
+    {
+        let scene = scene.clone();
+        thread::spawn(move || {
+            if let Err(e) = qtilerugo_compositor::wayland::run(scene) {
+                eprintln!("Wayland frontend failed: {}", e);
+            }
+        });
+    }
+    // Windows are made on the main thread's event loop, so commands are served on
+    // another one.
+    let render_loop = RenderLoop::new();
+    {
+        let scene = scene.clone();
+        let proxy = render_loop.proxy();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+            let commands = listen_for_commands(&socket_path, args, scene, ring, proxy);
+            if let Err(e) = runtime.block_on(commands) {
+                eprintln!("Error: {}", e);
+            }
+            std::process::exit(1);
+        });
     }
+    render_loop.run(scene)
 }
@@ -0,0 +1,239 @@
+// Dark and light appearance, switched between automatically. With appearance.source
+// set, either the time of day or the desktop portal's color-scheme setting picks the
+// dark or the light variant, and switching to one has the renderer change its theme
+// and wallpaper, exports its GTK and icon themes, and whether dark is preferred, over
+// XSETTINGS, and runs its command, which can restyle qtile. The schedule is checked every poll_ms; the portal is
+// read once, then followed through its SettingChanged signal with busctl's monitor.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime};
+use serde_json::Value;
+
+use crate::config::{AppearanceConfig, AppearanceSource, AppearanceVariant};
+use crate::media::busctl;
+use crate::xsettings;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME: &str = "color-scheme";
+/// The portal's color-scheme for preferring dark; 0 is no preference and 2 light.
+const PREFER_DARK: u64 = 1;
+
+/// Told of the variant switched to, for the renderer.
+pub type Report = Box<dyn Fn(&AppearanceVariant) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Dark,
+    Light,
+}
+
+impl Scheme {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        }
+    }
+}
+
+struct Switcher {
+    config: AppearanceConfig,
+    report: Report,
+    /// None should another settings manager hold the selection.
+    xsettings: Option<xsettings::Shared>,
+    current: Option<Scheme>,
+}
+
+/// Starts switching between the variants as `config.source` says, if it says to.
+pub fn start(
+    config: AppearanceConfig,
+    xsettings: Option<xsettings::Shared>,
+    report: Report,
+) -> Result<()> {
+    let schedule = match config.source {
+        AppearanceSource::Off => return Ok(()),
+        AppearanceSource::Schedule => Some(Schedule::new(&config)?),
+        AppearanceSource::Portal => None,
+    };
+    let mut switcher = Switcher {
+        config,
+        report,
+        xsettings,
+        current: None,
+    };
+    thread::spawn(move || match schedule {
+        Some(schedule) => switcher.follow_schedule(schedule),
+        None => switcher.follow_portal(),
+    });
+    Ok(())
+}
+
+impl Switcher {
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.config.poll_ms.max(1000))
+    }
+
+    fn follow_schedule(&mut self, schedule: Schedule) {
+        loop {
+            self.switch(schedule.scheme(Local::now().time()));
+            thread::sleep(self.interval());
+        }
+    }
+
+    fn follow_portal(&mut self) {
+        loop {
+            match read_color_scheme() {
+                Ok(scheme) => self.switch(scheme),
+                Err(e) => eprintln!("Failed to read the portal's color scheme: {}", e),
+            }
+            if let Err(e) = self.watch_portal() {
+                eprintln!("Stopped following the portal's color scheme: {}", e);
+            }
+            // The portal, or the bus, came and went; it is tried again.
+            thread::sleep(self.interval());
+        }
+    }
+
+    /// Switches as the portal's SettingChanged signals say, until the monitor exits.
+    fn watch_portal(&mut self) -> Result<()> {
+        let rule = format!(
+            "type='signal',interface='{}',member='SettingChanged'",
+            SETTINGS_INTERFACE
+        );
+        let mut monitor = Command::new("busctl")
+            .args(["--user", "--json=short", "monitor", "--match", &rule])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = monitor.stdout.take().ok_or("busctl has no output")?;
+        for line in BufReader::new(stdout).lines() {
+            let Ok(message) = serde_json::from_str::<Value>(&line?) else {
+                continue;
+            };
+            // {"payload":{"type":"ssv","data":["org.freedesktop.appearance",
+            // "color-scheme",{"type":"u","data":1}]}, ...}
+            let data = &message["payload"]["data"];
+            if data[0] == APPEARANCE_NAMESPACE && data[1] == COLOR_SCHEME {
+                if let Some(scheme) = scheme_of(&data[2]) {
+                    self.switch(scheme);
+                }
+            }
+        }
+        Err(format!("busctl monitor exited with {}", monitor.wait()?).into())
+    }
+
+    /// Switches to the variant of `scheme`, unless it is the one in use.
+    fn switch(&mut self, scheme: Scheme) {
+        if self.current == Some(scheme) {
+            return;
+        }
+        self.current = Some(scheme);
+        println!("Switching to the {} appearance", scheme.name());
+        let variant = match scheme {
+            Scheme::Dark => &self.config.dark,
+            Scheme::Light => &self.config.light,
+        };
+        (self.report)(variant);
+        if let Some(xsettings) = &self.xsettings {
+            let mut xsettings = xsettings.lock().unwrap();
+            if let Some(theme) = &variant.gtk_theme {
+                xsettings.set("Net/ThemeName", theme.as_str());
+            }
+            if let Some(theme) = &variant.icon_theme {
+                xsettings.set("Net/IconThemeName", theme.as_str());
+            }
+            xsettings.set("Gtk/ApplicationPreferDarkTheme", scheme == Scheme::Dark);
+            if let Err(e) = xsettings.publish() {
+                eprintln!("Failed to export themes over XSETTINGS: {}", e);
+            }
+        }
+        if let Some(command) = &variant.command {
+            if let Err(e) = crate::spawn_template(command, &[("scheme", scheme.name())]) {
+                eprintln!(
+                    "Failed to run the {} appearance's command: {}",
+                    scheme.name(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// The daily times the variants take over.
+struct Schedule {
+    light_at: NaiveTime,
+    dark_at: NaiveTime,
+}
+
+impl Schedule {
+    fn new(config: &AppearanceConfig) -> Result<Self> {
+        let time = |setting: &str, value: &str| {
+            NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| {
+                format!(
+                    "appearance.{} {:?} is not a time like 07:00",
+                    setting, value
+                )
+            })
+        };
+        let schedule = Self {
+            light_at: time("light_at", &config.light_at)?,
+            dark_at: time("dark_at", &config.dark_at)?,
+        };
+        if schedule.light_at == schedule.dark_at {
+            return Err("appearance.light_at and dark_at are the same".into());
+        }
+        Ok(schedule)
+    }
+
+    /// The scheme at `now`, light from light_at until dark_at, across midnight if need be.
+    fn scheme(&self, now: NaiveTime) -> Scheme {
+        let light = if self.light_at < self.dark_at {
+            self.light_at <= now && now < self.dark_at
+        } else {
+            now >= self.light_at || now < self.dark_at
+        };
+        if light {
+            Scheme::Light
+        } else {
+            Scheme::Dark
+        }
+    }
+}
+
+/// The portal's color scheme now, through ReadOne, or Read on portals before it.
+fn read_color_scheme() -> Result<Scheme> {
+    let args = |method| {
+        [
+            "call",
+            PORTAL,
+            PORTAL_PATH,
+            SETTINGS_INTERFACE,
+            method,
+            "ss",
+            APPEARANCE_NAMESPACE,
+            COLOR_SCHEME,
+        ]
+    };
+    let reply = busctl(&args("ReadOne")).or_else(|_| busctl(&args("Read")))?;
+    // {"type":"v","data":[{"type":"u","data":1}]}, with Read a variant more deeply.
+    Ok(scheme_of(&reply).ok_or("the portal's color scheme is not a number")?)
+}
+
+/// The scheme a color-scheme value says, however deep in variants it is.
+fn scheme_of(value: &Value) -> Option<Scheme> {
+    match value {
+        Value::Number(number) if number.as_u64() == Some(PREFER_DARK) => Some(Scheme::Dark),
+        Value::Number(_) => Some(Scheme::Light),
+        Value::Array(values) => values.first().and_then(scheme_of),
+        Value::Object(fields) => scheme_of(fields.get("data")?),
+        _ => None,
+    }
+}
@@ -0,0 +1,222 @@
+# Runs a qtile config.py against stand-ins for libqtile and prints what it defines as
+# the JSON export qtileru-migrate reads: keys, groups, layouts, floating rules, and
+# mouse bindings. Running it, rather than reading its syntax, follows the loops and
+# variables configs build their keys with. Modules of qtile and qtile-extras are
+# stand-ins that record what is made of them; hooks are never called.
+
+import json
+import sys
+import types
+
+
+class Lazy:
+    """A lazy.x.y[item](...) call, as a path of names, the item, and the arguments."""
+
+    def __init__(self, path=(), item=None, args=None):
+        self.path, self.item, self.args = list(path), item, args
+
+    def __getattr__(self, name):
+        if name.startswith("__"):
+            raise AttributeError(name)
+        if self.args is not None:
+            # Chained calls, like .when(...), are left out.
+            return self
+        return Lazy(self.path + [name], self.item)
+
+    def __getitem__(self, item):
+        return Lazy(self.path, str(item))
+
+    def __call__(self, *args, **kwargs):
+        if self.args is not None:
+            return self
+        return Lazy(self.path, self.item, [plain(arg) for arg in args])
+
+    def export(self):
+        return {"path": self.path, "item": self.item, "args": self.args or []}
+
+
+class Made:
+    """Anything else made from qtile's modules: its kind and its arguments."""
+
+    def __init__(self, kind, args=(), kwargs=None):
+        self.kind, self.args, self.kwargs = kind, list(args), dict(kwargs or {})
+
+    def __getattr__(self, name):
+        if name.startswith("__"):
+            raise AttributeError(name)
+        return self.kwargs.get(name, Made(self.kind + "." + name))
+
+    def __call__(self, *args, **kwargs):
+        return Made(self.kind, args, kwargs)
+
+    def __iter__(self):
+        return iter(())
+
+
+class Module(types.ModuleType):
+    """A stand-in module whose every name makes a `Made` of that kind."""
+
+    def __getattr__(self, name):
+        if name.startswith("__"):
+            raise AttributeError(name)
+        return Made(self.__name__.rsplit(".", 1)[-1] + "." + name)
+
+
+def plain(value):
+    if isinstance(value, (str, int, float, bool)) or value is None:
+        return value
+    if isinstance(value, (list, tuple)):
+        return [plain(v) for v in value]
+    return repr(value)
+
+
+class Key(Made):
+    def __init__(self, modifiers, key, *commands, **kwargs):
+        super().__init__("Key", (), kwargs)
+        self.modifiers, self.key, self.commands = list(modifiers), key, commands
+
+
+class KeyChord(Made):
+    def __init__(self, modifiers, key, submappings, **kwargs):
+        super().__init__("KeyChord", (), kwargs)
+        self.modifiers, self.key = list(modifiers), key
+        self.submappings = list(submappings)
+
+
+class Group(Made):
+    def __init__(self, name, *args, **kwargs):
+        super().__init__("Group", args, kwargs)
+        self.name = name
+
+
+class Match(Made):
+    def __init__(self, **kwargs):
+        super().__init__("Match", (), kwargs)
+
+
+class Hook:
+    def __getattr__(self, name):
+        return self
+
+    def __call__(self, *args, **kwargs):
+        # Decorators hand back what they decorate.
+        if len(args) == 1 and callable(args[0]) and not kwargs:
+            return args[0]
+        return self
+
+
+def install():
+    config = Module("libqtile.config")
+    for made in (Key, KeyChord, Group, Match):
+        setattr(config, made.__name__, made)
+    lazy = Module("libqtile.lazy")
+    lazy.lazy = Lazy()
+    command = Module("libqtile.command")
+    command.lazy = lazy.lazy
+    hook = Module("libqtile.hook")
+    hook.subscribe = Hook()
+    utils = Module("libqtile.utils")
+    utils.guess_terminal = lambda *args: None
+    modules = {
+        "libqtile": Module("libqtile"),
+        "libqtile.config": config,
+        "libqtile.lazy": lazy,
+        "libqtile.command": command,
+        "libqtile.hook": hook,
+        "libqtile.utils": utils,
+    }
+    for name in ("bar", "layout", "widget", "qtile", "log_utils", "backend", "extension"):
+        modules["libqtile." + name] = Module("libqtile." + name)
+    modules["libqtile"].hook = hook
+    modules["libqtile"].qtile = Made("qtile")
+    for name, module in modules.items():
+        if "." in name:
+            setattr(modules["libqtile"], name.split(".")[1], module)
+    sys.modules.update(modules)
+
+    class Extras:
+        """qtile-extras and anything under it."""
+
+        def find_spec(self, name, path, target=None):
+            if name == "qtile_extras" or name.startswith("qtile_extras."):
+                import importlib.machinery
+
+                return importlib.machinery.ModuleSpec(name, self, is_package=True)
+            return None
+
+        def create_module(self, spec):
+            module = Module(spec.name)
+            module.__path__ = []
+            return module
+
+        def exec_module(self, module):
+            pass
+
+    sys.meta_path.insert(0, Extras())
+
+
+def export_keys(bindings, chord, keys, chords):
+    """Adds the keys of `bindings`, typed after the combinations of `chord`, to `keys`,
+    and the modal chords among them to `chords`."""
+    for key in bindings:
+        if isinstance(key, Key):
+            keys.append({
+                "modifiers": key.modifiers,
+                "key": key.key,
+                "commands": [c.export() for c in key.commands if isinstance(c, Lazy)],
+                "chord": chord,
+            })
+        elif isinstance(key, KeyChord):
+            if key.kwargs.get("mode"):
+                chords.append({"modifiers": key.modifiers, "key": key.key})
+                continue
+            prefix = chord + [{"modifiers": key.modifiers, "key": key.key}]
+            export_keys(key.submappings, prefix, keys, chords)
+
+
+def export(names):
+    keys, chords, mouse = [], [], []
+    export_keys(names.get("keys", []), [], keys, chords)
+    for binding in names.get("mouse", []):
+        if isinstance(binding, Made):
+            mouse.append(binding.kind.rsplit(".", 1)[-1])
+    groups = [
+        {"name": str(group.name), "label": plain(group.kwargs.get("label"))}
+        for group in names.get("groups", [])
+        if isinstance(group, Group)
+    ]
+    layouts = [
+        layout.kind.rsplit(".", 1)[-1]
+        for layout in names.get("layouts", [])
+        if isinstance(layout, Made)
+    ]
+    rules = []
+    floating = names.get("floating_layout")
+    if isinstance(floating, Made):
+        for rule in floating.kwargs.get("float_rules", []):
+            if isinstance(rule, Match):
+                rules.append({k: plain(v) for k, v in rule.kwargs.items()})
+            elif isinstance(rule, Made):
+                rules.append({"default": rule.kind})
+    return {
+        "keys": keys,
+        "key_chords": chords,
+        "mouse": mouse,
+        "groups": groups,
+        "layouts": layouts,
+        "float_rules": rules,
+    }
+
+
+def main():
+    path = sys.argv[1]
+    install()
+    names = {"__name__": "config", "__file__": path}
+    with open(path) as source:
+        code = compile(source.read(), path, "exec")
+    sys.path.insert(0, path.rsplit("/", 1)[0] if "/" in path else ".")
+    exec(code, names)
+    json.dump(export(names), sys.stdout)
+
+
+main()
@@ -0,0 +1,325 @@
+// qtileru-migrate: writes the keys of a qtile config.py as the renderer's
+// [input.bindings], and lists what of the config has no equivalent.
+//
+//     qtileru-migrate ~/.config/qtile/config.py > bindings.toml
+//     qtileru-migrate --json export.json > bindings.toml
+//
+// The config is run by python3 against stand-ins for libqtile (extract.py), which
+// prints what it defines as JSON; --json reads that export from a file instead, for
+// configs that can't run outside qtile. The export is an object of:
+//
+//     keys: [{modifiers: ["mod4", "shift"], key: "Return",
+//             commands: [{path: ["spawn"], item: null, args: ["alacritty"]}],
+//             chord: [{modifiers, key}]}]
+//     key_chords: [{modifiers, key}]
+//     mouse: ["Drag", "Click"]
+//     groups: [{name, label}]
+//     layouts: ["MonadTall", "Max"]
+//     float_rules: [{wm_class: "confirmreset"}]
+//
+// where each command is a lazy call: `lazy.group["2"].toscreen()` has the path
+// ["group", "toscreen"] and the item "2"; a key's chord is the combinations of the
+// KeyChords it is in, typed before it, and key_chords are the modal ones, which
+// bindings can't follow. Every list may be left out.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const EXTRACT: &str = include_str!("extract.py");
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Export {
+    keys: Vec<Key>,
+    key_chords: Vec<Chord>,
+    mouse: Vec<String>,
+    groups: Vec<Group>,
+    layouts: Vec<String>,
+    float_rules: Vec<serde_json::Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Key {
+    modifiers: Vec<String>,
+    key: String,
+    #[serde(default)]
+    commands: Vec<LazyCall>,
+    #[serde(default)]
+    chord: Vec<Chord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chord {
+    modifiers: Vec<String>,
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Group {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LazyCall {
+    path: Vec<String>,
+    #[serde(default)]
+    item: Option<String>,
+    #[serde(default)]
+    args: Vec<Value>,
+}
+
+impl LazyCall {
+    /// How the config wrote it, for the report.
+    fn describe(&self) -> String {
+        let mut call = String::from("lazy");
+        for (i, name) in self.path.iter().enumerate() {
+            call.push('.');
+            call.push_str(name);
+            if i == 0 {
+                if let Some(item) = &self.item {
+                    call.push_str(&format!("[{:?}]", item));
+                }
+            }
+        }
+        let args: Vec<String> = self.args.iter().map(Value::to_string).collect();
+        format!("{}({})", call, args.join(", "))
+    }
+
+    /// The WM command it becomes, or why there is none.
+    fn command(&self) -> Result<Value, String> {
+        let path: Vec<&str> = self.path.iter().map(String::as_str).collect();
+        let name = match path.as_slice() {
+            ["layout", "left"] => "FocusLeft",
+            ["layout", "right"] => "FocusRight",
+            ["layout", "down"] => "FocusDown",
+            ["layout", "up"] => "FocusUp",
+            ["layout", "next"] => "FocusNext",
+            ["layout", "shuffle_left"] => "ShuffleLeft",
+            ["layout", "shuffle_right"] => "ShuffleRight",
+            ["layout", "shuffle_down"] => "ShuffleDown",
+            ["layout", "shuffle_up"] => "ShuffleUp",
+            ["layout", "grow_left"] => "GrowLeft",
+            ["layout", "grow_right"] => "GrowRight",
+            ["layout", "grow_down"] => "GrowDown",
+            ["layout", "grow_up"] => "GrowUp",
+            ["layout", "normalize"] => "Normalize",
+            ["layout", "toggle_split"] => "ToggleSplit",
+            ["next_layout"] => "NextLayout",
+            ["window", "kill"] => "KillWindow",
+            ["window", "toggle_fullscreen"] => "ToggleFullscreen",
+            ["window", "toggle_floating"] => "ToggleFloating",
+            // Without an argument it toggles.
+            ["window", "keep_above"] if self.args.is_empty() => "ToggleAlwaysOnTop",
+            ["reload_config"] | ["restart"] => "ReloadConfig",
+            ["shutdown"] => "Shutdown",
+            ["spawn"] => {
+                return match self.args.as_slice() {
+                    [Value::String(command), ..] => Ok(json!({ "Spawn": command })),
+                    [Value::Array(argv), ..] => {
+                        let argv: Vec<String> = argv
+                            .iter()
+                            .map(|arg| arg.as_str().map(quote))
+                            .collect::<Option<_>>()
+                            .ok_or("its arguments aren't all strings")?;
+                        Ok(json!({ "Spawn": argv.join(" ") }))
+                    }
+                    _ => Err("spawns something that isn't a string".to_string()),
+                };
+            }
+            ["group", "toscreen"] => {
+                return match &self.item {
+                    Some(group) => Ok(json!({ "SwitchWorkspace": group })),
+                    None => Err("names no group".to_string()),
+                };
+            }
+            ["window", "togroup"] => {
+                return Err("moves the focused window, which bindings can't name".to_string())
+            }
+            _ => return Err("has no WM command".to_string()),
+        };
+        Ok(Value::String(name.to_string()))
+    }
+}
+
+/// `arg`, quoted for the shell.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// qtile's modifiers and keys as the renderer's bindings name them, like
+/// "Super+Shift+Return".
+fn combination(modifiers: &[String], key: &str) -> Result<String, String> {
+    let mut names = Vec::new();
+    for modifier in modifiers {
+        let name = match modifier.to_ascii_lowercase().as_str() {
+            "control" => "Ctrl",
+            "mod1" => "Alt",
+            "shift" => "Shift",
+            "mod4" => "Super",
+            other => return Err(format!("uses {}, which bindings don't have", other)),
+        };
+        names.push(name);
+    }
+    // In one order, so keys bound twice are seen as such.
+    let rank = |name: &&str| {
+        ["Super", "Ctrl", "Alt", "Shift"]
+            .iter()
+            .position(|n| n == name)
+    };
+    names.sort_by_key(rank);
+    names.push(key);
+    Ok(names.join("+"))
+}
+
+/// The bindings for `export`, and a line for everything it has that they don't.
+fn migrate(export: &Export) -> (BTreeMap<String, String>, Vec<String>) {
+    let mut bindings = BTreeMap::new();
+    let mut skipped = Vec::new();
+    for key in &export.keys {
+        // A chord's combinations, then the key's, separated by spaces.
+        let typed = key.chord.iter().map(|c| (&c.modifiers, &c.key));
+        let combination = typed
+            .chain([(&key.modifiers, &key.key)])
+            .map(|(modifiers, key)| {
+                combination(modifiers, key)
+                    .map_err(|e| format!("{}+{}: {}", modifiers.join("+"), key, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|combinations| combinations.join(" "));
+        let combination = match combination {
+            Ok(combination) => combination,
+            Err(e) => {
+                skipped.push(e);
+                continue;
+            }
+        };
+        let command = match key.commands.as_slice() {
+            [call] => call
+                .command()
+                .map_err(|e| format!("{}: {} {}", combination, call.describe(), e)),
+            [] => Err(format!("{}: runs no lazy command", combination)),
+            calls => {
+                let calls: Vec<String> = calls.iter().map(LazyCall::describe).collect();
+                Err(format!(
+                    "{}: runs {}, and bindings send one command",
+                    combination,
+                    calls.join(" and ")
+                ))
+            }
+        };
+        match command {
+            Ok(command) => {
+                let value = match command {
+                    Value::String(name) => name,
+                    command => command.to_string(),
+                };
+                if bindings.insert(combination.clone(), value).is_some() {
+                    skipped.push(format!("{}: bound again, keeping the last", combination));
+                }
+            }
+            Err(e) => skipped.push(e),
+        }
+    }
+    for chord in &export.key_chords {
+        let keys = combination(&chord.modifiers, &chord.key).unwrap_or(chord.key.clone());
+        skipped.push(format!(
+            "the modal key chord at {}: chords end with their first command",
+            keys
+        ));
+    }
+    if !export.mouse.is_empty() {
+        skipped.push(format!(
+            "mouse bindings ({}): the compositor binds keys and gestures only",
+            export.mouse.join(", ")
+        ));
+    }
+    if !export.groups.is_empty() {
+        let names: Vec<&str> = export.groups.iter().map(|g| g.name.as_str()).collect();
+        skipped.push(format!(
+            "groups {}: qtile keeps them, and the bridge's [workspaces] commands reach them",
+            names.join(", ")
+        ));
+    }
+    if !export.layouts.is_empty() {
+        skipped.push(format!(
+            "layouts {}: qtile keeps arranging windows; qtilerugo has no layouts of its own",
+            export.layouts.join(", ")
+        ));
+    }
+    for rule in &export.float_rules {
+        skipped.push(format!(
+            "the floating rule {}: qtile keeps floating windows",
+            Value::Object(rule.clone())
+        ));
+    }
+    (bindings, skipped)
+}
+
+/// Runs `config` through extract.py.
+fn extract(config: &str) -> Result<Export, Box<dyn std::error::Error>> {
+    let mut child = Command::new("python3")
+        .args(["-", config])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run python3: {}", e))?;
+    child
+        .stdin
+        .take()
+        .ok_or("python3 has no input")?
+        .write_all(EXTRACT.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} didn't run outside qtile; export it as JSON and pass --json instead",
+            config
+        )
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (export, source) = match args.as_slice() {
+        [flag, path] if flag == "--json" => {
+            (serde_json::from_str(&fs::read_to_string(path)?)?, path)
+        }
+        [path] if !path.starts_with('-') => (extract(path)?, path),
+        _ => {
+            eprintln!("usage: qtileru-migrate <config.py> | --json <export.json>");
+            std::process::exit(2);
+        }
+    };
+    let (bindings, skipped) = migrate(&export);
+
+    let table = toml::to_string(&BTreeMap::from([(
+        "input",
+        BTreeMap::from([("bindings", &bindings)]),
+    )]))?;
+    println!("# The keys of {}, migrated by qtileru-migrate, for", source);
+    println!("# renderer_config.toml; the renderer reads keys with input.enabled and the");
+    println!("# libinput feature.");
+    print!("{}", table);
+
+    eprintln!("Migrated {} of {} keys.", bindings.len(), export.keys.len());
+    if !skipped.is_empty() {
+        eprintln!("Not migrated:");
+        for line in &skipped {
+            eprintln!("  {}", line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+// Centered windows, for emulators and pixel-art games: a window of a configured class,
+// or toggled with ToggleCentered, is kept at its own resolution, or the largest whole
+// multiple of it that fits, in the middle of the tile qtile gives it, and the renderer
+// fills the rest of the tile with letterbox bars. Its resolution is the one configured
+// for its class, or the fixed size or program-specified size of its WM_NORMAL_HINTS.
+// qtile lays it out as usual; whenever it reconfigures the window, that is its tile,
+// and qtile is asked to put the window back in the middle of it, since it would only
+// refuse the bridge's own ConfigureWindow for a tiled window.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::properties::{WmClass, WmSizeHints};
+use x11rb::protocol::xproto::{ConnectionExt as _, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::CenteredConfig;
+use crate::stack::WindowStack;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Told of the tile, border included, to letterbox each centered window in, or `None`
+/// once a window isn't.
+pub type Report = Box<dyn Fn(Window, Option<[i32; 4]>) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    /// Whether ToggleCentered has the window centered, over its class.
+    chosen: Option<bool>,
+    /// Where qtile last configured it: x, y, width, height, and border width.
+    tile: Option<[i32; 5]>,
+    /// Where the bridge put it in the tile, as its ConfigureNotify reports it.
+    placed: Option<[i32; 5]>,
+}
+
+pub struct Centered {
+    conn: Arc<RustConnection>,
+    config: CenteredConfig,
+    report: Mutex<Option<Report>>,
+    windows: Mutex<HashMap<Window, Entry>>,
+}
+
+impl Centered {
+    pub fn new(conn: Arc<RustConnection>, config: CenteredConfig) -> Arc<Self> {
+        Arc::new(Self {
+            conn,
+            config,
+            report: Mutex::new(None),
+            windows: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Reports letterboxes to `report` from now on.
+    pub fn set_report(&self, report: Report) {
+        *self.report.lock().unwrap() = Some(report);
+    }
+
+    /// Centers the windows mapped before the bridge started in their tiles.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            if let Err(e) = self.mapped(window.window) {
+                eprintln!("Failed to center window {}: {}", window.window, e);
+            }
+        }
+    }
+
+    /// Centers `window` in its tile, or gives it the whole tile back, however its
+    /// class is configured.
+    pub fn toggle(&self, window: Window) -> Result<()> {
+        let centers = !self.centers(window)?;
+        let centering = if centers { "Centering" } else { "No longer centering" };
+        trace::decision(&[window], format!("{} window {}", centering, window));
+        let tile = {
+            let mut windows = self.windows.lock().unwrap();
+            let entry = windows.entry(window).or_default();
+            entry.chosen = Some(centers);
+            entry.tile
+        };
+        let tile = match tile {
+            Some(tile) => tile,
+            None => self.geometry(window)?,
+        };
+        if centers {
+            return self.place(window, tile);
+        }
+        self.windows.lock().unwrap().insert(
+            window,
+            Entry {
+                chosen: Some(false),
+                ..Entry::default()
+            },
+        );
+        self.report(window, None);
+        let [x, y, width, height, _] = tile;
+        self.configure(window, [x, y, width, height])
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify.
+    pub fn event(&self, event: &Event) {
+        let result = match event {
+            Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+            Event::ConfigureNotify(e) if !e.override_redirect => self.configured(
+                e.window,
+                [
+                    i32::from(e.x),
+                    i32::from(e.y),
+                    i32::from(e.width),
+                    i32::from(e.height),
+                    i32::from(e.border_width),
+                ],
+            ),
+            Event::UnmapNotify(e) => {
+                if let Some(entry) = self.windows.lock().unwrap().get_mut(&e.window) {
+                    entry.placed = None;
+                }
+                Ok(())
+            }
+            Event::DestroyNotify(e) => {
+                if self.windows.lock().unwrap().remove(&e.window).is_some() {
+                    self.report(e.window, None);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to center a window: {}", e);
+        }
+    }
+
+    /// Centers a newly mapped window of a centered class in its tile, or where it is
+    /// the first time.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if !self.centers(window)? {
+            return Ok(());
+        }
+        let tile = self.windows.lock().unwrap().entry(window).or_default().tile;
+        let tile = match tile {
+            Some(tile) => tile,
+            None => self.geometry(window)?,
+        };
+        self.place(window, tile)
+    }
+
+    /// Takes where qtile configured a centered window as its tile, unless it was the
+    /// bridge that put it there.
+    fn configured(&self, window: Window, rect: [i32; 5]) -> Result<()> {
+        {
+            let windows = self.windows.lock().unwrap();
+            let Some(entry) = windows.get(&window) else {
+                return Ok(());
+            };
+            if entry.chosen == Some(false) || entry.placed == Some(rect) {
+                return Ok(());
+            }
+        }
+        self.place(window, rect)
+    }
+
+    /// Puts `window` in the middle of `tile` at its resolution, scaled as far as it
+    /// fits; a window without a known resolution, or too big for the tile, fills it.
+    fn place(&self, window: Window, tile: [i32; 5]) -> Result<()> {
+        let [x, y, width, height, border] = tile;
+        let size = self.resolution(window)?.and_then(|[w, h]| {
+            let fits = (width / w).min(height / h);
+            let scale = if self.config.integer_scale {
+                fits
+            } else {
+                fits.min(1)
+            };
+            (scale >= 1).then_some([w * scale, h * scale])
+        });
+        let [w, h] = size.unwrap_or([width, height]);
+        let rect = [x + (width - w) / 2, y + (height - h) / 2, w, h, border];
+        {
+            let mut windows = self.windows.lock().unwrap();
+            let entry = windows.entry(window).or_default();
+            entry.tile = Some(tile);
+            entry.placed = Some(rect);
+        }
+        let outer = [x, y, width + 2 * border, height + 2 * border];
+        self.report(window, size.map(|_| outer));
+        if rect == tile {
+            return Ok(());
+        }
+        self.configure(window, [rect[0], rect[1], w, h])
+    }
+
+    /// Has qtile put `window` at x, y, width, height.
+    fn configure(&self, window: Window, [x, y, width, height]: [i32; 4]) -> Result<()> {
+        let (window, x, y) = (window.to_string(), x.to_string(), y.to_string());
+        let (width, height) = (width.to_string(), height.to_string());
+        let values = [
+            ("window", window.as_str()),
+            ("x", x.as_str()),
+            ("y", y.as_str()),
+            ("width", width.as_str()),
+            ("height", height.as_str()),
+        ];
+        crate::spawn_template(&self.config.place, &values)
+    }
+
+    /// Whether `window` is centered, by ToggleCentered or its class.
+    fn centers(&self, window: Window) -> Result<bool> {
+        let windows = self.windows.lock().unwrap();
+        if let Some(chosen) = windows.get(&window).and_then(|entry| entry.chosen) {
+            return Ok(chosen);
+        }
+        drop(windows);
+        let class = self.class_of(window)?;
+        let config = &self.config;
+        Ok(config.classes.contains(&class)
+            || config.resolutions.iter().any(|rule| rule.class == class))
+    }
+
+    /// The width and height `window` is meant to be drawn at, once scaled.
+    fn resolution(&self, window: Window) -> Result<Option<[i32; 2]>> {
+        let class = self.class_of(window)?;
+        if let Some(rule) = self
+            .config
+            .resolutions
+            .iter()
+            .find(|rule| rule.class == class)
+        {
+            return Ok(Some([rule.width as i32, rule.height as i32]));
+        }
+        let Ok(hints) = WmSizeHints::get_normal_hints(&*self.conn, window)?.reply() else {
+            return Ok(None);
+        };
+        let size = match (hints.min_size, hints.max_size, hints.size) {
+            (Some(min), Some(max), _) if min == max => Some(min),
+            (_, _, Some((_, width, height))) => Some((width, height)),
+            _ => None,
+        };
+        Ok(size
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .map(|(width, height)| [width, height]))
+    }
+
+    /// The class of `window`, empty for windows without one.
+    fn class_of(&self, window: Window) -> Result<String> {
+        let class = WmClass::get(&*self.conn, window)?.reply().ok();
+        Ok(class.map_or_else(String::new, |class| {
+            String::from_utf8_lossy(class.class()).into_owned()
+        }))
+    }
+
+    fn geometry(&self, window: Window) -> Result<[i32; 5]> {
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        Ok([
+            i32::from(geometry.x),
+            i32::from(geometry.y),
+            i32::from(geometry.width),
+            i32::from(geometry.height),
+            i32::from(geometry.border_width),
+        ])
+    }
+
+    fn report(&self, window: Window, tile: Option<[i32; 4]>) {
+        if let Some(report) = &*self.report.lock().unwrap() {
+            report(window, tile);
+        }
+    }
+}
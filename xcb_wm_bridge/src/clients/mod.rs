@@ -0,0 +1,247 @@
+// What the renderer's taskbar shows of each client: its window's title and icon, and
+// whether it asks for attention, with the WM_CLASS its effects rules match. They are
+// read when a window maps, and again when one of the properties they come from
+// changes, and reported to the renderer.
+
+use std::sync::{Arc, Mutex};
+
+use qtilerugo_ipc::renderer::{IconData, WindowInfo};
+use x11rb::connection::Connection;
+use x11rb::properties::{WmClass, WmHints};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::stack::WindowStack;
+
+/// Icons are picked at this size, or the nearest size above it.
+const ICON_SIZE: u32 = 32;
+/// The most of _NET_WM_ICON read, in 32-bit words: a 256×256 icon and then some.
+const MAX_ICON_WORDS: u32 = 1 << 17;
+
+/// A window icon as _NET_WM_ICON holds it: ARGB words, not premultiplied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icon {
+    pub width: u32,
+    pub height: u32,
+    pub argb: Vec<u32>,
+}
+
+/// What changed of a window's client; `None` is left as the renderer has it.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub window: Window,
+    pub title: Option<String>,
+    /// The WM_CLASS class and instance.
+    pub class: Option<(String, String)>,
+    pub urgent: Option<bool>,
+    pub icon: Option<Icon>,
+}
+
+impl ClientInfo {
+    /// What the renderer's SetWindowInfo command tells of it.
+    pub fn window_info(&self) -> WindowInfo {
+        WindowInfo {
+            window: self.window,
+            title: self.title.clone(),
+            class: self.class.as_ref().map(|(class, _)| class.clone()),
+            instance: self.class.as_ref().map(|(_, instance)| instance.clone()),
+            urgent: self.urgent,
+            icon: self.icon.as_ref().map(|icon| IconData {
+                width: icon.width,
+                height: icon.height,
+                argb: icon.argb.clone(),
+            }),
+        }
+    }
+}
+
+/// Told what changed of a client.
+pub type Report = Box<dyn Fn(ClientInfo) + Send>;
+
+struct Atoms {
+    net_wm_name: Atom,
+    utf8_string: Atom,
+    net_wm_icon: Atom,
+    net_wm_state: Atom,
+    demands_attention: Atom,
+}
+
+/// Watches the properties of mapped windows.
+pub struct Clients {
+    conn: Arc<RustConnection>,
+    atoms: Atoms,
+    report: Mutex<Report>,
+}
+
+impl Clients {
+    pub fn new(
+        conn: Arc<RustConnection>,
+        report: Report,
+    ) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
+        let atom = |name: &str| -> Result<Atom, Box<dyn std::error::Error>> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            net_wm_name: atom("_NET_WM_NAME")?,
+            utf8_string: atom("UTF8_STRING")?,
+            net_wm_icon: atom("_NET_WM_ICON")?,
+            net_wm_state: atom("_NET_WM_STATE")?,
+            demands_attention: atom("_NET_WM_STATE_DEMANDS_ATTENTION")?,
+        };
+        Ok(Arc::new(Self {
+            conn,
+            atoms,
+            report: Mutex::new(report),
+        }))
+    }
+
+    /// Starts watching the windows mapped in `stack`, which were mapped before the
+    /// bridge started and never send a MapNotify.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            self.watch(window.window);
+        }
+    }
+
+    /// Starts watching a window that mapped, or reports the property of one that
+    /// changed.
+    pub fn event(&self, event: &Event) {
+        match event {
+            Event::MapNotify(e) if !e.override_redirect => self.watch(e.window),
+            Event::PropertyNotify(e) => {
+                let mut info = ClientInfo {
+                    window: e.window,
+                    ..ClientInfo::default()
+                };
+                let atoms = &self.atoms;
+                if e.atom == atoms.net_wm_name || e.atom == u32::from(AtomEnum::WM_NAME) {
+                    info.title = self.title(e.window);
+                } else if e.atom == u32::from(AtomEnum::WM_CLASS) {
+                    info.class = self.class(e.window);
+                } else if e.atom == atoms.net_wm_state || e.atom == u32::from(AtomEnum::WM_HINTS) {
+                    info.urgent = self.urgent(e.window);
+                } else if e.atom == atoms.net_wm_icon {
+                    info.icon = self.icon(e.window);
+                } else {
+                    return;
+                }
+                (self.report.lock().unwrap())(info);
+            }
+            _ => {}
+        }
+    }
+
+    /// Asks for property changes of `window` and reports everything about it.
+    fn watch(&self, window: Window) {
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        if let Err(e) = self.conn.change_window_attributes(window, &aux) {
+            eprintln!("Not watching window {}: {}", window, e);
+            return;
+        }
+        let _ = self.conn.flush();
+        let info = ClientInfo {
+            window,
+            title: self.title(window),
+            class: self.class(window),
+            urgent: self.urgent(window),
+            icon: self.icon(window),
+        };
+        (self.report.lock().unwrap())(info);
+    }
+
+    /// _NET_WM_NAME, or WM_NAME for clients without one.
+    fn title(&self, window: Window) -> Option<String> {
+        let utf8 = self.property(window, self.atoms.net_wm_name, self.atoms.utf8_string, 1024)?;
+        if !utf8.is_empty() {
+            return Some(String::from_utf8_lossy(&utf8).into_owned());
+        }
+        let name = self.property(window, AtomEnum::WM_NAME.into(), AtomEnum::ANY.into(), 1024)?;
+        // WM_NAME is usually Latin-1.
+        Some(name.iter().map(|&b| b as char).collect())
+    }
+
+    /// WM_CLASS, as the class and the instance.
+    fn class(&self, window: Window) -> Option<(String, String)> {
+        let class = WmClass::get(&*self.conn, window).ok()?.reply().ok()?;
+        Some((
+            String::from_utf8_lossy(class.class()).into_owned(),
+            String::from_utf8_lossy(class.instance()).into_owned(),
+        ))
+    }
+
+    /// Whether the urgency hint is set, or _NET_WM_STATE asks for attention.
+    fn urgent(&self, window: Window) -> Option<bool> {
+        let hinted = WmHints::get(&*self.conn, window)
+            .ok()?
+            .reply()
+            .ok()
+            .is_some_and(|hints| hints.urgent);
+        let state = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.net_wm_state,
+                AtomEnum::ATOM,
+                0,
+                32,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        let demands = state
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == self.atoms.demands_attention));
+        Some(hinted || demands)
+    }
+
+    /// The icon nearest `ICON_SIZE` among those in _NET_WM_ICON, preferring bigger ones.
+    fn icon(&self, window: Window) -> Option<Icon> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.net_wm_icon,
+                AtomEnum::CARDINAL,
+                0,
+                MAX_ICON_WORDS,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        let words: Vec<u32> = reply.value32()?.collect();
+        let mut icons = Vec::new();
+        let mut rest = &words[..];
+        while let [width, height, tail @ ..] = rest {
+            let pixels = (*width as usize).saturating_mul(*height as usize);
+            if pixels == 0 || pixels > tail.len() {
+                break;
+            }
+            icons.push((*width, *height, &tail[..pixels]));
+            rest = &tail[pixels..];
+        }
+        let (width, height, argb) = icons.into_iter().min_by_key(|(width, height, _)| {
+            let size = (*width).max(*height);
+            (size < ICON_SIZE, size.abs_diff(ICON_SIZE))
+        })?;
+        Some(Icon {
+            width,
+            height,
+            argb: argb.to_vec(),
+        })
+    }
+
+    fn property(&self, window: Window, property: Atom, type_: Atom, words: u32) -> Option<Vec<u8>> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, words)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(reply.value)
+    }
+}
@@ -0,0 +1,430 @@
+// Clipboard history: XFixes tells of every new owner of CLIPBOARD, and of PRIMARY if it
+// is followed, and the text each one holds is read into a list of recent entries, kept
+// on disk if configured. Selecting an entry has the bridge own CLIPBOARD with it,
+// answering paste requests itself; it also takes over the latest entry when the
+// application holding CLIPBOARD exits, so copied text outlives its window. The X side
+// goes over a connection of its own.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xfixes::{self, ConnectionExt as _, SelectionEvent, SelectionEventMask};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
+    SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+use crate::config::ClipboardConfig;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// A piece of copied text, and the selection it was copied to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub text: String,
+    /// "CLIPBOARD" or "PRIMARY".
+    pub selection: String,
+}
+
+struct Atoms {
+    clipboard: Atom,
+    primary: Atom,
+    targets: Atom,
+    utf8_string: Atom,
+    text: Atom,
+    incr: Atom,
+}
+
+/// Text arriving in pieces, for a selection too large for one property.
+struct Transfer {
+    selection: Atom,
+    bytes: Vec<u8>,
+}
+
+#[derive(Default)]
+struct History {
+    /// Most recent first.
+    entries: VecDeque<Entry>,
+    /// What the bridge answers CLIPBOARD requests with, while it owns it.
+    owned: Option<String>,
+}
+
+pub struct Clipboard {
+    conn: RustConnection,
+    /// Owns the selections the bridge serves and receives what owners convert.
+    window: Window,
+    atoms: Atoms,
+    config: ClipboardConfig,
+    history: Mutex<History>,
+}
+
+impl Clipboard {
+    /// Connects to the X server, loads the saved history, and starts following the
+    /// selections.
+    pub fn new(config: ClipboardConfig) -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        conn.extension_information(xfixes::X11_EXTENSION_NAME)?
+            .ok_or("the X server has no XFixes")?;
+        conn.xfixes_query_version(5, 0)?.reply()?;
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            clipboard: atom("CLIPBOARD")?,
+            primary: AtomEnum::PRIMARY.into(),
+            targets: atom("TARGETS")?,
+            utf8_string: atom("UTF8_STRING")?,
+            text: atom("TEXT")?,
+            incr: atom("INCR")?,
+        };
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            // Pieces of large selections come as changes to its properties.
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        let closed = SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+        conn.xfixes_select_selection_input(
+            window,
+            atoms.clipboard,
+            SelectionEventMask::SET_SELECTION_OWNER | closed,
+        )?;
+        if config.primary {
+            conn.xfixes_select_selection_input(
+                window,
+                atoms.primary,
+                SelectionEventMask::SET_SELECTION_OWNER,
+            )?;
+        }
+        conn.flush()?;
+
+        let history = History {
+            entries: load(&config),
+            owned: None,
+        };
+        let clipboard = Arc::new(Self {
+            conn,
+            window,
+            atoms,
+            config,
+            history: Mutex::new(history),
+        });
+        let watcher = clipboard.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped following the clipboard: {}", e);
+            }
+        });
+        Ok(clipboard)
+    }
+
+    /// The history, most recent first.
+    pub fn entries(&self) -> Vec<Entry> {
+        self.history
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Puts entry `index` of the history on CLIPBOARD, and first in the history.
+    pub fn select(&self, index: usize) -> Result<()> {
+        let text = {
+            let history = self.history.lock().unwrap();
+            let entry = history
+                .entries
+                .get(index)
+                .ok_or_else(|| format!("The clipboard history has no entry {}", index))?;
+            entry.text.clone()
+        };
+        println!("Putting clipboard entry {} on the clipboard", index);
+        self.own(text.clone())?;
+        self.record(text, self.atoms.clipboard);
+        Ok(())
+    }
+
+    /// Takes CLIPBOARD with `text`.
+    fn own(&self, text: String) -> Result<()> {
+        self.history.lock().unwrap().owned = Some(text);
+        self.conn
+            .set_selection_owner(self.window, self.atoms.clipboard, CURRENT_TIME)?;
+        let owner = self
+            .conn
+            .get_selection_owner(self.atoms.clipboard)?
+            .reply()?
+            .owner;
+        if owner != self.window {
+            self.history.lock().unwrap().owned = None;
+            return Err("another client kept the clipboard".into());
+        }
+        Ok(())
+    }
+
+    fn watch(&self) -> Result<()> {
+        let mut transfers: HashMap<Atom, Transfer> = HashMap::new();
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::XfixesSelectionNotify(e) => self.owner_changed(e),
+                Event::SelectionNotify(e) => self.converted(e, &mut transfers)?,
+                Event::PropertyNotify(e)
+                    if e.window == self.window && e.state == Property::NEW_VALUE =>
+                {
+                    self.piece(e.atom, &mut transfers)?;
+                }
+                Event::SelectionRequest(e) => self.requested(e)?,
+                Event::SelectionClear(e) if e.selection == self.atoms.clipboard => {
+                    self.history.lock().unwrap().owned = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Asks a new owner of a selection for its text, or takes CLIPBOARD over from an
+    /// owner that went away.
+    fn owner_changed(&self, e: xfixes::SelectionNotifyEvent) {
+        match e.subtype {
+            SelectionEvent::SET_SELECTION_OWNER if e.owner != self.window && e.owner != NONE => {
+                // Into a property named after the selection, so both can be read at once.
+                let converted = self.conn.convert_selection(
+                    self.window,
+                    e.selection,
+                    self.atoms.utf8_string,
+                    e.selection,
+                    e.selection_timestamp,
+                );
+                if let Err(e) = converted.and_then(|_| self.conn.flush()) {
+                    eprintln!("Failed to read the selection: {}", e);
+                }
+            }
+            SelectionEvent::SELECTION_WINDOW_DESTROY | SelectionEvent::SELECTION_CLIENT_CLOSE => {
+                let latest = self.history.lock().unwrap().entries.front().cloned();
+                let latest = latest.filter(|entry| entry.selection == "CLIPBOARD");
+                if let Some(entry) = latest {
+                    if let Err(e) = self.own(entry.text) {
+                        eprintln!("Failed to keep the clipboard: {}", e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads a selection's text once its owner has converted it.
+    fn converted(
+        &self,
+        e: SelectionNotifyEvent,
+        transfers: &mut HashMap<Atom, Transfer>,
+    ) -> Result<()> {
+        // Selections that aren't text, like images, have no UTF8_STRING.
+        if e.property == NONE {
+            return Ok(());
+        }
+        let longest = self.config.max_entry_bytes as u32 / 4 + 1;
+        let reply = self
+            .conn
+            .get_property(true, self.window, e.property, AtomEnum::ANY, 0, longest)?
+            .reply()?;
+        if reply.type_ == self.atoms.incr {
+            // Deleting the property above asked for the first piece.
+            let transfer = Transfer {
+                selection: e.selection,
+                bytes: Vec::new(),
+            };
+            transfers.insert(e.property, transfer);
+        } else if reply.bytes_after == 0 {
+            self.received(reply.value, e.selection);
+        } else {
+            // Too long to keep; what is left wasn't deleted with the read.
+            self.conn.delete_property(self.window, e.property)?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next piece of a large selection; an empty one ends it.
+    fn piece(&self, property: Atom, transfers: &mut HashMap<Atom, Transfer>) -> Result<()> {
+        let Some(transfer) = transfers.get_mut(&property) else {
+            return Ok(());
+        };
+        let reply = self
+            .conn
+            .get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX / 4)?
+            .reply()?;
+        self.conn.flush()?;
+        if !reply.value.is_empty() {
+            transfer.bytes.extend_from_slice(&reply.value);
+            if transfer.bytes.len() > self.config.max_entry_bytes {
+                // Read to the end regardless, which the owner waits for.
+                transfer.bytes.clear();
+                transfer.bytes.shrink_to_fit();
+                transfer.selection = NONE;
+            }
+            return Ok(());
+        }
+        if let Some(transfer) = transfers.remove(&property) {
+            if transfer.selection != NONE {
+                self.received(transfer.bytes, transfer.selection);
+            }
+        }
+        Ok(())
+    }
+
+    fn received(&self, bytes: Vec<u8>, selection: Atom) {
+        if bytes.len() > self.config.max_entry_bytes {
+            return;
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => self.record(text, selection),
+            Err(_) => eprintln!("Ignoring a selection that isn't UTF-8"),
+        }
+    }
+
+    /// Puts `text` first in the history, dropping an earlier copy of it.
+    fn record(&self, text: String, selection: Atom) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let selection = if selection == self.atoms.primary {
+            "PRIMARY"
+        } else {
+            "CLIPBOARD"
+        };
+        let mut history = self.history.lock().unwrap();
+        history.entries.retain(|entry| entry.text != text);
+        history.entries.push_front(Entry {
+            text,
+            selection: selection.to_string(),
+        });
+        history.entries.truncate(self.config.max_entries);
+        if let Err(e) = save(&self.config, &history.entries) {
+            eprintln!("Failed to save the clipboard history: {}", e);
+        }
+    }
+
+    /// Answers a paste from an application, as the owner of CLIPBOARD.
+    fn requested(&self, e: SelectionRequestEvent) -> Result<()> {
+        // Obsolete clients give no property, meaning the target's.
+        let property = if e.property == NONE {
+            e.target
+        } else {
+            e.property
+        };
+        let owned = self.history.lock().unwrap().owned.clone();
+        let answered = match owned {
+            _ if e.selection != self.atoms.clipboard => false,
+            Some(_) if e.target == self.atoms.targets => {
+                let targets = [
+                    self.atoms.targets,
+                    self.atoms.utf8_string,
+                    self.atoms.text,
+                    AtomEnum::STRING.into(),
+                ];
+                self.conn.change_property32(
+                    PropMode::REPLACE,
+                    e.requestor,
+                    property,
+                    AtomEnum::ATOM,
+                    &targets,
+                )?;
+                true
+            }
+            // Larger text would have to go in pieces, which isn't done.
+            Some(text) if text.len() + 32 > self.conn.maximum_request_bytes() => false,
+            Some(text) if e.target == self.atoms.utf8_string || e.target == self.atoms.text => {
+                let type_ = self.atoms.utf8_string;
+                self.conn.change_property8(
+                    PropMode::REPLACE,
+                    e.requestor,
+                    property,
+                    type_,
+                    text.as_bytes(),
+                )?;
+                true
+            }
+            Some(text) if e.target == Atom::from(AtomEnum::STRING) => {
+                let latin1: Vec<u8> = text
+                    .chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                    .collect();
+                self.conn.change_property8(
+                    PropMode::REPLACE,
+                    e.requestor,
+                    property,
+                    AtomEnum::STRING,
+                    &latin1,
+                )?;
+                true
+            }
+            _ => false,
+        };
+        let notify = SelectionNotifyEvent {
+            response_type: x11rb::protocol::xproto::SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: e.time,
+            requestor: e.requestor,
+            selection: e.selection,
+            target: e.target,
+            property: if answered { property } else { NONE },
+        };
+        self.conn
+            .send_event(false, e.requestor, EventMask::NO_EVENT, notify)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// The saved history, if it is kept and there is one.
+fn load(config: &ClipboardConfig) -> VecDeque<Entry> {
+    let Some(path) = &config.history_file else {
+        return VecDeque::new();
+    };
+    let entries: VecDeque<Entry> = match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            eprintln!("Ignoring the clipboard history in {}: {}", path, e);
+            VecDeque::new()
+        }),
+        Err(_) => VecDeque::new(),
+    };
+    entries.into_iter().take(config.max_entries).collect()
+}
+
+/// Saves the history, readable only by the user, since it may hold passwords.
+fn save(config: &ClipboardConfig, entries: &VecDeque<Entry>) -> Result<()> {
+    let Some(path) = &config.history_file else {
+        return Ok(());
+    };
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(&serde_json::to_vec(entries)?)?;
+    Ok(())
+}
@@ -1,11 +1,837 @@
+use qtilerugo_ipc::wm::InsertionPoint;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub commands: HashMap<String, String>,
+    #[serde(default)]
+    pub compositor: CompositorConfig,
+    #[serde(default)]
+    pub ping: PingConfig,
+    #[serde(default)]
+    pub workspaces: WorkspaceCommands,
+    #[serde(default)]
+    pub controls: ControlsConfig,
+    #[serde(default)]
+    pub media: MediaConfig,
+    #[serde(default)]
+    pub i3: I3Config,
+    #[serde(default)]
+    pub startup: StartupConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub floating: FloatingConfig,
+    #[serde(default)]
+    pub inject: InjectConfig,
+    #[serde(default)]
+    pub tags: TagsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub fake_fullscreen: FakeFullscreenConfig,
+    #[serde(default)]
+    pub centered: CenteredConfig,
+    #[serde(default)]
+    pub input_regions: InputRegionConfig,
+    #[serde(default)]
+    pub modal: ModalConfig,
+    #[serde(default)]
+    pub kiosk: KioskConfig,
+    #[serde(default)]
+    pub outputs: OutputsConfig,
+    #[serde(default)]
+    pub insertion: InsertionConfig,
+    #[serde(default)]
+    pub layouts: LayoutsConfig,
+    #[serde(default)]
+    pub focus_stealing: FocusStealingConfig,
+    #[serde(default)]
+    pub undo: UndoConfig,
+    #[serde(default)]
+    pub macros: MacrosConfig,
+    /// Workspace setups LaunchProfile launches, by name.
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
+    #[serde(default)]
+    pub appearance: AppearanceConfig,
+    #[serde(default)]
+    pub xsettings: XSettingsConfig,
+}
+
+/// The settings the bridge manages over XSETTINGS; those left out aren't set, for
+/// toolkits to take their own defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct XSettingsConfig {
+    /// Takes the settings selection even without an appearance variant exporting
+    /// themes, which takes it anyway.
+    pub enabled: bool,
+    /// Xft/DPI, the font DPI, like 96 or 144.
+    pub dpi: Option<f32>,
+    /// Keeps Xft/DPI at the primary monitor's, by its physical size, as monitors change.
+    pub dpi_from_monitor: bool,
+    /// Gtk/CursorThemeName and Gtk/CursorThemeSize.
+    pub cursor_theme: Option<String>,
+    pub cursor_size: Option<i32>,
+    /// Gtk/FontName, like "Cantarell 11".
+    pub font_name: Option<String>,
+    /// Net/ThemeName and Net/IconThemeName, until an appearance variant switches them.
+    pub theme: Option<String>,
+    pub icon_theme: Option<String>,
+    /// Gtk/ApplicationPreferDarkTheme, until an appearance variant switches it.
+    pub prefer_dark: Option<bool>,
+    /// Any other settings by name, as integers or strings, like
+    /// "Xft/Antialias" = 1 or "Xft/HintStyle" = "hintslight".
+    pub settings: HashMap<String, crate::xsettings::Value>,
+}
+
+/// When the dark and light variants take turns, and what each switches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AppearanceConfig {
+    pub source: AppearanceSource,
+    /// When the light variant takes over from the dark one each day, as HH:MM in
+    /// local time, for the schedule.
+    pub light_at: String,
+    pub dark_at: String,
+    /// How often the schedule is checked, and how soon the portal is watched again
+    /// after its bus went away, in milliseconds.
+    pub poll_ms: u64,
+    pub dark: AppearanceVariant,
+    pub light: AppearanceVariant,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        Self {
+            source: AppearanceSource::Off,
+            light_at: "07:00".to_string(),
+            dark_at: "19:00".to_string(),
+            poll_ms: 60_000,
+            dark: AppearanceVariant {
+                theme: Some("default-dark".to_string()),
+                gtk_theme: Some("Adwaita-dark".to_string()),
+                ..AppearanceVariant::default()
+            },
+            light: AppearanceVariant {
+                theme: Some("default-light".to_string()),
+                gtk_theme: Some("Adwaita".to_string()),
+                ..AppearanceVariant::default()
+            },
+        }
+    }
+}
+
+/// What decides between the dark and light variants.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum AppearanceSource {
+    /// Nothing; the variants aren't switched to.
+    #[default]
+    Off,
+    /// The time of day, by light_at and dark_at.
+    Schedule,
+    /// The color-scheme setting of the desktop portal, org.freedesktop.portal.Settings.
+    Portal,
+}
+
+/// What switching to a variant changes; what is left out stays as it is.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppearanceVariant {
+    /// A palette of the renderer's [theme].
+    pub theme: Option<String>,
+    /// A wallpaper for every output without its own, as the renderer's SetWallpaper takes.
+    pub wallpaper: Option<PathBuf>,
+    /// Net/ThemeName and Net/IconThemeName, exported over XSETTINGS for GTK, and Qt
+    /// with its gtk3 platform theme, along with Gtk/ApplicationPreferDarkTheme.
+    pub gtk_theme: Option<String>,
+    pub icon_theme: Option<String>,
+    /// A shell command run on switching, like one restyling qtile.
+    pub command: Option<String>,
+}
+
+/// What named outputs do differently, like a TV that always shows one window, and how
+/// qtile is asked to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutputsConfig {
+    pub rules: Vec<OutputRule>,
+    /// Switches a {group} to the {layout} named.
+    pub set_layout: String,
+    /// Takes the gaps from around the windows of a {group}.
+    pub no_gaps: String,
+    /// Makes a {window} fullscreen.
+    pub fullscreen: String,
+}
+
+impl Default for OutputsConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            set_layout: "qtile cmd-obj -o group {group} -f setlayout -a {layout}".to_string(),
+            no_gaps: "qtile cmd-obj -o group {group} -f eval -a \
+                      \"[setattr(layout, 'margin', 0) for layout in self.layouts] \
+                      + [self.layout_all()]\""
+                .to_string(),
+            fullscreen: "qtile cmd-obj -o window {window} -f enable_fullscreen".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutputRule {
+    /// The output's RandR monitor name, like "HDMI-1".
+    pub name: String,
+    /// The layout each group gets as it is shown there, like "max".
+    pub layout: Option<String>,
+    /// False takes the gaps from groups as they are shown there.
+    pub gaps: bool,
+    /// Makes windows fullscreen as they first map there.
+    pub fullscreen: bool,
+}
+
+impl Default for OutputRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            layout: None,
+            gaps: true,
+            fullscreen: false,
+        }
+    }
+}
+
+/// Where new tiled windows go among those of their group, and how qtile is asked to
+/// move one there.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InsertionConfig {
+    /// Until SetInsertionPoint changes it; without one, windows stay where the layout
+    /// puts them.
+    pub point: Option<InsertionPoint>,
+    /// Move a tiled {window} right after {focused}, the window focused before it, to
+    /// the end, first, and right after the largest other tile.
+    pub after_focused: String,
+    pub end: String,
+    pub master: String,
+    pub swallow_largest: String,
+}
+
+impl Default for InsertionConfig {
+    fn default() -> Self {
+        Self {
+            point: None,
+            after_focused: restack(
+                "next((j + 1 for j, w in enumerate(c) if w.wid == {focused}), i)",
+            ),
+            end: restack("len(c)"),
+            master: restack("0"),
+            swallow_largest: restack(
+                "max(enumerate(c), key=lambda p: p[1].width * p[1].height, \
+                 default=(i - 1, None))[0] + 1",
+            ),
+        }
+    }
+}
+
+/// Moves a tiled {window} to `index`, an expression of the layout's other windows `c`
+/// and where it was, `i`, in layouts keeping one list of their windows, like MonadTall,
+/// Tile, and Max; focus stays where it was. qtile's eval keeps its names out of lambdas
+/// and generator conditions, so `index` passes what those need as arguments.
+fn restack(index: &str) -> String {
+    format!(
+        "qtile cmd-obj -o window {{window}} -f eval -a \
+         \"s = getattr(self.group.layout, 'clients', None); c = getattr(s, 'clients', []); \
+         f = s.current_client if c else None; i = c.index(self) if self in c else -1; \
+         i < 0 or self.floating or (c.remove(self), c.insert({}, self), \
+         setattr(s, 'current_client', f), self.group.layout_all())\"",
+        index
+    )
+}
+
+/// Whether the layout of each group is kept across restarts, and how qtile is asked for
+/// it and to bring it back.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayoutsConfig {
+    pub remember: bool,
+    /// Where the layouts are kept; by default layouts.json in $XDG_STATE_HOME/qtilerugo,
+    /// or ~/.local/state/qtilerugo.
+    pub state_file: Option<String>,
+    /// How often qtile is asked for its layouts, and whether it restarted.
+    pub poll_ms: u64,
+    /// Prints every group's layout, ratio, and margin, as a JSON object by group name.
+    pub read: String,
+    /// Switches a {group} to the {layout} named, with the {ratio} and {margin} read,
+    /// as JSON, either left empty for layouts without it.
+    pub restore: String,
+}
+
+impl Default for LayoutsConfig {
+    fn default() -> Self {
+        Self {
+            remember: false,
+            state_file: None,
+            poll_ms: 2000,
+            read: "qtile cmd-obj -o root -f eval -a \
+                   \"__import__('json').dumps({n: {'layout': g.layout.name, \
+                   'ratio': getattr(g.layout, 'ratio', None), \
+                   'margin': getattr(g.layout, 'margin', None)} \
+                   for n, g in self.groups_map.items()}, ensure_ascii=False)\""
+                .to_string(),
+            restore: "qtile cmd-obj -o group {group} -f eval -a \
+                      \"import json; self.setlayout({layout}); l = self.layout; \
+                      r, m = {ratio}, {margin}; \
+                      r and hasattr(l, 'ratio') and setattr(l, 'ratio', json.loads(r)); \
+                      m and hasattr(l, 'margin') and setattr(l, 'margin', json.loads(m)); \
+                      self.layout_all()\""
+                .to_string(),
+        }
+    }
+}
+
+/// Puts a {window} at {x}, {y}, {width} by {height} through qtile, tiled or not.
+const PLACE: &str = "qtile cmd-obj -o window {window} -f eval -a \
+                     \"self.place(int({x}), int({y}), int({width}), int({height}), \
+                     self.borderwidth, self.bordercolor)\"";
+
+/// A single application, kept fullscreen and running, and nothing else.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KioskConfig {
+    pub enabled: bool,
+    /// The application, a shell command.
+    pub command: String,
+    /// The WM_CLASS class of its windows, for an application whose windows aren't of
+    /// the process started, like a browser handing off to one already running.
+    pub class: Option<String>,
+    /// Leaves kiosk mode, like "Ctrl+Alt+Shift+Escape"; there is no way out without it.
+    pub escape: Option<String>,
+    /// How long after the application exits it is started again.
+    pub restart_delay_ms: u64,
+    /// Has qtile drop its key bindings, and take them back.
+    pub lock_keys: String,
+    pub unlock_keys: String,
+}
+
+impl Default for KioskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: String::new(),
+            class: None,
+            escape: None,
+            restart_delay_ms: 1000,
+            lock_keys: "qtile cmd-obj -o cmd -f eval -a \"self.core.ungrab_keys()\"".to_string(),
+            unlock_keys: "qtile cmd-obj -o cmd -f eval -a \"self.grab_keys()\"".to_string(),
+        }
+    }
+}
+
+/// Whether modal dialogs are followed, and how qtile is asked to center them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModalConfig {
+    pub enabled: bool,
+    /// Keeps dialogs centered on the window they are modal for.
+    pub center: bool,
+    /// Puts a {window} at {x}, {y}, {width} by {height}.
+    pub place: String,
+}
+
+impl Default for ModalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            center: true,
+            place: PLACE.to_string(),
+        }
+    }
+}
+
+/// How much Undo remembers, and how it reverts what wm_config.toml's commands can't.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UndoConfig {
+    /// How many actions are remembered.
+    pub history: usize,
+    /// How long CloseWindow waits before closing a window, for Undo to keep it
+    /// open; at 0 windows close at once, and closing them can't be undone.
+    pub close_grace_ms: u64,
+    /// Switches back to the layout before NextLayout.
+    pub previous_layout: String,
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        Self {
+            history: 50,
+            close_grace_ms: 0,
+            previous_layout: "qtile cmd-obj -o cmd -f prev_layout".to_string(),
+        }
+    }
+}
+
+/// Where macros are kept, and how long one gets.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MacrosConfig {
+    /// By default macros.json in $XDG_STATE_HOME/qtilerugo, or ~/.local/state/qtilerugo.
+    pub state_file: Option<String>,
+    /// Commands recorded past this many are left out.
+    pub max_length: usize,
+}
+
+impl Default for MacrosConfig {
+    fn default() -> Self {
+        Self {
+            state_file: None,
+            max_length: 1000,
+        }
+    }
+}
+
+/// The apps a profile launches, the workspaces they go to, and how those are laid out.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub workspaces: Vec<ProfileWorkspace>,
+    /// How long the apps' windows are waited for.
+    pub timeout_ms: u64,
+    /// The workspace shown once the apps are in place.
+    pub switch_to: Option<String>,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            workspaces: Vec::new(),
+            timeout_ms: 15000,
+            switch_to: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileWorkspace {
+    pub workspace: String,
+    /// Set with outputs.set_layout before the windows come, like "columns".
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// Placed in this order.
+    #[serde(default)]
+    pub apps: Vec<ProfileApp>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileApp {
+    pub command: String,
+    /// The WM_CLASS class of the app's window.
+    pub class: String,
+    /// A pattern, with * for anything, the window's title matches, telling apart windows of
+    /// one class.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// How strictly newly mapped windows are kept from taking focus.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FocusStealingConfig {
+    pub level: FocusStealing,
+}
+
+/// Which new windows qtile's focusing them is undone for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum FocusStealing {
+    /// None of them.
+    Off,
+    /// Those opened before the user last interacted with the focused window.
+    #[default]
+    Smart,
+    /// Those as well that don't say when they were opened.
+    Strict,
+}
+
+/// Which windows let clicks through to what is under them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct InputRegionConfig {
+    /// WM_CLASS classes made click-through as they map, like overlays and notes.
+    pub click_through: Vec<String>,
+}
+
+/// Which windows are kept centered in their tiles at their own resolution, and how
+/// qtile is asked to put them there.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CenteredConfig {
+    /// WM_CLASS classes centered at the resolution their size hints give.
+    pub classes: Vec<String>,
+    /// Resolutions by class, for windows whose size hints don't say.
+    pub resolutions: Vec<Resolution>,
+    /// Scales windows up by the largest whole multiple that fits their tiles.
+    pub integer_scale: bool,
+    /// Puts a {window} at {x}, {y}, {width} by {height}, tiled or not.
+    pub place: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Resolution {
+    pub class: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for CenteredConfig {
+    fn default() -> Self {
+        Self {
+            classes: Vec::new(),
+            resolutions: Vec::new(),
+            integer_scale: true,
+            place: PLACE.to_string(),
+        }
+    }
+}
+
+/// Which windows stay in their tiles when they go fullscreen, and how qtile is asked.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FakeFullscreenConfig {
+    /// WM_CLASS classes that fake fullscreen, like "mpv".
+    pub classes: Vec<String>,
+    /// Takes a {window} qtile made fullscreen back to its tile.
+    pub unfullscreen: String,
+}
+
+impl Default for FakeFullscreenConfig {
+    fn default() -> Self {
+        Self {
+            classes: Vec::new(),
+            unfullscreen: "qtile cmd-obj -o window {window} -f disable_fullscreen".to_string(),
+        }
+    }
+}
+
+/// Where Prometheus can scrape the metrics GetMetrics answers with.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// An address to serve them on over HTTP, like "127.0.0.1:9750"; none without it.
+    pub listen: Option<String>,
+}
+
+/// Commands over TCP with TLS, for machines nobody has a shell on.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// An address to take them on, like "0.0.0.0:9760"; off without it.
+    pub listen: Option<String>,
+    /// PEM files of the certificate chain and its private key.
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    /// Holds the token clients send first, of 16 bytes at least.
+    pub token_file: Option<String>,
+}
+
+/// dwm-style tags as the workspace model, instead of qtile's groups.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TagsConfig {
+    pub enabled: bool,
+    /// How many tags there are, up to 32.
+    pub count: u32,
+    /// The qtile group that windows out of view wait on; it has to be in config.py.
+    pub hidden_group: String,
+}
+
+impl Default for TagsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            count: 9,
+            hidden_group: "tags".to_string(),
+        }
+    }
+}
+
+/// Whether SendKey, SendText, and ClickAt are taken, from the bridge's own user.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct InjectConfig {
+    pub enabled: bool,
+}
+
+/// Where floating windows are remembered to have been, and how qtile is asked.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FloatingConfig {
+    pub remember: bool,
+    /// Where the geometry is kept; by default floating.json in
+    /// $XDG_STATE_HOME/qtilerugo, or ~/.local/state/qtilerugo.
+    pub state_file: Option<String>,
+    /// Title patterns, with `*` for anything, telling apart windows of one class.
+    pub titles: Vec<TitlePattern>,
+    /// Prints `'floating': True` for a {window} qtile floats.
+    pub floats: String,
+    /// Floats a {window} at {x}, {y}, {width} by {height}.
+    pub place: String,
+    /// Where windows that start floating go when nothing is remembered for them.
+    pub placement: Placement,
+    /// How far each cascading window is down and right of the last.
+    pub cascade_step: i32,
+}
+
+/// Where a window that starts floating is put, inside the work area of the output of
+/// the focused group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Placement {
+    /// Where the client asks.
+    Client,
+    /// Where it overlaps the other windows least.
+    #[default]
+    Smart,
+    Center,
+    /// Centered on the pointer.
+    UnderPointer,
+    /// A step down and right of the last cascading window.
+    Cascade,
+}
+
+impl Default for FloatingConfig {
+    fn default() -> Self {
+        Self {
+            remember: true,
+            state_file: None,
+            titles: Vec::new(),
+            floats: "qtile cmd-obj -o window {window} -f info".to_string(),
+            place: "qtile cmd-obj -o window {window} -f set_size_floating -a {width} {height} \
+                    && qtile cmd-obj -o window {window} -f set_position_floating -a {x} {y}"
+                .to_string(),
+            placement: Placement::Smart,
+            cascade_step: 32,
+        }
+    }
+}
+
+/// Windows of `class` whose titles match `title` share their own floating geometry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TitlePattern {
+    pub class: String,
+    pub title: String,
+}
+
+/// The clipboard history and what goes in it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    pub enabled: bool,
+    /// Whether PRIMARY, the text last selected, goes in the history too.
+    pub primary: bool,
+    /// How many entries are kept.
+    pub max_entries: usize,
+    /// Longer text is left out of the history.
+    pub max_entry_bytes: usize,
+    /// Where the history is kept across restarts; without one it isn't.
+    pub history_file: Option<String>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            primary: false,
+            max_entries: 50,
+            max_entry_bytes: 64 * 1024,
+            history_file: None,
+        }
+    }
+}
+
+/// Startup notification for what the WM launches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    pub enabled: bool,
+    /// How long a launch waits for its window before it stops showing, in milliseconds.
+    pub timeout_ms: u64,
+    /// Whether the X pointer shows busy while launches wait; the renderer's follows
+    /// either way.
+    pub busy_cursor: bool,
+    /// Whether windows are moved back to the workspace they were launched from.
+    pub launch_workspace: bool,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 15000,
+            busy_cursor: true,
+            launch_workspace: true,
+        }
+    }
+}
+
+/// The i3-compatible IPC socket, for tools written for i3.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct I3Config {
+    pub enabled: bool,
+    /// Where it listens; tools find it through I3SOCK or the root window's
+    /// I3_SOCKET_PATH.
+    pub socket: String,
+}
+
+impl Default for I3Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: "/tmp/qtilerugo-i3.sock".to_string(),
+        }
+    }
+}
+
+/// Which MPRIS player the media commands and the bar follow, and how it is watched.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MediaConfig {
+    /// Whether the player is watched for the bar; the commands work either way.
+    pub enabled: bool,
+    /// Players to follow first, in order, by their MPRIS names, like "spotify".
+    pub players: Vec<String>,
+    /// How often the player is read, in milliseconds.
+    pub poll_ms: u64,
+    /// A shell command run when the track or its playback status changes, with
+    /// `{player}`, `{status}`, `{title}`, `{artist}`, and `{album}` replaced, quoted.
+    pub on_change: Option<String>,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            players: Vec::new(),
+            poll_ms: 1000,
+            on_change: None,
+        }
+    }
+}
+
+/// How the volume and brightness commands change them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlsConfig {
+    pub audio: AudioBackend,
+    /// Percentage points each VolumeUp or VolumeDown moves the volume.
+    pub volume_step: u32,
+    /// Highest VolumeUp goes, in percent; past 100 amplifies.
+    pub max_volume: u32,
+    pub brightness_step: u32,
+    /// Lowest BrightnessDown goes, in percent, so the screen never goes dark.
+    pub min_brightness: u32,
+    /// The backlight under /sys/class/backlight; the first one there without one.
+    pub backlight: Option<String>,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        Self {
+            audio: AudioBackend::Auto,
+            volume_step: 5,
+            max_volume: 100,
+            brightness_step: 5,
+            min_brightness: 1,
+            backlight: None,
+        }
+    }
+}
+
+/// Which mixer the volume commands run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum AudioBackend {
+    /// pactl if it is installed, and wpctl otherwise.
+    #[default]
+    Auto,
+    /// pactl, for PulseAudio or PipeWire's pulse server.
+    PulseAudio,
+    /// wpctl, for PipeWire with WirePlumber.
+    PipeWire,
+}
+
+/// Shell commands that have qtile act on workspaces for the renderer's pager.
+/// `{workspace}` and `{window}` are replaced with the workspace name and the X window
+/// id, quoted for the shell.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceCommands {
+    pub switch: String,
+    pub move_window: String,
+}
+
+impl Default for WorkspaceCommands {
+    fn default() -> Self {
+        Self {
+            switch: "qtile cmd-obj -o group {workspace} -f toscreen".to_string(),
+            move_window: "qtile cmd-obj -o window {window} -f togroup -a {workspace}".to_string(),
+        }
+    }
+}
+
+/// How clients are checked for answering _NET_WM_PING.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PingConfig {
+    pub enabled: bool,
+    /// Time between pings.
+    pub interval_ms: u64,
+    /// How long a ping may go unanswered before the client counts as not responding.
+    pub timeout_ms: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 2000,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// How the bridge keeps a separate rustVrender running.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CompositorConfig {
+    /// Shell command that starts the renderer; without one, a renderer started some
+    /// other way is watched but never restarted.
+    pub command: Option<String>,
+    /// Time between heartbeats, which is also how long each may take to be answered.
+    pub heartbeat_ms: u64,
+    /// Heartbeats missed in a row before the renderer counts as stalled.
+    pub missed_heartbeats: u32,
+    /// Wait before the first restart; it doubles with every failure in a row.
+    pub restart_delay_ms: u64,
+    pub max_restart_delay_ms: u64,
+}
+
+impl Default for CompositorConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            heartbeat_ms: 1000,
+            missed_heartbeats: 3,
+            restart_delay_ms: 500,
+            max_restart_delay_ms: 30_000,
+        }
+    }
 }
 
 impl Config {
@@ -0,0 +1,221 @@
+// Volume and brightness, so media keys can be bound to the WM's commands: the volume
+// of the default sink through PulseAudio's pactl, which PipeWire's pulse server also
+// answers, or PipeWire's own wpctl, and the brightness of a backlight in sysfs, or
+// through logind's SetBrightness where the bridge may not write there. Each change
+// reports the level it left to the renderer, which shows it in an OSD and the bar.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::config::{AudioBackend, ControlsConfig};
+
+const BACKLIGHT: &str = "/sys/class/backlight";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub use qtilerugo_ipc::renderer::{Control, Level};
+
+/// Told of the level every change leaves.
+pub type Report = Box<dyn Fn(Level) + Send + Sync>;
+
+/// Moves the volume `steps` steps up, or down if negative, unmuting it.
+pub fn change_volume(config: &ControlsConfig, steps: i32) -> Result<Level> {
+    let mixer = Mixer::find(config.audio)?;
+    let (volume, _) = mixer.get()?;
+    let step = config.volume_step as f32 / 100.0;
+    let max = config.max_volume as f32 / 100.0;
+    // Above the maximum, which another mixer may have set, it only comes down.
+    let target = (volume + steps as f32 * step).clamp(0.0, max.max(volume));
+    mixer.set_volume(target)?;
+    mixer.set_muted(Some(false))?;
+    Ok(Level {
+        control: Control::Volume,
+        level: target,
+        muted: false,
+    })
+}
+
+pub fn toggle_mute(config: &ControlsConfig) -> Result<Level> {
+    let mixer = Mixer::find(config.audio)?;
+    mixer.set_muted(None)?;
+    let (volume, muted) = mixer.get()?;
+    Ok(Level {
+        control: Control::Volume,
+        level: volume,
+        muted,
+    })
+}
+
+/// Moves the brightness `steps` steps up, or down if negative, never below the
+/// configured minimum.
+pub fn change_brightness(config: &ControlsConfig, steps: i32) -> Result<Level> {
+    let device = backlight(config)?;
+    let read =
+        |name: &str| -> Result<u32> { Ok(fs::read_to_string(device.join(name))?.trim().parse()?) };
+    let max = read("max_brightness")?.max(1);
+    let current = read("brightness")?.min(max);
+    let percent = current as f32 * 100.0 / max as f32;
+    let target = (percent + steps as f32 * config.brightness_step as f32)
+        .clamp(config.min_brightness.min(100) as f32, 100.0);
+    let lowest = ((config.min_brightness.min(100) * max) as f32 / 100.0).ceil() as u32;
+    let mut raw = (target * max as f32 / 100.0).round() as u32;
+    // Backlights with few steps still move a step per press.
+    if raw == current {
+        raw = match steps.signum() {
+            1 => (current + 1).min(max),
+            -1 => current.saturating_sub(1).max(lowest),
+            _ => current,
+        };
+    }
+    write_brightness(&device, raw)?;
+    Ok(Level {
+        control: Control::Brightness,
+        level: raw as f32 / max as f32,
+        muted: false,
+    })
+}
+
+/// The backlight under /sys/class/backlight the config names, or the first there.
+fn backlight(config: &ControlsConfig) -> Result<PathBuf> {
+    if let Some(name) = &config.backlight {
+        return Ok(PathBuf::from(BACKLIGHT).join(name));
+    }
+    let mut devices: Vec<PathBuf> = fs::read_dir(BACKLIGHT)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    devices.sort();
+    devices
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("no backlight in {}", BACKLIGHT).into())
+}
+
+/// Sets the backlight at `device` to `raw`, asking logind when the file isn't writable.
+fn write_brightness(device: &std::path::Path, raw: u32) -> Result<()> {
+    match fs::write(device.join("brightness"), raw.to_string()) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {}
+        Err(e) => return Err(e.into()),
+    }
+    let name = device
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("the backlight has no name")?;
+    run(
+        "busctl",
+        &[
+            "call",
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+            "SetBrightness",
+            "ssu",
+            "backlight",
+            name,
+            &raw.to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// The command line mixer the volume is changed with.
+#[derive(Debug, Clone, Copy)]
+enum Mixer {
+    Pactl,
+    Wpctl,
+}
+
+impl Mixer {
+    fn find(backend: AudioBackend) -> Result<Self> {
+        match backend {
+            AudioBackend::PulseAudio => Ok(Self::Pactl),
+            AudioBackend::PipeWire => Ok(Self::Wpctl),
+            AudioBackend::Auto => {
+                for mixer in [Self::Pactl, Self::Wpctl] {
+                    match Command::new(mixer.program()).arg("--version").output() {
+                        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                        _ => return Ok(mixer),
+                    }
+                }
+                Err("neither pactl nor wpctl is installed".into())
+            }
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Pactl => "pactl",
+            Self::Wpctl => "wpctl",
+        }
+    }
+
+    /// The default sink's volume, from 0 to 1 at 100%, and whether it is muted.
+    fn get(self) -> Result<(f32, bool)> {
+        match self {
+            Self::Pactl => {
+                // Volume: front-left: 26214 /  40% / -23.88 dB,   front-right: ...
+                let volume = run("pactl", &["get-sink-volume", "@DEFAULT_SINK@"])?;
+                let percent: f32 = volume
+                    .split_whitespace()
+                    .find_map(|word| word.strip_suffix('%')?.parse().ok())
+                    .ok_or_else(|| format!("pactl printed no volume: {:?}", volume))?;
+                let mute = run("pactl", &["get-sink-mute", "@DEFAULT_SINK@"])?;
+                Ok((percent / 100.0, mute.contains("yes")))
+            }
+            Self::Wpctl => {
+                // Volume: 0.40 [MUTED]
+                let volume = run("wpctl", &["get-volume", "@DEFAULT_AUDIO_SINK@"])?;
+                let level = volume
+                    .split_whitespace()
+                    .find_map(|word| word.parse().ok())
+                    .ok_or_else(|| format!("wpctl printed no volume: {:?}", volume))?;
+                Ok((level, volume.contains("[MUTED]")))
+            }
+        }
+    }
+
+    fn set_volume(self, level: f32) -> Result<()> {
+        match self {
+            Self::Pactl => {
+                let percent = format!("{}%", (level * 100.0).round());
+                run("pactl", &["set-sink-volume", "@DEFAULT_SINK@", &percent])?;
+            }
+            Self::Wpctl => {
+                let level = format!("{:.2}", level);
+                run("wpctl", &["set-volume", "@DEFAULT_AUDIO_SINK@", &level])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mutes or unmutes the default sink, or toggles it without `muted`.
+    fn set_muted(self, muted: Option<bool>) -> Result<()> {
+        let value = match muted {
+            Some(true) => "1",
+            Some(false) => "0",
+            None => "toggle",
+        };
+        match self {
+            Self::Pactl => run("pactl", &["set-sink-mute", "@DEFAULT_SINK@", value])?,
+            Self::Wpctl => run("wpctl", &["set-mute", "@DEFAULT_AUDIO_SINK@", value])?,
+        };
+        Ok(())
+    }
+}
+
+/// Runs `program` with `args`, returning what it printed.
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
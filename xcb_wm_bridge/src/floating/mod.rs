@@ -0,0 +1,395 @@
+// Floating geometry remembered per application: once a window's configures settle,
+// qtile is asked whether it floats, and if so where it is goes in a state file under
+// its WM_CLASS, or its WM_CLASS and a title pattern from the config. A window that
+// starts floating, when mapped or toggled in qtile, is put where the last matching
+// one was, in this session or an earlier one, or else where floating.placement puts
+// it, unless it asked for a position the user gave.
+
+pub mod placement;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use x11rb::properties::{WmClass, WmSizeHints, WmSizeHintsSpecification};
+use x11rb::protocol::xproto::{ConnectionExt as _, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::{FloatingConfig, Placement};
+use crate::stack::WindowStack;
+use crate::state;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How long a window's configures must stop before it is checked.
+const SETTLE: Duration = Duration::from_millis(300);
+/// How often settled windows are looked for.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What windows share a remembered geometry by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key {
+    pub class: String,
+    /// The configured title pattern the windows matched, if any did.
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    #[serde(flatten)]
+    key: Key,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+pub struct Floating {
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: state::Atoms,
+    config: FloatingConfig,
+    path: PathBuf,
+    /// What the state file holds.
+    entries: Mutex<Vec<Entry>>,
+    /// Floating windows, by the key their geometry goes under.
+    floating: Mutex<HashMap<Window, Key>>,
+    /// Windows to check, from when, and whether they may be put where their keys
+    /// remember.
+    pending: Mutex<HashMap<Window, (Instant, bool)>>,
+    /// How many windows have cascaded.
+    cascaded: Mutex<usize>,
+}
+
+impl Floating {
+    /// Loads the remembered geometry and starts checking windows as they settle.
+    pub fn new(
+        conn: Arc<RustConnection>,
+        root: Window,
+        config: FloatingConfig,
+    ) -> Result<Arc<Self>> {
+        let atoms = state::Atoms::new(&conn)?;
+        let path = match &config.state_file {
+            Some(path) => PathBuf::from(path),
+            None => default_path().ok_or("neither XDG_STATE_HOME nor HOME is set")?,
+        };
+        let entries = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!(
+                    "Ignoring the floating geometry in {}: {}",
+                    path.display(),
+                    e
+                );
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let floating = Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            config,
+            path,
+            entries: Mutex::new(entries),
+            floating: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            cascaded: Mutex::new(0),
+        });
+        let checker = floating.clone();
+        thread::spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+            checker.check_settled();
+        });
+        Ok(floating)
+    }
+
+    /// Checks the windows mapped before the bridge started, leaving them where they
+    /// are.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            self.settle(window.window, false);
+        }
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify.
+    pub fn event(&self, event: &Event) {
+        match event {
+            Event::MapNotify(e) if !e.override_redirect => self.settle(e.window, true),
+            Event::ConfigureNotify(e) if !e.override_redirect => self.settle(e.window, true),
+            // Windows stay known while unmapped, as they are on hidden groups.
+            Event::DestroyNotify(e) => {
+                self.pending.lock().unwrap().remove(&e.window);
+                self.floating.lock().unwrap().remove(&e.window);
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks `window` once it stops changing for a while.
+    fn settle(&self, window: Window, restore: bool) {
+        let mut pending = self.pending.lock().unwrap();
+        let due = Instant::now() + SETTLE;
+        let (at, allowed) = pending.entry(window).or_insert((due, restore));
+        *at = due;
+        *allowed &= restore;
+    }
+
+    /// Drops the geometry remembered under `key`.
+    pub fn forget(&self, key: &Key) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|entry| entry.key != *key);
+        if entries.len() == before {
+            return Err(format!("No floating geometry is remembered for {:?}", key).into());
+        }
+        println!("Forgetting the floating geometry of {:?}", key);
+        self.save(&entries)
+    }
+
+    fn check_settled(&self) {
+        let now = Instant::now();
+        let settled: Vec<(Window, bool)> = {
+            let mut pending = self.pending.lock().unwrap();
+            let settled = pending
+                .iter()
+                .filter(|(_, (due, _))| *due <= now)
+                .map(|(window, (_, restore))| (*window, *restore))
+                .collect::<Vec<_>>();
+            for (window, _) in &settled {
+                pending.remove(window);
+            }
+            settled
+        };
+        for (window, restore) in settled {
+            if let Err(e) = self.check(window, restore) {
+                eprintln!("Failed to check whether window {} floats: {}", window, e);
+            }
+        }
+    }
+
+    /// Asks qtile whether `window` floats: if it started to, puts it where its key
+    /// remembers, if it may be, and while it does, remembers where it is.
+    fn check(&self, window: Window, restore: bool) -> Result<()> {
+        // Only qtile's clients can be asked about.
+        if !state::client_list(&self.conn, self.root, &self.atoms)?.contains(&window) {
+            return Ok(());
+        }
+        let floats = self.floats(window)?;
+        let known = self.floating.lock().unwrap().get(&window).cloned();
+        let key = match (floats, known) {
+            (false, known) => {
+                if known.is_some() {
+                    self.floating.lock().unwrap().remove(&window);
+                }
+                return Ok(());
+            }
+            (true, Some(key)) => key,
+            (true, None) => {
+                let Some(key) = self.key_of(window)? else {
+                    return Ok(());
+                };
+                self.floating.lock().unwrap().insert(window, key.clone());
+                let remembered = self
+                    .entries
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|entry| self.config.remember && entry.key == key)
+                    .cloned();
+                // Where it ends up is remembered once that settles.
+                if let Some(entry) = remembered.filter(|_| restore) {
+                    let reason = format!("where {:?} last floated", entry.key);
+                    return self.place(window, &entry, &reason);
+                }
+                if restore && self.config.placement != Placement::Client {
+                    if let Some(entry) = self.placed(window, &key)? {
+                        let reason = format!("by {:?} placement", self.config.placement);
+                        return self.place(window, &entry, &reason);
+                    }
+                }
+                key
+            }
+        };
+        if !self.config.remember {
+            return Ok(());
+        }
+        self.remember(window, key)
+    }
+
+    /// Where floating.placement puts `window`, unless the user gave its position.
+    fn placed(&self, window: Window, key: &Key) -> Result<Option<Entry>> {
+        let hints = WmSizeHints::get_normal_hints(&*self.conn, window)?.reply_unchecked()?;
+        let user_position =
+            hints
+                .and_then(|hints| hints.position)
+                .is_some_and(|(specification, _, _)| {
+                    matches!(specification, WmSizeHintsSpecification::UserSpecified)
+                });
+        if user_position {
+            return Ok(None);
+        }
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        let size = [i32::from(geometry.width), i32::from(geometry.height)];
+        let snapshot = state::Snapshot::read(&self.conn, self.root, &self.atoms)?;
+        let pointer = self.conn.query_pointer(self.root)?.reply()?;
+        let pointer = [i32::from(pointer.root_x), i32::from(pointer.root_y)];
+        // The output under the pointer, for UnderPointer, or else the focused group's.
+        let output = match snapshot.current {
+            Some(current) if self.config.placement != Placement::UnderPointer => {
+                snapshot.placed(current).0
+            }
+            _ => snapshot.output_at(pointer),
+        };
+        let root = self.conn.get_geometry(self.root)?.reply()?;
+        let screen = [i32::from(root.width), i32::from(root.height)];
+        let struts = placement::struts(&self.conn, self.root, screen)?;
+        let area = placement::work_area(snapshot.output_rect(output), &struts);
+        let others: Vec<placement::Rect> = snapshot
+            .clients
+            .iter()
+            .filter(|client| client.window != window && client.mapped)
+            .map(|client| client.rect)
+            .collect();
+        let cascaded = {
+            let mut cascaded = self.cascaded.lock().unwrap();
+            let before = *cascaded;
+            if self.config.placement == Placement::Cascade {
+                *cascaded += 1;
+            }
+            before
+        };
+        let position = placement::position(
+            self.config.placement,
+            size,
+            area,
+            &others,
+            pointer,
+            cascaded,
+            self.config.cascade_step,
+        );
+        Ok(position.map(|[x, y]| Entry {
+            key: key.clone(),
+            x,
+            y,
+            width: u32::from(geometry.width),
+            height: u32::from(geometry.height),
+        }))
+    }
+
+    fn floats(&self, window: Window) -> Result<bool> {
+        let window = window.to_string();
+        let command = crate::fill_template(&self.config.floats, &[("window", &window)]);
+        let output = Command::new("sh").arg("-c").arg(&command).output()?;
+        if !output.status.success() {
+            return Err(format!("{} failed", command).into());
+        }
+        // As qtile's window info has it.
+        Ok(String::from_utf8_lossy(&output.stdout).contains("'floating': True"))
+    }
+
+    /// Where `window` floats goes under: its class, with the first configured title
+    /// pattern of the class its title matches. Windows without a class are skipped.
+    fn key_of(&self, window: Window) -> Result<Option<Key>> {
+        let class = WmClass::get(&*self.conn, window)?.reply()?;
+        let class = String::from_utf8_lossy(class.class()).into_owned();
+        if class.is_empty() {
+            return Ok(None);
+        }
+        let title = state::title_of(&self.conn, window, &self.atoms)?;
+        let pattern = self
+            .config
+            .titles
+            .iter()
+            .find(|rule| rule.class == class && glob(&rule.title, &title))
+            .map(|rule| rule.title.clone());
+        Ok(Some(Key {
+            class,
+            title: pattern,
+        }))
+    }
+
+    /// Floats `window` where `entry` says, for `reason`.
+    fn place(&self, window: Window, entry: &Entry, reason: &str) -> Result<()> {
+        trace::decision(&[window], format!("Putting window {} {}", window, reason));
+        let (window, x, y) = (window.to_string(), entry.x.to_string(), entry.y.to_string());
+        let (width, height) = (entry.width.to_string(), entry.height.to_string());
+        let values = [
+            ("window", window.as_str()),
+            ("x", x.as_str()),
+            ("y", y.as_str()),
+            ("width", width.as_str()),
+            ("height", height.as_str()),
+        ];
+        crate::spawn_template(&self.config.place, &values)
+    }
+
+    /// Saves where `window` is under `key`.
+    fn remember(&self, window: Window, key: Key) -> Result<()> {
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        let entry = Entry {
+            key,
+            x: i32::from(geometry.x),
+            y: i32::from(geometry.y),
+            width: u32::from(geometry.width),
+            height: u32::from(geometry.height),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter_mut().find(|known| known.key == entry.key) {
+            Some(known)
+                if (known.x, known.y, known.width, known.height)
+                    == (entry.x, entry.y, entry.width, entry.height) =>
+            {
+                return Ok(());
+            }
+            Some(known) => *known = entry,
+            None => entries.push(entry),
+        }
+        self.save(&entries)
+    }
+
+    fn save(&self, entries: &[Entry]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(entries)?)?;
+        Ok(())
+    }
+}
+
+/// $XDG_STATE_HOME/qtilerugo/floating.json, or under ~/.local/state without it.
+fn default_path() -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+        })?;
+    Some(state.join("qtilerugo").join("floating.json"))
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of characters.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the whole text.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,294 @@
+// Where a window that starts floating goes, when nothing is remembered for it: inside
+// the work area of the output it opens on, that output less the struts docks reserve
+// along the screen's edges, by floating.placement.
+
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, MapState, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::Placement;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// x, y, width, height, in root window pixels.
+pub type Rect = [i32; 4];
+
+/// Where a window of `size` goes in `area` by `policy`, among the `others` on it, or
+/// `None` where the client asks; the `cascaded`th cascading window steps `step` down
+/// and right from the area's corner.
+pub fn position(
+    policy: Placement,
+    size: [i32; 2],
+    area: Rect,
+    others: &[Rect],
+    pointer: [i32; 2],
+    cascaded: usize,
+    step: i32,
+) -> Option<[i32; 2]> {
+    let [left, top, width, height] = area;
+    let [w, h] = size;
+    // How far the window can go right and down and still fit.
+    let max_x = left + (width - w).max(0);
+    let max_y = top + (height - h).max(0);
+    Some(match policy {
+        Placement::Client => return None,
+        Placement::Center => [left + (width - w).max(0) / 2, top + (height - h).max(0) / 2],
+        Placement::UnderPointer => [
+            (pointer[0] - w / 2).clamp(left, max_x),
+            (pointer[1] - h / 2).clamp(top, max_y),
+        ],
+        Placement::Cascade => {
+            // Starting over from the corner once a step would leave the area.
+            let columns = ((max_x - left) / step.max(1)).max(0) as usize + 1;
+            let rows = ((max_y - top) / step.max(1)).max(0) as usize + 1;
+            let n = (cascaded % columns.min(rows)) as i32;
+            [left + n * step, top + n * step]
+        }
+        Placement::Smart => smart([w, h], [left, top, max_x, max_y], others),
+    })
+}
+
+/// The position overlapping `others` least, trying the area's corner and every place
+/// next to another window's edge; the topmost, then leftmost, of the best.
+fn smart(size: [i32; 2], [left, top, max_x, max_y]: [i32; 4], others: &[Rect]) -> [i32; 2] {
+    let [w, h] = size;
+    let mut xs = vec![left, max_x];
+    let mut ys = vec![top, max_y];
+    for [x, y, width, height] in others {
+        xs.extend([x + width, x - w]);
+        ys.extend([y + height, y - h]);
+    }
+    let mut best: Option<(i64, [i32; 2])> = None;
+    for &y in &ys {
+        for &x in &xs {
+            let candidate = [x.clamp(left, max_x), y.clamp(top, max_y)];
+            let overlap: i64 = others
+                .iter()
+                .map(|other| overlap([candidate[0], candidate[1], w, h], *other))
+                .sum();
+            let better = match best {
+                None => true,
+                Some((least, [bx, by])) => {
+                    overlap < least || (overlap == least && (candidate[1], candidate[0]) < (by, bx))
+                }
+            };
+            if better {
+                best = Some((overlap, candidate));
+            }
+        }
+    }
+    best.map_or([left, top], |(_, position)| position)
+}
+
+fn overlap([ax, ay, aw, ah]: Rect, [bx, by, bw, bh]: Rect) -> i64 {
+    let width = (ax + aw).min(bx + bw) - ax.max(bx);
+    let height = (ay + ah).min(by + bh) - ay.max(by);
+    if width <= 0 || height <= 0 {
+        return 0;
+    }
+    i64::from(width) * i64::from(height)
+}
+
+/// `output` less the `struts` overlapping it, each cut off the side of the screen it
+/// is reserved along.
+pub fn work_area(output: Rect, struts: &[Strut]) -> Rect {
+    let [mut left, mut top, width, height] = output;
+    let (mut right, mut bottom) = (left + width, top + height);
+    for strut in struts {
+        if overlap(strut.rect, output) == 0 {
+            continue;
+        }
+        let [x, y, w, h] = strut.rect;
+        match strut.side {
+            Side::Left => left = left.max(x + w),
+            Side::Right => right = right.min(x),
+            Side::Top => top = top.max(y + h),
+            Side::Bottom => bottom = bottom.min(y),
+        }
+    }
+    [left, top, (right - left).max(0), (bottom - top).max(0)]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// Room reserved along an edge of the screen, in root window pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strut {
+    pub side: Side,
+    pub rect: Rect,
+}
+
+/// The struts of the mapped children of `root`, from their _NET_WM_STRUT_PARTIAL, or
+/// _NET_WM_STRUT, which reserves along the whole edge.
+pub fn struts(conn: &RustConnection, root: Window, screen: [i32; 2]) -> Result<Vec<Strut>> {
+    let atom =
+        |name: &str| -> Result<_> { Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom) };
+    let (partial, whole) = (atom("_NET_WM_STRUT_PARTIAL")?, atom("_NET_WM_STRUT")?);
+    let [sw, sh] = screen;
+    let mut struts = Vec::new();
+    for window in conn.query_tree(root)?.reply()?.children {
+        // Windows may go away while they are read.
+        let Ok(attributes) = conn.get_window_attributes(window)?.reply() else {
+            continue;
+        };
+        if attributes.map_state != MapState::VIEWABLE {
+            continue;
+        }
+        let mut values = read_cardinals(conn, window, partial, 12)?;
+        if values.len() < 12 {
+            values = read_cardinals(conn, window, whole, 4)?;
+            if values.len() < 4 {
+                continue;
+            }
+            values.extend([0, sh - 1, 0, sh - 1, 0, sw - 1, 0, sw - 1]);
+        }
+        let span = |start: i32, end: i32| (end - start + 1).max(0);
+        let reserved = [
+            (
+                Side::Left,
+                [0, values[4], values[0], span(values[4], values[5])],
+            ),
+            (
+                Side::Right,
+                [
+                    sw - values[1],
+                    values[6],
+                    values[1],
+                    span(values[6], values[7]),
+                ],
+            ),
+            (
+                Side::Top,
+                [values[8], 0, span(values[8], values[9]), values[2]],
+            ),
+            (
+                Side::Bottom,
+                [
+                    values[10],
+                    sh - values[3],
+                    span(values[10], values[11]),
+                    values[3],
+                ],
+            ),
+        ];
+        struts.extend(
+            reserved
+                .into_iter()
+                .filter(|(_, [_, _, w, h])| *w > 0 && *h > 0)
+                .map(|(side, rect)| Strut { side, rect }),
+        );
+    }
+    Ok(struts)
+}
+
+fn read_cardinals(
+    conn: &RustConnection,
+    window: Window,
+    property: u32,
+    length: u32,
+) -> Result<Vec<i32>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::CARDINAL, 0, length)?
+        .reply()?;
+    Ok(reply
+        .value32()
+        .map(|values| values.map(|value| value as i32).collect())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inside([x, y]: [i32; 2], [w, h]: [i32; 2], [left, top, width, height]: Rect) -> bool {
+        x >= left && y >= top && x + w <= left + width && y + h <= top + height
+    }
+
+    #[test]
+    fn smart_overlaps_least() {
+        let area = [0, 0, 1000, 800];
+        let others = [[0, 0, 500, 400]];
+        let at = position(Placement::Smart, [400, 300], area, &others, [0, 0], 0, 32);
+        assert_eq!(at, Some([500, 0]));
+        // Nowhere is free: the spot least covered.
+        let others = [[0, 0, 700, 800], [700, 0, 300, 500]];
+        let at = position(Placement::Smart, [400, 400], area, &others, [0, 0], 0, 32);
+        assert_eq!(at, Some([600, 400]));
+    }
+
+    #[test]
+    fn smart_takes_the_corner_of_an_empty_area() {
+        let at = position(
+            Placement::Smart,
+            [400, 300],
+            [1920, 30, 1920, 1050],
+            &[],
+            [0, 0],
+            0,
+            32,
+        );
+        assert_eq!(at, Some([1920, 30]));
+    }
+
+    #[test]
+    fn struts_shrink_the_work_area_of_their_output() {
+        let output = [0, 0, 1920, 1080];
+        let bar = Strut {
+            side: Side::Top,
+            rect: [0, 0, 1920, 30],
+        };
+        let dock = Strut {
+            side: Side::Left,
+            rect: [0, 30, 64, 1050],
+        };
+        assert_eq!(work_area(output, &[bar, dock]), [64, 30, 1856, 1050]);
+        // A bar on the other output leaves this one whole.
+        let elsewhere = Strut {
+            side: Side::Top,
+            rect: [1920, 0, 1280, 30],
+        };
+        assert_eq!(work_area(output, &[elsewhere]), output);
+    }
+
+    #[test]
+    fn cascade_stays_inside_the_output() {
+        let area = [1920, 0, 1000, 800];
+        let size = [900, 700];
+        let positions: Vec<_> = (0..10)
+            .map(|n| position(Placement::Cascade, size, area, &[], [0, 0], n, 32).unwrap())
+            .collect();
+        assert_eq!(positions[0], [1920, 0]);
+        assert_eq!(positions[1], [1952, 32]);
+        assert!(positions.iter().all(|&at| inside(at, size, area)));
+        // Back at the corner once the next step would leave it.
+        assert_eq!(positions[4], [1920, 0]);
+    }
+
+    #[test]
+    fn center_and_pointer_stay_inside_the_output() {
+        let area = [1920, 0, 1920, 1080];
+        let at = position(Placement::Center, [800, 600], area, &[], [0, 0], 0, 32);
+        assert_eq!(at, Some([2480, 240]));
+        let at = position(Placement::Center, [2400, 600], area, &[], [0, 0], 0, 32);
+        assert_eq!(at, Some([1920, 240]));
+        let at = position(
+            Placement::UnderPointer,
+            [800, 600],
+            area,
+            &[],
+            [3800, 20],
+            0,
+            32,
+        );
+        assert_eq!(at, Some([3040, 0]));
+        assert_eq!(
+            position(Placement::Client, [800, 600], area, &[], [0, 0], 0, 32),
+            None
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// Focus stealing prevention, after EWMH's _NET_WM_USER_TIME. qtile focuses windows as
+// they first map; when one of them was opened before the user last interacted with the
+// focused window, going by its user time, or the _TIME of its startup id, focus goes
+// back to that window, and the new one is marked as demanding attention instead. A
+// user time of 0 asks not to be focused at all. Dialogs of the focused window may
+// always take focus. The X side goes over a connection of its own, which watches maps
+// and focus changes on the root.
+
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _, EventMask,
+    Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{CURRENT_TIME, NONE};
+
+use crate::config::FocusStealing;
+use crate::state;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How soon after its first map qtile's focusing a window counts as its taking focus.
+const FRESH_FOR: Duration = Duration::from_secs(1);
+/// _NET_WM_STATE's action that adds a state.
+const STATE_ADD: u32 = 1;
+
+struct Atoms {
+    user_time: Atom,
+    user_time_window: Atom,
+    startup_id: Atom,
+    wm_state: Atom,
+    demands_attention: Atom,
+    state: state::Atoms,
+}
+
+/// A window that just mapped for the first time.
+struct Fresh {
+    mapped: Instant,
+    /// The window focused when it mapped.
+    focused: Option<Window>,
+}
+
+pub struct FocusGuard {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    level: FocusStealing,
+    /// Windows that have mapped, which qtile maps again whenever it shows their group.
+    seen: HashSet<Window>,
+    fresh: HashMap<Window, Fresh>,
+    focused: Option<Window>,
+}
+
+impl FocusGuard {
+    /// Connects to the X server and starts keeping new windows from taking focus as
+    /// strictly as `level` says.
+    pub fn start(level: FocusStealing) -> Result<()> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            user_time: atom("_NET_WM_USER_TIME")?,
+            user_time_window: atom("_NET_WM_USER_TIME_WINDOW")?,
+            startup_id: atom("_NET_STARTUP_ID")?,
+            wm_state: atom("_NET_WM_STATE")?,
+            demands_attention: atom("_NET_WM_STATE_DEMANDS_ATTENTION")?,
+            state: state::Atoms::new(&conn)?,
+        };
+        // Maps of top-level windows, and focus changes on the root.
+        let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+            .check()?;
+        conn.flush()?;
+
+        let seen = state::client_list(&conn, root, &atoms.state)?
+            .into_iter()
+            .collect();
+        let focused = state::active_window(&conn, root, &atoms.state)?;
+        let mut guard = Self {
+            conn,
+            root,
+            atoms,
+            level,
+            seen,
+            fresh: HashMap::new(),
+            focused,
+        };
+        thread::spawn(move || {
+            if let Err(e) = guard.watch() {
+                eprintln!("Stopped preventing focus stealing: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    fn watch(&mut self) -> Result<()> {
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => {
+                    if self.seen.insert(e.window) {
+                        let fresh = Fresh {
+                            mapped: Instant::now(),
+                            focused: self.focused,
+                        };
+                        self.fresh.insert(e.window, fresh);
+                    }
+                    Ok(())
+                }
+                Event::DestroyNotify(e) => {
+                    self.seen.remove(&e.window);
+                    self.fresh.remove(&e.window);
+                    Ok(())
+                }
+                Event::PropertyNotify(e)
+                    if e.window == self.root && e.atom == self.atoms.state.net_active_window =>
+                {
+                    self.focus_changed()
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to check a window for focus stealing: {}", e);
+            }
+        }
+    }
+
+    /// Sends focus back where it was if qtile just gave it to a new window that may
+    /// not take it.
+    fn focus_changed(&mut self) -> Result<()> {
+        self.fresh
+            .retain(|_, fresh| fresh.mapped.elapsed() < FRESH_FOR);
+        let active = state::active_window(&self.conn, self.root, &self.atoms.state)?;
+        let fresh = active.and_then(|window| Some((window, self.fresh.remove(&window)?)));
+        let Some((
+            window,
+            Fresh {
+                focused: Some(previous),
+                ..
+            },
+        )) = fresh
+        else {
+            self.focused = active;
+            return Ok(());
+        };
+        if previous == window || self.may_take_focus(window, previous)? {
+            self.focused = active;
+            return Ok(());
+        }
+        trace::decision(
+            &[window, previous],
+            format!("Keeping focus on window {} rather than new window {}", previous, window),
+        );
+        // Source 2: a pager, which qtile always follows.
+        let type_ = self.atoms.state.net_active_window;
+        let event = ClientMessageEvent::new(32, previous, type_, [2, CURRENT_TIME, 0, 0, 0]);
+        self.send_to_root(event)?;
+        let data = [STATE_ADD, self.atoms.demands_attention, 0, 2, 0];
+        self.send_to_root(ClientMessageEvent::new(
+            32,
+            window,
+            self.atoms.wm_state,
+            data,
+        ))?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Whether `window` may take focus from `previous`.
+    fn may_take_focus(&self, window: Window, previous: Window) -> Result<bool> {
+        let transient_for = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+        if transient_for == Some(previous) {
+            return Ok(true);
+        }
+        let opened = match self.user_time(window)? {
+            Some(time) => Some(time),
+            None => self.startup_time(window)?,
+        };
+        Ok(match opened {
+            Some(0) => false,
+            Some(opened) => self
+                .user_time(previous)?
+                .is_none_or(|interacted| !before(opened, interacted)),
+            None => self.level == FocusStealing::Smart,
+        })
+    }
+
+    /// When the user last interacted with `window`, which a client may keep on a
+    /// window of its own that _NET_WM_USER_TIME_WINDOW names.
+    fn user_time(&self, window: Window) -> Result<Option<u32>> {
+        let holder = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.user_time_window,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next())
+            .filter(|&holder| holder != NONE)
+            .unwrap_or(window);
+        Ok(self
+            .conn
+            .get_property(
+                false,
+                holder,
+                self.atoms.user_time,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next()))
+    }
+
+    /// The time `window`'s launch was asked for, as the _TIME at the end of its
+    /// startup id.
+    fn startup_time(&self, window: Window) -> Result<Option<u32>> {
+        let id = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.startup_id,
+                self.atoms.state.utf8_string,
+                0,
+                256,
+            )?
+            .reply()?
+            .value;
+        let id = String::from_utf8_lossy(&id);
+        Ok(id
+            .rsplit_once("_TIME")
+            .and_then(|(_, time)| time.parse().ok()))
+    }
+
+    fn send_to_root(&self, event: ClientMessageEvent) -> Result<()> {
+        let mask = EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+        self.conn.send_event(false, self.root, mask, event)?;
+        Ok(())
+    }
+}
+
+/// Whether server time `a` is before `b`, across the wrap of the 32-bit clock.
+fn before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
@@ -0,0 +1,190 @@
+// Fake fullscreen: windows of the configured classes, or toggled with
+// ToggleFakeFullscreen, stay in their tiles when they ask for fullscreen while being
+// told they got it, so a video player can "fullscreen" into half the screen. qtile
+// takes _NET_WM_STATE requests before the bridge can stop them, so it is asked to undo
+// them, and _NET_WM_STATE_FULLSCREEN is put back into the window's state for as long
+// as the client thinks it is fullscreen, whatever qtile writes there. The property
+// changes come from the PropertyChange mask the client watcher selects.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::Connection;
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, PropMode, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::config::FakeFullscreenConfig;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+struct Atoms {
+    wm_state: Atom,
+    fullscreen: Atom,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Faked {
+    /// Whether the window fakes fullscreen by ToggleFakeFullscreen, over its class.
+    chosen: Option<bool>,
+    /// Whether the client thinks it is fullscreen.
+    claimed: bool,
+}
+
+pub struct FakeFullscreen {
+    conn: Arc<RustConnection>,
+    atoms: Atoms,
+    config: FakeFullscreenConfig,
+    windows: Mutex<HashMap<Window, Faked>>,
+}
+
+impl FakeFullscreen {
+    pub fn new(conn: Arc<RustConnection>, config: FakeFullscreenConfig) -> Result<Arc<Self>> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            wm_state: atom("_NET_WM_STATE")?,
+            fullscreen: atom("_NET_WM_STATE_FULLSCREEN")?,
+        };
+        Ok(Arc::new(Self {
+            conn,
+            atoms,
+            config,
+            windows: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Has `window` fake fullscreen, or stop faking it, however its class is
+    /// configured. A window made to fake it while really fullscreen goes back into its
+    /// tile, still thinking it is fullscreen; one made to stop is told it isn't.
+    pub fn toggle(&self, window: Window) -> Result<()> {
+        let fakes = !self.fakes(window)?;
+        let faking = if fakes { "Faking" } else { "No longer faking" };
+        trace::decision(&[window], format!("{} fullscreen for window {}", faking, window));
+        let fullscreen = self.state(window)?.contains(&self.atoms.fullscreen);
+        let claimed = {
+            let mut windows = self.windows.lock().unwrap();
+            let faked = windows.entry(window).or_default();
+            faked.chosen = Some(fakes);
+            faked.claimed = fakes && fullscreen;
+            faked.claimed
+        };
+        if claimed {
+            self.unfullscreen(window)?;
+        }
+        self.write_state(window, claimed)
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify, and the property
+    /// changes of the windows.
+    pub fn event(&self, event: &Event) {
+        let result = match event {
+            Event::ClientMessage(e) if e.type_ == self.atoms.wm_state && e.format == 32 => {
+                self.state_message(e.window, e.data.as_data32())
+            }
+            Event::PropertyNotify(e) if e.atom == self.atoms.wm_state => {
+                self.state_changed(e.window)
+            }
+            Event::DestroyNotify(e) => {
+                self.windows.lock().unwrap().remove(&e.window);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to fake fullscreen: {}", e);
+        }
+    }
+
+    /// Keeps a faking window in its tile through a _NET_WM_STATE request for
+    /// fullscreen, and notes whether the client now thinks it has it.
+    fn state_message(&self, window: Window, data: [u32; 5]) -> Result<()> {
+        let [action, first, second, ..] = data;
+        if first != self.atoms.fullscreen && second != self.atoms.fullscreen {
+            return Ok(());
+        }
+        if !self.fakes(window)? {
+            return Ok(());
+        }
+        let claimed = {
+            let mut windows = self.windows.lock().unwrap();
+            let faked = windows.entry(window).or_default();
+            faked.claimed = match action {
+                0 => false,
+                1 => true,
+                _ => !faked.claimed,
+            };
+            faked.claimed
+        };
+        let goes = if claimed { "goes" } else { "leaves" };
+        trace::decision(&[window], format!("Window {} {} fullscreen in its tile", window, goes));
+        // A toggle qtile took as going fullscreen is undone too.
+        self.unfullscreen(window)?;
+        self.write_state(window, claimed)
+    }
+
+    /// Puts the fullscreen state back as the client thinks it is, if qtile changed it.
+    fn state_changed(&self, window: Window) -> Result<()> {
+        let claimed = match self.windows.lock().unwrap().get(&window) {
+            Some(faked) if faked.chosen.unwrap_or(true) => faked.claimed,
+            _ => return Ok(()),
+        };
+        self.write_state(window, claimed)
+    }
+
+    /// Whether `window` fakes fullscreen, by ToggleFakeFullscreen or its class.
+    fn fakes(&self, window: Window) -> Result<bool> {
+        let chosen = self
+            .windows
+            .lock()
+            .unwrap()
+            .get(&window)
+            .and_then(|faked| faked.chosen);
+        if let Some(chosen) = chosen {
+            return Ok(chosen);
+        }
+        let class = WmClass::get(&*self.conn, window)?.reply()?;
+        let class = String::from_utf8_lossy(class.class());
+        Ok(self.config.classes.iter().any(|known| *known == class))
+    }
+
+    fn unfullscreen(&self, window: Window) -> Result<()> {
+        let window = window.to_string();
+        crate::spawn_template(&self.config.unfullscreen, &[("window", &window)])
+    }
+
+    fn state(&self, window: Window) -> Result<Vec<Atom>> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atoms.wm_state, AtomEnum::ATOM, 0, 64)?
+            .reply()?;
+        Ok(reply.value32().into_iter().flatten().collect())
+    }
+
+    /// Leaves _NET_WM_STATE_FULLSCREEN in the window's state if `fullscreen`, keeping
+    /// the other states there; unchanged states aren't written, which would only
+    /// come back as another change.
+    fn write_state(&self, window: Window, fullscreen: bool) -> Result<()> {
+        let mut state = self.state(window)?;
+        if state.contains(&self.atoms.fullscreen) == fullscreen {
+            return Ok(());
+        }
+        state.retain(|&atom| atom != self.atoms.fullscreen);
+        if fullscreen {
+            state.push(self.atoms.fullscreen);
+        }
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms.wm_state,
+            AtomEnum::ATOM,
+            &state,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
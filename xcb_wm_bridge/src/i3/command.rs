@@ -0,0 +1,438 @@
+// RUN_COMMAND: the part of i3's command language with WM commands to run it, like
+// `workspace number 2`, `[class="firefox"] focus`, or `move container to workspace
+// web; exec alacritty`. Commands are split on `;`, and on `,` sharing the criteria in
+// brackets before them; each is one outcome in the reply.
+
+use x11rb::protocol::xproto::Window;
+
+use crate::state::Snapshot;
+use crate::WmCommand;
+
+/// Windows picked out by `[key=value ...]`. Values match whole, not as regular
+/// expressions.
+#[derive(Debug, Default)]
+struct Criteria {
+    con_id: Option<u64>,
+    id: Option<Window>,
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+}
+
+impl Criteria {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut criteria = Self::default();
+        for word in words(text) {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value in criteria, got {}", word))?;
+            let number = || -> Result<u64, String> {
+                let parsed = match value.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => value.parse(),
+                };
+                parsed.map_err(|_| format!("{} is not a number", value))
+            };
+            match key {
+                "con_id" => criteria.con_id = Some(number()?),
+                "id" => criteria.id = Some(number()? as Window),
+                "class" => criteria.class = Some(value.to_string()),
+                "instance" => criteria.instance = Some(value.to_string()),
+                "title" => criteria.title = Some(value.to_string()),
+                _ => return Err(format!("Unsupported criterion {}", key)),
+            }
+        }
+        Ok(criteria)
+    }
+
+    fn matching(&self, snapshot: &Snapshot) -> Vec<Window> {
+        snapshot
+            .clients
+            .iter()
+            .filter(|c| {
+                self.con_id.is_none_or(|id| id == u64::from(c.window))
+                    && self.id.is_none_or(|id| id == c.window)
+                    && self.class.as_ref().is_none_or(|class| *class == c.class)
+                    && self
+                        .instance
+                        .as_ref()
+                        .is_none_or(|instance| *instance == c.instance)
+                    && self.title.as_ref().is_none_or(|title| *title == c.title)
+            })
+            .map(|c| c.window)
+            .collect()
+    }
+}
+
+/// Each command in `payload`, in order, as the WM commands that run it, or why there
+/// are none.
+pub fn parse(payload: &str, snapshot: &Snapshot) -> Vec<Result<Vec<WmCommand>, String>> {
+    let mut outcomes = Vec::new();
+    for statement in split(payload, &[';', '\n']) {
+        let statement = statement.trim();
+        if statement.is_empty() {
+            continue;
+        }
+        let (targets, rest) = match statement.strip_prefix('[') {
+            Some(rest) => match split(rest, &[']']).as_slice() {
+                [criteria, rest] => match Criteria::parse(criteria) {
+                    Ok(criteria) => (Some(criteria.matching(snapshot)), *rest),
+                    Err(e) => {
+                        outcomes.push(Err(e));
+                        continue;
+                    }
+                },
+                _ => {
+                    outcomes.push(Err("Criteria are not closed with ]".to_string()));
+                    continue;
+                }
+            },
+            None => (None, statement),
+        };
+        for command in split(rest, &[',']) {
+            outcomes.push(parse_one(command.trim(), targets.as_deref(), snapshot));
+        }
+    }
+    outcomes
+}
+
+/// One command, for the windows `targets` picked, or the focused one without criteria.
+fn parse_one(
+    text: &str,
+    targets: Option<&[Window]>,
+    snapshot: &Snapshot,
+) -> Result<Vec<WmCommand>, String> {
+    // exec runs the rest as it was written.
+    if let Some(command) = text.strip_prefix("exec") {
+        let command = command.trim_start();
+        let command = command
+            .strip_prefix("--no-startup-id")
+            .unwrap_or(command)
+            .trim();
+        let command = match command.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\""),
+            None => command.to_string(),
+        };
+        if command.is_empty() {
+            return Err("exec needs a command".to_string());
+        }
+        return Ok(vec![WmCommand::Spawn(command)]);
+    }
+    let words = words(text);
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let windows = || -> Result<Vec<Window>, String> {
+        match targets {
+            Some([]) => Err("No window matches the criteria".to_string()),
+            Some(targets) => Ok(targets.to_vec()),
+            None => snapshot
+                .focused
+                .map(|window| vec![window])
+                .ok_or_else(|| "No window is focused".to_string()),
+        }
+    };
+    let single = |command: WmCommand| -> Result<Vec<WmCommand>, String> {
+        match targets {
+            Some(_) => Err(format!("{} takes no criteria here", words[0])),
+            None => Ok(vec![command]),
+        }
+    };
+    match words.as_slice() {
+        ["nop", ..] => Ok(Vec::new()),
+        ["focus"] => {
+            let window = *windows()?.first().ok_or("No window to focus")?;
+            Ok(vec![WmCommand::FocusWindow(window)])
+        }
+        ["focus", direction] => single(match *direction {
+            "left" => WmCommand::FocusLeft,
+            "right" => WmCommand::FocusRight,
+            "up" => WmCommand::FocusUp,
+            "down" => WmCommand::FocusDown,
+            "next" => WmCommand::FocusNext,
+            _ => return Err(format!("Unsupported focus target {}", direction)),
+        }),
+        ["kill", ..] => match targets {
+            Some(_) => Ok(windows()?.into_iter().map(WmCommand::CloseWindow).collect()),
+            None => Ok(vec![WmCommand::KillWindow]),
+        },
+        ["workspace", rest @ ..] => {
+            let workspace = workspace(rest, snapshot)?;
+            single(WmCommand::SwitchWorkspace(workspace))
+        }
+        ["move", rest @ ..] => {
+            let rest = match rest {
+                ["window" | "container", rest @ ..] => rest,
+                rest => rest,
+            };
+            let rest = match rest {
+                ["to", rest @ ..] => rest,
+                rest => rest,
+            };
+            match rest {
+                ["workspace", rest @ ..] => {
+                    let workspace = workspace(rest, snapshot)?;
+                    let moves = windows()?
+                        .into_iter()
+                        .map(|window| WmCommand::MoveToWorkspace {
+                            window,
+                            workspace: workspace.clone(),
+                        });
+                    Ok(moves.collect())
+                }
+                [direction, ..] => single(match *direction {
+                    "left" => WmCommand::ShuffleLeft,
+                    "right" => WmCommand::ShuffleRight,
+                    "up" => WmCommand::ShuffleUp,
+                    "down" => WmCommand::ShuffleDown,
+                    _ => return Err(format!("Unsupported move target {}", direction)),
+                }),
+                [] => Err("move needs a target".to_string()),
+            }
+        }
+        ["fullscreen"] | ["fullscreen", "toggle", ..] => single(WmCommand::ToggleFullscreen),
+        ["floating", "toggle"] => single(WmCommand::ToggleFloating),
+        ["layout", "toggle", "split"] | ["split", "toggle"] => single(WmCommand::ToggleSplit),
+        ["layout", "toggle", ..] => single(WmCommand::NextLayout),
+        // qtile grows the window toward a side, and shrinks it by growing the other.
+        ["resize", change, dimension, ..] => single(match (*change, *dimension) {
+            ("grow", "width") => WmCommand::GrowRight,
+            ("shrink", "width") => WmCommand::GrowLeft,
+            ("grow", "height") => WmCommand::GrowDown,
+            ("shrink", "height") => WmCommand::GrowUp,
+            _ => return Err(format!("Unsupported resize {} {}", change, dimension)),
+        }),
+        ["reload"] | ["restart"] => single(WmCommand::ReloadConfig),
+        ["exit"] => single(WmCommand::Shutdown),
+        [] => Err("Empty command".to_string()),
+        _ => Err(format!("Unsupported command: {}", text)),
+    }
+}
+
+/// The workspace `workspace ...` or `move ... workspace ...` names.
+fn workspace(words: &[&str], snapshot: &Snapshot) -> Result<String, String> {
+    let words: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|word| *word != "--no-auto-back-and-forth")
+        .collect();
+    match words.as_slice() {
+        ["number", number, ..] => {
+            let number = number
+                .parse()
+                .map_err(|_| format!("{} is not a workspace number", number))?;
+            Ok(snapshot.numbered(number))
+        }
+        ["next" | "next_on_output"] => snapshot
+            .neighbour(1)
+            .ok_or("No workspace is current".into()),
+        ["prev" | "prev_on_output"] => snapshot
+            .neighbour(-1)
+            .ok_or("No workspace is current".into()),
+        ["back_and_forth"] => Err("back_and_forth is not supported".to_string()),
+        [] => Err("Expected a workspace".to_string()),
+        name => Ok(name.join(" ")),
+    }
+}
+
+/// `text` split at each of `separators` outside double quotes.
+fn split<'a>(text: &'a str, separators: &[char]) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if !quoted && separators.contains(&c) => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// The words of `text`, with double quotes taken off and `\"` read as a quote.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let (mut quoted, mut escaped) = (false, false);
+    for c in text.chars() {
+        match c {
+            _ if escaped => {
+                word.get_or_insert_with(String::new).push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::Layer;
+    use crate::state::Client;
+
+    fn client(window: Window, class: &str, title: &str) -> Client {
+        Client {
+            window,
+            desktop: Some(0),
+            title: title.to_string(),
+            class: class.to_string(),
+            instance: class.to_lowercase(),
+            rect: [0, 0, 100, 100],
+            mapped: true,
+            urgent: false,
+            layer: Layer::Normal,
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        snapshot.workspaces = vec!["1:term".to_string(), "2:web".to_string(), "3".to_string()];
+        snapshot.current = Some(0);
+        snapshot.focused = Some(10);
+        snapshot.clients = vec![
+            client(10, "Alacritty", "shell"),
+            client(11, "firefox", "mail"),
+            client(12, "firefox", "news"),
+        ];
+        snapshot
+    }
+
+    fn commands(payload: &str) -> Vec<Result<Vec<WmCommand>, String>> {
+        parse(payload, &snapshot())
+    }
+
+    #[test]
+    fn statements_split_on_semicolons_and_commas() {
+        assert_eq!(
+            commands("workspace number 2; exec alacritty"),
+            [
+                Ok(vec![WmCommand::SwitchWorkspace("2:web".to_string())]),
+                Ok(vec![WmCommand::Spawn("alacritty".to_string())]),
+            ]
+        );
+        // Commas share the criteria before them.
+        assert_eq!(
+            commands("[class=\"firefox\"] kill, move to workspace 3"),
+            [
+                Ok(vec![WmCommand::CloseWindow(11), WmCommand::CloseWindow(12)]),
+                Ok(vec![
+                    WmCommand::MoveToWorkspace {
+                        window: 11,
+                        workspace: "3".to_string(),
+                    },
+                    WmCommand::MoveToWorkspace {
+                        window: 12,
+                        workspace: "3".to_string(),
+                    },
+                ]),
+            ]
+        );
+        assert!(commands(" ; ;").is_empty());
+    }
+
+    #[test]
+    fn criteria_pick_windows() {
+        assert_eq!(
+            commands("[class=firefox title=news] focus"),
+            [Ok(vec![WmCommand::FocusWindow(12)])]
+        );
+        assert_eq!(
+            commands("[id=0xb] focus"),
+            [Ok(vec![WmCommand::FocusWindow(11)])]
+        );
+        assert_eq!(
+            commands("[class=xterm] focus"),
+            [Err("No window matches the criteria".to_string())]
+        );
+        assert_eq!(
+            commands("[class=firefox focus"),
+            [Err("Criteria are not closed with ]".to_string())]
+        );
+        assert_eq!(
+            commands("[urgent=latest] focus"),
+            [Err("Unsupported criterion urgent".to_string())]
+        );
+        // Commands on the focused workspace take no criteria.
+        assert_eq!(
+            commands("[class=firefox] workspace 3"),
+            [Err("workspace takes no criteria here".to_string())]
+        );
+    }
+
+    #[test]
+    fn without_criteria_the_focused_window_is_meant() {
+        assert_eq!(
+            commands("move container to workspace web"),
+            [Ok(vec![WmCommand::MoveToWorkspace {
+                window: 10,
+                workspace: "web".to_string(),
+            }])]
+        );
+        assert_eq!(commands("kill"), [Ok(vec![WmCommand::KillWindow])]);
+    }
+
+    #[test]
+    fn workspaces_by_number_and_neighbour() {
+        assert_eq!(
+            commands("workspace number 1"),
+            [Ok(vec![WmCommand::SwitchWorkspace("1:term".to_string())])]
+        );
+        // A number no workspace has is taken as a name.
+        assert_eq!(
+            commands("workspace number 7"),
+            [Ok(vec![WmCommand::SwitchWorkspace("7".to_string())])]
+        );
+        assert_eq!(
+            commands("workspace prev"),
+            [Ok(vec![WmCommand::SwitchWorkspace("3".to_string())])]
+        );
+        assert_eq!(
+            commands("workspace --no-auto-back-and-forth \"my stuff\""),
+            [Ok(vec![WmCommand::SwitchWorkspace("my stuff".to_string())])]
+        );
+        assert!(commands("workspace back_and_forth")[0].is_err());
+    }
+
+    #[test]
+    fn exec_keeps_the_command_as_written() {
+        assert_eq!(
+            commands("exec --no-startup-id \"notify-send \\\"hi; there\\\"\""),
+            [Ok(vec![WmCommand::Spawn(
+                "notify-send \"hi; there\"".to_string()
+            )])]
+        );
+        assert_eq!(commands("exec"), [Err("exec needs a command".to_string())]);
+    }
+
+    #[test]
+    fn other_commands() {
+        assert_eq!(commands("focus left"), [Ok(vec![WmCommand::FocusLeft])]);
+        assert_eq!(commands("move up"), [Ok(vec![WmCommand::ShuffleUp])]);
+        assert_eq!(
+            commands("resize shrink width 10 px"),
+            [Ok(vec![WmCommand::GrowLeft])]
+        );
+        assert_eq!(
+            commands("layout toggle split"),
+            [Ok(vec![WmCommand::ToggleSplit])]
+        );
+        assert_eq!(commands("nop anything"), [Ok(Vec::new())]);
+        assert_eq!(
+            commands("sticky enable"),
+            [Err("Unsupported command: sticky enable".to_string())]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+// An i3-compatible IPC socket, so tools written for i3, like i3status-rust, polybar's
+// i3 module, and i3-resurrect, work with qtile. It answers GET_WORKSPACES, GET_OUTPUTS,
+// and GET_TREE from qtile's EWMH properties, runs the commands of RUN_COMMAND it has WM
+// commands for, and sends workspace, window, and output events to subscribers. State is
+// read over a connection of the shim's own, which also watches for the property
+// changes events are made from.
+
+mod command;
+mod tree;
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, PropMode, Window,
+};
+use x11rb::protocol::Event as XEvent;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::config::I3Config;
+use crate::state::{Atoms, Snapshot};
+use crate::WindowManager;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const MAGIC: &[u8] = b"i3-ipc";
+/// Messages past this many bytes close the connection.
+const MAX_MESSAGE: u32 = 1 << 20;
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+const GET_TREE: u32 = 4;
+const GET_MARKS: u32 = 5;
+const GET_BAR_CONFIG: u32 = 6;
+const GET_VERSION: u32 = 7;
+const GET_BINDING_MODES: u32 = 8;
+const GET_CONFIG: u32 = 9;
+const SEND_TICK: u32 = 10;
+const SYNC: u32 = 11;
+const GET_BINDING_STATE: u32 = 12;
+
+/// Event types have the high bit set.
+const EVENT: u32 = 1 << 31;
+const WORKSPACE_EVENT: u32 = 0;
+const OUTPUT_EVENT: u32 = 1;
+const WINDOW_EVENT: u32 = 3;
+const TICK_EVENT: u32 = 7;
+/// The events subscribers can name, by their types without the high bit.
+const EVENTS: [&str; 8] = [
+    "workspace",
+    "output",
+    "mode",
+    "window",
+    "barconfig_update",
+    "binding",
+    "shutdown",
+    "tick",
+];
+
+/// An event, by its type without the high bit, and its payload.
+#[derive(Debug, Clone)]
+struct Event {
+    kind: u32,
+    payload: Value,
+}
+
+struct Shim {
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: Atoms,
+    wm: Arc<WindowManager>,
+    events: broadcast::Sender<Event>,
+}
+
+/// Starts listening on `config.socket` and watching qtile for events, running commands
+/// through `wm`.
+pub fn start(config: &I3Config, wm: Arc<WindowManager>) -> Result<()> {
+    let (conn, screen) = RustConnection::connect(None)?;
+    let conn = Arc::new(conn);
+    let root = conn.setup().roots[screen].root;
+    let atoms = Atoms::new(&conn)?;
+
+    if Path::new(&config.socket).exists() {
+        std::fs::remove_file(&config.socket)?;
+    }
+    let listener = UnixListener::bind(&config.socket)?;
+    // Where i3 leaves its socket path, for `i3 --get-socketpath` and tools reading it.
+    let socket_path = conn.intern_atom(false, b"I3_SOCKET_PATH")?.reply()?.atom;
+    conn.change_property8(
+        PropMode::REPLACE,
+        root,
+        socket_path,
+        atoms.utf8_string,
+        config.socket.as_bytes(),
+    )?;
+    conn.flush()?;
+    println!("Listening for i3 IPC on {}", config.socket);
+
+    let (events, _) = broadcast::channel(64);
+    let shim = Arc::new(Shim {
+        conn,
+        root,
+        atoms,
+        wm,
+        events,
+    });
+    let watcher = shim.clone();
+    thread::spawn(move || {
+        if let Err(e) = watcher.watch() {
+            eprintln!("Stopped sending i3 events: {}", e);
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let shim = shim.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = shim.client(stream).await {
+                            eprintln!("i3 IPC client failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => eprintln!("i3 IPC connection error: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+impl Shim {
+    fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::read(&self.conn, self.root, &self.atoms)
+    }
+
+    /// Answers one client's messages until it goes away.
+    async fn client(self: Arc<Self>, stream: UnixStream) -> Result<()> {
+        let (mut reader, writer) = stream.into_split();
+        let writer = Arc::new(tokio::sync::Mutex::new(writer));
+        // What the client subscribed to, by event type; events start flowing with the
+        // first subscription.
+        let subscribed: Arc<Mutex<HashSet<u32>>> = Arc::default();
+        let mut forwarding = None;
+        loop {
+            let mut header = [0u8; 14];
+            match reader.read_exact(&mut header).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if &header[..6] != MAGIC {
+                return Err("not an i3 IPC message".into());
+            }
+            let length = u32::from_ne_bytes(header[6..10].try_into()?);
+            let kind = u32::from_ne_bytes(header[10..14].try_into()?);
+            if length > MAX_MESSAGE {
+                return Err(format!("a message of {} bytes is too long", length).into());
+            }
+            let mut payload = vec![0u8; length as usize];
+            reader.read_exact(&mut payload).await?;
+            let payload = String::from_utf8_lossy(&payload);
+
+            let reply = match kind {
+                SUBSCRIBE => {
+                    let reply = subscribe(&payload, &subscribed);
+                    if forwarding.is_none() {
+                        let events = self.events.subscribe();
+                        let task = forward(events, subscribed.clone(), writer.clone());
+                        forwarding = Some(tokio::spawn(task));
+                    }
+                    reply
+                }
+                kind => self.answer(kind, &payload),
+            };
+            write(&mut *writer.lock().await, kind, &reply).await?;
+        }
+        if let Some(forwarding) = forwarding {
+            forwarding.abort();
+        }
+        Ok(())
+    }
+
+    /// The reply to a message other than SUBSCRIBE.
+    fn answer(&self, kind: u32, payload: &str) -> Value {
+        let snapshot = || {
+            self.snapshot().unwrap_or_else(|e| {
+                eprintln!("Failed to read qtile's state for i3 IPC: {}", e);
+                Snapshot::default()
+            })
+        };
+        match kind {
+            RUN_COMMAND => self.run(payload, &snapshot()),
+            GET_WORKSPACES => snapshot().workspaces_reply(),
+            GET_OUTPUTS => snapshot().outputs_reply(),
+            GET_TREE => snapshot().tree(),
+            GET_MARKS => json!([]),
+            GET_BAR_CONFIG if payload.is_empty() => json!([]),
+            GET_BAR_CONFIG => json!({ "id": payload }),
+            GET_VERSION => json!({
+                "major": 4,
+                "minor": 22,
+                "patch": 0,
+                "human_readable": "4.22 (qtilerugo i3 IPC shim)",
+                "loaded_config_file_name": "",
+            }),
+            GET_BINDING_MODES => json!(["default"]),
+            GET_CONFIG => json!({ "config": "" }),
+            SEND_TICK => {
+                let _ = self.events.send(Event {
+                    kind: TICK_EVENT,
+                    payload: json!({ "first": false, "payload": payload }),
+                });
+                json!({ "success": true })
+            }
+            SYNC => json!({ "success": true }),
+            GET_BINDING_STATE => json!({ "name": "default" }),
+            _ => json!({ "success": false, "error": format!("Unknown message type {}", kind) }),
+        }
+    }
+
+    /// Runs the commands of a RUN_COMMAND, with an outcome for each.
+    fn run(&self, payload: &str, snapshot: &Snapshot) -> Value {
+        let outcomes: Vec<Value> = command::parse(payload, snapshot)
+            .into_iter()
+            .map(|commands| {
+                let result = commands.and_then(|commands| {
+                    for command in commands {
+                        println!("Running i3 command as {:?}", command);
+                        self.wm.handle_command(command).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => json!({ "success": true }),
+                    Err(e) => json!({ "success": false, "parse_error": false, "error": e }),
+                }
+            })
+            .collect();
+        Value::Array(outcomes)
+    }
+
+    /// Reads qtile's state again whenever a property it comes from changes, and sends
+    /// an event for each difference.
+    fn watch(&self) -> Result<()> {
+        let conn = &self.conn;
+        let mask = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        conn.change_window_attributes(self.root, &mask)?;
+        conn.randr_select_input(self.root, NotifyMask::SCREEN_CHANGE)?;
+        conn.flush()?;
+        let mut last = self.snapshot()?;
+        let mut watched = HashSet::new();
+        loop {
+            for client in &last.clients {
+                // Titles, groups, and urgency are properties of the clients.
+                if watched.insert(client.window) {
+                    conn.change_window_attributes(client.window, &mask)?;
+                }
+            }
+            conn.flush()?;
+            watched.retain(|window| last.client(*window).is_some());
+
+            let event = conn.wait_for_event()?;
+            let mut relevant = self.relevant(&event);
+            // A burst of changes is read once.
+            while let Some(event) = conn.poll_for_event()? {
+                relevant |= self.relevant(&event);
+            }
+            if !relevant {
+                continue;
+            }
+            let snapshot = match self.snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    eprintln!("Failed to read qtile's state for i3 events: {}", e);
+                    continue;
+                }
+            };
+            for event in changes(&last, &snapshot) {
+                // Sending only fails without subscribers.
+                let _ = self.events.send(event);
+            }
+            last = snapshot;
+        }
+    }
+
+    fn relevant(&self, event: &XEvent) -> bool {
+        let atoms = &self.atoms;
+        match event {
+            XEvent::PropertyNotify(e) => [
+                atoms.net_desktop_names,
+                atoms.net_number_of_desktops,
+                atoms.net_current_desktop,
+                atoms.net_active_window,
+                atoms.net_client_list,
+                atoms.net_wm_desktop,
+                atoms.net_wm_name,
+                AtomEnum::WM_NAME.into(),
+                AtomEnum::WM_HINTS.into(),
+            ]
+            .contains(&e.atom),
+            XEvent::RandrScreenChangeNotify(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Adds the events named in a SUBSCRIBE payload, a JSON list of names.
+fn subscribe(payload: &str, subscribed: &Mutex<HashSet<u32>>) -> Value {
+    let Ok(names) = serde_json::from_str::<Vec<String>>(payload) else {
+        return json!({ "success": false, "error": "Expected a list of event names" });
+    };
+    let mut subscribed = subscribed.lock().unwrap();
+    for name in &names {
+        match EVENTS.iter().position(|event| event == name) {
+            Some(kind) => subscribed.insert(kind as u32),
+            None => return json!({ "success": false, "error": format!("Unknown event {}", name) }),
+        };
+    }
+    json!({ "success": true })
+}
+
+/// Writes the events a client subscribed to until it goes away.
+async fn forward(
+    mut events: broadcast::Receiver<Event>,
+    subscribed: Arc<Mutex<HashSet<u32>>>,
+    writer: Arc<tokio::sync::Mutex<OwnedWriteHalf>>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            // A client too slow to keep up misses events rather than holding others up.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if !subscribed.lock().unwrap().contains(&event.kind) {
+            continue;
+        }
+        let mut writer = writer.lock().await;
+        if write(&mut writer, EVENT | event.kind, &event.payload)
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+async fn write(writer: &mut OwnedWriteHalf, kind: u32, payload: &Value) -> Result<()> {
+    let payload = payload.to_string();
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    writer.write_all(&message).await?;
+    Ok(())
+}
+
+/// The events that take qtile's state from `old` to `new`.
+fn changes(old: &Snapshot, new: &Snapshot) -> Vec<Event> {
+    let workspace = |payload: Value| Event {
+        kind: WORKSPACE_EVENT,
+        payload,
+    };
+    let window = |payload: Value| Event {
+        kind: WINDOW_EVENT,
+        payload,
+    };
+    let node = |snapshot: &Snapshot, index: Option<usize>| {
+        index.map_or(Value::Null, |index| snapshot.workspace_node(index))
+    };
+    let mut events = Vec::new();
+    if old.outputs != new.outputs {
+        events.push(Event {
+            kind: OUTPUT_EVENT,
+            payload: json!({ "change": "unspecified" }),
+        });
+    }
+    for (index, name) in new.workspaces.iter().enumerate() {
+        if !old.workspaces.contains(name) {
+            events.push(workspace(
+                json!({ "change": "init", "current": node(new, Some(index)), "old": null }),
+            ));
+        }
+    }
+    for (index, name) in old.workspaces.iter().enumerate() {
+        if !new.workspaces.contains(name) {
+            events.push(workspace(
+                json!({ "change": "empty", "current": node(old, Some(index)), "old": null }),
+            ));
+        }
+    }
+    let named =
+        |snapshot: &Snapshot, index: Option<usize>| index.map(|i| snapshot.workspaces[i].clone());
+    if named(old, old.current) != named(new, new.current) {
+        events.push(workspace(json!({
+            "change": "focus",
+            "current": node(new, new.current),
+            "old": node(old, old.current),
+        })));
+    }
+
+    for client in &new.clients {
+        let container = new.window_node(client);
+        match old.client(client.window) {
+            None => events.push(window(json!({ "change": "new", "container": container }))),
+            Some(before) => {
+                if before.title != client.title {
+                    events.push(window(json!({ "change": "title", "container": container })));
+                }
+                if before.desktop != client.desktop {
+                    events.push(window(json!({ "change": "move", "container": container })));
+                }
+                if before.urgent != client.urgent {
+                    events.push(window(
+                        json!({ "change": "urgent", "container": container }),
+                    ));
+                }
+            }
+        }
+    }
+    for client in &old.clients {
+        if new.client(client.window).is_none() {
+            let container = old.window_node(client);
+            events.push(window(json!({ "change": "close", "container": container })));
+        }
+    }
+    if old.focused != new.focused {
+        if let Some(client) = new.focused.and_then(|window| new.client(window)) {
+            let container = new.window_node(client);
+            events.push(window(json!({ "change": "focus", "container": container })));
+        }
+    }
+    events
+}
@@ -0,0 +1,176 @@
+// What the i3 shim answers with: qtile's state laid out the way i3 describes its own,
+// as outputs holding workspaces holding windows.
+
+use serde_json::{json, Value};
+
+use crate::state::{number_of, Client, Snapshot};
+
+/// Container ids of what isn't a window; windows go by their X ids, which stay below.
+const ROOT_ID: u64 = 1 << 34;
+const OUTPUT_ID: u64 = 2 << 32;
+const WORKSPACE_ID: u64 = 1 << 32;
+
+impl Snapshot {
+    /// The GET_WORKSPACES reply.
+    pub fn workspaces_reply(&self) -> Value {
+        let workspaces: Vec<Value> = (0..self.workspaces.len())
+            .map(|index| {
+                let (output, visible) = self.placed(index);
+                json!({
+                    "id": WORKSPACE_ID + index as u64,
+                    "num": number_of(&self.workspaces[index]),
+                    "name": self.workspaces[index],
+                    "visible": visible,
+                    "focused": self.current == Some(index),
+                    "urgent": self.urgent(index),
+                    "rect": rect(self.output_rect(output)),
+                    "output": self.output_name(output),
+                })
+            })
+            .collect();
+        Value::Array(workspaces)
+    }
+
+    /// The GET_OUTPUTS reply.
+    pub fn outputs_reply(&self) -> Value {
+        let outputs: Vec<Value> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| {
+                json!({
+                    "name": output.name,
+                    "active": true,
+                    "primary": output.primary,
+                    "rect": rect(output.rect),
+                    "current_workspace": self.shown_on(index),
+                })
+            })
+            .collect();
+        Value::Array(outputs)
+    }
+
+    /// The GET_TREE reply: the root, its outputs, their workspaces, and their windows.
+    pub fn tree(&self) -> Value {
+        let current = self.current.map(|i| json!(WORKSPACE_ID + i as u64));
+        let outputs: Vec<Value> = (0..self.outputs.len().max(1))
+            .map(|index| {
+                let workspaces: Vec<Value> = (0..self.workspaces.len())
+                    .filter(|&i| self.placed(i).0 == index)
+                    .map(|i| self.workspace_node(i))
+                    .collect();
+                let rect = self.output_rect(index);
+                let mut content = node(OUTPUT_ID + 0x100 + index as u64, "con", "content", rect);
+                content["focus"] = focus_of(&workspaces, current.clone());
+                content["nodes"] = Value::Array(workspaces);
+                let mut output = node(
+                    OUTPUT_ID + index as u64,
+                    "output",
+                    &self.output_name(index),
+                    rect,
+                );
+                output["layout"] = json!("output");
+                output["focus"] = json!([content["id"]]);
+                output["nodes"] = json!([content]);
+                output
+            })
+            .collect();
+        let bounds = self.outputs.iter().fold([0, 0, 0, 0], |[_, _, w, h], o| {
+            let [x, y, width, height] = o.rect;
+            [0, 0, w.max(x + width), h.max(y + height)]
+        });
+        let mut root = node(ROOT_ID, "root", "root", bounds);
+        let output = self
+            .current
+            .map(|i| json!(OUTPUT_ID + self.placed(i).0 as u64));
+        root["focus"] = focus_of(&outputs, output);
+        root["nodes"] = Value::Array(outputs);
+        root
+    }
+
+    /// Workspace `index` as a tree node, with each of its windows.
+    pub fn workspace_node(&self, index: usize) -> Value {
+        let (output, _) = self.placed(index);
+        let name = &self.workspaces[index];
+        let windows: Vec<Value> = self
+            .clients
+            .iter()
+            .filter(|c| c.desktop == Some(index))
+            .map(|c| self.window_node(c))
+            .collect();
+        let mut workspace = node(
+            WORKSPACE_ID + index as u64,
+            "workspace",
+            name,
+            self.output_rect(output),
+        );
+        workspace["num"] = json!(number_of(name));
+        workspace["output"] = json!(self.output_name(output));
+        workspace["urgent"] = json!(self.urgent(index));
+        workspace["focus"] = focus_of(&windows, self.focused.map(|w| json!(w)));
+        workspace["nodes"] = Value::Array(windows);
+        workspace
+    }
+
+    /// A client as a tree node.
+    pub fn window_node(&self, client: &Client) -> Value {
+        let mut window = node(client.window.into(), "con", &client.title, client.rect);
+        window["window"] = json!(client.window);
+        window["window_type"] = json!("normal");
+        window["window_properties"] = json!({
+            "class": client.class,
+            "instance": client.instance,
+            "title": client.title,
+        });
+        window["window_rect"] = rect([0, 0, client.rect[2], client.rect[3]]);
+        window["geometry"] = window["window_rect"].clone();
+        window["focused"] = json!(self.focused == Some(client.window));
+        window["urgent"] = json!(client.urgent);
+        window["visible"] = json!(client.mapped);
+        window
+    }
+}
+
+/// A container with what i3 gives every one, for the kind of node to fill in.
+fn node(id: u64, type_: &str, name: &str, bounds: [i32; 4]) -> Value {
+    json!({
+        "id": id,
+        "type": type_,
+        "name": name,
+        "rect": rect(bounds),
+        "window_rect": rect([0, 0, 0, 0]),
+        "deco_rect": rect([0, 0, 0, 0]),
+        "geometry": rect([0, 0, 0, 0]),
+        "window": null,
+        "urgent": false,
+        "focused": false,
+        "layout": "splith",
+        "orientation": "horizontal",
+        "border": "none",
+        "current_border_width": 0,
+        "percent": null,
+        "marks": [],
+        "fullscreen_mode": 0,
+        "sticky": false,
+        "floating": "auto_off",
+        "scratchpad_state": "none",
+        "focus": [],
+        "nodes": [],
+        "floating_nodes": [],
+    })
+}
+
+fn rect([x, y, width, height]: [i32; 4]) -> Value {
+    json!({ "x": x, "y": y, "width": width, "height": height })
+}
+
+/// The ids of `nodes`, which i3 lists most recently focused first, starting with
+/// `focused` if it is among them.
+fn focus_of(nodes: &[Value], focused: Option<Value>) -> Value {
+    let mut ids: Vec<Value> = nodes.iter().map(|n| n["id"].clone()).collect();
+    if let Some(index) = ids.iter().position(|id| Some(id) == focused.as_ref()) {
+        let id = ids.remove(index);
+        ids.insert(0, id);
+    }
+    Value::Array(ids)
+}
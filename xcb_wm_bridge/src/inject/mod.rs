@@ -0,0 +1,233 @@
+// Synthetic input through XTest, for automation and accessibility tools: SendKey
+// presses key combinations, SendText types text, and ClickAt clicks at a point on the
+// root window, all as if from the real keyboard and pointer. Keys the keymap lacks are
+// mapped onto a keycode without symbols for as long as it takes to type them. Only
+// connections from the bridge's own user may inject, which serve checks. The X side
+// goes over a connection of its own.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{self, ConnectionExt as _, Keycode, Window};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+use xkeysym::Keysym;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// The most characters one SendText types.
+const MAX_TEXT: usize = 4096;
+
+const SHIFT: u32 = 0xffe1;
+
+pub struct Injector {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl Injector {
+    pub fn new() -> Result<Self> {
+        let (conn, screen) = x11rb::connect(None)?;
+        if conn.extension_information(xtest::X11_EXTENSION_NAME)?.is_none() {
+            return Err("the X server has no XTEST extension".into());
+        }
+        let root = conn.setup().roots[screen].root;
+        Ok(Self { conn, root })
+    }
+
+    /// Presses and releases each combination in `keys`, separated by spaces, like
+    /// "Ctrl+x Ctrl+s", with modifiers named as the renderer's bindings name them.
+    pub fn send_key(&self, keys: &str) -> Result<()> {
+        let combinations = keys
+            .split_whitespace()
+            .map(parse_combination)
+            .collect::<Result<Vec<_>>>()?;
+        if combinations.is_empty() {
+            return Err(format!("{:?} has no key", keys).into());
+        }
+        println!("Sending the keys {}", keys);
+        let keymap = Keymap::read(&self.conn)?;
+        for (modifiers, keysym) in combinations {
+            self.tap(&keymap, keysym, &modifiers)?;
+        }
+        Ok(())
+    }
+
+    /// Types `text` a character at a time.
+    pub fn send_text(&self, text: &str) -> Result<()> {
+        if text.chars().count() > MAX_TEXT {
+            return Err(format!("text is typed {} characters at most", MAX_TEXT).into());
+        }
+        println!("Typing {} characters", text.chars().count());
+        let keymap = Keymap::read(&self.conn)?;
+        for c in text.chars() {
+            let keysym = match c {
+                '\n' => 0xff0d,
+                c => Keysym::from_char(c).raw(),
+            };
+            if keysym == 0 {
+                return Err(format!("{:?} has no keysym", c).into());
+            }
+            self.tap(&keymap, keysym, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Moves the pointer to `x`, `y` on the root window and clicks `button` there.
+    pub fn click_at(&self, x: i16, y: i16, button: u8) -> Result<()> {
+        println!("Clicking button {} at {}, {}", button, x, y);
+        let fake = |type_, detail| {
+            self.conn
+                .xtest_fake_input(type_, detail, CURRENT_TIME, self.root, x, y, 0)
+        };
+        fake(xproto::MOTION_NOTIFY_EVENT, 0)?;
+        fake(xproto::BUTTON_PRESS_EVENT, button)?;
+        fake(xproto::BUTTON_RELEASE_EVENT, button)?;
+        self.conn.sync()?;
+        Ok(())
+    }
+
+    /// Presses `keysym` with `modifiers`, and Shift if it takes it, and releases them.
+    fn tap(&self, keymap: &Keymap, keysym: u32, modifiers: &[u32]) -> Result<()> {
+        let (keycode, shifted, spare) = match keymap.find(keysym) {
+            Some((keycode, shifted)) => (keycode, shifted, false),
+            None => {
+                let keycode = keymap.spare().ok_or("the keymap has no spare keycode")?;
+                keymap.map(&self.conn, keycode, keysym)?;
+                (keycode, false, true)
+            }
+        };
+        let mut held = Vec::new();
+        let shift = shifted && !modifiers.contains(&SHIFT);
+        for &modifier in modifiers.iter().chain(shift.then_some(&SHIFT)) {
+            let (code, _) = keymap
+                .find(modifier)
+                .ok_or_else(|| format!("the keymap has no {:?}", Keysym::new(modifier)))?;
+            held.push(code);
+        }
+        let fake = |type_, detail| {
+            self.conn
+                .xtest_fake_input(type_, detail, CURRENT_TIME, self.root, 0, 0, 0)
+        };
+        for &code in &held {
+            fake(xproto::KEY_PRESS_EVENT, code)?;
+        }
+        fake(xproto::KEY_PRESS_EVENT, keycode)?;
+        fake(xproto::KEY_RELEASE_EVENT, keycode)?;
+        for &code in held.iter().rev() {
+            fake(xproto::KEY_RELEASE_EVENT, code)?;
+        }
+        self.conn.sync()?;
+        if spare {
+            keymap.map(&self.conn, keycode, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The keyboard's keysyms, as GetKeyboardMapping lists them.
+pub struct Keymap {
+    min_keycode: Keycode,
+    per_keycode: u8,
+    keysyms: Vec<u32>,
+}
+
+impl Keymap {
+    pub fn read(conn: &RustConnection) -> Result<Self> {
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let reply = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+        Ok(Self {
+            min_keycode,
+            per_keycode: reply.keysyms_per_keycode,
+            keysyms: reply.keysyms,
+        })
+    }
+
+    fn keycodes(&self) -> impl DoubleEndedIterator<Item = (Keycode, &[u32])> {
+        let per = usize::from(self.per_keycode.max(1));
+        let min = self.min_keycode;
+        self.keysyms
+            .chunks(per)
+            .enumerate()
+            .map(move |(i, keysyms)| (min + i as u8, keysyms))
+    }
+
+    /// The keycode typing `keysym` in the first group, and whether it takes Shift.
+    pub fn find(&self, keysym: u32) -> Option<(Keycode, bool)> {
+        self.keycodes().find_map(|(keycode, keysyms)| {
+            let position = keysyms.iter().take(2).position(|&k| k == keysym)?;
+            Some((keycode, position == 1))
+        })
+    }
+
+    /// The highest keycode without keysyms, to map one the keyboard lacks onto.
+    fn spare(&self) -> Option<Keycode> {
+        self.keycodes()
+            .rev()
+            .find(|(_, keysyms)| keysyms.iter().all(|&k| k == 0))
+            .map(|(keycode, _)| keycode)
+    }
+
+    /// Has `keycode` type `keysym` at every level, or nothing with 0.
+    fn map(&self, conn: &RustConnection, keycode: Keycode, keysym: u32) -> Result<()> {
+        let keysyms = vec![keysym; usize::from(self.per_keycode)];
+        conn.change_keyboard_mapping(1, keycode, self.per_keycode, &keysyms)?;
+        conn.sync()?;
+        Ok(())
+    }
+}
+
+/// The modifier keysyms and the key of a combination like "Super+Shift+Return".
+pub fn parse_combination(combination: &str) -> Result<(Vec<u32>, u32)> {
+    let mut parts: Vec<&str> = combination.split('+').collect();
+    let key = parts.pop().unwrap_or_default();
+    let keysym = keysym_named(key).ok_or_else(|| format!("{:?} names no key", key))?;
+    let mut modifiers = Vec::new();
+    for part in parts {
+        modifiers.push(match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => 0xffe3,
+            "alt" | "mod1" => 0xffe9,
+            "shift" => SHIFT,
+            "super" | "logo" | "mod4" => 0xffeb,
+            _ => return Err(format!("{:?} is not a modifier", part).into()),
+        });
+    }
+    Ok((modifiers, keysym))
+}
+
+/// The keysym with the XKB name `name`, like "Return" or "XF86AudioMute", or that
+/// types the character `name` is.
+fn keysym_named(name: &str) -> Option<u32> {
+    static NAMES: OnceLock<HashMap<String, u32>> = OnceLock::new();
+    let names = NAMES.get_or_init(|| {
+        // xkeysym only goes from keysyms to names, so the ranges in use are read off.
+        (0..=0xffff)
+            .chain(0x1008_0000..=0x1008_ffff)
+            .filter_map(|raw| {
+                let name = Keysym::new(raw).name()?;
+                let name = match name.strip_prefix("XF86XK_") {
+                    Some(name) => format!("XF86{}", name),
+                    None => name.strip_prefix("XK_")?.to_string(),
+                };
+                Some((name, raw))
+            })
+            .collect()
+    });
+    if let Some(&raw) = names.get(name) {
+        return Some(raw);
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Keysym::from_char(c).raw()).filter(|&raw| raw != 0),
+        _ => names
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|(_, &raw)| raw),
+    }
+}
@@ -0,0 +1,169 @@
+// Insertion points: qtile's layouts each put a new window where they like, most after
+// the focused one, some first. With insertion.point set, or SetInsertionPoint sent, the
+// bridge moves each tiled window as it first maps: right after the window focused
+// before it, to the end, first as the new master, or right after the largest tile.
+// Layout is qtile's, so each move is asked of it through a template. The X side goes
+// over a connection of its own, which watches maps and the focus.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::InsertionConfig;
+use crate::state;
+use crate::trace;
+use qtilerugo_ipc::wm::InsertionPoint;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub struct Insertion {
+    conn: RustConnection,
+    root: Window,
+    atoms: state::Atoms,
+    config: InsertionConfig,
+    point: Mutex<Option<InsertionPoint>>,
+    /// Windows mapped already, which qtile maps again on every group switch.
+    seen: Mutex<HashSet<Window>>,
+    /// The focused window, and the one focused before it.
+    focus: Mutex<(Option<Window>, Option<Window>)>,
+}
+
+impl Insertion {
+    /// Connects to the X server and starts moving new windows to the insertion point.
+    pub fn new(config: InsertionConfig) -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = state::Atoms::new(&conn)?;
+        // Maps of top-level windows, and the focus changing on the root.
+        let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+            .check()?;
+        conn.flush()?;
+
+        let seen = state::client_list(&conn, root, &atoms)?
+            .into_iter()
+            .collect();
+        let focused = state::active_window(&conn, root, &atoms)?;
+        let insertion = Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            point: Mutex::new(config.point),
+            config,
+            seen: Mutex::new(seen),
+            focus: Mutex::new((focused, None)),
+        });
+        let watcher = insertion.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped moving new windows: {}", e);
+            }
+        });
+        Ok(insertion)
+    }
+
+    /// Where new windows go from now, or where the layout puts them without `point`.
+    pub fn set(&self, point: Option<InsertionPoint>) {
+        match point {
+            Some(point) => println!("New windows go {}", describe(point)),
+            None => println!("New windows go where the layout puts them"),
+        }
+        *self.point.lock().unwrap() = point;
+    }
+
+    fn watch(&self) -> Result<()> {
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+                Event::PropertyNotify(e)
+                    if e.window == self.root && e.atom == self.atoms.net_active_window =>
+                {
+                    self.focused()
+                }
+                Event::DestroyNotify(e) => {
+                    self.seen.lock().unwrap().remove(&e.window);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to move a new window: {}", e);
+            }
+        }
+    }
+
+    fn focused(&self) -> Result<()> {
+        let window = state::active_window(&self.conn, self.root, &self.atoms)?;
+        let mut focus = self.focus.lock().unwrap();
+        if window.is_some() && window != focus.0 {
+            *focus = (window, focus.0);
+        }
+        Ok(())
+    }
+
+    /// Moves a window mapping for the first time to the insertion point.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if !self.seen.lock().unwrap().insert(window) {
+            return Ok(());
+        }
+        let Some(point) = *self.point.lock().unwrap() else {
+            return Ok(());
+        };
+        // Dialogs float, and have no place in the layout.
+        if self.transient(window)? {
+            return Ok(());
+        }
+        // qtile may have focused the window before it mapped, or not yet.
+        let before = match *self.focus.lock().unwrap() {
+            (Some(focused), previous) if focused == window => previous,
+            (focused, _) => focused,
+        };
+        if point == InsertionPoint::AfterFocused && before.is_none() {
+            return Ok(());
+        }
+        let template = match point {
+            InsertionPoint::AfterFocused => &self.config.after_focused,
+            InsertionPoint::End => &self.config.end,
+            InsertionPoint::Master => &self.config.master,
+            InsertionPoint::SwallowLargest => &self.config.swallow_largest,
+        };
+        trace::decision(
+            &[window],
+            format!("Moving new window {} {}", window, describe(point)),
+        );
+        let focused = before.unwrap_or_default().to_string();
+        let window = window.to_string();
+        crate::spawn_template(template, &[("window", &window), ("focused", &focused)])
+    }
+
+    fn transient(&self, window: Window) -> Result<bool> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(reply.value_len > 0)
+    }
+}
+
+fn describe(point: InsertionPoint) -> &'static str {
+    match point {
+        InsertionPoint::AfterFocused => "after the focused window",
+        InsertionPoint::End => "to the end",
+        InsertionPoint::Master => "first, as the master",
+        InsertionPoint::SwallowLargest => "after the largest tile",
+    }
+}
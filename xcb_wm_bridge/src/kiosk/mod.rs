@@ -0,0 +1,274 @@
+// Kiosk mode: with kiosk.enabled, the bridge runs kiosk.command and nothing else, for
+// signage and points of sale. The application's windows, those of its process, of
+// kiosk.class, or transient for one of them, are made fullscreen as they map, and any
+// other window that maps is unmapped again, as are those open at the start. qtile drops
+// its key bindings through kiosk.lock_keys, and the application is started again,
+// after kiosk.restart_delay_ms, whenever it exits. Pressing kiosk.escape, which the
+// bridge grabs, leaves kiosk mode: qtile takes its bindings back, windows map as they
+// like, and the application runs on without being restarted. The kiosk follows windows
+// on a connection of its own, on a thread of its own.
+
+use std::collections::HashSet;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use x11rb::connection::Connection;
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, GrabMode, ModMask,
+    Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::KioskConfig;
+use crate::inject;
+use crate::state;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+struct Atoms {
+    net_wm_pid: Atom,
+    state: state::Atoms,
+}
+
+pub struct Kiosk {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    config: KioskConfig,
+    /// Makes a {window} fullscreen, as outputs.fullscreen does.
+    fullscreen: String,
+    /// The application's process, while it runs.
+    pid: AtomicU32,
+    /// The application's windows.
+    windows: Mutex<HashSet<Window>>,
+    /// Until the escape combination is pressed.
+    locked: AtomicBool,
+}
+
+impl Kiosk {
+    /// Connects to the X server, locks the session down, and starts the application.
+    pub fn new(config: KioskConfig, fullscreen: String) -> Result<Arc<Self>> {
+        if config.command.trim().is_empty() {
+            return Err("kiosk mode needs a kiosk.command".into());
+        }
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = Atoms {
+            net_wm_pid: conn.intern_atom(false, b"_NET_WM_PID")?.reply()?.atom,
+            state: state::Atoms::new(&conn)?,
+        };
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_NOTIFY);
+        conn.change_window_attributes(root, &aux)?.check()?;
+
+        let kiosk = Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            config,
+            fullscreen,
+            pid: AtomicU32::new(0),
+            windows: Mutex::new(HashSet::new()),
+            locked: AtomicBool::new(true),
+        });
+        if let Some(escape) = &kiosk.config.escape {
+            kiosk.grab(escape)?;
+        }
+        kiosk.conn.flush()?;
+        if let Err(e) = crate::spawn_template(&kiosk.config.lock_keys, &[]) {
+            eprintln!("Failed to take qtile's key bindings: {}", e);
+        }
+        println!("Kiosk mode, running {}", kiosk.config.command);
+        for window in state::client_list(&kiosk.conn, root, &kiosk.atoms.state)? {
+            if let Err(e) = kiosk.mapped(window) {
+                eprintln!("Failed to lock window {} out: {}", window, e);
+            }
+        }
+
+        let runner = kiosk.clone();
+        thread::spawn(move || runner.run());
+        let watcher = kiosk.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped keeping the kiosk: {}", e);
+            }
+        });
+        Ok(kiosk)
+    }
+
+    /// Runs the application, again after it exits, until kiosk mode is left.
+    fn run(&self) {
+        let delay = Duration::from_millis(self.config.restart_delay_ms);
+        while self.locked.load(Ordering::Relaxed) {
+            match Command::new("sh")
+                .arg("-c")
+                .arg(&self.config.command)
+                .spawn()
+            {
+                Ok(mut child) => {
+                    self.pid.store(child.id(), Ordering::Relaxed);
+                    let status = child.wait();
+                    self.pid.store(0, Ordering::Relaxed);
+                    if !self.locked.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    match status {
+                        Ok(status) => eprintln!("The kiosk application exited: {}", status),
+                        Err(e) => eprintln!("Lost the kiosk application: {}", e),
+                    }
+                }
+                Err(e) => eprintln!("Failed to start the kiosk application: {}", e),
+            }
+            thread::sleep(delay);
+        }
+    }
+
+    fn watch(&self) -> Result<()> {
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect && e.event == self.root => {
+                    self.mapped(e.window)
+                }
+                Event::DestroyNotify(e) => {
+                    self.windows.lock().unwrap().remove(&e.window);
+                    Ok(())
+                }
+                Event::KeyPress(_) => self.escape(),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to keep the kiosk: {}", e);
+            }
+        }
+    }
+
+    /// Makes the application's windows fullscreen, and unmaps every other while locked.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if !self.locked.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let transient = self.transient_for(window)?;
+        if transient.is_some_and(|parent| self.windows.lock().unwrap().contains(&parent)) {
+            // The application's dialogs stay as they are.
+            self.windows.lock().unwrap().insert(window);
+            return Ok(());
+        }
+        if self.is_application(window)? {
+            self.windows.lock().unwrap().insert(window);
+            println!("Making kiosk window {} fullscreen", window);
+            let window = window.to_string();
+            return crate::spawn_template(&self.fullscreen, &[("window", &window)]);
+        }
+        println!("Unmapping window {}, outside the kiosk", window);
+        self.conn.unmap_window(window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Whether `window` is of the application's process or of kiosk.class.
+    fn is_application(&self, window: Window) -> Result<bool> {
+        let pid = self.pid.load(Ordering::Relaxed);
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.net_wm_pid,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )?
+            .reply()?;
+        // The shell running the command may have exec'd it, or started it as its child.
+        if pid != 0 {
+            if let Some(window_pid) = reply.value32().and_then(|mut values| values.next()) {
+                if window_pid == pid || parent_of(window_pid) == Some(pid) {
+                    return Ok(true);
+                }
+            }
+        }
+        let Some(class) = &self.config.class else {
+            return Ok(false);
+        };
+        Ok(match WmClass::get(&self.conn, window)?.reply_unchecked()? {
+            Some(wm_class) => wm_class.class() == class.as_bytes(),
+            None => false,
+        })
+    }
+
+    fn transient_for(&self, window: Window) -> Result<Option<Window>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    }
+
+    /// Leaves kiosk mode, giving qtile its bindings back.
+    fn escape(&self) -> Result<()> {
+        if !self.locked.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        println!("Leaving kiosk mode");
+        self.conn.ungrab_key(0u8, self.root, ModMask::ANY)?;
+        self.conn.flush()?;
+        crate::spawn_template(&self.config.unlock_keys, &[])
+    }
+
+    /// Grabs the escape combination, whatever the state of Caps Lock and Num Lock.
+    fn grab(&self, combination: &str) -> Result<()> {
+        let (modifiers, keysym) = inject::parse_combination(combination)?;
+        let keymap = inject::Keymap::read(&self.conn)?;
+        let (keycode, _) = keymap
+            .find(keysym)
+            .ok_or_else(|| format!("no key types {:?}", combination))?;
+        let mut mask = ModMask::from(0u16);
+        for modifier in modifiers {
+            mask |= match modifier {
+                0xffe1 => ModMask::SHIFT,
+                0xffe3 => ModMask::CONTROL,
+                0xffe9 => ModMask::M1,
+                _ => ModMask::M4,
+            };
+        }
+        for locks in [
+            ModMask::from(0u16),
+            ModMask::LOCK,
+            ModMask::M2,
+            ModMask::LOCK | ModMask::M2,
+        ] {
+            self.conn
+                .grab_key(
+                    true,
+                    self.root,
+                    mask | locks,
+                    keycode,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )?
+                .check()
+                .map_err(|e| format!("failed to grab {}: {:?}", combination, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The parent of process `pid`, from /proc.
+fn parent_of(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // After the command name, in parentheses that may hold anything, come the state
+    // and the parent's pid.
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
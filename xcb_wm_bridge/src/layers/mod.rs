@@ -0,0 +1,241 @@
+// Window layers: below, normal, above, and overlay, each stacked over the one before
+// whatever qtile raises. Windows get theirs from the SetLayer and ToggleAlwaysOnTop
+// commands, or from _NET_WM_STATE_ABOVE and _NET_WM_STATE_BELOW, as clients set them
+// before mapping and ask for them with _NET_WM_STATE messages, which the bridge also
+// keeps up to date for pagers. Whenever the stack changes, windows out of layer order
+// are restacked, as few as it takes, keeping their order within each layer.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConfigureWindowAux, ConnectionExt as _, PropMode, StackMode, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::stack::WindowStack;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub use qtilerugo_ipc::wm::Layer;
+
+struct Atoms {
+    wm_state: Atom,
+    above: Atom,
+    below: Atom,
+}
+
+pub struct Layers {
+    conn: Arc<RustConnection>,
+    atoms: Atoms,
+    /// Windows not in the normal layer.
+    assigned: Mutex<HashMap<Window, Layer>>,
+}
+
+impl Layers {
+    pub fn new(conn: Arc<RustConnection>) -> Result<Arc<Self>> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            wm_state: atom("_NET_WM_STATE")?,
+            above: atom("_NET_WM_STATE_ABOVE")?,
+            below: atom("_NET_WM_STATE_BELOW")?,
+        };
+        Ok(Arc::new(Self {
+            conn,
+            atoms,
+            assigned: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn layer(&self, window: Window) -> Layer {
+        let assigned = self.assigned.lock().unwrap();
+        assigned.get(&window).copied().unwrap_or_default()
+    }
+
+    /// Puts `window` in `layer` and restacks `stack` for it.
+    pub fn set(&self, window: Window, layer: Layer, stack: &WindowStack) -> Result<()> {
+        trace::decision(
+            &[window],
+            format!("Putting window {} in the {} layer", window, layer.name()),
+        );
+        {
+            let mut assigned = self.assigned.lock().unwrap();
+            if layer == Layer::Normal {
+                assigned.remove(&window);
+            } else {
+                assigned.insert(window, layer);
+            }
+        }
+        self.write_state(window, layer)?;
+        self.restack(stack)
+    }
+
+    /// Keeps `window` above others, or puts it back in the normal layer if it is.
+    pub fn toggle_above(&self, window: Window, stack: &WindowStack) -> Result<()> {
+        let layer = match self.layer(window) {
+            Layer::Above => Layer::Normal,
+            _ => Layer::Above,
+        };
+        self.set(window, layer, stack)
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify, restacking when the
+    /// stack `changed`.
+    pub fn event(&self, event: &Event, stack: &WindowStack, changed: bool) {
+        let result = match event {
+            Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+            Event::DestroyNotify(e) => {
+                self.assigned.lock().unwrap().remove(&e.window);
+                Ok(())
+            }
+            Event::ClientMessage(e) if e.type_ == self.atoms.wm_state && e.format == 32 => {
+                self.state_message(e.window, e.data.as_data32(), stack)
+            }
+            _ => Ok(()),
+        };
+        let result = match result {
+            Ok(()) if changed => self.restack(stack),
+            result => result,
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to keep windows in their layers: {}", e);
+        }
+    }
+
+    /// Puts a newly mapped window in the layer its _NET_WM_STATE asks for, unless it
+    /// was given one already.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if self.assigned.lock().unwrap().contains_key(&window) {
+            return Ok(());
+        }
+        let state = self.state(window)?;
+        let layer = if state.contains(&self.atoms.above) {
+            Layer::Above
+        } else if state.contains(&self.atoms.below) {
+            Layer::Below
+        } else {
+            return Ok(());
+        };
+        self.assigned.lock().unwrap().insert(window, layer);
+        Ok(())
+    }
+
+    /// Applies a client's _NET_WM_STATE request: an action, 0 to remove, 1 to add, or
+    /// 2 to toggle, for up to two states.
+    fn state_message(&self, window: Window, data: [u32; 5], stack: &WindowStack) -> Result<()> {
+        let [action, first, second, ..] = data;
+        let current = self.layer(window);
+        let mut layer = current;
+        for (state, wanted) in [
+            (self.atoms.above, Layer::Above),
+            (self.atoms.below, Layer::Below),
+        ] {
+            if first != state && second != state {
+                continue;
+            }
+            layer = match action {
+                0 if layer == wanted => Layer::Normal,
+                1 => wanted,
+                2 if layer == wanted => Layer::Normal,
+                2 => wanted,
+                _ => layer,
+            };
+        }
+        if layer == current {
+            return Ok(());
+        }
+        self.set(window, layer, stack)
+    }
+
+    fn state(&self, window: Window) -> Result<Vec<Atom>> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atoms.wm_state, AtomEnum::ATOM, 0, 64)?
+            .reply()?;
+        Ok(reply.value32().into_iter().flatten().collect())
+    }
+
+    /// Leaves _NET_WM_STATE_ABOVE or _NET_WM_STATE_BELOW in the window's state as
+    /// `layer` has it, keeping the states qtile keeps there.
+    fn write_state(&self, window: Window, layer: Layer) -> Result<()> {
+        let mut state = self.state(window)?;
+        state.retain(|&atom| atom != self.atoms.above && atom != self.atoms.below);
+        match layer {
+            Layer::Above | Layer::Overlay => state.push(self.atoms.above),
+            Layer::Below => state.push(self.atoms.below),
+            Layer::Normal => {}
+        }
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms.wm_state,
+            AtomEnum::ATOM,
+            &state,
+        )?;
+        Ok(())
+    }
+
+    /// Restacks the windows of `stack` that are out of layer order. Those in the
+    /// longest run already in order stay; of the rest, those wanted under the lowest
+    /// staying window go right below the window wanted above them, top down, and the
+    /// others right above the window wanted below them, bottom up.
+    fn restack(&self, stack: &WindowStack) -> Result<()> {
+        let current: Vec<Window> = stack.focusable().map(|w| w.window).collect();
+        let mut wanted = current.clone();
+        {
+            let assigned = self.assigned.lock().unwrap();
+            let layer = |window: &Window| assigned.get(window).copied().unwrap_or_default();
+            wanted.sort_by_key(layer);
+        }
+        if wanted == current {
+            return Ok(());
+        }
+        let position: HashMap<Window, usize> =
+            wanted.iter().enumerate().map(|(i, &w)| (w, i)).collect();
+        let order: Vec<usize> = current.iter().map(|w| position[w]).collect();
+        let staying = longest_increasing(&order);
+        let lowest = staying[0];
+        let under = (0..lowest).rev().map(|index| (index, index + 1, StackMode::BELOW));
+        let over = (lowest + 1..wanted.len())
+            .filter(|index| !staying.contains(index))
+            .map(|index| (index, index - 1, StackMode::ABOVE));
+        for (index, sibling, mode) in under.chain(over) {
+            let aux = ConfigureWindowAux::new()
+                .sibling(wanted[sibling])
+                .stack_mode(mode);
+            self.conn.configure_window(wanted[index], &aux)?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// The values of the longest increasing run, not necessarily adjacent, in `values`.
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // ends[k]: where the smallest last value of a run of k + 1 values is.
+    let mut ends: Vec<usize> = Vec::new();
+    let mut before: Vec<Option<usize>> = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let k = ends.partition_point(|&end| values[end] < value);
+        before[i] = k.checked_sub(1).map(|k| ends[k]);
+        if k == ends.len() {
+            ends.push(i);
+        } else {
+            ends[k] = i;
+        }
+    }
+    let mut run = Vec::new();
+    let mut at = ends.last().copied();
+    while let Some(i) = at {
+        run.push(values[i]);
+        at = before[i];
+    }
+    run.reverse();
+    run
+}
@@ -0,0 +1,222 @@
+// Layouts kept across restarts: qtile starts every group on its first layout, with
+// the ratios and margins its config gives, however they were left. With
+// layouts.remember, the bridge asks qtile for each group's layout, ratio, and margin
+// every so often and keeps them in a state file as they change, and brings them back
+// when qtile starts, or restarts in place, which gives the root a new
+// _NET_SUPPORTING_WM_CHECK window. Layout is qtile's, so both go through templates.
+// The X side goes over a connection of its own, which only reads that property.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::LayoutsConfig;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// What a group's layout is brought back with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct GroupLayout {
+    layout: String,
+    /// The share of the main pane, for layouts that have one, like MonadTall.
+    #[serde(default)]
+    ratio: Option<serde_json::Value>,
+    /// The gaps around windows: a number, or one per side.
+    #[serde(default)]
+    margin: Option<serde_json::Value>,
+}
+
+pub struct Layouts {
+    conn: RustConnection,
+    root: Window,
+    supporting_wm_check: Atom,
+    config: LayoutsConfig,
+    path: PathBuf,
+    /// What the state file holds, by group name.
+    saved: BTreeMap<String, GroupLayout>,
+    /// The _NET_SUPPORTING_WM_CHECK window of the qtile the layouts went back to.
+    restored: Option<Window>,
+}
+
+impl Layouts {
+    /// Loads the kept layouts and starts following qtile's.
+    pub fn start(config: LayoutsConfig) -> Result<()> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let supporting_wm_check = conn
+            .intern_atom(false, b"_NET_SUPPORTING_WM_CHECK")?
+            .reply()?
+            .atom;
+        let path = match &config.state_file {
+            Some(path) => PathBuf::from(path),
+            None => default_path().ok_or("neither XDG_STATE_HOME nor HOME is set")?,
+        };
+        let saved = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("Ignoring the layouts in {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        let mut layouts = Self {
+            conn,
+            root,
+            supporting_wm_check,
+            config,
+            path,
+            saved,
+            restored: None,
+        };
+        thread::spawn(move || loop {
+            if let Err(e) = layouts.poll() {
+                eprintln!("Failed to keep the layouts: {}", e);
+            }
+            thread::sleep(Duration::from_millis(layouts.config.poll_ms));
+        });
+        Ok(())
+    }
+
+    /// Brings the layouts back to a qtile that hasn't had them yet, or else keeps
+    /// what changed of them.
+    fn poll(&mut self) -> Result<()> {
+        let Some(qtile) = self.supporting_window()? else {
+            return Ok(());
+        };
+        if self.restored != Some(qtile) {
+            // Only once qtile answers, which it may not yet as it starts.
+            self.read()?;
+            self.restore()?;
+            self.restored = Some(qtile);
+            return Ok(());
+        }
+        let layouts = self.read()?;
+        if layouts == self.saved {
+            return Ok(());
+        }
+        self.saved = layouts;
+        self.save()
+    }
+
+    /// The window qtile names as its own on the root, once it runs.
+    fn supporting_window(&self) -> Result<Option<Window>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.supporting_wm_check,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(reply
+            .value32()
+            .and_then(|mut windows| windows.next())
+            .filter(|&window| window != x11rb::NONE))
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, GroupLayout>> {
+        let command = &self.config.read;
+        let output = Command::new("sh").arg("-c").arg(command).output()?;
+        if !output.status.success() {
+            return Err(format!("{} failed", command).into());
+        }
+        parse(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| format!("{} printed no layouts", command).into())
+    }
+
+    /// Switches each group qtile has back to its kept layout, waiting for each, so
+    /// the next reading finds them back.
+    fn restore(&self) -> Result<()> {
+        for (group, kept) in &self.saved {
+            println!("Group {} gets back layout {}", group, kept.layout);
+            let json = |value: &Option<serde_json::Value>| {
+                value
+                    .as_ref()
+                    .filter(|value| !value.is_null())
+                    .map_or_else(String::new, ToString::to_string)
+            };
+            let (ratio, margin) = (json(&kept.ratio), json(&kept.margin));
+            let values = [
+                ("group", group.as_str()),
+                ("layout", kept.layout.as_str()),
+                ("ratio", ratio.as_str()),
+                ("margin", margin.as_str()),
+            ];
+            let command = crate::fill_template(&self.config.restore, &values);
+            // A group qtile no longer has fails alone.
+            if !Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .status()?
+                .success()
+            {
+                eprintln!("Failed to bring back the layout of group {}", group);
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(&self.saved)?)?;
+        Ok(())
+    }
+}
+
+/// The layouts in what the read template printed: qtile's eval prints the JSON inside
+/// the tuple it replies with.
+fn parse(output: &str) -> Option<BTreeMap<String, GroupLayout>> {
+    let start = output.find('{')?;
+    let end = output.rfind('}')?;
+    serde_json::from_str(output.get(start..=end)?).ok()
+}
+
+/// $XDG_STATE_HOME/qtilerugo/layouts.json, or under ~/.local/state without it.
+fn default_path() -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+        })?;
+    Some(state.join("qtilerugo").join("layouts.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_are_read_out_of_qtiles_reply() {
+        let reply = "(True, '{\"1\": {\"layout\": \"monadtall\", \"ratio\": 0.6, \"margin\": 8}, \
+                     \"www\": {\"layout\": \"max\", \"ratio\": null, \"margin\": [4, 0, 4, 0]}}')\n";
+        let layouts = parse(reply).unwrap();
+        assert_eq!(
+            layouts["1"],
+            GroupLayout {
+                layout: "monadtall".into(),
+                ratio: Some(serde_json::json!(0.6)),
+                margin: Some(serde_json::json!(8)),
+            }
+        );
+        assert_eq!(layouts["www"].layout, "max");
+        assert_eq!(layouts["www"].ratio, None);
+        assert_eq!(layouts["www"].margin, Some(serde_json::json!([4, 0, 4, 0])));
+    }
+
+    #[test]
+    fn a_failed_eval_has_no_layouts() {
+        assert_eq!(parse("(False, 'Traceback ...')\n"), None);
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, IoSliceMut, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{ConnectionExt as _, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::clients::ClientInfo;
+use crate::config::AppearanceVariant;
+use crate::controls::Level;
+use crate::media::Track;
+use qtilerugo_ipc::encode;
+use qtilerugo_ipc::renderer::{OutputInfo, RendererCommand};
+use crate::shm::{Record, RingWriter, FLAG_BLUR, FLAG_FOCUSED, FLAG_FULLSCREEN, RECORD_UPSERT};
+use crate::stack::{StackedWindow, WindowStack};
+
+pub use qtilerugo_ipc::renderer::SOCKET as RENDERER_SOCKET;
+/// How long to wait before retrying a renderer that was not there.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pushes window stack changes to a separate rustVrender process.
+///
+/// Updates go through the renderer's shared-memory ring; when it is full the whole
+/// stack is sent as an `UpdateWindows` command on the socket instead.
+#[derive(Default)]
+pub struct RendererLink {
+    stream: Option<UnixStream>,
+    ring: Option<RingWriter>,
+    /// What the renderer was last told about each window.
+    sent: HashMap<Window, Record>,
+    /// Whether the next batch must start by clearing the renderer's windows.
+    clear: bool,
+    last_attempt: Option<Instant>,
+    /// Monitors reported with `SetOutputs` on every attach; none leaves outputs alone.
+    outputs: Vec<OutputInfo>,
+    /// Everything reported of the clients of mapped windows, sent again on every attach.
+    clients: HashMap<Window, ClientInfo>,
+    /// What the followed media player plays, sent again on every attach.
+    media: Option<Track>,
+    /// Whether launches wait for their windows, sent again on every attach.
+    busy: bool,
+    /// The theme and wallpaper of the appearance switched to, sent again on every
+    /// attach.
+    theme: Option<String>,
+    wallpaper: Option<PathBuf>,
+    /// The tiles centered windows are letterboxed in, sent again on every attach.
+    letterboxes: HashMap<Window, [i32; 4]>,
+    /// The click-through windows, sent again on every attach.
+    click_through: HashSet<Window>,
+    /// The rectangles of shaped windows, sent again on every attach.
+    shapes: HashMap<Window, Vec<[i32; 4]>>,
+    /// The windows modal dialogs are open for, sent again on every attach.
+    dimmed: HashSet<Window>,
+}
+
+impl RendererLink {
+    /// A link that has the renderer draw one output per entry of `outputs`.
+    pub fn new(outputs: Vec<OutputInfo>) -> Self {
+        Self {
+            outputs,
+            ..Self::default()
+        }
+    }
+
+    /// Sends whatever changed in `stack` since the last call.
+    pub fn sync(&mut self, stack: &WindowStack) {
+        if self.stream.is_none() && !self.connect() {
+            return;
+        }
+        if let Err(e) = self.send_stack(stack) {
+            self.lost(e.as_ref());
+        }
+    }
+
+    /// Tells the renderer whether the client of `window` answers pings, if attached.
+    pub fn report_responsive(&mut self, window: Window, responsive: bool) {
+        self.send(RendererCommand::SetResponsive { window, responsive });
+    }
+
+    /// Has the renderer show where a volume or brightness change left it; dropped while
+    /// detached, since the moment has passed by the time it attaches.
+    pub fn report_level(&mut self, level: Level) {
+        self.send(RendererCommand::SetLevel(level));
+    }
+
+    /// Tells the renderer what the followed media player plays, now if attached and
+    /// otherwise once it is.
+    pub fn report_media(&mut self, track: Option<Track>) {
+        self.media = track;
+        self.send(media_command(self.media.as_ref()));
+    }
+
+    /// Tells the renderer whether launches wait for their windows, now if attached and
+    /// otherwise once it is.
+    pub fn report_busy(&mut self, busy: bool) {
+        self.busy = busy;
+        self.send(RendererCommand::SetBusy(busy));
+    }
+
+    /// Has the renderer take on the theme and wallpaper of the appearance switched to,
+    /// now if attached and otherwise once it is.
+    pub fn report_appearance(&mut self, variant: &AppearanceVariant) {
+        if variant.theme.is_some() {
+            self.theme.clone_from(&variant.theme);
+        }
+        if variant.wallpaper.is_some() {
+            self.wallpaper.clone_from(&variant.wallpaper);
+        }
+        if let Some(theme) = &variant.theme {
+            self.send(RendererCommand::SetTheme(theme.clone()));
+        }
+        if let Some(path) = &variant.wallpaper {
+            self.send(wallpaper_command(path));
+        }
+    }
+
+    /// Has the renderer letterbox a centered window in its tile, or stop with `None`,
+    /// now if attached and otherwise once it is.
+    pub fn report_letterbox(&mut self, window: Window, tile: Option<[i32; 4]>) {
+        match tile {
+            Some(tile) => self.letterboxes.insert(window, tile),
+            None => self.letterboxes.remove(&window),
+        };
+        self.send(letterbox_command(window, tile));
+    }
+
+    /// Tells the renderer whether a window is click-through, now if attached and
+    /// otherwise once it is.
+    pub fn report_click_through(&mut self, window: Window, click_through: bool) {
+        if click_through {
+            self.click_through.insert(window);
+        } else {
+            self.click_through.remove(&window);
+        }
+        self.send(click_through_command(window, click_through));
+    }
+
+    /// Has the renderer dim a window a modal dialog is open for, or stop, now if
+    /// attached and otherwise once it is.
+    pub fn report_dimmed(&mut self, window: Window, dimmed: bool) {
+        if dimmed {
+            self.dimmed.insert(window);
+        } else {
+            self.dimmed.remove(&window);
+        }
+        self.send(dimmed_command(window, dimmed));
+    }
+
+    /// Tells the renderer the rectangles a shaped window is drawn as, or that it isn't
+    /// shaped with `None`, now if attached and otherwise once it is.
+    pub fn report_shape(&mut self, window: Window, rects: Option<Vec<[i32; 4]>>) {
+        match &rects {
+            Some(rects) => self.shapes.insert(window, rects.clone()),
+            None => self.shapes.remove(&window),
+        };
+        self.send(shape_command(window, rects.as_deref()));
+    }
+
+    /// Tells the renderer what changed of a window's client, now if attached and
+    /// otherwise once it is.
+    pub fn report_client(&mut self, info: ClientInfo) {
+        let known = self.clients.entry(info.window).or_insert_with(|| ClientInfo {
+            window: info.window,
+            ..ClientInfo::default()
+        });
+        if info.title.is_some() {
+            known.title.clone_from(&info.title);
+        }
+        if info.class.is_some() {
+            known.class.clone_from(&info.class);
+        }
+        if info.urgent.is_some() {
+            known.urgent = info.urgent;
+        }
+        if info.icon.is_some() {
+            known.icon.clone_from(&info.icon);
+        }
+        self.send(RendererCommand::SetWindowInfo(info.window_info()));
+    }
+
+    /// Drops the connection, so the next `sync` attaches again right away and sends
+    /// everything; for a renderer that was restarted.
+    pub fn detach(&mut self) {
+        self.stream = None;
+        self.ring = None;
+        self.last_attempt = None;
+    }
+
+    /// Connects and attaches to the ring, at most once per `RECONNECT_INTERVAL`.
+    fn connect(&mut self) -> bool {
+        if self
+            .last_attempt
+            .is_some_and(|at| at.elapsed() < RECONNECT_INTERVAL)
+        {
+            return false;
+        }
+        self.last_attempt = Some(Instant::now());
+        match attach() {
+            Ok((mut stream, ring)) => {
+                println!("Attached to renderer shared memory");
+                if !self.outputs.is_empty() {
+                    let command = RendererCommand::SetOutputs(self.outputs.clone());
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report outputs: {}", e);
+                    }
+                }
+                for info in self.clients.values() {
+                    let command = RendererCommand::SetWindowInfo(info.window_info());
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report window {}: {}", info.window, e);
+                        break;
+                    }
+                }
+                if self.media.is_some() {
+                    let command = media_command(self.media.as_ref());
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report the media player: {}", e);
+                    }
+                }
+                for (&window, &tile) in &self.letterboxes {
+                    let command = letterbox_command(window, Some(tile));
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report the letterbox of window {}: {}", window, e);
+                        break;
+                    }
+                }
+                for &window in &self.click_through {
+                    let command = click_through_command(window, true);
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report click-through window {}: {}", window, e);
+                        break;
+                    }
+                }
+                for &window in &self.dimmed {
+                    if let Err(e) = write_command(&mut stream, &dimmed_command(window, true)) {
+                        eprintln!("Failed to report dimmed window {}: {}", window, e);
+                        break;
+                    }
+                }
+                for (&window, rects) in &self.shapes {
+                    let command = shape_command(window, Some(rects));
+                    if let Err(e) = write_command(&mut stream, &command) {
+                        eprintln!("Failed to report the shape of window {}: {}", window, e);
+                        break;
+                    }
+                }
+                if self.busy {
+                    if let Err(e) = write_command(&mut stream, &RendererCommand::SetBusy(true)) {
+                        eprintln!("Failed to report pending launches: {}", e);
+                    }
+                }
+                if let Err(e) = write_appearance(&mut stream, self.theme.as_ref(), self.wallpaper.as_ref()) {
+                    eprintln!("Failed to report the appearance: {}", e);
+                }
+                self.stream = Some(stream);
+                self.ring = Some(ring);
+                // The renderer may hold windows from an earlier bridge.
+                self.sent.clear();
+                self.clear = true;
+                true
+            }
+            Err(e) => {
+                eprintln!("Renderer not available: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Sends `command` if attached, dropping the connection if that fails.
+    fn send(&mut self, command: RendererCommand) {
+        let Some(stream) = self.stream.as_mut() else {
+            return;
+        };
+        if let Err(e) = write_command(stream, &command) {
+            self.lost(&e);
+        }
+    }
+
+    fn lost(&mut self, e: &dyn fmt::Display) {
+        eprintln!("Lost renderer connection: {}", e);
+        self.stream = None;
+        self.ring = None;
+    }
+
+    fn send_stack(&mut self, stack: &WindowStack) -> Result<(), Box<dyn std::error::Error>> {
+        let current: HashMap<Window, Record> = stack
+            .mapped()
+            .enumerate()
+            .map(|(z, w)| (w.window, record(stack, w, z as i32)))
+            .collect();
+        // Windows map again before they are shown, and are reported again then.
+        self.clients.retain(|window, _| current.contains_key(window));
+
+        let mut records = Vec::new();
+        if self.clear {
+            records.push(Record::clear());
+        }
+        records.extend(
+            self.sent
+                .keys()
+                .filter(|id| !current.contains_key(id))
+                .map(|id| Record::remove(*id)),
+        );
+        records.extend(
+            current
+                .iter()
+                .filter(|(id, r)| self.sent.get(id) != Some(r))
+                .map(|(_, r)| *r),
+        );
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let ring = self.ring.as_mut().ok_or("not attached")?;
+        if !ring.push(&records)? {
+            // A full ring means the renderer is behind; let it catch up in one go.
+            let stream = self.stream.as_mut().ok_or("not connected")?;
+            write_command(stream, &update_windows(stack))?;
+        }
+        self.sent = current;
+        self.clear = false;
+        Ok(())
+    }
+}
+
+fn write_command(stream: &mut UnixStream, command: &RendererCommand) -> io::Result<()> {
+    stream.write_all(encode(command).as_bytes())
+}
+
+/// Sends the renderer `theme` and `wallpaper`, those that are given.
+fn write_appearance(
+    stream: &mut UnixStream,
+    theme: Option<&String>,
+    wallpaper: Option<&PathBuf>,
+) -> io::Result<()> {
+    if let Some(theme) = theme {
+        write_command(stream, &RendererCommand::SetTheme(theme.clone()))?;
+    }
+    if let Some(path) = wallpaper {
+        write_command(stream, &wallpaper_command(path))?;
+    }
+    Ok(())
+}
+
+fn wallpaper_command(path: &Path) -> RendererCommand {
+    RendererCommand::SetWallpaper {
+        output: None,
+        path: Some(path.to_path_buf()),
+    }
+}
+
+fn media_command(track: Option<&Track>) -> RendererCommand {
+    RendererCommand::SetMedia(track.map(Track::media_info))
+}
+
+fn letterbox_command(window: Window, tile: Option<[i32; 4]>) -> RendererCommand {
+    RendererCommand::SetLetterbox { window, tile }
+}
+
+fn click_through_command(window: Window, click_through: bool) -> RendererCommand {
+    RendererCommand::SetClickThrough { window, click_through }
+}
+
+fn dimmed_command(window: Window, dimmed: bool) -> RendererCommand {
+    RendererCommand::SetDimmed { window, dimmed }
+}
+
+fn shape_command(window: Window, rects: Option<&[[i32; 4]]>) -> RendererCommand {
+    let rects = rects.map(<[_]>::to_vec);
+    RendererCommand::SetWindowShape { window, rects }
+}
+
+/// Asks the renderer for its ring and maps the memfd and eventfd it sends back.
+fn attach() -> Result<(UnixStream, RingWriter), Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(RENDERER_SOCKET)?;
+    write_command(&mut stream, &RendererCommand::AttachShm)?;
+
+    let mut payload = [0u8; 1];
+    let mut iov = [IoSliceMut::new(&mut payload)];
+    let mut space = nix::cmsg_space!([RawFd; 2]);
+    let msg = recvmsg::<()>(stream.as_raw_fd(), &mut iov, Some(&mut space), MsgFlags::empty())?;
+    let mut fds = Vec::new();
+    for cmsg in msg.cmsgs()? {
+        if let ControlMessageOwned::ScmRights(received) = cmsg {
+            // SAFETY: the kernel just installed these descriptors for us.
+            fds.extend(received.into_iter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+        }
+    }
+    let mut fds = fds.into_iter();
+    let (Some(memfd), Some(doorbell)) = (fds.next(), fds.next()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "renderer sent no ring").into());
+    };
+    Ok((stream, RingWriter::attach(memfd, doorbell)?))
+}
+
+/// The ring record for a mapped window of `stack` at stack index `z`.
+fn record(stack: &WindowStack, w: &StackedWindow, z: i32) -> Record {
+    let border = w.border_width as i32;
+    let properties = &w.properties;
+    let flags = [
+        (stack.focused() == Some(w.window), FLAG_FOCUSED),
+        (properties.fullscreen, FLAG_FULLSCREEN),
+        (properties.blur, FLAG_BLUR),
+    ]
+    .into_iter()
+    .filter(|&(set, _)| set)
+    .fold(0, |flags, (_, flag)| flags | flag);
+    Record {
+        kind: RECORD_UPSERT,
+        window: w.window,
+        // The renderer places windows by their contents and draws borders outside.
+        x: w.x as i32 + border,
+        y: w.y as i32 + border,
+        width: w.width as u32,
+        height: w.height as u32,
+        border_width: w.border_width as u32,
+        z,
+        opacity: properties.opacity,
+        flags,
+        window_type: properties.window_type as u32,
+    }
+}
+
+fn update_windows(stack: &WindowStack) -> RendererCommand {
+    let windows = stack
+        .mapped()
+        .enumerate()
+        .map(|(z, w)| record(stack, w, z as i32).window_state())
+        .collect();
+    RendererCommand::UpdateWindows(windows)
+}
+
+/// The RandR monitors of `root`, as `SetOutputs` entries.
+pub fn monitors(
+    conn: &RustConnection,
+    root: Window,
+) -> Result<Vec<OutputInfo>, Box<dyn std::error::Error>> {
+    // Monitors arrived in RandR 1.5.
+    conn.randr_query_version(1, 5)?.reply()?;
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+    reply
+        .monitors
+        .iter()
+        .map(|m| {
+            let name = conn.get_atom_name(m.name)?.reply()?.name;
+            Ok(OutputInfo {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                x: m.x.into(),
+                y: m.y.into(),
+                width: m.width.into(),
+                height: m.height.into(),
+                refresh_mhz: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::mem::size_of;
+
+    use memmap2::MmapMut;
+    use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+    use x11rb::protocol::xproto::{CreateNotifyEvent, MapNotifyEvent};
+    use x11rb::protocol::Event;
+
+    use super::*;
+    use crate::shm::{Header, MAGIC, VERSION};
+    use crate::stack::Properties;
+
+    const ROOT: Window = 1;
+    const CAPACITY: usize = 16;
+
+    /// A ring as the renderer makes one, and the records published in it.
+    fn ring() -> (RingWriter, MmapMut) {
+        let memfd = File::from(memfd_create(c"test-ring", MemFdCreateFlag::empty()).unwrap());
+        memfd
+            .set_len((size_of::<Header>() + CAPACITY * size_of::<Record>()) as u64)
+            .unwrap();
+        let mut map = unsafe { MmapMut::map_mut(&memfd).unwrap() };
+        let header = map.as_mut_ptr() as *mut Header;
+        unsafe {
+            (*header).magic = MAGIC;
+            (*header).version = VERSION;
+            (*header).capacity = CAPACITY as u32;
+        }
+        let doorbell = memfd_create(c"test-doorbell", MemFdCreateFlag::empty()).unwrap();
+        let writer = RingWriter::attach(memfd.try_clone().unwrap().into(), doorbell).unwrap();
+        (writer, map)
+    }
+
+    fn published(map: &MmapMut) -> Vec<Record> {
+        let header = unsafe { &*(map.as_ptr() as *const Header) };
+        let head = header.head.load(std::sync::atomic::Ordering::Acquire) as usize;
+        let records = unsafe { (map.as_ptr() as *const Header).add(1) as *const Record };
+        (0..head).map(|i| unsafe { records.add(i).read() }).collect()
+    }
+
+    fn map_window(stack: &mut WindowStack, window: Window) {
+        stack.apply(
+            ROOT,
+            &Event::CreateNotify(CreateNotifyEvent {
+                response_type: 0,
+                sequence: 0,
+                parent: ROOT,
+                window,
+                x: 10,
+                y: 20,
+                width: 300,
+                height: 200,
+                border_width: 2,
+                override_redirect: false,
+            }),
+        );
+        stack.apply(
+            ROOT,
+            &Event::MapNotify(MapNotifyEvent {
+                response_type: 0,
+                sequence: 0,
+                event: ROOT,
+                window,
+                override_redirect: false,
+            }),
+        );
+    }
+
+    #[test]
+    fn focused_window_arrives_focused() {
+        let (writer, map) = ring();
+        let mut link = RendererLink {
+            ring: Some(writer),
+            ..RendererLink::default()
+        };
+        let mut stack = WindowStack::default();
+        map_window(&mut stack, 5);
+        map_window(&mut stack, 6);
+        stack.set_focused(Some(6));
+        let properties = Properties {
+            opacity: 0.5,
+            fullscreen: true,
+            ..Properties::default()
+        };
+        stack.set_properties(6, properties);
+        link.send_stack(&stack).unwrap();
+
+        let states: HashMap<u32, _> = published(&map)
+            .iter()
+            .map(|record| (record.window, record.window_state()))
+            .collect();
+        assert!(!states[&5].focused && !states[&5].fullscreen);
+        assert_eq!(states[&5].opacity, 1.0);
+        assert!(states[&6].focused && states[&6].fullscreen && !states[&6].blur);
+        assert_eq!(states[&6].opacity, 0.5);
+        assert_eq!((states[&6].x, states[&6].y), (12, 22));
+
+        // Moving the focus sends both windows again.
+        stack.set_focused(Some(5));
+        link.send_stack(&stack).unwrap();
+        let records = published(&map);
+        let last: HashMap<u32, _> = records[2..]
+            .iter()
+            .map(|record| (record.window, record.window_state()))
+            .collect();
+        assert!(last[&5].focused && !last[&6].focused);
+        assert_eq!(
+            update_windows(&stack),
+            RendererCommand::UpdateWindows(vec![last[&5].clone(), last[&6].clone()])
+        );
+    }
+}
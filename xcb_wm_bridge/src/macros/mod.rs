@@ -0,0 +1,163 @@
+// Macros: RecordMacro starts taking down the commands the bridge carries out, as
+// they arrive on the command socket or from i3 clients, until StopRecording keeps them
+// under the macro's name; PlayMacro runs them again in order. They are commands, not
+// keys, so a macro replays the same way whatever the bindings are. Macros are kept in
+// macros.json in the state dir, over restarts, and one that plays another plays it
+// too, a few levels deep at most.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use qtilerugo_ipc::wm::WmCommand;
+
+use crate::config::MacrosConfig;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How deep macros that play macros go.
+const MAX_DEPTH: usize = 8;
+
+/// The macro being recorded.
+struct Recording {
+    name: String,
+    commands: Vec<WmCommand>,
+}
+
+pub struct Macros {
+    path: PathBuf,
+    max_length: usize,
+    saved: Mutex<BTreeMap<String, Vec<WmCommand>>>,
+    recording: Mutex<Option<Recording>>,
+    /// How many macros are playing, inside one another.
+    depth: AtomicUsize,
+}
+
+impl Macros {
+    /// Loads the macros kept in the state dir.
+    pub fn new(config: &MacrosConfig) -> Result<Self> {
+        let path = match &config.state_file {
+            Some(path) => PathBuf::from(path),
+            None => default_path().ok_or("neither XDG_STATE_HOME nor HOME is set")?,
+        };
+        let saved = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("Ignoring the macros in {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            max_length: config.max_length,
+            saved: Mutex::new(saved),
+            recording: Mutex::new(None),
+            depth: AtomicUsize::new(0),
+        })
+    }
+
+    /// Starts recording `name`, which replaces a macro of that name once stopped.
+    pub fn record(&self, name: String) -> Result<()> {
+        if name.is_empty() {
+            return Err("macros need a name".into());
+        }
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(recording) = recording.as_ref() {
+            return Err(format!("already recording {}", recording.name).into());
+        }
+        println!("Recording macro {}", name);
+        *recording = Some(Recording {
+            name,
+            commands: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Takes down `cmd`, carried out while recording, unless it manages macros.
+    pub fn saw(&self, cmd: &WmCommand) {
+        if matches!(
+            cmd,
+            WmCommand::RecordMacro(_) | WmCommand::StopRecording | WmCommand::DeleteMacro(_)
+        ) {
+            return;
+        }
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(recording) = recording.as_mut() {
+            if recording.commands.len() < self.max_length {
+                recording.commands.push(cmd.clone());
+            }
+        }
+    }
+
+    /// Keeps the macro being recorded; returns its name and length.
+    pub fn stop(&self) -> Result<(String, usize)> {
+        let Recording { name, commands } = self
+            .recording
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("no macro is being recorded")?;
+        let length = commands.len();
+        let mut saved = self.saved.lock().unwrap();
+        saved.insert(name.clone(), commands);
+        self.save(&saved)?;
+        println!("Recorded macro {} of {} commands", name, length);
+        Ok((name, length))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let mut saved = self.saved.lock().unwrap();
+        saved
+            .remove(name)
+            .ok_or_else(|| format!("no macro is named {}", name))?;
+        self.save(&saved)
+    }
+
+    /// Runs the commands of `name` through `run`, stopping at the first that fails.
+    pub fn play(&self, name: &str, mut run: impl FnMut(WmCommand) -> Result<()>) -> Result<()> {
+        let commands = self
+            .saved
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("no macro is named {}", name))?;
+        if self.depth.fetch_add(1, Ordering::Relaxed) >= MAX_DEPTH {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            return Err(format!("macros play more than {} deep", MAX_DEPTH).into());
+        }
+        let result = commands.into_iter().try_for_each(&mut run);
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        result.map_err(|e| format!("macro {}: {}", name, e).into())
+    }
+
+    /// The macros kept, and the one being recorded, for ListMacros.
+    pub fn list(&self) -> serde_json::Value {
+        let saved = self.saved.lock().unwrap();
+        let recording = self.recording.lock().unwrap();
+        serde_json::json!({
+            "macros": *saved,
+            "recording": recording.as_ref().map(|recording| &recording.name),
+        })
+    }
+
+    fn save(&self, saved: &BTreeMap<String, Vec<WmCommand>>) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(saved)?)?;
+        Ok(())
+    }
+}
+
+/// $XDG_STATE_HOME/qtilerugo/macros.json, or under ~/.local/state without it.
+fn default_path() -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+        })?;
+    Some(state.join("qtilerugo").join("macros.json"))
+}
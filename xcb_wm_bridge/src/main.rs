@@ -1,55 +1,194 @@
+mod appearance;
+mod clients;
+mod cli;
+mod clipboard;
+mod centered;
 mod config;
+mod controls;
+mod floating;
+mod focus;
+mod fullscreen;
+mod i3;
+mod inject;
+mod insertion;
+mod kiosk;
+mod layers;
+mod layouts;
+mod link;
+mod macros;
+mod media;
+mod metrics;
+mod modal;
+mod outputs;
+mod ping;
+mod predict;
+mod profiles;
+mod query;
+mod remote;
+mod search;
+mod selection;
+mod shape;
+mod shm;
+mod stack;
+mod startup;
+mod state;
+mod tags;
+mod trace;
+mod undo;
+#[cfg(feature = "unified")]
+mod unified;
+mod wait;
+mod watchdog;
+mod xsettings;
 
-use config::Config;
+use config::{AppearanceSource, Config, FocusStealing, PingConfig};
+use stack::SharedStack;
 use x11rb::{
     connection::Connection,
-    protocol::xproto::{ConfigureWindowAux, StackMode, ConnectionExt},
+    protocol::xproto::{
+        ClientMessageEvent, ConfigureWindowAux, ConnectionExt, EventMask, InputFocus, StackMode,
+        Window,
+    },
     rust_connection::RustConnection,
+    CURRENT_TIME,
 };
 use tokio::net::UnixListener;
-use tokio::io::AsyncReadExt;
-use serde::Deserialize;
-use std::path::Path;
-use std::collections::HashMap;
-
-#[derive(Debug, Deserialize)]
-enum WmCommand {
-    FocusLeft,
-    FocusRight,
-    FocusDown,
-    FocusUp,
-    FocusNext,
-    ShuffleLeft,
-    ShuffleRight,
-    ShuffleDown,
-    ShuffleUp,
-    GrowLeft,
-    GrowRight,
-    GrowDown,
-    GrowUp,
-    Normalize,
-    ToggleSplit,
-    SpawnTerminal,
-    NextLayout,
-    KillWindow,
-    ToggleFullscreen,
-    ToggleFloating,
-    ReloadConfig,
-    Shutdown,
-    SpawnRofi,
-}
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use clap::Parser;
+use qtilerugo_ipc::wm::{Request, WmCommand};
+use qtilerugo_ipc::Decoder;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The most times `Repeat` runs a command.
+const MAX_REPEAT: u32 = 100;
+/// How long WaitForWindow waits without a timeout.
+const WAIT_TIMEOUT_MS: u64 = 30000;
 
 struct WindowManager {
-    conn: RustConnection,
+    conn: Arc<RustConnection>,
+    root: Window,
     config: Config,
+    stack: SharedStack,
+    /// Where volume and brightness changes are shown.
+    levels: Option<controls::Report>,
+    /// Announces launches; without it they run plainly.
+    startup: Option<Arc<startup::Startup>>,
+    /// Keeps the clipboard history, unless turned off.
+    clipboard: Option<Arc<clipboard::Clipboard>>,
+    /// Keeps windows stacked by layer.
+    layers: Arc<layers::Layers>,
+    /// Remembers floating geometry, unless turned off.
+    floating: Option<Arc<floating::Floating>>,
+    /// Sends synthetic input, when it is on.
+    injector: Option<inject::Injector>,
+    /// Keeps windows' tags, when tags are the workspace model.
+    tags: Option<Arc<tags::Tags>>,
+    metrics: Arc<metrics::Metrics>,
+    /// Keeps windows that fake fullscreen in their tiles.
+    fake_fullscreen: Option<Arc<fullscreen::FakeFullscreen>>,
+    /// Keeps centered windows in the middle of their tiles.
+    centered: Arc<centered::Centered>,
+    /// Shapes windows' input, where the X server can.
+    input_regions: Option<Arc<shape::InputRegions>>,
+    /// Follows the shapes of shaped windows, where the X server can.
+    shapes: Option<Arc<shape::bounding::Shapes>>,
+    /// Logs what happens to the windows asked for.
+    tracer: Option<Arc<trace::Tracer>>,
+    /// What Undo can revert.
+    undo: Arc<undo::History>,
+    /// Unless the state dir can't be found.
+    macros: Option<macros::Macros>,
+    /// Answers WaitForWindow.
+    waiters: Option<Arc<wait::Waiters>>,
+    /// Moves new windows to the insertion point; started once one is set.
+    insertion: Mutex<Option<Arc<insertion::Insertion>>>,
 }
 
 impl WindowManager {
-    fn new(conn: RustConnection, config: Config) -> Self {
-        Self { conn, config }
+    fn new(
+        conn: Arc<RustConnection>,
+        root: Window,
+        config: Config,
+        stack: SharedStack,
+        layers: Arc<layers::Layers>,
+    ) -> Self {
+        let centered = centered::Centered::new(conn.clone(), config.centered.clone());
+        let undo = undo::History::new(config.undo.history);
+        let macros = macros::Macros::new(&config.macros)
+            .map_err(|e| eprintln!("Macros are off: {}", e))
+            .ok();
+        Self {
+            conn,
+            root,
+            config,
+            stack,
+            levels: None,
+            startup: None,
+            clipboard: None,
+            layers,
+            floating: None,
+            injector: None,
+            tags: None,
+            metrics: metrics::Metrics::new(),
+            fake_fullscreen: None,
+            centered,
+            input_regions: None,
+            shapes: None,
+            tracer: None,
+            undo,
+            macros,
+            waiters: None,
+            insertion: Mutex::new(None),
+        }
     }
 
     fn handle_command(&self, cmd: WmCommand) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.run_command(cmd, false);
+        self.metrics.command(result.is_err());
+        result
+    }
+
+    /// Handles `cmd` as it arrives, taking it down for the macro being recorded once
+    /// carried out; the commands it runs in turn aren't.
+    fn run_command(&self, cmd: WmCommand, owner: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let recorded = self.macros.is_some().then(|| cmd.clone());
+        self.handle_command_from(cmd, owner)?;
+        if let (Some(macros), Some(cmd)) = (&self.macros, recorded) {
+            macros.saw(&cmd);
+        }
+        Ok(())
+    }
+
+    /// Handles `cmd`, from the bridge's own user if `owner`, who alone may send
+    /// synthetic input, and remembers it for Undo if it can be reverted.
+    fn handle_command_from(
+        &self,
+        cmd: WmCommand,
+        owner: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let WmCommand::CloseWindow(window) = cmd {
+            if self.config.undo.close_grace_ms > 0 {
+                return self.close_later(window);
+            }
+        }
+        let tags = self.tags.is_some();
+        let action = undo::Action::of(&cmd, &self.conn, self.root, &self.layers, tags)
+            .unwrap_or_else(|e| {
+                eprintln!("Not remembering {:?} for undo: {}", cmd, e);
+                None
+            });
+        self.perform(cmd, owner)?;
+        if let Some(action) = action {
+            self.undo.push(action);
+        }
+        Ok(())
+    }
+
+    /// Carries out `cmd`, as handle_command_from does, without remembering it.
+    fn perform(&self, cmd: WmCommand, owner: bool) -> Result<(), Box<dyn std::error::Error>> {
         let command_str = match cmd {
             WmCommand::FocusLeft => &self.config.commands["focus_left"],
             WmCommand::FocusRight => &self.config.commands["focus_right"],
@@ -74,12 +213,226 @@ impl WindowManager {
             WmCommand::ReloadConfig => &self.config.commands["reload_config"],
             WmCommand::Shutdown => &self.config.commands["shutdown"],
             WmCommand::SpawnRofi => &self.config.commands["spawn_rofi"],
+            WmCommand::KillClient(window) => {
+                trace::decision(&[window], format!("Killing the client of window {}", window));
+                self.conn.kill_client(window)?;
+                self.conn.flush()?;
+                return Ok(());
+            }
+            WmCommand::SwitchWorkspace(workspace) if self.tags.is_some() => {
+                let tags = self.tags()?;
+                return tags.view(tags.named(&workspace)?);
+            }
+            WmCommand::MoveToWorkspace { window, workspace } if self.tags.is_some() => {
+                let tags = self.tags()?;
+                return tags.set(window, tags.named(&workspace)?);
+            }
+            WmCommand::ViewTag(mask) => return self.tags()?.view(mask),
+            WmCommand::ToggleWindowTag(number) => {
+                return self.tags()?.toggle(self.focused_window()?, number)
+            }
+            WmCommand::SwitchWorkspace(workspace) => {
+                let command = &self.config.workspaces.switch;
+                return spawn_template(command, &[("workspace", &workspace)]);
+            }
+            WmCommand::MoveToWorkspace { window, workspace } => {
+                let message = format!("Moving window {} to workspace {}", window, workspace);
+                trace::decision(&[window], message);
+                let command = &self.config.workspaces.move_window;
+                let window = window.to_string();
+                return spawn_template(command, &[("window", &window), ("workspace", &workspace)]);
+            }
+            // Through EWMH, so qtile switches to the window's group and focuses it, or
+            // closes it the way it closes any window.
+            WmCommand::FocusWindow(window) => {
+                trace::decision(&[window], format!("Activating window {}", window));
+                // Source 2: a pager, acting for the user.
+                return self.send_to_root(window, "_NET_ACTIVE_WINDOW", [2, CURRENT_TIME, 0, 0, 0]);
+            }
+            WmCommand::CloseWindow(window) => {
+                trace::decision(&[window], format!("Closing window {}", window));
+                return self.send_to_root(window, "_NET_CLOSE_WINDOW", [CURRENT_TIME, 2, 0, 0, 0]);
+            }
+            WmCommand::Spawn(command) => return self.launch(&command),
+            WmCommand::VolumeUp => return self.show_level(controls::change_volume(&self.config.controls, 1)),
+            WmCommand::VolumeDown => return self.show_level(controls::change_volume(&self.config.controls, -1)),
+            WmCommand::VolumeMute => return self.show_level(controls::toggle_mute(&self.config.controls)),
+            WmCommand::BrightnessUp => return self.show_level(controls::change_brightness(&self.config.controls, 1)),
+            WmCommand::BrightnessDown => return self.show_level(controls::change_brightness(&self.config.controls, -1)),
+            WmCommand::MediaPlayPause => return media::send(&self.config.media, media::Action::PlayPause),
+            WmCommand::MediaNext => return media::send(&self.config.media, media::Action::Next),
+            WmCommand::MediaPrevious => return media::send(&self.config.media, media::Action::Previous),
+            WmCommand::Query { .. }
+            | WmCommand::ListClipboard
+            | WmCommand::GetMetrics
+            | WmCommand::GetUndoHistory
+            | WmCommand::ListMacros
+            | WmCommand::WaitForWindow { .. }
+            | WmCommand::SearchWindows(_) => {
+                return Err("queries are answered on the command socket".into())
+            }
+            WmCommand::SelectClipboard(index) => {
+                let clipboard = self.clipboard.as_ref().ok_or("the clipboard history is off")?;
+                return clipboard.select(index);
+            }
+            WmCommand::ToggleAlwaysOnTop => {
+                let window = self.focused_window()?;
+                return self.layers.toggle_above(window, &self.stack.lock().unwrap());
+            }
+            WmCommand::SetLayer(layer) => {
+                let window = self.focused_window()?;
+                return self.layers.set(window, layer, &self.stack.lock().unwrap());
+            }
+            WmCommand::ForgetFloating { class, title } => {
+                let floating = self.floating.as_ref().ok_or("floating geometry isn't remembered")?;
+                return floating.forget(&floating::Key { class, title });
+            }
+            WmCommand::ToggleFakeFullscreen => {
+                let fake = self.fake_fullscreen.as_ref().ok_or("fullscreen can't be faked")?;
+                return fake.toggle(self.focused_window()?);
+            }
+            WmCommand::ToggleCentered => return self.centered.toggle(self.focused_window()?),
+            WmCommand::SetClickThrough { window, click_through } => {
+                let window = self.window_or_focused(window)?;
+                return self.input_regions()?.set_click_through(window, click_through);
+            }
+            WmCommand::SetInputRegion { window, rects } => {
+                let window = self.window_or_focused(window)?;
+                return self.input_regions()?.set(window, rects);
+            }
+            WmCommand::Repeat { count, command } => {
+                if count > MAX_REPEAT {
+                    return Err(format!("{} repeats are more than {}", count, MAX_REPEAT).into());
+                }
+                // Stopping at the first failure, which later runs would likely repeat.
+                for _ in 0..count {
+                    self.handle_command_from((*command).clone(), owner)?;
+                }
+                return Ok(());
+            }
+            WmCommand::SendKey(keys) => return self.injector(owner)?.send_key(&keys),
+            WmCommand::SendText(text) => return self.injector(owner)?.send_text(&text),
+            WmCommand::ClickAt { x, y, button } => {
+                return self.injector(owner)?.click_at(x, y, button.unwrap_or(1))
+            }
+            WmCommand::Undo => return self.undo(),
+            WmCommand::RecordMacro(name) => return self.macros()?.record(name),
+            WmCommand::StopRecording => return self.macros()?.stop().map(|_| ()),
+            WmCommand::PlayMacro(name) => {
+                return self
+                    .macros()?
+                    .play(&name, |cmd| self.handle_command_from(cmd, owner))
+            }
+            WmCommand::DeleteMacro(name) => return self.macros()?.delete(&name),
+            WmCommand::FocusSearchResult { query, index } => {
+                let snapshot = self.snapshot()?;
+                let found = search::search(&snapshot, &query);
+                let result = found.get(index).ok_or_else(|| {
+                    format!("{:?} has {} results, not {}", query, found.len(), index + 1)
+                })?;
+                // Focused as FocusWindow focuses it, to be undone the same.
+                return self.handle_command_from(WmCommand::FocusWindow(result.client.window), owner);
+            }
+            WmCommand::LaunchProfile(name) => {
+                let profile = self.config.profiles.get(&name).ok_or_else(|| format!("no profile is named {}", name))?;
+                return profiles::launch(name, profile.clone(), self.profile_hooks());
+            }
+            WmCommand::TraceWindow { window, on } => {
+                let window = self.window_or_focused(window)?;
+                return self.tracer()?.set(window, on);
+            }
+            WmCommand::SetInsertionPoint(point) => {
+                let mut insertion = self.insertion.lock().unwrap();
+                match insertion.as_ref() {
+                    Some(insertion) => insertion.set(point),
+                    // Without a point there is nothing to move windows to yet.
+                    None if point.is_none() => {}
+                    None => {
+                        let started = insertion::Insertion::new(self.config.insertion.clone())?;
+                        started.set(point);
+                        *insertion = Some(started);
+                    }
+                }
+                return Ok(());
+            }
         };
 
         println!("Executing command: {}", command_str);
+        self.metrics.layout_requested();
         self.execute_command(command_str)
     }
 
+    /// What a profile launches, moves, lays out, and switches with: startup
+    /// notification and tags when they are on, and the templates otherwise.
+    fn profile_hooks(&self) -> profiles::Hooks {
+        let startup = self.startup.clone();
+        let tags = self.tags.clone();
+        let move_tags = self.tags.clone();
+        let switch = self.config.workspaces.switch.clone();
+        let move_window = self.config.workspaces.move_window.clone();
+        let set_layout = self.config.outputs.set_layout.clone();
+        profiles::Hooks {
+            spawn: Box::new(move |command| match &startup {
+                Some(startup) => startup.spawn(command),
+                None => spawn_template(command, &[]),
+            }),
+            move_window: Box::new(move |window, workspace| match &move_tags {
+                Some(tags) => tags.set(window, tags.named(workspace)?),
+                None => {
+                    let window = window.to_string();
+                    spawn_template(&move_window, &[("window", &window), ("workspace", workspace)])
+                }
+            }),
+            set_layout: Box::new(move |group, layout| {
+                spawn_template(&set_layout, &[("group", group), ("layout", layout)])
+            }),
+            switch: Box::new(move |workspace| match &tags {
+                Some(tags) => tags.view(tags.named(workspace)?),
+                None => spawn_template(&switch, &[("workspace", workspace)]),
+            }),
+        }
+    }
+
+    fn macros(&self) -> Result<&macros::Macros, Box<dyn std::error::Error>> {
+        Ok(self.macros.as_ref().ok_or("macros are off")?)
+    }
+
+    fn input_regions(&self) -> Result<&shape::InputRegions, Box<dyn std::error::Error>> {
+        Ok(self.input_regions.as_deref().ok_or("input regions can't be set")?)
+    }
+
+    /// `window`, or the focused window without one.
+    fn window_or_focused(&self, window: Option<u32>) -> Result<Window, Box<dyn std::error::Error>> {
+        match window {
+            Some(window) => Ok(window),
+            None => self.focused_window(),
+        }
+    }
+
+    fn tracer(&self) -> Result<&trace::Tracer, Box<dyn std::error::Error>> {
+        Ok(self.tracer.as_deref().ok_or("windows can't be traced")?)
+    }
+
+    fn tags(&self) -> Result<&tags::Tags, Box<dyn std::error::Error>> {
+        Ok(self.tags.as_deref().ok_or("tags aren't the workspace model")?)
+    }
+
+    /// What sends synthetic input for the bridge's own user.
+    fn injector(&self, owner: bool) -> Result<&inject::Injector, Box<dyn std::error::Error>> {
+        if !owner {
+            return Err("synthetic input is only taken from the bridge's own user".into());
+        }
+        Ok(self.injector.as_ref().ok_or("synthetic input is off")?)
+    }
+
+    /// Runs `command` in a shell, with startup notification if it is on.
+    fn launch(&self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.startup {
+            Some(startup) => startup.spawn(command),
+            None => spawn_template(command, &[]),
+        }
+    }
+
     fn execute_command(&self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
         match command {
             "move_focus -x -1" => {
@@ -90,9 +443,13 @@ impl WindowManager {
                 println!("Focusing right");
                 // Add logic to focus right
             },
+            "move_focus next" => {
+                println!("Focusing next window");
+                self.focus_next()?;
+            },
             "spawn alacritty" => {
                 println!("Spawning terminal");
-                std::process::Command::new("alacritty").spawn()?;
+                self.launch("alacritty")?;
             },
             "close_window" => {
                 println!("Killing window");
@@ -102,75 +459,839 @@ impl WindowManager {
             },
             _ => println!("Unknown command: {}", command),
         }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Answers a command with a reply, for the command socket.
+    fn answer(&self, cmd: WmCommand) -> serde_json::Value {
+        match cmd {
+            WmCommand::Query { domain, filters } => self.query(domain, &filters),
+            WmCommand::ListClipboard => match &self.clipboard {
+                Some(clipboard) => {
+                    let entries = clipboard.entries().into_iter().enumerate();
+                    let entries: Vec<serde_json::Value> = entries
+                        .map(|(index, entry)| {
+                            serde_json::json!({
+                                "index": index,
+                                "text": entry.text,
+                                "selection": entry.selection,
+                            })
+                        })
+                        .collect();
+                    serde_json::Value::Array(entries)
+                }
+                None => serde_json::json!({ "error": "The clipboard history is off" }),
+            },
+            WmCommand::GetMetrics => serde_json::Value::String(self.metrics()),
+            WmCommand::GetUndoHistory => serde_json::json!(self.undo.list()),
+            WmCommand::ListMacros => match &self.macros {
+                Some(macros) => macros.list(),
+                None => serde_json::json!({ "error": "Macros are off" }),
+            },
+            WmCommand::SearchWindows(query) => match self.snapshot() {
+                Ok(snapshot) => search::results(&snapshot, &query),
+                Err(e) => serde_json::json!({ "error": e }),
+            },
+            cmd => serde_json::json!({ "error": format!("{:?} has no reply", cmd) }),
+        }
+    }
+
+    /// Answers WaitForWindow with the window's id once it maps, or an error when it
+    /// hasn't within `timeout` milliseconds.
+    async fn wait_for_window(
+        &self,
+        class: Option<String>,
+        title: Option<String>,
+        timeout: Option<u64>,
+    ) -> serde_json::Value {
+        let Some(waiters) = &self.waiters else {
+            return serde_json::json!({ "error": "Windows can't be waited for" });
+        };
+        let timeout = Duration::from_millis(timeout.unwrap_or(WAIT_TIMEOUT_MS));
+        match tokio::time::timeout(timeout, waiters.wait(class, title)).await {
+            Ok(Ok(window)) => serde_json::json!({ "window": window }),
+            Ok(Err(_)) => serde_json::json!({ "error": "Stopped waiting for windows" }),
+            Err(_) => serde_json::json!({ "error": "No such window mapped in time" }),
+        }
+    }
+
+    /// Answers a query from qtile's state: the matches, or what was wrong with it.
+    fn query(&self, domain: query::Domain, filters: &[String]) -> serde_json::Value {
+        let matches = self
+            .snapshot()
+            .and_then(|snapshot| query::run(&snapshot, domain, filters));
+        match matches {
+            Ok(matches) => matches,
+            Err(e) => serde_json::json!({ "error": e }),
+        }
+    }
+
+    /// What `cmd` would change, from qtile's state now, without carrying it out.
+    fn dry_run(&self, cmd: &WmCommand) -> serde_json::Value {
+        let diff = self.snapshot().and_then(|before| {
+            let tags = self.tags.is_some();
+            let mut prediction = predict::Prediction::new(&self.config, tags, before.clone());
+            prediction.apply(cmd)?;
+            Ok(prediction.diff(&before))
+        });
+        match diff {
+            Ok(diff) => diff,
+            Err(e) => serde_json::json!({ "error": e }),
+        }
+    }
+
+    /// qtile's state, with the layers the bridge keeps.
+    fn snapshot(&self) -> Result<state::Snapshot, String> {
+        state::Atoms::new(&self.conn)
+            .and_then(|atoms| state::Snapshot::read(&self.conn, self.root, &atoms))
+            .map_err(|e| format!("Failed to read qtile's state: {}", e))
+            .map(|mut snapshot| {
+                for client in &mut snapshot.clients {
+                    client.layer = self.layers.layer(client.window);
+                }
+                snapshot
+            })
+    }
+
+    /// Reverts the last action Undo can, keeping it for another try if that fails.
+    fn undo(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let action = self.undo.pop().ok_or("there is nothing to undo")?;
+        trace::decision(&action.windows(), format!("Undoing {:?}", action));
+        let result = self.revert(&action);
+        if result.is_err() {
+            self.undo.restore(action);
+        }
+        result
+    }
+
+    fn revert(&self, action: &undo::Action) -> Result<(), Box<dyn std::error::Error>> {
+        match action {
+            undo::Action::MoveToWorkspace { window, from, .. } => {
+                let cmd = WmCommand::MoveToWorkspace {
+                    window: *window,
+                    workspace: from.clone(),
+                };
+                self.perform(cmd, false)
+            }
+            undo::Action::SwitchWorkspace { from, .. } => {
+                self.perform(WmCommand::SwitchWorkspace(from.clone()), false)
+            }
+            undo::Action::Toggle { window, command }
+            | undo::Action::Shuffle { window, command } => {
+                if self.focused_window()? != *window {
+                    let message = format!("window {} has to be focused to undo {:?}", window, command);
+                    return Err(message.into());
+                }
+                let command = undo::shuffle_back(command).unwrap_or_else(|| command.clone());
+                self.perform(command, false)
+            }
+            undo::Action::NextLayout => spawn_template(&self.config.undo.previous_layout, &[]),
+            undo::Action::SetLayer { window, from, .. } => {
+                self.layers.set(*window, *from, &self.stack.lock().unwrap())
+            }
+            // Kept open as it was taken from the history.
+            undo::Action::CloseWindow { .. } => Ok(()),
+        }
+    }
+
+    /// Closes `window` once undo.close_grace_ms is up, unless Undo keeps it open.
+    fn close_later(&self, window: Window) -> Result<(), Box<dyn std::error::Error>> {
+        let grace = Duration::from_millis(self.config.undo.close_grace_ms);
+        trace::decision(&[window], format!("Closing window {} in {:?}", window, grace));
+        let (conn, root) = (self.conn.clone(), self.root);
+        self.undo.close_later(window, grace, move || {
+            trace::decision(&[window], format!("Closing window {}", window));
+            let data = [CURRENT_TIME, 2, 0, 0, 0];
+            if let Err(e) = send_to_root(&conn, root, window, "_NET_CLOSE_WINDOW", data) {
+                eprintln!("Failed to close window {}: {}", window, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// The metrics in Prometheus' text format.
+    fn metrics(&self) -> String {
+        let windows = state::Atoms::new(&self.conn)
+            .and_then(|atoms| state::client_list(&self.conn, self.root, &atoms))
+            .map(|clients| clients.len());
+        self.metrics.render(windows.ok())
+    }
+
+    /// The window qtile has focused.
+    fn focused_window(&self) -> Result<Window, Box<dyn std::error::Error>> {
+        let atoms = state::Atoms::new(&self.conn)?;
+        let window = state::active_window(&self.conn, self.root, &atoms)?;
+        Ok(window.ok_or("no window is focused")?)
+    }
+
+    /// Reports where a volume or brightness change left it, for the renderer to show.
+    fn show_level(
+        &self,
+        level: Result<controls::Level, Box<dyn std::error::Error>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let level = level?;
+        let muted = if level.muted { ", muted" } else { "" };
+        println!("{:?} is at {:.0}%{}", level.control, level.level * 100.0, muted);
+        if let Some(report) = &self.levels {
+            report(level);
+        }
+        Ok(())
+    }
+
+    /// Sends the window manager an EWMH request about `window`.
+    fn send_to_root(
+        &self,
+        window: Window,
+        message: &str,
+        data: [u32; 5],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        send_to_root(&self.conn, self.root, window, message, data)
+    }
+
+    /// Focuses and raises the window above the focused one, wrapping to the bottom.
+    fn focus_next(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let focused = self.conn.get_input_focus()?.reply()?.focus;
+        let next = {
+            let stack = self.stack.lock().unwrap();
+            let windows: Vec<_> = stack.focusable().map(|w| w.window).collect();
+            match windows.iter().position(|w| *w == focused) {
+                Some(index) => windows.get(index + 1).or(windows.first()).copied(),
+                None => windows.first().copied(),
+            }
+        };
+        if let Some(window) = next {
+            self.conn.set_input_focus(InputFocus::PARENT, window, CURRENT_TIME)?;
+            self.conn.configure_window(window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+        }
         Ok(())
     }
 }
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+/// Runs `template` in a shell with each `{name}` in it replaced by its value, quoted.
+fn spawn_template(template: &str, values: &[(&str, &str)]) -> Result<(), Box<dyn std::error::Error>> {
+    let command = fill_template(template, values);
+    println!("Executing command: {}", command);
+    std::process::Command::new("sh").arg("-c").arg(&command).spawn()?;
+    Ok(())
+}
+
+/// `template` with each `{name}` in it replaced by its value, quoted for a shell.
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut command = template.to_string();
+    for (name, value) in values {
+        let quoted = format!("'{}'", value.replace('\'', "'\\''"));
+        command = command.replace(&format!("{{{}}}", name), &quoted);
+    }
+    command
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = cli::Args::parse();
+    args.log_level.apply()?;
+    let selection = selection::Selection::acquire(args.replace)?;
+    cli::take_socket(&args.socket, args.replace)?;
+    // Before the runtime starts its threads, which a fork leaves behind.
+    if args.daemonize {
+        cli::daemonize()?;
+    }
+    tokio::runtime::Runtime::new()?.block_on(run(args, selection))
+}
+
+async fn run(
+    args: cli::Args,
+    selection: selection::Selection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // A bridge taking over binds the socket afresh.
+    selection.watch(|| std::process::exit(0));
     // Load configuration
-    let config = Config::load("wm_config.toml")?;
+    let config = Config::load(&args.config)?;
 
     // Create X11 connection
-    let (conn, _) = RustConnection::connect(None)?;
-    let wm = WindowManager::new(conn, config);
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let conn = Arc::new(conn);
+    let root = conn.setup().roots[screen_num].root;
+    let stack = stack::WindowStack::shared();
+    let layers = layers::Layers::new(conn.clone())?;
+    let mut wm = WindowManager::new(conn.clone(), root, config, stack.clone(), layers);
+    start_floating(&mut wm);
+    start_tags(&mut wm);
+    start_fake_fullscreen(&mut wm);
+    start_input_regions(&mut wm);
+    start_shapes(&mut wm);
+    start_tracer(&mut wm);
+    start_waiters(&mut wm);
+    start_insertion(&mut wm);
 
-    let sock_path = "/tmp/x11rb_wm.sock";
+    if args.unified {
+        return run_unified(conn, root, stack, wm, args.socket);
+    }
+
+    let outputs = link::monitors(&conn, root).unwrap_or_else(|e| {
+        eprintln!("Failed to query monitors, leaving outputs to the renderer: {}", e);
+        Vec::new()
+    });
+    let link = Arc::new(Mutex::new(link::RendererLink::new(outputs)));
+    {
+        let link = link.clone();
+        wm.levels = Some(Box::new(move |level| link.lock().unwrap().report_level(level)));
+    }
+    let pinger = {
+        let link = link.clone();
+        let report = Box::new(move |window, responsive| {
+            link.lock().unwrap().report_responsive(window, responsive)
+        });
+        start_pinger(&conn, root, &wm.config.ping, &stack, report)
+    };
+    {
+        let link = link.clone();
+        let report = move |window, tile| link.lock().unwrap().report_letterbox(window, tile);
+        wm.centered.set_report(Box::new(report));
+    }
+    if let Some(input_regions) = &wm.input_regions {
+        let link = link.clone();
+        let report = move |window, click_through| {
+            link.lock().unwrap().report_click_through(window, click_through)
+        };
+        input_regions.set_report(Box::new(report));
+    }
+    if let Some(shapes) = &wm.shapes {
+        let link = link.clone();
+        let report = move |window, rects| link.lock().unwrap().report_shape(window, rects);
+        shapes.set_report(Box::new(report));
+    }
+    let clients = {
+        let link = link.clone();
+        let report = Box::new(move |info| link.lock().unwrap().report_client(info));
+        clients::Clients::new(conn.clone(), report)?
+    };
+    {
+        let link = link.clone();
+        let clients = clients.clone();
+        let mut observe = observer(&wm);
+        stack::watch(conn.clone(), root, stack.clone(), move |event, stack, changed| {
+            if let Some(pinger) = &pinger {
+                pinger.event(event);
+            }
+            clients.event(event);
+            observe(event, stack, changed);
+            if changed {
+                link.lock().unwrap().sync(stack);
+            }
+        })?;
+    }
+    clients.watch_mapped(&stack.lock().unwrap());
+    if let Some(floating) = &wm.floating {
+        floating.watch_mapped(&stack.lock().unwrap());
+    }
+    if let Some(tags) = &wm.tags {
+        tags.watch_mapped(&stack.lock().unwrap());
+    }
+    wm.centered.watch_mapped(&stack.lock().unwrap());
+    if let Some(input_regions) = &wm.input_regions {
+        input_regions.watch_mapped(&stack.lock().unwrap());
+    }
+    if let Some(shapes) = &wm.shapes {
+        shapes.watch_mapped(&stack.lock().unwrap());
+    }
+    if wm.config.media.enabled {
+        let link = link.clone();
+        let report = Box::new(move |track| link.lock().unwrap().report_media(track));
+        media::watch(wm.config.media.clone(), report);
+    }
+    {
+        let link = link.clone();
+        start_startup(&mut wm, Box::new(move |busy| link.lock().unwrap().report_busy(busy)));
+    }
+    {
+        let link = link.clone();
+        let report = move |window, dimmed| link.lock().unwrap().report_dimmed(window, dimmed);
+        start_modal(&wm.config, Box::new(report));
+    }
+    start_output_rules(&wm.config);
+    start_layouts(&wm.config);
+    start_kiosk(&wm.config);
+    start_focus_guard(&wm.config);
+    {
+        let link = link.clone();
+        let report = move |variant: &_| link.lock().unwrap().report_appearance(variant);
+        start_appearance(&wm.config, start_xsettings(&wm.config), Box::new(report));
+    }
+    start_clipboard(&mut wm);
+    start_injector(&mut wm);
+    wm.metrics.set_frames(Box::new(metrics::renderer_frames));
+    watchdog::Watchdog::new(conn, wm.config.compositor.clone(), stack, link).spawn();
+    let wm = Arc::new(wm);
+    start_i3(&wm);
+    start_metrics(&wm);
+    start_remote(&wm);
+    serve(wm, &args.socket).await
+}
+
+/// Sends the window manager on `root` an EWMH request about `window`.
+fn send_to_root(
+    conn: &RustConnection,
+    root: Window,
+    window: Window,
+    message: &str,
+    data: [u32; 5],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let type_ = conn.intern_atom(false, message.as_bytes())?.reply()?.atom;
+    let event = ClientMessageEvent::new(32, window, type_, data);
+    let mask = EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+    conn.send_event(false, root, mask, event)?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Starts pinging clients, unless turned off, reporting responsiveness to `report`.
+fn start_pinger(
+    conn: &Arc<RustConnection>,
+    root: x11rb::protocol::xproto::Window,
+    config: &PingConfig,
+    stack: &SharedStack,
+    report: ping::Report,
+) -> Option<Arc<ping::Pinger>> {
+    if !config.enabled {
+        return None;
+    }
+    let pinger = ping::Pinger::new(conn.clone(), root, config.clone(), stack.clone(), report)
+        .map_err(|e| eprintln!("Not pinging clients: {}", e))
+        .ok()?;
+    pinger.spawn();
+    Some(pinger)
+}
+
+/// Starts announcing launches, unless turned off, reporting pending ones to `report`.
+fn start_startup(wm: &mut WindowManager, report: startup::Report) {
+    if !wm.config.startup.enabled {
+        return;
+    }
+    let move_window = wm.config.workspaces.move_window.clone();
+    match startup::Startup::new(wm.config.startup.clone(), move_window, report) {
+        Ok(startup) => wm.startup = Some(startup),
+        Err(e) => eprintln!("Not sending startup notifications: {}", e),
+    }
+}
+
+/// Starts following modal dialogs, unless turned off, reporting the windows they dim
+/// to `report`.
+fn start_modal(config: &Config, report: modal::Report) {
+    if !config.modal.enabled {
+        return;
+    }
+    if let Err(e) = modal::Modal::new(config.modal.clone(), report) {
+        eprintln!("Not following modal dialogs: {}", e);
+    }
+}
+
+/// Locks the session down to one application, in kiosk mode.
+fn start_kiosk(config: &Config) {
+    if !config.kiosk.enabled {
+        return;
+    }
+    if let Err(e) = kiosk::Kiosk::new(config.kiosk.clone(), config.outputs.fullscreen.clone()) {
+        eprintln!("Not running a kiosk: {}", e);
+    }
+}
+
+/// Starts applying the per-output rules, if there are any.
+fn start_output_rules(config: &Config) {
+    if config.outputs.rules.is_empty() {
+        return;
+    }
+    if let Err(e) = outputs::OutputRules::new(config.outputs.clone()) {
+        eprintln!("Not applying output rules: {}", e);
+    }
+}
+
+/// Starts keeping the groups' layouts across restarts, if asked to.
+fn start_layouts(config: &Config) {
+    if !config.layouts.remember {
+        return;
+    }
+    if let Err(e) = layouts::Layouts::start(config.layouts.clone()) {
+        eprintln!("Not keeping layouts: {}", e);
+    }
+}
+
+/// Starts keeping new windows from taking focus, unless turned off.
+fn start_focus_guard(config: &Config) {
+    if config.focus_stealing.level == FocusStealing::Off {
+        return;
+    }
+    if let Err(e) = focus::FocusGuard::start(config.focus_stealing.level) {
+        eprintln!("Not preventing focus stealing: {}", e);
+    }
+}
+
+/// Manages XSETTINGS if [xsettings] says to, or an appearance variant exports themes.
+fn start_xsettings(config: &Config) -> Option<xsettings::Shared> {
+    let appearance = &config.appearance;
+    let exports = appearance.source != AppearanceSource::Off
+        && [&appearance.dark, &appearance.light]
+            .iter()
+            .any(|variant| variant.gtk_theme.is_some() || variant.icon_theme.is_some());
+    if !config.xsettings.enabled && !exports {
+        return None;
+    }
+    xsettings::XSettings::start(&config.xsettings)
+        .inspect_err(|e| eprintln!("Not managing XSETTINGS: {}", e))
+        .ok()
+}
+
+fn start_appearance(
+    config: &Config,
+    xsettings: Option<xsettings::Shared>,
+    report: appearance::Report,
+) {
+    if let Err(e) = appearance::start(config.appearance.clone(), xsettings, report) {
+        eprintln!("Not switching the appearance: {}", e);
+    }
+}
+
+/// What follows the stack the same way in either mode.
+fn observer(
+    wm: &WindowManager,
+) -> impl FnMut(&x11rb::protocol::Event, &stack::WindowStack, bool) + Send + 'static {
+    let layers = wm.layers.clone();
+    let floating = wm.floating.clone();
+    let tags = wm.tags.clone();
+    let metrics = wm.metrics.clone();
+    let fake_fullscreen = wm.fake_fullscreen.clone();
+    let centered = wm.centered.clone();
+    let input_regions = wm.input_regions.clone();
+    let shapes = wm.shapes.clone();
+    move |event, stack, changed| {
+        metrics.event(event);
+        centered.event(event);
+        if let Some(input_regions) = &input_regions {
+            input_regions.event(event);
+        }
+        if let Some(shapes) = &shapes {
+            shapes.event(event);
+        }
+        layers.event(event, stack, changed);
+        if let Some(fake_fullscreen) = &fake_fullscreen {
+            fake_fullscreen.event(event);
+        }
+        if let Some(floating) = &floating {
+            floating.event(event);
+        }
+        if let Some(tags) = &tags {
+            tags.event(event);
+        }
+    }
+}
+
+/// Starts keeping windows' tags, if tags are the workspace model.
+fn start_tags(wm: &mut WindowManager) {
+    if !wm.config.tags.enabled {
+        return;
+    }
+    let move_window = wm.config.workspaces.move_window.clone();
+    match tags::Tags::new(wm.conn.clone(), wm.root, wm.config.tags.clone(), move_window) {
+        Ok(tags) => wm.tags = Some(tags),
+        Err(e) => eprintln!("Not using tags: {}", e),
+    }
+}
+
+/// Starts keeping windows that fake fullscreen in their tiles.
+fn start_fake_fullscreen(wm: &mut WindowManager) {
+    let config = wm.config.fake_fullscreen.clone();
+    match fullscreen::FakeFullscreen::new(wm.conn.clone(), config) {
+        Ok(fake_fullscreen) => wm.fake_fullscreen = Some(fake_fullscreen),
+        Err(e) => eprintln!("Not faking fullscreen: {}", e),
+    }
+}
+
+/// Starts shaping windows' input, if the X server can.
+fn start_input_regions(wm: &mut WindowManager) {
+    let config = wm.config.input_regions.clone();
+    match shape::InputRegions::new(wm.conn.clone(), config) {
+        Ok(input_regions) => wm.input_regions = Some(input_regions),
+        Err(e) => eprintln!("Not shaping input: {}", e),
+    }
+}
+
+fn start_shapes(wm: &mut WindowManager) {
+    match shape::bounding::Shapes::new(wm.conn.clone()) {
+        Ok(shapes) => wm.shapes = Some(shapes),
+        Err(e) => eprintln!("Not following window shapes: {}", e),
+    }
+}
+
+fn start_waiters(wm: &mut WindowManager) {
+    match wait::Waiters::new() {
+        Ok(waiters) => wm.waiters = Some(waiters),
+        Err(e) => eprintln!("Not able to wait for windows: {}", e),
+    }
+}
+
+/// Starts moving new windows, if insertion.point is set; SetInsertionPoint starts it
+/// otherwise.
+fn start_insertion(wm: &mut WindowManager) {
+    if wm.config.insertion.point.is_none() {
+        return;
+    }
+    match insertion::Insertion::new(wm.config.insertion.clone()) {
+        Ok(insertion) => *wm.insertion.get_mut().unwrap() = Some(insertion),
+        Err(e) => eprintln!("Not moving new windows to an insertion point: {}", e),
+    }
+}
+
+fn start_tracer(wm: &mut WindowManager) {
+    match trace::Tracer::new() {
+        Ok(tracer) => wm.tracer = Some(tracer),
+        Err(e) => eprintln!("Not able to trace windows: {}", e),
+    }
+}
+
+/// Starts remembering floating geometry and placing floating windows, unless both are
+/// turned off.
+fn start_floating(wm: &mut WindowManager) {
+    let config = &wm.config.floating;
+    if !config.remember && config.placement == config::Placement::Client {
+        return;
+    }
+    match floating::Floating::new(wm.conn.clone(), wm.root, wm.config.floating.clone()) {
+        Ok(floating) => wm.floating = Some(floating),
+        Err(e) => eprintln!("Not remembering floating geometry: {}", e),
+    }
+}
+
+/// Starts keeping the clipboard history, unless turned off.
+fn start_clipboard(wm: &mut WindowManager) {
+    if !wm.config.clipboard.enabled {
+        return;
+    }
+    match clipboard::Clipboard::new(wm.config.clipboard.clone()) {
+        Ok(clipboard) => wm.clipboard = Some(clipboard),
+        Err(e) => eprintln!("Not keeping the clipboard history: {}", e),
+    }
+}
+
+/// Starts sending synthetic input, if it is enabled.
+fn start_injector(wm: &mut WindowManager) {
+    if !wm.config.inject.enabled {
+        return;
+    }
+    match inject::Injector::new() {
+        Ok(injector) => wm.injector = Some(injector),
+        Err(e) => eprintln!("Not sending synthetic input: {}", e),
+    }
+}
+
+/// Starts serving metrics over HTTP, if an address is given.
+fn start_metrics(wm: &Arc<WindowManager>) {
+    let Some(address) = wm.config.metrics.listen.clone() else {
+        return;
+    };
+    let render = {
+        let wm = wm.clone();
+        move || wm.metrics()
+    };
+    tokio::spawn(async move {
+        if let Err(e) = metrics::listen(&address, render).await {
+            eprintln!("Not serving metrics: {}", e);
+        }
+    });
+}
+
+/// Starts taking commands over TLS, if an address is given.
+fn start_remote(wm: &Arc<WindowManager>) {
+    let config = wm.config.remote.clone();
+    let serve = {
+        let wm = wm.clone();
+        move |stream| {
+            tokio::spawn(serve_client(wm.clone(), stream, false));
+        }
+    };
+    tokio::spawn(async move {
+        if let Err(e) = remote::listen(&config, serve).await {
+            eprintln!("Not taking remote commands: {}", e);
+        }
+    });
+}
+
+/// Starts the i3-compatible IPC socket, if it is enabled.
+fn start_i3(wm: &Arc<WindowManager>) {
+    if wm.config.i3.enabled {
+        if let Err(e) = i3::start(&wm.config.i3, wm.clone()) {
+            eprintln!("Not serving i3 IPC: {}", e);
+        }
+    }
+}
+
+/// Runs the compositor on this thread while commands are served in the background.
+#[cfg(feature = "unified")]
+fn run_unified(
+    conn: Arc<RustConnection>,
+    root: x11rb::protocol::xproto::Window,
+    stack: SharedStack,
+    mut wm: WindowManager,
+    socket: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use qtilerugo_compositor::compositor;
+
+    let scene = unified::start(conn, root, stack.clone(), &wm.config.ping, observer(&wm))?;
+    if let Some(floating) = &wm.floating {
+        floating.watch_mapped(&stack.lock().unwrap());
+    }
+    if let Some(tags) = &wm.tags {
+        tags.watch_mapped(&stack.lock().unwrap());
+    }
+    wm.centered.set_report(unified::letterbox_report(&scene));
+    wm.centered.watch_mapped(&stack.lock().unwrap());
+    if let Some(input_regions) = &wm.input_regions {
+        input_regions.set_report(unified::click_through_report(&scene));
+        input_regions.watch_mapped(&stack.lock().unwrap());
+    }
+    if let Some(shapes) = &wm.shapes {
+        shapes.set_report(unified::shape_report(&scene));
+        shapes.watch_mapped(&stack.lock().unwrap());
+    }
+    wm.levels = Some(unified::level_report(&scene));
+    if wm.config.media.enabled {
+        media::watch(wm.config.media.clone(), unified::media_report(&scene));
+    }
+    start_startup(&mut wm, unified::busy_report(&scene));
+    start_modal(&wm.config, unified::dimmed_report(&scene));
+    start_output_rules(&wm.config);
+    start_layouts(&wm.config);
+    start_kiosk(&wm.config);
+    start_focus_guard(&wm.config);
+    let xsettings = start_xsettings(&wm.config);
+    start_appearance(&wm.config, xsettings, unified::appearance_report(&scene));
+    start_clipboard(&mut wm);
+    start_injector(&mut wm);
+    wm.metrics.set_frames(unified::frame_source(&scene));
+    let wm = Arc::new(wm);
+    start_i3(&wm);
+    start_metrics(&wm);
+    start_remote(&wm);
+    tokio::spawn(async move {
+        if let Err(e) = serve(wm, &socket).await {
+            eprintln!("Command listener failed: {}", e);
+        }
+    });
+    println!("Running in unified compositor mode");
+    let render_loop = compositor::RenderLoop::new();
+    // Queued until the loop runs, which then opens the window first thing.
+    render_loop
+        .proxy()
+        .send_event(compositor::LoopRequest::SpawnWindow)
+        .expect("failed to queue the compositor window");
+    render_loop.run(scene)
+}
+
+#[cfg(not(feature = "unified"))]
+fn run_unified(
+    _conn: Arc<RustConnection>,
+    _root: x11rb::protocol::xproto::Window,
+    _stack: SharedStack,
+    _wm: WindowManager,
+    _socket: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("--unified requires building with `--features unified`".into())
+}
+
+/// Accepts clients on the command socket and executes their commands.
+async fn serve(
+    wm: Arc<WindowManager>,
+    sock_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
 
     // Remove existing socket if it exists
-    if Path::new(sock_path).exists() {
+    if sock_path.exists() {
         std::fs::remove_file(sock_path)?;
         println!("Removed existing socket");
     }
 
     // Bind to socket
     let listener = UnixListener::bind(sock_path)?;
-    println!("Listening on socket: {}", sock_path);
+    println!("Listening on socket: {}", sock_path.display());
+    // Whoever owns the socket is the bridge's own user.
+    let uid = std::fs::metadata(sock_path)?.uid();
 
     // Main loop
     loop {
         match listener.accept().await {
-            Ok((mut stream, _)) => {
+            Ok((stream, _)) => {
                 println!("New client connected");
-                let mut buf = [0u8; 1024];
-                let mut buffer = Vec::new();  // Buffer for partial data
-
-                loop {
-                    match stream.read(&mut buf).await {
-                        Ok(n) if n == 0 => {
-                            println!("Client disconnected");
-                            break;
+                let owner = stream.peer_cred().is_ok_and(|cred| cred.uid() == uid);
+                // Each client on its own task, so one waiting for a window holds up
+                // only itself.
+                tokio::spawn(serve_client(wm.clone(), stream, owner));
+            }
+            Err(e) => eprintln!("Connection error: {}", e),
+        }
+    }
+}
+
+/// Executes the commands of one client of the command socket, or a remote one, in
+/// order, until it disconnects.
+async fn serve_client<S>(wm: Arc<WindowManager>, mut stream: S, owner: bool)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; 1024];
+    let mut decoder = Decoder::default();
+
+    'client: loop {
+        match stream.read(&mut buf).await {
+            Ok(0) => {
+                println!("Client disconnected");
+                break;
+            }
+            Ok(n) => {
+                decoder.push(&buf[..n]);
+                println!("Received raw data: {:?}", &buf[..n]);
+
+                // Every whole command received so far, in order
+                while let Some(parsed) = decoder.message::<Request>() {
+                    match parsed {
+                        Ok(request) if request.dry_run => {
+                            let reply = wm.dry_run(&request.command);
+                            let reply = qtilerugo_ipc::reply::json(&reply);
+                            if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                                eprintln!("Failed to answer dry run: {}", e);
+                                break 'client;
+                            }
+                        }
+                        Ok(Request { command: WmCommand::WaitForWindow { class, title, timeout }, .. }) => {
+                            wm.metrics.command(false);
+                            let reply = wm.wait_for_window(class, title, timeout).await;
+                            let reply = qtilerugo_ipc::reply::json(&reply);
+                            if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                                eprintln!("Failed to answer a wait: {}", e);
+                                break 'client;
+                            }
                         }
-                        Ok(n) => {
-                            buffer.extend_from_slice(&buf[..n]);  // Append new data to buffer
-                            println!("Received raw data: {:?}", &buffer);
-
-                            // Attempt to parse JSON
-                            match serde_json::from_slice(&buffer) {
-                                Ok(cmd) => {
-                                    println!("Parsed command: {:?}", cmd);
-                                    if let Err(e) = wm.handle_command(cmd) {
-                                        eprintln!("Error handling command: {}", e);
-                                    }
-                                    buffer.clear();  // Clear buffer after successful parse
-                                }
-                                Err(e) if e.is_eof() => {
-                                    // Incomplete data, wait for more
-                                    continue;
-                                }
-                                Err(e) => {
-                                    eprintln!("Invalid command: {} (data: {:?})", e, buffer);
-                                    buffer.clear();  // Clear buffer on error
-                                }
+                        Ok(Request { command: cmd, .. }) if cmd.replies() => {
+                            wm.metrics.command(false);
+                            let reply = qtilerugo_ipc::reply::json(&wm.answer(cmd));
+                            if let Err(e) = stream.write_all(reply.as_bytes()).await {
+                                eprintln!("Failed to answer query: {}", e);
+                                break 'client;
                             }
                         }
-                        Err(e) => {
-                            eprintln!("Read error: {}", e);
-                            break;
+                        Ok(Request { command: cmd, .. }) => {
+                            println!("Parsed command: {:?}", cmd);
+                            let result = wm.run_command(cmd, owner);
+                            wm.metrics.command(result.is_err());
+                            if let Err(e) = result {
+                                eprintln!("Error handling command: {}", e);
+                            }
                         }
+                        Err(e) => eprintln!("Invalid command: {}", e),
                     }
                 }
             }
-            Err(e) => eprintln!("Connection error: {}", e),
+            Err(e) => {
+                eprintln!("Read error: {}", e);
+                break;
+            }
         }
     }
 }
@@ -0,0 +1,211 @@
+// Media players, through MPRIS on the session bus, so media keys can be bound to the
+// WM's commands and the renderer's bar can show what plays. The bus is reached with
+// systemd's busctl, as the controls reach logind. One player is followed: the one the
+// config names, or the one playing, or the first; a thread reads it on an interval and
+// reports its track and playback status when they change, to the renderer and to an
+// optional shell command.
+
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use qtilerugo_ipc::renderer::{MediaInfo, Playback};
+use serde_json::Value;
+
+use crate::config::MediaConfig;
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// What the followed player plays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Track {
+    /// The player's bus name, after org.mpris.MediaPlayer2.
+    pub player: String,
+    /// Playing, Paused, or Stopped.
+    pub status: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+impl Track {
+    /// What the renderer's SetMedia command tells of it.
+    pub fn media_info(&self) -> MediaInfo {
+        MediaInfo {
+            player: self.player.clone(),
+            status: match self.status.as_str() {
+                "Playing" => Playback::Playing,
+                "Paused" => Playback::Paused,
+                _ => Playback::Stopped,
+            },
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+        }
+    }
+}
+
+/// Told of the followed player's track whenever it changes; `None` without a player.
+pub type Report = Box<dyn Fn(Option<Track>) + Send + Sync>;
+
+/// What the media commands ask the followed player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl Action {
+    fn method(self) -> &'static str {
+        match self {
+            Self::PlayPause => "PlayPause",
+            Self::Next => "Next",
+            Self::Previous => "Previous",
+        }
+    }
+}
+
+/// Has the followed player do `action`.
+pub fn send(config: &MediaConfig, action: Action) -> Result<()> {
+    let player = follow(config)?.ok_or("no media player is running")?;
+    let name = format!("{}{}", MPRIS_PREFIX, player);
+    busctl(&["call", &name, MPRIS_PATH, PLAYER_INTERFACE, action.method()])?;
+    Ok(())
+}
+
+/// Reads the followed player every `poll_ms`, reporting its track to `report` when it
+/// changes, starting with the first reading.
+pub fn watch(config: MediaConfig, report: Report) {
+    let interval = Duration::from_millis(config.poll_ms.max(100));
+    thread::spawn(move || {
+        let mut last: Option<Option<Track>> = None;
+        loop {
+            let track = match follow(&config).and_then(|player| player.map(track).transpose()) {
+                Ok(track) => track,
+                Err(e) => {
+                    // The bus, or the player, came and went; it is tried again.
+                    if last.is_none() {
+                        eprintln!("Not reading media players: {}", e);
+                    }
+                    None
+                }
+            };
+            if last.as_ref() != Some(&track) {
+                if let (Some(template), Some(track)) = (&config.on_change, &track) {
+                    let values = [
+                        ("player", track.player.as_str()),
+                        ("status", track.status.as_str()),
+                        ("title", track.title.as_str()),
+                        ("artist", track.artist.as_str()),
+                        ("album", track.album.as_str()),
+                    ];
+                    if let Err(e) = crate::spawn_template(template, &values) {
+                        eprintln!("Failed to run media.on_change: {}", e);
+                    }
+                }
+                report(track.clone());
+                last = Some(track);
+            }
+            thread::sleep(interval);
+        }
+    });
+}
+
+/// The player to follow, by its name after org.mpris.MediaPlayer2: the first the
+/// config names that is running, or else the first playing, or else the first.
+fn follow(config: &MediaConfig) -> Result<Option<String>> {
+    let names = busctl(&[
+        "call",
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "ListNames",
+    ])?;
+    // {"type":"as","data":[["org.freedesktop.DBus", ...]]}
+    let mut players: Vec<String> = names["data"][0]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str()?.strip_prefix(MPRIS_PREFIX))
+        .map(str::to_string)
+        .collect();
+    players.sort();
+    for preferred in &config.players {
+        // Players with several instances add one to their name, like firefox.instance_1_2.
+        let named = players
+            .iter()
+            .find(|player| *player == preferred || player.starts_with(&format!("{}.", preferred)));
+        if let Some(player) = named {
+            return Ok(Some(player.clone()));
+        }
+    }
+    let playing = players
+        .iter()
+        .find(|player| status(player).is_ok_and(|status| status == "Playing"));
+    Ok(playing.or(players.first()).cloned())
+}
+
+fn status(player: &str) -> Result<String> {
+    let status = property(player, "PlaybackStatus")?;
+    Ok(status["data"].as_str().unwrap_or("Stopped").to_string())
+}
+
+/// What `player` plays now.
+fn track(player: String) -> Result<Track> {
+    let status = status(&player)?;
+    // {"type":"a{sv}","data":{"xesam:title":{"type":"s","data":"..."}, ...}}
+    let metadata = property(&player, "Metadata")?;
+    let field = |name: &str| match &metadata["data"][name]["data"] {
+        Value::String(value) => value.clone(),
+        // Artists come as a list.
+        Value::Array(values) => {
+            let values: Vec<&str> = values.iter().filter_map(Value::as_str).collect();
+            values.join(", ")
+        }
+        _ => String::new(),
+    };
+    Ok(Track {
+        title: field("xesam:title"),
+        artist: field("xesam:artist"),
+        album: field("xesam:album"),
+        player,
+        status,
+    })
+}
+
+fn property(player: &str, name: &str) -> Result<Value> {
+    let bus_name = format!("{}{}", MPRIS_PREFIX, player);
+    busctl(&[
+        "get-property",
+        &bus_name,
+        MPRIS_PATH,
+        PLAYER_INTERFACE,
+        name,
+    ])
+}
+
+/// Runs busctl on the session bus with `args`, returning the JSON it printed.
+pub fn busctl(args: &[&str]) -> Result<Value> {
+    let output = Command::new("busctl")
+        .args(["--user", "--json=short"])
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "busctl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    // Methods without a reply print nothing.
+    if output.stdout.trim_ascii().is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
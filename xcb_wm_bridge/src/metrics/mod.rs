@@ -0,0 +1,304 @@
+// Metrics in Prometheus' text format, for those who graph their desktop like a server:
+// GetMetrics answers with them on the command socket, and metrics.listen serves them
+// over HTTP at /metrics for a scraper. Layout is qtile's, so relayout latency is timed
+// from a layout command to the first window qtile reconfigures after it.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write as _};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use x11rb::protocol::Event;
+
+use qtilerugo_ipc::encode;
+use qtilerugo_ipc::renderer::RendererCommand;
+
+use crate::link::RENDERER_SOCKET;
+
+/// Upper bounds of the relayout latency buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+/// How long after a layout command a reconfigure still counts as its relayout.
+const RELAYOUT_WINDOW: Duration = Duration::from_secs(5);
+/// How long the renderer gets to report its frames.
+const RENDERER_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub use qtilerugo_ipc::renderer::OutputReport;
+
+/// Where the compositor's frames are read from.
+pub type FrameSource = Box<dyn Fn() -> io::Result<Vec<OutputReport>> + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations at most each bucket's bound, not counting lower buckets.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+pub struct Metrics {
+    commands: AtomicU64,
+    failed_commands: AtomicU64,
+    x_errors: AtomicU64,
+    /// When the last layout command still waiting for qtile to relayout was run.
+    layout_requested: Mutex<Option<Instant>>,
+    relayouts: Mutex<Histogram>,
+    frames: Mutex<Option<FrameSource>>,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            commands: AtomicU64::new(0),
+            failed_commands: AtomicU64::new(0),
+            x_errors: AtomicU64::new(0),
+            layout_requested: Mutex::new(None),
+            relayouts: Mutex::new(Histogram::default()),
+            frames: Mutex::new(None),
+        })
+    }
+
+    /// Reads the compositor's frames from `source` from now on.
+    pub fn set_frames(&self, source: FrameSource) {
+        *self.frames.lock().unwrap() = Some(source);
+    }
+
+    /// Counts a command from a socket, and whether it failed.
+    pub fn command(&self, failed: bool) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failed_commands.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Starts timing the relayout a layout command asks qtile for.
+    pub fn layout_requested(&self) {
+        *self.layout_requested.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify, and the X errors that
+    /// come with it.
+    pub fn event(&self, event: &Event) {
+        match event {
+            Event::Error(_) => {
+                self.x_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Event::ConfigureNotify(e) if !e.override_redirect => {
+                let Some(at) = self.layout_requested.lock().unwrap().take() else {
+                    return;
+                };
+                let latency = at.elapsed();
+                if latency <= RELAYOUT_WINDOW {
+                    self.relayouts
+                        .lock()
+                        .unwrap()
+                        .observe(latency.as_secs_f64());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The metrics, with `windows` managed, if they could be counted.
+    pub fn render(&self, windows: Option<usize>) -> String {
+        let mut text = String::new();
+        if let Some(windows) = windows {
+            family(
+                &mut text,
+                "qtilerugo_windows",
+                "gauge",
+                "Windows qtile manages.",
+            );
+            let _ = writeln!(text, "qtilerugo_windows {}", windows);
+        }
+        family(
+            &mut text,
+            "qtilerugo_ipc_commands_total",
+            "counter",
+            "Commands taken on the command and i3 sockets.",
+        );
+        let commands = self.commands.load(Ordering::Relaxed);
+        let failed = self.failed_commands.load(Ordering::Relaxed);
+        let _ = writeln!(
+            text,
+            "qtilerugo_ipc_commands_total{{result=\"ok\"}} {}",
+            commands - failed
+        );
+        let _ = writeln!(
+            text,
+            "qtilerugo_ipc_commands_total{{result=\"error\"}} {}",
+            failed
+        );
+        family(
+            &mut text,
+            "qtilerugo_x_errors_total",
+            "counter",
+            "X errors the bridge's connection got.",
+        );
+        let _ = writeln!(
+            text,
+            "qtilerugo_x_errors_total {}",
+            self.x_errors.load(Ordering::Relaxed)
+        );
+        family(
+            &mut text,
+            "qtilerugo_relayout_seconds",
+            "histogram",
+            "Time from a layout command to qtile reconfiguring a window.",
+        );
+        let relayouts = self.relayouts.lock().unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(relayouts.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                "qtilerugo_relayout_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            text,
+            "qtilerugo_relayout_seconds_bucket{{le=\"+Inf\"}} {}",
+            relayouts.count
+        );
+        let _ = writeln!(text, "qtilerugo_relayout_seconds_sum {}", relayouts.sum);
+        let _ = writeln!(text, "qtilerugo_relayout_seconds_count {}", relayouts.count);
+        drop(relayouts);
+        if let Some(outputs) = self.read_frames() {
+            render_frames(&mut text, &outputs);
+        }
+        text
+    }
+
+    fn read_frames(&self) -> Option<Vec<OutputReport>> {
+        let frames = self.frames.lock().unwrap();
+        match (*frames.as_ref()?)() {
+            Ok(outputs) => Some(outputs),
+            Err(e) => {
+                eprintln!("Failed to read the compositor's frames: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The compositor's frame metrics, for each of `outputs`.
+fn render_frames(text: &mut String, outputs: &[OutputReport]) {
+    let label = |output: &OutputReport| output.output.replace('\\', "\\\\").replace('"', "\\\"");
+    family(
+        text,
+        "qtilerugo_compositor_fps",
+        "gauge",
+        "Frames per second of each output while it draws continuously.",
+    );
+    for output in outputs {
+        if let Some(fps) = output.fps {
+            let _ = writeln!(
+                text,
+                "qtilerugo_compositor_fps{{output=\"{}\"}} {}",
+                label(output),
+                fps
+            );
+        }
+    }
+    family(
+        text,
+        "qtilerugo_compositor_frames_total",
+        "counter",
+        "Frames each output drew.",
+    );
+    for output in outputs {
+        let _ = writeln!(
+            text,
+            "qtilerugo_compositor_frames_total{{output=\"{}\"}} {}",
+            label(output),
+            output.frames
+        );
+    }
+    family(
+        text,
+        "qtilerugo_compositor_missed_vblanks_total",
+        "counter",
+        "Vblanks each output's frames missed.",
+    );
+    for output in outputs {
+        let _ = writeln!(
+            text,
+            "qtilerugo_compositor_missed_vblanks_total{{output=\"{}\"}} {}",
+            label(output),
+            output.missed_vblanks
+        );
+    }
+}
+
+/// Starts a metric family, with its help text and its type.
+fn family(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// Asks the renderer, over a connection of its own, for its frames.
+pub fn renderer_frames() -> io::Result<Vec<OutputReport>> {
+    let stream = UnixStream::connect(RENDERER_SOCKET)?;
+    stream.set_read_timeout(Some(RENDERER_TIMEOUT))?;
+    let mut stream = BufReader::new(stream);
+    let command = encode(&RendererCommand::GetFrameStats);
+    stream.get_mut().write_all(command.as_bytes())?;
+    let mut line = String::new();
+    stream.read_line(&mut line)?;
+    serde_json::from_str(line.trim())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, line.trim().to_string()))
+}
+
+/// Serves what `render` gives at /metrics over HTTP on `address`, like "127.0.0.1:9750".
+pub async fn listen<F>(address: &str, render: F) -> io::Result<()>
+where
+    F: Fn() -> String + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address).await?;
+    println!("Serving metrics on http://{}/metrics", address);
+    let render = Arc::new(render);
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let render = render.clone();
+        tokio::spawn(async move {
+            // Only the request line matters, which fits in the first read.
+            let mut buf = [0u8; 1024];
+            let Ok(n) = stream.read(&mut buf).await else {
+                return;
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let response = if request.starts_with("GET ") && path == "/metrics" {
+                let body = tokio::task::spawn_blocking(move || render())
+                    .await
+                    .unwrap_or_default();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                eprintln!("Failed to serve metrics: {}", e);
+            }
+        });
+    }
+}
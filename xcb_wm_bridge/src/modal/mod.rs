@@ -0,0 +1,355 @@
+// Modal dialogs, after EWMH: a mapped window with _NET_WM_STATE_MODAL is modal for the
+// window it is transient for, or, transient for none or the root, for every window of
+// its WM_HINTS group. While it is open, focusing one of those windows focuses the
+// dialog instead, the renderer dims them, and the dialog is kept centered on the one
+// it is transient for, or its group's leader, as that moves. The X side goes over a
+// connection of its own, which watches maps, moves, and the properties involved.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::properties::WmHints;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _, EventMask,
+    MapState, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{CURRENT_TIME, NONE};
+
+use crate::config::ModalConfig;
+use crate::state;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Told whether each window is dimmed for a modal dialog of its own.
+pub type Report = Box<dyn Fn(Window, bool) + Send + Sync>;
+
+struct Atoms {
+    wm_state: Atom,
+    modal: Atom,
+    state: state::Atoms,
+}
+
+#[derive(Debug, Clone)]
+struct Dialog {
+    /// The windows it is modal for.
+    parents: Vec<Window>,
+    /// The window it is kept centered on.
+    anchor: Option<Window>,
+}
+
+pub struct Modal {
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: Atoms,
+    config: ModalConfig,
+    report: Report,
+    /// The mapped modal dialogs.
+    dialogs: Mutex<HashMap<Window, Dialog>>,
+    /// The windows reported dimmed.
+    dimmed: Mutex<HashSet<Window>>,
+    /// Where each anchor was last seen, x, y, width, height, and border width.
+    anchors: Mutex<HashMap<Window, [i32; 5]>>,
+}
+
+impl Modal {
+    /// Connects to the X server and starts following modal dialogs.
+    pub fn new(config: ModalConfig, report: Report) -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let conn = Arc::new(conn);
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            wm_state: atom("_NET_WM_STATE")?,
+            modal: atom("_NET_WM_STATE_MODAL")?,
+            state: state::Atoms::new(&conn)?,
+        };
+        // Maps and moves of top-level windows, and focus changes on the root.
+        let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+            .check()?;
+        conn.flush()?;
+
+        let modal = Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            config,
+            report,
+            dialogs: Mutex::new(HashMap::new()),
+            dimmed: Mutex::new(HashSet::new()),
+            anchors: Mutex::new(HashMap::new()),
+        });
+        for window in state::client_list(&modal.conn, root, &modal.atoms.state)? {
+            if let Err(e) = modal.mapped(window) {
+                eprintln!("Failed to check window {} for modality: {}", window, e);
+            }
+        }
+        let watcher = modal.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped following modal dialogs: {}", e);
+            }
+        });
+        Ok(modal)
+    }
+
+    fn watch(&self) -> Result<()> {
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+                Event::UnmapNotify(e) => {
+                    self.closed(e.window);
+                    Ok(())
+                }
+                Event::DestroyNotify(e) => {
+                    self.destroyed(e.window);
+                    Ok(())
+                }
+                Event::ConfigureNotify(e) if !e.override_redirect => self.moved(
+                    e.window,
+                    [
+                        i32::from(e.x),
+                        i32::from(e.y),
+                        i32::from(e.width),
+                        i32::from(e.height),
+                        i32::from(e.border_width),
+                    ],
+                ),
+                Event::PropertyNotify(e)
+                    if e.window == self.root && e.atom == self.atoms.state.net_active_window =>
+                {
+                    self.focused()
+                }
+                Event::PropertyNotify(e)
+                    if e.atom == self.atoms.wm_state
+                        || e.atom == Atom::from(AtomEnum::WM_TRANSIENT_FOR) =>
+                {
+                    self.check(e.window)
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to follow a modal dialog: {}", e);
+            }
+        }
+    }
+
+    /// Follows the properties of a newly mapped window, and checks whether it is modal.
+    fn mapped(&self, window: Window) -> Result<()> {
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        self.conn.change_window_attributes(window, &aux)?;
+        self.check(window)
+    }
+
+    /// Opens or closes `window` as a dialog, however its state now has it.
+    fn check(&self, window: Window) -> Result<()> {
+        let attributes = self.conn.get_window_attributes(window)?.reply()?;
+        let modal = attributes.map_state == MapState::VIEWABLE && self.is_modal(window)?;
+        if !modal {
+            self.closed(window);
+            return Ok(());
+        }
+        if self.dialogs.lock().unwrap().contains_key(&window) {
+            return Ok(());
+        }
+        let dialog = self.dialog(window)?;
+        let message = format!("Window {} is modal for {:?}", window, dialog.parents);
+        trace::decision(&[&[window], &dialog.parents[..]].concat(), message);
+        let anchor = dialog.anchor;
+        self.dialogs.lock().unwrap().insert(window, dialog);
+        self.refresh();
+        if let Some(anchor) = anchor {
+            let rect = geometry(&self.conn, anchor)?;
+            self.anchors.lock().unwrap().insert(anchor, rect);
+            self.center(window, rect)?;
+        }
+        self.focused()
+    }
+
+    /// Forgets `window` as a dialog, as it closes or qtile hides its group; it opens
+    /// again as it maps again.
+    fn closed(&self, window: Window) {
+        if self.dialogs.lock().unwrap().remove(&window).is_some() {
+            trace::decision(&[window], format!("Modal dialog {} closed", window));
+            self.refresh();
+        }
+    }
+
+    /// Forgets `window` as a dialog, and as a window dialogs are modal for.
+    fn destroyed(&self, window: Window) {
+        self.closed(window);
+        for dialog in self.dialogs.lock().unwrap().values_mut() {
+            dialog.parents.retain(|&parent| parent != window);
+        }
+        self.anchors.lock().unwrap().remove(&window);
+        self.refresh();
+    }
+
+    /// Centers the dialogs anchored on `window` again if it moved or resized.
+    fn moved(&self, window: Window, rect: [i32; 5]) -> Result<()> {
+        let dialogs: Vec<Window> = self
+            .dialogs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, dialog)| dialog.anchor == Some(window))
+            .map(|(&dialog, _)| dialog)
+            .collect();
+        if dialogs.is_empty() {
+            return Ok(());
+        }
+        if self.anchors.lock().unwrap().insert(window, rect) == Some(rect) {
+            return Ok(());
+        }
+        for dialog in dialogs {
+            self.center(dialog, rect)?;
+        }
+        Ok(())
+    }
+
+    /// Hands focus on to the dialog open for the focused window, if there is one.
+    fn focused(&self) -> Result<()> {
+        let Some(active) = state::active_window(&self.conn, self.root, &self.atoms.state)? else {
+            return Ok(());
+        };
+        let dialog = self
+            .dialogs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(&dialog, found)| dialog != active && found.parents.contains(&active))
+            .map(|(&dialog, _)| dialog);
+        let Some(dialog) = dialog else {
+            return Ok(());
+        };
+        trace::decision(
+            &[dialog, active],
+            format!("Focusing modal dialog {} instead of window {}", dialog, active),
+        );
+        let type_ = self.atoms.state.net_active_window;
+        // Source 2: a pager, which qtile always follows.
+        let event = ClientMessageEvent::new(32, dialog, type_, [2, CURRENT_TIME, active, 0, 0]);
+        let mask = EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+        self.conn.send_event(false, self.root, mask, event)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Reports the windows that became dimmed, or stopped being.
+    fn refresh(&self) {
+        let wanted: HashSet<Window> = self
+            .dialogs
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(|dialog| dialog.parents.iter().copied())
+            .collect();
+        let mut dimmed = self.dimmed.lock().unwrap();
+        for &window in wanted.difference(&dimmed) {
+            (self.report)(window, true);
+        }
+        for &window in dimmed.difference(&wanted) {
+            (self.report)(window, false);
+        }
+        *dimmed = wanted;
+    }
+
+    /// Puts `dialog` in the middle of `rect`, the window it is centered on.
+    fn center(&self, dialog: Window, rect: [i32; 5]) -> Result<()> {
+        if !self.config.center {
+            return Ok(());
+        }
+        let [x, y, width, height, border] = rect;
+        let [_, _, w, h, b] = geometry(&self.conn, dialog)?;
+        let x = x + (width + 2 * border - w - 2 * b) / 2;
+        let y = y + (height + 2 * border - h - 2 * b) / 2;
+        let (window, x, y) = (dialog.to_string(), x.to_string(), y.to_string());
+        let (width, height) = (w.to_string(), h.to_string());
+        let values = [
+            ("window", window.as_str()),
+            ("x", x.as_str()),
+            ("y", y.as_str()),
+            ("width", width.as_str()),
+            ("height", height.as_str()),
+        ];
+        crate::spawn_template(&self.config.place, &values)
+    }
+
+    fn is_modal(&self, window: Window) -> Result<bool> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atoms.wm_state, AtomEnum::ATOM, 0, 64)?
+            .reply()?;
+        let modal = reply
+            .value32()
+            .into_iter()
+            .flatten()
+            .any(|atom| atom == self.atoms.modal);
+        Ok(modal)
+    }
+
+    /// The windows `window` is modal for, and the one it is centered on.
+    fn dialog(&self, window: Window) -> Result<Dialog> {
+        let transient = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next())
+            .filter(|&parent| parent != NONE && parent != self.root);
+        if let Some(parent) = transient {
+            return Ok(Dialog {
+                parents: vec![parent],
+                anchor: Some(parent),
+            });
+        }
+        let group = WmHints::get(&*self.conn, window)?
+            .reply()
+            .ok()
+            .and_then(|hints| hints.window_group);
+        let Some(group) = group else {
+            return Ok(Dialog {
+                parents: Vec::new(),
+                anchor: None,
+            });
+        };
+        let mut parents = Vec::new();
+        for client in state::client_list(&self.conn, self.root, &self.atoms.state)? {
+            if client == window {
+                continue;
+            }
+            let hints = WmHints::get(&*self.conn, client)?.reply().ok();
+            if hints.and_then(|hints| hints.window_group) == Some(group) {
+                parents.push(client);
+            }
+        }
+        let anchor = parents.contains(&group).then_some(group);
+        Ok(Dialog { parents, anchor })
+    }
+}
+
+/// Where `window` is, x, y, width, height, and border width.
+fn geometry(conn: &RustConnection, window: Window) -> Result<[i32; 5]> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    Ok([
+        i32::from(geometry.x),
+        i32::from(geometry.y),
+        i32::from(geometry.width),
+        i32::from(geometry.height),
+        i32::from(geometry.border_width),
+    ])
+}
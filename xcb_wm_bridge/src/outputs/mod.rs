@@ -0,0 +1,163 @@
+// Per-output exceptions, for TVs and projectors driven as secondary outputs: a rule
+// for a named output can give each group a layout and take its gaps away as the group
+// is shown there, and make windows fullscreen as they first map there. Layout is
+// qtile's, so each is asked of it through a template. The X side goes over a
+// connection of its own, which watches maps and the groups qtile shows.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::{OutputRule, OutputsConfig};
+use crate::state;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub struct OutputRules {
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: state::Atoms,
+    config: OutputsConfig,
+    /// The group each ruled output was last seen showing.
+    shown: Mutex<HashMap<String, String>>,
+    /// Windows made fullscreen already, which qtile maps again on every group switch.
+    fullscreened: Mutex<HashSet<Window>>,
+}
+
+impl OutputRules {
+    /// Connects to the X server and starts applying `config`'s rules.
+    pub fn new(config: OutputsConfig) -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let conn = Arc::new(conn);
+        let root = conn.setup().roots[screen].root;
+        let atoms = state::Atoms::new(&conn)?;
+        // Maps of top-level windows, and qtile's groups changing on the root.
+        let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+            .check()?;
+        conn.flush()?;
+
+        let rules = Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            config,
+            shown: Mutex::new(HashMap::new()),
+            fullscreened: Mutex::new(HashSet::new()),
+        });
+        rules.arrange()?;
+        let watcher = rules.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped applying output rules: {}", e);
+            }
+        });
+        Ok(rules)
+    }
+
+    fn watch(&self) -> Result<()> {
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => {
+                    self.arrange().and_then(|()| self.mapped(e.window))
+                }
+                Event::PropertyNotify(e)
+                    if e.window == self.root && e.atom == self.atoms.net_current_desktop =>
+                {
+                    self.arrange()
+                }
+                Event::DestroyNotify(e) => {
+                    self.fullscreened.lock().unwrap().remove(&e.window);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to apply an output rule: {}", e);
+            }
+        }
+    }
+
+    /// Applies the rules of the outputs that show another group than they did.
+    fn arrange(&self) -> Result<()> {
+        let snapshot = state::Snapshot::read(&self.conn, self.root, &self.atoms)?;
+        for rule in &self.config.rules {
+            let Some(output) = snapshot.outputs.iter().position(|o| o.name == rule.name) else {
+                continue;
+            };
+            let Some(group) = snapshot.shown_on(output) else {
+                continue;
+            };
+            let mut shown = self.shown.lock().unwrap();
+            if shown.get(&rule.name).map(String::as_str) == Some(group) {
+                continue;
+            }
+            shown.insert(rule.name.clone(), group.to_string());
+            drop(shown);
+            self.shown_group(rule, group)?;
+        }
+        Ok(())
+    }
+
+    /// Gives `group`, now shown on the output of `rule`, its layout and gaps.
+    fn shown_group(&self, rule: &OutputRule, group: &str) -> Result<()> {
+        if let Some(layout) = &rule.layout {
+            println!("Group {} on {} gets layout {}", group, rule.name, layout);
+            let values = [("group", group), ("layout", layout.as_str())];
+            crate::spawn_template(&self.config.set_layout, &values)?;
+        }
+        if !rule.gaps {
+            crate::spawn_template(&self.config.no_gaps, &[("group", group)])?;
+        }
+        Ok(())
+    }
+
+    /// Makes a newly mapped window fullscreen if it maps on an output that wants it.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if self.fullscreened.lock().unwrap().contains(&window) {
+            return Ok(());
+        }
+        let snapshot = state::Snapshot::read(&self.conn, self.root, &self.atoms)?;
+        let Some(client) = snapshot.client(window) else {
+            return Ok(());
+        };
+        let [x, y, width, height] = client.rect;
+        let output = snapshot.output_name(snapshot.output_at([x + width / 2, y + height / 2]));
+        let wanted = self
+            .config
+            .rules
+            .iter()
+            .any(|rule| rule.fullscreen && rule.name == output);
+        // Dialogs stay the size they ask for.
+        if !wanted || self.transient(window)? {
+            return Ok(());
+        }
+        trace::decision(&[window], format!("Making window {} fullscreen on {}", window, output));
+        self.fullscreened.lock().unwrap().insert(window);
+        let window = window.to_string();
+        crate::spawn_template(&self.config.fullscreen, &[("window", &window)])
+    }
+
+    fn transient(&self, window: Window) -> Result<bool> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(reply.value_len > 0)
+    }
+}
@@ -0,0 +1,155 @@
+// _NET_WM_PING: the client of every mapped window that supports it is pinged on an
+// interval, and one that leaves a ping unanswered for `timeout_ms` is reported to the
+// compositor as not responding, which draws its window gray and offers to kill it.
+// Clients answer by sending the ping back to the root window, where the stack's event
+// thread sees it; an answer reports the client responsive again.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+use crate::config::PingConfig;
+use crate::stack::SharedStack;
+
+/// Told whether the client of a window responds, whenever that changes.
+pub type Report = Box<dyn Fn(Window, bool) + Send>;
+
+/// Pings clients, and keeps track of which ones answer.
+pub struct Pinger {
+    conn: Arc<RustConnection>,
+    root: Window,
+    config: PingConfig,
+    stack: SharedStack,
+    wm_protocols: u32,
+    net_wm_ping: u32,
+    clients: Mutex<HashMap<Window, Client>>,
+    report: Mutex<Report>,
+}
+
+#[derive(Default)]
+struct Client {
+    /// Whether the window lists _NET_WM_PING in WM_PROTOCOLS; asked once.
+    supported: bool,
+    /// When the oldest unanswered ping was sent.
+    waiting_since: Option<Instant>,
+    unresponsive: bool,
+}
+
+impl Pinger {
+    pub fn new(
+        conn: Arc<RustConnection>,
+        root: Window,
+        config: PingConfig,
+        stack: SharedStack,
+        report: Report,
+    ) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
+        let atom = |name: &str| -> Result<u32, Box<dyn std::error::Error>> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        Ok(Arc::new(Self {
+            wm_protocols: atom("WM_PROTOCOLS")?,
+            net_wm_ping: atom("_NET_WM_PING")?,
+            conn,
+            root,
+            config,
+            stack,
+            clients: Mutex::new(HashMap::new()),
+            report: Mutex::new(report),
+        }))
+    }
+
+    /// Pings clients on their own thread.
+    pub fn spawn(self: &Arc<Self>) {
+        let pinger = self.clone();
+        let interval = Duration::from_millis(self.config.interval_ms.max(100));
+        thread::spawn(move || loop {
+            if let Err(e) = pinger.tick() {
+                eprintln!("Failed to ping clients: {}", e);
+            }
+            thread::sleep(interval);
+        });
+    }
+
+    /// Handles `event` if it is a client answering a ping.
+    pub fn event(&self, event: &Event) {
+        let Event::ClientMessage(e) = event else {
+            return;
+        };
+        let data = e.data.as_data32();
+        if e.window != self.root || e.type_ != self.wm_protocols || data[0] != self.net_wm_ping {
+            return;
+        }
+        let window = data[2];
+        let mut clients = self.clients.lock().unwrap();
+        let Some(client) = clients.get_mut(&window) else {
+            return;
+        };
+        client.waiting_since = None;
+        if client.unresponsive {
+            client.unresponsive = false;
+            (self.report.lock().unwrap())(window, true);
+        }
+    }
+
+    fn tick(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let windows: Vec<Window> = {
+            let stack = self.stack.lock().unwrap();
+            stack.focusable().map(|w| w.window).collect()
+        };
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|window, _| windows.contains(window));
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        for window in windows {
+            let client = match clients.get_mut(&window) {
+                Some(client) => client,
+                None => {
+                    let supported = self.supports_ping(window).unwrap_or(false);
+                    clients.entry(window).or_insert(Client {
+                        supported,
+                        ..Client::default()
+                    })
+                }
+            };
+            if !client.supported {
+                continue;
+            }
+            let late = client
+                .waiting_since
+                .is_some_and(|since| since.elapsed() >= timeout);
+            if late && !client.unresponsive {
+                client.unresponsive = true;
+                (self.report.lock().unwrap())(window, false);
+            }
+            let message = ClientMessageEvent::new(
+                32,
+                window,
+                self.wm_protocols,
+                [self.net_wm_ping, CURRENT_TIME, window, 0, 0],
+            );
+            self.conn
+                .send_event(false, window, EventMask::NO_EVENT, message)?;
+            client.waiting_since.get_or_insert_with(Instant::now);
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn supports_ping(&self, window: Window) -> Result<bool, Box<dyn std::error::Error>> {
+        let protocols = self
+            .conn
+            .get_property(false, window, self.wm_protocols, AtomEnum::ATOM, 0, 32)?
+            .reply()?;
+        Ok(protocols
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == self.net_wm_ping)))
+    }
+}
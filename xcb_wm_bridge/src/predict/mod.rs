@@ -0,0 +1,329 @@
+// Dry runs: what a command would change, worked out on a snapshot of qtile's state
+// instead of being carried out. The bridge knows what its own commands do to focus,
+// workspaces, and layers, and where windows go between groups; how qtile's layouts
+// then place the windows it can't know, so a prediction lists what it leaves open
+// next to what it changes, along with the commands of wm_config.toml that would run
+// and what would change outside the windows, like the volume.
+
+use std::collections::BTreeSet;
+
+use qtilerugo_ipc::wm::WmCommand;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use x11rb::protocol::xproto::Window;
+
+use crate::config::Config;
+use crate::layers::Layer;
+use crate::state::{Client, Snapshot};
+
+/// What a command would do to the state it starts from.
+pub struct Prediction<'a> {
+    config: &'a Config,
+    /// Whether tags are the workspace model.
+    tags: bool,
+    state: Snapshot,
+    /// Commands of wm_config.toml that would run.
+    runs: Vec<String>,
+    /// What the state doesn't show that would change.
+    side_effects: BTreeSet<&'static str>,
+    /// What would change in ways the bridge can't tell.
+    unknown: BTreeSet<&'static str>,
+}
+
+impl<'a> Prediction<'a> {
+    pub fn new(config: &'a Config, tags: bool, state: Snapshot) -> Self {
+        Self {
+            config,
+            tags,
+            state,
+            runs: Vec::new(),
+            side_effects: BTreeSet::new(),
+            unknown: BTreeSet::new(),
+        }
+    }
+
+    /// Changes the state as `cmd` would.
+    pub fn apply(&mut self, cmd: &WmCommand) -> Result<(), String> {
+        if let Some((key, unknown)) = config_command(cmd) {
+            let command = self.config.commands.get(key).cloned().unwrap_or_default();
+            self.runs.push(command);
+            self.unknown.extend(unknown);
+            if matches!(cmd, WmCommand::KillWindow) {
+                if let Some(window) = self.state.focused {
+                    self.close(window);
+                }
+            }
+            return Ok(());
+        }
+        match cmd {
+            WmCommand::KillClient(window) | WmCommand::CloseWindow(window) => {
+                self.client(*window)?;
+                self.close(*window);
+            }
+            WmCommand::SwitchWorkspace(_) | WmCommand::MoveToWorkspace { .. } if self.tags => {
+                self.unknown.insert("windows");
+            }
+            WmCommand::SwitchWorkspace(workspace) => {
+                let index = self.workspace(workspace)?;
+                self.runs.push(self.config.workspaces.switch.clone());
+                self.show(index);
+            }
+            WmCommand::MoveToWorkspace { window, workspace } => {
+                let index = self.workspace(workspace)?;
+                self.runs.push(self.config.workspaces.move_window.clone());
+                let shown = self.state.placed(index).1;
+                let client = self.client_mut(*window)?;
+                client.desktop = Some(index);
+                client.mapped = shown;
+                self.unknown.insert("geometry");
+                if self.state.focused == Some(*window) && !shown {
+                    self.unknown.insert("focus");
+                }
+            }
+            WmCommand::FocusWindow(window) => {
+                // qtile shows the window's group, or focuses the screen showing it.
+                match self.client(*window)?.desktop {
+                    Some(index) if !self.state.placed(index).1 => self.state.show(index),
+                    Some(index) => self.state.current = Some(index),
+                    None => {}
+                }
+                self.state.focused = Some(*window);
+            }
+            WmCommand::ViewTag(_) | WmCommand::ToggleWindowTag(_) => {
+                self.unknown.insert("windows");
+            }
+            WmCommand::ToggleAlwaysOnTop => {
+                let client = self.focused_mut()?;
+                client.layer = match client.layer {
+                    Layer::Above => Layer::Normal,
+                    _ => Layer::Above,
+                };
+            }
+            WmCommand::SetLayer(layer) => self.focused_mut()?.layer = *layer,
+            WmCommand::ToggleFakeFullscreen | WmCommand::ToggleCentered => {
+                self.focused_mut()?;
+                self.unknown.insert("geometry");
+            }
+            WmCommand::Spawn(_) => {
+                self.side_effects.insert("starts a program");
+                self.unknown.insert("windows");
+            }
+            WmCommand::VolumeUp | WmCommand::VolumeDown | WmCommand::VolumeMute => {
+                self.side_effects.insert("changes the volume");
+            }
+            WmCommand::BrightnessUp | WmCommand::BrightnessDown => {
+                self.side_effects.insert("changes the brightness");
+            }
+            WmCommand::MediaPlayPause | WmCommand::MediaNext | WmCommand::MediaPrevious => {
+                self.side_effects.insert("controls the media player");
+            }
+            WmCommand::SelectClipboard(_) => {
+                self.side_effects.insert("sets the clipboard");
+            }
+            WmCommand::ForgetFloating { .. } => {
+                self.side_effects.insert("forgets floating geometry");
+            }
+            WmCommand::SetClickThrough { .. } | WmCommand::SetInputRegion { .. } => {
+                self.side_effects.insert("shapes a window's input");
+            }
+            WmCommand::SendKey(_) | WmCommand::SendText(_) | WmCommand::ClickAt { .. } => {
+                self.side_effects.insert("sends input");
+                self.unknown.insert("windows");
+            }
+            // The history isn't the prediction's to read.
+            WmCommand::Undo => {
+                self.side_effects.insert("reverts the last action");
+                self.unknown.insert("windows");
+            }
+            WmCommand::TraceWindow { .. } => {
+                self.side_effects.insert("traces a window");
+            }
+            WmCommand::SetInsertionPoint(_) => {
+                self.side_effects.insert("sets where new windows go");
+            }
+            WmCommand::Repeat { count, command } => {
+                if *count > crate::MAX_REPEAT {
+                    return Err(format!(
+                        "{} repeats are more than {}",
+                        count,
+                        crate::MAX_REPEAT
+                    ));
+                }
+                for _ in 0..*count {
+                    self.apply(command)?;
+                }
+            }
+            WmCommand::Query { .. }
+            | WmCommand::ListClipboard
+            | WmCommand::GetMetrics
+            | WmCommand::GetUndoHistory => {}
+            // The commands of wm_config.toml, above.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The changes from `before` to the predicted state, as JSON: "focus" and
+    /// "workspace" as {"from", "to"}, "windows" by id with what changes of each,
+    /// "closed", "runs", "side_effects", and "unknown", each only when there is any.
+    pub fn diff(&self, before: &Snapshot) -> Value {
+        let after = &self.state;
+        let mut diff = Map::new();
+        if before.focused != after.focused {
+            diff.insert("focus".to_string(), change(before.focused, after.focused));
+        }
+        let name = |state: &Snapshot, index: Option<usize>| {
+            index.and_then(|index| state.workspaces.get(index).cloned())
+        };
+        let (from, to) = (name(before, before.current), name(after, after.current));
+        if from != to {
+            diff.insert("workspace".to_string(), change(from, to));
+        }
+
+        let mut windows = Map::new();
+        let mut closed = Vec::new();
+        for client in &before.clients {
+            match after.client(client.window) {
+                Some(predicted) => {
+                    let changes = client_changes(before, client, predicted);
+                    if !changes.is_empty() {
+                        windows.insert(client.window.to_string(), Value::Object(changes));
+                    }
+                }
+                None => closed.push(client.window),
+            }
+        }
+        if !windows.is_empty() {
+            diff.insert("windows".to_string(), Value::Object(windows));
+        }
+        if !closed.is_empty() {
+            diff.insert("closed".to_string(), json!(closed));
+        }
+        if !self.runs.is_empty() {
+            diff.insert("runs".to_string(), json!(self.runs));
+        }
+        if !self.side_effects.is_empty() {
+            diff.insert("side_effects".to_string(), json!(self.side_effects));
+        }
+        if !self.unknown.is_empty() {
+            diff.insert("unknown".to_string(), json!(self.unknown));
+        }
+        Value::Object(diff)
+    }
+
+    /// Shows workspace `index` on the focused screen, as qtile does.
+    fn show(&mut self, index: usize) {
+        let before = self.state.current;
+        self.state.show(index);
+        if before == self.state.current {
+            return;
+        }
+        // qtile focuses the window the group last had focused, which only it knows.
+        let occupied = self.state.clients.iter().any(|c| c.desktop == Some(index));
+        if occupied {
+            self.unknown.insert("focus");
+        } else {
+            self.state.focused = None;
+        }
+    }
+
+    fn close(&mut self, window: Window) {
+        self.state.clients.retain(|c| c.window != window);
+        if self.state.focused == Some(window) {
+            self.state.focused = None;
+            self.unknown.insert("focus");
+        }
+        self.unknown.insert("geometry");
+    }
+
+    fn workspace(&self, name: &str) -> Result<usize, String> {
+        self.state
+            .workspaces
+            .iter()
+            .position(|workspace| workspace == name)
+            .ok_or_else(|| format!("no workspace is named {}", name))
+    }
+
+    fn client(&self, window: Window) -> Result<&Client, String> {
+        self.state
+            .client(window)
+            .ok_or_else(|| format!("qtile doesn't manage window {}", window))
+    }
+
+    fn client_mut(&mut self, window: Window) -> Result<&mut Client, String> {
+        self.state
+            .clients
+            .iter_mut()
+            .find(|c| c.window == window)
+            .ok_or_else(|| format!("qtile doesn't manage window {}", window))
+    }
+
+    fn focused_mut(&mut self) -> Result<&mut Client, String> {
+        let window = self.state.focused.ok_or("no window is focused")?;
+        self.client_mut(window)
+    }
+}
+
+/// The key in wm_config.toml's [commands] of a command that runs one, with what it
+/// leaves to qtile.
+fn config_command(cmd: &WmCommand) -> Option<(&'static str, &'static [&'static str])> {
+    const FOCUS: &[&str] = &["focus"];
+    const GEOMETRY: &[&str] = &["geometry"];
+    const WINDOWS: &[&str] = &["windows"];
+    const EVERYTHING: &[&str] = &["focus", "geometry", "windows"];
+    Some(match cmd {
+        WmCommand::FocusLeft => ("focus_left", FOCUS),
+        WmCommand::FocusRight => ("focus_right", FOCUS),
+        WmCommand::FocusDown => ("focus_down", FOCUS),
+        WmCommand::FocusUp => ("focus_up", FOCUS),
+        WmCommand::FocusNext => ("focus_next", FOCUS),
+        WmCommand::ShuffleLeft => ("shuffle_left", GEOMETRY),
+        WmCommand::ShuffleRight => ("shuffle_right", GEOMETRY),
+        WmCommand::ShuffleDown => ("shuffle_down", GEOMETRY),
+        WmCommand::ShuffleUp => ("shuffle_up", GEOMETRY),
+        WmCommand::GrowLeft => ("grow_left", GEOMETRY),
+        WmCommand::GrowRight => ("grow_right", GEOMETRY),
+        WmCommand::GrowDown => ("grow_down", GEOMETRY),
+        WmCommand::GrowUp => ("grow_up", GEOMETRY),
+        WmCommand::Normalize => ("normalize", GEOMETRY),
+        WmCommand::ToggleSplit => ("toggle_split", GEOMETRY),
+        WmCommand::NextLayout => ("next_layout", GEOMETRY),
+        WmCommand::ToggleFullscreen => ("toggle_fullscreen", GEOMETRY),
+        WmCommand::ToggleFloating => ("toggle_floating", GEOMETRY),
+        WmCommand::SpawnTerminal => ("spawn_terminal", WINDOWS),
+        WmCommand::SpawnRofi => ("spawn_rofi", WINDOWS),
+        WmCommand::KillWindow => ("kill_window", &[]),
+        WmCommand::ReloadConfig => ("reload_config", EVERYTHING),
+        WmCommand::Shutdown => ("shutdown", EVERYTHING),
+        _ => return None,
+    })
+}
+
+/// What changes of `client` to `predicted`.
+fn client_changes(state: &Snapshot, client: &Client, predicted: &Client) -> Map<String, Value> {
+    let mut changes = Map::new();
+    let workspace = |c: &Client| {
+        c.desktop
+            .and_then(|index| state.workspaces.get(index).cloned())
+    };
+    if client.desktop != predicted.desktop {
+        let workspace = change(workspace(client), workspace(predicted));
+        changes.insert("workspace".to_string(), workspace);
+    }
+    if client.mapped != predicted.mapped {
+        let visible = change(client.mapped, predicted.mapped);
+        changes.insert("visible".to_string(), visible);
+    }
+    if client.layer != predicted.layer {
+        let layer = change(client.layer.name(), predicted.layer.name());
+        changes.insert("layer".to_string(), layer);
+    }
+    if client.rect != predicted.rect {
+        changes.insert("geometry".to_string(), change(client.rect, predicted.rect));
+    }
+    changes
+}
+
+fn change<T: Serialize>(from: T, to: T) -> Value {
+    json!({ "from": from, "to": to })
+}
@@ -0,0 +1,138 @@
+// Profiles: LaunchProfile sets up the workspaces of a [profiles.<name>] in one go. It
+// launches every app the profile lists, waits for a window of each to appear, told
+// apart by its WM_CLASS class and, optionally, a title pattern, then moves the windows
+// to their workspaces in the order listed, so the workspaces' layouts, which the
+// profile may set, place them in that order, and finally switches to switch_to. Apps
+// whose windows don't appear within timeout_ms are left where they are. The launch
+// runs on a thread of its own, with a connection of its own for reading the windows.
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::ProfileConfig;
+use crate::floating::glob;
+use crate::state;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How often the client list is read for new windows.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a launch does through the bridge: launching a command, moving a window to a
+/// workspace, setting a workspace's layout, and switching to a workspace.
+pub struct Hooks {
+    /// Takes a command, or a workspace to switch to.
+    pub spawn: Box<Hook>,
+    pub move_window: Box<MoveHook>,
+    /// Takes the workspace and the layout.
+    pub set_layout: Box<LayoutHook>,
+    pub switch: Box<Hook>,
+}
+
+type Hook = dyn Fn(&str) -> Result<()> + Send;
+type MoveHook = dyn Fn(Window, &str) -> Result<()> + Send;
+type LayoutHook = dyn Fn(&str, &str) -> Result<()> + Send;
+
+/// An app launched, and the window found for it.
+struct Pending {
+    workspace: usize,
+    class: String,
+    title: Option<String>,
+    window: Option<Window>,
+}
+
+/// Launches the profile `name` on a thread of its own.
+pub fn launch(name: String, profile: ProfileConfig, hooks: Hooks) -> Result<()> {
+    let (conn, _) = RustConnection::connect(None)?;
+    thread::spawn(move || {
+        if let Err(e) = run(&conn, &name, &profile, &hooks) {
+            eprintln!("Failed to launch profile {}: {}", name, e);
+        }
+    });
+    Ok(())
+}
+
+fn run(conn: &RustConnection, name: &str, profile: &ProfileConfig, hooks: &Hooks) -> Result<()> {
+    let root = x11rb::connection::Connection::setup(conn).roots[0].root;
+    let atoms = state::Atoms::new(conn)?;
+    // Windows open already are never taken for the apps launched.
+    let mut seen: HashSet<Window> = state::client_list(conn, root, &atoms)?
+        .into_iter()
+        .collect();
+    println!("Launching profile {}", name);
+
+    let mut pending = Vec::new();
+    for (index, workspace) in profile.workspaces.iter().enumerate() {
+        for app in &workspace.apps {
+            if let Err(e) = (hooks.spawn)(&app.command) {
+                eprintln!("Profile {}: failed to launch {}: {}", name, app.command, e);
+                continue;
+            }
+            pending.push(Pending {
+                workspace: index,
+                class: app.class.clone(),
+                title: app.title.clone(),
+                window: None,
+            });
+        }
+    }
+
+    // Windows are matched to the first app still waiting whose rule they meet; a window
+    // meeting none is looked at again, since titles often come after the map.
+    let deadline = Instant::now() + Duration::from_millis(profile.timeout_ms);
+    while pending.iter().any(|app| app.window.is_none()) && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+        for window in state::client_list(conn, root, &atoms)? {
+            if seen.contains(&window) {
+                continue;
+            }
+            let Ok(class) = WmClass::get(conn, window)?.reply() else {
+                continue;
+            };
+            let class = String::from_utf8_lossy(class.class()).into_owned();
+            let title = state::title_of(conn, window, &atoms).unwrap_or_default();
+            let app = pending.iter_mut().find(|app| {
+                app.window.is_none()
+                    && app.class == class
+                    && app
+                        .title
+                        .as_deref()
+                        .is_none_or(|pattern| glob(pattern, &title))
+            });
+            if let Some(app) = app {
+                app.window = Some(window);
+                seen.insert(window);
+            }
+        }
+    }
+
+    for (index, workspace) in profile.workspaces.iter().enumerate() {
+        if let Some(layout) = &workspace.layout {
+            if let Err(e) = (hooks.set_layout)(&workspace.workspace, layout) {
+                eprintln!(
+                    "Profile {}: failed to set the layout of {}: {}",
+                    name, workspace.workspace, e
+                );
+            }
+        }
+        for app in pending.iter().filter(|app| app.workspace == index) {
+            let Some(window) = app.window else {
+                eprintln!("Profile {}: no window of {} appeared", name, app.class);
+                continue;
+            };
+            if let Err(e) = (hooks.move_window)(window, &workspace.workspace) {
+                eprintln!("Profile {}: failed to move window {}: {}", name, window, e);
+            }
+        }
+    }
+    if let Some(workspace) = &profile.switch_to {
+        (hooks.switch)(workspace)?;
+    }
+    println!("Launched profile {}", name);
+    Ok(())
+}
@@ -0,0 +1,334 @@
+// Queries on the command socket, in the manner of bspc query: a domain and filters
+// written as options, like `windows --workspace focused --class firefox` or
+// `workspaces --occupied`, answered with the records, or with --ids just the ids, of
+// what matches every filter. Answers come from a fresh reading of qtile's state, so
+// scripts needn't filter a whole dump themselves.
+
+use serde_json::{json, Value};
+
+use crate::layers::Layer;
+use crate::state::{Client, Snapshot};
+
+pub use qtilerugo_ipc::wm::Domain;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    /// On, or for outputs showing, the named workspace, or "focused".
+    Workspace(String),
+    /// On the named output, or "focused", that of the focused workspace.
+    Output(String),
+    /// Windows with the class, instance, or title, or workspaces holding one.
+    Class(String),
+    Instance(String),
+    Title(String),
+    /// Windows in the layer.
+    Layer(Layer),
+    /// Workspaces or outputs by name.
+    Name(String),
+    Focused,
+    Urgent,
+    Visible,
+    Hidden,
+    Occupied,
+    Empty,
+    Primary,
+}
+
+impl Filter {
+    fn option(&self) -> &'static str {
+        match self {
+            Self::Workspace(_) => "--workspace",
+            Self::Output(_) => "--output",
+            Self::Class(_) => "--class",
+            Self::Instance(_) => "--instance",
+            Self::Title(_) => "--title",
+            Self::Layer(_) => "--layer",
+            Self::Name(_) => "--name",
+            Self::Focused => "--focused",
+            Self::Urgent => "--urgent",
+            Self::Visible => "--visible",
+            Self::Hidden => "--hidden",
+            Self::Occupied => "--occupied",
+            Self::Empty => "--empty",
+            Self::Primary => "--primary",
+        }
+    }
+
+    fn applies(&self, domain: Domain) -> bool {
+        use Filter::*;
+        match domain {
+            Domain::Windows => !matches!(self, Name(_) | Occupied | Empty | Primary),
+            Domain::Workspaces => !matches!(self, Workspace(_) | Layer(_) | Primary),
+            Domain::Outputs => matches!(self, Name(_) | Workspace(_) | Focused | Primary),
+        }
+    }
+}
+
+/// Answers a query on `snapshot` with a list of records, or of ids with --ids.
+pub fn run(snapshot: &Snapshot, domain: Domain, filters: &[String]) -> Result<Value, String> {
+    let (filters, ids) = parse(filters)?;
+    if let Some(filter) = filters.iter().find(|filter| !filter.applies(domain)) {
+        return Err(format!("{} doesn't apply to {:?}", filter.option(), domain));
+    }
+    let query = Query { snapshot, filters };
+    let matches = match domain {
+        Domain::Windows => {
+            let mut matches = Vec::new();
+            for client in &snapshot.clients {
+                if query.window(client)? {
+                    matches.push(if ids {
+                        json!(client.window)
+                    } else {
+                        query.window_record(client)
+                    });
+                }
+            }
+            matches
+        }
+        Domain::Workspaces => {
+            let mut matches = Vec::new();
+            for index in 0..snapshot.workspaces.len() {
+                if query.workspace(index)? {
+                    matches.push(if ids {
+                        json!(snapshot.workspaces[index])
+                    } else {
+                        query.workspace_record(index)
+                    });
+                }
+            }
+            matches
+        }
+        Domain::Outputs => {
+            let mut matches = Vec::new();
+            for index in 0..snapshot.outputs.len() {
+                if query.output(index)? {
+                    matches.push(if ids {
+                        json!(snapshot.outputs[index].name)
+                    } else {
+                        query.output_record(index)
+                    });
+                }
+            }
+            matches
+        }
+    };
+    Ok(Value::Array(matches))
+}
+
+/// The filters in `words`, and whether --ids was among them.
+fn parse(words: &[String]) -> Result<(Vec<Filter>, bool), String> {
+    let mut filters = Vec::new();
+    let mut ids = false;
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let mut value = || {
+            words
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", word))
+        };
+        filters.push(match word.as_str() {
+            "--ids" => {
+                ids = true;
+                continue;
+            }
+            "--workspace" => Filter::Workspace(value()?),
+            "--output" => Filter::Output(value()?),
+            "--class" => Filter::Class(value()?),
+            "--instance" => Filter::Instance(value()?),
+            "--title" => Filter::Title(value()?),
+            "--name" => Filter::Name(value()?),
+            "--layer" => {
+                let name = value()?;
+                Filter::Layer(
+                    Layer::from_name(&name).ok_or_else(|| format!("No layer is named {}", name))?,
+                )
+            }
+            "--focused" => Filter::Focused,
+            "--urgent" => Filter::Urgent,
+            "--visible" => Filter::Visible,
+            "--hidden" => Filter::Hidden,
+            "--occupied" => Filter::Occupied,
+            "--empty" => Filter::Empty,
+            "--primary" => Filter::Primary,
+            _ => return Err(format!("Unknown filter {}", word)),
+        });
+    }
+    Ok((filters, ids))
+}
+
+struct Query<'a> {
+    snapshot: &'a Snapshot,
+    filters: Vec<Filter>,
+}
+
+impl Query<'_> {
+    /// The index of the workspace `name` names.
+    fn workspace_index(&self, name: &str) -> Result<usize, String> {
+        let snapshot = self.snapshot;
+        if name == "focused" {
+            return snapshot
+                .current
+                .ok_or_else(|| "No workspace is focused".to_string());
+        }
+        snapshot
+            .workspaces
+            .iter()
+            .position(|workspace| workspace == name)
+            .ok_or_else(|| format!("No workspace is named {}", name))
+    }
+
+    /// The index of the output `name` names.
+    fn output_index(&self, name: &str) -> Result<usize, String> {
+        let snapshot = self.snapshot;
+        if name == "focused" {
+            return Ok(snapshot.current.map_or(0, |index| snapshot.placed(index).0));
+        }
+        snapshot
+            .outputs
+            .iter()
+            .position(|output| output.name == name)
+            .ok_or_else(|| format!("No output is named {}", name))
+    }
+
+    fn client_output(&self, client: &Client) -> usize {
+        let [x, y, width, height] = client.rect;
+        self.snapshot.output_at([x + width / 2, y + height / 2])
+    }
+
+    /// Whether `client` matches every filter.
+    fn window(&self, client: &Client) -> Result<bool, String> {
+        for filter in &self.filters {
+            let matches = match filter {
+                Filter::Workspace(name) => client.desktop == Some(self.workspace_index(name)?),
+                Filter::Output(name) => self.client_output(client) == self.output_index(name)?,
+                Filter::Class(class) => client.class == *class,
+                Filter::Instance(instance) => client.instance == *instance,
+                Filter::Title(title) => client.title == *title,
+                Filter::Layer(layer) => client.layer == *layer,
+                Filter::Focused => self.snapshot.focused == Some(client.window),
+                Filter::Urgent => client.urgent,
+                Filter::Visible => client.mapped,
+                Filter::Hidden => !client.mapped,
+                // The rest don't apply, which run checks first.
+                _ => false,
+            };
+            if !matches {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether workspace `index` matches every filter.
+    fn workspace(&self, index: usize) -> Result<bool, String> {
+        let snapshot = self.snapshot;
+        let (output, visible) = snapshot.placed(index);
+        let holds = |matches: &dyn Fn(&Client) -> bool| {
+            snapshot
+                .clients
+                .iter()
+                .any(|c| c.desktop == Some(index) && matches(c))
+        };
+        for filter in &self.filters {
+            let matches = match filter {
+                Filter::Name(name) => snapshot.workspaces[index] == *name,
+                Filter::Output(name) => output == self.output_index(name)?,
+                Filter::Class(class) => holds(&|c| c.class == *class),
+                Filter::Instance(instance) => holds(&|c| c.instance == *instance),
+                Filter::Title(title) => holds(&|c| c.title == *title),
+                Filter::Focused => snapshot.current == Some(index),
+                Filter::Urgent => snapshot.urgent(index),
+                Filter::Visible => visible,
+                Filter::Hidden => !visible,
+                Filter::Occupied => holds(&|_| true),
+                Filter::Empty => !holds(&|_| true),
+                // The rest don't apply, which run checks first.
+                _ => false,
+            };
+            if !matches {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether output `index` matches every filter.
+    fn output(&self, index: usize) -> Result<bool, String> {
+        let snapshot = self.snapshot;
+        for filter in &self.filters {
+            let matches = match filter {
+                Filter::Name(name) => snapshot.outputs[index].name == *name,
+                Filter::Workspace(name) => {
+                    snapshot.placed(self.workspace_index(name)?) == (index, true)
+                }
+                Filter::Focused => self.output_index("focused")? == index,
+                Filter::Primary => snapshot.outputs[index].primary,
+                // The rest don't apply, which run checks first.
+                _ => false,
+            };
+            if !matches {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn window_record(&self, client: &Client) -> Value {
+        let snapshot = self.snapshot;
+        let [x, y, width, height] = client.rect;
+        json!({
+            "id": client.window,
+            "workspace": client.desktop.and_then(|index| snapshot.workspaces.get(index)),
+            "output": snapshot.output_name(self.client_output(client)),
+            "title": client.title,
+            "class": client.class,
+            "instance": client.instance,
+            "layer": client.layer.name(),
+            "x": x,
+            "y": y,
+            "width": width,
+            "height": height,
+            "focused": snapshot.focused == Some(client.window),
+            "urgent": client.urgent,
+            "visible": client.mapped,
+        })
+    }
+
+    fn workspace_record(&self, index: usize) -> Value {
+        let snapshot = self.snapshot;
+        let (output, visible) = snapshot.placed(index);
+        let windows: Vec<u32> = snapshot
+            .clients
+            .iter()
+            .filter(|c| c.desktop == Some(index))
+            .map(|c| c.window)
+            .collect();
+        json!({
+            "name": snapshot.workspaces[index],
+            "index": index,
+            "output": snapshot.output_name(output),
+            "focused": snapshot.current == Some(index),
+            "visible": visible,
+            "urgent": snapshot.urgent(index),
+            "windows": windows,
+        })
+    }
+
+    fn output_record(&self, index: usize) -> Value {
+        let snapshot = self.snapshot;
+        let output = &snapshot.outputs[index];
+        let [x, y, width, height] = output.rect;
+        let focused = snapshot.current.map(|i| snapshot.placed(i).0) == Some(index);
+        json!({
+            "name": output.name,
+            "primary": output.primary,
+            "x": x,
+            "y": y,
+            "width": width,
+            "height": height,
+            "focused": focused,
+            "workspace": snapshot.shown_on(index),
+        })
+    }
+}
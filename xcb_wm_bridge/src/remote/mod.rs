@@ -0,0 +1,151 @@
+// Remote commands: with remote.listen set, the bridge also takes commands over TCP,
+// for kiosks and headless machines nobody has a shell on. Connections are TLS, with
+// the certificate and key in remote.cert and remote.key, and each starts with a line
+// holding the token in remote.token_file; after it, the connection speaks the command
+// socket's protocol, replies included. A wrong token is answered with an error, a
+// second later, and the connection closed. Remote clients aren't the bridge's own
+// user, so they can't send synthetic input.
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::RemoteConfig;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Tokens shorter than this are refused, being easy to guess.
+const MIN_TOKEN_LEN: usize = 16;
+/// How long a client has to finish the handshake and send its token.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Before a wrong token is answered, to slow down guessing.
+const AUTH_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+/// A client that sent the right token.
+pub type Stream = TlsStream<TcpStream>;
+
+/// Loads the certificate, key, and token, and listens on remote.listen, handing each
+/// client that sends the token to `serve`; returns at once when remote.listen isn't set.
+pub async fn listen<F>(config: &RemoteConfig, serve: F) -> Result<()>
+where
+    F: Fn(Stream) + Send + Sync + 'static,
+{
+    let Some(address) = &config.listen else {
+        return Ok(());
+    };
+    let acceptor = acceptor(config)?;
+    let token = token(config)?;
+    let listener = TcpListener::bind(address).await?;
+    println!("Taking commands over TLS on {}", address);
+    let serve = Arc::new(serve);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let token = token.clone();
+        let serve = serve.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(AUTH_TIMEOUT, authenticate(acceptor, stream, &token)).await {
+                Ok(Ok(Some(stream))) => {
+                    println!("Remote client {} connected", peer);
+                    serve(stream);
+                }
+                Ok(Ok(None)) => eprintln!("Remote client {} sent a wrong token", peer),
+                Ok(Err(e)) => eprintln!("Remote client {} failed to connect: {}", peer, e),
+                Err(_) => eprintln!("Remote client {} didn't send a token in time", peer),
+            }
+        });
+    }
+}
+
+/// Completes the handshake and reads the token line: the stream if it is `token`.
+async fn authenticate(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    token: &[u8],
+) -> io::Result<Option<Stream>> {
+    let mut stream = acceptor.accept(stream).await?;
+    // A byte at a time, leaving what follows the line to the command protocol.
+    let mut line = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        if byte == b'\n' {
+            break;
+        }
+        if line.len() > 1024 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "token line too long",
+            ));
+        }
+        line.push(byte);
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    if equal(&line, token) {
+        return Ok(Some(stream));
+    }
+    tokio::time::sleep(AUTH_FAILURE_DELAY).await;
+    let reply = qtilerugo_ipc::reply::json(&serde_json::json!({ "error": "Wrong token" }));
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(None)
+}
+
+fn acceptor(config: &RemoteConfig) -> Result<TlsAcceptor> {
+    let cert = config
+        .cert
+        .as_ref()
+        .ok_or("remote.listen needs remote.cert")?;
+    let key = config
+        .key
+        .as_ref()
+        .ok_or("remote.listen needs remote.key")?;
+    let certs = CertificateDer::pem_file_iter(cert)
+        .map_err(|e| format!("{}: {}", cert, e))?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", cert, e))?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {}", key, e))?;
+    let server = ServerConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+fn token(config: &RemoteConfig) -> Result<Arc<[u8]>> {
+    let path = config
+        .token_file
+        .as_ref()
+        .ok_or("remote.listen needs remote.token_file")?;
+    let token = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let token = token.trim();
+    if token.len() < MIN_TOKEN_LEN {
+        return Err(format!(
+            "the token in {} is shorter than {} bytes",
+            path, MIN_TOKEN_LEN
+        )
+        .into());
+    }
+    Ok(token.as_bytes().into())
+}
+
+/// Compares in time that doesn't depend on where they differ.
+fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (x, y)| difference | (x ^ y))
+            == 0
+}
@@ -0,0 +1,194 @@
+// Window search: SearchWindows matches a query fuzzily against the titles, classes,
+// and instances of qtile's windows on every workspace, answering with those that
+// match, best first, for a "jump to window" picker. Each word of the query must
+// appear in one of them in order, though not necessarily together, ignoring case; runs
+// of consecutive letters, and letters starting a word, count for more, and gaps less.
+// FocusSearchResult runs the same search and focuses a result, the best by default.
+
+use serde_json::{json, Value};
+
+use crate::state::{Client, Snapshot};
+
+/// For each letter matched.
+const MATCHED: i64 = 16;
+/// For a letter right after the one matched before it.
+const CONSECUTIVE: i64 = 24;
+/// For a letter starting the text or a word in it.
+const WORD_START: i64 = 20;
+/// Per letter skipped between two matched ones, up to MAX_GAP_PENALTY a gap.
+const GAP: i64 = 2;
+const MAX_GAP_PENALTY: i64 = 12;
+/// Classes and instances are short and chosen, so a match there counts for more.
+const CLASS_BONUS: i64 = 8;
+
+/// A window matching a query, and how well.
+pub struct Found<'a> {
+    pub client: &'a Client,
+    pub score: i64,
+}
+
+/// The windows of `snapshot` matching `query`, best first; every window, as qtile
+/// manages them, for an empty one.
+pub fn search<'a>(snapshot: &'a Snapshot, query: &str) -> Vec<Found<'a>> {
+    let words: Vec<Vec<char>> = query
+        .split_whitespace()
+        .map(|word| word.to_lowercase().chars().collect())
+        .collect();
+    let mut found: Vec<Found> = snapshot
+        .clients
+        .iter()
+        .filter_map(|client| {
+            let score = words.iter().try_fold(0, |total, word| {
+                let title = word_score(word, &client.title);
+                let class = word_score(word, &client.class).map(|score| score + CLASS_BONUS);
+                let instance = word_score(word, &client.instance).map(|score| score + CLASS_BONUS);
+                Some(total + title.max(class).max(instance)?)
+            })?;
+            Some(Found { client, score })
+        })
+        .collect();
+    // Stable, so equal scores stay in qtile's order.
+    found.sort_by_key(|found| std::cmp::Reverse(found.score));
+    found
+}
+
+/// The results of `query` as SearchWindows answers them.
+pub fn results(snapshot: &Snapshot, query: &str) -> Value {
+    let results: Vec<Value> = search(snapshot, query)
+        .into_iter()
+        .map(|Found { client, score }| {
+            json!({
+                "id": client.window,
+                "title": client.title,
+                "class": client.class,
+                "instance": client.instance,
+                "workspace": client.desktop.and_then(|index| snapshot.workspaces.get(index)),
+                "focused": snapshot.focused == Some(client.window),
+                "score": score,
+            })
+        })
+        .collect();
+    Value::Array(results)
+}
+
+/// How well `word`, lowercase, matches `text`, if each of its letters is in it in
+/// order.
+fn word_score(word: &[char], text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    if word.is_empty() {
+        return Some(0);
+    }
+    // Trying the match from every place the first letter is found, keeping the best.
+    (0..text.len())
+        .filter(|&start| text[start] == word[0])
+        .filter_map(|start| score_from(word, &text, start))
+        .max()
+}
+
+/// The score of matching `word` in `text` from `start`, each letter after the first
+/// taken at the next place it is found.
+fn score_from(word: &[char], text: &[char], start: usize) -> Option<i64> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut at = start;
+    for &letter in word {
+        let index = at + text[at..].iter().position(|&c| c == letter)?;
+        score += MATCHED;
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+        match previous {
+            Some(previous) if index == previous + 1 => score += CONSECUTIVE,
+            Some(previous) => score -= (GAP * (index - previous - 1) as i64).min(MAX_GAP_PENALTY),
+            None => {}
+        }
+        previous = Some(index);
+        at = index + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::Layer;
+
+    fn client(window: u32, desktop: usize, title: &str, class: &str) -> Client {
+        Client {
+            window,
+            desktop: Some(desktop),
+            title: title.to_string(),
+            class: class.to_string(),
+            instance: class.to_lowercase(),
+            rect: [0, 0, 100, 100],
+            mapped: true,
+            urgent: false,
+            layer: Layer::Normal,
+        }
+    }
+
+    fn snapshot(clients: Vec<Client>) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        snapshot.workspaces = vec!["1".to_string(), "2".to_string()];
+        snapshot.clients = clients;
+        snapshot
+    }
+
+    fn windows(snapshot: &Snapshot, query: &str) -> Vec<u32> {
+        search(snapshot, query)
+            .iter()
+            .map(|found| found.client.window)
+            .collect()
+    }
+
+    #[test]
+    fn prefix_beats_subsequence() {
+        let word: Vec<char> = "fire".chars().collect();
+        let prefix = word_score(&word, "firefox").unwrap();
+        let subsequence = word_score(&word, "find a receipt").unwrap();
+        assert!(prefix > subsequence);
+
+        let snapshot = snapshot(vec![
+            client(1, 0, "find a receipt", "Evince"),
+            client(2, 0, "firefox", "Navigator"),
+        ]);
+        assert_eq!(windows(&snapshot, "fire"), [2, 1]);
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        let word: Vec<char> = "term".chars().collect();
+        assert_eq!(word_score(&word, "TERMINAL"), word_score(&word, "terminal"));
+
+        let snapshot = snapshot(vec![client(1, 0, "Terminal", "Alacritty")]);
+        assert_eq!(windows(&snapshot, "TeRm"), [1]);
+        assert_eq!(windows(&snapshot, "ALAC"), [1]);
+    }
+
+    #[test]
+    fn no_match_is_none() {
+        let word: Vec<char> = "xyz".chars().collect();
+        assert_eq!(word_score(&word, "firefox"), None);
+        // Letters out of order don't match either.
+        let word: Vec<char> = "xof".chars().collect();
+        assert_eq!(word_score(&word, "firefox"), None);
+
+        let snapshot = snapshot(vec![client(1, 0, "firefox", "Navigator")]);
+        assert!(windows(&snapshot, "firefox xyz").is_empty());
+    }
+
+    #[test]
+    fn ties_across_workspaces_keep_qtiles_order() {
+        let snapshot = snapshot(vec![
+            client(3, 1, "notes", "Gedit"),
+            client(1, 0, "notes", "Gedit"),
+            client(2, 1, "mail", "Thunderbird"),
+        ]);
+        let found = search(&snapshot, "notes");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].score, found[1].score);
+        assert_eq!(windows(&snapshot, "notes"), [3, 1]);
+        // An empty query lists every window, as qtile manages them.
+        assert_eq!(windows(&snapshot, ""), [3, 1, 2]);
+    }
+}
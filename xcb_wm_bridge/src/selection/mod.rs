@@ -0,0 +1,152 @@
+// The ICCCM manager selection, WM_S<screen>, which says who manages the screen. The
+// bridge takes it at startup over a connection of its own, from a window made to hold
+// it: while another client holds it the bridge refuses to start, unless it is to
+// replace that client, in which case it takes the selection anyway and waits for the
+// old owner's window to go, as a manager that loses the selection is to exit. Taking
+// it is announced with a MANAGER message on the root. Should another client take it
+// later, the bridge is told with SelectionClear, and exits in turn.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _,
+    CreateWindowAux, EventMask, PropMode, Timestamp, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{COPY_DEPTH_FROM_PARENT, NONE};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How long a replaced manager gets to give up the screen.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLACE_POLL: Duration = Duration::from_millis(50);
+
+/// Holds WM_S<screen> for as long as it lives.
+pub struct Selection {
+    conn: RustConnection,
+    window: Window,
+    atom: Atom,
+    /// The selection's name, for messages.
+    name: String,
+}
+
+impl Selection {
+    /// Takes the selection, from another manager only with `replace`.
+    pub fn acquire(replace: bool) -> Result<Self> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let name = format!("WM_S{}", screen);
+        let atom = conn.intern_atom(false, name.as_bytes())?.reply()?.atom;
+        let manager = conn.intern_atom(false, b"MANAGER")?.reply()?.atom;
+
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            // The property change that gives a timestamp to take the selection with.
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        let time = timestamp(&conn, window)?;
+
+        let owner = conn.get_selection_owner(atom)?.reply()?.owner;
+        if owner != NONE {
+            if !replace {
+                return Err(format!(
+                    "another window manager holds {}; pass --replace to take over",
+                    name
+                )
+                .into());
+            }
+            println!("Replacing the window manager holding {}", name);
+        }
+        // Told when the old owner's window goes; an error means it already has.
+        let mask = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+        let waiting = owner != NONE && conn.change_window_attributes(owner, &mask)?.check().is_ok();
+        conn.set_selection_owner(window, atom, time)?;
+        if conn.get_selection_owner(atom)?.reply()?.owner != window {
+            return Err(format!("failed to take {}", name).into());
+        }
+        if waiting {
+            wait_for_destroy(&conn, owner, &name)?;
+        }
+
+        let event = ClientMessageEvent::new(32, root, manager, [time, atom, window, 0, 0]);
+        conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, event)?;
+        conn.flush()?;
+        Ok(Self {
+            conn,
+            window,
+            atom,
+            name,
+        })
+    }
+
+    /// Calls `lost`, on a thread of its own, once another client takes the selection.
+    pub fn watch(self, lost: impl FnOnce() + Send + 'static) {
+        thread::spawn(move || loop {
+            match self.conn.wait_for_event() {
+                Ok(Event::SelectionClear(e))
+                    if e.owner == self.window && e.selection == self.atom =>
+                {
+                    println!("Another window manager took {}", self.name);
+                    return lost();
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Stopped watching {}: {}", self.name, e);
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// The server's time now, from the notification of a change to `window`'s properties.
+pub fn timestamp(conn: &RustConnection, window: Window) -> Result<Timestamp> {
+    conn.change_property(
+        PropMode::APPEND,
+        window,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        8,
+        0,
+        &[],
+    )?;
+    conn.flush()?;
+    loop {
+        if let Event::PropertyNotify(e) = conn.wait_for_event()? {
+            if e.window == window {
+                return Ok(e.time);
+            }
+        }
+    }
+}
+
+/// Waits for the replaced manager's `window` to be destroyed.
+fn wait_for_destroy(conn: &RustConnection, window: Window, name: &str) -> Result<()> {
+    let started = Instant::now();
+    loop {
+        while let Some(event) = conn.poll_for_event()? {
+            if matches!(event, Event::DestroyNotify(e) if e.window == window) {
+                return Ok(());
+            }
+        }
+        if started.elapsed() > REPLACE_TIMEOUT {
+            return Err(format!("the window manager holding {} did not exit", name).into());
+        }
+        thread::sleep(REPLACE_POLL);
+    }
+}
@@ -0,0 +1,156 @@
+// Shaped windows, like xeyes or launchers cut to the outline of their artwork: the
+// bounding and clip shapes of every mapped window are followed through ShapeNotify,
+// and the rectangles both leave of a window are reported, so the renderer draws just
+// those, without the border and shadow it gives rectangular windows.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::shape::{self, ConnectionExt as _, SK};
+use x11rb::protocol::xproto::Window;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::stack::WindowStack;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Told the rectangles of each shaped window, x, y, width, height inside it, or `None`
+/// once a window isn't.
+pub type Report = Box<dyn Fn(Window, Option<Vec<[i32; 4]>>) + Send + Sync>;
+
+pub struct Shapes {
+    conn: Arc<RustConnection>,
+    report: Mutex<Option<Report>>,
+    shaped: Mutex<HashMap<Window, Vec<[i32; 4]>>>,
+}
+
+impl Shapes {
+    pub fn new(conn: Arc<RustConnection>) -> Result<Arc<Self>> {
+        if conn
+            .extension_information(shape::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Err("the X server has no SHAPE extension".into());
+        }
+        Ok(Arc::new(Self {
+            conn,
+            report: Mutex::new(None),
+            shaped: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Reports shaped windows to `report` from now on.
+    pub fn set_report(&self, report: Report) {
+        *self.report.lock().unwrap() = Some(report);
+    }
+
+    /// Follows the shapes of the windows mapped before the bridge started.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            if let Err(e) = self.mapped(window.window) {
+                eprintln!(
+                    "Failed to follow the shape of window {}: {}",
+                    window.window, e
+                );
+            }
+        }
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify, and the shape
+    /// changes of the windows.
+    pub fn event(&self, event: &Event) {
+        let result = match event {
+            Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+            // Input shapes only change where clicks go.
+            Event::ShapeNotify(e) if e.shape_kind != SK::INPUT => self.update(e.affected_window),
+            Event::DestroyNotify(e) => {
+                let removed = self.shaped.lock().unwrap().remove(&e.window);
+                if removed.is_some() {
+                    self.report(e.window, None);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to follow a window's shape: {}", e);
+        }
+    }
+
+    /// Asks for the shape changes of a newly mapped window and reads its shape.
+    fn mapped(&self, window: Window) -> Result<()> {
+        self.conn.shape_select_input(window, true)?;
+        self.conn.flush()?;
+        self.update(window)
+    }
+
+    /// Reads the shape of `window` again, reporting it if it changed.
+    fn update(&self, window: Window) -> Result<()> {
+        let extents = self.conn.shape_query_extents(window)?.reply()?;
+        let rects = if extents.bounding_shaped || extents.clip_shaped {
+            // Unshaped kinds come back as their default, the whole window.
+            let bounding = self.rectangles(window, SK::BOUNDING)?;
+            let clip = self.rectangles(window, SK::CLIP)?;
+            Some(intersect(&bounding, &clip))
+        } else {
+            None
+        };
+        let changed = {
+            let mut shaped = self.shaped.lock().unwrap();
+            match &rects {
+                Some(rects) => shaped.insert(window, rects.clone()).as_ref() != Some(rects),
+                None => shaped.remove(&window).is_some(),
+            }
+        };
+        if changed {
+            let message = match &rects {
+                Some(rects) => format!("Window {} is shaped, {} rects", window, rects.len()),
+                None => format!("Window {} is no longer shaped", window),
+            };
+            trace::decision(&[window], message);
+            self.report(window, rects);
+        }
+        Ok(())
+    }
+
+    fn rectangles(&self, window: Window, kind: SK) -> Result<Vec<[i32; 4]>> {
+        let reply = self.conn.shape_get_rectangles(window, kind)?.reply()?;
+        Ok(reply
+            .rectangles
+            .iter()
+            .map(|rect| {
+                [
+                    i32::from(rect.x),
+                    i32::from(rect.y),
+                    i32::from(rect.width),
+                    i32::from(rect.height),
+                ]
+            })
+            .collect())
+    }
+
+    fn report(&self, window: Window, rects: Option<Vec<[i32; 4]>>) {
+        if let Some(report) = &*self.report.lock().unwrap() {
+            report(window, rects);
+        }
+    }
+}
+
+/// The rectangles covered by both `a` and `b`.
+fn intersect(a: &[[i32; 4]], b: &[[i32; 4]]) -> Vec<[i32; 4]> {
+    let mut rects = Vec::new();
+    for &[ax, ay, aw, ah] in a {
+        for &[bx, by, bw, bh] in b {
+            let (left, top) = (ax.max(bx), ay.max(by));
+            let right = (ax + aw).min(bx + bw);
+            let bottom = (ay + ah).min(by + bh);
+            if right > left && bottom > top {
+                rects.push([left, top, right - left, bottom - top]);
+            }
+        }
+    }
+    rects
+}
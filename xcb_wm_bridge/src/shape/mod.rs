@@ -0,0 +1,169 @@
+// Input regions through the X Shape extension: a window can be made click-through,
+// with an empty input shape, so clicks reach whatever is under it, as HUD overlays and
+// transparent notes want, or given rectangles of its own that take input while the
+// rest lets it through. Windows of the configured classes are made click-through as
+// they map. The renderer is told which windows take no input at all, so its own
+// pointer handling, like dragging pinned windows, passes over them too.
+
+pub mod bounding;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::properties::WmClass;
+use x11rb::protocol::shape::{self, ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::{ClipOrdering, Rectangle, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::NONE;
+
+use crate::config::InputRegionConfig;
+use crate::stack::WindowStack;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Told whether each window with a region of its own is click-through all over.
+pub type Report = Box<dyn Fn(Window, bool) + Send + Sync>;
+
+pub struct InputRegions {
+    conn: Arc<RustConnection>,
+    config: InputRegionConfig,
+    report: Mutex<Option<Report>>,
+    /// The rectangles windows take input in, x, y, width, height inside them.
+    regions: Mutex<HashMap<Window, Vec<[i32; 4]>>>,
+}
+
+impl InputRegions {
+    pub fn new(conn: Arc<RustConnection>, config: InputRegionConfig) -> Result<Arc<Self>> {
+        if conn
+            .extension_information(shape::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Err("the X server has no SHAPE extension".into());
+        }
+        let version = conn.shape_query_version()?.reply()?;
+        // Input shapes came with 1.1.
+        if (version.major_version, version.minor_version) < (1, 1) {
+            return Err("the X server's SHAPE extension has no input shapes".into());
+        }
+        Ok(Arc::new(Self {
+            conn,
+            config,
+            report: Mutex::new(None),
+            regions: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Tells `report` which windows are click-through from now on.
+    pub fn set_report(&self, report: Report) {
+        *self.report.lock().unwrap() = Some(report);
+    }
+
+    /// Makes the windows of click-through classes mapped before the bridge started
+    /// click-through.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            if let Err(e) = self.mapped(window.window) {
+                eprintln!("Failed to shape window {}: {}", window.window, e);
+            }
+        }
+    }
+
+    /// Has `window` take input only in `rects`, inside it, none being click-through
+    /// all over, or everywhere again with `None`.
+    pub fn set(&self, window: Window, rects: Option<Vec<[i32; 4]>>) -> Result<()> {
+        let message = match &rects {
+            Some(rects) if rects.is_empty() => format!("Making window {} click-through", window),
+            Some(rects) => format!("Giving window {} {} input rects", window, rects.len()),
+            None => format!("Window {} takes input everywhere again", window),
+        };
+        trace::decision(&[window], message);
+        match &rects {
+            Some(rects) => {
+                let rectangles = rects
+                    .iter()
+                    .map(|&[x, y, width, height]| Rectangle {
+                        x: x as i16,
+                        y: y as i16,
+                        width: width.max(0) as u16,
+                        height: height.max(0) as u16,
+                    })
+                    .collect::<Vec<_>>();
+                self.conn.shape_rectangles(
+                    SO::SET,
+                    SK::INPUT,
+                    ClipOrdering::UNSORTED,
+                    window,
+                    0,
+                    0,
+                    &rectangles,
+                )?;
+            }
+            // No mask puts back the default, the window's bounding shape.
+            None => {
+                self.conn
+                    .shape_mask(SO::SET, SK::INPUT, window, 0, 0, NONE)?;
+            }
+        }
+        self.conn.flush()?;
+        let click_through = rects.as_ref().is_some_and(Vec::is_empty);
+        match rects {
+            Some(rects) => self.regions.lock().unwrap().insert(window, rects),
+            None => self.regions.lock().unwrap().remove(&window),
+        };
+        self.report(window, click_through);
+        Ok(())
+    }
+
+    /// Makes `window` click-through, or takes input everywhere again.
+    pub fn set_click_through(&self, window: Window, click_through: bool) -> Result<()> {
+        self.set(window, click_through.then(Vec::new))
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify.
+    pub fn event(&self, event: &Event) {
+        match event {
+            Event::MapNotify(e) if !e.override_redirect => {
+                if let Err(error) = self.mapped(e.window) {
+                    eprintln!("Failed to shape window {}: {}", e.window, error);
+                }
+            }
+            Event::DestroyNotify(e) => {
+                let removed = self.regions.lock().unwrap().remove(&e.window);
+                if removed.is_some() {
+                    self.report(e.window, false);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Makes a newly mapped window of a click-through class click-through, unless it
+    /// has a region already.
+    fn mapped(&self, window: Window) -> Result<()> {
+        if self.regions.lock().unwrap().contains_key(&window) {
+            return Ok(());
+        }
+        let Ok(class) = WmClass::get(&*self.conn, window)?.reply() else {
+            return Ok(());
+        };
+        let class = String::from_utf8_lossy(class.class());
+        if !self
+            .config
+            .click_through
+            .iter()
+            .any(|known| *known == class)
+        {
+            return Ok(());
+        }
+        self.set_click_through(window, true)
+    }
+
+    fn report(&self, window: Window, click_through: bool) {
+        if let Some(report) = &*self.report.lock().unwrap() {
+            report(window, click_through);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// Producer end of the renderer's shared-memory ring, whose layout is qtilerugo-ipc's.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::size_of;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+
+use memmap2::MmapMut;
+
+pub use qtilerugo_ipc::ring::{
+    Header, Record, FLAG_BLUR, FLAG_FOCUSED, FLAG_FULLSCREEN, MAGIC, RECORD_UPSERT, VERSION,
+};
+
+pub struct RingWriter {
+    map: MmapMut,
+    doorbell: File,
+    capacity: u64,
+}
+
+impl RingWriter {
+    /// Maps a ring received from the renderer and checks that its layout matches ours.
+    pub fn attach(memfd: OwnedFd, doorbell: OwnedFd) -> io::Result<Self> {
+        let memfd = File::from(memfd);
+        // SAFETY: the renderer never shrinks the memfd after handing it out.
+        let map = unsafe { MmapMut::map_mut(&memfd)? };
+        if map.len() < size_of::<Header>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "ring is too small"));
+        }
+        let writer = Self {
+            map,
+            doorbell: File::from(doorbell),
+            capacity: 0,
+        };
+        let header = writer.header();
+        if header.magic != MAGIC || header.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("incompatible ring (version {})", header.version),
+            ));
+        }
+        let capacity = header.capacity as u64;
+        if writer.map.len() < size_of::<Header>() + capacity as usize * size_of::<Record>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "ring is truncated"));
+        }
+        Ok(Self { capacity, ..writer })
+    }
+
+    /// Publishes `records` as one batch; returns `false` without writing if they do not fit.
+    pub fn push(&mut self, records: &[Record]) -> io::Result<bool> {
+        let header = self.header();
+        let head = header.head.load(Ordering::Relaxed);
+        let tail = header.tail.load(Ordering::Acquire);
+        if head - tail + records.len() as u64 > self.capacity {
+            return Ok(false);
+        }
+        // SAFETY: records follow the header, and slots in [head, tail + capacity) are ours.
+        let slots = unsafe { (self.map.as_mut_ptr() as *mut Header).add(1) as *mut Record };
+        for (i, record) in records.iter().enumerate() {
+            let slot = (head + i as u64) % self.capacity;
+            unsafe { slots.add(slot as usize).write(*record) };
+        }
+        self.header()
+            .head
+            .store(head + records.len() as u64, Ordering::Release);
+        self.doorbell.write_all(&1u64.to_ne_bytes())?;
+        Ok(true)
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: `attach` checked that the mapping holds a header.
+        unsafe { &*(self.map.as_ptr() as *const Header) }
+    }
+}
@@ -0,0 +1,251 @@
+mod properties;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ChangeWindowAttributesAux, ConnectionExt, EventMask, MapState, Place, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::NONE;
+
+pub use self::properties::Properties;
+
+/// Stack shared between the X event thread and command handling.
+pub type SharedStack = Arc<Mutex<WindowStack>>;
+
+/// A top-level window as last reported by the X server.
+#[derive(Debug, Clone, Copy)]
+pub struct StackedWindow {
+    pub window: Window,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub border_width: u16,
+    pub mapped: bool,
+    pub override_redirect: bool,
+    /// As last read, while mapped.
+    pub properties: Properties,
+}
+
+/// Children of the root window in stacking order, bottom first.
+#[derive(Debug, Default)]
+pub struct WindowStack {
+    windows: Vec<StackedWindow>,
+    /// The window qtile focuses, from _NET_ACTIVE_WINDOW.
+    focused: Option<Window>,
+}
+
+impl WindowStack {
+    pub fn shared() -> SharedStack {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    /// Reads the current children of `root` from the server.
+    pub fn load(conn: &RustConnection, root: Window) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut windows = Vec::new();
+        // QueryTree lists children bottom first.
+        for window in conn.query_tree(root)?.reply()?.children {
+            let (Ok(geometry), Ok(attributes)) = (
+                conn.get_geometry(window)?.reply(),
+                conn.get_window_attributes(window)?.reply(),
+            ) else {
+                // The window went away while we were asking about it.
+                continue;
+            };
+            windows.push(StackedWindow {
+                window,
+                x: geometry.x,
+                y: geometry.y,
+                width: geometry.width,
+                height: geometry.height,
+                border_width: geometry.border_width,
+                mapped: attributes.map_state != MapState::UNMAPPED,
+                override_redirect: attributes.override_redirect,
+                properties: Properties::default(),
+            });
+        }
+        Ok(Self {
+            windows,
+            focused: None,
+        })
+    }
+
+    /// Mapped windows, including override-redirect ones, bottom first.
+    pub fn mapped(&self) -> impl Iterator<Item = &StackedWindow> {
+        self.windows.iter().filter(|w| w.mapped)
+    }
+
+    /// Mapped windows a user would expect to focus, bottom first.
+    pub fn focusable(&self) -> impl Iterator<Item = &StackedWindow> {
+        self.mapped().filter(|w| !w.override_redirect)
+    }
+
+    /// Updates the stack from a root SubstructureNotify event; returns whether it changed.
+    pub fn apply(&mut self, root: Window, event: &Event) -> bool {
+        match event {
+            Event::CreateNotify(e) if e.parent == root => {
+                // New windows start at the top of the stack.
+                self.windows.push(StackedWindow {
+                    window: e.window,
+                    x: e.x,
+                    y: e.y,
+                    width: e.width,
+                    height: e.height,
+                    border_width: e.border_width,
+                    mapped: false,
+                    override_redirect: e.override_redirect,
+                    properties: Properties::default(),
+                });
+                true
+            }
+            Event::DestroyNotify(e) => self.remove(e.window),
+            Event::ReparentNotify(e) if e.parent != root => self.remove(e.window),
+            Event::ReparentNotify(e) => {
+                self.remove(e.window);
+                self.windows.push(StackedWindow {
+                    window: e.window,
+                    x: e.x,
+                    y: e.y,
+                    width: 0,
+                    height: 0,
+                    border_width: 0,
+                    mapped: false,
+                    override_redirect: e.override_redirect,
+                    properties: Properties::default(),
+                });
+                true
+            }
+            Event::MapNotify(e) => self.update(e.window, |w| w.mapped = true),
+            Event::UnmapNotify(e) => self.update(e.window, |w| w.mapped = false),
+            Event::ConfigureNotify(e) => {
+                let changed = self.update(e.window, |w| {
+                    w.x = e.x;
+                    w.y = e.y;
+                    w.width = e.width;
+                    w.height = e.height;
+                    w.border_width = e.border_width;
+                    w.override_redirect = e.override_redirect;
+                });
+                changed && self.restack(e.window, e.above_sibling)
+            }
+            Event::CirculateNotify(e) => {
+                let Some(index) = self.position(e.window) else {
+                    return false;
+                };
+                let window = self.windows.remove(index);
+                if e.place == Place::ON_TOP {
+                    self.windows.push(window);
+                } else {
+                    self.windows.insert(0, window);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn focused(&self) -> Option<Window> {
+        self.focused
+    }
+
+    /// Sets the focused window; returns whether it changed.
+    pub fn set_focused(&mut self, window: Option<Window>) -> bool {
+        let changed = self.focused != window;
+        self.focused = window;
+        changed
+    }
+
+    pub fn get(&self, window: Window) -> Option<&StackedWindow> {
+        self.windows.iter().find(|w| w.window == window)
+    }
+
+    /// Sets what was read of `window`'s properties; returns whether they changed.
+    pub fn set_properties(&mut self, window: Window, properties: Properties) -> bool {
+        match self.windows.iter_mut().find(|w| w.window == window) {
+            Some(w) if w.properties != properties => {
+                w.properties = properties;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn position(&self, window: Window) -> Option<usize> {
+        self.windows.iter().position(|w| w.window == window)
+    }
+
+    fn remove(&mut self, window: Window) -> bool {
+        let before = self.windows.len();
+        self.windows.retain(|w| w.window != window);
+        self.windows.len() != before
+    }
+
+    fn update(&mut self, window: Window, f: impl FnOnce(&mut StackedWindow)) -> bool {
+        match self.windows.iter_mut().find(|w| w.window == window) {
+            Some(w) => {
+                f(w);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves `window` directly above `sibling`, or to the bottom when there is none.
+    fn restack(&mut self, window: Window, sibling: Window) -> bool {
+        let Some(index) = self.position(window) else {
+            return false;
+        };
+        let entry = self.windows.remove(index);
+        let target = if sibling == NONE {
+            0
+        } else {
+            self.position(sibling).map_or(self.windows.len(), |i| i + 1)
+        };
+        self.windows.insert(target, entry);
+        true
+    }
+}
+
+/// Loads the stack and keeps it in sync from a dedicated X event thread.
+///
+/// `on_event` sees every event after the stack has been updated, including ones
+/// the stack does not care about, along with whether the stack changed.
+pub fn watch<F>(
+    conn: Arc<RustConnection>,
+    root: Window,
+    stack: SharedStack,
+    mut on_event: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(&Event, &WindowStack, bool) + Send + 'static,
+{
+    // The focus is a property of the root.
+    let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+    conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+        .check()?;
+    let properties = properties::Atoms::new(&conn)?;
+    {
+        let mut stack = stack.lock().unwrap();
+        *stack = WindowStack::load(&conn, root)?;
+        properties.watch_mapped(&conn, root, &mut stack);
+    }
+
+    thread::spawn(move || loop {
+        let event = match conn.wait_for_event() {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Lost X connection: {}", e);
+                break;
+            }
+        };
+        let mut stack = stack.lock().unwrap();
+        let mut changed = stack.apply(root, &event);
+        changed |= properties.event(&conn, &mut stack, &event);
+        on_event(&event, &stack, changed);
+    });
+    Ok(())
+}
@@ -0,0 +1,253 @@
+// What the renderer is told of a window besides its geometry, from its EWMH
+// properties: its type, its opacity, and whether it is fullscreen or wants what is
+// behind it blurred. They are read on the stack's connection when the window maps, and
+// again as they change, as is the window qtile focuses, from the root.
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use qtilerugo_ipc::renderer::WindowType;
+
+use super::WindowStack;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// The _NET_WM_WINDOW_TYPE atoms, each with the type effects and animations know it
+/// as, the same as for Xwayland windows.
+const WINDOW_TYPES: [(&str, WindowType); 14] = [
+    ("_NET_WM_WINDOW_TYPE_NORMAL", WindowType::Normal),
+    ("_NET_WM_WINDOW_TYPE_DIALOG", WindowType::Dialog),
+    ("_NET_WM_WINDOW_TYPE_DOCK", WindowType::Dock),
+    ("_NET_WM_WINDOW_TYPE_TOOLBAR", WindowType::Dock),
+    ("_NET_WM_WINDOW_TYPE_NOTIFICATION", WindowType::Notification),
+    ("_NET_WM_WINDOW_TYPE_MENU", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_DROPDOWN_MENU", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_POPUP_MENU", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_TOOLTIP", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_COMBO", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_DND", WindowType::Popup),
+    ("_NET_WM_WINDOW_TYPE_UTILITY", WindowType::Normal),
+    ("_NET_WM_WINDOW_TYPE_SPLASH", WindowType::Normal),
+    ("_NET_WM_WINDOW_TYPE_DESKTOP", WindowType::Normal),
+];
+
+/// What the renderer draws a window by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Properties {
+    pub window_type: WindowType,
+    /// _NET_WM_WINDOW_OPACITY, from 0 to 1.
+    pub opacity: f32,
+    /// _NET_WM_STATE_FULLSCREEN, as qtile sets it.
+    pub fullscreen: bool,
+    /// Whether the client asked for what is behind it blurred, with
+    /// _KDE_NET_WM_BLUR_BEHIND_REGION.
+    pub blur: bool,
+}
+
+impl Default for Properties {
+    fn default() -> Self {
+        Self {
+            window_type: WindowType::Normal,
+            opacity: 1.0,
+            fullscreen: false,
+            blur: false,
+        }
+    }
+}
+
+pub struct Atoms {
+    net_active_window: Atom,
+    net_wm_window_type: Atom,
+    net_wm_window_opacity: Atom,
+    net_wm_state: Atom,
+    net_wm_state_fullscreen: Atom,
+    blur_behind: Atom,
+    window_types: Vec<(Atom, WindowType)>,
+}
+
+impl Atoms {
+    pub fn new(conn: &RustConnection) -> Result<Self> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let window_types = WINDOW_TYPES
+            .iter()
+            .map(|&(name, window_type)| Ok((atom(name)?, window_type)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            net_active_window: atom("_NET_ACTIVE_WINDOW")?,
+            net_wm_window_type: atom("_NET_WM_WINDOW_TYPE")?,
+            net_wm_window_opacity: atom("_NET_WM_WINDOW_OPACITY")?,
+            net_wm_state: atom("_NET_WM_STATE")?,
+            net_wm_state_fullscreen: atom("_NET_WM_STATE_FULLSCREEN")?,
+            blur_behind: atom("_KDE_NET_WM_BLUR_BEHIND_REGION")?,
+            window_types,
+        })
+    }
+
+    /// Follows the properties of the windows in `stack`, which were mapped before the
+    /// bridge started and never send a MapNotify, and the focus on `root`.
+    pub fn watch_mapped(&self, conn: &RustConnection, root: Window, stack: &mut WindowStack) {
+        stack.set_focused(self.active_window(conn, root));
+        let mapped: Vec<_> = stack
+            .mapped()
+            .map(|w| (w.window, w.override_redirect))
+            .collect();
+        for (window, override_redirect) in mapped {
+            self.watch(conn, stack, window, override_redirect);
+        }
+    }
+
+    /// Reads the properties of a window that mapped, or again those of one that
+    /// changed; returns whether the stack changed.
+    pub fn event(&self, conn: &RustConnection, stack: &mut WindowStack, event: &Event) -> bool {
+        match event {
+            Event::MapNotify(e) => self.watch(conn, stack, e.window, e.override_redirect),
+            Event::PropertyNotify(e) if e.atom == self.net_active_window => {
+                stack.set_focused(self.active_window(conn, e.window))
+            }
+            Event::PropertyNotify(e) if self.watched(e.atom) => {
+                let override_redirect = stack.get(e.window).is_some_and(|w| w.override_redirect);
+                stack.set_properties(e.window, self.read(conn, e.window, override_redirect))
+            }
+            _ => false,
+        }
+    }
+
+    /// Asks for property changes of `window`, unless it is override-redirect and nobody
+    /// manages it, and reads its properties.
+    fn watch(
+        &self,
+        conn: &RustConnection,
+        stack: &mut WindowStack,
+        window: Window,
+        override_redirect: bool,
+    ) -> bool {
+        if !override_redirect {
+            // The same mask the client watcher selects on this connection.
+            let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+            if let Err(e) = conn.change_window_attributes(window, &aux) {
+                eprintln!("Not following the properties of window {}: {}", window, e);
+            }
+            let _ = conn.flush();
+        }
+        stack.set_properties(window, self.read(conn, window, override_redirect))
+    }
+
+    fn watched(&self, atom: Atom) -> bool {
+        [
+            self.net_wm_window_type,
+            self.net_wm_window_opacity,
+            self.net_wm_state,
+            self.blur_behind,
+        ]
+        .contains(&atom)
+    }
+
+    /// The properties of `window`, or the defaults for one that went away.
+    fn read(&self, conn: &RustConnection, window: Window, override_redirect: bool) -> Properties {
+        self.try_read(conn, window, override_redirect)
+            .unwrap_or_default()
+    }
+
+    fn try_read(
+        &self,
+        conn: &RustConnection,
+        window: Window,
+        override_redirect: bool,
+    ) -> Result<Properties> {
+        let opacity = conn
+            .get_property(
+                false,
+                window,
+                self.net_wm_window_opacity,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next())
+            .map_or(1.0, |opacity| (f64::from(opacity) / f64::from(u32::MAX)) as f32);
+        let fullscreen = conn
+            .get_property(false, window, self.net_wm_state, AtomEnum::ATOM, 0, 32)?
+            .reply()?
+            .value32()
+            .is_some_and(|mut states| states.any(|state| state == self.net_wm_state_fullscreen));
+        // An empty region blurs behind the whole window.
+        let blur = conn
+            .get_property(false, window, self.blur_behind, AtomEnum::ANY, 0, 0)?
+            .reply()?
+            .type_
+            != x11rb::NONE;
+        Ok(Properties {
+            window_type: self.window_type(conn, window, override_redirect)?,
+            opacity,
+            fullscreen,
+            blur,
+        })
+    }
+
+    /// The window _NET_ACTIVE_WINDOW names on `root`, if any.
+    fn active_window(&self, conn: &RustConnection, root: Window) -> Option<Window> {
+        conn.get_property(false, root, self.net_active_window, AtomEnum::WINDOW, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?
+            .value32()?
+            .next()
+            .filter(|&window| window != x11rb::NONE)
+    }
+
+    /// The first type in _NET_WM_WINDOW_TYPE the renderer knows; without one, a dialog
+    /// for a transient window, as EWMH has it, and a normal window otherwise.
+    /// Override-redirect windows are popups, whatever they say.
+    fn window_type(
+        &self,
+        conn: &RustConnection,
+        window: Window,
+        override_redirect: bool,
+    ) -> Result<WindowType> {
+        if override_redirect {
+            return Ok(WindowType::Popup);
+        }
+        let reply = conn
+            .get_property(
+                false,
+                window,
+                self.net_wm_window_type,
+                AtomEnum::ATOM,
+                0,
+                16,
+            )?
+            .reply()?;
+        let known = reply.value32().into_iter().flatten().find_map(|atom| {
+            self.window_types
+                .iter()
+                .find(|(known, _)| *known == atom)
+                .map(|&(_, window_type)| window_type)
+        });
+        if let Some(window_type) = known {
+            return Ok(window_type);
+        }
+        let transient = conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(if transient.value_len > 0 {
+            WindowType::Dialog
+        } else {
+            WindowType::Normal
+        })
+    }
+}
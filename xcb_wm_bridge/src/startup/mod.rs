@@ -0,0 +1,410 @@
+// Startup notification, after the freedesktop startup-notification spec, for what the
+// WM launches: each launch gets a DESKTOP_STARTUP_ID, is announced on the root window
+// with a `new:` message, and shows the pointer busy until a window carrying the id, or
+// of the launched process, maps, the application says `remove:`, or it times out. A
+// window that maps on another workspace than the one it was launched from is moved
+// back there. The X side goes over a connection of its own, which watches for maps
+// and startup messages.
+
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::cursor::Handle;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _,
+    CreateWindowAux, Cursor, EventMask, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::resource_manager;
+use x11rb::rust_connection::RustConnection;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+use crate::config::StartupConfig;
+use crate::state;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Told whether launches are pending, to show the pointer busy.
+pub type Report = Box<dyn Fn(bool) + Send + Sync>;
+
+/// How often launches are checked for timing out.
+const EXPIRY_INTERVAL: Duration = Duration::from_millis(250);
+/// Each startup message event carries this much of the message.
+const CHUNK: usize = 20;
+
+/// A launch waiting for its window.
+#[derive(Debug)]
+struct Launch {
+    id: String,
+    /// The launched shell's process id, for clients that don't set _NET_STARTUP_ID.
+    pid: u32,
+    /// The workspace it was launched from.
+    workspace: Option<String>,
+    deadline: Instant,
+}
+
+struct Atoms {
+    info_begin: Atom,
+    info: Atom,
+    startup_id: Atom,
+    wm_pid: Atom,
+    state: state::Atoms,
+}
+
+/// The root window's cursors for while launches are pending and after.
+struct Cursors {
+    busy: Cursor,
+    plain: Cursor,
+}
+
+pub struct Startup {
+    conn: Arc<RustConnection>,
+    root: Window,
+    /// Startup messages are sent from this window.
+    window: Window,
+    atoms: Atoms,
+    config: StartupConfig,
+    /// workspaces.move_window, for windows that map on another workspace.
+    move_window: String,
+    cursors: Option<Cursors>,
+    report: Report,
+    launches: Mutex<Vec<Launch>>,
+    next: AtomicU64,
+}
+
+impl Startup {
+    /// Connects to the X server and starts watching for launched windows.
+    pub fn new(config: StartupConfig, move_window: String, report: Report) -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let conn = Arc::new(conn);
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let atoms = Atoms {
+            info_begin: atom("_NET_STARTUP_INFO_BEGIN")?,
+            info: atom("_NET_STARTUP_INFO")?,
+            startup_id: atom("_NET_STARTUP_ID")?,
+            wm_pid: atom("_NET_WM_PID")?,
+            state: state::Atoms::new(&conn)?,
+        };
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().override_redirect(1),
+        )?;
+        // Maps of top-level windows, and applications' startup messages to the root.
+        let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))?
+            .check()?;
+        let cursors = if config.busy_cursor {
+            let database = resource_manager::new_from_default(&*conn)?;
+            let handle = Handle::new(&*conn, screen, &database)?.reply()?;
+            Some(Cursors {
+                busy: handle.load_cursor(&*conn, "left_ptr_watch")?,
+                plain: handle.load_cursor(&*conn, "left_ptr")?,
+            })
+        } else {
+            None
+        };
+        conn.flush()?;
+
+        let startup = Arc::new(Self {
+            conn,
+            root,
+            window,
+            atoms,
+            config,
+            move_window,
+            cursors,
+            report,
+            launches: Mutex::new(Vec::new()),
+            next: AtomicU64::new(1),
+        });
+        let watcher = startup.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped watching launched windows: {}", e);
+            }
+        });
+        let expiry = startup.clone();
+        thread::spawn(move || loop {
+            thread::sleep(EXPIRY_INTERVAL);
+            expiry.expire();
+        });
+        Ok(startup)
+    }
+
+    /// Runs `command` in a shell with a startup id of its own, announced on the root.
+    pub fn spawn(&self, command: &str) -> Result<()> {
+        let id = format!(
+            "qtilerugo/{}-{}",
+            std::process::id(),
+            self.next.fetch_add(1, Ordering::Relaxed)
+        );
+        let name = command.split_whitespace().next().unwrap_or(command);
+        let workspace = state::current_workspace(&self.conn, self.root, &self.atoms.state)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to read the current workspace: {}", e);
+                None
+            });
+        let mut message = format!(
+            "new: ID={} NAME={} BIN={}",
+            quote(&id),
+            quote(name),
+            quote(name)
+        );
+        if let Some(index) = state::workspaces(&self.conn, self.root, &self.atoms.state)
+            .ok()
+            .and_then(|names| names.iter().position(|n| Some(n) == workspace.as_ref()))
+        {
+            message.push_str(&format!(" DESKTOP={}", index));
+        }
+        self.broadcast(&message)?;
+
+        println!("Launching {} as {}", command, id);
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("DESKTOP_STARTUP_ID", &id)
+            .spawn()?;
+        let mut launches = self.launches.lock().unwrap();
+        launches.push(Launch {
+            id,
+            pid: child.id(),
+            workspace,
+            deadline: Instant::now() + Duration::from_millis(self.config.timeout_ms),
+        });
+        if launches.len() == 1 {
+            self.show_busy(true);
+        }
+        Ok(())
+    }
+
+    /// Ends launches whose windows map, or whose applications say they started.
+    fn watch(&self) -> Result<()> {
+        // Messages come in pieces, from each sender in turn.
+        let mut messages: HashMap<Window, Vec<u8>> = HashMap::new();
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => self.mapped(e.window),
+                Event::ClientMessage(e) if e.window != self.window => {
+                    let data = e.data.as_data8();
+                    if e.type_ == self.atoms.info_begin {
+                        messages.insert(e.window, Vec::new());
+                    } else if e.type_ != self.atoms.info {
+                        continue;
+                    }
+                    let Some(message) = messages.get_mut(&e.window) else {
+                        continue;
+                    };
+                    message.extend_from_slice(&data);
+                    if let Some(end) = message.iter().position(|&b| b == 0) {
+                        let message = messages.remove(&e.window).unwrap_or_default();
+                        let message = String::from_utf8_lossy(&message[..end]).into_owned();
+                        if let Some(id) = removed_id(&message) {
+                            self.finish(|launch| launch.id == id, None);
+                        }
+                    }
+                }
+                Event::DestroyNotify(e) => {
+                    messages.remove(&e.window);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Ends the launch `window` is of, if any.
+    fn mapped(&self, window: Window) {
+        let id = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms.startup_id,
+                self.atoms.state.utf8_string,
+                0,
+                256,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+            .filter(|id| !id.is_empty());
+        if let Some(id) = id {
+            self.finish(|launch| launch.id == id, Some(window));
+            return;
+        }
+        let pid = self
+            .conn
+            .get_property(false, window, self.atoms.wm_pid, AtomEnum::CARDINAL, 0, 1)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()));
+        if let Some(pid) = pid {
+            self.finish(|launch| launch.pid == pid, Some(window));
+        }
+    }
+
+    /// Ends the first launch `matches`, placing its `window`, if one mapped.
+    fn finish(&self, matches: impl Fn(&Launch) -> bool, window: Option<Window>) {
+        let launch = {
+            let mut launches = self.launches.lock().unwrap();
+            let Some(index) = launches.iter().position(matches) else {
+                return;
+            };
+            let launch = launches.remove(index);
+            if launches.is_empty() {
+                self.show_busy(false);
+            }
+            launch
+        };
+        println!("{} started", launch.id);
+        if let Some(window) = window {
+            // Telling everyone done, as launchers do for clients that never say so.
+            if let Err(e) = self.broadcast(&format!("remove: ID={}", quote(&launch.id))) {
+                eprintln!("Failed to end startup notification: {}", e);
+            }
+            if self.config.launch_workspace {
+                self.place(window, &launch);
+            }
+        }
+    }
+
+    /// Moves `window` to the workspace `launch` was started from if it mapped on the
+    /// current one instead, which is where qtile puts windows its rules don't place.
+    fn place(&self, window: Window, launch: &Launch) {
+        let Some(workspace) = &launch.workspace else {
+            return;
+        };
+        let atoms = &self.atoms.state;
+        let (Ok(names), Ok(Some(desktop)), Ok(Some(current))) = (
+            state::workspaces(&self.conn, self.root, atoms),
+            state::desktop_of(&self.conn, window, atoms),
+            state::current_workspace(&self.conn, self.root, atoms),
+        ) else {
+            return;
+        };
+        let on = names.get(desktop);
+        if on != Some(&current) || on == Some(workspace) {
+            return;
+        }
+        println!("Moving window {} back to workspace {}", window, workspace);
+        let window = window.to_string();
+        let values = [
+            ("window", window.as_str()),
+            ("workspace", workspace.as_str()),
+        ];
+        if let Err(e) = crate::spawn_template(&self.move_window, &values) {
+            eprintln!("Failed to move a launched window: {}", e);
+        }
+    }
+
+    /// Gives up on launches past their deadline.
+    fn expire(&self) {
+        let now = Instant::now();
+        let expired: Vec<Launch> = {
+            let mut launches = self.launches.lock().unwrap();
+            if launches.iter().all(|launch| launch.deadline > now) {
+                return;
+            }
+            let (expired, pending) = std::mem::take(&mut *launches)
+                .into_iter()
+                .partition(|launch| launch.deadline <= now);
+            *launches = pending;
+            if launches.is_empty() {
+                self.show_busy(false);
+            }
+            expired
+        };
+        for launch in expired {
+            println!("{} timed out", launch.id);
+            if let Err(e) = self.broadcast(&format!("remove: ID={}", quote(&launch.id))) {
+                eprintln!("Failed to end startup notification: {}", e);
+            }
+        }
+    }
+
+    fn show_busy(&self, busy: bool) {
+        if let Some(cursors) = &self.cursors {
+            let cursor = if busy { cursors.busy } else { cursors.plain };
+            let aux = ChangeWindowAttributesAux::new().cursor(cursor);
+            if let Err(e) = self.conn.change_window_attributes(self.root, &aux) {
+                eprintln!("Failed to change the root cursor: {}", e);
+            }
+            let _ = self.conn.flush();
+        }
+        (self.report)(busy);
+    }
+
+    /// Sends `message` to the root window the way the spec has it: in pieces of 20
+    /// bytes, the first as _NET_STARTUP_INFO_BEGIN, ending in a null.
+    fn broadcast(&self, message: &str) -> Result<()> {
+        let mut bytes = message.as_bytes().to_vec();
+        bytes.push(0);
+        for (i, chunk) in bytes.chunks(CHUNK).enumerate() {
+            let mut data = [0u8; CHUNK];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let type_ = if i == 0 {
+                self.atoms.info_begin
+            } else {
+                self.atoms.info
+            };
+            let event = ClientMessageEvent::new(8, self.window, type_, data);
+            self.conn
+                .send_event(false, self.root, EventMask::PROPERTY_CHANGE, event)?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// `value` quoted as startup messages quote values.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The ID of a `remove:` message.
+fn removed_id(message: &str) -> Option<String> {
+    let mut rest = message.strip_prefix("remove:")?.trim_start();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let mut value = String::new();
+        let mut chars = after.chars();
+        let mut quoted = false;
+        let mut consumed = 0;
+        while let Some(c) = chars.next() {
+            consumed += c.len_utf8();
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        consumed += next.len_utf8();
+                        value.push(next);
+                    }
+                }
+                ' ' if !quoted => break,
+                c => value.push(c),
+            }
+        }
+        if key.trim() == "ID" {
+            return Some(value);
+        }
+        rest = after[consumed..].trim_start();
+    }
+    None
+}
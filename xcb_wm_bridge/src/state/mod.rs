@@ -0,0 +1,376 @@
+// qtile's state as its EWMH properties on the root window and its clients give it:
+// its groups, which is current, its clients and their groups, and the monitors. The
+// i3 shim and queries answer from a fresh reading each time.
+
+use x11rb::properties::{WmClass, WmHints};
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, MapState, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::layers::Layer;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// The atoms the snapshot is read from.
+pub struct Atoms {
+    pub net_desktop_names: Atom,
+    pub net_number_of_desktops: Atom,
+    pub net_current_desktop: Atom,
+    pub net_active_window: Atom,
+    pub net_client_list: Atom,
+    pub net_wm_desktop: Atom,
+    pub net_wm_name: Atom,
+    pub utf8_string: Atom,
+}
+
+impl Atoms {
+    pub fn new(conn: &RustConnection) -> Result<Self> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        Ok(Self {
+            net_desktop_names: atom("_NET_DESKTOP_NAMES")?,
+            net_number_of_desktops: atom("_NET_NUMBER_OF_DESKTOPS")?,
+            net_current_desktop: atom("_NET_CURRENT_DESKTOP")?,
+            net_active_window: atom("_NET_ACTIVE_WINDOW")?,
+            net_client_list: atom("_NET_CLIENT_LIST")?,
+            net_wm_desktop: atom("_NET_WM_DESKTOP")?,
+            net_wm_name: atom("_NET_WM_NAME")?,
+            utf8_string: atom("UTF8_STRING")?,
+        })
+    }
+}
+
+/// A monitor, in root window pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub name: String,
+    pub primary: bool,
+    pub rect: [i32; 4],
+}
+
+/// A client qtile manages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Client {
+    pub window: Window,
+    /// The index of its group, or `None` for sticky windows.
+    pub desktop: Option<usize>,
+    pub title: String,
+    pub class: String,
+    pub instance: String,
+    /// Where it is, in root window pixels.
+    pub rect: [i32; 4],
+    pub mapped: bool,
+    pub urgent: bool,
+    /// Normal as read; the bridge keeps the layers, and fills them in.
+    pub layer: Layer,
+}
+
+/// One reading of qtile's state.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub outputs: Vec<Output>,
+    /// Group names, in index order.
+    pub workspaces: Vec<String>,
+    /// The group of the focused screen.
+    pub current: Option<usize>,
+    pub focused: Option<Window>,
+    /// In the order qtile manages them.
+    pub clients: Vec<Client>,
+    /// The output each workspace is on, and whether it is shown there.
+    placement: Vec<(usize, bool)>,
+}
+
+impl Snapshot {
+    pub fn read(conn: &RustConnection, root: Window, atoms: &Atoms) -> Result<Self> {
+        let workspaces = workspaces(conn, root, atoms)?;
+        let current = current(conn, root, atoms, &workspaces)?;
+        let focused = active_window(conn, root, atoms)?;
+
+        let mut clients = Vec::new();
+        for window in client_list(conn, root, atoms)? {
+            // Clients may go away while they are read.
+            if let Ok(client) = read_client(conn, root, atoms, window) {
+                clients.push(client);
+            }
+        }
+        let outputs = read_outputs(conn, root).unwrap_or_default();
+        let pointer = conn
+            .query_pointer(root)?
+            .reply()
+            .map(|p| [i32::from(p.root_x), i32::from(p.root_y)])
+            .unwrap_or_default();
+
+        let mut snapshot = Self {
+            outputs,
+            workspaces,
+            current,
+            focused,
+            clients,
+            placement: Vec::new(),
+        };
+        snapshot.placement = (0..snapshot.workspaces.len())
+            .map(|index| snapshot.place(index, pointer))
+            .collect();
+        Ok(snapshot)
+    }
+
+    /// Where workspace `index` is: on the output of a window of it that is mapped,
+    /// which qtile only maps on the groups its screens show, or, for the current one
+    /// without windows, the output under the pointer.
+    fn place(&self, index: usize, pointer: [i32; 2]) -> (usize, bool) {
+        let mapped = self
+            .clients
+            .iter()
+            .find(|c| c.mapped && c.desktop == Some(index));
+        if let Some(client) = mapped {
+            let [x, y, width, height] = client.rect;
+            return (self.output_at([x + width / 2, y + height / 2]), true);
+        }
+        if self.current == Some(index) {
+            return (self.output_at(pointer), true);
+        }
+        let primary = self.outputs.iter().position(|o| o.primary);
+        (primary.unwrap_or(0), false)
+    }
+
+    /// The output workspace `index` is on, and whether it is shown there.
+    pub fn placed(&self, index: usize) -> (usize, bool) {
+        self.placement.get(index).copied().unwrap_or((0, false))
+    }
+
+    pub fn output_at(&self, [x, y]: [i32; 2]) -> usize {
+        self.outputs
+            .iter()
+            .position(|o| {
+                let [left, top, width, height] = o.rect;
+                x >= left && x < left + width && y >= top && y < top + height
+            })
+            .unwrap_or(0)
+    }
+
+    pub fn client(&self, window: Window) -> Option<&Client> {
+        self.clients.iter().find(|c| c.window == window)
+    }
+
+    /// The workspace i3 would give `number`: the first whose name starts with it, or
+    /// else one named it.
+    pub fn numbered(&self, number: i64) -> String {
+        self.workspaces
+            .iter()
+            .find(|name| number_of(name) == number)
+            .cloned()
+            .unwrap_or_else(|| number.to_string())
+    }
+
+    /// The workspace `offset` after the current one, wrapping around.
+    pub fn neighbour(&self, offset: isize) -> Option<String> {
+        let count = self.workspaces.len() as isize;
+        let current = self.current? as isize;
+        let index = (current + offset).rem_euclid(count);
+        self.workspaces.get(index as usize).cloned()
+    }
+
+    pub fn output_name(&self, output: usize) -> String {
+        self.outputs
+            .get(output)
+            .map_or_else(|| "xroot-0".to_string(), |o| o.name.clone())
+    }
+
+    pub fn output_rect(&self, output: usize) -> [i32; 4] {
+        self.outputs.get(output).map_or([0, 0, 0, 0], |o| o.rect)
+    }
+
+    /// The workspace shown on `output`, preferring the focused one.
+    pub fn shown_on(&self, output: usize) -> Option<&str> {
+        let mut shown = (0..self.workspaces.len()).filter(|&i| self.placed(i) == (output, true));
+        let first = shown.clone().next();
+        shown
+            .find(|&i| self.current == Some(i))
+            .or(first)
+            .map(|i| self.workspaces[i].as_str())
+    }
+
+    /// Shows workspace `index` on the focused screen, as qtile does: the group it
+    /// showed is hidden, or moved to the screen `index` was shown on, if any.
+    pub fn show(&mut self, index: usize) {
+        let Some(current) = self.current.filter(|&current| current != index) else {
+            return;
+        };
+        let (output, _) = self.placed(current);
+        self.placement.resize(self.workspaces.len(), (0, false));
+        self.placement[current] = match self.placed(index) {
+            (other, true) => (other, true),
+            _ => (output, false),
+        };
+        self.placement[index] = (output, true);
+        self.current = Some(index);
+        for client in &mut self.clients {
+            if let Some(desktop) = client.desktop.filter(|&d| d == current || d == index) {
+                client.mapped = self.placement[desktop].1;
+            }
+        }
+    }
+
+    pub fn urgent(&self, index: usize) -> bool {
+        self.clients
+            .iter()
+            .any(|c| c.urgent && c.desktop == Some(index))
+    }
+}
+
+/// The names of qtile's groups, in index order.
+pub fn workspaces(conn: &RustConnection, root: Window, atoms: &Atoms) -> Result<Vec<String>> {
+    let names = property(conn, root, atoms.net_desktop_names, atoms.utf8_string)?;
+    let mut workspaces: Vec<String> = names
+        .split(|&b| b == 0)
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect();
+    // The list ends in a null, leaving an empty name after it.
+    if workspaces.last().is_some_and(String::is_empty) {
+        workspaces.pop();
+    }
+    let count = cardinal(conn, root, atoms.net_number_of_desktops)?.unwrap_or(0) as usize;
+    while workspaces.len() < count {
+        workspaces.push((workspaces.len() + 1).to_string());
+    }
+    Ok(workspaces)
+}
+
+/// The index in `workspaces` of the group of the focused screen.
+fn current(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    workspaces: &[String],
+) -> Result<Option<usize>> {
+    Ok(cardinal(conn, root, atoms.net_current_desktop)?
+        .map(|index| index as usize)
+        .filter(|&index| index < workspaces.len()))
+}
+
+/// The name of the group of the focused screen.
+pub fn current_workspace(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+) -> Result<Option<String>> {
+    let mut workspaces = workspaces(conn, root, atoms)?;
+    Ok(current(conn, root, atoms, &workspaces)?.map(|index| workspaces.swap_remove(index)))
+}
+
+/// The windows qtile manages, in the order it manages them.
+pub fn client_list(conn: &RustConnection, root: Window, atoms: &Atoms) -> Result<Vec<Window>> {
+    windows(conn, root, atoms.net_client_list)
+}
+
+/// The focused window, as qtile has it.
+pub fn active_window(conn: &RustConnection, root: Window, atoms: &Atoms) -> Result<Option<Window>> {
+    Ok(windows(conn, root, atoms.net_active_window)?
+        .into_iter()
+        .find(|&window| window != x11rb::NONE))
+}
+
+/// The index of the group `window` is on, or `None` for sticky windows.
+pub fn desktop_of(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<Option<usize>> {
+    // 0xFFFFFFFF is on every desktop.
+    Ok(cardinal(conn, window, atoms.net_wm_desktop)?
+        .filter(|&desktop| desktop != u32::MAX)
+        .map(|desktop| desktop as usize))
+}
+
+/// `window`'s _NET_WM_NAME, or its WM_NAME without one.
+pub fn title_of(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<String> {
+    let mut title = property(conn, window, atoms.net_wm_name, atoms.utf8_string)?;
+    if title.is_empty() {
+        title = property(conn, window, AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())?;
+    }
+    Ok(String::from_utf8_lossy(&title).into_owned())
+}
+
+/// The number i3 gives a workspace named `name`: what its name starts with, or -1.
+pub fn number_of(name: &str) -> i64 {
+    let digits: String = name.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().unwrap_or(-1)
+}
+fn read_client(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    window: Window,
+) -> Result<Client> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let position = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+    let attributes = conn.get_window_attributes(window)?.reply()?;
+    let (instance, class) = match WmClass::get(conn, window)?.reply() {
+        Ok(class) => (
+            String::from_utf8_lossy(class.instance()).into_owned(),
+            String::from_utf8_lossy(class.class()).into_owned(),
+        ),
+        _ => Default::default(),
+    };
+    let title = title_of(conn, window, atoms)?;
+    let urgent = WmHints::get(conn, window)?
+        .reply()
+        .is_ok_and(|hints| hints.urgent);
+    let desktop = desktop_of(conn, window, atoms)?;
+    Ok(Client {
+        window,
+        desktop,
+        title,
+        class,
+        instance,
+        rect: [
+            i32::from(position.dst_x),
+            i32::from(position.dst_y),
+            i32::from(geometry.width),
+            i32::from(geometry.height),
+        ],
+        mapped: attributes.map_state == MapState::VIEWABLE,
+        urgent,
+        layer: Layer::Normal,
+    })
+}
+
+fn read_outputs(conn: &RustConnection, root: Window) -> Result<Vec<Output>> {
+    conn.randr_query_version(1, 5)?.reply()?;
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+    reply
+        .monitors
+        .iter()
+        .map(|m| {
+            let name = conn.get_atom_name(m.name)?.reply()?.name;
+            Ok(Output {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                primary: m.primary,
+                rect: [
+                    i32::from(m.x),
+                    i32::from(m.y),
+                    i32::from(m.width),
+                    i32::from(m.height),
+                ],
+            })
+        })
+        .collect()
+}
+
+fn property(conn: &RustConnection, window: Window, property: Atom, type_: Atom) -> Result<Vec<u8>> {
+    Ok(conn
+        .get_property(false, window, property, type_, 0, 4096)?
+        .reply()?
+        .value)
+}
+
+fn cardinal(conn: &RustConnection, window: Window, property: Atom) -> Result<Option<u32>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::CARDINAL, 0, 1)?
+        .reply()?;
+    Ok(reply.value32().and_then(|mut values| values.next()))
+}
+
+fn windows(conn: &RustConnection, window: Window, property: Atom) -> Result<Vec<Window>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::WINDOW, 0, 4096)?
+        .reply()?;
+    Ok(reply.value32().into_iter().flatten().collect())
+}
@@ -0,0 +1,200 @@
+// dwm-style tags, the workspace model instead of qtile's groups when configured: every
+// window carries a set of tags, a bit mask kept in its _QTILERUGO_TAGS property so it
+// outlives the bridge, and the focused screen shows the windows with any of the tags
+// in view. qtile still lays windows out by group, so viewing tags moves the windows
+// that have them onto the focused screen's group, and those that don't onto a group
+// kept hidden for them; windows on the groups other screens show are left alone.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, PropMode, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::config::TagsConfig;
+use crate::stack::WindowStack;
+use crate::state;
+use crate::trace;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// The most tags there can be, one per bit of a mask.
+pub const MAX_TAGS: u32 = 32;
+
+pub struct Tags {
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: state::Atoms,
+    tags_atom: Atom,
+    config: TagsConfig,
+    /// Moves a {window} to a {workspace}.
+    move_window: String,
+    /// Tag masks, by window.
+    windows: Mutex<HashMap<Window, u32>>,
+    /// The tags in view.
+    view: Mutex<u32>,
+}
+
+impl Tags {
+    pub fn new(
+        conn: Arc<RustConnection>,
+        root: Window,
+        config: TagsConfig,
+        move_window: String,
+    ) -> Result<Arc<Self>> {
+        if !(1..=MAX_TAGS).contains(&config.count) {
+            return Err(format!("tags.count is {}, not 1 to {}", config.count, MAX_TAGS).into());
+        }
+        let atoms = state::Atoms::new(&conn)?;
+        let tags_atom = conn.intern_atom(false, b"_QTILERUGO_TAGS")?.reply()?.atom;
+        Ok(Arc::new(Self {
+            conn,
+            root,
+            atoms,
+            tags_atom,
+            config,
+            move_window,
+            windows: Mutex::new(HashMap::new()),
+            view: Mutex::new(1),
+        }))
+    }
+
+    /// The mask of the tag a workspace name numbers, from 1, as the workspace commands
+    /// name tags.
+    pub fn named(&self, name: &str) -> Result<u32> {
+        let number = state::number_of(name);
+        if !(1..=i64::from(self.config.count)).contains(&number) {
+            return Err(format!("{:?} names no tag of 1 to {}", name, self.config.count).into());
+        }
+        Ok(1 << (number - 1))
+    }
+
+    /// Shows the windows with any of the tags in `mask`.
+    pub fn view(&self, mask: u32) -> Result<()> {
+        let mask = mask & self.all();
+        if mask == 0 {
+            return Err("no tag is in view that way".into());
+        }
+        println!("Viewing tags {:#b}", mask);
+        *self.view.lock().unwrap() = mask;
+        self.arrange()
+    }
+
+    /// Gives `window` the tags in `mask` instead of the ones it has.
+    pub fn set(&self, window: Window, mask: u32) -> Result<()> {
+        let mask = mask & self.all();
+        if mask == 0 {
+            return Err("a window keeps at least one tag".into());
+        }
+        trace::decision(&[window], format!("Tagging window {} with {:#b}", window, mask));
+        self.remember(window, mask)?;
+        self.arrange()
+    }
+
+    /// Adds tag `number`, from 1, to `window`, or takes it off unless it is the last.
+    pub fn toggle(&self, window: Window, number: u32) -> Result<()> {
+        if !(1..=self.config.count).contains(&number) {
+            return Err(format!("there is no tag {}", number).into());
+        }
+        let mask = self.tags_of(window)? ^ (1 << (number - 1));
+        self.set(window, mask)
+    }
+
+    /// Tags the windows mapped before the bridge started, as their property remembers
+    /// or with the tags in view.
+    pub fn watch_mapped(&self, stack: &WindowStack) {
+        for window in stack.focusable() {
+            if let Err(e) = self.tags_of(window.window) {
+                eprintln!("Failed to tag window {}: {}", window.window, e);
+            }
+        }
+    }
+
+    /// Follows `event` from the root window's SubstructureNotify: windows get the tags
+    /// in view as they first map.
+    pub fn event(&self, event: &Event) {
+        match event {
+            Event::MapNotify(e) if !e.override_redirect => {
+                if let Err(error) = self.tags_of(e.window) {
+                    eprintln!("Failed to tag window {}: {}", e.window, error);
+                }
+            }
+            Event::DestroyNotify(e) => {
+                self.windows.lock().unwrap().remove(&e.window);
+            }
+            _ => {}
+        }
+    }
+
+    fn all(&self) -> u32 {
+        u32::MAX >> (MAX_TAGS - self.config.count)
+    }
+
+    /// The tags of `window`, which are those in view if it had none yet.
+    fn tags_of(&self, window: Window) -> Result<u32> {
+        if let Some(&mask) = self.windows.lock().unwrap().get(&window) {
+            return Ok(mask);
+        }
+        let reply = self
+            .conn
+            .get_property(false, window, self.tags_atom, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?;
+        let remembered = reply.value32().and_then(|mut values| values.next());
+        let mask = match remembered.map(|mask| mask & self.all()) {
+            Some(mask) if mask != 0 => mask,
+            _ => {
+                let view = *self.view.lock().unwrap();
+                self.remember(window, view)?;
+                return Ok(view);
+            }
+        };
+        self.windows.lock().unwrap().insert(window, mask);
+        Ok(mask)
+    }
+
+    fn remember(&self, window: Window, mask: u32) -> Result<()> {
+        self.windows.lock().unwrap().insert(window, mask);
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.tags_atom,
+            AtomEnum::CARDINAL,
+            &[mask],
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Moves the windows in view onto the focused screen's group, from the hidden one,
+    /// and the others off it, into the hidden one.
+    fn arrange(&self) -> Result<()> {
+        let workspaces = state::workspaces(&self.conn, self.root, &self.atoms)?;
+        let hidden = &self.config.hidden_group;
+        let hidden_index = workspaces
+            .iter()
+            .position(|name| name == hidden)
+            .ok_or_else(|| format!("qtile has no group {:?} to hide windows in", hidden))?;
+        let current = state::current_workspace(&self.conn, self.root, &self.atoms)?
+            .ok_or("no group is focused")?;
+        if current == *hidden {
+            return Err(format!("the hidden group {:?} is focused", hidden).into());
+        }
+        let current_index = workspaces.iter().position(|name| *name == current);
+        let view = *self.view.lock().unwrap();
+        for window in state::client_list(&self.conn, self.root, &self.atoms)? {
+            let shown = self.tags_of(window)? & view != 0;
+            let desktop = state::desktop_of(&self.conn, window, &self.atoms)?;
+            let to = match desktop {
+                Some(index) if index == hidden_index && shown => current.as_str(),
+                Some(index) if Some(index) == current_index && !shown => hidden.as_str(),
+                _ => continue,
+            };
+            let window = window.to_string();
+            crate::spawn_template(&self.move_window, &[("window", &window), ("workspace", to)])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+// Tracing windows, for finding out why one did what it did. While a window is traced,
+// the X events it gets, over a connection of the tracer's own that asks for its
+// structure, property, focus, and visibility changes, and the decisions the bridge's
+// modules make for it are logged to stdout, each a line of JSON starting with
+// {"trace":<window>, so they can be picked out of the rest of the log with grep. Every
+// line carries the wall-clock time in milliseconds and, for events that have one, the
+// X server's time. A window stops being traced as it's destroyed.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Property, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// The windows traced, which every decision checks.
+static TRACED: Mutex<BTreeSet<Window>> = Mutex::new(BTreeSet::new());
+
+/// One line of the trace.
+#[derive(Serialize)]
+struct Line<'a> {
+    trace: Window,
+    /// Milliseconds since the Unix epoch.
+    time: u64,
+    /// The X server's time, in milliseconds since it started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_time: Option<u32>,
+    /// An X event's name, or "Decision".
+    what: &'a str,
+    details: Value,
+}
+
+pub struct Tracer {
+    conn: RustConnection,
+    /// Atom names, as property changes are logged by them.
+    names: Mutex<HashMap<Atom, String>>,
+}
+
+impl Tracer {
+    /// Connects to the X server and starts logging the events of traced windows.
+    pub fn new() -> Result<Arc<Self>> {
+        let (conn, _) = RustConnection::connect(None)?;
+        let tracer = Arc::new(Self {
+            conn,
+            names: Mutex::new(HashMap::new()),
+        });
+        let watcher = tracer.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped tracing windows: {}", e);
+            }
+        });
+        Ok(tracer)
+    }
+
+    /// Starts or stops tracing `window`.
+    pub fn set(&self, window: Window, on: bool) -> Result<()> {
+        let mask = if on {
+            EventMask::STRUCTURE_NOTIFY
+                | EventMask::PROPERTY_CHANGE
+                | EventMask::FOCUS_CHANGE
+                | EventMask::VISIBILITY_CHANGE
+        } else {
+            EventMask::NO_EVENT
+        };
+        let aux = ChangeWindowAttributesAux::new().event_mask(mask);
+        self.conn.change_window_attributes(window, &aux)?.check()?;
+        if on {
+            println!("Tracing window {}", window);
+            TRACED.lock().unwrap().insert(window);
+            log(
+                window,
+                None,
+                "Decision",
+                json!({ "message": "Tracing starts" }),
+            );
+        } else {
+            log(
+                window,
+                None,
+                "Decision",
+                json!({ "message": "Tracing stops" }),
+            );
+            TRACED.lock().unwrap().remove(&window);
+            println!("No longer tracing window {}", window);
+        }
+        Ok(())
+    }
+
+    fn watch(&self) -> Result<()> {
+        loop {
+            let event = self.conn.wait_for_event()?;
+            if let Some((window, server_time, what, details)) = self.describe(&event) {
+                if traced(window) {
+                    log(window, server_time, what, details);
+                }
+            }
+            if let Event::DestroyNotify(e) = event {
+                TRACED.lock().unwrap().remove(&e.window);
+            }
+        }
+    }
+
+    /// The window `event` is of, its server time, its name, and what it says.
+    fn describe(&self, event: &Event) -> Option<(Window, Option<u32>, &'static str, Value)> {
+        Some(match event {
+            Event::MapNotify(e) => (e.window, None, "MapNotify", json!({})),
+            Event::UnmapNotify(e) => (e.window, None, "UnmapNotify", json!({})),
+            Event::DestroyNotify(e) => (e.window, None, "DestroyNotify", json!({})),
+            Event::ReparentNotify(e) => (
+                e.window,
+                None,
+                "ReparentNotify",
+                json!({ "parent": e.parent, "x": e.x, "y": e.y }),
+            ),
+            Event::ConfigureNotify(e) => (
+                e.window,
+                None,
+                "ConfigureNotify",
+                json!({
+                    "x": e.x,
+                    "y": e.y,
+                    "width": e.width,
+                    "height": e.height,
+                    "border_width": e.border_width,
+                    "above": e.above_sibling,
+                }),
+            ),
+            Event::GravityNotify(e) => (
+                e.window,
+                None,
+                "GravityNotify",
+                json!({ "x": e.x, "y": e.y }),
+            ),
+            Event::PropertyNotify(e) => (
+                e.window,
+                Some(e.time),
+                "PropertyNotify",
+                json!({
+                    "property": self.name(e.atom),
+                    "deleted": e.state == Property::DELETE,
+                }),
+            ),
+            Event::FocusIn(e) => (
+                e.event,
+                None,
+                "FocusIn",
+                json!({ "mode": format!("{:?}", e.mode), "detail": format!("{:?}", e.detail) }),
+            ),
+            Event::FocusOut(e) => (
+                e.event,
+                None,
+                "FocusOut",
+                json!({ "mode": format!("{:?}", e.mode), "detail": format!("{:?}", e.detail) }),
+            ),
+            Event::VisibilityNotify(e) => (
+                e.window,
+                None,
+                "VisibilityNotify",
+                json!({ "state": format!("{:?}", e.state) }),
+            ),
+            _ => return None,
+        })
+    }
+
+    /// `atom`'s name, or its number should the server not say.
+    fn name(&self, atom: Atom) -> String {
+        let mut names = self.names.lock().unwrap();
+        names
+            .entry(atom)
+            .or_insert_with(|| {
+                self.conn
+                    .get_atom_name(atom)
+                    .ok()
+                    .and_then(|cookie| cookie.reply().ok())
+                    .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+                    .unwrap_or_else(|| atom.to_string())
+            })
+            .clone()
+    }
+}
+
+/// Prints `message`, a decision made for `windows`, and logs it for those traced.
+pub fn decision(windows: &[Window], message: String) {
+    println!("{}", message);
+    for &window in windows {
+        if traced(window) {
+            log(window, None, "Decision", json!({ "message": message }));
+        }
+    }
+}
+
+fn traced(window: Window) -> bool {
+    TRACED.lock().unwrap().contains(&window)
+}
+
+fn log(window: Window, server_time: Option<u32>, what: &str, details: Value) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64);
+    let line = Line {
+        trace: window,
+        time,
+        server_time,
+        what,
+        details,
+    };
+    if let Ok(line) = serde_json::to_string(&line) {
+        println!("{}", line);
+    }
+}
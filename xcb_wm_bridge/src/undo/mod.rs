@@ -0,0 +1,243 @@
+// Undo: a bounded history of the actions the bridge can revert, taken as the commands
+// run, with what they are reverted to. Toggles are undone by toggling again and
+// shuffles by shuffling back, both only while the window they acted on is still
+// focused, as qtile's commands act on the focused window; layouts go back through
+// undo.previous_layout, layers and workspaces to what they were. A window can't be
+// opened again once closed, so with undo.close_grace_ms CloseWindow waits that long
+// first, and Undo in that time keeps it open.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use qtilerugo_ipc::wm::WmCommand;
+use serde::Serialize;
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+use crate::layers::{Layer, Layers};
+use crate::state;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// An action, with what it changed from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action")]
+pub enum Action {
+    MoveToWorkspace {
+        window: Window,
+        from: String,
+        to: String,
+    },
+    SwitchWorkspace {
+        from: String,
+        to: String,
+    },
+    /// A toggle of `window`, which toggling it again undoes.
+    Toggle {
+        window: Window,
+        command: WmCommand,
+    },
+    /// A shuffle of `window`, which shuffling it the other way undoes.
+    Shuffle {
+        window: Window,
+        command: WmCommand,
+    },
+    NextLayout,
+    SetLayer {
+        window: Window,
+        from: Layer,
+        to: Layer,
+    },
+    /// A window waiting to close.
+    CloseWindow {
+        window: Window,
+    },
+}
+
+impl Action {
+    /// The action `cmd` is, read from qtile's state before it runs, or `None` for
+    /// commands that can't be undone. With `tags`, workspaces are tags, which the
+    /// history leaves out.
+    pub fn of(
+        cmd: &WmCommand,
+        conn: &RustConnection,
+        root: Window,
+        layers: &Layers,
+        tags: bool,
+    ) -> Result<Option<Self>> {
+        let atoms = state::Atoms::new(conn)?;
+        let focused = || -> Result<Window> {
+            Ok(state::active_window(conn, root, &atoms)?.ok_or("no window is focused")?)
+        };
+        Ok(Some(match cmd {
+            WmCommand::MoveToWorkspace { window, workspace } if !tags => {
+                let Some(index) = state::desktop_of(conn, *window, &atoms)? else {
+                    return Ok(None);
+                };
+                let Some(from) = state::workspaces(conn, root, &atoms)?.get(index).cloned() else {
+                    return Ok(None);
+                };
+                Self::MoveToWorkspace {
+                    window: *window,
+                    from,
+                    to: workspace.clone(),
+                }
+            }
+            WmCommand::SwitchWorkspace(workspace) if !tags => {
+                let Some(from) = state::current_workspace(conn, root, &atoms)? else {
+                    return Ok(None);
+                };
+                Self::SwitchWorkspace {
+                    from,
+                    to: workspace.clone(),
+                }
+            }
+            WmCommand::ToggleFloating
+            | WmCommand::ToggleFullscreen
+            | WmCommand::ToggleSplit
+            | WmCommand::ToggleFakeFullscreen
+            | WmCommand::ToggleCentered => Self::Toggle {
+                window: focused()?,
+                command: cmd.clone(),
+            },
+            WmCommand::ShuffleLeft
+            | WmCommand::ShuffleRight
+            | WmCommand::ShuffleUp
+            | WmCommand::ShuffleDown => Self::Shuffle {
+                window: focused()?,
+                command: cmd.clone(),
+            },
+            WmCommand::NextLayout => Self::NextLayout,
+            WmCommand::ToggleAlwaysOnTop | WmCommand::SetLayer(_) => {
+                let window = focused()?;
+                let from = layers.layer(window);
+                let to = match cmd {
+                    WmCommand::SetLayer(layer) => *layer,
+                    _ if from == Layer::Above => Layer::Normal,
+                    _ => Layer::Above,
+                };
+                Self::SetLayer { window, from, to }
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    /// The windows it acted on.
+    pub fn windows(&self) -> Vec<Window> {
+        match self {
+            Self::MoveToWorkspace { window, .. }
+            | Self::Toggle { window, .. }
+            | Self::Shuffle { window, .. }
+            | Self::SetLayer { window, .. }
+            | Self::CloseWindow { window } => vec![*window],
+            Self::SwitchWorkspace { .. } | Self::NextLayout => Vec::new(),
+        }
+    }
+}
+
+/// The shuffle that undoes `command`.
+pub fn shuffle_back(command: &WmCommand) -> Option<WmCommand> {
+    Some(match command {
+        WmCommand::ShuffleLeft => WmCommand::ShuffleRight,
+        WmCommand::ShuffleRight => WmCommand::ShuffleLeft,
+        WmCommand::ShuffleUp => WmCommand::ShuffleDown,
+        WmCommand::ShuffleDown => WmCommand::ShuffleUp,
+        _ => return None,
+    })
+}
+
+struct Entry {
+    id: u64,
+    action: Action,
+    /// Set by Undo for a window waiting to close.
+    cancelled: Option<Arc<AtomicBool>>,
+}
+
+pub struct History {
+    limit: usize,
+    entries: Mutex<VecDeque<Entry>>,
+    next_id: AtomicU64,
+}
+
+impl History {
+    /// A history of up to `limit` actions.
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Remembers `action` as the last one, forgetting the first beyond the limit.
+    pub fn push(&self, action: Action) {
+        self.insert(action, None);
+    }
+
+    /// Calls `close` to close `window` after `grace`, unless Undo comes first.
+    pub fn close_later(
+        self: &Arc<Self>,
+        window: Window,
+        grace: Duration,
+        close: impl FnOnce() + Send + 'static,
+    ) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let id = self.insert(Action::CloseWindow { window }, Some(cancelled.clone()));
+        let history = self.clone();
+        thread::spawn(move || {
+            thread::sleep(grace);
+            // Under the lock Undo takes it with, so it either closes or stays open.
+            {
+                let mut entries = history.entries.lock().unwrap();
+                if cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                entries.retain(|entry| entry.id != id);
+            }
+            close();
+        });
+    }
+
+    /// Takes the last action to undo, keeping a window waiting to close open.
+    pub fn pop(&self) -> Option<Action> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.pop_back()?;
+        if let Some(cancelled) = entry.cancelled {
+            cancelled.store(true, Ordering::SeqCst);
+        }
+        Some(entry.action)
+    }
+
+    /// Gives back an action that failed to be undone, as the last one again.
+    pub fn restore(&self, action: Action) {
+        self.push(action);
+    }
+
+    /// The actions, the last first.
+    pub fn list(&self) -> Vec<Action> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .map(|entry| entry.action.clone())
+            .collect()
+    }
+
+    fn insert(&self, action: Action, cancelled: Option<Arc<AtomicBool>>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(Entry {
+            id,
+            action,
+            cancelled,
+        });
+        // A window that waits to close still closes once forgotten.
+        while entries.len() > self.limit {
+            entries.pop_front();
+        }
+        id
+    }
+}
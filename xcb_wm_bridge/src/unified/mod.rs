@@ -0,0 +1,385 @@
+mod sync;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use qtilerugo_compositor::scene::{
+    Layer, Scene, SharedScene, WindowContents, WindowState,
+};
+use x11rb::connection::Connection;
+use x11rb::protocol::composite::{ConnectionExt as _, Redirect};
+use x11rb::protocol::damage::{self, ConnectionExt as _, ReportLevel};
+use x11rb::protocol::xproto::{ConnectionExt as _, ImageFormat, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::NONE;
+
+use crate::appearance;
+use crate::centered;
+use crate::config::AppearanceVariant;
+use crate::clients::{ClientInfo, Clients};
+use crate::config::PingConfig;
+use crate::controls;
+use crate::media;
+use crate::shape;
+use crate::metrics;
+use crate::modal;
+use crate::startup;
+use crate::stack::{self, SharedStack, StackedWindow, WindowStack};
+
+use self::sync::FrameSync;
+
+/// Redirects the root's children and feeds the stack straight into a compositor scene.
+///
+/// The returned scene is ready to hand to `qtilerugo_compositor::compositor::RenderLoop::run`.
+pub fn start(
+    conn: Arc<RustConnection>,
+    root: Window,
+    stack: SharedStack,
+    ping: &PingConfig,
+    mut observe: impl FnMut(&Event, &WindowStack, bool) + Send + 'static,
+) -> Result<SharedScene, Box<dyn std::error::Error>> {
+    conn.composite_query_version(0, 4)?.reply()?;
+    conn.damage_query_version(1, 1)?.reply()?;
+    // Automatic redirection keeps the server painting the screen if we go away.
+    conn.composite_redirect_subwindows(root, Redirect::AUTOMATIC)?
+        .check()?;
+
+    let scene = Scene::shared();
+    // Without SYNC, resized windows show whatever their pixmap holds.
+    let sync = FrameSync::new(&conn)
+        .map_err(|e| eprintln!("Not syncing frames with clients: {}", e))
+        .ok();
+    let tracker = Arc::new(Mutex::new(ContentTracker {
+        sync,
+        ..Default::default()
+    }));
+    let pinger = {
+        let scene = scene.clone();
+        let report = Box::new(move |window, responsive| {
+            scene.lock().unwrap().set_responsive(window, responsive)
+        });
+        crate::start_pinger(&conn, root, ping, &stack, report)
+    };
+    let clients = {
+        let scene = scene.clone();
+        let report = Box::new(move |info: ClientInfo| {
+            scene.lock().unwrap().set_window_info(info.window_info())
+        });
+        Clients::new(conn.clone(), report)?
+    };
+    {
+        let conn = conn.clone();
+        let scene = scene.clone();
+        let tracker = tracker.clone();
+        let clients = clients.clone();
+        stack::watch(conn.clone(), root, stack.clone(), move |event, stack, changed| {
+            if let Some(pinger) = &pinger {
+                pinger.event(event);
+            }
+            clients.event(event);
+            observe(event, stack, changed);
+            let mut tracker = tracker.lock().unwrap();
+            // Before syncing, so a resized window keeps its old size until redrawn.
+            if let Event::ConfigureNotify(e) = event {
+                tracker.resized(&conn, stack, e.window);
+            }
+            // Sync first, so unmapped windows start closing before their contents go.
+            if changed {
+                scene
+                    .lock()
+                    .unwrap()
+                    .sync_windows(&window_states(stack, &tracker));
+            }
+            tracker.handle(&conn, &scene, event, stack);
+        })?;
+    }
+
+    // Windows mapped before we started never send a MapNotify.
+    let stack = stack.lock().unwrap();
+    clients.watch_mapped(&stack);
+    let mut tracker = tracker.lock().unwrap();
+    for window in stack.mapped() {
+        if let Err(e) = tracker.track(&conn, &scene, &stack, window.window) {
+            eprintln!("Failed to track window {}: {}", window.window, e);
+        }
+    }
+    scene
+        .lock()
+        .unwrap()
+        .sync_windows(&window_states(&stack, &tracker));
+    Ok(scene)
+}
+
+/// Shows the volume and brightness changes of the WM's commands in `scene`.
+pub fn level_report(scene: &SharedScene) -> controls::Report {
+    let scene = scene.clone();
+    Box::new(move |level: controls::Level| scene.lock().unwrap().set_level(level))
+}
+
+/// Letterboxes centered windows in `scene`.
+pub fn letterbox_report(scene: &SharedScene) -> centered::Report {
+    let scene = scene.clone();
+    Box::new(move |window, tile| scene.lock().unwrap().set_letterbox(window, tile))
+}
+
+/// Tells `scene` which windows are click-through.
+pub fn click_through_report(scene: &SharedScene) -> shape::Report {
+    let scene = scene.clone();
+    Box::new(move |window, click_through| {
+        scene.lock().unwrap().set_click_through(window, click_through)
+    })
+}
+
+/// Dims the windows of modal dialogs in `scene`.
+pub fn dimmed_report(scene: &SharedScene) -> modal::Report {
+    let scene = scene.clone();
+    Box::new(move |window, dimmed| scene.lock().unwrap().set_dimmed(window, dimmed))
+}
+
+/// Draws shaped windows in `scene` as their shapes.
+pub fn shape_report(scene: &SharedScene) -> shape::bounding::Report {
+    let scene = scene.clone();
+    Box::new(move |window, rects| scene.lock().unwrap().set_window_shape(window, rects))
+}
+
+/// Reads the compositor's frames from `scene`.
+pub fn frame_source(scene: &SharedScene) -> metrics::FrameSource {
+    let scene = scene.clone();
+    Box::new(move || {
+        Ok(scene.lock().unwrap().frame_stats().report())
+    })
+}
+
+/// Shows `scene`'s pointer busy while launches wait for their windows.
+pub fn busy_report(scene: &SharedScene) -> startup::Report {
+    let scene = scene.clone();
+    Box::new(move |busy| scene.lock().unwrap().set_busy(busy))
+}
+
+/// Has `scene` take on the theme and wallpaper of the appearance switched to.
+pub fn appearance_report(scene: &SharedScene) -> appearance::Report {
+    let scene = scene.clone();
+    Box::new(move |variant: &AppearanceVariant| {
+        let mut scene = scene.lock().unwrap();
+        if let Some(theme) = &variant.theme {
+            if let Err(e) = scene.set_theme(theme) {
+                eprintln!("Ignoring appearance theme: {}", e);
+            }
+        }
+        if let Some(path) = &variant.wallpaper {
+            if let Err(e) = scene.set_wallpaper(None, Some(path.clone())) {
+                eprintln!("Ignoring appearance wallpaper: {}", e);
+            }
+        }
+    })
+}
+
+/// Shows what the followed media player plays in `scene`'s bar.
+pub fn media_report(scene: &SharedScene) -> media::Report {
+    let scene = scene.clone();
+    Box::new(move |track: Option<media::Track>| {
+        let info = track.as_ref().map(media::Track::media_info);
+        scene.lock().unwrap().set_media(info);
+    })
+}
+
+/// Scene state for every mapped window, bottom first; windows whose clients are still
+/// drawing a new size keep the geometry of their current contents.
+fn window_states(stack: &WindowStack, tracker: &ContentTracker) -> Vec<WindowState> {
+    stack
+        .mapped()
+        .map(|w| (w, tracker.held(w.window).unwrap_or(w)))
+        .map(|(w, shown)| WindowState {
+            id: w.window,
+            x: shown.x as i32,
+            y: shown.y as i32,
+            // The named pixmap includes the X border, so the node covers it too.
+            width: outer_size(shown).0,
+            height: outer_size(shown).1,
+            border_width: 0,
+            focused: stack.focused() == Some(w.window),
+            opacity: w.properties.opacity,
+            window_type: w.properties.window_type,
+            fullscreen: w.properties.fullscreen,
+            blur: w.properties.blur,
+            layer: Layer::Normal,
+            visible: true,
+        })
+        .collect()
+}
+
+fn outer_size(w: &StackedWindow) -> (u32, u32) {
+    let border = 2 * w.border_width as u32;
+    (w.width as u32 + border, w.height as u32 + border)
+}
+
+/// Keeps one Damage object per mapped window and re-reads its pixels when it fires.
+#[derive(Default)]
+struct ContentTracker {
+    damages: HashMap<Window, damage::Damage>,
+    /// Each window's geometry when its pixels were last read.
+    drawn: HashMap<Window, StackedWindow>,
+    sync: Option<FrameSync>,
+}
+
+impl ContentTracker {
+    fn handle(
+        &mut self,
+        conn: &RustConnection,
+        scene: &SharedScene,
+        event: &Event,
+        stack: &WindowStack,
+    ) {
+        let result = match event {
+            Event::MapNotify(e) => self.track(conn, scene, stack, e.window),
+            Event::UnmapNotify(e) => self.forget(conn, scene, e.window, true),
+            Event::DestroyNotify(e) => self.forget(conn, scene, e.window, false),
+            Event::DamageNotify(e) => conn
+                .damage_subtract(e.damage, NONE, NONE)
+                .map_err(Into::into)
+                .and_then(|_| self.refresh(conn, scene, stack, e.drawable)),
+            Event::SyncAlarmNotify(e) => match self.sync.as_mut().and_then(|s| s.alarm(e)) {
+                Some(window) => self.release(conn, scene, stack, window),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to update window contents: {}", e);
+        }
+    }
+
+    /// Starts following damage on `window` and reads its current pixels.
+    fn track(
+        &mut self,
+        conn: &RustConnection,
+        scene: &SharedScene,
+        stack: &WindowStack,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Entry::Vacant(entry) = self.damages.entry(window) {
+            let damage = conn.generate_id()?;
+            conn.damage_create(damage, window, ReportLevel::NON_EMPTY)?;
+            entry.insert(damage);
+        }
+        if let Some(sync) = &mut self.sync {
+            sync.track(conn, window)?;
+        }
+        self.refresh(conn, scene, stack, window)
+    }
+
+    /// The geometry `window` is shown at while its client draws a new size.
+    fn held(&self, window: Window) -> Option<&StackedWindow> {
+        self.sync.as_ref()?.held(window)
+    }
+
+    /// Asks the client of `window` to report when it has drawn its new size, if that
+    /// differs from the size of its current contents.
+    fn resized(&mut self, conn: &RustConnection, stack: &WindowStack, window: Window) {
+        let (Some(sync), Some(drawn)) = (self.sync.as_mut(), self.drawn.get(&window)) else {
+            return;
+        };
+        let Some(entry) = stack.mapped().find(|w| w.window == window) else {
+            return;
+        };
+        if outer_size(entry) == outer_size(drawn) {
+            return;
+        }
+        if let Err(e) = sync.request(conn, window, *drawn) {
+            eprintln!("Failed to sync with window {}: {}", window, e);
+        }
+    }
+
+    /// Reads the current pixels of `window`, unless its client is drawing a new size.
+    fn refresh(
+        &mut self,
+        conn: &RustConnection,
+        scene: &SharedScene,
+        stack: &WindowStack,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.held(window).is_some() {
+            return Ok(());
+        }
+        let Some(entry) = stack.mapped().find(|w| w.window == window) else {
+            return Ok(());
+        };
+        let contents = read_contents(conn, entry)?;
+        self.drawn.insert(window, *entry);
+        scene.lock().unwrap().set_window_contents(window, contents);
+        Ok(())
+    }
+
+    /// Shows `window` at its new size, with the contents its client drew for it.
+    fn release(
+        &mut self,
+        conn: &RustConnection,
+        scene: &SharedScene,
+        stack: &WindowStack,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(entry) = stack.mapped().find(|w| w.window == window) else {
+            return Ok(());
+        };
+        let contents = read_contents(conn, entry)?;
+        self.drawn.insert(window, *entry);
+        // Both at once, so no frame shows the new size with the old contents.
+        let mut scene = scene.lock().unwrap();
+        scene.set_window_contents(window, contents);
+        scene.sync_windows(&window_states(stack, self));
+        Ok(())
+    }
+
+    fn forget(
+        &mut self,
+        conn: &RustConnection,
+        scene: &SharedScene,
+        window: Window,
+        alive: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        scene.lock().unwrap().clear_window_contents(window);
+        self.drawn.remove(&window);
+        if let Some(sync) = &mut self.sync {
+            sync.forget(conn, window)?;
+        }
+        if let Some(damage) = self.damages.remove(&window) {
+            // Damage objects die with their drawable.
+            if alive {
+                conn.damage_destroy(damage)?;
+                conn.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the current pixels of the window at `entry` through its composite pixmap.
+fn read_contents(
+    conn: &RustConnection,
+    entry: &StackedWindow,
+) -> Result<WindowContents, Box<dyn std::error::Error>> {
+    let window = entry.window;
+    let (width, height) = outer_size(entry);
+
+    let pixmap = conn.generate_id()?;
+    conn.composite_name_window_pixmap(window, pixmap)?;
+    let image = conn
+        .get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, width as u16, height as u16, !0)?
+        .reply();
+    conn.free_pixmap(pixmap)?;
+    conn.flush()?;
+    let image = image?;
+
+    // 24 and 32 bit ZPixmaps are 4 bytes per pixel in BGRA order on little-endian servers.
+    if image.data.len() != (width * height * 4) as usize {
+        return Err(format!("unexpected image size for window {}", window).into());
+    }
+    Ok(WindowContents {
+        width,
+        height,
+        data: Arc::new(image.data),
+        opaque: image.depth != 32,
+    })
+}
@@ -0,0 +1,252 @@
+// The _NET_WM_SYNC_REQUEST protocol: when a window is resized, its client is asked to
+// set its XSync counter to a new value once it has drawn the new size. Until an alarm
+// on that counter fires, the scene keeps showing the window at the size and contents
+// it last drew, so re-tiling never samples a stretched or half-drawn pixmap. Clients
+// that don't answer are waited for at most `SYNC_TIMEOUT_MS`, timed by a second alarm
+// on the server's SERVERTIME counter, since the event thread has no timers of its own.
+
+use std::collections::HashMap;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::sync::{
+    Alarm, AlarmNotifyEvent, ChangeAlarmAux, ConnectionExt as _, Counter, CreateAlarmAux, Int64,
+    TESTTYPE, VALUETYPE,
+};
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+use crate::stack::StackedWindow;
+
+/// Longest a resized window waits for its client to draw.
+const SYNC_TIMEOUT_MS: i64 = 200;
+
+/// Sync counters of the windows whose clients take part, and what they were asked.
+pub struct FrameSync {
+    wm_protocols: u32,
+    sync_request: u32,
+    sync_request_counter: u32,
+    /// SERVERTIME, counting milliseconds; clients are waited for forever without it.
+    server_time: Option<Counter>,
+    windows: HashMap<Window, SyncedWindow>,
+    /// The window each alarm is for.
+    alarms: HashMap<Alarm, Window>,
+}
+
+struct SyncedWindow {
+    counter: Counter,
+    /// The counter value last asked for.
+    value: i64,
+    /// Fires once the counter reaches `value`; created by the first request.
+    alarm: Option<Alarm>,
+    /// Fires at `deadline` on SERVERTIME; created by the first request.
+    timeout: Option<Alarm>,
+    deadline: i64,
+    /// The geometry of the contents shown while waiting for the client to draw.
+    waiting: Option<StackedWindow>,
+}
+
+impl FrameSync {
+    pub fn new(conn: &RustConnection) -> Result<Self, Box<dyn std::error::Error>> {
+        conn.sync_initialize(3, 1)?.reply()?;
+        let atom = |name: &str| -> Result<u32, Box<dyn std::error::Error>> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let server_time = conn
+            .sync_list_system_counters()?
+            .reply()?
+            .counters
+            .into_iter()
+            .find(|counter| counter.name == b"SERVERTIME")
+            .map(|counter| counter.counter);
+        Ok(Self {
+            wm_protocols: atom("WM_PROTOCOLS")?,
+            sync_request: atom("_NET_WM_SYNC_REQUEST")?,
+            sync_request_counter: atom("_NET_WM_SYNC_REQUEST_COUNTER")?,
+            server_time,
+            windows: HashMap::new(),
+            alarms: HashMap::new(),
+        })
+    }
+
+    /// Starts syncing with the client of `window`, if it supports the protocol.
+    pub fn track(
+        &mut self,
+        conn: &RustConnection,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.windows.contains_key(&window) {
+            return Ok(());
+        }
+        let protocols = conn
+            .get_property(false, window, self.wm_protocols, AtomEnum::ATOM, 0, 32)?
+            .reply()?;
+        let supported = protocols
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == self.sync_request));
+        if !supported {
+            return Ok(());
+        }
+        // A second counter, for clients doing the extended protocol, may follow; the
+        // basic one is enough here.
+        let counters = conn
+            .get_property(
+                false,
+                window,
+                self.sync_request_counter,
+                AtomEnum::CARDINAL,
+                0,
+                2,
+            )?
+            .reply()?;
+        let Some(counter) = counters.value32().and_then(|mut counters| counters.next()) else {
+            return Ok(());
+        };
+        let value = from_int64(conn.sync_query_counter(counter)?.reply()?.counter_value);
+        self.windows.insert(
+            window,
+            SyncedWindow {
+                counter,
+                value,
+                alarm: None,
+                timeout: None,
+                deadline: 0,
+                waiting: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Asks the client of `window`, which was resized after drawing at `drawn`, to
+    /// report when it has drawn the new size; returns whether it will be waited for.
+    pub fn request(
+        &mut self,
+        conn: &RustConnection,
+        window: Window,
+        drawn: StackedWindow,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(synced) = self.windows.get_mut(&window) else {
+            return Ok(false);
+        };
+        synced.value += 1;
+        let message = ClientMessageEvent::new(
+            32,
+            window,
+            self.wm_protocols,
+            [
+                self.sync_request,
+                CURRENT_TIME,
+                synced.value as u32,
+                (synced.value >> 32) as u32,
+                0,
+            ],
+        );
+        conn.send_event(false, window, EventMask::NO_EVENT, message)?;
+        synced.alarm = Some(set_alarm(conn, synced.alarm, synced.counter, synced.value)?);
+        if let Some(server_time) = self.server_time {
+            let now = from_int64(conn.sync_query_counter(server_time)?.reply()?.counter_value);
+            synced.deadline = now + SYNC_TIMEOUT_MS;
+            synced.timeout = Some(set_alarm(
+                conn,
+                synced.timeout,
+                server_time,
+                synced.deadline,
+            )?);
+        }
+        conn.flush()?;
+        for alarm in synced.alarm.iter().chain(&synced.timeout) {
+            self.alarms.insert(*alarm, window);
+        }
+        // Resized again while waiting, the window still shows what was drawn first.
+        synced.waiting.get_or_insert(drawn);
+        Ok(true)
+    }
+
+    /// The geometry `window` is shown at while its client draws a new size.
+    pub fn held(&self, window: Window) -> Option<&StackedWindow> {
+        self.windows.get(&window)?.waiting.as_ref()
+    }
+
+    /// Handles alarm `event`; returns the window whose client finished drawing, or
+    /// ran out of time to.
+    pub fn alarm(&mut self, event: &AlarmNotifyEvent) -> Option<Window> {
+        let window = *self.alarms.get(&event.alarm)?;
+        let synced = self.windows.get_mut(&window)?;
+        // Alarms set for an earlier request may still be on their way.
+        let done = if synced.alarm == Some(event.alarm) {
+            from_int64(event.counter_value) >= synced.value
+        } else {
+            from_int64(event.alarm_value) >= synced.deadline
+        };
+        if !done {
+            return None;
+        }
+        synced.waiting.take().map(|_| window)
+    }
+
+    pub fn forget(
+        &mut self,
+        conn: &RustConnection,
+        window: Window,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(synced) = self.windows.remove(&window) else {
+            return Ok(());
+        };
+        // Alarms outlive the counters they watch, so they go either way.
+        for alarm in synced.alarm.into_iter().chain(synced.timeout) {
+            self.alarms.remove(&alarm);
+            conn.sync_destroy_alarm(alarm)?;
+        }
+        conn.flush()?;
+        Ok(())
+    }
+}
+
+/// Points `alarm`, or a new one without it, at `counter` reaching `value`.
+fn set_alarm(
+    conn: &RustConnection,
+    alarm: Option<Alarm>,
+    counter: Counter,
+    value: i64,
+) -> Result<Alarm, Box<dyn std::error::Error>> {
+    // A zero delta deactivates the alarm once it fires, until it is set again.
+    let zero = Int64 { hi: 0, lo: 0 };
+    match alarm {
+        Some(alarm) => {
+            let aux = ChangeAlarmAux::new()
+                .counter(counter)
+                .value_type(VALUETYPE::ABSOLUTE)
+                .value(to_int64(value))
+                .test_type(TESTTYPE::POSITIVE_COMPARISON)
+                .delta(zero)
+                .events(1);
+            conn.sync_change_alarm(alarm, &aux)?;
+            Ok(alarm)
+        }
+        None => {
+            let alarm = conn.generate_id()?;
+            let aux = CreateAlarmAux::new()
+                .counter(counter)
+                .value_type(VALUETYPE::ABSOLUTE)
+                .value(to_int64(value))
+                .test_type(TESTTYPE::POSITIVE_COMPARISON)
+                .delta(zero)
+                .events(1);
+            conn.sync_create_alarm(alarm, &aux)?;
+            Ok(alarm)
+        }
+    }
+}
+
+fn to_int64(value: i64) -> Int64 {
+    Int64 {
+        hi: (value >> 32) as i32,
+        lo: value as u32,
+    }
+}
+
+fn from_int64(value: Int64) -> i64 {
+    ((value.hi as i64) << 32) | value.lo as i64
+}
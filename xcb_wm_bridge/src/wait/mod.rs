@@ -0,0 +1,155 @@
+// Waiting for windows: WaitForWindow is answered once a window maps whose WM_CLASS
+// class, and title if a pattern is given, match, so a script can spawn an application
+// and arrange its window without sleeping first. Titles often come after the map, so
+// a window that mapped after the wait began is looked at again as its title changes.
+// Windows are followed on a connection of their own, on a thread of its own; the
+// command socket awaits the answer without holding up other clients.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tokio::sync::oneshot;
+use x11rb::connection::Connection;
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::floating::glob;
+use crate::state;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// A WaitForWindow waiting.
+struct Waiter {
+    class: Option<String>,
+    title: Option<String>,
+    /// Windows that mapped after this are taken.
+    since: u64,
+    reply: oneshot::Sender<Window>,
+}
+
+pub struct Waiters {
+    conn: RustConnection,
+    atoms: state::Atoms,
+    waiting: Mutex<Vec<Waiter>>,
+    /// When each window mapped, counting maps.
+    mapped: Mutex<(u64, HashMap<Window, u64>)>,
+}
+
+impl Waiters {
+    /// Connects to the X server and starts following windows as they map.
+    pub fn new() -> Result<Arc<Self>> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = state::Atoms::new(&conn)?;
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_NOTIFY);
+        conn.change_window_attributes(root, &aux)?.check()?;
+        conn.flush()?;
+
+        let waiters = Arc::new(Self {
+            conn,
+            atoms,
+            waiting: Mutex::new(Vec::new()),
+            mapped: Mutex::new((0, HashMap::new())),
+        });
+        let watcher = waiters.clone();
+        thread::spawn(move || {
+            if let Err(e) = watcher.watch() {
+                eprintln!("Stopped waiting for windows: {}", e);
+            }
+        });
+        Ok(waiters)
+    }
+
+    /// Answered with the first window to map, from now, with the class and a title
+    /// matching the pattern, of those given.
+    pub fn wait(&self, class: Option<String>, title: Option<String>) -> oneshot::Receiver<Window> {
+        let (reply, receiver) = oneshot::channel();
+        let since = self.mapped.lock().unwrap().0;
+        let mut waiting = self.waiting.lock().unwrap();
+        // Forgetting those nobody waits for anymore.
+        waiting.retain(|waiter| !waiter.reply.is_closed());
+        waiting.push(Waiter {
+            class,
+            title,
+            since,
+            reply,
+        });
+        receiver
+    }
+
+    fn watch(&self) -> Result<()> {
+        let wm_name = Atom::from(AtomEnum::WM_NAME);
+        loop {
+            let result = match self.conn.wait_for_event()? {
+                Event::MapNotify(e) if !e.override_redirect => self.mapped_window(e.window),
+                Event::PropertyNotify(e)
+                    if e.atom == wm_name || e.atom == self.atoms.net_wm_name =>
+                {
+                    self.check(e.window)
+                }
+                Event::UnmapNotify(e) => {
+                    self.mapped.lock().unwrap().1.remove(&e.window);
+                    Ok(())
+                }
+                Event::DestroyNotify(e) => {
+                    self.mapped.lock().unwrap().1.remove(&e.window);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to check a window waited for: {}", e);
+            }
+        }
+    }
+
+    /// Follows the title of a newly mapped window, and checks it.
+    fn mapped_window(&self, window: Window) -> Result<()> {
+        {
+            let mut mapped = self.mapped.lock().unwrap();
+            mapped.0 += 1;
+            let count = mapped.0;
+            mapped.1.insert(window, count);
+        }
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        self.conn.change_window_attributes(window, &aux)?;
+        self.conn.flush()?;
+        self.check(window)
+    }
+
+    /// Answers the first waiter `window` matches, if it mapped after it began waiting.
+    fn check(&self, window: Window) -> Result<()> {
+        let Some(&mapped_at) = self.mapped.lock().unwrap().1.get(&window) else {
+            return Ok(());
+        };
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting.retain(|waiter| !waiter.reply.is_closed());
+        if !waiting.iter().any(|waiter| waiter.since < mapped_at) {
+            return Ok(());
+        }
+        let class = match WmClass::get(&self.conn, window)?.reply_unchecked()? {
+            Some(class) => String::from_utf8_lossy(class.class()).into_owned(),
+            None => String::new(),
+        };
+        let title = state::title_of(&self.conn, window, &self.atoms)?;
+        let matches = |waiter: &Waiter| {
+            waiter.since < mapped_at
+                && waiter.class.as_ref().is_none_or(|wanted| *wanted == class)
+                && waiter
+                    .title
+                    .as_deref()
+                    .is_none_or(|pattern| glob(pattern, &title))
+        };
+        if let Some(index) = waiting.iter().position(matches) {
+            let waiter = waiting.remove(index);
+            // The requester may have given up waiting.
+            let _ = waiter.reply.send(window);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+// Keeps a separate rustVrender alive. It is pinged on its socket every heartbeat; once
+// it exits, or misses enough heartbeats in a row to count as stalled, its X connection
+// is killed, so the server stops showing its output windows over the desktop and drops
+// any redirection it held, leaving windows to paint themselves unredirected. The
+// compositor command is then started again, waiting longer after each failure, and the
+// new renderer gets the window stack and monitors from scratch.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use qtilerugo_ipc::encode;
+use qtilerugo_ipc::renderer::RendererCommand;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::CompositorConfig;
+use crate::link::{RendererLink, RENDERER_SOCKET};
+use crate::stack::SharedStack;
+
+/// How long a started compositor may take to answer its first heartbeat.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a compositor has to keep answering before the restart delay starts over.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Watches the renderer the link feeds, and restarts it when it fails.
+pub struct Watchdog {
+    conn: Arc<RustConnection>,
+    config: CompositorConfig,
+    stack: SharedStack,
+    link: Arc<Mutex<RendererLink>>,
+    /// The compositor started by the watchdog, if it started one.
+    child: Option<Child>,
+    /// When `child` was started.
+    started: Option<Instant>,
+    /// Heartbeats are sent on their own connection, so they never wait behind updates.
+    stream: Option<BufReader<UnixStream>>,
+    /// The renderer's output windows, as of its last answer.
+    windows: Vec<u32>,
+    /// Whether the renderer answered since it was last started or lost.
+    answering: bool,
+    missed: u32,
+    restart_at: Option<Instant>,
+    restart_delay: Duration,
+}
+
+impl Watchdog {
+    pub fn new(
+        conn: Arc<RustConnection>,
+        config: CompositorConfig,
+        stack: SharedStack,
+        link: Arc<Mutex<RendererLink>>,
+    ) -> Self {
+        Self {
+            conn,
+            // Started unless a renderer already answers the first heartbeat.
+            restart_at: config.command.as_ref().map(|_| Instant::now()),
+            restart_delay: Duration::from_millis(config.restart_delay_ms),
+            config,
+            stack,
+            link,
+            child: None,
+            started: None,
+            stream: None,
+            windows: Vec::new(),
+            answering: false,
+            missed: 0,
+        }
+    }
+
+    /// Runs the watchdog on its own thread.
+    pub fn spawn(mut self) {
+        let interval = Duration::from_millis(self.config.heartbeat_ms.max(50));
+        thread::spawn(move || loop {
+            self.tick(interval);
+            thread::sleep(interval);
+        });
+    }
+
+    /// Sends one heartbeat, waiting up to `timeout` for the answer, and acts on it.
+    fn tick(&mut self, timeout: Duration) {
+        match self.ping(timeout) {
+            Ok(windows) => self.answered(windows),
+            Err(e) => self.missed(e),
+        }
+        if !self.answering && self.restart_at.is_some_and(|at| Instant::now() >= at) {
+            self.start();
+        }
+    }
+
+    fn ping(&mut self, timeout: Duration) -> io::Result<Vec<u32>> {
+        if self.stream.is_none() {
+            let stream = UnixStream::connect(RENDERER_SOCKET)?;
+            stream.set_read_timeout(Some(timeout))?;
+            self.stream = Some(BufReader::new(stream));
+        }
+        let stream = self.stream.as_mut().unwrap();
+        stream.get_mut().write_all(encode(&RendererCommand::Ping).as_bytes())?;
+        let mut line = String::new();
+        if stream.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        serde_json::from_str(line.trim())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, line.trim().to_string()))
+    }
+
+    fn answered(&mut self, windows: Vec<u32>) {
+        self.windows = windows;
+        self.missed = 0;
+        self.restart_at = None;
+        if self.started.is_some_and(|at| at.elapsed() >= STABLE_AFTER) {
+            self.restart_delay = Duration::from_millis(self.config.restart_delay_ms);
+        }
+        if !self.answering {
+            self.answering = true;
+            println!("Compositor is up; sending it the window stack");
+            // Whatever it had came from before it started, if anything.
+            let stack = self.stack.lock().unwrap();
+            let mut link = self.link.lock().unwrap();
+            link.detach();
+            link.sync(&stack);
+        }
+    }
+
+    fn missed(&mut self, error: io::Error) {
+        // A late answer would be taken for the next one.
+        self.stream = None;
+        let exited = self
+            .child
+            .as_mut()
+            .and_then(|child| child.try_wait().ok().flatten());
+        if let Some(status) = exited {
+            self.fail(&format!("exited ({})", status), false);
+            return;
+        }
+        let gone = matches!(
+            error.kind(),
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+        );
+        if !self.answering {
+            let stuck = self
+                .started
+                .is_some_and(|at| self.child.is_some() && at.elapsed() >= STARTUP_TIMEOUT);
+            if stuck {
+                self.fail("never answered", true);
+            }
+            return;
+        }
+        self.missed += 1;
+        if gone {
+            self.fail("went away", false);
+        } else if self.missed >= self.config.missed_heartbeats.max(1) {
+            let reason = format!("missed {} heartbeats ({})", self.missed, error);
+            self.fail(&reason, true);
+        }
+    }
+
+    /// Gives the screen back to the X server and schedules a restart; a compositor that
+    /// may still be `alive` is cut off first.
+    fn fail(&mut self, reason: &str, alive: bool) {
+        eprintln!(
+            "Compositor {}; falling back to unredirected rendering",
+            reason
+        );
+        if alive {
+            // All output windows belong to the same connection, which this closes.
+            if let Some(window) = self.windows.first() {
+                if let Err(e) = self.disconnect(*window) {
+                    eprintln!("Failed to disconnect the compositor: {}", e);
+                }
+            }
+        }
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.link.lock().unwrap().detach();
+        self.windows.clear();
+        self.answering = false;
+        self.missed = 0;
+        self.schedule_restart();
+    }
+
+    /// Closes the X connection of the client owning `window`.
+    fn disconnect(&self, window: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.kill_client(window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn schedule_restart(&mut self) {
+        if self.config.command.is_none() {
+            return;
+        }
+        println!("Restarting the compositor in {:?}", self.restart_delay);
+        self.restart_at = Some(Instant::now() + self.restart_delay);
+        let max = Duration::from_millis(self.config.max_restart_delay_ms);
+        self.restart_delay = (self.restart_delay * 2).min(max);
+    }
+
+    fn start(&mut self) {
+        let Some(command) = &self.config.command else {
+            return;
+        };
+        self.restart_at = None;
+        // exec, so killing the child kills the compositor rather than the shell.
+        match Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec {}", command))
+            .spawn()
+        {
+            Ok(child) => {
+                println!("Started the compositor: {}", command);
+                self.child = Some(child);
+                self.started = Some(Instant::now());
+            }
+            Err(e) => {
+                eprintln!("Failed to start the compositor: {}", e);
+                self.schedule_restart();
+            }
+        }
+    }
+}
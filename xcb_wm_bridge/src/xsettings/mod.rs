@@ -0,0 +1,279 @@
+// The XSETTINGS manager, which GTK, and Qt with its gtk3 platform theme, read desktop
+// settings like the font DPI, the cursor, and the theme from: the owner of the
+// _XSETTINGS_S<screen> selection keeps the settings in its window's
+// _XSETTINGS_SETTINGS property, and clients restyle as it changes, without a restart.
+// The bridge takes the selection at startup with the settings of [xsettings], and the
+// appearance switches and, with xsettings.dpi_from_monitor, monitor changes update
+// them. Another manager, like xsettingsd or a desktop's settings daemon, is left to it.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::Deserialize;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xproto::{
+    Atom, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Window,
+    WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, NONE};
+
+use crate::config::XSettingsConfig;
+use crate::selection;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Settings' types; XSETTINGS' third, colors, goes unused.
+const INTEGER: u8 = 0;
+const STRING: u8 = 1;
+/// Xft/DPI is in 1024ths of a dot per inch.
+const DPI_UNIT: f32 = 1024.0;
+/// What monitors without a physical size are taken to have.
+const FALLBACK_DPI: f32 = 96.0;
+
+/// A setting's value.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Integer(i32),
+    String(String),
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+/// The manager, shared by what updates it.
+pub type Shared = Arc<Mutex<XSettings>>;
+
+pub struct XSettings {
+    conn: RustConnection,
+    window: Window,
+    property: Atom,
+    serial: u32,
+    /// The settings, with the serial they last changed at.
+    settings: BTreeMap<String, (Value, u32)>,
+}
+
+impl XSettings {
+    /// Takes the selection, unless another settings manager holds it, and publishes
+    /// the settings of `config`.
+    pub fn start(config: &XSettingsConfig) -> Result<Shared> {
+        let mut xsettings = Self::acquire()?;
+        let named = [
+            ("Xft/DPI", config.dpi.map(dpi_value)),
+            ("Gtk/FontName", config.font_name.as_deref().map(Value::from)),
+            (
+                "Gtk/CursorThemeName",
+                config.cursor_theme.as_deref().map(Value::from),
+            ),
+            ("Gtk/CursorThemeSize", config.cursor_size.map(Value::from)),
+            ("Net/ThemeName", config.theme.as_deref().map(Value::from)),
+            (
+                "Net/IconThemeName",
+                config.icon_theme.as_deref().map(Value::from),
+            ),
+            (
+                "Gtk/ApplicationPreferDarkTheme",
+                config.prefer_dark.map(Value::from),
+            ),
+        ];
+        for (name, value) in named {
+            if let Some(value) = value {
+                xsettings.set(name, value);
+            }
+        }
+        for (name, value) in &config.settings {
+            xsettings.set(name, value.clone());
+        }
+        xsettings.publish()?;
+        let xsettings = Arc::new(Mutex::new(xsettings));
+        if config.dpi_from_monitor {
+            let watched = xsettings.clone();
+            thread::spawn(move || {
+                if let Err(e) = follow_dpi(&watched) {
+                    eprintln!("Stopped following the monitor's DPI: {}", e);
+                }
+            });
+        }
+        Ok(xsettings)
+    }
+
+    fn acquire() -> Result<Self> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let name = format!("_XSETTINGS_S{}", screen);
+        let atom = conn.intern_atom(false, name.as_bytes())?.reply()?.atom;
+        let manager = conn.intern_atom(false, b"MANAGER")?.reply()?.atom;
+        let property = conn
+            .intern_atom(false, b"_XSETTINGS_SETTINGS")?
+            .reply()?
+            .atom;
+        if conn.get_selection_owner(atom)?.reply()?.owner != NONE {
+            return Err(format!("another settings manager holds {}", name).into());
+        }
+
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        let time = selection::timestamp(&conn, window)?;
+        conn.set_selection_owner(window, atom, time)?;
+        if conn.get_selection_owner(atom)?.reply()?.owner != window {
+            return Err(format!("failed to take {}", name).into());
+        }
+        let event = ClientMessageEvent::new(32, root, manager, [time, atom, window, 0, 0]);
+        conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, event)?;
+        conn.flush()?;
+        println!("Managing {}", name);
+        Ok(Self {
+            conn,
+            window,
+            property,
+            serial: 0,
+            settings: BTreeMap::new(),
+        })
+    }
+
+    /// Sets setting `name`, like Net/ThemeName, once published.
+    pub fn set(&mut self, name: &str, value: impl Into<Value>) {
+        let value = value.into();
+        let changed = self.serial + 1;
+        match self.settings.get_mut(name) {
+            Some((current, _)) if *current == value => {}
+            Some(setting) => *setting = (value, changed),
+            None => {
+                self.settings.insert(name.to_string(), (value, changed));
+            }
+        }
+    }
+
+    /// Writes the settings for clients to read, if any changed.
+    pub fn publish(&mut self) -> Result<()> {
+        let changed = self.serial + 1;
+        if self.serial > 0 && self.settings.values().all(|(_, at)| *at != changed) {
+            return Ok(());
+        }
+        self.serial = changed;
+        let data = encode(self.serial, &self.settings);
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            self.window,
+            self.property,
+            self.property,
+            &data,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// Keeps Xft/DPI at the primary monitor's, as RandR reports monitors changing.
+fn follow_dpi(xsettings: &Shared) -> Result<()> {
+    let (conn, screen) = RustConnection::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    conn.randr_query_version(1, 5)?.reply()?;
+    conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE | NotifyMask::OUTPUT_CHANGE)?;
+    conn.flush()?;
+    loop {
+        let dpi = monitor_dpi(&conn, root)?;
+        {
+            let mut xsettings = xsettings.lock().unwrap();
+            xsettings.set("Xft/DPI", dpi_value(dpi));
+            xsettings.publish()?;
+        }
+        // Monitors change in bursts of events; the next reading is after all of them.
+        let mut event = conn.wait_for_event()?;
+        loop {
+            if matches!(
+                event,
+                Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_)
+            ) {
+                break;
+            }
+            event = conn.wait_for_event()?;
+        }
+        while conn.poll_for_event()?.is_some() {}
+    }
+}
+
+/// The DPI of the primary monitor, or the first, by its physical width.
+fn monitor_dpi(conn: &RustConnection, root: Window) -> Result<f32> {
+    let monitors = conn.randr_get_monitors(root, true)?.reply()?.monitors;
+    let monitor = monitors
+        .iter()
+        .find(|monitor| monitor.primary)
+        .or(monitors.first());
+    Ok(match monitor {
+        Some(monitor) if monitor.width_in_millimeters > 0 => {
+            (monitor.width as f32 * 25.4 / monitor.width_in_millimeters as f32).round()
+        }
+        _ => FALLBACK_DPI,
+    })
+}
+
+fn dpi_value(dpi: f32) -> Value {
+    Value::Integer((dpi * DPI_UNIT).round() as i32)
+}
+
+/// The settings as _XSETTINGS_SETTINGS holds them, in this machine's byte order.
+fn encode(serial: u32, settings: &BTreeMap<String, (Value, u32)>) -> Vec<u8> {
+    let little = cfg!(target_endian = "little");
+    let mut data = vec![if little { 0 } else { 1 }, 0, 0, 0];
+    data.extend(serial.to_ne_bytes());
+    data.extend((settings.len() as u32).to_ne_bytes());
+    for (name, (value, changed)) in settings {
+        let kind = match value {
+            Value::Integer(_) => INTEGER,
+            Value::String(_) => STRING,
+        };
+        data.extend([kind, 0]);
+        data.extend((name.len() as u16).to_ne_bytes());
+        padded(&mut data, name.as_bytes());
+        data.extend(changed.to_ne_bytes());
+        match value {
+            Value::Integer(value) => data.extend(value.to_ne_bytes()),
+            Value::String(value) => {
+                data.extend((value.len() as u32).to_ne_bytes());
+                padded(&mut data, value.as_bytes());
+            }
+        }
+    }
+    data
+}
+
+/// Appends `bytes`, padded to a multiple of 4.
+fn padded(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend(bytes);
+    data.resize(data.len() + (4 - bytes.len() % 4) % 4, 0);
+}